@@ -0,0 +1,207 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Minimal MCP client for the DML MCP server
+//!
+//! Starts `dml-mcp-server`, lists its tools and calls each of them with the
+//! arguments in `examples/sample_project/tool_calls.json`, against the
+//! sample project next to it. Build the server first, then run:
+//!
+//! ```text
+//! cargo build --bin dml-mcp-server
+//! cargo run --example mcp_client [-- <path to dml-mcp-server>]
+//! ```
+//!
+//! The process exits with a failure status if any request fails, so the
+//! example doubles as a smoke test of a server build.
+
+use std::env;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Command, ExitCode, Stdio};
+
+use serde_json::{json, Value};
+
+/// An MCP server process, spoken to with JSON-RPC over its stdin and stdout
+struct Client {
+    server: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    next_id: u64,
+}
+
+impl Client {
+    fn start(server: &Path) -> std::io::Result<Client> {
+        let mut server = Command::new(server)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = server.stdin.take().unwrap();
+        let stdout = BufReader::new(server.stdout.take().unwrap());
+        Ok(Client { server, stdin, stdout, next_id: 1 })
+    }
+
+    /// Send a notification, which the server does not respond to
+    fn notify(&mut self, method: &str) -> Result<(), String> {
+        let notification = json!({"jsonrpc": "2.0", "method": method});
+        writeln!(self.stdin, "{}", notification)
+            .and_then(|()| self.stdin.flush())
+            .map_err(|e| format!("Could not send {}: {}", method, e))
+    }
+
+    /// Send a request, returning its result or a description of its error
+    fn request(&mut self, method: &str, params: Value)
+               -> Result<Value, String> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params,
+        });
+        writeln!(self.stdin, "{}", request)
+            .and_then(|()| self.stdin.flush())
+            .map_err(|e| format!("Could not send {}: {}", method, e))?;
+        let mut line = String::new();
+        match self.stdout.read_line(&mut line) {
+            Ok(0) => return Err(format!("Server exited during {}", method)),
+            Ok(_) => (),
+            Err(e) => return Err(format!("Could not read response: {}", e)),
+        }
+        let response: Value = serde_json::from_str(&line)
+            .map_err(|e| format!("Invalid response to {}: {}", method, e))?;
+        if response["id"] != json!(id) {
+            return Err(format!("Response to {} has id {}, expected {}",
+                               method, response["id"], id));
+        }
+        if let Some(error) = response.get("error") {
+            return Err(format!("{} failed: {}", method, error));
+        }
+        Ok(response["result"].clone())
+    }
+
+    /// All the items `key` of a paginated list, following its cursors
+    fn list(&mut self, method: &str, key: &str) -> Result<Vec<Value>, String> {
+        let mut items = vec![];
+        let mut cursor = Value::Null;
+        loop {
+            let mut page = self.request(method, json!({"cursor": cursor}))?;
+            items.extend(page[key].as_array_mut().map(std::mem::take)
+                         .unwrap_or_default());
+            cursor = page["nextCursor"].take();
+            if cursor.is_null() {
+                return Ok(items);
+            }
+        }
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        let _ = self.server.kill();
+        let _ = self.server.wait();
+    }
+}
+
+/// The server binary built next to the example, in `target/<profile>`
+fn default_server() -> PathBuf {
+    let exe = env::current_exe().expect("Path of the running example");
+    exe.parent().and_then(Path::parent).unwrap_or(Path::new("."))
+        .join(format!("dml-mcp-server{}", env::consts::EXE_SUFFIX))
+}
+
+/// Replace `$PROJECT` in the string values of `arguments`
+fn with_project(arguments: &Value, project: &str) -> Value {
+    match arguments {
+        Value::String(s) => Value::String(s.replace("$PROJECT", project)),
+        Value::Array(values) => values.iter()
+            .map(|value| with_project(value, project)).collect(),
+        Value::Object(map) => map.iter()
+            .map(|(key, value)| (key.clone(), with_project(value, project)))
+            .collect(),
+        other => other.clone(),
+    }
+}
+
+fn first_line(text: &str) -> &str {
+    text.lines().next().unwrap_or("")
+}
+
+fn run(server: &Path) -> Result<usize, String> {
+    let project = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("examples/sample_project");
+    let calls: Value = serde_json::from_str(include_str!(
+        "sample_project/tool_calls.json")).expect("Valid tool calls");
+
+    let mut client = Client::start(server)
+        .map_err(|e| format!("Could not start {}: {}", server.display(), e))?;
+    let init = client.request("initialize", json!({
+        "protocolVersion": "2024-11-05",
+        "capabilities": {},
+        "clientInfo": {"name": "mcp_client example", "version": "1.0"},
+    }))?;
+    println!("Connected to {} {}", init["serverInfo"]["name"],
+             init["serverInfo"]["version"]);
+    client.notify("notifications/initialized")?;
+
+    let mut failures = 0;
+    let tools = client.list("tools/list", "tools")?;
+    for tool in &tools {
+        let name = tool["name"].as_str().unwrap_or_default();
+        let Some(arguments) = calls.get(name) else {
+            println!("{:<26} no example call", name);
+            failures += 1;
+            continue;
+        };
+        let arguments = with_project(arguments, &project.to_string_lossy());
+        match client.request("tools/call", json!({
+            "name": name,
+            "arguments": arguments,
+        })) {
+            Ok(result) if result["isError"] == json!(true) => {
+                println!("{:<26} error: {}", name,
+                         first_line(result["content"][0]["text"]
+                                    .as_str().unwrap_or_default()));
+                failures += 1;
+            },
+            Ok(result) => {
+                let content = result["content"].as_array()
+                    .map(Vec::len).unwrap_or(0);
+                println!("{:<26} ok, {} content item(s): {}", name, content,
+                         first_line(result["content"][0]["text"]
+                                    .as_str().unwrap_or_default()));
+            },
+            Err(e) => {
+                println!("{:<26} {}", name, e);
+                failures += 1;
+            },
+        }
+    }
+
+    let resources = client.list("resources/list", "resources")?;
+    for resource in &resources {
+        let contents = client.request("resources/read", json!({
+            "uri": resource["uri"],
+        }))?;
+        println!("{:<26} {} bytes", resource["uri"].as_str().unwrap_or(""),
+                 contents["contents"][0]["text"].as_str().map(str::len)
+                 .unwrap_or(0));
+    }
+    Ok(failures)
+}
+
+fn main() -> ExitCode {
+    let server = env::args_os().nth(1).map(PathBuf::from)
+        .unwrap_or_else(default_server);
+    match run(&server) {
+        Ok(0) => ExitCode::SUCCESS,
+        Ok(failures) => {
+            eprintln!("{} tool call(s) failed", failures);
+            ExitCode::FAILURE
+        },
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        },
+    }
+}
@@ -20,10 +20,11 @@ use itertools::{Either, Itertools};
 
 use crate::actions::analysis_storage::{AnalysisStorage, ResultChannel,
                                        TimestampedStorage, timestamp_is_newer};
-use crate::analysis::{DeviceAnalysis, IsolatedAnalysis};
+use crate::analysis::{AnalysisOptions, DeviceAnalysis, IsolatedAnalysis};
+use crate::analysis::parsing::incremental::PreviousParse;
 use crate::analysis::structure::objects::Import;
 
-use crate::concurrency::JobToken;
+use crate::concurrency::{spawn_analysis, JobToken};
 use crate::file_management::CanonPath;
 use crate::vfs::{TextFile, Vfs};
 use crate::server::ServerToHandle;
@@ -92,13 +93,15 @@ impl AnalysisQueue {
                                 context: Option<CanonPath>,
                                 path: CanonPath,
                                 client_path: PathBuf,
+                                options: AnalysisOptions,
                                 tracking_token: JobToken) -> bool {
         match IsolatedAnalysisJob::new(tracking_token,
                                        storage,
                                        context,
                                        vfs,
                                        path,
-                                       client_path) {
+                                       client_path,
+                                       options) {
             // NOTE: An enqueued isolated job is always considered newer
             // than an ongoing one, so we always queue
             Ok(newjob) => {
@@ -119,8 +122,10 @@ impl AnalysisQueue {
                               storage: &mut AnalysisStorage,
                               device: &CanonPath,
                               bases: HashSet<CanonPath>,
+                              options: AnalysisOptions,
                               tracking_token: JobToken) -> bool {
-        match DeviceAnalysisJob::new(tracking_token, storage, bases, device) {
+        match DeviceAnalysisJob::new(tracking_token, storage, bases, device,
+                                     options) {
             Ok(newjob) => {
                 if let Some((_, previous_bases)) = self.device_tracker
                     .lock().unwrap()
@@ -221,7 +226,7 @@ impl AnalysisQueue {
             match job {
                 Some(QueuedJob::Terminate) => return,
                 Some(QueuedJob::IsolatedAnalysisJob(job)) => {
-                    thread::spawn({
+                    spawn_analysis({
                         let iso_tracker = Arc::clone(&isolated_tracker);
                         let hash = job.hash;
                         move ||{
@@ -233,7 +238,7 @@ impl AnalysisQueue {
                     job.process()
                 },
                 Some(QueuedJob::DeviceAnalysisJob(job)) => {
-                    thread::spawn({
+                    spawn_analysis({
                         let dev_tracker = Arc::clone(&device_tracker);
                         let hash = job.hash;
                         move ||{
@@ -391,7 +396,10 @@ pub struct IsolatedAnalysisJob {
     report: ResultChannel,
     notify: channel::Sender<ServerToHandle>,
     content: TextFile,
+    // The parse of the file before it was last edited
+    previous: Option<Arc<PreviousParse>>,
     context: Option<CanonPath>,
+    options: AnalysisOptions,
     hash: u64,
     token: JobToken,
 }
@@ -402,7 +410,8 @@ impl IsolatedAnalysisJob {
            context: Option<CanonPath>,
            vfs: &Vfs,
            path: CanonPath,
-           client_path: PathBuf)
+           client_path: PathBuf,
+           options: AnalysisOptions)
            -> Result<IsolatedAnalysisJob, String> {
 
         // TODO: Use some sort of timestamp from VFS instead of systemtime
@@ -413,6 +422,7 @@ impl IsolatedAnalysisJob {
         let hash = hasher.finish();
         // TODO: error handling
         let content = vfs.snapshot_file(&path)?;
+        let previous = analysis.previous_parses.get(&path).cloned();
         Ok(IsolatedAnalysisJob {
             path,
             client_path,
@@ -422,17 +432,24 @@ impl IsolatedAnalysisJob {
             hash,
             context,
             content,
+            previous,
+            options,
             token,
         })
     }
 
     fn process(self) {
         info!("Started work on isolated analysis of {}", self.path.as_str());
-        match IsolatedAnalysis::new(&self.path,
-                                    &self.client_path,
-                                    self.content,
-                                    self.token.status) {
-            Ok(analysis) => {
+        match IsolatedAnalysis::new_incrementally(&self.path,
+                                                  &self.client_path,
+                                                  self.content,
+                                                  self.previous.as_deref(),
+                                                  self.token.status) {
+            Ok(mut analysis) => {
+                if !self.options.keep_ast {
+                    analysis.ast = None;
+                    analysis.reparse = None;
+                }
                 let new_context = if analysis.is_device_file() {
                     Some(self.path.clone())
                 } else {
@@ -465,6 +482,7 @@ pub struct DeviceAnalysisJob {
     timestamp: SystemTime,
     report: ResultChannel,
     notify: channel::Sender<ServerToHandle>,
+    options: AnalysisOptions,
     hash: u64,
     token: JobToken,
 }
@@ -473,7 +491,8 @@ impl DeviceAnalysisJob {
     fn new(token: JobToken,
            analysis: &mut AnalysisStorage,
            bases: HashSet<CanonPath>,
-           root: &CanonPath)
+           root: &CanonPath,
+           options: AnalysisOptions)
            -> Result<DeviceAnalysisJob, String> {
         info!("Creating a device analysis job of {:?}", root);
         // TODO: Use some sort of timestamp from VFS instead of systemtime
@@ -524,6 +543,7 @@ impl DeviceAnalysisJob {
             import_sources,
             report: analysis.report.clone(),
             notify: analysis.notify.clone(),
+            options,
             hash,
             token,
         })
@@ -537,6 +557,7 @@ impl DeviceAnalysisJob {
         match DeviceAnalysis::new(self.root,
                                   self.bases,
                                   self.import_sources,
+                                  self.options,
                                   self.token.status) {
             Ok(analysis) => {
                 info!("Finished device analysis of {:?}", analysis.name);
@@ -10,18 +10,27 @@ use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fmt;
 use std::path::{Path, PathBuf};
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, SystemTime};
 
 use crate::actions::ContextDefinition;
 use crate::analysis::scope::ContextKey;
 use crate::analysis::structure::objects::Import;
+use crate::analysis::deprecations::deprecation_errors;
+use crate::analysis::import_cycles::{describe_cycle, find_import_cycles,
+                                     ImportGraph};
+use crate::analysis::stdlib_versions::{stdlib_version_errors,
+                                       SimicsApiVersion};
+use crate::analysis::unused::UnusedDeclaration;
 use crate::analysis::{DMLError, DeviceAnalysis, IsolatedAnalysis};
+use crate::analysis::parsing::incremental::PreviousParse;
+pub use crate::analysis::TimestampedStorage;
 
 use crate::lsp_data::*;
-use crate::analysis::parsing::tree::{ZeroSpan, ZeroFilePosition};
+use crate::analysis::parsing::tree::{ZeroRange, ZeroSpan, ZeroFilePosition};
 use crate::server::ServerToHandle;
 
+use crate::config::UnusedDeclarations;
 use crate::lint::LinterAnalysis;
 
 use crate::file_management::{PathResolver, CanonPath};
@@ -46,12 +55,6 @@ impl AnalysisResult {
 
 pub type ResultChannel = channel::Sender<TimestampedStorage<AnalysisResult>>;
 
-#[derive(Debug, Clone)]
-pub struct TimestampedStorage<T> {
-    pub timestamp: SystemTime,
-    pub stored: T,
-}
-
 impl TimestampedStorage<AnalysisResult> {
     pub fn make_isolated_result(timestamp: SystemTime,
                                 analysis: IsolatedAnalysis)
@@ -113,6 +116,9 @@ pub struct AnalysisStorage {
             CanonPath, TimestampedStorage<DeviceAnalysis>>,
     pub lint_analysis: HashMap<
             CanonPath, TimestampedStorage<LinterAnalysis>>,
+    // The parses of edited files that are being analyzed again, which
+    // they are re-parsed from
+    pub previous_parses: HashMap<CanonPath, Arc<PreviousParse>>,
     // Maps file paths to device paths that depend on them
     pub device_triggers: HashMap<CanonPath, HashSet<CanonPath>>,
     // The inverse of the above
@@ -198,6 +204,7 @@ impl AnalysisStorage {
             results,
             report,
             lint_analysis: HashMap::default(),
+            previous_parses: HashMap::default(),
             isolated_analysis: HashMap::default(),
             device_analysis:  HashMap::default(),
             device_triggers:  HashMap::default(),
@@ -404,6 +411,7 @@ impl AnalysisStorage {
                 self.import_map.entry(path.clone()).or_default();
             let context_import_maps =
                 all_import_maps.entry(context.cloned()).or_default();
+            context_import_maps.clear();
 
             debug!("While updating dependencies, these were not resolved {:?}",
                    missing);
@@ -492,6 +500,7 @@ impl AnalysisStorage {
                                                                *invalid)) {
                             trace!("was new, or fresh compared to previous");
                             dependencies_to_update.insert(canon_path.clone());
+                            self.previous_parses.remove(&canon_path);
                             self.isolated_analysis.insert(canon_path.clone(),
                                                           TimestampedStorage {
                                                               timestamp,
@@ -585,6 +594,7 @@ impl AnalysisStorage {
                     info!("Discarded analysis of {} due to it being \
                            unused for too long.", path.as_str());
                     self.mark_file_dirty(&path);
+                    self.previous_parses.remove(&path);
                 }
         }
     }
@@ -634,7 +644,12 @@ impl AnalysisStorage {
 
     pub fn mark_file_dirty(&mut self, path: &CanonPath) {
         trace!("Marked {} as dirty", path.as_str());
-        self.isolated_analysis.remove(path);
+        // Files edited again before they were re-analyzed are re-parsed
+        // from the last parse of them
+        if let Some(previous) = self.isolated_analysis.remove(path)
+            .and_then(|analysis|analysis.stored.into_previous_parse()) {
+                self.previous_parses.insert(path.clone(), Arc::new(previous));
+            }
         self.lint_analysis.remove(path);
         self.discard_dependant_device_analysis(path);
         self.invalidators.insert(path.clone(), SystemTime::now());
@@ -678,7 +693,17 @@ impl AnalysisStorage {
         }
     }
 
-    pub fn gather_errors(&mut self, filter: Option<&HashSet<ContextDefinition>>)
+    // The directory of the DML standard library in use, if it was found
+    pub fn stdlib_dir(&self) -> Option<PathBuf> {
+        self.isolated_analysis.keys()
+            .find(|cp|cp.as_path().ends_with("dml-builtins.dml"))
+            .and_then(|cp|cp.as_path().parent())
+            .map(Path::to_path_buf)
+    }
+
+    pub fn gather_errors(&mut self, filter: Option<&HashSet<ContextDefinition>>,
+                         simics_api: Option<SimicsApiVersion>,
+                         unused: &UnusedDeclarations)
                          -> FilteredErrors {
         // By this being a hashset, we will not double-report any errors
         let mut isolated_errors: HashMap<PathBuf, HashSet<DMLError>>
@@ -692,11 +717,21 @@ impl AnalysisStorage {
             .chain(self.isolated_analysis.keys())
             .chain(self.lint_analysis.keys())
             .collect();
+        let stdlib_dir = self.stdlib_dir();
+        let mut cycle_errors = self.import_cycle_errors();
         for file in all_files {
             if let Some((ifile, ierrors)) = self.gather_local_errors(file) {
-                isolated_errors.entry(ifile)
-                    .or_default()
-                    .extend(ierrors.into_iter());
+                let ierrors_entry = isolated_errors.entry(ifile).or_default();
+                ierrors_entry.extend(ierrors.into_iter());
+                ierrors_entry.extend(
+                    cycle_errors.remove(file).into_iter().flatten());
+                // The standard library itself is not checked, it
+                // legitimately declares items regardless of version
+                if !stdlib_dir.as_ref().is_some_and(
+                    |dir|file.as_path().starts_with(dir)) {
+                    ierrors_entry.extend(
+                        self.gather_stdlib_version_errors(file, simics_api));
+                }
             }
 
             lint_errors.entry(file.clone().into())
@@ -723,9 +758,51 @@ impl AnalysisStorage {
                 }
             }
         }
+        for (file, errors) in self.unused_declaration_errors(filter, unused) {
+            if !stdlib_dir.as_ref().is_some_and(|dir|file.starts_with(dir)) {
+                device_errors.entry(file).or_default().extend(errors);
+            }
+        }
         (isolated_errors, device_errors, lint_errors)
     }
 
+    // Errors about declarations that none of the devices with the file they
+    // are in uses, reported if one of those devices is in `filter`
+    pub fn unused_declaration_errors(
+        &self,
+        filter: Option<&HashSet<ContextDefinition>>,
+        config: &UnusedDeclarations) -> HashMap<PathBuf, Vec<DMLError>> {
+        let mut users: HashMap<PathBuf, usize> = HashMap::default();
+        let mut active: HashSet<PathBuf> = HashSet::default();
+        let mut unused_in: HashMap<&UnusedDeclaration, usize>
+            = HashMap::default();
+        for (path, device) in &self.device_analysis {
+            let device = &device.stored;
+            let is_active = filter.is_none_or(
+                |f|f.contains(&path.clone().into()));
+            for file in &device.dependant_files {
+                *users.entry(file.clone().into()).or_default() += 1;
+                if is_active {
+                    active.insert(file.clone().into());
+                }
+            }
+            for unused in device.unused.iter().collect::<HashSet<_>>() {
+                *unused_in.entry(unused).or_default() += 1;
+            }
+        }
+        let mut errors: HashMap<PathBuf, Vec<DMLError>> = HashMap::default();
+        for (unused, count) in unused_in {
+            let file = unused.span.path();
+            if active.contains(&file) && users.get(&file) == Some(&count) {
+                if let Some(severity) = config.severity(unused.kind) {
+                    errors.entry(file).or_default()
+                        .push(unused.to_error(severity));
+                }
+            }
+        }
+        errors
+    }
+
     pub fn gather_linter_errors(&self, path: &CanonPath) -> Vec<DMLError> {
         // This is not a user-initiated request, so it's ok to drop
         // the error here
@@ -745,6 +822,71 @@ impl AnalysisStorage {
             .map(|a|(a.clientpath.clone(), a.errors.clone()))
     }
 
+    // Errors about unavailable or deprecated standard library items and
+    // deprecated names
+    pub fn gather_stdlib_version_errors(&self,
+                                        path: &CanonPath,
+                                        version: Option<SimicsApiVersion>)
+                                        -> Vec<DMLError> {
+        // This is not a user-initiated request, so it's ok to drop
+        // the error here
+        let Ok(analysis) = self.get_isolated_analysis(path) else {
+            return vec![];
+        };
+        let mut errors = deprecation_errors(&analysis.toplevel, version);
+        if let Some(version) = version {
+            errors.extend(stdlib_version_errors(&analysis.toplevel, version));
+        }
+        errors
+    }
+
+    // Errors about circular imports, by the file they are reported in. Each
+    // cycle is reported at each of its imports, listing the cycle from the
+    // file of the import
+    pub fn import_cycle_errors(&self) -> HashMap<CanonPath, Vec<DMLError>> {
+        let mut graph: ImportGraph<CanonPath> = ImportGraph::new();
+        let mut imports: HashMap<(&CanonPath, CanonPath), &Import>
+            = HashMap::default();
+        for (path, contexts) in &self.import_map {
+            for (import, resolved) in contexts.values().flatten() {
+                let Some(resolved) = CanonPath::from_path_buf(
+                    PathBuf::from(resolved)) else {
+                    continue;
+                };
+                graph.entry(path.clone()).or_default().insert(resolved.clone());
+                // Implicit imports have no location to report at
+                if import.span.range != ZeroRange::invalid() {
+                    imports.entry((path, resolved)).or_insert(import);
+                }
+            }
+        }
+        let mut errors: HashMap<CanonPath, Vec<DMLError>> = HashMap::default();
+        for cycle in find_import_cycles(&graph) {
+            let edges: Vec<(&CanonPath, &CanonPath)> = cycle.iter()
+                .zip(cycle.iter().cycle().skip(1)).collect();
+            for (index, (from, to)) in edges.iter().enumerate() {
+                let Some(import) = imports.get(&(*from, (*to).clone())) else {
+                    continue;
+                };
+                let files: Vec<&Path> = cycle[index..].iter()
+                    .chain(&cycle[..index]).map(CanonPath::as_path).collect();
+                let related = edges[index + 1..].iter().chain(&edges[..index])
+                    .filter_map(|(from, to)|imports.get(&(*from, (*to).clone())))
+                    .map(|import|(import.span, format!(
+                        "Imports '{}'", import.imported_name())))
+                    .collect();
+                errors.entry((*from).clone()).or_default().push(DMLError {
+                    span: import.span,
+                    description: format!("Import cycle: {}",
+                                         describe_cycle(&files)),
+                    severity: Some(DiagnosticSeverity::WARNING),
+                    related,
+                });
+            }
+        }
+        errors
+    }
+
     pub fn gather_device_errors(&self, path: &CanonPath)
                                 -> HashMap<PathBuf, Vec<DMLError>> {
         // This is not a user-initiated request, so it's ok to drop
@@ -0,0 +1,318 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Call hierarchies of methods, following the calls in method bodies to the
+//! methods they resolve to, in each device the method is part of
+use std::collections::{BTreeMap, HashSet};
+
+use lsp_types::{CallHierarchyItem, SymbolKind};
+
+use crate::actions::analysis_storage::AnalysisStorage;
+use crate::analysis::{DeviceAnalysis, LocationSpan, Named, ZeroSpan};
+use crate::analysis::parsing::tree::ZeroFilePosition;
+use crate::analysis::scope::{ContextKey, SubSymbol, SymbolContext};
+use crate::file_management::CanonPath;
+use crate::lsp_data::ls_util;
+
+/// A method declaration, as found in the symbol contexts of its file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MethodDeclaration {
+    pub name: String,
+    /// The name of the method in the declaration
+    pub loc: ZeroSpan,
+    /// The whole declaration, including the body
+    pub span: ZeroSpan,
+    /// The names of the objects and templates the declaration is in
+    pub container: Vec<String>,
+}
+
+impl MethodDeclaration {
+    pub fn to_item(&self) -> CallHierarchyItem {
+        let location = ls_util::dls_to_location(&self.span);
+        CallHierarchyItem {
+            name: self.name.clone(),
+            kind: SymbolKind::METHOD,
+            tags: None,
+            detail: (!self.container.is_empty())
+                .then(||self.container.join(".")),
+            uri: location.uri,
+            range: location.range,
+            selection_range: ls_util::dls_to_range(self.loc.range),
+            data: None,
+        }
+    }
+}
+
+fn method_declarations(context: &SymbolContext,
+                       container: &mut Vec<String>,
+                       declarations: &mut Vec<MethodDeclaration>) {
+    for sub in &context.subsymbols {
+        let SubSymbol::Context(sub) = sub else {
+            continue;
+        };
+        match &sub.context {
+            ContextKey::Method(_) => declarations.push(MethodDeclaration {
+                name: sub.get_name(),
+                loc: *sub.loc_span(),
+                span: sub.span,
+                container: container.clone(),
+            }),
+            ContextKey::Structure(_) | ContextKey::Template(_) => {
+                container.push(sub.get_name());
+                method_declarations(sub, container, declarations);
+                container.pop();
+            },
+            ContextKey::AllWithTemplate(..) =>
+                method_declarations(sub, container, declarations),
+        }
+    }
+}
+
+// The method declarations of the file a span is in
+fn file_method_declarations(storage: &AnalysisStorage, span: &ZeroSpan)
+                            -> Vec<MethodDeclaration> {
+    let Ok(path) = CanonPath::try_from_path_buf(span.path()) else {
+        return vec![];
+    };
+    let Ok(isolated) = storage.get_isolated_analysis(&path) else {
+        return vec![];
+    };
+    let mut declarations = vec![];
+    method_declarations(&isolated.top_context, &mut vec![],
+                        &mut declarations);
+    declarations
+}
+
+/// The method declaration whose name is at `loc`
+pub fn declaration_at(storage: &AnalysisStorage, loc: &ZeroSpan)
+                      -> Option<MethodDeclaration> {
+    file_method_declarations(storage, loc).into_iter()
+        .find(|declaration|declaration.loc == *loc)
+}
+
+// The innermost method declaration containing each span, with the spans it
+// contains, in the order of the declarations
+fn group_by_declaration(storage: &AnalysisStorage,
+                        spans: impl IntoIterator<Item = ZeroSpan>)
+                        -> Vec<(MethodDeclaration, Vec<ZeroSpan>)> {
+    let mut grouped: BTreeMap<ZeroSpan, (MethodDeclaration, Vec<ZeroSpan>)>
+        = BTreeMap::new();
+    for span in spans {
+        let declaration = file_method_declarations(storage, &span)
+            .into_iter()
+            .filter(|declaration|declaration.span.range
+                    .contains_pos(span.range.start()))
+            .min_by_key(|declaration|declaration.span.range);
+        if let Some(declaration) = declaration {
+            grouped.entry(declaration.loc)
+                .or_insert_with(||(declaration, vec![]))
+                .1.push(span);
+        }
+    }
+    grouped.into_values().map(|(declaration, mut spans)| {
+        spans.sort();
+        spans.dedup();
+        (declaration, spans)
+    }).collect()
+}
+
+/// The methods calling the method declared with the name at `loc`, with the
+/// calls in each. Calls of the methods it overrides are included, as they
+/// may end up in it, except for its own calls of them through `default`.
+pub fn incoming_calls(storage: &AnalysisStorage,
+                      devices: &[&DeviceAnalysis],
+                      loc: &ZeroSpan)
+                      -> Vec<(MethodDeclaration, Vec<ZeroSpan>)> {
+    let callee = declaration_at(storage, loc);
+    let is_outside_callee = |call: &&ZeroSpan|callee.as_ref().is_none_or(
+        |callee|call.file != callee.span.file
+            || !callee.span.range.contains_pos(call.range.start()));
+    let mut calls = HashSet::new();
+    for device in devices {
+        let methods = &device.symbol_info.method_symbols;
+        for (parent, symbol) in methods.get(loc).into_iter().flatten() {
+            let symbol = symbol.lock().unwrap();
+            calls.extend(symbol.references.iter().cloned());
+            for base in symbol.bases.iter().filter(|base|*base != loc) {
+                if let Some(base_symbol) = methods.get(base)
+                    .and_then(|by_parent|by_parent.get(parent)) {
+                        calls.extend(base_symbol.lock().unwrap()
+                                     .references.iter()
+                                     .filter(is_outside_callee).cloned());
+                    }
+            }
+        }
+    }
+    group_by_declaration(storage, calls)
+}
+
+/// The methods called by the method declared with the name at `loc`, with
+/// the calls of each
+pub fn outgoing_calls(storage: &AnalysisStorage,
+                      devices: &[&DeviceAnalysis],
+                      loc: &ZeroSpan)
+                      -> Vec<(MethodDeclaration, Vec<ZeroSpan>)> {
+    let Some(caller) = declaration_at(storage, loc) else {
+        return vec![];
+    };
+    let mut callees: BTreeMap<ZeroSpan, HashSet<ZeroSpan>> = BTreeMap::new();
+    for device in devices {
+        for symbol in device.symbol_info.method_symbols.values()
+            .flat_map(|by_parent|by_parent.values()) {
+                let symbol = symbol.lock().unwrap();
+                let calls: Vec<ZeroSpan> = symbol.references.iter()
+                    .filter(|call|call.file == caller.span.file
+                            && caller.span.range.contains_pos(
+                                call.range.start()))
+                    .cloned().collect();
+                if !calls.is_empty() {
+                    callees.entry(symbol.loc).or_default().extend(calls);
+                }
+            }
+    }
+    callees.into_iter().filter_map(|(callee, calls)| {
+        let mut calls: Vec<ZeroSpan> = calls.into_iter().collect();
+        calls.sort();
+        declaration_at(storage, &callee).map(|callee|(callee, calls))
+    }).collect()
+}
+
+/// The method declared with its name at, or called at, `pos`, and the calls
+/// of it, if it neither overrides nor is overridden by other methods
+pub fn calls_of_method_at(devices: &[&DeviceAnalysis], pos: &ZeroFilePosition)
+                          -> Option<(ZeroSpan, Vec<ZeroSpan>)> {
+    let at = |span: &ZeroSpan|span.contains_pos(pos);
+    let mut found = None;
+    let mut calls = HashSet::new();
+    for device in devices {
+        for (loc, by_parent) in &device.symbol_info.method_symbols {
+            for symbol in by_parent.values() {
+                let symbol = symbol.lock().unwrap();
+                if !at(loc) && !symbol.references.iter().any(at) {
+                    continue;
+                }
+                if found.is_some_and(|found|found != *loc)
+                    || !symbol.implementations.is_empty()
+                    || symbol.bases.iter().any(|base|base != loc) {
+                    return None;
+                }
+                found = Some(*loc);
+                calls.extend(symbol.references.iter().cloned());
+            }
+        }
+    }
+    let mut calls: Vec<ZeroSpan> = calls.into_iter().collect();
+    calls.sort();
+    found.map(|loc|(loc, calls))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::PathBuf;
+    use std::str::FromStr;
+    use std::time::SystemTime;
+    use crossbeam::channel;
+    use crate::actions::analysis_storage::TimestampedStorage;
+    use crate::analysis::{AnalysisOptions, IsolatedAnalysis};
+    use crate::concurrency::JobStatusKeeper;
+    use crate::vfs::TextFile;
+
+    const SOURCE: &str = "dml 1.4;
+device dev;
+template counter {
+    method step() {
+        log info: \"step\";
+    }
+    method tick() {
+        step();
+    }
+}
+template resettable is counter {
+    method reset() {
+        tick();
+    }
+}
+bank regs {
+    register r size 4 @ 0x0 is resettable;
+    method clear() {
+        r.reset();
+        r.tick();
+        r.tick();
+    }
+}
+";
+
+    // The storage with the analysis of a device file, which must exist as
+    // declarations are looked up by canonical path, and the analysis of the
+    // device
+    fn analyze(file: &PathBuf, source: &str)
+               -> (AnalysisStorage, DeviceAnalysis) {
+        std::fs::write(file, source).unwrap();
+        let path = CanonPath::from_path_buf(file.clone()).unwrap();
+        let (_keeper, status) = JobStatusKeeper::new();
+        let isolated = IsolatedAnalysis::new(
+            &path, file, TextFile::from_str(source).unwrap(),
+            status.clone()).unwrap();
+        let timestamp = SystemTime::now();
+        let device = DeviceAnalysis::new(
+            isolated.clone(),
+            vec![TimestampedStorage { timestamp, stored: isolated.clone() }],
+            Default::default(), AnalysisOptions::default(), status).unwrap();
+        let (notify, _) = channel::unbounded();
+        let mut storage = AnalysisStorage::init(notify);
+        storage.isolated_analysis.insert(
+            path, TimestampedStorage { timestamp, stored: isolated });
+        (storage, device)
+    }
+
+    fn declaration_named(storage: &AnalysisStorage, device: &DeviceAnalysis,
+                         name: &str) -> MethodDeclaration {
+        let mut declarations = vec![];
+        method_declarations(
+            &storage.get_isolated_analysis(&device.path).unwrap().top_context,
+            &mut vec![], &mut declarations);
+        declarations.into_iter()
+            .find(|declaration|declaration.name == name).unwrap()
+    }
+
+    // The names of the methods in calls, with the zero-based positions of
+    // the calls in each
+    fn calls(calls: Vec<(MethodDeclaration, Vec<ZeroSpan>)>)
+             -> Vec<(String, Vec<(u32, u32)>)> {
+        calls.into_iter().map(|(declaration, calls)|(
+            declaration.name,
+            calls.iter().map(|call|(call.range.row_start.0,
+                                    call.range.col_start.0)).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn test_call_hierarchy() {
+        let dir = std::env::temp_dir().join(
+            format!("dls-call-hierarchy-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (storage, device) = analyze(&dir.join("dev.dml"), SOURCE);
+        let devices = [&device];
+        let tick = declaration_named(&storage, &device, "tick");
+        assert_eq!(tick.container, vec!["counter"]);
+        assert_eq!(declaration_at(&storage, &tick.loc), Some(tick.clone()));
+        // Called from a template instantiating the one it is declared in,
+        // and from a bank through the register instantiating both
+        assert_eq!(calls(incoming_calls(&storage, &devices, &tick.loc)),
+                   vec![("reset".to_string(), vec![(12, 8)]),
+                        ("clear".to_string(), vec![(19, 10), (20, 10)])]);
+        assert_eq!(calls(outgoing_calls(&storage, &devices, &tick.loc)),
+                   vec![("step".to_string(), vec![(7, 8)])]);
+        let clear = declaration_named(&storage, &device, "clear");
+        assert_eq!(clear.container, vec!["regs"]);
+        assert_eq!(calls(incoming_calls(&storage, &devices, &clear.loc)),
+                   vec![]);
+        assert_eq!(calls(outgoing_calls(&storage, &devices, &clear.loc)),
+                   vec![("tick".to_string(), vec![(19, 10), (20, 10)]),
+                        ("reset".to_string(), vec![(18, 10)])]);
+        let step = declaration_named(&storage, &device, "step");
+        assert_eq!(calls(outgoing_calls(&storage, &devices, &step.loc)),
+                   vec![]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
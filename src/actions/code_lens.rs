@@ -0,0 +1,161 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Code lenses summarizing the register layout of banks, and offering to
+//! generate tests and register maps of devices
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+use lsp_types::{CodeLens, Command, Range, Uri};
+
+use crate::analysis::DeviceAnalysis;
+use crate::analysis::layout::{containing_bank, eval_param, instances,
+                              register_offsets, UNMAPPED_OFFSET};
+use crate::analysis::structure::objects::CompObjectKind;
+use crate::analysis::templating::objects::DMLCompositeObject;
+use crate::analysis::ZeroRange;
+use crate::lsp_data::ls_util;
+use crate::span::PathBufKey;
+
+/// The command generating a Simics test suite for the device declared in
+/// the file given as argument
+pub const GENERATE_TEST_COMMAND: &str = "dls.generateTest";
+/// The command exporting the register map of the device declared in the file
+/// given as argument, as an HTML page
+pub const EXPORT_REGISTER_MAP_COMMAND: &str = "dls.exportRegisterMap";
+/// The command generating the Markdown documentation of the device declared
+/// in the file given as argument
+pub const GENERATE_DOCS_COMMAND: &str = "dls.generateDocs";
+
+/// The commands offered by device code lenses
+pub fn device_commands() -> Vec<String> {
+    if cfg!(feature = "exporters") {
+        vec![GENERATE_TEST_COMMAND.to_string(),
+             EXPORT_REGISTER_MAP_COMMAND.to_string(),
+             GENERATE_DOCS_COMMAND.to_string()]
+    } else {
+        vec![]
+    }
+}
+
+/// The number of registers in each element of a bank, and the first and
+/// last byte they are mapped at
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BankLayout {
+    pub registers: usize,
+    pub span: Option<(u64, u64)>,
+}
+
+impl BankLayout {
+    pub fn label(&self) -> String {
+        let registers = match self.registers {
+            1 => "1 register".to_string(),
+            count => format!("{} registers", count),
+        };
+        match self.span {
+            Some((first, last)) =>
+                format!("{}, {:#x}–{:#x}", registers, first, last),
+            None => registers,
+        }
+    }
+}
+
+/// The layout of the registers of a bank, or None if the sizes or offsets of
+/// its registers or arrays cannot be evaluated
+pub fn bank_layout(device: &DeviceAnalysis, bank: &DMLCompositeObject)
+                   -> Option<BankLayout> {
+    let elements = instances(&device.objects, bank)?.len();
+    let mut registers = 0;
+    let mut span: Option<(u64, u64)> = None;
+    for register in device.objects.values()
+        .filter(|obj|obj.kind == CompObjectKind::Register
+                && containing_bank(&device.objects, obj)
+                .is_some_and(|parent|parent.key == bank.key)) {
+            let offsets = register_offsets(&device.objects, register)?;
+            let indices = instances(&device.objects, register)?;
+            registers += offsets.len();
+            for (offset, indices) in offsets.into_iter().zip(&indices) {
+                if offset == UNMAPPED_OFFSET {
+                    continue;
+                }
+                let size = eval_param(&device.objects, register, "size",
+                                      indices)
+                    .and_then(|size|u64::try_from(size).ok())?;
+                let last = offset.checked_add(size.max(1) - 1)?;
+                span = Some(span.map_or((offset, last), |(first, end)|
+                                        (first.min(offset), end.max(last))));
+            }
+        }
+    Some(BankLayout {
+        registers: registers / elements.max(1),
+        span,
+    })
+}
+
+/// The lenses of the banks declared in `file`, as evaluated in `devices`.
+/// Declarations of banks whose layout differs between devices are not given
+/// lenses.
+pub fn bank_lenses(devices: &[&DeviceAnalysis], file: PathBufKey)
+                   -> Vec<CodeLens> {
+    let mut labels: BTreeMap<ZeroRange, Option<String>> = BTreeMap::new();
+    for device in devices {
+        for bank in device.objects.values()
+            .filter(|obj|obj.kind == CompObjectKind::Bank) {
+                let locs: Vec<ZeroRange> = bank.all_decls.iter()
+                    .filter(|spec|spec.loc.file == file)
+                    .map(|spec|spec.loc.range).collect();
+                if locs.is_empty() {
+                    continue;
+                }
+                let label = bank_layout(device, bank)
+                    .map(|layout|layout.label());
+                for loc in locs {
+                    labels.entry(loc)
+                        .and_modify(|existing| if *existing != label {
+                            *existing = None;
+                        })
+                        .or_insert_with(||label.clone());
+                }
+            }
+    }
+    labels.into_iter()
+        .filter_map(|(loc, label)|label.map(|label|CodeLens {
+            range: ls_util::dls_to_range(loc),
+            // Summaries do nothing when clicked
+            command: Some(Command::new(label, String::new(), None)),
+            data: None,
+        }))
+        .collect()
+}
+
+/// The lenses of a device declaration at `range` in the file at `uri`
+pub fn device_lenses(uri: &Uri, range: Range) -> Vec<CodeLens> {
+    vec![(GENERATE_TEST_COMMAND, "Generate Python test"),
+         (EXPORT_REGISTER_MAP_COMMAND, "Export register map"),
+         (GENERATE_DOCS_COMMAND, "Generate documentation")].into_iter()
+        .filter(|(command, _)|device_commands().iter()
+                .any(|offered|offered == command))
+        .map(|(command, title)|CodeLens {
+            range,
+            command: Some(Command::new(
+                title.to_string(), command.to_string(),
+                Some(vec![serde_json::Value::String(
+                    uri.as_str().to_string())]))),
+            data: None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_bank_layout_label() {
+        assert_eq!(BankLayout { registers: 4, span: Some((0, 0xf)) }.label(),
+                   "4 registers, 0x0–0xf");
+        assert_eq!(BankLayout { registers: 1, span: Some((0x10, 0x13)) }
+                   .label(), "1 register, 0x10–0x13");
+        assert_eq!(BankLayout { registers: 0, span: None }.label(),
+                   "0 registers");
+    }
+}
@@ -0,0 +1,568 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Completion of declarations, by the kind of object they are written in,
+//! and of the names of templates, interfaces and imported files
+//!
+//! What may be declared depends on the object a declaration is in, so the
+//! enclosing objects are found from the tokens before the position. This
+//! works on the text being written, which rarely parses.
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use lsp_types::{CompletionItem, CompletionItemKind, CompletionTextEdit,
+                Documentation, InsertTextFormat, MarkupContent, MarkupKind,
+                Position, Range, TextEdit};
+
+use crate::actions::InitActionContext;
+use crate::analysis::parsing::lexer::TokenKind;
+use crate::analysis::parsing::tree::ZeroPosition;
+use crate::analysis::quick_fixes::{code_tokens, Token};
+use crate::analysis::signatures::{call_site, CallSite};
+use crate::analysis::structure::objects::CompObjectKind;
+use crate::file_management::CanonPath;
+use crate::catalog::Catalog;
+use crate::server::Output;
+
+/// The kind of body that a declaration is written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Body {
+    /// The top level of a file, which is in the device
+    TopLevel,
+    Template,
+    Object(CompObjectKind),
+    /// Method bodies, initializers and anything else that is not
+    /// declarations
+    Code,
+}
+
+/// What can be written at a position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompletionContext {
+    /// The start of a declaration in a body. Objects declared there are
+    /// in `declaring`, which differs from `body` in groups.
+    Declaration { body: Body, declaring: Body },
+    /// A template name after `is`
+    Template,
+    /// An interface name after `implement` or `interface`
+    Interface,
+}
+
+fn object_kind(kind: TokenKind) -> Option<CompObjectKind> {
+    Some(match kind {
+        TokenKind::Attribute => CompObjectKind::Attribute,
+        TokenKind::Bank => CompObjectKind::Bank,
+        TokenKind::Connect => CompObjectKind::Connect,
+        TokenKind::Event => CompObjectKind::Event,
+        TokenKind::Field => CompObjectKind::Field,
+        TokenKind::Group => CompObjectKind::Group,
+        TokenKind::Implement => CompObjectKind::Implement,
+        TokenKind::Interface => CompObjectKind::Interface,
+        TokenKind::Port => CompObjectKind::Port,
+        TokenKind::Register => CompObjectKind::Register,
+        TokenKind::Subdevice => CompObjectKind::Subdevice,
+        _ => return None,
+    })
+}
+
+// The body opened by a brace ending a statement that starts with `first`,
+// or None if the brace keeps the body it is in, as in '#if' and '#else'
+fn opened_body(first: Option<&Token<'_>>, parent: Body) -> Option<Body> {
+    let Some(first) = first else {
+        return Some(Body::Code);
+    };
+    if parent == Body::Code {
+        return Some(Body::Code);
+    }
+    match first.kind {
+        TokenKind::HashIf | TokenKind::HashElse => None,
+        TokenKind::Template => Some(Body::Template),
+        // What 'in each' applies to is not known from the text alone
+        TokenKind::In => Some(Body::Template),
+        kind => Some(object_kind(kind).map_or(Body::Code, Body::Object)),
+    }
+}
+
+/// What can be written at a position in `text`, if anything is completed
+/// there
+pub fn completion_context(text: &str, position: ZeroPosition)
+                          -> Option<CompletionContext> {
+    let mut tokens: Vec<Token<'_>> = code_tokens(text).into_iter()
+        .take_while(|token|(token.range.row_start, token.range.col_start)
+                    < (position.row, position.col))
+        .collect();
+    // The word that the position is in is what is completed
+    if tokens.last().is_some_and(
+        |token|token.range.row_end == position.row
+            && token.range.col_end >= position.col
+            && token.text.chars().all(|c|c.is_alphanumeric() || c == '_')) {
+        tokens.pop();
+    }
+    let mut bodies = vec![];
+    let mut statement_start = 0;
+    for (index, token) in tokens.iter().enumerate() {
+        match token.kind {
+            TokenKind::LBrace => {
+                let parent = bodies.last().copied().unwrap_or(Body::TopLevel);
+                bodies.push(opened_body(tokens[statement_start..index]
+                                        .first(), parent)
+                            .unwrap_or(parent));
+                statement_start = index + 1;
+            },
+            TokenKind::RBrace => {
+                bodies.pop();
+                statement_start = index + 1;
+            },
+            TokenKind::SemiColon => statement_start = index + 1,
+            _ => (),
+        }
+    }
+    let body = bodies.last().copied().unwrap_or(Body::TopLevel);
+    match tokens.last().map(|token|token.kind) {
+        _ if body == Body::Code => None,
+        None | Some(TokenKind::LBrace | TokenKind::RBrace
+                    | TokenKind::SemiColon) =>
+            Some(CompletionContext::Declaration {
+                body, declaring: declaring_body(&bodies),
+            }),
+        Some(TokenKind::Is) => Some(CompletionContext::Template),
+        Some(TokenKind::Implement | TokenKind::Interface) =>
+            Some(CompletionContext::Interface),
+        Some(TokenKind::LParen | TokenKind::Comma)
+            if matches!(call_site(text, position),
+                        Some(CallSite::Templates { .. })) =>
+            Some(CompletionContext::Template),
+        _ => None,
+    }
+}
+
+// The kind of object that declarations in a body are in, where groups are
+// in the object that they group declarations of
+fn declaring_body(bodies: &[Body]) -> Body {
+    bodies.iter().rev().copied()
+        .find(|body|*body != Body::Object(CompObjectKind::Group))
+        .unwrap_or(Body::TopLevel)
+}
+
+/// The kinds of objects that can be declared in a body
+pub fn child_objects(body: Body) -> &'static [CompObjectKind] {
+    use CompObjectKind::*;
+    match body {
+        Body::TopLevel | Body::Object(Device | Subdevice) =>
+            &[Bank, Port, Subdevice, Group, Attribute, Connect, Event,
+              Implement],
+        Body::Object(Port) => &[Group, Attribute, Connect, Event, Implement],
+        Body::Object(Bank) => &[Register, Group, Attribute, Connect, Event],
+        Body::Object(Register) => &[Field],
+        Body::Object(Connect) => &[Interface],
+        Body::Template | Body::Object(Group) =>
+            &[Bank, Port, Subdevice, Group, Register, Field, Attribute,
+              Connect, Event, Implement, Interface],
+        Body::Object(Attribute | Event | Field | Implement | Interface)
+            | Body::Code => &[],
+    }
+}
+
+// Declaration keywords other than objects, by body
+fn declaration_keywords(body: Body) -> &'static [&'static str] {
+    match body {
+        Body::TopLevel => &["param", "method", "is", "in each", "session",
+                            "saved", "hook", "#if", "import", "template",
+                            "typedef", "extern", "constant", "loggroup",
+                            "header", "footer"],
+        Body::Template => &["param", "method", "is", "in each", "session",
+                            "saved", "hook", "#if", "shared"],
+        Body::Object(CompObjectKind::Implement) => &["method", "#if"],
+        Body::Object(CompObjectKind::Interface) => &["#if"],
+        Body::Object(_) => &["param", "method", "is", "in each", "session",
+                             "saved", "hook", "#if"],
+        Body::Code => &[],
+    }
+}
+
+/// The standard methods that objects of a kind commonly override, with
+/// their parameters and returns
+pub fn override_methods(kind: CompObjectKind)
+                        -> &'static [(&'static str, &'static str)] {
+    match kind {
+        CompObjectKind::Register => &[
+            ("read", "() -> (uint64)"),
+            ("write", "(uint64 val)"),
+            ("init", "()"),
+            ("post_init", "()"),
+            ("read_register", "(uint64 enabled_bytes, void *aux) -> (uint64)"),
+            ("write_register",
+             "(uint64 value, uint64 enabled_bytes, void *aux)"),
+            ("get", "() -> (uint64)"),
+            ("set", "(uint64 value)"),
+        ],
+        CompObjectKind::Field => &[
+            ("read", "() -> (uint64)"),
+            ("write", "(uint64 val)"),
+            ("init", "()"),
+            ("post_init", "()"),
+            ("read_field", "(uint64 enabled_bits, void *aux) -> (uint64)"),
+            ("write_field", "(uint64 val, uint64 enabled_bits, void *aux)"),
+            ("get", "() -> (uint64)"),
+            ("set", "(uint64 value)"),
+        ],
+        CompObjectKind::Bank => &[
+            ("init", "()"),
+            ("post_init", "()"),
+            ("unmapped_read",
+             "(uint64 offset, uint64 bits, void *aux) -> (uint64) throws"),
+            ("unmapped_write",
+             "(uint64 offset, uint64 value, uint64 bits, void *aux) throws"),
+        ],
+        CompObjectKind::Event => &[
+            ("event", "(void *data)"),
+        ],
+        CompObjectKind::Implement | CompObjectKind::Interface => &[],
+        _ => &[("init", "()"), ("post_init", "()")],
+    }
+}
+
+fn markdown(value: String) -> Documentation {
+    Documentation::MarkupContent(MarkupContent {
+        kind: MarkupKind::Markdown,
+        value,
+    })
+}
+
+fn keyword_item(keyword: &str) -> CompletionItem {
+    CompletionItem {
+        label: keyword.to_string(),
+        kind: Some(CompletionItemKind::KEYWORD),
+        ..CompletionItem::default()
+    }
+}
+
+fn method_item(name: &str, signature: &str, snippets: bool)
+               -> CompletionItem {
+    let declaration = format!("method {}{}", name, signature);
+    let (insert_text, insert_text_format) = if snippets {
+        (format!("{} {{\n\t$0\n}}", declaration), InsertTextFormat::SNIPPET)
+    } else {
+        (format!("{} {{\n}}", declaration), InsertTextFormat::PLAIN_TEXT)
+    };
+    CompletionItem {
+        label: name.to_string(),
+        kind: Some(CompletionItemKind::METHOD),
+        detail: Some(declaration),
+        insert_text: Some(insert_text),
+        insert_text_format: Some(insert_text_format),
+        ..CompletionItem::default()
+    }
+}
+
+// Templates instantiated by a statement, rather than after 'is'
+fn instantiation_item(name: &str, description: Option<&String>)
+                      -> CompletionItem {
+    CompletionItem {
+        label: name.to_string(),
+        kind: Some(CompletionItemKind::CLASS),
+        detail: Some(format!("is {}", name)),
+        documentation: description.cloned().map(markdown),
+        insert_text: Some(format!("is {};", name)),
+        ..CompletionItem::default()
+    }
+}
+
+fn template_item(name: &str, description: Option<&String>) -> CompletionItem {
+    CompletionItem {
+        label: name.to_string(),
+        kind: Some(CompletionItemKind::CLASS),
+        detail: Some("template".to_string()),
+        documentation: description.cloned().map(markdown),
+        ..CompletionItem::default()
+    }
+}
+
+/// The completions of a declaration in a body, given the standard
+/// templates in `catalog`
+pub fn declaration_completions(body: Body, declaring: Body,
+                               catalog: &Catalog, snippets: bool)
+                               -> Vec<CompletionItem> {
+    let mut items: Vec<CompletionItem> = child_objects(declaring).iter()
+        .map(|kind|keyword_item(kind.kind_name()))
+        .chain(declaration_keywords(body).iter()
+               .map(|keyword|keyword_item(keyword)))
+        .collect();
+    let kind = match body {
+        Body::TopLevel => CompObjectKind::Device,
+        Body::Object(kind) => kind,
+        Body::Template | Body::Code => return items,
+    };
+    items.extend(override_methods(kind).iter().map(
+        |(name, signature)|method_item(name, signature, snippets)));
+    items.extend(catalog.templates.values()
+                 .filter(|template|template.applies_to.iter()
+                         .any(|applies|applies == kind.kind_name()))
+                 .map(|template|instantiation_item(
+                     &template.name, template.description.as_ref())));
+    items
+}
+
+fn is_identifier(name: &str) -> bool {
+    name.chars().next().is_some_and(|c|c.is_alphabetic() || c == '_')
+        && name.chars().all(|c|c.is_alphanumeric() || c == '_')
+}
+
+// The templates that can be instantiated in a file: those of the devices
+// it is in, or those it declares itself if it is in none, and those of the
+// catalog
+fn template_completions<O: Output>(ctx: &InitActionContext<O>,
+                                   canon_path: &CanonPath, catalog: &Catalog)
+                                   -> Vec<CompletionItem> {
+    let filter = ctx.device_active_contexts.lock().unwrap().clone();
+    let analysis = ctx.analysis.lock().unwrap();
+    let mut names: BTreeMap<String, Option<&String>> = BTreeMap::new();
+    for device in analysis.filtered_device_analysises_containing_file(
+        canon_path, Some(&filter)) {
+        // Missing templates have no location, and each file is also a
+        // template named by its path
+        names.extend(device.templates.templates.values()
+                     .filter(|template|template.location.is_some()
+                             && is_identifier(&template.name))
+                     .map(|template|(template.name.clone(), None)));
+    }
+    if let Ok(isolated) = analysis.get_isolated_analysis(canon_path) {
+        names.extend(isolated.toplevel.templates.iter()
+                     .map(|template|(template.obj.object.name.val.to_string(),
+                                     None)));
+    }
+    for template in catalog.templates.values() {
+        names.insert(template.name.clone(), template.description.as_ref());
+    }
+    names.into_iter()
+        // Templates starting with an underscore are internal to the
+        // standard library
+        .filter(|(name, _)|!name.starts_with('_'))
+        .map(|(name, description)|template_item(&name, description))
+        .collect()
+}
+
+fn interface_completions(catalog: &Catalog) -> Vec<CompletionItem> {
+    catalog.interfaces.values().map(|interface| {
+        let mut documentation = interface.description.clone()
+            .unwrap_or_default();
+        if !interface.methods.is_empty() {
+            documentation.push_str(&format!(
+                "\n```c\n{}\n```", interface.methods.join("\n")));
+        }
+        CompletionItem {
+            label: interface.name.clone(),
+            kind: Some(CompletionItemKind::INTERFACE),
+            detail: interface.import.as_ref()
+                .map(|import|format!("import \"{}\"", import)),
+            documentation: (!documentation.is_empty())
+                .then(||markdown(documentation.trim().to_string())),
+            ..CompletionItem::default()
+        }
+    }).collect()
+}
+
+/// The path written so far in the string of an import statement that a
+/// position is in
+pub fn import_prefix(text: &str, position: ZeroPosition) -> Option<&str> {
+    let line = text.lines().nth(position.row.0 as usize)?;
+    let end = line.char_indices().nth(position.col.0 as usize)
+        .map_or(line.len(), |(index, _)|index);
+    let rest = line[..end].trim_start().strip_prefix("import")?;
+    let prefix = rest.trim_start().strip_prefix('"')?;
+    (rest.starts_with(char::is_whitespace) && !prefix.contains('"'))
+        .then_some(prefix)
+}
+
+/// The files and directories that can complete the import path `prefix`,
+/// searched for in `search_dirs` as imports are, with the last component of
+/// `prefix` left for the client to filter on
+pub fn import_candidates(prefix: &str, search_dirs: &[PathBuf])
+                         -> BTreeMap<String, CompletionItemKind> {
+    let directory = &prefix[..prefix.rfind('/').map_or(0, |slash|slash + 1)];
+    let mut candidates = BTreeMap::new();
+    // The standard library keeps DML 1.4 files in a subdirectory
+    for dir in search_dirs.iter()
+        .flat_map(|dir|vec![dir.join(directory), dir.join("1.4")
+                            .join(directory)]) {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.filter_map(Result::ok) {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with('.') {
+                continue;
+            }
+            let path = entry.path();
+            if path.is_dir() {
+                candidates.insert(format!("{}/", name),
+                                  CompletionItemKind::FOLDER);
+            } else if path.extension().is_some_and(|ext|ext == "dml") {
+                candidates.insert(name, CompletionItemKind::FILE);
+            }
+        }
+    }
+    candidates
+}
+
+fn import_completions<O: Output>(ctx: &InitActionContext<O>,
+                                 canon_path: &CanonPath, prefix: &str,
+                                 position: ZeroPosition)
+                                 -> Vec<CompletionItem> {
+    let mut search_dirs = vec![];
+    // Relative imports are only resolved from the directory of the file
+    if !(prefix.starts_with("./") || prefix.starts_with("../")) {
+        search_dirs = ctx.import_search_dirs();
+    }
+    search_dirs.extend(canon_path.as_path().parent()
+                       .map(Path::to_path_buf));
+    // The last component of the path is replaced, so that clients do not
+    // need to know that paths are split by slashes
+    let written = prefix.rsplit('/').next().unwrap_or(prefix);
+    let start = Position::new(
+        position.row.0,
+        position.col.0 - written.encode_utf16().count() as u32);
+    let end = Position::new(position.row.0, position.col.0);
+    import_candidates(prefix, &search_dirs).into_iter()
+        .filter(|(name, _)|canon_path.as_path().file_name()
+                .is_none_or(|file|file.to_string_lossy() != *name))
+        .map(|(name, kind)|CompletionItem {
+            label: name.clone(),
+            kind: Some(kind),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                range: Range::new(start, end),
+                new_text: name,
+            })),
+            ..CompletionItem::default()
+        })
+        .collect()
+}
+
+/// The completions at a position of a file
+pub fn completions<O: Output>(ctx: &InitActionContext<O>,
+                              canon_path: &CanonPath, text: &str,
+                              position: ZeroPosition)
+                              -> Vec<CompletionItem> {
+    if let Some(prefix) = import_prefix(text, position) {
+        return import_completions(ctx, canon_path, prefix, position);
+    }
+    let Some(context) = completion_context(text, position) else {
+        return vec![];
+    };
+    let catalog = match ctx.simics_api() {
+        Some(version) => Catalog::builtin().for_simics_api(version),
+        None => Catalog::builtin(),
+    };
+    match context {
+        CompletionContext::Declaration { body, declaring } =>
+            declaration_completions(
+                body, declaring, &catalog,
+                ctx.client_capabilities.snippet_support()),
+        CompletionContext::Template =>
+            template_completions(ctx, canon_path, &catalog),
+        CompletionContext::Interface => interface_completions(&catalog),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SOURCE: &str = "dml 1.4;
+device test;
+bank b {
+    group g {
+        register r size 4 @ 0 is (read_only, ) {
+            re
+        }
+        #if (true) {\x20
+        }
+    }
+    method m() {\x20
+    }
+}
+";
+
+    fn context(row: u32, col: u32) -> Option<CompletionContext> {
+        completion_context(SOURCE, ZeroPosition::from_u32(row, col))
+    }
+
+    fn declaration(body: Body, declaring: Body) -> Option<CompletionContext> {
+        Some(CompletionContext::Declaration { body, declaring })
+    }
+
+    #[test]
+    fn test_completion_context() {
+        let bank = Body::Object(CompObjectKind::Bank);
+        let group = Body::Object(CompObjectKind::Group);
+        let register = Body::Object(CompObjectKind::Register);
+        assert_eq!(context(2, 0), declaration(Body::TopLevel,
+                                              Body::TopLevel));
+        assert_eq!(context(3, 4), declaration(bank, bank));
+        assert_eq!(context(5, 12), declaration(register, register));
+        assert_eq!(context(5, 14), declaration(register, register));
+        assert_eq!(context(8, 8), declaration(group, bank));
+        assert_eq!(context(4, 34), Some(CompletionContext::Template));
+        assert_eq!(context(4, 37), Some(CompletionContext::Template));
+        assert_eq!(context(4, 44), Some(CompletionContext::Template));
+        assert_eq!(context(4, 19), None);
+        assert_eq!(context(10, 17), None);
+        assert_eq!(context(12, 1), declaration(Body::TopLevel,
+                                               Body::TopLevel));
+    }
+
+    #[test]
+    fn test_declaration_completions() {
+        let register = Body::Object(CompObjectKind::Register);
+        let items = declaration_completions(register, register,
+                                            &Catalog::builtin(), false);
+        let labels: Vec<&str> = items.iter()
+            .map(|item|item.label.as_str()).collect();
+        for expected in ["field", "param", "read", "write", "init",
+                         "read_only"] {
+            assert!(labels.contains(&expected), "missing {}", expected);
+        }
+        for unexpected in ["register", "bank", "function_mapped_bank"] {
+            assert!(!labels.contains(&unexpected), "has {}", unexpected);
+        }
+        let read = items.iter().find(|item|item.label == "read").unwrap();
+        assert_eq!(read.insert_text.as_deref(),
+                   Some("method read() -> (uint64) {\n}"));
+    }
+
+    #[test]
+    fn test_interface_context() {
+        let text = "dml 1.4;\nimplement sig\nconnect c {\n    interface ";
+        assert_eq!(completion_context(text, ZeroPosition::from_u32(1, 12)),
+                   Some(CompletionContext::Interface));
+        assert_eq!(completion_context(text, ZeroPosition::from_u32(3, 14)),
+                   Some(CompletionContext::Interface));
+    }
+
+    #[test]
+    fn test_import_completions() {
+        let text = "dml 1.4;\nimport \"lib/sub\";\n  import \"\nlog \"";
+        let at = |row, col|import_prefix(text, ZeroPosition::from_u32(row,
+                                                                     col));
+        assert_eq!(at(1, 15), Some("lib/sub"));
+        assert_eq!(at(1, 12), Some("lib/"));
+        assert_eq!(at(1, 16), None);
+        assert_eq!(at(2, 10), Some(""));
+        assert_eq!(at(3, 5), None);
+
+        let root = std::env::temp_dir().join(
+            format!("dls-import-completion-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("lib/sub")).unwrap();
+        std::fs::create_dir_all(root.join("1.4/lib")).unwrap();
+        std::fs::write(root.join("lib/a.dml"), "").unwrap();
+        std::fs::write(root.join("lib/notes.txt"), "").unwrap();
+        std::fs::write(root.join("1.4/lib/b.dml"), "").unwrap();
+        let candidates: Vec<(String, CompletionItemKind)> =
+            import_candidates("lib/s", std::slice::from_ref(&root)).into_iter().collect();
+        assert_eq!(candidates, vec![
+            ("a.dml".to_string(), CompletionItemKind::FILE),
+            ("b.dml".to_string(), CompletionItemKind::FILE),
+            ("sub/".to_string(), CompletionItemKind::FOLDER),
+        ]);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
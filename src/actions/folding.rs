@@ -0,0 +1,302 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Folding ranges of the declarations, blocks and comments of a file, and the
+//! nested selection ranges of its syntax tree
+use lsp_types::{FoldingRange, FoldingRangeKind, Position, SelectionRange};
+
+use crate::analysis::parsing::lexer::TokenKind;
+use crate::analysis::parsing::structure::{DMLObject, DMLObjectContent,
+                                          CompositeObjectContent, HashElse,
+                                          ObjectStatements,
+                                          ObjectStatementsContent, TopAst};
+use crate::analysis::parsing::tree::{Content, TreeElement, TreeElementMember,
+                                     TreeElements, ZeroPosition};
+use crate::analysis::quick_fixes::{is_comment, tokens_with_comments, Token};
+use crate::analysis::ZeroRange;
+use crate::lsp_data::ls_util;
+
+fn fold(start: u32, end: u32, kind: FoldingRangeKind) -> FoldingRange {
+    FoldingRange {
+        start_line: start,
+        start_character: None,
+        end_line: end,
+        end_character: None,
+        kind: Some(kind),
+        collapsed_text: None,
+    }
+}
+
+// The fold of a declaration starting at `start` with the body `statements`,
+// and the folds of the declarations in the body
+fn body_folds(start: ZeroRange, statements: &ObjectStatements,
+              tokens: &[Token<'_>], folds: &mut Vec<FoldingRange>) {
+    if let Content::Some(ObjectStatementsContent::List(_, objects, _))
+        = &*statements.content {
+            folds.push(fold(start.row_start.0, statements.range.row_end.0,
+                            FoldingRangeKind::Region));
+            for object in objects {
+                object_folds(object, tokens, folds);
+            }
+        }
+}
+
+fn composite_folds(content: &CompositeObjectContent, tokens: &[Token<'_>],
+                   folds: &mut Vec<FoldingRange>) {
+    body_folds(content.kind.range(), &content.statements, tokens, folds);
+}
+
+// The folds of the braced blocks in a method, which are the body of the
+// method and the blocks of the statements in it
+fn method_folds(range: ZeroRange, tokens: &[Token<'_>],
+                folds: &mut Vec<FoldingRange>) {
+    let first = folds.len();
+    let mut opened = vec![];
+    for token in tokens.iter().filter(|token|range.contains_pos(
+        token.range.start())) {
+        match token.kind {
+            TokenKind::LBrace => opened.push(token.range.row_start.0),
+            TokenKind::RBrace => if let Some(start) = opened.pop() {
+                folds.push(fold(start, token.range.row_end.0,
+                                FoldingRangeKind::Region));
+            },
+            _ => (),
+        }
+    }
+    // The body closes last, and is folded from the start of the declaration
+    if let Some(body) = folds[first..].last_mut() {
+        body.start_line = range.row_start.0;
+    }
+}
+
+fn object_folds(object: &DMLObject, tokens: &[Token<'_>],
+                folds: &mut Vec<FoldingRange>) {
+    let Content::Some(content) = &*object.content else {
+        return;
+    };
+    match content {
+        DMLObjectContent::Attribute(content)
+            | DMLObjectContent::Bank(content)
+            | DMLObjectContent::Connection(content)
+            | DMLObjectContent::Event(content)
+            | DMLObjectContent::Group(content)
+            | DMLObjectContent::Implement(content)
+            | DMLObjectContent::Interface(content)
+            | DMLObjectContent::Port(content)
+            | DMLObjectContent::Subdevice(content) =>
+            composite_folds(content, tokens, folds),
+        DMLObjectContent::Field(content) =>
+            composite_folds(&content.obj, tokens, folds),
+        DMLObjectContent::Register(content) =>
+            composite_folds(&content.obj, tokens, folds),
+        DMLObjectContent::Template(content) =>
+            body_folds(content.template.range(), &content.statements,
+                       tokens, folds),
+        DMLObjectContent::InEach(content) =>
+            body_folds(content.intok.range(), &content.statements,
+                       tokens, folds),
+        DMLObjectContent::Method(_) =>
+            method_folds(object.range, tokens, folds),
+        DMLObjectContent::HashIf(content) => {
+            let start = folds.len();
+            body_folds(content.iftok.range(), &content.truestatements,
+                       tokens, folds);
+            match &content.elsebranch {
+                Some((elsetok, HashElse::Statements(statements))) =>
+                    body_folds(elsetok.range(), statements, tokens, folds),
+                Some((_, HashElse::HashIf(hashif))) =>
+                    object_folds(hashif, tokens, folds),
+                None => (),
+            }
+            // Keep the `#else` line visible when folding the true branch
+            if let (Some(truefold), Some((elsetok, _))) =
+                (folds.get_mut(start), &content.elsebranch) {
+                    if truefold.end_line == elsetok.range().row_start.0 {
+                        truefold.end_line -= 1;
+                    }
+                }
+        },
+        _ => (),
+    }
+}
+
+// The folds of blocks of comments, which are comments on consecutive lines
+// with nothing else on them, or multi-line comments
+fn comment_folds(tokens: &[Token<'_>], folds: &mut Vec<FoldingRange>) {
+    let mut block: Option<(u32, u32)> = None;
+    let mut previous_row = None;
+    for token in tokens {
+        let alone = previous_row != Some(token.range.row_start.0);
+        previous_row = Some(token.range.row_end.0);
+        if is_comment(token.kind) && alone {
+            block = match block {
+                Some((start, end)) if end + 1 == token.range.row_start.0 =>
+                    Some((start, token.range.row_end.0)),
+                _ => {
+                    if let Some((start, end)) = block {
+                        folds.push(fold(start, end, FoldingRangeKind::Comment));
+                    }
+                    Some((token.range.row_start.0, token.range.row_end.0))
+                },
+            };
+        } else if let Some((start, end)) = block.take() {
+            folds.push(fold(start, end, FoldingRangeKind::Comment));
+        }
+    }
+    if let Some((start, end)) = block {
+        folds.push(fold(start, end, FoldingRangeKind::Comment));
+    }
+}
+
+/// The folding ranges of a file with the syntax tree `ast`. Ranges starting
+/// on the same line are reduced to the outermost of them.
+pub fn folding_ranges(ast: &TopAst, text: &str) -> Vec<FoldingRange> {
+    let tokens = tokens_with_comments(text);
+    let mut folds = vec![];
+    let mut imports: Option<(u32, u32)> = None;
+    for object in &ast.declarations {
+        if let Content::Some(DMLObjectContent::Import(_)) = &*object.content {
+            imports = Some((imports.map_or(object.range.row_start.0,
+                                           |(start, _)|start),
+                            object.range.row_end.0));
+            continue;
+        }
+        if let Some((start, end)) = imports.take() {
+            folds.push(fold(start, end, FoldingRangeKind::Imports));
+        }
+        object_folds(object, &tokens, &mut folds);
+    }
+    if let Some((start, end)) = imports {
+        folds.push(fold(start, end, FoldingRangeKind::Imports));
+    }
+    comment_folds(&tokens, &mut folds);
+    folds.retain(|fold|fold.start_line < fold.end_line);
+    folds.sort_by_key(|fold|(fold.start_line,
+                             std::cmp::Reverse(fold.end_line)));
+    folds.dedup_by_key(|fold|fold.start_line);
+    folds
+}
+
+fn contains_inclusive(range: &ZeroRange, position: ZeroPosition) -> bool {
+    range.start() <= position && position <= range.end()
+}
+
+// The node containing a position, of nodes that may overlap. Nodes with the
+// position before their end are preferred over those ending at it, and later
+// nodes over those they overlap.
+fn containing_sub<'t>(subs: &TreeElements<'t>, position: ZeroPosition)
+                      -> Option<&'t dyn TreeElementMember> {
+    let latest = |contains: &dyn Fn(&ZeroRange) -> bool| subs.iter()
+        .filter(|sub|contains(&sub.range()))
+        .max_by_key(|sub|sub.range().start())
+        .copied();
+    latest(&|range|range.contains_pos(position))
+        .or_else(||latest(&|range|contains_inclusive(range, position)))
+}
+
+/// The ranges of the nodes of the syntax tree `ast` that contain
+/// `position`, innermost first
+pub fn enclosing_ranges(ast: &TopAst, position: ZeroPosition)
+                        -> Vec<ZeroRange> {
+    let mut ranges = vec![];
+    let mut subs = ast.subs();
+    if contains_inclusive(&ast.range(), position) {
+        ranges.push(ast.range());
+    }
+    while let Some(sub) = containing_sub(&subs, position) {
+            let range = sub.range();
+            if range.start() < range.end() && ranges.last() != Some(&range) {
+                ranges.push(range);
+            }
+            subs = sub.subs();
+        }
+    ranges.reverse();
+    ranges
+}
+
+/// The selection range at `position`, expanding through the nodes of the
+/// syntax tree `ast` that contain it
+pub fn selection_range(ast: &TopAst, position: Position) -> SelectionRange {
+    let ranges = enclosing_ranges(ast, ls_util::position_to_dls(position));
+    let mut selection: Option<SelectionRange> = None;
+    for range in ranges.into_iter().rev() {
+        selection = Some(SelectionRange {
+            range: ls_util::dls_to_range(range),
+            parent: selection.map(Box::new),
+        });
+    }
+    selection.unwrap_or(SelectionRange {
+        range: lsp_types::Range::new(position, position),
+        parent: None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::PathBuf;
+    use std::str::FromStr;
+    use crate::analysis::{parse_file, FileSpec};
+    use crate::vfs::TextFile;
+
+    fn ast(source: &str) -> TopAst {
+        let path = PathBuf::from("test.dml");
+        let file = TextFile::from_str(source).unwrap();
+        let filespec = FileSpec { path: &path, file: &file };
+        parse_file(&path, filespec).unwrap().0
+    }
+
+    const SOURCE: &str = "dml 1.4;
+device test;
+import \"a.dml\";
+import \"b.dml\";
+// A bank
+// with registers
+bank b {
+    register r size 4 @ 0x0 {
+        method write(uint64 value) {
+            if (value == 0) {
+                log info: \"zero\";
+            }
+        }
+    }
+    #if (true) {
+        register s size 4 @ 0x4;
+    } #else {
+        register s size 2 @ 0x4;
+    }
+}
+";
+
+    #[test]
+    fn test_folding_ranges() {
+        let folds: Vec<(u32, u32, FoldingRangeKind)> = folding_ranges(
+            &ast(SOURCE), SOURCE).into_iter()
+            .map(|fold|(fold.start_line, fold.end_line, fold.kind.unwrap()))
+            .collect();
+        assert_eq!(folds, vec![
+            (2, 3, FoldingRangeKind::Imports),
+            (4, 5, FoldingRangeKind::Comment),
+            (6, 19, FoldingRangeKind::Region),
+            (7, 13, FoldingRangeKind::Region),
+            (8, 12, FoldingRangeKind::Region),
+            (9, 11, FoldingRangeKind::Region),
+            (14, 15, FoldingRangeKind::Region),
+            (16, 18, FoldingRangeKind::Region),
+        ]);
+    }
+
+    #[test]
+    fn test_enclosing_ranges() {
+        let ranges = enclosing_ranges(&ast(SOURCE),
+                                      ZeroPosition::from_u32(10, 28));
+        // The string, the log statement, the block of the if statement, the
+        // if statement...
+        assert_eq!(ranges[0], ZeroRange::from_u32(10, 10, 26, 32));
+        assert_eq!(ranges[1], ZeroRange::from_u32(10, 10, 16, 33));
+        assert!(ranges.windows(2).all(|pair|contains_inclusive(
+            &pair[1], pair[0].start()) && contains_inclusive(
+                &pair[1], pair[0].end()) && pair[0] != pair[1]));
+        assert_eq!(ranges.last().unwrap().start(),
+                   ZeroPosition::from_u32(0, 0));
+    }
+}
@@ -2,10 +2,25 @@
 //  SPDX-License-Identifier: Apache-2.0 and MIT
 use log::*;
 
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::sync::Arc;
+
 use crate::span::{Range, ZeroIndexed};
 use serde::{Deserialize, Serialize};
 
-use crate::actions::InitActionContext;
+use crate::actions::{AnalysisCoverageSpec, AnalysisProgressKind,
+                     AnalysisWaitKind, InitActionContext};
+use crate::actions::inlay_hints::{list_bits, list_offsets};
+use crate::actions::semantic_lookup::describe_symbols_at_fp;
+use crate::analysis::{DeviceAnalysis, Named, SymbolRef};
+use crate::analysis::layout::{eval_param, instances, register_offsets};
+use crate::analysis::structure::objects::CompObjectKind;
+use crate::analysis::symbols::DMLSymbolKind;
+use crate::analysis::templating::objects::{DMLCompositeObject, DMLObject,
+                                           StructureContainer};
+use crate::analysis::templating::traits::DMLTemplate;
+use crate::file_management::CanonPath;
 use crate::lsp_data::*;
 use crate::server::{Output, ResponseError};
 
@@ -22,8 +37,186 @@ pub fn tooltip<O: Output>(
 ) -> Result<Tooltip, ResponseError> {
     let hover_file_path = parse_file_path!(&params.text_document.uri, "hover")?;
     let hover_span = ctx.convert_pos_to_span(hover_file_path, params.position);
-    // TODO: sort out how to handle hovers, and what info they should provide
-    let contents = vec![];
+    let contents: Vec<MarkedString> = symbol_descriptions(ctx, params)
+        .into_iter().map(MarkedString::String).collect();
     trace!("tooltip: contents.len: {}", contents.len());
     Ok(Tooltip { contents, range: hover_span.range })
 }
+
+// Markdown descriptions of the symbols at the hovered position, without
+// duplicates from the several devices a file may be analyzed in
+fn symbol_descriptions<O: Output>(ctx: &InitActionContext<O>,
+                                  params: &TextDocumentPositionParams)
+                                  -> Vec<String> {
+    let Some(fp) = ctx.text_doc_pos_to_pos(params, "hover") else {
+        return vec![];
+    };
+    let Ok(canon_path) = CanonPath::try_from_path_buf(fp.path()) else {
+        return vec![];
+    };
+    wait_for_device_path!(ctx, canon_path);
+    match describe_symbols_at_fp(ctx, &fp, &mut HashSet::new(),
+                                 describe_symbol) {
+        Ok(mut descriptions) => {
+            let mut seen = HashSet::new();
+            descriptions.retain(|description|seen.insert(description.clone()));
+            descriptions
+        },
+        Err(error) => {
+            debug!("No hover at {:?}: {:?}", fp, error);
+            vec![]
+        },
+    }
+}
+
+fn describe_symbol(device: &DeviceAnalysis, symbol: &SymbolRef)
+                   -> Option<String> {
+    let (kind, loc) = {
+        let symbol = symbol.lock().unwrap();
+        (symbol.kind, symbol.loc)
+    };
+    match kind {
+        DMLSymbolKind::CompObject(CompObjectKind::Register) => device
+            .symbol_info.object_symbols.iter()
+            .find(|(_, object_symbol)|Arc::ptr_eq(object_symbol, symbol))
+            .and_then(|(key, _)|device.objects.get(*key))
+            .map(|register|describe_register(&device.objects, register)),
+        DMLSymbolKind::Template => device.templates.templates.values()
+            .find(|template|template.location == Some(loc))
+            .map(|template|describe_template(template)),
+        _ => None,
+    }
+}
+
+// The name of an object qualified by the objects it is in, with the index
+// variables of arrays
+fn qualified_name(container: &StructureContainer, obj: &DMLCompositeObject)
+                  -> String {
+    let mut parts = vec![];
+    let mut current = Some(obj);
+    while let Some(obj) = current.filter(
+        |obj|obj.kind != CompObjectKind::Device) {
+        let mut part = obj.identity.val.to_string();
+        for dim in &obj.arraydimvars {
+            part.push_str(&format!("[{}]", dim.indexvar.val));
+        }
+        parts.push(part);
+        current = obj.parent.and_then(|parent|container.get(parent));
+    }
+    parts.reverse();
+    parts.join(".")
+}
+
+fn describe_register(container: &StructureContainer,
+                     register: &DMLCompositeObject) -> String {
+    let first = instances(container, register)
+        .and_then(|instances|instances.into_iter().next())
+        .unwrap_or_default();
+    let value = |name| eval_param(container, register, name, &first);
+    let unknown = || "unknown".to_string();
+    let mut description = format!(
+        "```dml\nregister {}\n```\n", qualified_name(container, register));
+    description.push_str(&format!(
+        "- Size: {}\n", value("size").map_or_else(unknown,
+                                                  |size|size.to_string())));
+    description.push_str(&format!(
+        "- Offset: {}\n", register_offsets(container, register)
+            .map_or_else(unknown, |offsets|list_offsets(&offsets))));
+    description.push_str(&format!(
+        "- Reset value: {}\n", value("init_val").map_or_else(
+            unknown, |init|format!("{:#x}", init))));
+
+    // The fields of the first element of the register, most significant
+    // first
+    let mut fields: Vec<(Option<(u64, u64)>, String)> = vec![];
+    for field in register.components.values().filter_map(|component| {
+        match component {
+            DMLObject::CompObject(key) => container.get(*key)
+                .filter(|obj|obj.kind == CompObjectKind::Field),
+            _ => None,
+        }
+    }) {
+        for indices in instances(container, field).into_iter().flatten()
+            .filter(|indices|indices.iter().all(
+                |(key, vars)|*key == field.key
+                    || vars.iter().all(|(_, index)|*index == 0))) {
+                let bit = |name|eval_param(container, field, name, &indices)
+                    .and_then(|bit|u64::try_from(bit).ok());
+                let mut name = field.identity.val.to_string();
+                for (_, index) in indices.get(&field.key).into_iter()
+                    .flatten() {
+                    name.push_str(&format!("[{}]", index));
+                }
+                fields.push((bit("msb").zip(bit("lsb")), name));
+            }
+    }
+    if !fields.is_empty() {
+        fields.sort_by(|(bits, name), (other_bits, other_name)|
+                       other_bits.map(|(_, lsb)|lsb)
+                       .cmp(&bits.map(|(_, lsb)|lsb))
+                       .then_with(||name.cmp(other_name)));
+        let fields: Vec<(String, String)> = fields.into_iter()
+            .map(|(bits, name)|(bits.map_or_else(
+                ||"?".to_string(), |bits|list_bits(&[bits])), name))
+            .collect();
+        let width = fields.iter().map(|(bits, _)|bits.chars().count())
+            .max().unwrap_or(0);
+        description.push_str("\nFields:\n```\n");
+        for (bits, name) in fields {
+            description.push_str(&format!("{:width$}  {}\n", bits, name,
+                                          width = width));
+        }
+        description.push_str("```\n");
+    }
+    description
+}
+
+// What a template adds to the objects instantiating it, directly and
+// through the templates it instantiates in turn
+fn describe_template(template: &Arc<DMLTemplate>) -> String {
+    let mut description = format!("```dml\ntemplate {}\n```\n", template.name);
+    let mut seen: HashSet<&str> = HashSet::new();
+    let mut queue = vec![template];
+    while !queue.is_empty() {
+        let current = queue.remove(0);
+        if !seen.insert(&current.name) {
+            continue;
+        }
+        let spec = &current.spec;
+        let mut params: Vec<String> = spec.params.iter()
+            .map(|param|param.get_name()).collect();
+        let mut methods: Vec<String> = spec.methods.iter()
+            .map(|method|method.get_name()).collect();
+        let mut objects: Vec<String> = spec.subobjs.keys()
+            .map(|obj|format!("{} {}", obj.obj.kind.kind.kind_name(),
+                              obj.get_name())).collect();
+        for list in [&mut params, &mut methods, &mut objects] {
+            list.sort();
+            list.dedup();
+        }
+        let lines: Vec<String> = vec![("Parameters", params),
+                                      ("Methods", methods),
+                                      ("Objects", objects)].into_iter()
+            .filter(|(_, names)|!names.is_empty())
+            .map(|(what, names)|format!(
+                "- {}: {}", what, names.iter()
+                    .map(|name|format!("`{}`", name))
+                    .collect::<Vec<_>>().join(", ")))
+            .collect();
+        if !lines.is_empty() {
+            if !Arc::ptr_eq(current, template) {
+                description.push_str(
+                    &format!("\nFrom `{}`:\n", current.name));
+            }
+            for line in lines {
+                description.push_str(&line);
+                description.push('\n');
+            }
+        }
+        let mut instantiated: Vec<&Arc<DMLTemplate>> =
+            spec.instantiations.values().flatten().collect();
+        instantiated.sort_by(|a, b|a.name.cmp(&b.name));
+        queue.extend(instantiated);
+    }
+    description
+}
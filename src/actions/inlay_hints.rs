@@ -0,0 +1,180 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Inlay hints showing the evaluated offsets of registers and bit ranges of
+//! fields next to their declarations
+use std::collections::BTreeMap;
+
+use lsp_types::{InlayHint, InlayHintLabel, Position};
+
+use crate::analysis::DeviceAnalysis;
+use crate::analysis::layout::{field_bits, instances, register_offsets,
+                              UNMAPPED_OFFSET};
+use crate::analysis::structure::expressions::ExpressionKind;
+use crate::analysis::structure::objects::{CompObjectKind, ParamValue};
+use crate::analysis::templating::objects::DMLCompositeObject;
+use crate::analysis::ZeroRange;
+use crate::span::PathBufKey;
+
+// How many elements of an array are listed before the last one
+const LISTED_ELEMENTS: usize = 3;
+
+// List the values of the elements of an array, eliding the middle of long
+// ones
+fn list_values<T>(values: &[T], show: impl Fn(&T) -> String) -> String {
+    if values.len() <= LISTED_ELEMENTS + 1 {
+        values.iter().map(show).collect::<Vec<_>>().join(", ")
+    } else {
+        format!("{}, …, {}",
+                values[..LISTED_ELEMENTS].iter().map(&show)
+                .collect::<Vec<_>>().join(", "),
+                show(values.last().unwrap()))
+    }
+}
+
+/// The offsets of the elements of a register, as listed in hints
+pub fn list_offsets(offsets: &[u64]) -> String {
+    list_values(offsets, |offset| if *offset == UNMAPPED_OFFSET {
+        "unmapped".to_string()
+    } else {
+        format!("{:#x}", offset)
+    })
+}
+
+/// The bit ranges of the elements of a field, as listed in hints
+pub fn list_bits(bits: &[(u64, u64)]) -> String {
+    list_values(bits, |(msb, lsb)| if msb == lsb {
+        format!("[{}]", msb)
+    } else {
+        format!("[{}:{}]", msb, lsb)
+    })
+}
+
+fn offsets_label(offsets: &[u64]) -> String {
+    if offsets.iter().all(|offset|*offset == UNMAPPED_OFFSET) {
+        "unmapped".to_string()
+    } else {
+        format!("@ {}", list_offsets(offsets))
+    }
+}
+
+fn bits_label(bits: &[(u64, u64)]) -> String {
+    format!("@ {}", list_bits(bits))
+}
+
+// Whether parameters of an object are set directly to integer literals, in
+// which case their values are already in plain sight
+fn is_literal(obj: &DMLCompositeObject, params: &[&str]) -> bool {
+    params.iter().all(|name|obj.get_param(*name).is_some_and(
+        |param|matches!(&param.get_likely_definition().value,
+                        Some(ParamValue::Set(expr))
+                        if matches!(**expr,
+                                    ExpressionKind::IntegerLiteral(_)))))
+}
+
+// The values of the elements of an object, or only those of the elements
+// of its own arrays if these are the same in every element of its parent
+fn local_values<T: PartialEq>(device: &DeviceAnalysis,
+                              obj: &DMLCompositeObject, mut values: Vec<T>)
+                              -> Vec<T> {
+    let parents = obj.parent.and_then(|parent|device.objects.get(parent))
+        .and_then(|parent|instances(&device.objects, parent))
+        .map_or(1, |instances|instances.len());
+    if parents > 1 && values.len().is_multiple_of(parents) {
+        let local = values.len() / parents;
+        if values.chunks(local).all(|chunk|chunk == &values[..local]) {
+            values.truncate(local);
+        }
+    }
+    values
+}
+
+// The hint label of a register or field, if it has a layout worth showing
+fn layout_label(device: &DeviceAnalysis, obj: &DMLCompositeObject)
+                -> Option<String> {
+    let (params, values, label): (&[&str], _, _) = match obj.kind {
+        CompObjectKind::Register => {
+            let offsets = local_values(
+                device, obj, register_offsets(&device.objects, obj)?);
+            (&["offset"], offsets.len(), offsets_label(&offsets))
+        },
+        CompObjectKind::Field => {
+            let bits = local_values(
+                device, obj, field_bits(&device.objects, obj)?);
+            (&["msb", "lsb"], bits.len(), bits_label(&bits))
+        },
+        _ => return None,
+    };
+    if values == 1 && obj.arraydimvars.is_empty() && is_literal(obj, params) {
+        None
+    } else {
+        Some(label)
+    }
+}
+
+// Where the hint of an object declared at `loc` goes: after its name and
+// the array dimensions that follow it
+fn hint_position(obj: &DMLCompositeObject, loc: ZeroRange) -> Position {
+    let end = obj.arraydimvars.iter()
+        .map(|dim|dim.span.range)
+        .filter(|dim|dim.row_start == loc.row_end
+                && dim.col_start >= loc.col_end)
+        .map(|dim|dim.col_end)
+        .fold(loc.col_end, std::cmp::max);
+    Position::new(loc.row_end.0, end.0)
+}
+
+/// The layout hints of the registers and fields declared in `file`, as
+/// evaluated in `devices`. Declarations whose layout differs between the
+/// objects they declare, such as those in templates, are not given hints.
+pub fn file_hints(devices: &[&DeviceAnalysis], file: PathBufKey)
+                  -> Vec<InlayHint> {
+    let mut labels: BTreeMap<(u32, u32), Option<String>> = BTreeMap::new();
+    for device in devices {
+        for obj in device.objects.values() {
+            let locs: Vec<ZeroRange> = obj.all_decls.iter()
+                .filter(|spec|spec.loc.file == file)
+                .map(|spec|spec.loc.range).collect();
+            if locs.is_empty() {
+                continue;
+            }
+            let label = layout_label(device, obj);
+            for loc in locs {
+                let position = hint_position(obj, loc);
+                labels.entry((position.line, position.character))
+                    .and_modify(|existing| if *existing != label {
+                        *existing = None;
+                    })
+                    .or_insert_with(||label.clone());
+            }
+        }
+    }
+    labels.into_iter()
+        .filter_map(|((line, character), label)|label.map(|label|InlayHint {
+            position: Position::new(line, character),
+            label: InlayHintLabel::String(label),
+            kind: None,
+            text_edits: None,
+            tooltip: None,
+            padding_left: Some(true),
+            padding_right: None,
+            data: None,
+        }))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_layout_labels() {
+        assert_eq!(offsets_label(&[0x10]), "@ 0x10");
+        assert_eq!(offsets_label(&[0, 4, 8, 12]), "@ 0x0, 0x4, 0x8, 0xc");
+        assert_eq!(offsets_label(&[0, 4, 8, 12, 16]),
+                   "@ 0x0, 0x4, 0x8, …, 0x10");
+        assert_eq!(offsets_label(&[UNMAPPED_OFFSET]), "unmapped");
+        assert_eq!(offsets_label(&[0, UNMAPPED_OFFSET]), "@ 0x0, unmapped");
+        assert_eq!(bits_label(&[(3, 0), (7, 4)]), "@ [3:0], [7:4]");
+        assert_eq!(bits_label(&[(5, 5)]), "@ [5]");
+    }
+}
@@ -8,6 +8,7 @@ use thiserror::Error;
 use crossbeam::channel;
 use serde::Deserialize;
 use serde_json::json;
+use rayon::prelude::*;
 
 use std::collections::{HashMap, HashSet};
 use std::cmp::Ordering;
@@ -18,27 +19,39 @@ use std::sync::{Arc, Mutex};
 
 use crate::actions::analysis_storage::AnalysisStorage;
 use crate::actions::analysis_queue::AnalysisQueue;
+use crate::actions::requests::{context_to_workspace_symbols, IndexedFiles,
+                               MemoryStats, ServerStatus};
+use crate::actions::symbol_index::SymbolIndex;
 use crate::actions::progress::{AnalysisProgressNotifier,
                                AnalysisDiagnosticsNotifier,
                                DiagnosticsNotifier,
+                               IndexingProgress,
                                ProgressNotifier};
-use crate::analysis::DMLError;
+pub use crate::analysis::SourcedDMLError;
 use crate::analysis::IMPLICIT_IMPORTS;
+use crate::analysis::intern;
+use crate::analysis::stdlib_versions::SimicsApiVersion;
 use crate::analysis::structure::expressions::Expression;
-use crate::concurrency::{Jobs, ConcurrentJob};
+use crate::concurrency::{install_analysis, set_analysis_threads, Jobs,
+                         ConcurrentJob};
 use crate::config::{Config, DeviceContextMode};
-use crate::file_management::{PathResolver, CanonPath};
-use crate::lint::{LintCfg, maybe_parse_lint_cfg};
+use crate::file_management::{dml_files, PathResolver, CanonPath};
+use crate::lint::LintCfg;
+use crate::project::{ProjectConfig, PROJECT_FILE};
 use crate::lsp_data;
 use crate::lsp_data::*;
+use crate::lsp_data::notification::DidChangeWatchedFiles;
+use crate::lsp_data::request::{RegisterCapability, UnregisterCapability};
 use crate::lsp_data::ls_util::{dls_to_range, dls_to_location};
 use crate::server::{Output, ServerToHandle, error_message,
-                    Request, RequestId, SentRequest};
+                    maybe_parse_lint_cfg, maybe_project_lint_cfg, Request,
+                    RequestId, SentRequest};
 use crate::server::message::RawResponse;
 use crate::server::dispatch::HandleResponseType;
 use crate::Span;
 use crate::span;
 use crate::span::{ZeroIndexed, FilePosition};
+use crate::stats;
 use crate::vfs::Vfs;
 
 use jsonrpc::error::{standard_error, StandardError};
@@ -87,12 +100,20 @@ macro_rules! ignore_non_file_uri {
 
 pub mod analysis_storage;
 pub mod analysis_queue;
+pub mod call_hierarchy;
+pub mod code_lens;
+pub mod completion;
+pub mod folding;
 pub mod hover;
+pub mod inlay_hints;
 pub mod notifications;
 pub mod requests;
 pub mod progress;
+pub mod register_map;
 pub mod work_pool;
 pub mod semantic_lookup;
+pub mod semantic_tokens;
+pub mod symbol_index;
 
 /// Persistent context shared across all requests and notifications.
 pub enum ActionContext<O: Output> {
@@ -174,6 +195,19 @@ impl <O: Output> ActionContext<O> {
             ActionContext::Init(ctx) => ctx.pid,
         }
     }
+
+    /// Warm up the server right after it is initialized, see
+    /// `InitActionContext::warmup`
+    pub fn warmup_on_init(&mut self) {
+        if let ActionContext::Uninit(ctx) = self {
+            ctx.warmup = true;
+        }
+    }
+
+    /// Whether the server should be warmed up right after it is initialized
+    pub fn wants_warmup(&self) -> bool {
+        matches!(self, ActionContext::Uninit(ctx) if ctx.warmup)
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -187,6 +221,7 @@ pub struct CompilationDefine {
 pub struct CompilationInfo {
     pub extra_defines: Vec<CompilationDefine>,
     pub include_paths: HashSet<PathBuf>,
+    pub simics_api: Option<SimicsApiVersion>,
 }
 
 pub type CompilationInfoStorage = HashMap<CanonPath, CompilationInfo>;
@@ -255,6 +290,8 @@ pub struct InitActionContext<O: Output> {
     // Queues analysis jobs so that we don't over-use the CPU.
     analysis_queue: Arc<AnalysisQueue>,
     current_notifier: Arc<Mutex<Option<String>>>,
+    // progress of the files queued by warmup
+    indexing: Arc<Mutex<Option<IndexingProgress<O>>>>,
 
     // Set to true when a potentially mutating request is received. Set to false
     // if a change arrives. We can thus tell if the DLS has been quiescent while
@@ -263,12 +300,18 @@ pub struct InitActionContext<O: Output> {
 
     // the root workspaces
     pub workspace_roots: Arc<Mutex<Vec<Workspace>>>,
+    // persisted symbol indexes, by canonical workspace root
+    pub symbol_indexes: Arc<Mutex<HashMap<PathBuf, SymbolIndex>>>,
 
     pub cached_path_resolver: Arc<Mutex<Option<PathResolver>>>,
 
     // directly opened files
     pub direct_opens: Arc<Mutex<HashSet<CanonPath>>>,
     pub compilation_info: Arc<Mutex<CompilationInfoStorage>>,
+    // the project files of the workspace roots, by root
+    pub project_configs: Arc<Mutex<Vec<(PathBuf, ProjectConfig)>>>,
+    // the file watches last registered with the client
+    registered_watches: Arc<Mutex<Option<Value>>>,
 
     // maps files to the paths of device contexts they should be
     // analyzed under
@@ -301,6 +344,7 @@ pub struct UninitActionContext {
     vfs: Arc<Vfs>,
     config: Arc<Mutex<Config>>,
     pid: u32,
+    warmup: bool,
 }
 
 impl UninitActionContext {
@@ -309,7 +353,8 @@ impl UninitActionContext {
         vfs: Arc<Vfs>,
         config: Arc<Mutex<Config>>,
     ) -> UninitActionContext {
-        UninitActionContext { analysis, vfs, config, pid: ::std::process::id() }
+        UninitActionContext { analysis, vfs, config, pid: ::std::process::id(),
+                              warmup: false }
     }
 }
 
@@ -335,12 +380,6 @@ impl ContextDefinition {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct SourcedDMLError {
-    pub error: DMLError,
-    pub source: &'static str,
-}
-
 impl SourcedDMLError {
     pub fn to_diagnostic(&self) -> Diagnostic {
         Diagnostic::new(
@@ -378,6 +417,7 @@ impl <O: Output> InitActionContext<O> {
             analysis_queue: Arc::new(AnalysisQueue::init(
                 Arc::clone(&shut_down))),
             current_notifier: Arc::default(),
+            indexing: Arc::default(),
             config,
             lint_config: Arc::new(Mutex::new(LintCfg::default())),
             jobs: Arc::default(),
@@ -393,7 +433,10 @@ impl <O: Output> InitActionContext<O> {
             shut_down,
             pid,
             workspace_roots: Arc::default(),
+            symbol_indexes: Arc::default(),
             compilation_info: Arc::default(),
+            project_configs: Arc::default(),
+            registered_watches: Arc::default(),
             sent_warnings: Arc::default(),
             device_active_contexts: Arc::default(),
             previously_checked_contexts: Arc::default(),
@@ -426,16 +469,191 @@ impl <O: Output> InitActionContext<O> {
                              remove: Vec<Workspace>,
                              out: &O) {
         let any_change = !(add.is_empty() && remove.is_empty());
+        self.update_symbol_indexes(&add, &remove);
         if let Ok(mut workspaces) = self.workspace_roots.lock() {
             workspaces.retain(|workspace|
                               remove.iter().all(|rem|rem != workspace));
             workspaces.append(&mut add);
         }
         if any_change {
+            self.update_project_configs(out);
             self.update_compilation_info(out);
         }
     }
 
+    /// Re-read the project files of the workspace roots, and re-resolve
+    /// imports and re-load lint rules by them
+    pub fn update_project_configs(&self, out: &O) {
+        trace!("Updating project configs");
+        let roots: Vec<PathBuf> = self.workspace_roots.lock().unwrap().iter()
+            .filter_map(|w|parse_file_path!(&w.uri, "workspace").ok())
+            .collect();
+        let mut projects = vec![];
+        for root in roots {
+            let path = root.join(PROJECT_FILE);
+            if !path.is_file() {
+                continue;
+            }
+            match ProjectConfig::load(&path) {
+                Ok(project) => {
+                    debug!("Loaded project config {:?}", project);
+                    projects.push((root, project));
+                },
+                Err(e) => {
+                    error!("Failed to load project config: {}", e);
+                    error_message(out, e.to_string());
+                },
+            }
+        }
+        *self.project_configs.lock().unwrap() = projects;
+        self.cached_path_resolver.lock().unwrap().take();
+        let resolver = self.construct_resolver();
+        self.analysis.lock().unwrap().update_all_context_dependencies(resolver);
+        self.update_linter_config(out);
+        self.update_file_watches(false, out);
+    }
+
+    /// Register the files we watch for changes with the client, replacing
+    /// the previous registration if the include paths have changed since.
+    /// Nothing is registered before the client is initialized, that is
+    /// unless `initial` is set or something was registered already.
+    pub fn update_file_watches(&self, initial: bool, out: &O) {
+        const WATCH_ID: &str = "dls-watch";
+        let method = <DidChangeWatchedFiles as LSPNotification>::METHOD;
+        let watchers = FileWatch::new(self).watchers_config();
+        let mut registered = self.registered_watches.lock().unwrap();
+        match registered.as_ref() {
+            None if !initial => return,
+            Some(previous) if *previous == watchers => return,
+            Some(_) => {
+                debug!("Watched files changed, re-registering");
+                self.send_request::<UnregisterCapability>(
+                    UnregistrationParams {
+                        unregisterations: vec![Unregistration {
+                            id: WATCH_ID.to_owned(),
+                            method: method.to_owned(),
+                        }],
+                    }, out);
+            },
+            None => (),
+        }
+        self.send_request::<RegisterCapability>(RegistrationParams {
+            registrations: vec![Registration {
+                id: WATCH_ID.to_owned(),
+                method: method.to_owned(),
+                register_options: Some(watchers.clone()),
+            }],
+        }, out);
+        *registered = Some(watchers);
+    }
+
+    /// Re-analyze DML files that were changed outside of the editor, such
+    /// as shared files of the include paths edited elsewhere, and the
+    /// devices that import them. Open files are left to the editor.
+    pub fn reanalyze_changed_files(&self, changes: &[FileEvent], out: &O) {
+        let mut created_or_deleted = false;
+        let mut orphaned_devices: HashSet<CanonPath> = HashSet::default();
+        for change in changes {
+            let Ok(path) = parse_file_path!(&change.uri, "watched file")
+            else { continue; };
+            let Some(canon_path) = CanonPath::from_removed_path_buf(
+                path.clone()) else { continue; };
+            if self.direct_opens.lock().unwrap().contains(&canon_path) {
+                continue;
+            }
+            debug!("{} changed on disk ({:?})", canon_path.as_str(),
+                   change.typ);
+            self.vfs.flush_file(&path).ok();
+            self.vfs.flush_file(canon_path.as_path()).ok();
+            created_or_deleted |= change.typ != FileChangeType::CHANGED;
+            let mut analysis = self.analysis.lock().unwrap();
+            if !analysis.has_isolated_analysis(&canon_path) {
+                continue;
+            }
+            if change.typ == FileChangeType::DELETED {
+                orphaned_devices.extend(
+                    analysis.device_triggers.get(&canon_path)
+                        .into_iter().flatten()
+                        .filter(|device|**device != canon_path)
+                        .cloned());
+                analysis.mark_file_dirty(&canon_path);
+            } else {
+                analysis.mark_file_dirty(&canon_path);
+                drop(analysis);
+                self.isolated_analyze(&path, None, None, out);
+            }
+        }
+        // Imports may now resolve to other files, or not at all
+        if created_or_deleted {
+            self.cached_path_resolver.lock().unwrap().take();
+            let resolver = self.construct_resolver();
+            self.analysis.lock().unwrap()
+                .update_all_context_dependencies(resolver);
+            // The device analyses of devices importing removed files are
+            // not outdated by any newer analysis, so start over with them
+            for device in orphaned_devices {
+                self.analysis.lock().unwrap().mark_file_dirty(&device);
+                self.isolated_analyze(device.as_path(), None, None, out);
+            }
+        }
+    }
+
+    // Load the persisted symbol indexes of added workspaces, and save and
+    // drop those of removed ones
+    fn update_symbol_indexes(&self, add: &[Workspace], remove: &[Workspace]) {
+        let roots = |workspaces: &[Workspace]| workspaces.iter()
+            .filter_map(|w|parse_file_path!(&w.uri, "workspace").ok())
+            .filter_map(CanonPath::from_path_buf)
+            .map(PathBuf::from)
+            .collect::<Vec<_>>();
+        let persist = self.config.lock().unwrap().persist_symbol_index;
+        let mut indexes = self.symbol_indexes.lock().unwrap();
+        for root in roots(remove) {
+            if let Some(mut index) = indexes.remove(&root) {
+                if let Err(e) = index.save(&root) {
+                    warn!("Could not save symbol index of {:?}: {}", root, e);
+                }
+            }
+        }
+        if persist {
+            for root in roots(add) {
+                indexes.entry(root.clone())
+                    .or_insert_with(||SymbolIndex::load(&root));
+            }
+        }
+    }
+
+    /// Save the persisted symbol indexes of the workspaces that changed
+    pub fn save_symbol_indexes(&self) {
+        if !self.config.lock().unwrap().persist_symbol_index {
+            return;
+        }
+        for (root, index) in self.symbol_indexes.lock().unwrap().iter_mut() {
+            if let Err(e) = index.save(root) {
+                warn!("Could not save symbol index of {:?}: {}", root, e);
+            }
+        }
+    }
+
+    /// Record the symbols of an analyzed file in the symbol index of the
+    /// workspace it is in
+    pub fn index_symbols(&self, path: &CanonPath) {
+        let (hash, symbols) = {
+            let analysis = self.analysis.lock().unwrap();
+            let Ok(isolated) = analysis.get_isolated_analysis(path) else {
+                return;
+            };
+            let mut symbols = vec![];
+            context_to_workspace_symbols(&isolated.top_context, &mut symbols);
+            (isolated.content_hash, symbols)
+        };
+        if let Some(index) = self.symbol_indexes.lock().unwrap().iter_mut()
+            .find(|(root, _)|path.as_path().starts_with(root))
+            .map(|(_, index)|index) {
+                index.update(path.clone().into(), hash, symbols);
+            }
+    }
+
     fn update_linter_config(&self, out: &O) {
         trace!("Updating linter config");
         if let Ok(config) = self.config.lock() {
@@ -443,10 +661,17 @@ impl <O: Output> InitActionContext<O> {
                 if let Some(cfg) = maybe_parse_lint_cfg(lint_path.clone(), out) {
                     *self.lint_config.lock().unwrap() = cfg;
                 }
-            } else {
-                // If no lint config path is set, use default
-                *self.lint_config.lock().unwrap() = LintCfg::default();
-            }
+            } else if let Some(project_cfg) = self.project_configs.lock()
+                .unwrap().iter().find_map(|(_, project)|project.lint_config()) {
+                    // Without a lint config path, the rules of a project
+                    // file apply
+                    if let Some(cfg) = maybe_project_lint_cfg(project_cfg, out) {
+                        *self.lint_config.lock().unwrap() = cfg;
+                    }
+                } else {
+                    // If no lint config path is set, use default
+                    *self.lint_config.lock().unwrap() = LintCfg::default();
+                }
         }
     }
 
@@ -498,13 +723,38 @@ impl <O: Output> InitActionContext<O> {
         } else {
             trace!("Failed to lock config");
         }
+        self.update_file_watches(false, out);
+    }
+
+    /// The Simics API version set in the config or the compile info,
+    /// or guessed from the location of the standard library
+    pub fn simics_api(&self) -> Option<SimicsApiVersion> {
+        if let Some(version) = self.config.lock().unwrap()
+            .simics_api_version.as_ref() {
+                match version.parse() {
+                    Ok(version) => return Some(version),
+                    Err(e) => warn!("{}", e),
+                }
+            }
+        if let Some(version) = self.project_configs.lock().unwrap().iter()
+            .find_map(|(_, project)|project.simics_api()) {
+                return Some(version);
+            }
+        self.compilation_info.lock().unwrap().values()
+            .filter_map(|info|info.simics_api).max()
+            .or_else(||self.analysis.lock().unwrap().stdlib_dir().as_deref()
+                     .and_then(SimicsApiVersion::from_stdlib_path))
     }
 
     pub fn report_errors(&self, output: &O) {
         self.update_analysis();
         let filter = Some(self.device_active_contexts.lock().unwrap().clone());
+        let simics_api = self.simics_api();
+        let unused = self.config.lock().unwrap().unused_declarations.clone();
         let (isolated, device, mut lint) =
-            self.analysis.lock().unwrap().gather_errors(filter.as_ref());
+            self.analysis.lock().unwrap().gather_errors(filter.as_ref(),
+                                                        simics_api,
+                                                        &unused);
         let notifier = AnalysisDiagnosticsNotifier::new("indexing".to_string(),
                                                         output.clone());
         notifier.notify_begin_diagnostics();
@@ -563,7 +813,6 @@ impl <O: Output> InitActionContext<O> {
             let file_content = fs::read_to_string(path).map_err(
                 |e|e.to_string())?;
             trace!("Content is {:?}", file_content);
-            #[allow(dead_code)]
             #[derive(Deserialize)]
             struct FileInfo {
                 dmlc_flags: Vec<String>,
@@ -579,7 +828,7 @@ impl <O: Output> InitActionContext<O> {
                 if let Some(extension) = file.extension() {
                     if extension == "dml" {
                         let FileInfo {
-                            includes, ..
+                            includes, dmlc_flags,
                         } = file_info;
                         if let Some(canon_path) = CanonPath::from_path_buf(file)
                         {
@@ -587,11 +836,14 @@ impl <O: Output> InitActionContext<O> {
                                 canon_path).or_insert(CompilationInfo {
                                     extra_defines: vec![],
                                     include_paths : HashSet::default(),
+                                    simics_api: None,
                                 });
-                            // TODO: For now, ignore flags since we have no
-                            // means to pass them to device analysis anyway
+                            // TODO: For now, ignore other flags since we have
+                            // no means to pass them to device analysis anyway
                             compentry.include_paths
                                 .extend(includes.into_iter());
+                            compentry.simics_api = SimicsApiVersion
+                                ::from_dmlc_flags(&dmlc_flags);
                         }
                     } else {
                         warn!(
@@ -672,6 +924,17 @@ impl <O: Output> InitActionContext<O> {
             if config.compile_info_path != old_config.compile_info_path {
                 self.update_compilation_info(out);
             }
+            if config.library_paths != old_config.library_paths {
+                // Imports may now resolve to other files
+                self.cached_path_resolver.lock().unwrap().take();
+                let resolver = self.construct_resolver();
+                self.analysis.lock().unwrap()
+                    .update_all_context_dependencies(resolver);
+                self.update_file_watches(false, out);
+            }
+            if config.analysis_threads != old_config.analysis_threads {
+                set_analysis_threads(config.analysis_threads);
+            }
             if config.linting_enabled != old_config.linting_enabled {
                 if config.linting_enabled {
                     lint_reissue = lint_reissue.upgrade_to(
@@ -721,16 +984,7 @@ impl <O: Output> InitActionContext<O> {
                 }
                 self.report_errors(out);
             },
-            LintReissueRequirement::AnalyzeAll => {
-                let paths: Vec<_> = {
-                    self.analysis.lock().unwrap()
-                        .isolated_analysis.keys().cloned().collect()
-                };
-                for path in paths {
-                    self.maybe_trigger_lint_analysis(path.as_path(), out);
-                }
-                self.report_errors(out);
-            },
+            LintReissueRequirement::AnalyzeAll => self.relint_all(out),
         }
 
         // Re-update log level
@@ -739,6 +993,18 @@ impl <O: Output> InitActionContext<O> {
         }
     }
 
+    /// Lint all analyzed files again, such as after the lint rules changed
+    pub fn relint_all(&self, out: &O) {
+        let paths: Vec<_> = {
+            self.analysis.lock().unwrap()
+                .isolated_analysis.keys().cloned().collect()
+        };
+        for path in paths {
+            self.maybe_trigger_lint_analysis(path.as_path(), out);
+        }
+        self.report_errors(out);
+    }
+
     // Call before adding new analysis
     pub fn maybe_start_progress(&self, out: &O) {
 
@@ -764,6 +1030,11 @@ impl <O: Output> InitActionContext<O> {
                     "Analysing".to_string(),
                     out.clone());
                 notifier.notify_end_progress();
+                // Files whose analysis failed are never reported done
+                if let Some(indexing) = self.indexing.lock().unwrap().take() {
+                    indexing.end();
+                }
+                self.save_symbol_indexes();
                 self.maybe_warn_missing_builtins(out);
                 *self.current_notifier.lock().unwrap() = None;
             }
@@ -787,6 +1058,30 @@ impl <O: Output> InitActionContext<O> {
             }
     }
 
+    /// The directories that imports are searched in besides that of the
+    /// importing file: include paths, workspace roots, the standard library
+    /// and the library paths, canonicalized
+    pub fn import_search_dirs(&self) -> Vec<PathBuf> {
+        let mut search_dirs: Vec<PathBuf> = self.compilation_info.lock()
+            .unwrap().values()
+            .flat_map(|info|info.include_paths.iter().cloned())
+            .collect();
+        search_dirs.extend(self.workspace_roots.lock().unwrap().iter()
+                           .filter_map(|root|parse_file_path!(
+                               &root.uri, "workspace").ok()));
+        search_dirs.extend(self.analysis.lock().unwrap().stdlib_dir());
+        search_dirs.extend(self.config.lock().unwrap().library_paths
+                           .iter().cloned());
+        let mut canonical: Vec<PathBuf> = vec![];
+        for dir in search_dirs.iter()
+            .filter_map(|dir|dir.canonicalize().ok()) {
+                if !canonical.contains(&dir) {
+                    canonical.push(dir);
+                }
+            }
+        canonical
+    }
+
     pub fn construct_resolver(&self) -> PathResolver {
         if let Some(resolver) = self.cached_path_resolver.lock()
             .unwrap().as_ref() {
@@ -798,11 +1093,24 @@ impl <O: Output> InitActionContext<O> {
         toret.add_paths(self.workspace_roots.lock().unwrap()
                         .iter().map(|w|parse_file_path!(&w.uri, "workspace")
                                     .unwrap()));
-        toret.set_include_paths(&self.compilation_info.lock().unwrap().iter()
-                                .map(|(r, info)|(r.clone(),
-                                                 info.include_paths.clone()
-                                                 .into_iter().collect()))
-                                .collect());
+        let mut library_paths = self.config.lock().unwrap()
+            .library_paths.clone();
+        // The include paths of project files apply to the files under
+        // them, unless compile info gives those of a device
+        let mut include_paths: HashMap<CanonPath, Vec<PathBuf>>
+            = HashMap::default();
+        for (root, project) in self.project_configs.lock().unwrap().iter() {
+            if let Some(root) = CanonPath::from_path_buf(root.clone()) {
+                include_paths.insert(root, project.include_paths.clone());
+            }
+            library_paths.extend(project.library_paths());
+        }
+        include_paths.extend(self.compilation_info.lock().unwrap().iter()
+                             .map(|(r, info)|(r.clone(),
+                                              info.include_paths.clone()
+                                              .into_iter().collect())));
+        toret.set_include_paths(&include_paths);
+        toret.set_library_paths(&library_paths);
         trace!("Constructed resolver: {:?}", toret);
         *self.cached_path_resolver.lock().unwrap() = Some(toret.clone());
         toret
@@ -835,10 +1143,11 @@ impl <O: Output> InitActionContext<O> {
         self.maybe_start_progress(out);
         let (job, token) = ConcurrentJob::new();
         let job_ident = format!("{}-isolated", path.as_str());
+        let options = self.config.lock().unwrap().analysis_options();
         let enqueued = self.analysis_queue.enqueue_isolated_job(
             &mut self.analysis.lock().unwrap(),
             &self.vfs, context, path.clone(),
-            client_path.to_path_buf(), token);
+            client_path.to_path_buf(), options, token);
         if  enqueued {
             // Kill ongoing device analysises that depend on this path
             if let Some(devices)
@@ -851,6 +1160,102 @@ impl <O: Output> InitActionContext<O> {
         }
     }
 
+    /// Queue isolated analysis of the DML files under the workspace roots
+    /// and of the top-level files of the standard library, so that the
+    /// first requests after opening a project do not wait for them to be
+    /// analyzed. Returns the number of files that were queued.
+    pub fn warmup(&self, out: &O) -> usize {
+        let resolver = self.construct_resolver();
+        let mut files: Vec<PathBuf> = resolver
+            .resolve_under_any_context(Path::new("dml-builtins.dml"))
+            .and_then(|builtins|builtins.as_path().parent()
+                      .map(|stdlib|dml_files(stdlib, 1).collect()))
+            .unwrap_or_default();
+        let roots: Vec<PathBuf> = self.workspace_roots.lock().unwrap().iter()
+            .filter_map(|w|parse_file_path!(&w.uri, "workspace").ok())
+            .collect();
+        for root in roots {
+            files.extend(dml_files(&root, usize::MAX));
+        }
+        let files: Vec<(PathBuf, CanonPath)> = {
+            let analysis = self.analysis.lock().unwrap();
+            files.into_iter().filter_map(
+                |file|CanonPath::from_path_buf(file.clone())
+                    .filter(|path|!analysis.has_isolated_analysis(path))
+                    .map(|path|(file, path)))
+                .collect()
+        };
+        info!("Warming up with the analysis of {} files", files.len());
+        if files.is_empty() {
+            return 0;
+        }
+        let paths: HashSet<CanonPath> = files.iter()
+            .map(|(_, path)|path.clone()).collect();
+        {
+            let mut indexing = self.indexing.lock().unwrap();
+            match indexing.as_mut() {
+                Some(indexing) => indexing.add_files(paths),
+                None => *indexing = Some(
+                    IndexingProgress::begin(paths, out.clone())),
+            }
+        }
+        // Reading the files is what takes time before their analysis can
+        // be queued, so it is done in parallel
+        install_analysis(||files.par_iter().for_each(|(file, _)|{
+            if let Err(e) = self.vfs.load_file(file) {
+                debug!("Could not read {}: {:?}", file.display(), e);
+            }
+        }));
+        for (file, _) in &files {
+            self.isolated_analyze(file, None, None, out);
+        }
+        files.len()
+    }
+
+    /// The memory held by the server and what is in it
+    pub fn memory_stats(&self) -> MemoryStats {
+        let (interned_names, interned_name_bytes) = intern::interned();
+        let analysis = self.analysis.lock().unwrap();
+        MemoryStats {
+            resident_bytes: stats::resident_bytes(),
+            interned_names,
+            interned_name_bytes,
+            interned_paths: span::tracked_paths(),
+            isolated_analyses: analysis.isolated_analysis.len(),
+            device_analyses: analysis.device_analysis.len(),
+            device_symbols: analysis.device_analysis.values()
+                .map(|device|device.stored.symbol_info.all_symbols().count())
+                .sum(),
+        }
+    }
+
+    /// The statistics of the work done by the server, see `stats`
+    pub fn server_status(&self) -> ServerStatus {
+        let indexed_files = {
+            let analysis = self.analysis.lock().unwrap();
+            IndexedFiles {
+                analyzed: analysis.isolated_analysis.len(),
+                devices: analysis.device_analysis.len(),
+                linted: analysis.lint_analysis.len(),
+                symbol_index: self.symbol_indexes.lock().unwrap().values()
+                    .map(|index|index.symbols().count()).sum(),
+            }
+        };
+        ServerStatus {
+            stats: stats::snapshot(),
+            indexed_files,
+            memory: self.memory_stats(),
+        }
+    }
+
+    /// Report that the analysis of a file queued by warmup is done
+    pub fn indexed(&self, path: &CanonPath) {
+        let mut indexing = self.indexing.lock().unwrap();
+        if indexing.as_mut().is_some_and(|indexing|indexing.file_done(path)) {
+            indexing.take().unwrap().end();
+        }
+    }
+
     fn device_job_id(path: &str) -> String {
         format!("{}-device", path)
     }
@@ -861,6 +1266,7 @@ impl <O: Output> InitActionContext<O> {
         self.maybe_add_device_context(device);
         let (job, token) = ConcurrentJob::new();
         let job_ident = Self::device_job_id(device.as_str());
+        let options = self.config.lock().unwrap().analysis_options();
         let locked_analysis = &mut self.analysis.lock().unwrap();
         let dependencies = locked_analysis.all_dependencies(device,
                                                             Some(device));
@@ -868,6 +1274,7 @@ impl <O: Output> InitActionContext<O> {
             locked_analysis,
             device,
             dependencies,
+            options,
             token) {
             self.add_job(job_ident, job);
         }
@@ -1407,22 +1814,24 @@ fn find_word_at_pos(line: &str, pos: Column) -> (Column, Column) {
 
 // /// Client file-watching request / filtering logic
 pub struct FileWatch {
-    file_path: PathBuf,
+    file_path: Option<PathBuf>,
+    // The include and library paths, whose DML files are watched along
+    // with those of the workspace
+    search_dirs: Vec<PathBuf>,
 }
 
 impl FileWatch {
     /// Construct a new `FileWatch`.
-    pub fn new<O: Output>(ctx: &InitActionContext<O>) -> Option<Self> {
+    pub fn new<O: Output>(ctx: &InitActionContext<O>) -> Self {
+        let search_dirs = ctx.construct_resolver().search_dirs();
         match ctx.config.lock() {
-            Ok(config) => {
-                config.compile_info_path.as_ref().map(
-                    |c| FileWatch {
-                        file_path: c.clone()
-                    })
+            Ok(config) => FileWatch {
+                file_path: config.compile_info_path.clone(),
+                search_dirs,
             },
             Err(e) => {
                 error!("Unable to access configuration: {:?}", e);
-                None
+                FileWatch { file_path: None, search_dirs }
             }
         }
     }
@@ -1435,7 +1844,20 @@ impl FileWatch {
     fn relevant_change_kind(&self, change_uri: &Uri,
                             _kind: FileChangeType) -> bool {
         let path = change_uri.as_str();
-        self.file_path.to_str().map_or(false, |fp|fp == path)
+        self.file_path.as_ref().and_then(|fp|fp.to_str())
+            .map_or(false, |fp|fp == path)
+    }
+
+    /// Returns if a file change is to a project file
+    #[inline]
+    pub fn is_project_file(&self, change: &FileEvent) -> bool {
+        change.uri.path().as_str().rsplit('/').next() == Some(PROJECT_FILE)
+    }
+
+    /// Returns if a file change is to a DML file
+    #[inline]
+    pub fn is_dml_file(&self, change: &FileEvent) -> bool {
+        change.uri.path().as_str().ends_with(".dml")
     }
 
     #[inline]
@@ -1462,8 +1884,21 @@ impl FileWatch {
                                 kind: Some(kind) }
         }
 
-        let watchers = vec![watcher(
-            self.file_path.to_string_lossy().to_string())];
+        let mut watchers: Vec<FileSystemWatcher> = self.file_path.iter()
+            .map(|fp|watcher(fp.to_string_lossy().to_string())).collect();
+        watchers.push(watcher(format!("**/{}", PROJECT_FILE)));
+        watchers.push(watcher("**/*.dml".to_string()));
+        for dir in &self.search_dirs {
+            let Some(uri) = dir.to_str().and_then(
+                |dir|parse_uri(dir).ok()) else { continue; };
+            watchers.push(FileSystemWatcher {
+                glob_pattern: GlobPattern::Relative(RelativePattern {
+                    base_uri: OneOf::Right(uri),
+                    pattern: "**/*.dml".to_string(),
+                }),
+                kind: None,
+            });
+        }
 
         json!({ "watchers": watchers })
     }
@@ -57,17 +57,7 @@ impl BlockingNotificationAction for Initialized {
             }
 
         // Register files we watch for changes based on config
-        const WATCH_ID: &str = "dls-watch";
-        let reg_params = RegistrationParams {
-            registrations: vec![Registration {
-                id: WATCH_ID.to_owned(),
-                method: <DidChangeWatchedFiles as LSPNotification>
-                    ::METHOD.to_owned(),
-                register_options: FileWatch::new(ctx).map(
-                    |fw|fw.watchers_config()),
-            }],
-        };
-        ctx.send_request::<RegisterCapability>(reg_params, &out);
+        ctx.update_file_watches(true, &out);
         Ok(())
     }
 }
@@ -247,11 +237,19 @@ impl BlockingNotificationAction for DidChangeWatchedFiles {
         ctx: &mut InitActionContext<O>,
         out: O,
     ) -> Result<(), ResponseError> {
-        if let Some(file_watch) = FileWatch::new(ctx) {
-            if params.changes.iter().any(|c| file_watch.is_relevant(c)) {
-                ctx.update_compilation_info(&out);
-                ctx.update_linter_config(&out);
-            }
+        let file_watch = FileWatch::new(ctx);
+        if params.changes.iter().any(|c| file_watch.is_project_file(c)) {
+            ctx.update_project_configs(&out);
+            ctx.relint_all(&out);
+        }
+        if params.changes.iter().any(|c| file_watch.is_relevant(c)) {
+            ctx.update_compilation_info(&out);
+            ctx.update_linter_config(&out);
+        }
+        let dml_changes: Vec<FileEvent> = params.changes.into_iter()
+            .filter(|c| file_watch.is_dml_file(c)).collect();
+        if !dml_changes.is_empty() {
+            ctx.reanalyze_changed_files(&dml_changes, &out);
         }
         Ok(())
     }
@@ -1,7 +1,9 @@
 //  © 2024 Intel Corporation
 //  SPDX-License-Identifier: Apache-2.0 and MIT
+use std::collections::HashSet;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
+use crate::file_management::CanonPath;
 use crate::server::{Notification, Output};
 use lazy_static::lazy_static;
 use lsp_types::notification::{Progress, PublishDiagnostics, ShowMessage};
@@ -146,6 +148,72 @@ impl<O: Output> ProgressNotifier for AnalysisProgressNotifier<O> {
     }
 }
 
+/// Progress of the indexing of a set of files, such as those of the
+/// workspace on warmup, reported as how many of them have been analyzed
+pub struct IndexingProgress<O: Output> {
+    notifier: AnalysisProgressNotifier<O>,
+    total: usize,
+    pending: HashSet<CanonPath>,
+}
+
+impl<O: Output> IndexingProgress<O> {
+    pub fn begin(files: HashSet<CanonPath>, out: O) -> IndexingProgress<O> {
+        let mut progress = IndexingProgress {
+            notifier: AnalysisProgressNotifier::new(
+                "Indexing".to_string(), out),
+            total: files.len(),
+            pending: files,
+        };
+        progress.update();
+        progress.notifier.notify_begin_progress();
+        progress
+    }
+
+    fn update(&mut self) -> bool {
+        let done = self.total - self.pending.len();
+        let percentage = (done * 100 / self.total.max(1)) as u32;
+        let changed = self.notifier.percentage != Some(percentage);
+        self.notifier.update(ProgressUpdate::Message(
+            format!("{}/{} files", done, self.total)));
+        self.notifier.update(ProgressUpdate::Percentage(percentage));
+        changed
+    }
+
+    /// Index more files along with those already being indexed
+    pub fn add_files(&mut self, files: HashSet<CanonPath>) {
+        for file in files {
+            if self.pending.insert(file) {
+                self.total += 1;
+            }
+        }
+        self.update();
+        self.notifier.notify_progress(ProgressUpdate::Cancellable(false));
+    }
+
+    /// Record that a file has been analyzed, returning true once all of
+    /// them have. Progress is reported as the percentage changes.
+    pub fn file_done(&mut self, file: &CanonPath) -> bool {
+        if !self.pending.remove(file) {
+            return false;
+        }
+        if self.pending.is_empty() {
+            return true;
+        }
+        if self.update() {
+            self.notifier.notify_progress(ProgressUpdate::Cancellable(false));
+        }
+        false
+    }
+
+    /// End the progress, also when files could not be analyzed
+    pub fn end(mut self) {
+        let indexed = self.total - self.pending.len();
+        self.notifier.update(ProgressUpdate::Message(
+            format!("Indexed {} files", indexed)));
+        self.notifier.notify_end_progress();
+    }
+}
+
 /// Notifier of diagnostics after analysis has completed
 pub struct AnalysisDiagnosticsNotifier<O: Output> {
     sub_notifier: AnalysisProgressNotifier<O>,
@@ -0,0 +1,313 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! The register maps of devices, with every element of arrays of banks,
+//! registers and fields listed on its own, as sent to clients rendering them,
+//! and the layouts of their banks in address order
+use std::convert::TryFrom;
+
+use lsp_types::Location;
+use serde::{Deserialize, Serialize};
+
+use crate::analysis::DeviceAnalysis;
+use crate::analysis::layout::{containing_bank, element_name, eval_param,
+                              instances, is_within, Indices, UNMAPPED_OFFSET};
+use crate::analysis::structure::objects::CompObjectKind;
+use crate::analysis::templating::objects::{DMLCompositeObject, DMLObject,
+                                           StructureContainer};
+use crate::lsp_data::ls_util;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisterMap {
+    pub device: String,
+    pub banks: Vec<BankMap>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BankMap {
+    /// The name of the bank element, with its indices
+    pub name: String,
+    pub location: Location,
+    /// The registers of the bank element, by increasing offset
+    pub registers: Vec<RegisterMapEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisterMapEntry {
+    /// The name of the register element relative to its bank, with the
+    /// indices of it and the groups it is in
+    pub name: String,
+    pub location: Location,
+    /// The offset in the bank, unless unmapped or not evaluated
+    pub offset: Option<u64>,
+    pub unmapped: bool,
+    pub size: Option<u64>,
+    pub reset_value: Option<u64>,
+    /// The fields of the register element, most significant first
+    pub fields: Vec<FieldMapEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldMapEntry {
+    pub name: String,
+    pub location: Location,
+    pub msb: Option<u64>,
+    pub lsb: Option<u64>,
+}
+
+fn value(container: &StructureContainer, obj: &DMLCompositeObject,
+         name: &str, indices: &Indices) -> Option<u64> {
+    eval_param(container, obj, name, indices)
+        .and_then(|value|u64::try_from(value).ok())
+}
+
+fn field_entries(container: &StructureContainer,
+                 register: &DMLCompositeObject, indices: &Indices)
+                 -> Vec<FieldMapEntry> {
+    let mut fields = vec![];
+    for field in register.components.values().filter_map(
+        |component|match component {
+            DMLObject::CompObject(key) => container.get(*key)
+                .filter(|obj|obj.kind == CompObjectKind::Field),
+            _ => None,
+        }) {
+        for field_indices in instances(container, field).into_iter()
+            .flatten()
+            .filter(|field_indices|is_within(field_indices, indices)) {
+                fields.push(FieldMapEntry {
+                    name: element_name(container, field, &field_indices,
+                                       Some(register.key)),
+                    location: ls_util::dls_to_location(&field.declloc),
+                    msb: value(container, field, "msb", &field_indices),
+                    lsb: value(container, field, "lsb", &field_indices),
+                });
+            }
+    }
+    fields.sort_by(|field, other|other.lsb.cmp(&field.lsb)
+                   .then_with(||field.name.cmp(&other.name)));
+    fields
+}
+
+fn bank_map(device: &DeviceAnalysis, bank: &DMLCompositeObject,
+            indices: &Indices) -> BankMap {
+    let container = &device.objects;
+    let mut registers = vec![];
+    for register in container.values().filter(
+        |obj|obj.kind == CompObjectKind::Register
+            && containing_bank(container, obj)
+            .is_some_and(|parent|parent.key == bank.key)) {
+        for register_indices in instances(container, register).into_iter()
+            .flatten()
+            .filter(|register_indices|is_within(register_indices, indices)) {
+                let offset = value(container, register, "offset",
+                                   &register_indices);
+                registers.push(RegisterMapEntry {
+                    name: element_name(container, register,
+                                       &register_indices, Some(bank.key)),
+                    location: ls_util::dls_to_location(&register.declloc),
+                    offset: offset.filter(|offset|*offset != UNMAPPED_OFFSET),
+                    unmapped: offset == Some(UNMAPPED_OFFSET),
+                    size: value(container, register, "size",
+                                &register_indices),
+                    reset_value: value(container, register, "init_val",
+                                       &register_indices),
+                    fields: field_entries(container, register,
+                                          &register_indices),
+                });
+            }
+    }
+    // Unmapped registers and those of unknown offset go last
+    registers.sort_by(|register, other|
+                      (register.offset.is_none(), register.offset)
+                      .cmp(&(other.offset.is_none(), other.offset))
+                      .then_with(||register.name.cmp(&other.name)));
+    BankMap {
+        name: element_name(container, bank, indices, None),
+        location: ls_util::dls_to_location(&bank.declloc),
+        registers,
+    }
+}
+
+/// The register map of a device, with its banks by name. Arrays whose sizes
+/// cannot be evaluated are left out.
+pub fn register_map(device: &DeviceAnalysis) -> RegisterMap {
+    let mut banks: Vec<BankMap> = device.objects.values()
+        .filter(|obj|obj.kind == CompObjectKind::Bank)
+        .flat_map(|bank|instances(&device.objects, bank).into_iter()
+                  .flatten()
+                  .map(move |indices|bank_map(device, bank, &indices)))
+        .collect();
+    banks.sort_by(|bank, other|bank.name.cmp(&other.name));
+    RegisterMap {
+        device: device.name.clone(),
+        banks,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceLayout {
+    pub device: String,
+    pub banks: Vec<BankLayout>,
+}
+
+/// The address space of a bank element, from its lowest mapped byte to its
+/// highest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BankLayout {
+    pub name: String,
+    pub location: Location,
+    /// The registers and the bytes between them, by increasing offset
+    pub entries: Vec<LayoutEntry>,
+    /// The number of bytes mapped by registers
+    pub mapped_bytes: u64,
+    /// The number of bytes in gaps and paddings between registers
+    pub unmapped_bytes: u64,
+    /// The registers declared without an offset
+    pub unmapped: Vec<String>,
+    /// The registers whose offset or size cannot be evaluated
+    pub unresolved: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum LayoutEntry {
+    #[serde(rename_all = "camelCase")]
+    Register {
+        name: String,
+        location: Location,
+        offset: u64,
+        size: u64,
+        /// Whether the register shares bytes with a register before it
+        overlapping: bool,
+    },
+    /// Bytes between registers that no register maps
+    Gap { offset: u64, size: u64 },
+    /// Bytes that no register maps before a register aligned to its size
+    Padding { offset: u64, size: u64 },
+}
+
+/// The layout of a bank element from its register map
+pub fn bank_layout(bank: &BankMap) -> BankLayout {
+    let mut entries = vec![];
+    let (mut mapped_bytes, mut unmapped_bytes) = (0, 0);
+    let mut unmapped = vec![];
+    let mut unresolved = vec![];
+    // The end of the bytes mapped so far
+    let mut end: Option<u64> = None;
+    for register in &bank.registers {
+        let (offset, size) = match (register.offset, register.size) {
+            (Some(offset), Some(size)) if size > 0 => (offset, size),
+            _ if register.unmapped => {
+                unmapped.push(register.name.clone());
+                continue;
+            },
+            _ => {
+                unresolved.push(register.name.clone());
+                continue;
+            },
+        };
+        let register_end = offset.saturating_add(size);
+        match end {
+            Some(end) if end < offset => {
+                let gap = offset - end;
+                unmapped_bytes += gap;
+                entries.push(if gap < size && offset % size == 0 {
+                    LayoutEntry::Padding { offset: end, size: gap }
+                } else {
+                    LayoutEntry::Gap { offset: end, size: gap }
+                });
+                mapped_bytes += size;
+            },
+            Some(end) => mapped_bytes += register_end.saturating_sub(end),
+            None => mapped_bytes += size,
+        }
+        entries.push(LayoutEntry::Register {
+            name: register.name.clone(),
+            location: register.location.clone(),
+            offset,
+            size,
+            overlapping: end.is_some_and(|end|offset < end),
+        });
+        end = Some(end.map_or(register_end, |end|end.max(register_end)));
+    }
+    BankLayout {
+        name: bank.name.clone(),
+        location: bank.location.clone(),
+        entries,
+        mapped_bytes,
+        unmapped_bytes,
+        unmapped,
+        unresolved,
+    }
+}
+
+/// The layouts of the banks of a device, by bank name
+pub fn device_layout(device: &DeviceAnalysis) -> DeviceLayout {
+    let map = register_map(device);
+    DeviceLayout {
+        device: map.device,
+        banks: map.banks.iter().map(bank_layout).collect(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    use lsp_types::Uri;
+
+    fn register(name: &str, offset: Option<u64>, size: u64)
+                -> RegisterMapEntry {
+        RegisterMapEntry {
+            name: name.to_string(),
+            location: Location {
+                uri: Uri::from_str("file:///bank.dml").unwrap(),
+                range: lsp_types::Range::default(),
+            },
+            offset,
+            unmapped: offset.is_none(),
+            size: Some(size),
+            reset_value: None,
+            fields: vec![],
+        }
+    }
+
+    #[test]
+    fn test_bank_layout() {
+        let registers = vec![
+            register("a", Some(0), 2),
+            register("b", Some(4), 4),
+            register("c", Some(6), 2),
+            register("d", Some(0x10), 4),
+            register("e", None, 4),
+        ];
+        let location = registers[0].location.clone();
+        let layout = bank_layout(&BankMap {
+            name: "regs".to_string(),
+            location: location.clone(),
+            registers,
+        });
+        let at = |name: &str, offset, size, overlapping|
+        LayoutEntry::Register {
+            name: name.to_string(), location: location.clone(), offset, size,
+            overlapping,
+        };
+        assert_eq!(layout.entries, vec![
+            at("a", 0, 2, false),
+            LayoutEntry::Padding { offset: 2, size: 2 },
+            at("b", 4, 4, false),
+            at("c", 6, 2, true),
+            LayoutEntry::Gap { offset: 8, size: 8 },
+            at("d", 0x10, 4, false),
+        ]);
+        assert_eq!((layout.mapped_bytes, layout.unmapped_bytes), (10, 10));
+        assert_eq!(layout.unmapped, vec!["e".to_string()]);
+        assert!(layout.unresolved.is_empty());
+    }
+}
@@ -6,38 +6,78 @@ use jsonrpc::error::StandardError;
 use log::{debug, error, info, trace, warn};
 use serde::{Deserialize, Serialize};
 
-use std::collections::HashSet;
-use std::path::Path;
-
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::actions::call_hierarchy;
+use crate::actions::code_lens;
+use crate::actions::completion;
+use crate::actions::folding;
 use crate::actions::hover;
+use crate::actions::inlay_hints;
 use crate::actions::{AnalysisProgressKind, AnalysisWaitKind,
                      AnalysisCoverageSpec,
                      ContextDefinition, InitActionContext,
                      rpc_error_code};
 use crate::actions::notifications::ContextDefinitionKindParam;
-use crate::actions::semantic_lookup::{DLSLimitation, declarations_at_fp, definitions_at_fp, implementations_at_fp, references_at_fp};
-use crate::analysis::{Named, DeclarationSpan, LocationSpan};
+use crate::actions::register_map::{self, DeviceLayout, RegisterMap};
+use crate::actions::semantic_tokens;
+use crate::actions::symbol_index::{fuzzy_match, SymbolIndex};
+use crate::actions::semantic_lookup::{DLSLimitation, declarations_at_fp, describe_symbols_at_fp, definitions_at_fp, implementations_at_fp, references_at_fp, rename_locations_at_fp};
+use crate::analysis::{parse_file, FileSpec, Named, DeclarationSpan,
+                      LocationSpan, ZeroRange, ZeroSpan};
+use crate::analysis::parsing::lexer::{is_identifier, TokenKind};
+use crate::analysis::parsing::structure::TopAst;
+use crate::analysis::parsing::tree::{ZeroFilePosition, ZeroPosition};
+use crate::analysis::deprecations::{deprecated_uses, DeprecatedUse};
+use crate::analysis::expansion::{self, ExpandedObject};
+use crate::analysis::quick_fixes::{add_import, code_tokens,
+                                   documentation_stubs,
+                                   import_path, missing_template,
+                                   parameter_keywords, remove_declaration,
+                                   organize_imports, remove_instantiation,
+                                   QuickFix};
+use crate::analysis::refactorings::{extract_method, extract_template,
+                                    inline_method};
+use crate::analysis::signatures::{call_site, method_signature,
+                                  template_signature, CallSite, Signature};
 use crate::analysis::symbols::SimpleSymbol;
+use crate::analysis::unused::{declaration_range, unused_declaration,
+                              UnusedKind};
 use crate::config::Config;
+use crate::stats::Stats;
 
 pub use crate::lsp_data::request::{
     ApplyWorkspaceEdit,
+    CallHierarchyIncomingCalls,
+    CallHierarchyOutgoingCalls,
+    CallHierarchyPrepare,
     CodeActionRequest,
     CodeLensRequest,
     Completion,
     DocumentHighlightRequest,
     DocumentSymbolRequest,
     ExecuteCommand,
+    FoldingRangeRequest,
     Formatting,
     GotoDeclaration, GotoDeclarationResponse,
     GotoDefinition,
     GotoImplementation, GotoImplementationResponse,
     HoverRequest,
+    InlayHintRequest,
+    OnTypeFormatting,
+    PrepareRenameRequest,
     RangeFormatting,
     References,
     RegisterCapability,
+    UnregisterCapability,
     Rename,
     ResolveCompletionItem as ResolveCompletion,
+    SemanticTokensFullRequest,
+    SemanticTokensRangeRequest,
+    SelectionRangeRequest,
+    SignatureHelpRequest,
     WorkspaceConfiguration,
     WorkspaceSymbolRequest,
 };
@@ -50,8 +90,11 @@ use crate::analysis::scope::{SymbolContext, SubSymbol, ContextKey, Scope};
 use crate::analysis::symbols::{DMLSymbolKind, StructureSymbol};
 use crate::actions::analysis_storage::AnalysisLookupError;
 use crate::config::WarningFrequency;
-use crate::file_management::CanonPath;
+use crate::file_management::{dml_files, CanonPath};
+use crate::formatting::{format_lines, format_source, reindent_line,
+                        FormatConfig, FormatError, IndentStyle, LineEdit};
 use crate::server;
+use crate::vfs::TextFile;
 use crate::server::{Ack, Output, Request, RequestAction, SentRequest,
                     Response, ResponseError, ResponseWithMessage};
 
@@ -162,15 +205,21 @@ fn structure_to_symbolkind(kind: DMLSymbolKind)
         DMLSymbolKind::Local |
         DMLSymbolKind::MethodArg =>
             SymbolKind::VARIABLE,
-        DMLSymbolKind::Hook |
-        DMLSymbolKind::Method =>
+        DMLSymbolKind::Hook =>
             SymbolKind::FUNCTION,
+        DMLSymbolKind::Method =>
+            SymbolKind::METHOD,
         DMLSymbolKind::Template =>
             SymbolKind::CLASS,
         // TODO: There is no typedef kind?
         DMLSymbolKind::Typedef =>
             SymbolKind::CONSTANT,
         DMLSymbolKind::CompObject(kind) => match kind {
+            CompObjectKind::Device => SymbolKind::MODULE,
+            CompObjectKind::Register => SymbolKind::OBJECT,
+            CompObjectKind::Field => SymbolKind::FIELD,
+            CompObjectKind::Attribute => SymbolKind::PROPERTY,
+            CompObjectKind::Event => SymbolKind::EVENT,
             CompObjectKind::Interface => SymbolKind::INTERFACE,
             CompObjectKind::Implement => SymbolKind::STRUCT,
             // Generic comp objects most easily map to namespaces, I think?
@@ -179,6 +228,29 @@ fn structure_to_symbolkind(kind: DMLSymbolKind)
     }
 }
 
+// The kind of a symbol as written in DML, shown next to its name in
+// document outlines
+fn symbol_detail(kind: DMLSymbolKind) -> &'static str {
+    kind.kind_name()
+}
+
+// The document symbols of the subsymbols of a context, in the order they
+// are declared in. Objects and methods are both symbols and contexts of
+// their own, and are only listed as the latter.
+fn document_symbols(subsymbols: &[SubSymbol]) -> Vec<DocumentSymbol> {
+    let contexts: HashSet<ZeroSpan> = subsymbols.iter()
+        .filter(|sub|matches!(sub, SubSymbol::Context(_)))
+        .map(|sub|*sub.loc_span()).collect();
+    let mut symbols: Vec<DocumentSymbol> = subsymbols.iter()
+        .filter(|sub|matches!(sub, SubSymbol::Context(_))
+                || !contexts.contains(sub.loc_span()))
+        .map(subsymbol_to_document_symbol)
+        .collect();
+    symbols.sort_by_key(|symbol|(symbol.selection_range.start.line,
+                                 symbol.selection_range.start.character));
+    symbols
+}
+
 fn subsymbol_to_document_symbol(sub: &SubSymbol) -> DocumentSymbol {
     match sub {
         SubSymbol::Context(con) => context_to_document_symbol(con),
@@ -186,7 +258,7 @@ fn subsymbol_to_document_symbol(sub: &SubSymbol) -> DocumentSymbol {
             #[allow(deprecated)]
             DocumentSymbol {
                 name: simple.get_name(),
-                detail: None,
+                detail: Some(symbol_detail(simple.kind()).to_string()),
                 kind: structure_to_symbolkind(simple.kind()),
                 tags: None,
                 deprecated: None,
@@ -223,15 +295,14 @@ fn context_to_document_symbol(context: &SymbolContext) -> DocumentSymbol {
     #[allow(deprecated)]
     DocumentSymbol {
         name,
-        detail: None,
+        detail: context.context.kind()
+            .map(|kind|symbol_detail(kind).to_string()),
         kind: context_to_symbolkind(context),
         tags: None,
         deprecated: None,
         range: ls_util::dls_to_range(span.range),
         selection_range: ls_util::dls_to_range(loc.range),
-        children: Some(context.subsymbols.iter()
-                       .map(subsymbol_to_document_symbol)
-                       .collect()),
+        children: Some(document_symbols(&context.subsymbols)),
     }
 }
 
@@ -268,7 +339,7 @@ fn context_to_workspace_symbols_aux(context: &SymbolContext,
     }
 }
 
-fn context_to_workspace_symbols(context: &SymbolContext,
+pub(crate) fn context_to_workspace_symbols(context: &SymbolContext,
                                 symbols: &mut Vec<WorkspaceSymbol>) {
     context_to_workspace_symbols_aux(context, None, symbols);
 }
@@ -288,16 +359,40 @@ impl RequestAction for WorkspaceSymbolRequest {
         ctx: InitActionContext<O>,
         params: Self::Params,
     ) -> Result<Self::Response, ResponseError> {
-        let analysis = ctx.analysis.lock().unwrap();
         let mut workspace_symbols = vec![];
-        for context in analysis.all_isolated_analysises().values()
-            .map(|i|&i.top_context) {
+        let analyzed: HashSet<PathBuf> = {
+            let analysis = ctx.analysis.lock().unwrap();
+            let isolated = analysis.all_isolated_analysises();
+            for context in isolated.values().map(|i|&i.top_context) {
                 context_to_workspace_symbols(context,
                                              &mut workspace_symbols);
             }
+            isolated.into_keys().map(|path|path.clone().into()).collect()
+        };
+        // Files indexed by an earlier session, that have not been analyzed
+        // in this one
+        for (_, symbols) in ctx.symbol_indexes.lock().unwrap().values()
+            .flat_map(SymbolIndex::symbols)
+            .filter(|(file, _)|!analyzed.contains(*file)) {
+                workspace_symbols.extend(symbols.iter().cloned());
+            }
+        // Best matches first, and each symbol once even if it is both a
+        // symbol and a context
+        let mut matches: Vec<(i64, WorkspaceSymbol)> = workspace_symbols
+            .into_iter()
+            .filter_map(|sym|fuzzy_match(&params.query, &sym.name)
+                        .map(|score|(score, sym)))
+            .collect();
+        matches.sort_by(|(score, sym), (other_score, other)|
+                        other_score.cmp(score)
+                        .then_with(||sym.name.cmp(&other.name)));
+        let mut seen = HashSet::new();
         Ok(Some(WorkspaceSymbolResponse::Nested(
-            workspace_symbols.into_iter()
-                .filter(|sym|sym.name.contains(&params.query)).collect()
+            matches.into_iter().map(|(_, sym)|sym)
+                .filter(|sym|seen.insert((
+                    sym.name.clone(), sym.container_name.clone(),
+                    serde_json::to_string(&sym.location).unwrap_or_default())))
+                .collect()
         )))
     }
 }
@@ -332,15 +427,484 @@ impl RequestAction for DocumentSymbolRequest {
             .get_isolated_analysis(&canon_path)
             .map(|isolated|{
                 let context = isolated.toplevel.to_context();
-                // Fold out the toplevel context
-                let symbols = context.subsymbols.iter().map(
-                    subsymbol_to_document_symbol).collect();
-                Some(DocumentSymbolResponse::Nested(symbols))
+                // Fold out the toplevel context, the declarations of a
+                // device file are instead shown under the device
+                let symbols = document_symbols(&context.subsymbols);
+                let Some(device) = &isolated.toplevel.device else {
+                    return Some(DocumentSymbolResponse::Nested(symbols));
+                };
+                #[allow(deprecated)]
+                let device = DocumentSymbol {
+                    name: device.name.val.to_string(),
+                    detail: Some(symbol_detail(DMLSymbolKind::CompObject(
+                        CompObjectKind::Device)).to_string()),
+                    kind: SymbolKind::MODULE,
+                    tags: None,
+                    deprecated: None,
+                    range: ls_util::dls_to_range(
+                        isolated.toplevel.filespan.range),
+                    selection_range: ls_util::dls_to_range(
+                        device.name.span.range),
+                    children: Some(symbols),
+                };
+                Some(DocumentSymbolResponse::Nested(vec![device]))
             })
             .or(Self::fallback_response())
     }
 }
 
+// The semantic tokens of a file, in the device contexts that are active
+fn file_semantic_tokens<O: Output>(ctx: &InitActionContext<O>,
+                                   uri: &Uri)
+                                   -> Result<Vec<semantic_tokens::Token>,
+                                             ResponseError> {
+    let canon_path = {
+        let parsed = parse_file_path!(uri, "semantic tokens")
+            .map_err(|_|ResponseError::Message(
+                rpc_error_code(StandardError::ParseError),
+                "Failed to parse file path".to_string()))?;
+        make_canon_path!(parsed)?
+    };
+    let (_, text) = document_text(ctx, uri)?;
+    let filter = ctx.device_active_contexts.lock().unwrap().clone();
+    let analysis = ctx.analysis.lock().unwrap();
+    let isolated = match analysis.get_isolated_analysis(&canon_path) {
+        Ok(isolated) => isolated,
+        Err(error) => {
+            warn_miss_lookup(error, Some(uri.as_str()));
+            return Ok(vec![]);
+        },
+    };
+    let devices = analysis.filtered_device_analysises_containing_file(
+        &canon_path, Some(&filter));
+    Ok(semantic_tokens::file_tokens(isolated, &text, &devices))
+}
+
+impl RequestAction for SemanticTokensFullRequest {
+    type Response = Option<SemanticTokensResult>;
+
+    fn fallback_response() -> Result<Self::Response, ResponseError> {
+        Ok(None)
+    }
+
+    fn get_identifier(params: &Self::Params) -> String {
+        Self::request_identifier(params.text_document.uri.as_str())
+    }
+
+    fn handle<O: Output>(
+        ctx: InitActionContext<O>,
+        params: Self::Params,
+    ) -> Result<Self::Response, ResponseError> {
+        debug!("Handling semantic tokens request {:?}", params);
+        let tokens = file_semantic_tokens(&ctx, &params.text_document.uri)?;
+        Ok(Some(SemanticTokensResult::Tokens(SemanticTokens {
+            result_id: None,
+            data: semantic_tokens::encode(&tokens),
+        })))
+    }
+}
+
+impl RequestAction for SemanticTokensRangeRequest {
+    type Response = Option<SemanticTokensRangeResult>;
+
+    fn fallback_response() -> Result<Self::Response, ResponseError> {
+        Ok(None)
+    }
+
+    fn get_identifier(params: &Self::Params) -> String {
+        Self::request_identifier(params.text_document.uri.as_str())
+    }
+
+    fn handle<O: Output>(
+        ctx: InitActionContext<O>,
+        params: Self::Params,
+    ) -> Result<Self::Response, ResponseError> {
+        debug!("Handling semantic tokens range request {:?}", params);
+        let range = ls_util::range_to_dls(params.range);
+        let tokens: Vec<semantic_tokens::Token> =
+            file_semantic_tokens(&ctx, &params.text_document.uri)?
+            .into_iter().filter(|token|token.range.overlaps(range)).collect();
+        Ok(Some(SemanticTokensRangeResult::Tokens(SemanticTokens {
+            result_id: None,
+            data: semantic_tokens::encode(&tokens),
+        })))
+    }
+}
+
+impl RequestAction for InlayHintRequest {
+    type Response = Option<Vec<InlayHint>>;
+
+    fn fallback_response() -> Result<Self::Response, ResponseError> {
+        Ok(None)
+    }
+
+    fn get_identifier(params: &Self::Params) -> String {
+        Self::request_identifier(params.text_document.uri.as_str())
+    }
+
+    fn handle<O: Output>(
+        ctx: InitActionContext<O>,
+        params: Self::Params,
+    ) -> Result<Self::Response, ResponseError> {
+        debug!("Handling inlay hint request {:?}", params);
+        let canon_path = {
+            let parsed = parse_file_path!(
+                &params.text_document.uri, "inlay hints")
+                .map_err(|_|ResponseError::Message(
+                    rpc_error_code(StandardError::ParseError),
+                    "Failed to parse file path".to_string()))?;
+            make_canon_path!(parsed)?
+        };
+        let range = ls_util::range_to_dls(params.range);
+        let filter = ctx.device_active_contexts.lock().unwrap().clone();
+        let analysis = ctx.analysis.lock().unwrap();
+        let file = match analysis.get_isolated_analysis(&canon_path) {
+            Ok(isolated) => isolated.toplevel.filespan.file,
+            Err(error) => {
+                warn_miss_lookup(error,
+                                 Some(params.text_document.uri.as_str()));
+                return Self::fallback_response();
+            },
+        };
+        let devices = analysis.filtered_device_analysises_containing_file(
+            &canon_path, Some(&filter));
+        Ok(Some(inlay_hints::file_hints(&devices, file).into_iter()
+                .filter(|hint|range.contains_pos(
+                    ls_util::position_to_dls(hint.position)))
+                .collect()))
+    }
+}
+
+// The signatures of the method declarations that the name at a position
+// resolves to
+fn method_signatures<O: Output>(ctx: &InitActionContext<O>,
+                                params: &TextDocumentPositionParams)
+                                -> Vec<Signature> {
+    let Some(fp) = ctx.text_doc_pos_to_pos(params, "signature help") else {
+        return vec![];
+    };
+    let Ok(canon_path) = CanonPath::try_from_path_buf(fp.path()) else {
+        return vec![];
+    };
+    wait_for_device_path!(ctx, canon_path);
+    let declarations = describe_symbols_at_fp(
+        ctx, &fp, &mut HashSet::new(), |_, symbol| {
+            let symbol = symbol.lock().unwrap();
+            (symbol.kind == DMLSymbolKind::Method)
+                .then(||symbol.declarations.clone())
+        });
+    let mut declarations: Vec<ZeroSpan> = match declarations {
+        Ok(declarations) => declarations.into_iter().flatten().collect(),
+        Err(error) => {
+            debug!("No signature help at {:?}: {:?}", fp, error);
+            return vec![];
+        },
+    };
+    declarations.sort();
+    declarations.dedup();
+    let mut signatures: Vec<Signature> = vec![];
+    for declaration in declarations {
+        let path = declaration.path();
+        let Ok(text) = ctx.vfs.load_file(&path)
+            .and_then(|contents|contents.as_string_content()) else {
+                continue;
+            };
+        if let Some(signature) = method_signature(&text, declaration.range) {
+            if !signatures.contains(&signature) {
+                signatures.push(signature);
+            }
+        }
+    }
+    signatures
+}
+
+// The signature of the template named `name` in the devices a file is
+// analyzed in
+fn template_signatures<O: Output>(ctx: &InitActionContext<O>,
+                                  canon_path: &CanonPath, name: &str)
+                                  -> Vec<Signature> {
+    wait_for_device_path!(ctx, canon_path.clone());
+    let filter = ctx.device_active_contexts.lock().unwrap().clone();
+    let analysis = ctx.analysis.lock().unwrap();
+    let mut signatures: Vec<Signature> = vec![];
+    for device in analysis.filtered_device_analysises_containing_file(
+        canon_path, Some(&filter)) {
+        let Some(template) = device.templates.templates.get(name) else {
+            continue;
+        };
+        let Some(location) = template.location else {
+            continue;
+        };
+        let Ok(text) = ctx.vfs.load_file(&location.path())
+            .and_then(|contents|contents.as_string_content()) else {
+                continue;
+            };
+        let parameters: Vec<ZeroRange> = template.spec.params.iter()
+            .map(|param|param.obj.object.name.span)
+            .filter(|span|span.file == location.file)
+            .map(|span|span.range)
+            .collect();
+        if let Some(signature) = template_signature(
+            &text, location.range, &parameters) {
+            if !signatures.contains(&signature) {
+                signatures.push(signature);
+            }
+        }
+    }
+    signatures
+}
+
+fn markdown_documentation(value: String) -> Documentation {
+    Documentation::MarkupContent(MarkupContent {
+        kind: MarkupKind::Markdown,
+        value,
+    })
+}
+
+// Offsets into the label of a signature are counted in UTF-16 code units
+fn signature_information(signature: Signature) -> SignatureInformation {
+    let utf16_offset = |offset: usize|signature.label[..offset]
+        .encode_utf16().count() as u32;
+    let parameters = signature.parameters.iter()
+        .map(|parameter|ParameterInformation {
+            label: ParameterLabel::LabelOffsets(
+                [utf16_offset(parameter.label.start),
+                 utf16_offset(parameter.label.end)]),
+            documentation: parameter.documentation.clone()
+                .map(markdown_documentation),
+        })
+        .collect();
+    SignatureInformation {
+        parameters: Some(parameters),
+        documentation: signature.documentation.map(markdown_documentation),
+        label: signature.label,
+        active_parameter: None,
+    }
+}
+
+impl RequestAction for SignatureHelpRequest {
+    type Response = Option<SignatureHelp>;
+
+    fn fallback_response() -> Result<Self::Response, ResponseError> {
+        Ok(None)
+    }
+
+    fn get_identifier(params: &Self::Params) -> String {
+        Self::request_identifier(
+            &text_document_position_to_ident(
+                &params.text_document_position_params))
+    }
+
+    fn handle<O: Output>(
+        ctx: InitActionContext<O>,
+        params: Self::Params,
+    ) -> Result<Self::Response, ResponseError> {
+        debug!("Handling signature help request {:?}", params);
+        let doc_pos = &params.text_document_position_params;
+        let (path, text) = document_text(&ctx, &doc_pos.text_document.uri)?;
+        let position = ls_util::position_to_dls(doc_pos.position);
+        let (signatures, active_parameter) = match call_site(&text, position) {
+            Some(CallSite::Method { name, argument }) => {
+                let name_pos = TextDocumentPositionParams {
+                    text_document: doc_pos.text_document.clone(),
+                    position: ls_util::dls_to_position(name.start()),
+                };
+                (method_signatures(&ctx, &name_pos), Some(argument))
+            },
+            Some(CallSite::Templates { template: Some(name) }) => {
+                let canon_path = make_canon_path!(path)?;
+                (template_signatures(&ctx, &canon_path, &name), None)
+            },
+            _ => return Self::fallback_response(),
+        };
+        if signatures.is_empty() {
+            return Self::fallback_response();
+        }
+        Ok(Some(SignatureHelp {
+            signatures: signatures.into_iter().map(signature_information)
+                .collect(),
+            active_signature: Some(0),
+            active_parameter,
+        }))
+    }
+}
+
+impl RequestAction for CallHierarchyPrepare {
+    type Response = Option<Vec<CallHierarchyItem>>;
+
+    fn fallback_response() -> Result<Self::Response, ResponseError> {
+        Ok(None)
+    }
+
+    fn get_identifier(params: &Self::Params) -> String {
+        Self::request_identifier(
+            &text_document_position_to_ident(
+                &params.text_document_position_params))
+    }
+
+    fn handle<O: Output>(
+        ctx: InitActionContext<O>,
+        params: Self::Params,
+    ) -> Result<Self::Response, ResponseError> {
+        debug!("Preparing call hierarchy with params {:?}", params);
+        let Some(fp) = ctx.text_doc_pos_to_pos(
+            &params.text_document_position_params, "call hierarchy") else {
+                return Self::fallback_response();
+            };
+        let canon_path = make_canon_path!(fp.path())?;
+        wait_for_device_path!(ctx, canon_path);
+        let locs = describe_symbols_at_fp(
+            &ctx, &fp, &mut HashSet::new(), |_, symbol| {
+                let symbol = symbol.lock().unwrap();
+                (symbol.kind == DMLSymbolKind::Method).then_some(symbol.loc)
+            });
+        let mut locs = match locs {
+            Ok(locs) => locs,
+            Err(error) => {
+                warn_miss_lookup(error, fp.path().to_str());
+                return Self::fallback_response();
+            },
+        };
+        locs.sort();
+        locs.dedup();
+        let analysis = ctx.analysis.lock().unwrap();
+        let items: Vec<CallHierarchyItem> = locs.iter()
+            .filter_map(|loc|call_hierarchy::declaration_at(&analysis, loc))
+            .map(|declaration|declaration.to_item())
+            .collect();
+        Ok((!items.is_empty()).then_some(items))
+    }
+}
+
+// The file of a call hierarchy item, and the name of the method declaration
+// it is
+fn call_hierarchy_lookup<O: Output>(ctx: &InitActionContext<O>,
+                                    item: &CallHierarchyItem)
+                                    -> Option<(CanonPath, ZeroSpan)> {
+    let path = parse_file_path!(&item.uri, "call hierarchy").ok()?;
+    let canon_path = CanonPath::try_from_path_buf(path).ok()?;
+    let loc = ZeroSpan::from_range(
+        ls_util::range_to_dls(item.selection_range), canon_path.as_path());
+    wait_for_device_path!(ctx, canon_path.clone());
+    Some((canon_path, loc))
+}
+
+impl RequestAction for CallHierarchyIncomingCalls {
+    type Response = Option<Vec<CallHierarchyIncomingCall>>;
+
+    fn fallback_response() -> Result<Self::Response, ResponseError> {
+        Ok(None)
+    }
+
+    fn get_identifier(params: &Self::Params) -> String {
+        Self::request_identifier(params.item.uri.as_str())
+    }
+
+    fn handle<O: Output>(
+        ctx: InitActionContext<O>,
+        params: Self::Params,
+    ) -> Result<Self::Response, ResponseError> {
+        debug!("Requesting incoming calls with params {:?}", params);
+        let Some((canon_path, loc)) = call_hierarchy_lookup(
+            &ctx, &params.item) else {
+                return Self::fallback_response();
+            };
+        let filter = ctx.device_active_contexts.lock().unwrap().clone();
+        let analysis = ctx.analysis.lock().unwrap();
+        let devices = analysis.filtered_device_analysises_containing_file(
+            &canon_path, Some(&filter));
+        Ok(Some(call_hierarchy::incoming_calls(&analysis, &devices, &loc)
+                .into_iter()
+                .map(|(caller, calls)|CallHierarchyIncomingCall {
+                    from: caller.to_item(),
+                    from_ranges: calls.into_iter()
+                        .map(|call|ls_util::dls_to_range(call.range))
+                        .collect(),
+                })
+                .collect()))
+    }
+}
+
+impl RequestAction for CallHierarchyOutgoingCalls {
+    type Response = Option<Vec<CallHierarchyOutgoingCall>>;
+
+    fn fallback_response() -> Result<Self::Response, ResponseError> {
+        Ok(None)
+    }
+
+    fn get_identifier(params: &Self::Params) -> String {
+        Self::request_identifier(params.item.uri.as_str())
+    }
+
+    fn handle<O: Output>(
+        ctx: InitActionContext<O>,
+        params: Self::Params,
+    ) -> Result<Self::Response, ResponseError> {
+        debug!("Requesting outgoing calls with params {:?}", params);
+        let Some((canon_path, loc)) = call_hierarchy_lookup(
+            &ctx, &params.item) else {
+                return Self::fallback_response();
+            };
+        let filter = ctx.device_active_contexts.lock().unwrap().clone();
+        let analysis = ctx.analysis.lock().unwrap();
+        let devices = analysis.filtered_device_analysises_containing_file(
+            &canon_path, Some(&filter));
+        Ok(Some(call_hierarchy::outgoing_calls(&analysis, &devices, &loc)
+                .into_iter()
+                .map(|(callee, calls)|CallHierarchyOutgoingCall {
+                    to: callee.to_item(),
+                    from_ranges: calls.into_iter()
+                        .map(|call|ls_util::dls_to_range(call.range))
+                        .collect(),
+                })
+                .collect()))
+    }
+}
+
+impl RequestAction for FoldingRangeRequest {
+    type Response = Option<Vec<FoldingRange>>;
+
+    fn fallback_response() -> Result<Self::Response, ResponseError> {
+        Ok(None)
+    }
+
+    fn get_identifier(params: &Self::Params) -> String {
+        Self::request_identifier(params.text_document.uri.as_str())
+    }
+
+    fn handle<O: Output>(
+        ctx: InitActionContext<O>,
+        params: Self::Params,
+    ) -> Result<Self::Response, ResponseError> {
+        debug!("Handling folding range request {:?}", params);
+        let (ast, text) = document_ast(&ctx, &params.text_document.uri)?;
+        Ok(Some(folding::folding_ranges(&ast, &text)))
+    }
+}
+
+impl RequestAction for SelectionRangeRequest {
+    type Response = Option<Vec<SelectionRange>>;
+
+    fn fallback_response() -> Result<Self::Response, ResponseError> {
+        Ok(None)
+    }
+
+    fn get_identifier(params: &Self::Params) -> String {
+        Self::request_identifier(params.text_document.uri.as_str())
+    }
+
+    fn handle<O: Output>(
+        ctx: InitActionContext<O>,
+        params: Self::Params,
+    ) -> Result<Self::Response, ResponseError> {
+        debug!("Handling selection range request {:?}", params);
+        let (ast, _) = document_ast(&ctx, &params.text_document.uri)?;
+        Ok(Some(params.positions.into_iter()
+                .map(|position|folding::selection_range(&ast, position))
+                .collect()))
+    }
+}
+
 fn text_document_position_to_ident(doc_pos: &TextDocumentPositionParams)
                                    -> String {
     format!(
@@ -533,7 +1097,7 @@ impl RequestAction for GotoDefinition {
 
         let mut limitations = HashSet::new();
         match definitions_at_fp(&ctx, &fp, &mut limitations) {
-            Ok(locs) => {
+            Ok(locs) if !locs.is_empty() => {
                 let lsp_locations: Vec<_> = locs.into_iter()
                     .map(|l|ls_util::dls_to_location(&l))
                     .collect();
@@ -543,16 +1107,69 @@ impl RequestAction for GotoDefinition {
                     limitations,
                     &ctx))
             },
-            Err(lookuperror) => {
-                let main_file_name = fp.path();
-                warn_miss_lookup(lookuperror,
-                                 main_file_name.to_str());
-                Self::fallback_response()
+            result => {
+                if let Err(lookuperror) = result {
+                    let main_file_name = fp.path();
+                    warn_miss_lookup(lookuperror,
+                                     main_file_name.to_str());
+                }
+                // Templates of libraries that the device does not import
+                // are only found by name
+                let doc_pos = &params.text_document_position_params;
+                let (_, text) = document_text(&ctx,
+                                              &doc_pos.text_document.uri)?;
+                match instantiated_template_at(
+                    &text, ls_util::position_to_dls(doc_pos.position)) {
+                    Some(template) => {
+                        let locations = library_template_declarations(
+                            template, &ctx.import_search_dirs());
+                        Ok(Some(GotoDefinitionResponse::Array(
+                            locations.iter().map(ls_util::dls_to_location)
+                                .collect())).into())
+                    },
+                    None => Self::fallback_response(),
+                }
             },
         }
     }
 }
 
+// The name of the template instantiated at a position, in an `is`
+// statement or clause
+fn instantiated_template_at(text: &str, position: ZeroPosition)
+                            -> Option<&str> {
+    let tokens = code_tokens(text);
+    let index = tokens.iter().position(
+        |token|token.kind == TokenKind::Identifier
+            && token.range.contains_pos(position))?;
+    let name = &tokens[index];
+    let is_instantiation = index.checked_sub(1)
+        .is_some_and(|before|tokens[before].kind == TokenKind::Is)
+        || matches!(call_site(text, name.range.start()),
+                    Some(CallSite::Templates { .. }));
+    is_instantiation.then_some(name.text)
+}
+
+// The declarations of templates named `name` in the DML files of `dirs`,
+// and of the subdirectories that keep their DML 1.4 files
+fn library_template_declarations(name: &str, dirs: &[PathBuf])
+                                 -> Vec<ZeroSpan> {
+    let mut declarations = vec![];
+    for file in dirs.iter().flat_map(
+        |dir|dml_files(dir, 1).chain(dml_files(&dir.join("1.4"), 1))) {
+        let Ok(text) = std::fs::read_to_string(&file) else {
+            continue;
+        };
+        let tokens = code_tokens(&text);
+        declarations.extend(tokens.windows(2)
+            .filter(|pair|pair[0].kind == TokenKind::Template
+                    && pair[1].kind == TokenKind::Identifier
+                    && pair[1].text == name)
+            .map(|pair|ZeroSpan::from_range(pair[1].range, file.clone())));
+    }
+    declarations
+}
+
 impl RequestAction for References {
     type Response = ResponseWithMessage<Vec<Location>>;
 
@@ -589,8 +1206,14 @@ impl RequestAction for References {
         wait_for_device_path!(ctx, canon_path);
 
         let mut limitations = HashSet::new();
-        match references_at_fp(&ctx, &fp, &mut limitations) {
-            Ok(locs) => {
+        match references_at_fp(&ctx, &fp,
+                               params.context.include_declaration,
+                               &mut limitations) {
+            Ok(mut locs) => {
+                // The same location may be found through several symbols,
+                // such as those of the objects instantiating a template
+                locs.sort();
+                locs.dedup();
                 let lsp_locations: Vec<_> = locs.into_iter()
                     .map(|l|ls_util::dls_to_location(&l))
                     .collect();
@@ -618,16 +1241,21 @@ impl RequestAction for Completion {
         Ok(vec![])
     }
 
-    fn get_identifier(_params: &Self::Params) -> String {
-        todo!("COMPLETION REQUEST NOT IMPLEMENTED");
+    fn get_identifier(params: &Self::Params) -> String {
+        Self::request_identifier(
+            &text_document_position_to_ident(&params.text_document_position))
     }
 
     fn handle<O: Output>(
-        _ctx: InitActionContext<O>,
-        _params: Self::Params,
+        ctx: InitActionContext<O>,
+        params: Self::Params,
     ) -> Result<Self::Response, ResponseError> {
-        // TODO: Acquire completions for location
-        Self::fallback_response()
+        debug!("Handling completion request {:?}", params);
+        let doc_pos = &params.text_document_position;
+        let (path, text) = document_text(&ctx, &doc_pos.text_document.uri)?;
+        let canon_path = make_canon_path!(path)?;
+        Ok(completion::completions(&ctx, &canon_path, &text,
+                                   ls_util::position_to_dls(doc_pos.position)))
     }
 }
 
@@ -672,20 +1300,164 @@ impl RequestAction for Rename {
                 &params.text_document_position))
     }
 
+    fn timeout() -> std::time::Duration {
+        crate::server::dispatch::DEFAULT_REQUEST_TIMEOUT * 5
+    }
+
     fn handle<O: Output>(
-        _ctx: InitActionContext<O>,
-        _params: Self::Params,
+        ctx: InitActionContext<O>,
+        params: Self::Params,
     ) -> Result<Self::Response, ResponseError> {
-        // TODO: Perform a rename
-        Self::fallback_response()
+        debug!("Requesting rename with params {:?}", params);
+        check_new_name(&params.new_name)?;
+        let mut limitations = HashSet::new();
+        let Some(target) = rename_target(
+            &ctx, &params.text_document_position, &mut limitations)? else {
+                return Self::fallback_response();
+            };
+        let mut file_edits: HashMap<PathBuf, Vec<TextEdit>> = HashMap::new();
+        for span in &target.locations {
+            file_edits.entry(span.path()).or_default().push(TextEdit {
+                range: ls_util::dls_to_range(span.range),
+                new_text: params.new_name.clone(),
+            });
+        }
+        let edit = WorkspaceEdit {
+            changes: Some(file_edits.into_iter().filter_map(
+                |(path, edits)|Some((parse_uri(path.to_str()?).ok()?, edits)))
+                          .collect()),
+            ..WorkspaceEdit::default()
+        };
+        Ok(response_maybe_with_limitations(
+            &target.at.path(), edit, limitations, &ctx))
     }
 }
 
-#[derive(Debug)]
-pub enum ExecuteCommandResponse {
-    /// Response/client request containing workspace edits.
-    ApplyEdit(ApplyWorkspaceEditParams),
-}
+impl RequestAction for PrepareRenameRequest {
+    type Response = Option<PrepareRenameResponse>;
+
+    fn timeout() -> std::time::Duration {
+        crate::server::dispatch::DEFAULT_REQUEST_TIMEOUT * 5
+    }
+
+    fn fallback_response() -> Result<Self::Response, ResponseError> {
+        Ok(None)
+    }
+
+    fn get_identifier(params: &Self::Params) -> String {
+        Self::request_identifier(&text_document_position_to_ident(params))
+    }
+
+    fn handle<O: Output>(
+        ctx: InitActionContext<O>,
+        params: Self::Params,
+    ) -> Result<Self::Response, ResponseError> {
+        debug!("Preparing rename with params {:?}", params);
+        let Some(target) = rename_target(&ctx, &params,
+                                         &mut HashSet::new())? else {
+            return Self::fallback_response();
+        };
+        Ok(Some(PrepareRenameResponse::RangeWithPlaceholder {
+            range: ls_util::dls_to_range(target.at.range),
+            placeholder: target.name,
+        }))
+    }
+}
+
+// A symbol to rename, with all the locations of its name
+#[derive(Debug)]
+struct RenameTarget {
+    // The name at the renamed position
+    at: ZeroSpan,
+    name: String,
+    locations: Vec<ZeroSpan>,
+}
+
+// Symbols can only be renamed to names that can be declared
+fn check_new_name(new_name: &str) -> Result<(), ResponseError> {
+    if is_identifier(new_name) {
+        Ok(())
+    } else {
+        Err(ResponseError::Message(
+            rpc_error_code(StandardError::InvalidParams),
+            format!("'{}' is not a valid DML identifier", new_name)))
+    }
+}
+
+// The text of a single-line span, from the file as currently known
+fn span_text<O: Output>(ctx: &InitActionContext<O>, span: &ZeroSpan)
+                        -> Option<String> {
+    if span.range.row_start != span.range.row_end {
+        return None;
+    }
+    let line = ctx.vfs.load_line(&span.path(), span.range.row_start).ok()?;
+    let (start, end) = (span.range.col_start.0 as usize,
+                        span.range.col_end.0 as usize);
+    Some(line.chars().skip(start).take(end.checked_sub(start)?).collect())
+}
+
+// The symbol to rename at a position, if any. Symbols that are also
+// declared in the standard library cannot be renamed, and neither can
+// symbols of other kinds than devices, banks, registers, fields, methods,
+// templates and parameters.
+fn rename_target<O: Output>(ctx: &InitActionContext<O>,
+                            position: &TextDocumentPositionParams,
+                            limitations: &mut HashSet<DLSLimitation>)
+                            -> Result<Option<RenameTarget>, ResponseError> {
+    let Some(fp) = ctx.text_doc_pos_to_pos(position, "rename") else {
+        return Ok(None);
+    };
+    let canon_path = make_canon_path!(fp.path())?;
+    wait_for_device_path!(ctx, canon_path);
+    let locations = match rename_locations_at_fp(ctx, &fp, limitations) {
+        Ok(locations) => locations,
+        Err(lookuperror) => {
+            warn_miss_lookup(lookuperror, fp.path().to_str());
+            return Ok(None);
+        },
+    };
+    let stdlib_dir = ctx.analysis.lock().unwrap().stdlib_dir()
+        .and_then(|dir|dir.canonicalize().ok());
+    rename_target_among(&fp, locations, stdlib_dir.as_deref(),
+                        |span|span_text(ctx, span)).map(Some)
+}
+
+// The symbol to rename at `fp`, given the locations of the names of the
+// renameable symbols there, the directory of the standard library, and the
+// text of a span
+fn rename_target_among(fp: &ZeroFilePosition,
+                       locations: Vec<ZeroSpan>,
+                       stdlib_dir: Option<&Path>,
+                       text: impl Fn(&ZeroSpan) -> Option<String>)
+                       -> Result<RenameTarget, ResponseError> {
+    let Some(at) = locations.iter().find(|span|span.contains_pos(fp))
+        .cloned() else {
+            return Err(ResponseError::Message(
+                rpc_error_code(StandardError::InvalidRequest),
+                "No renameable symbol at this position".to_string()));
+        };
+    let name = text(&at).unwrap_or_default();
+    if let Some(dir) = stdlib_dir {
+        if locations.iter().any(|span|span.path().starts_with(dir)) {
+            return Err(ResponseError::Message(
+                rpc_error_code(StandardError::InvalidRequest),
+                format!("'{}' is declared in the standard library, and \
+                         cannot be renamed", name)));
+        }
+    }
+    // Locations that do not spell out the name, such as that of 'this',
+    // are not renamed
+    let locations = locations.into_iter()
+        .filter(|span|text(span).as_deref() == Some(&name))
+        .collect();
+    Ok(RenameTarget { at, name, locations })
+}
+
+#[derive(Debug)]
+pub enum ExecuteCommandResponse {
+    /// Response/client request containing workspace edits.
+    ApplyEdit(ApplyWorkspaceEditParams),
+}
 
 impl server::Response for ExecuteCommandResponse {
     fn send<O: Output>(self, id: server::RequestId, out: &O) {
@@ -694,7 +1466,7 @@ impl server::Response for ExecuteCommandResponse {
             ExecuteCommandResponse::ApplyEdit(ref params) => {
                 let id = out.provide_id();
                 let params = ApplyWorkspaceEditParams {
-                    label: None,
+                    label: params.label.clone(),
                     edit: params.edit.clone() };
 
                 let request = Request::<ApplyWorkspaceEdit>::new(id, params);
@@ -723,37 +1495,436 @@ impl RequestAction for ExecuteCommand {
         )
     }
 
-    /// Currently, no support for this
+    /// The commands of device code lenses, creating the files generated for
+    /// the device
     fn handle<O: Output>(
-        _ctx: InitActionContext<O>,
-        _params: ExecuteCommandParams,
+        ctx: InitActionContext<O>,
+        params: ExecuteCommandParams,
     ) -> Result<Self::Response, ResponseError> {
-        // TODO: handle specialized commands. or if no such commands, remove
+        debug!("Handling execute command request {:?}", params);
+        #[cfg(feature = "exporters")]
+        if let Some(edit) = generated_files_edit(&ctx, &params)? {
+            return Ok(ExecuteCommandResponse::ApplyEdit(
+                ApplyWorkspaceEditParams {
+                    label: Some(params.command),
+                    edit,
+                }));
+        }
+        #[cfg(not(feature = "exporters"))]
+        let _ = (ctx, params);
         Self::fallback_response()
     }
 }
 
+// The edit creating the files that a device code lens command generates,
+// next to the file of the device
+#[cfg(feature = "exporters")]
+fn generated_files_edit<O: Output>(ctx: &InitActionContext<O>,
+                                   params: &ExecuteCommandParams)
+                                   -> Result<Option<WorkspaceEdit>,
+                                             ResponseError> {
+    use crate::mcp::analysis::parse_dml;
+    use crate::mcp::exporters::{c_identifier, generate_device_docs,
+                                generate_register_map_html,
+                                generate_simics_test, DocFormat,
+                                GeneratedFile, SimicsTestOptions};
+    let command = params.command.as_str();
+    if !code_lens::device_commands().iter().any(|offered|offered == command) {
+        return Ok(None);
+    }
+    let Some(uri) = params.arguments.first()
+        .and_then(|argument|argument.as_str())
+        .and_then(|uri|Uri::from_str(uri).ok()) else {
+            return Err(ResponseError::Message(
+                rpc_error_code(StandardError::InvalidParams),
+                format!("{} expects the URI of a device file", command)));
+        };
+    let (path, text) = document_text(ctx, &uri)?;
+    let spec = parse_dml(&path, &text)
+        .map_err(|e|ResponseError::Message(
+            rpc_error_code(StandardError::InternalError),
+            format!("Could not analyze {}: {}", path.display(), e)))?
+        .to_device_spec();
+    let files = if command == code_lens::GENERATE_TEST_COMMAND {
+        generate_simics_test(&spec, &SimicsTestOptions::default())
+    } else if command == code_lens::GENERATE_DOCS_COMMAND {
+        vec![GeneratedFile {
+            path: format!("{}.{}", c_identifier(&spec.name),
+                          DocFormat::Markdown.extension()),
+            content: generate_device_docs(&spec, DocFormat::Markdown),
+        }]
+    } else {
+        vec![GeneratedFile {
+            path: format!("{}_register_map.html", c_identifier(&spec.name)),
+            content: generate_register_map_html(&spec),
+        }]
+    };
+    // The generated files are named relative to the directory of the device
+    let directory = uri.as_str().rsplit_once('/')
+        .map_or(uri.as_str(), |(directory, _)|directory);
+    let mut operations = vec![];
+    for file in files {
+        let uri = Uri::from_str(&format!("{}/{}", directory, file.path))
+            .map_err(|e|ResponseError::Message(
+                rpc_error_code(StandardError::InternalError),
+                format!("Could not create a URI for {}: {}", file.path, e)))?;
+        operations.push(DocumentChangeOperation::Op(ResourceOp::Create(
+            CreateFile {
+                uri: uri.clone(),
+                options: Some(CreateFileOptions {
+                    overwrite: Some(true),
+                    ignore_if_exists: None,
+                }),
+                annotation_id: None,
+            })));
+        operations.push(DocumentChangeOperation::Edit(TextDocumentEdit {
+            text_document: OptionalVersionedTextDocumentIdentifier {
+                uri,
+                version: None,
+            },
+            edits: vec![OneOf::Left(TextEdit {
+                range: Range::default(),
+                new_text: file.content,
+            })],
+        }));
+    }
+    Ok(Some(WorkspaceEdit {
+        document_changes: Some(DocumentChanges::Operations(operations)),
+        ..WorkspaceEdit::default()
+    }))
+}
+
 impl RequestAction for CodeActionRequest {
-    type Response = Vec<Command>;
+    type Response = CodeActionResponse;
 
     fn fallback_response() -> Result<Self::Response, ResponseError> {
         Ok(vec![])
     }
 
-    fn get_identifier(_params: &Self::Params) -> String {
-        todo!("CODE ACTION REQUEST NOT IMPLEMENTED");
-    }
-
+    fn get_identifier(params: &Self::Params) -> String {
+        Self::request_identifier(&format!(
+            "{}-{}-{}-{}-{}",
+            params.text_document.uri.as_str(),
+            params.range.start.line,
+            params.range.start.character,
+            params.range.end.line,
+            params.range.end.character))
+    }
+
+    /// Quick fixes replacing deprecated names, and a source action
+    /// applying all of them in the file, along with the other quick fixes
+    /// of `quick_fix_actions`
     fn handle<O: Output>(
-        _ctx: InitActionContext<O>,
-        _params: Self::Params,
+        ctx: InitActionContext<O>,
+        params: Self::Params,
     ) -> Result<Self::Response, ResponseError> {
-        // TODO: figure out if we want to use this
-        // note: a "code action" is like a command tied to a code position, I think
-        Self::fallback_response()
+        debug!("Handling code action request {:?}", params);
+        let canon_path = {
+            let parsed = parse_file_path!(
+                &params.text_document.uri, "code actions")
+                .map_err(|_|ResponseError::Message(
+                    rpc_error_code(StandardError::ParseError),
+                    "Failed to parse file path".to_string()))?;
+            make_canon_path!(parsed)?
+        };
+        let quick_fixes = quick_fix_actions(&ctx, &params, &canon_path);
+        let simics_api = ctx.simics_api();
+        let uses = match ctx.analysis.lock().unwrap()
+            .get_isolated_analysis(&canon_path) {
+                Ok(isolated) => deprecated_uses(&isolated.toplevel,
+                                                simics_api),
+                Err(_) => return Self::fallback_response(),
+            };
+        let url = params.text_document.uri;
+        let rewrite_edit = |uses: &[&DeprecatedUse]| {
+            let edits = uses.iter().filter_map(
+                |use_|use_.safe_rewrite().map(|replacement|TextEdit {
+                    range: ls_util::dls_to_range(use_.span.range),
+                    new_text: replacement.to_string(),
+                })).collect();
+            WorkspaceEdit {
+                changes: Some(HashMap::from([(url.clone(), edits)])),
+                ..WorkspaceEdit::default()
+            }
+        };
+        let fixable: Vec<&DeprecatedUse> = uses.iter()
+            .filter(|use_|use_.safe_rewrite().is_some()).collect();
+        let mut actions = vec![];
+        for use_ in &fixable {
+            let range = ls_util::dls_to_range(use_.span.range);
+            if range.end < params.range.start || params.range.end < range.start {
+                continue;
+            }
+            let diagnostics = params.context.diagnostics.iter()
+                .filter(|diag|diag.range == range).cloned().collect();
+            actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                title: format!("Replace '{}' with '{}'",
+                               use_.deprecation.name,
+                               use_.safe_rewrite().unwrap()),
+                kind: Some(CodeActionKind::QUICKFIX),
+                diagnostics: Some(diagnostics),
+                edit: Some(rewrite_edit(&[use_])),
+                is_preferred: Some(true),
+                ..CodeAction::default()
+            }));
+        }
+        if fixable.len() > 1 {
+            actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                title: "Replace all deprecated names".to_string(),
+                kind: Some(CodeActionKind::SOURCE_FIX_ALL),
+                edit: Some(rewrite_edit(&fixable)),
+                ..CodeAction::default()
+            }));
+        }
+        actions.extend(quick_fixes);
+        Ok(actions)
     }
 }
 
+// A code action applying a quick fix to a document
+fn quick_fix_action(url: &Uri, fix: QuickFix, kind: CodeActionKind,
+                    diagnostics: Vec<Diagnostic>) -> CodeActionOrCommand {
+    let edits = fix.edits.into_iter().map(|(range, new_text)|TextEdit {
+        range: ls_util::dls_to_range(range),
+        new_text,
+    }).collect();
+    CodeActionOrCommand::CodeAction(CodeAction {
+        title: fix.title,
+        kind: Some(kind),
+        diagnostics: (!diagnostics.is_empty()).then_some(diagnostics),
+        edit: Some(WorkspaceEdit {
+            changes: Some(HashMap::from([(url.clone(), edits)])),
+            ..WorkspaceEdit::default()
+        }),
+        ..CodeAction::default()
+    })
+}
+
+// Quick fixes importing or removing the instantiations of missing
+// templates, removing unused declarations, replacing DML 1.2 'parameter'
+// declarations and documenting objects declared in the requested range,
+// the source action organizing imports, and refactorings
+fn quick_fix_actions<O: Output>(ctx: &InitActionContext<O>,
+                                params: &CodeActionParams,
+                                path: &CanonPath)
+                                -> Vec<CodeActionOrCommand> {
+    let url = &params.text_document.uri;
+    let Ok((_, text)) = document_text(ctx, url) else {
+        return vec![];
+    };
+    let range = ls_util::range_to_dls(params.range);
+    let diagnostics_on_row = |row: u32| params.context.diagnostics.iter()
+        .filter(|diag|diag.range.start.line == row).cloned().collect();
+    let mut actions = vec![];
+
+    let keywords = parameter_keywords(&text);
+    let replace_keywords = |title: &str, keywords: &[ZeroRange]| QuickFix {
+        title: title.to_string(),
+        edits: keywords.iter().map(|range|(*range, "param".to_string()))
+            .collect(),
+    };
+    for keyword in keywords.iter().filter(|keyword|keyword.overlaps(range)) {
+        actions.push(quick_fix_action(
+            url, replace_keywords("Replace 'parameter' with 'param'",
+                                  &[*keyword]),
+            CodeActionKind::QUICKFIX,
+            diagnostics_on_row(keyword.row_start.0)));
+    }
+    if keywords.len() > 1 {
+        actions.push(quick_fix_action(
+            url, replace_keywords("Replace all 'parameter' with 'param'",
+                                  &keywords),
+            CodeActionKind::SOURCE_FIX_ALL, vec![]));
+    }
+
+    // Templates can be imported from the directory of the file, include
+    // paths, the workspace or the standard library
+    let search_dirs = ctx.import_search_dirs();
+    let analysis = ctx.analysis.lock().unwrap();
+    let Ok(isolated) = analysis.get_isolated_analysis(path) else {
+        return actions;
+    };
+    for diagnostic in &params.context.diagnostics {
+        if let Some((kind, name)) = unused_declaration(&diagnostic.message) {
+            if let Some(range) = declaration_range(
+                &isolated.toplevel, kind,
+                ls_util::range_to_dls(diagnostic.range)) {
+                let title = format!("Remove unused {} '{}'",
+                                    kind.kind_name(), name);
+                actions.push(quick_fix_action(
+                    url, remove_declaration(&text, title, range),
+                    CodeActionKind::QUICKFIX, vec![diagnostic.clone()]));
+            }
+            continue;
+        }
+        let Some(template) = missing_template(&diagnostic.message) else {
+            continue;
+        };
+        let mut imports: Vec<String> = analysis.all_isolated_analysises()
+            .into_iter()
+            .filter(|(other, analysis)|*other != path
+                    && analysis.toplevel.templates.iter().any(
+                        |decl|decl.obj.object.name.val == template))
+            .filter_map(|(other, _)|import_path(
+                path.as_path(), other.as_path(), &search_dirs))
+            .collect();
+        imports.sort();
+        imports.dedup();
+        for import in imports {
+            actions.push(quick_fix_action(
+                url, add_import(&isolated.toplevel, &import),
+                CodeActionKind::QUICKFIX, vec![diagnostic.clone()]));
+        }
+        if let Some(fix) = remove_instantiation(
+            &text, ls_util::range_to_dls(diagnostic.range)) {
+            actions.push(quick_fix_action(url, fix, CodeActionKind::QUICKFIX,
+                                          vec![diagnostic.clone()]));
+        }
+    }
+    for fix in documentation_stubs(&isolated.toplevel, &text,
+                                   params.range.start.line
+                                   ..=params.range.end.line) {
+        actions.push(quick_fix_action(url, fix, CodeActionKind::QUICKFIX,
+                                      vec![]));
+    }
+    // Imports that no device using the file uses are removed
+    let unused: Vec<ZeroRange> = analysis.unused_declaration_errors(
+        None, &ctx.config.lock().unwrap().unused_declarations)
+        .remove(path.as_path()).into_iter().flatten()
+        .filter(|error|unused_declaration(&error.description).is_some_and(
+            |(kind, _)|kind == UnusedKind::Import))
+        .map(|error|error.span.range)
+        .collect();
+    let grouping = ctx.config.lock().unwrap().import_grouping;
+    if let Some(fix) = organize_imports(&text, &unused, grouping) {
+        actions.push(quick_fix_action(url, fix,
+                                      CodeActionKind::SOURCE_ORGANIZE_IMPORTS,
+                                      vec![]));
+    }
+    if let Some(fix) = extract_method(&isolated.toplevel, &text, range) {
+        actions.push(quick_fix_action(url, fix,
+                                      CodeActionKind::REFACTOR_EXTRACT,
+                                      vec![]));
+    }
+    if let Some(fix) = extract_template(&isolated.toplevel, &text, range) {
+        actions.push(quick_fix_action(url, fix,
+                                      CodeActionKind::REFACTOR_EXTRACT,
+                                      vec![]));
+    }
+    // Only methods called once, in every device using the file, are inlined
+    let position = ZeroFilePosition::new(range.start(), path.as_path());
+    if let Some((method, calls)) = call_hierarchy::calls_of_method_at(
+        &analysis.all_device_analysises_containing_file(path), &position) {
+        let fix = match calls.as_slice() {
+            [call] if method.file == position.file
+                && call.file == position.file =>
+                inline_method(&isolated.toplevel, &text, method.range,
+                              call.range),
+            _ => None,
+        };
+        if let Some(fix) = fix {
+            actions.push(quick_fix_action(url, fix,
+                                          CodeActionKind::REFACTOR_INLINE,
+                                          vec![]));
+        }
+    }
+    actions
+}
+
+// The formatting requested by the options of a formatting request
+fn format_config(options: &FormattingOptions) -> FormatConfig {
+    FormatConfig {
+        indent_style: if options.insert_spaces {
+            IndentStyle::Spaces(options.tab_size as usize)
+        } else {
+            IndentStyle::Tabs
+        },
+        .. FormatConfig::default()
+    }
+}
+
+// The path and current text of a document
+fn document_text<O: Output>(ctx: &InitActionContext<O>, uri: &Uri)
+                            -> Result<(PathBuf, String), ResponseError> {
+    let path = parse_file_path!(uri, "document text")
+        .map_err(|_|ResponseError::Message(
+            rpc_error_code(StandardError::ParseError),
+            "Failed to parse file path".to_string()))?;
+    let text = ctx.vfs.load_file(&path)
+        .and_then(|contents|contents.as_string_content())
+        .map_err(|e|ResponseError::Message(
+            rpc_error_code(StandardError::InternalError),
+            format!("Could not load {}: {:?}", path.display(), e)))?;
+    Ok((path, text))
+}
+
+// The syntax tree of a document, parsed from its current text
+fn document_ast<O: Output>(ctx: &InitActionContext<O>, uri: &Uri)
+                           -> Result<(TopAst, String), ResponseError> {
+    let (path, text) = document_text(ctx, uri)?;
+    let file = TextFile::from_str(&text).map_err(|e|ResponseError::Message(
+        rpc_error_code(StandardError::InternalError),
+        format!("Could not read {}: {:?}", path.display(), e)))?;
+    let (ast, _, _) = parse_file(&path, FileSpec { path: &path, file: &file })
+        .map_err(|e|ResponseError::Message(
+            rpc_error_code(StandardError::InternalError),
+            format!("Could not parse {}: {:?}", path.display(), e)))?;
+    Ok((ast, text))
+}
+
+// The position of the end of a line, given without its line ending
+fn line_end(row: u32, line: &str) -> Position {
+    Position {
+        line: row,
+        character: line.trim_end_matches('\r').encode_utf16().count() as u32,
+    }
+}
+
+// Format some lines of a document, which is left as it is if it has
+// syntax errors
+fn format_document_lines(path: &Path, text: &str, config: &FormatConfig,
+                         rows: std::ops::RangeInclusive<u32>)
+                         -> Result<Vec<TextEdit>, ResponseError> {
+    let edits = match format_lines(text, config, rows) {
+        Ok(edits) => edits,
+        Err(e @ FormatError::Syntax { .. }) => {
+            debug!("Not formatting {:?}: {}", path, e);
+            return Ok(vec![]);
+        },
+        Err(e) => {
+            error!("Failed to format {:?}: {}", path, e);
+            return Err(ResponseError::Message(
+                rpc_error_code(StandardError::InternalError),
+                e.to_string()));
+        },
+    };
+    Ok(text_edits(text, edits))
+}
+
+// The text edits making line edits of a document
+fn text_edits(text: &str, edits: Vec<LineEdit>) -> Vec<TextEdit> {
+    let source: Vec<&str> = text.split('\n').collect();
+    edits.into_iter().map(|edit|{
+        let (first, last) = (*edit.rows.start(), *edit.rows.end());
+        match edit.text {
+            Some(new_text) => TextEdit {
+                range: Range {
+                    start: Position { line: first, character: 0 },
+                    end: line_end(last, source[last as usize]),
+                },
+                new_text,
+            },
+            None => TextEdit {
+                range: Range {
+                    start: Position { line: first, character: 0 },
+                    end: Position { line: last + 1, character: 0 },
+                },
+                new_text: String::new(),
+            },
+        }
+    }).collect()
+}
+
 impl RequestAction for Formatting {
     type Response = Vec<TextEdit>;
 
@@ -764,16 +1935,47 @@ impl RequestAction for Formatting {
         ))
     }
 
-    fn get_identifier(_params: &Self::Params) -> String {
-        todo!("FORMATTING REQUEST NOT IMPLEMENTED");
+    fn get_identifier(params: &Self::Params) -> String {
+        Self::request_identifier(params.text_document.uri.as_str())
     }
 
+    /// Replace the whole document with its formatted text, with its imports
+    /// organized if so configured. Documents with syntax errors are left as
+    /// they are.
     fn handle<O: Output>(
-        _ctx: InitActionContext<O>,
-        _params: Self::Params,
+        ctx: InitActionContext<O>,
+        params: Self::Params,
     ) -> Result<Self::Response, ResponseError> {
-        // TODO: format document
-        Self::fallback_response()
+        debug!("Handling formatting request {:?}", params);
+        let (path, text) = document_text(&ctx, &params.text_document.uri)?;
+        let mut config = format_config(&params.options);
+        {
+            let dls_config = ctx.config.lock().unwrap();
+            if dls_config.organize_imports_on_format {
+                config.organize_imports = Some(dls_config.import_grouping);
+            }
+        }
+        let formatted = match format_source(&text, &config) {
+            Ok(formatted) => formatted,
+            Err(e @ FormatError::Syntax { .. }) => {
+                debug!("Not formatting {:?}: {}", path, e);
+                return Ok(vec![]);
+            },
+            Err(e) => {
+                error!("Failed to format {:?}: {}", path, e);
+                return Self::fallback_response();
+            },
+        };
+        if formatted == text {
+            return Ok(vec![]);
+        }
+        let last_row = text.matches('\n').count() as u32;
+        let end = line_end(last_row, text.rsplit('\n').next()
+                           .unwrap_or_default());
+        Ok(vec![TextEdit {
+            range: Range { start: Position::default(), end },
+            new_text: formatted,
+        }])
     }
 }
 
@@ -787,16 +1989,68 @@ impl RequestAction for RangeFormatting {
         ))
     }
 
-    fn get_identifier(_params: &Self::Params) -> String {
-        todo!("RANGE FORMATTING REQUEST NOT IMPLEMENTED");
+    fn get_identifier(params: &Self::Params) -> String {
+        Self::request_identifier(&format!(
+            "{}-{}-{}",
+            params.text_document.uri.as_str(),
+            params.range.start.line,
+            params.range.end.line))
     }
 
+    /// Format the lines of the range, and the lines they share comments
+    /// with
     fn handle<O: Output>(
-        _ctx: InitActionContext<O>,
-        _params: Self::Params,
+        ctx: InitActionContext<O>,
+        params: Self::Params,
     ) -> Result<Self::Response, ResponseError> {
-        // TODO: format range
-        Self::fallback_response()
+        debug!("Handling range formatting request {:?}", params);
+        let (path, text) = document_text(&ctx, &params.text_document.uri)?;
+        let Range { start, end } = params.range;
+        // A range ending at the start of a line ends with the line before
+        let last = if end.character == 0 && end.line > start.line {
+            end.line - 1
+        } else {
+            end.line
+        };
+        format_document_lines(&path, &text, &format_config(&params.options),
+                              start.line..=last)
+    }
+}
+
+impl RequestAction for OnTypeFormatting {
+    type Response = Option<Vec<TextEdit>>;
+
+    fn fallback_response() -> Result<Self::Response, ResponseError> {
+        Ok(None)
+    }
+
+    fn get_identifier(params: &Self::Params) -> String {
+        Self::request_identifier(&format!(
+            "{}-{}",
+            text_document_position_to_ident(
+                &params.text_document_position),
+            params.ch))
+    }
+
+    /// Reindent the line where a statement or block was ended, or the line
+    /// ended by a newline, by the depth of the braces around it. The new
+    /// line itself is left alone, keeping the indentation the editor gave
+    /// it.
+    fn handle<O: Output>(
+        ctx: InitActionContext<O>,
+        params: Self::Params,
+    ) -> Result<Self::Response, ResponseError> {
+        debug!("Handling on-type formatting request {:?}", params);
+        let position = params.text_document_position.position;
+        let row = match params.ch.as_str() {
+            "\n" if position.line == 0 => return Ok(None),
+            "\n" => position.line - 1,
+            _ => position.line,
+        };
+        let (_, text) = document_text(
+            &ctx, &params.text_document_position.text_document.uri)?;
+        let edit = reindent_line(&text, row, &format_config(&params.options));
+        Ok(Some(text_edits(&text, edit.into_iter().collect())))
     }
 }
 
@@ -824,16 +2078,322 @@ impl RequestAction for CodeLensRequest {
         Err(ResponseError::Empty)
     }
 
-    fn get_identifier(_params: &Self::Params) -> String {
-        todo!("CODE LENSES NOT IMPLEMENTED");
+    fn get_identifier(params: &Self::Params) -> String {
+        Self::request_identifier(params.text_document.uri.as_str())
     }
 
+    /// The layouts of the banks declared in the file, and the generation
+    /// commands of the device declared in it
     fn handle<O: Output>(
-        _ctx: InitActionContext<O>,
-        _params: Self::Params,
+        ctx: InitActionContext<O>,
+        params: Self::Params,
     ) -> Result<Self::Response, ResponseError> {
-        // TODO: figure out if we want to use this
-        Self::fallback_response()
+        debug!("Handling code lens request {:?}", params);
+        let canon_path = {
+            let parsed = parse_file_path!(
+                &params.text_document.uri, "code lenses")
+                .map_err(|_|ResponseError::Message(
+                    rpc_error_code(StandardError::ParseError),
+                    "Failed to parse file path".to_string()))?;
+            make_canon_path!(parsed)?
+        };
+        let filter = ctx.device_active_contexts.lock().unwrap().clone();
+        let analysis = ctx.analysis.lock().unwrap();
+        let isolated = match analysis.get_isolated_analysis(&canon_path) {
+            Ok(isolated) => isolated,
+            Err(error) => {
+                warn_miss_lookup(error,
+                                 Some(params.text_document.uri.as_str()));
+                return Ok(vec![]);
+            },
+        };
+        let mut lenses = isolated.toplevel.device.as_ref()
+            .map(|device|code_lens::device_lenses(
+                &params.text_document.uri,
+                ls_util::dls_to_range(device.name.span.range)))
+            .unwrap_or_default();
+        let devices = analysis.filtered_device_analysises_containing_file(
+            &canon_path, Some(&filter));
+        lenses.extend(code_lens::bank_lenses(
+            &devices, isolated.toplevel.filespan.file));
+        Ok(lenses)
+    }
+}
+
+/// Analyze the workspace and standard library in the background, see
+/// `InitActionContext::warmup`
+#[derive(Debug, Clone)]
+pub struct WarmupRequest;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct WarmupResult {
+    /// The number of files queued for analysis
+    pub files: usize,
+}
+
+impl LSPRequest for WarmupRequest {
+    type Params = ();
+    type Result = WarmupResult;
+
+    const METHOD: &'static str = "server/warmup";
+}
+
+/// The memory held by the server and what is in it, to measure the effect
+/// of changes to the analysis data model
+#[derive(Debug, Clone)]
+pub struct MemoryStatsRequest;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MemoryStats {
+    /// Resident memory of the server process in bytes, where known
+    pub resident_bytes: Option<u64>,
+    /// Distinct identifiers interned, and the bytes they take up
+    pub interned_names: usize,
+    pub interned_name_bytes: usize,
+    /// Distinct file paths interned
+    pub interned_paths: usize,
+    pub isolated_analyses: usize,
+    pub device_analyses: usize,
+    /// Symbols of all device analyses
+    pub device_symbols: usize,
+}
+
+impl LSPRequest for MemoryStatsRequest {
+    type Params = ();
+    type Result = MemoryStats;
+
+    const METHOD: &'static str = "server/memoryStats";
+}
+
+/// What the server has done since it started, for performance reports: the
+/// time spent in each phase of the analysis, how often its caches were hit,
+/// how many files it has analyzed and the memory it holds
+#[derive(Debug, Clone)]
+pub struct ServerStatusRequest;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerStatus {
+    #[serde(flatten)]
+    pub stats: Stats,
+    pub indexed_files: IndexedFiles,
+    pub memory: MemoryStats,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexedFiles {
+    /// Files with an isolated analysis
+    pub analyzed: usize,
+    /// Device files with a device analysis
+    pub devices: usize,
+    pub linted: usize,
+    /// Files in the persisted symbol indexes of the workspaces
+    pub symbol_index: usize,
+}
+
+impl LSPRequest for ServerStatusRequest {
+    type Params = ();
+    type Result = ServerStatus;
+
+    const METHOD: &'static str = "dml/serverStatus";
+}
+
+/// The register maps of the devices a document is part of, for extensions
+/// rendering them
+#[derive(Debug, Clone)]
+pub struct RegisterMapRequest;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisterMapParams {
+    pub text_document: TextDocumentIdentifier,
+}
+
+impl LSPRequest for RegisterMapRequest {
+    type Params = RegisterMapParams;
+    type Result = Vec<RegisterMap>;
+
+    const METHOD: &'static str = "dml/registerMap";
+}
+
+impl RequestAction for RegisterMapRequest {
+    type Response = Vec<RegisterMap>;
+
+    fn fallback_response() -> Result<Self::Response, ResponseError> {
+        Ok(vec![])
+    }
+
+    fn get_identifier(params: &Self::Params) -> String {
+        Self::request_identifier(params.text_document.uri.as_str())
+    }
+
+    fn handle<O: Output>(
+        ctx: InitActionContext<O>,
+        params: Self::Params,
+    ) -> Result<Self::Response, ResponseError> {
+        debug!("Handling register map request {:?}", params);
+        let canon_path = {
+            let parsed = parse_file_path!(
+                &params.text_document.uri, "register map")
+                .map_err(|_|ResponseError::Message(
+                    rpc_error_code(StandardError::ParseError),
+                    "Failed to parse file path".to_string()))?;
+            make_canon_path!(parsed)?
+        };
+        wait_for_device_path!(ctx, canon_path.clone());
+        let filter = ctx.device_active_contexts.lock().unwrap().clone();
+        let analysis = ctx.analysis.lock().unwrap();
+        let mut maps: Vec<RegisterMap> = analysis
+            .filtered_device_analysises_containing_file(
+                &canon_path, Some(&filter))
+            .into_iter()
+            .map(register_map::register_map)
+            .collect();
+        maps.sort_by(|map, other|map.device.cmp(&other.device));
+        Ok(maps)
+    }
+}
+
+/// The layouts of the banks of the devices a document is part of, with the
+/// registers in address order and the bytes between them, for reviewing how
+/// the address space is covered
+#[derive(Debug, Clone)]
+pub struct BankLayoutRequest;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BankLayoutParams {
+    pub text_document: TextDocumentIdentifier,
+    // Only the layouts of bank elements of this name, with indices
+    pub bank: Option<String>,
+}
+
+impl LSPRequest for BankLayoutRequest {
+    type Params = BankLayoutParams;
+    type Result = Vec<DeviceLayout>;
+
+    const METHOD: &'static str = "dml/bankLayout";
+}
+
+impl RequestAction for BankLayoutRequest {
+    type Response = Vec<DeviceLayout>;
+
+    fn fallback_response() -> Result<Self::Response, ResponseError> {
+        Ok(vec![])
+    }
+
+    fn get_identifier(params: &Self::Params) -> String {
+        Self::request_identifier(params.text_document.uri.as_str())
+    }
+
+    fn handle<O: Output>(
+        ctx: InitActionContext<O>,
+        params: Self::Params,
+    ) -> Result<Self::Response, ResponseError> {
+        debug!("Handling bank layout request {:?}", params);
+        let canon_path = {
+            let parsed = parse_file_path!(
+                &params.text_document.uri, "bank layout")
+                .map_err(|_|ResponseError::Message(
+                    rpc_error_code(StandardError::ParseError),
+                    "Failed to parse file path".to_string()))?;
+            make_canon_path!(parsed)?
+        };
+        wait_for_device_path!(ctx, canon_path.clone());
+        let filter = ctx.device_active_contexts.lock().unwrap().clone();
+        let analysis = ctx.analysis.lock().unwrap();
+        let mut layouts: Vec<DeviceLayout> = analysis
+            .filtered_device_analysises_containing_file(
+                &canon_path, Some(&filter))
+            .into_iter()
+            .map(register_map::device_layout)
+            .collect();
+        if let Some(bank) = &params.bank {
+            for layout in &mut layouts {
+                layout.banks.retain(|layout|&layout.name == bank);
+            }
+        }
+        layouts.sort_by(|layout, other|layout.device.cmp(&other.device));
+        Ok(layouts)
+    }
+}
+
+/// The object trees of the devices a document is part of, after templates
+/// and 'in each' declarations are applied
+#[derive(Debug, Clone)]
+pub struct ExpandTemplatesRequest;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExpandTemplatesParams {
+    pub text_document: TextDocumentIdentifier,
+    // Expand only the innermost object declared around this position
+    pub position: Option<lsp_types::Position>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExpandedDevice {
+    pub device: String,
+    pub object: ExpandedObject<lsp_types::Location>,
+}
+
+impl LSPRequest for ExpandTemplatesRequest {
+    type Params = ExpandTemplatesParams;
+    type Result = Vec<ExpandedDevice>;
+
+    const METHOD: &'static str = "dml/expandTemplates";
+}
+
+impl RequestAction for ExpandTemplatesRequest {
+    type Response = Vec<ExpandedDevice>;
+
+    fn fallback_response() -> Result<Self::Response, ResponseError> {
+        Ok(vec![])
+    }
+
+    fn get_identifier(params: &Self::Params) -> String {
+        Self::request_identifier(params.text_document.uri.as_str())
+    }
+
+    fn handle<O: Output>(
+        ctx: InitActionContext<O>,
+        params: Self::Params,
+    ) -> Result<Self::Response, ResponseError> {
+        debug!("Handling template expansion request {:?}", params);
+        let canon_path = {
+            let parsed = parse_file_path!(
+                &params.text_document.uri, "template expansion")
+                .map_err(|_|ResponseError::Message(
+                    rpc_error_code(StandardError::ParseError),
+                    "Failed to parse file path".to_string()))?;
+            make_canon_path!(parsed)?
+        };
+        wait_for_device_path!(ctx, canon_path.clone());
+        let position = params.position.map(|position|ZeroFilePosition::new(
+            ls_util::position_to_dls(position), canon_path.as_path()));
+        let filter = ctx.device_active_contexts.lock().unwrap().clone();
+        let analysis = ctx.analysis.lock().unwrap();
+        let mut devices: Vec<ExpandedDevice> = analysis
+            .filtered_device_analysises_containing_file(
+                &canon_path, Some(&filter))
+            .into_iter()
+            .filter_map(|device| {
+                let object = match &position {
+                    Some(position) =>
+                        expansion::expand_object_at(device, position),
+                    None => expansion::expand_device(device),
+                }?;
+                Some(ExpandedDevice {
+                    device: device.name.clone(),
+                    object: object.map_locations(&ls_util::dls_to_location),
+                })
+            })
+            .collect();
+        devices.sort_by(|device, other|device.device.cmp(&other.device));
+        Ok(devices)
     }
 }
 
@@ -951,6 +2511,14 @@ impl SentRequest for RegisterCapability {
         }
 }
 
+impl SentRequest for UnregisterCapability {
+    type Response = <Self as lsp_data::request::Request>::Result;
+    fn on_response<O: Output>
+        (_ctx: &InitActionContext<O>, _response: Self::Response, _out: &O) {
+            info!("Successful unregistration of some capability");
+        }
+}
+
 impl SentRequest for WorkspaceConfiguration {
     type Response = <Self as lsp_data::request::Request>::Result;
     fn on_response<O: Output>
@@ -995,3 +2563,53 @@ impl SentRequest for WorkspaceConfiguration {
         ctx.maybe_changed_config(old, out);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rename_new_name() {
+        assert!(check_new_name("threshold").is_ok());
+        assert!(check_new_name("_tick2").is_ok());
+        for invalid in ["", "not a name", "2fast", "a.b", "method"] {
+            assert!(matches!(check_new_name(invalid),
+                             Err(ResponseError::Message(..))), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_rename_target() {
+        let dir = PathBuf::from("/project");
+        let span = |file: &str, row, col_start, col_end|ZeroSpan::from_range(
+            ZeroRange::from_u32(row, row, col_start, col_end), dir.join(file));
+        let declaration = span("dev.dml", 5, 10, 15);
+        let reference = span("dev.dml", 7, 20, 25);
+        let elsewhere = span("lib.dml", 2, 4, 9);
+        // The implicit reference of a 'this' to its object
+        let this = span("dev.dml", 9, 8, 12);
+        let texts = HashMap::from([(declaration, "limit"), (reference, "limit"),
+                                   (elsewhere, "limit"), (this, "this")]);
+        let text = |span: &ZeroSpan|texts.get(span).map(|t|t.to_string());
+        let at = |file: &str, row, col|ZeroFilePosition::new(
+            ZeroPosition::from_u32(row, col), dir.join(file));
+        let locations = vec![declaration, reference, elsewhere, this];
+
+        let target = rename_target_among(&at("dev.dml", 7, 22),
+                                         locations.clone(), None, text)
+            .unwrap();
+        assert_eq!(target.at, reference);
+        assert_eq!(target.name, "limit");
+        assert_eq!(target.locations, vec![declaration, reference, elsewhere]);
+
+        // Positions between the names have nothing to rename
+        assert!(rename_target_among(&at("dev.dml", 6, 0), locations.clone(),
+                                    None, text).is_err());
+
+        // Nor do symbols also declared in the standard library
+        assert!(rename_target_among(&at("dev.dml", 5, 12), locations.clone(),
+                                    Some(&dir), text).is_err());
+        assert!(rename_target_among(&at("dev.dml", 5, 12), locations,
+                                    Some(Path::new("/stdlib")), text).is_ok());
+    }
+}
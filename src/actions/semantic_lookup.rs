@@ -5,7 +5,7 @@
 use log::{debug, error};
 
 use std::collections::HashSet;
-use std::{fmt, mem};
+use std::mem;
 use std::sync::Arc;
 
 use crate::actions::analysis_storage::{AnalysisLookupError, AnalysisStorage};
@@ -20,46 +20,9 @@ use crate::analysis::reference::{Reference, ReferenceKind};
 use crate::file_management::CanonPath;
 use crate::server::Output;
 
-// The issue number is used as the _unique identifier_ for this
-// limitation, and should be the number of an open GITHUB
-// issue.
-// Example:
-// DLSLimitation {
-//         issue_num: 42,
-//         description: "Example of a DLS limitation".to_string(),
-//     };
-
-#[derive(Clone, Debug, Eq, PartialEq, Hash,)]
-pub struct DLSLimitation {
-    pub issue_num: u64,
-    pub description: String,
-}
-
-impl fmt::Display for DLSLimitation {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{} (issue#{})", self.description, self.issue_num)
-    }
-}
-
-pub fn type_semantic_limitation() -> DLSLimitation {
-    DLSLimitation {
-        issue_num: 65,
-        description: "The DLS does not currently support semantic analysis of \
-                      types, including reference finding".to_string(),
-    }
-}
-
-pub fn isolated_template_limitation(template_name: &str) -> DLSLimitation {
-    DLSLimitation {
-        issue_num: 31,
-        description:
-        format!("References from, and definitions inside, a template \
-                 cannot be evaluated \
-                 without an instantiating object. Open a device file \
-                 that uses the template '{}' to obtain such information.",
-                 template_name),
-    }
-}
+pub use crate::analysis::limitations::{DLSLimitation,
+                                       isolated_template_limitation,
+                                       type_semantic_limitation};
 
 // Because symbols need to be tied to their source analysis that result is
 // a [(DeviceAnalysis, [SymbolRef])] list
@@ -232,54 +195,6 @@ fn context_symbol_at_pos<'t>(isolated_analysis: &'t IsolatedAnalysis, pos: &Zero
     context
 }
 
-fn symbol_implementations_of_symbol<'t>(symbol: &'t SymbolRef,
-                                        analysis: &'t DeviceAnalysis)
-    -> Vec<SymbolRef> {
-    let symbol_lock = symbol.lock().unwrap();
-    
-    // Special case for methods, recursively follow the implementations to find
-    // all methods
-    if symbol_lock.kind == DMLSymbolKind::Method {
-        // Re-dropping the lock here is sensible, as we want to iterate over non-locked symbols
-        drop(symbol_lock);
-        #[allow(clippy::mutable_key_type)]
-        let mut syms: HashSet::<&'t SymbolRef> = HashSet::default();
-        let mut next_iteration = vec![symbol];
-        while let Some(next) = next_iteration.pop() {
-            let next_lock = next.lock().unwrap();
-            if !syms.contains(next) {
-                let parent = if let Some(meth_source) = next_lock.source.as_method() {
-                    if !meth_source.1.is_abstract() {
-                        syms.insert(next);
-                    }
-                    meth_source.0
-                } else {
-                    internal_error!("Expected method symbol source of symbol iterated over by\
-                                     implementations_of_symbol, symbol is {:?}", next_lock);
-                    continue;
-                };
-                
-                for impl_ref in &next_lock.implementations {
-                    // Note: We only need to find the implementations of the variant of this method
-                    // under parent. Other variants are handled by finding multiple symbols at
-                    // the ref location
-                    if let Some(impl_sym) = analysis.symbol_info.method_symbols.get(impl_ref)
-                        .and_then(|m|m.get(parent)) {
-                        next_iteration.push(impl_sym);
-                    } else {
-                        internal_error!("Expected method implementation symbol to exist in \
-                                             analysis, symbol is {:?}, implementation ref is {:?}",
-                        next_lock, impl_ref);
-                    }
-                }
-            }
-        }
-        syms.into_iter().map(Arc::clone).collect()
-    } else {
-        vec![]
-    }
-}
-
 // NOTE: It'd be nice for some of these methods to return hashsets of symbolrefs.
 // however, SymbolRefs can in fact be modified while these requests are running,
 // due to symbol->reference caching, so it would break hashset invariance
@@ -290,7 +205,7 @@ fn extra_implementations_of_method_symbols<'t>(semantic_lookup: &SemanticLookup<
     for (_device_analysis, symbols) in &semantic_lookup.stored_symbols {
         for symbol in symbols {
             full_method_symbol_set.extend(
-                symbol_implementations_of_symbol(symbol, _device_analysis).into_iter());
+                _device_analysis.symbol_implementations(symbol).into_iter());
         }
     }
     full_method_symbol_set
@@ -382,8 +297,11 @@ pub fn declarations_at_fp(context: &InitActionContext<impl Output>,
        .collect())
 }
 
+/// The references to the symbols at a position, optionally with their
+/// declarations and definitions
 pub fn references_at_fp(context: &InitActionContext<impl Output>,
                         fp: &ZeroFilePosition,
+                        include_declaration: bool,
                         relevant_limitations: &mut HashSet<DLSLimitation>)
     -> Result<Vec<ZeroSpan>, AnalysisLookupError> {
     let analysis_lock = context.analysis.lock().unwrap();
@@ -394,6 +312,249 @@ pub fn references_at_fp(context: &InitActionContext<impl Output>,
     mem::swap(relevant_limitations, &mut semantic_lookup.recognized_limitations);
     Ok(semantic_lookup.symbols()
        .into_iter()
-       .flat_map(|s|s.lock().unwrap().references.clone())
+       .flat_map(|s|s.lock().unwrap().reference_sites(include_declaration))
+       .collect())
+}
+
+/// Describe each of the symbols at a position with `describe`, which is
+/// given the device analysis the symbol is from
+pub fn describe_symbols_at_fp<T>(context: &InitActionContext<impl Output>,
+                                 fp: &ZeroFilePosition,
+                                 relevant_limitations:
+                                 &mut HashSet<DLSLimitation>,
+                                 describe: impl Fn(&DeviceAnalysis,
+                                                   &SymbolRef) -> Option<T>)
+    -> Result<Vec<T>, AnalysisLookupError> {
+    let analysis_lock = context.analysis.lock().unwrap();
+    let mut semantic_lookup = SemanticLookup::create_lookup(
+        fp,
+        &analysis_lock,
+        context)?;
+    mem::swap(relevant_limitations, &mut semantic_lookup.recognized_limitations);
+    Ok(semantic_lookup.stored_symbols.iter()
+       .flat_map(|(device, symbols)|symbols.iter()
+                 .filter_map(|symbol|describe(device, symbol))
+                 .collect::<Vec<_>>())
        .collect())
-}
\ No newline at end of file
+}
+
+/// The locations of all names of the renameable symbols at a position, in
+/// their declarations, definitions and references, along with those of any
+/// methods overriding or overridden by them
+pub fn rename_locations_at_fp(context: &InitActionContext<impl Output>,
+                              fp: &ZeroFilePosition,
+                              relevant_limitations: &mut HashSet<DLSLimitation>)
+    -> Result<Vec<ZeroSpan>, AnalysisLookupError> {
+    let analysis_lock = context.analysis.lock().unwrap();
+    let mut semantic_lookup = SemanticLookup::create_lookup(
+        fp,
+        &analysis_lock,
+        context)?;
+    mem::swap(relevant_limitations, &mut semantic_lookup.recognized_limitations);
+    Ok(rename_locations(&semantic_lookup.stored_symbols))
+}
+
+// The locations of all names of the renameable symbols among those found by
+// a lookup, see rename_locations_at_fp
+fn rename_locations(stored_symbols: &DeviceSymbols<'_>) -> Vec<ZeroSpan> {
+    let mut locations: HashSet<ZeroSpan> = HashSet::new();
+    for (device, device_symbols) in stored_symbols {
+        locations.extend(device.rename_locations(device_symbols));
+    }
+    let mut locations: Vec<ZeroSpan> = locations.into_iter().collect();
+    locations.sort();
+    locations
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::Path;
+    use std::str::FromStr;
+    use std::time::SystemTime;
+
+    use super::*;
+    use crate::analysis::{AnalysisOptions, TimestampedStorage};
+    use crate::analysis::parsing::tree::ZeroPosition;
+    use crate::concurrency::JobStatusKeeper;
+    use crate::file_management::PathResolver;
+    use crate::vfs::TextFile;
+
+    // The isolated analyses of the device file at `path` and the files it
+    // imports, and its device analysis
+    fn analyze(path: &Path)
+               -> (HashMap<CanonPath, IsolatedAnalysis>, DeviceAnalysis) {
+        let root = CanonPath::from_path_buf(path.to_path_buf()).unwrap();
+        let resolver = PathResolver::from(None);
+        let (_keeper, status) = JobStatusKeeper::new();
+        let mut analyses = HashMap::new();
+        let mut import_sources = HashMap::new();
+        let mut queue = vec![root.clone()];
+        while let Some(next) = queue.pop() {
+            if analyses.contains_key(&next) {
+                continue;
+            }
+            let file = TextFile::from_str(
+                &fs::read_to_string(next.as_path()).unwrap()).unwrap();
+            let analysis = IsolatedAnalysis::new(
+                &next, &next.to_path_buf(), file, status.clone()).unwrap();
+            for (dependency, import) in analysis.resolve_imports(
+                &resolver, Some(&root)).0 {
+                import_sources.insert(import, dependency.as_str().to_string());
+                queue.push(dependency);
+            }
+            analyses.insert(next, analysis);
+        }
+        let timestamp = SystemTime::now();
+        let bases = analyses.values().cloned()
+            .map(|stored|TimestampedStorage { timestamp, stored })
+            .collect();
+        let device = DeviceAnalysis::new(analyses[&root].clone(), bases,
+                                         import_sources,
+                                         AnalysisOptions::default(),
+                                         status).unwrap();
+        (analyses, device)
+    }
+
+    // The symbols at a one-based position in the file at `path`, as looked
+    // up by the language server
+    fn symbols_at<'t>(analysis_info: &AnalysisInfo<'t>, path: &Path,
+                      line: u32, column: u32) -> DeviceSymbols<'t> {
+        let fp = ZeroFilePosition::new(
+            ZeroPosition::from_u32(line - 1, column - 1), path.to_path_buf());
+        let mut limitations = HashSet::new();
+        match get_refs_and_syms_at_fp(&fp, analysis_info, &mut limitations)
+            .unwrap() {
+            SymbolsOrReference::Symbols(symbols) => symbols,
+            SymbolsOrReference::Reference(reference) => get_symbols_of_ref(
+                &reference, analysis_info, &mut limitations),
+            SymbolsOrReference::Nothing => vec![],
+        }
+    }
+
+    #[test]
+    fn test_rename_locations() {
+        let dir = std::env::temp_dir().join(
+            format!("dls-rename-locations-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("lib.dml"), "dml 1.4;
+template base {
+    method reset() default {}
+}
+template counter is base {
+    method reset() default {
+        default();
+    }
+}
+").unwrap();
+        let file = dir.join("dev.dml");
+        fs::write(&file, "dml 1.4;
+device dev;
+import \"lib.dml\";
+session int resets;
+bank regs is counter {
+    method reset() {
+        default();
+        resets++;
+    }
+}
+method poll() {
+    regs.reset();
+}
+").unwrap();
+        let (analyses, device) = analyze(&file);
+        let isolated = &analyses[&CanonPath::from_path_buf(file.clone())
+                                 .unwrap()];
+        let analysis_info = AnalysisInfo {
+            isolated_analysis: isolated,
+            device_analysises: vec![&device],
+        };
+        let rename_at = |line, column| -> Vec<String> {
+            rename_locations(&symbols_at(&analysis_info, &file, line, column))
+                .into_iter()
+                .map(|span|format!(
+                    "{}:{}:{}",
+                    span.path().file_name().unwrap().to_str().unwrap(),
+                    span.range.row_start.0 + 1, span.range.col_start.0 + 1))
+                .collect()
+        };
+
+        // A method is renamed along with all those it overrides and that
+        // override it, through both templates, wherever it is called. The
+        // calls of 'default' refer to it too, and are left out when renaming
+        // as they do not spell out its name.
+        let mut family = rename_at(6, 13);
+        family.sort();
+        assert_eq!(family, ["dev.dml:12:10", "dev.dml:6:12", "dev.dml:7:9",
+                            "lib.dml:3:12", "lib.dml:6:12", "lib.dml:7:9"]);
+        let mut called = rename_at(12, 11);
+        called.sort();
+        assert_eq!(called, family);
+        // Templates are renamed where declared and instantiated
+        let mut template = rename_at(5, 15);
+        template.sort();
+        assert_eq!(template, ["dev.dml:5:14", "lib.dml:5:10"]);
+        // Session variables are not renamed
+        assert!(rename_at(4, 13).is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_reference_sites() {
+        let dir = std::env::temp_dir().join(
+            format!("dls-reference-sites-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("dev.dml");
+        fs::write(&file, "dml 1.4;
+device dev;
+template limited {
+    param limit default 4;
+    method check() -> (bool) {
+        return this.val > limit;
+    }
+}
+bank regs {
+    register r size 4 @ 0x0 is limited;
+    in each register {
+        param limit = 8;
+        method reset() {
+            if (limit > 2) { log info: \"reset\"; }
+        }
+    }
+}
+method poll() -> (uint64) {
+    return regs.r.limit;
+}
+").unwrap();
+        let (analyses, device) = analyze(&file);
+        let analysis_info = AnalysisInfo {
+            isolated_analysis: &analyses[&CanonPath::from_path_buf(
+                file.clone()).unwrap()],
+            device_analysises: vec![&device],
+        };
+        let sites_at = |line, column, include_declaration| {
+            let symbols: Vec<SymbolRef> = symbols_at(
+                &analysis_info, &file, line, column).into_iter()
+                .flat_map(|(_, symbols)|symbols).collect();
+            assert_eq!(symbols.len(), 1);
+            let mut sites: Vec<(u32, u32)> = symbols[0].lock().unwrap()
+                .reference_sites(include_declaration).iter()
+                .map(|span|(span.range.row_start.0 + 1,
+                            span.range.col_start.0 + 1))
+                .collect();
+            sites.sort();
+            sites
+        };
+
+        // The parameter is referred to in the template instantiated by the
+        // register, in the 'in each' declaration and from outside the bank
+        let references = vec![(6, 27), (14, 17), (19, 19)];
+        assert_eq!(sites_at(19, 19, false), references);
+        assert_eq!(sites_at(12, 15, false), references);
+        // Declared in the template and in the 'in each' declaration
+        assert_eq!(sites_at(14, 18, true),
+                   vec![(4, 11), (6, 27), (12, 15), (14, 17), (19, 19)]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
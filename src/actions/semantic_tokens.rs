@@ -0,0 +1,264 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Semantic token highlighting of DML files
+//!
+//! Tokens are given to the names of declared symbols and to the references
+//! that resolve to them, typed by the kind of symbol so that editors can
+//! color hardware-model constructs distinctly from plain identifiers.
+use std::collections::{BTreeMap, HashSet};
+
+use lsp_types::{SemanticToken, SemanticTokenModifier, SemanticTokenType,
+                SemanticTokensLegend};
+
+use crate::analysis::{DeviceAnalysis, IsolatedAnalysis, LocationSpan,
+                      ZeroRange, ZeroSpan};
+use crate::analysis::parsing::lexer::TokenKind;
+use crate::analysis::quick_fixes::code_tokens;
+use crate::analysis::scope::{Scope, SubSymbol, SymbolContext};
+use crate::analysis::structure::objects::CompObjectKind;
+use crate::analysis::symbols::{DMLSymbolKind, StructureSymbol};
+
+/// The token types reported by the server, indexed by `token_type`
+pub const TOKEN_TYPES: &[SemanticTokenType] = &[
+    SemanticTokenType::NAMESPACE,
+    SemanticTokenType::new("template"),
+    SemanticTokenType::new("bank"),
+    SemanticTokenType::new("register"),
+    SemanticTokenType::new("field"),
+    SemanticTokenType::PARAMETER,
+    SemanticTokenType::INTERFACE,
+    SemanticTokenType::METHOD,
+    SemanticTokenType::VARIABLE,
+    SemanticTokenType::PROPERTY,
+    SemanticTokenType::EVENT,
+    SemanticTokenType::TYPE,
+    SemanticTokenType::ENUM_MEMBER,
+];
+
+/// The token modifiers reported by the server, indexed by bit
+pub const TOKEN_MODIFIERS: &[SemanticTokenModifier] = &[
+    SemanticTokenModifier::DECLARATION,
+    SemanticTokenModifier::READONLY,
+];
+
+const DECLARATION: u32 = 1 << 0;
+const READONLY: u32 = 1 << 1;
+
+pub fn legend() -> SemanticTokensLegend {
+    SemanticTokensLegend {
+        token_types: TOKEN_TYPES.to_vec(),
+        token_modifiers: TOKEN_MODIFIERS.to_vec(),
+    }
+}
+
+fn token_type_index(name: &str) -> u32 {
+    TOKEN_TYPES.iter().position(|typ|typ.as_str() == name).unwrap() as u32
+}
+
+// The token type and modifiers of the names of a kind of symbol
+fn token_type(kind: DMLSymbolKind) -> (u32, u32) {
+    let (name, modifiers) = match kind {
+        DMLSymbolKind::CompObject(kind) => (match kind {
+            CompObjectKind::Bank => "bank",
+            CompObjectKind::Register => "register",
+            CompObjectKind::Field => "field",
+            CompObjectKind::Interface | CompObjectKind::Implement
+                => "interface",
+            CompObjectKind::Attribute | CompObjectKind::Connect
+                => "property",
+            CompObjectKind::Event => "event",
+            CompObjectKind::Device | CompObjectKind::Group
+                | CompObjectKind::Port | CompObjectKind::Subdevice
+                => "namespace",
+        }, 0),
+        DMLSymbolKind::Parameter => ("parameter", READONLY),
+        DMLSymbolKind::Constant => ("variable", READONLY),
+        DMLSymbolKind::Extern | DMLSymbolKind::Local
+            | DMLSymbolKind::MethodArg | DMLSymbolKind::Saved
+            | DMLSymbolKind::Session => ("variable", 0),
+        DMLSymbolKind::Hook => ("event", 0),
+        DMLSymbolKind::Loggroup => ("enumMember", READONLY),
+        DMLSymbolKind::Method => ("method", 0),
+        DMLSymbolKind::Template => ("template", 0),
+        DMLSymbolKind::Typedef => ("type", 0),
+    };
+    (token_type_index(name), modifiers)
+}
+
+/// A semantic token, before delta encoding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token {
+    pub range: ZeroRange,
+    pub token_type: u32,
+    pub modifiers: u32,
+}
+
+impl Token {
+    fn new(range: ZeroRange, kind: DMLSymbolKind, declaration: bool) -> Self {
+        let (token_type, modifiers) = token_type(kind);
+        Token {
+            range,
+            token_type,
+            modifiers: if declaration {
+                modifiers | DECLARATION
+            } else {
+                modifiers
+            },
+        }
+    }
+}
+
+// The tokens of a file by their start, so that each name is only given one
+// token. The first token inserted for a name is kept.
+struct TokenMap {
+    // Symbols are also declared and referenced at keywords, such as the
+    // implicit instantiation of 'bank' by a bank, which are not given tokens
+    identifiers: HashSet<ZeroRange>,
+    tokens: BTreeMap<(u32, u32), Token>,
+}
+
+impl TokenMap {
+    fn new(text: &str) -> Self {
+        TokenMap {
+            identifiers: code_tokens(text).into_iter()
+                .filter(|token|token.kind == TokenKind::Identifier)
+                .map(|token|token.range).collect(),
+            tokens: BTreeMap::new(),
+        }
+    }
+
+    fn insert(&mut self, token: Token) {
+        if self.identifiers.contains(&token.range) {
+            self.tokens.entry((token.range.row_start.0,
+                               token.range.col_start.0))
+                .or_insert(token);
+        }
+    }
+}
+
+fn declaration_tokens(context: &SymbolContext, tokens: &mut TokenMap) {
+    if let Some(kind) = context.context.kind() {
+        tokens.insert(Token::new(context.loc_span().range, kind, true));
+    }
+    for sub in &context.subsymbols {
+        match sub {
+            SubSymbol::Context(con) => declaration_tokens(con, tokens),
+            SubSymbol::Simple(simple) => tokens.insert(
+                Token::new(simple.loc_span().range, simple.kind(), true)),
+        }
+    }
+}
+
+// Without a device analysis, only template instantiations are known to
+// refer to a certain kind of symbol
+fn instantiation_tokens(scope: &dyn Scope, tokens: &mut TokenMap) {
+    for reference in scope.defined_references() {
+        if reference.extra_info.was_instantiation {
+            tokens.insert(Token::new(reference.loc_span().range,
+                                     DMLSymbolKind::Template, false));
+        }
+    }
+    for subscope in scope.defined_scopes() {
+        instantiation_tokens(subscope, tokens);
+    }
+}
+
+/// The semantic tokens of a file with contents `text`, in order. References
+/// are typed by the symbols they resolve to in `devices`, the device analyses
+/// that include the file.
+pub fn file_tokens(isolated: &IsolatedAnalysis, text: &str,
+                   devices: &[&DeviceAnalysis]) -> Vec<Token> {
+    let file = isolated.toplevel.filespan.file;
+    let in_file = |span: &&ZeroSpan|span.file == file;
+    let mut tokens = TokenMap::new(text);
+    declaration_tokens(&isolated.top_context, &mut tokens);
+    for device in devices {
+        for symbol in device.symbol_info.all_symbols() {
+            let symbol = symbol.lock().unwrap();
+            for span in symbol.declarations.iter()
+                .chain(symbol.definitions.iter()).filter(in_file) {
+                    tokens.insert(Token::new(span.range, symbol.kind, true));
+                }
+            for span in symbol.references.iter().filter(in_file) {
+                tokens.insert(Token::new(span.range, symbol.kind, false));
+            }
+        }
+    }
+    instantiation_tokens(&isolated.toplevel, &mut tokens);
+    tokens.tokens.into_values().collect()
+}
+
+/// Delta encode ordered tokens, as sent to the client
+pub fn encode(tokens: &[Token]) -> Vec<SemanticToken> {
+    let (mut line, mut start) = (0, 0);
+    tokens.iter().map(|token| {
+        let (token_line, token_start) = (token.range.row_start.0,
+                                         token.range.col_start.0);
+        let delta_start = if token_line == line {
+            token_start - start
+        } else {
+            token_start
+        };
+        let encoded = SemanticToken {
+            delta_line: token_line - line,
+            delta_start,
+            length: token.range.col_end.0 - token_start,
+            token_type: token.token_type,
+            token_modifiers_bitset: token.modifiers,
+        };
+        (line, start) = (token_line, token_start);
+        encoded
+    }).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_keywords_not_tokens() {
+        let mut tokens = TokenMap::new("bank b is regs;");
+        let bank = DMLSymbolKind::CompObject(CompObjectKind::Bank);
+        tokens.insert(Token::new(ZeroRange::from_u32(0, 0, 0, 4),
+                                 DMLSymbolKind::Template, false));
+        tokens.insert(Token::new(ZeroRange::from_u32(0, 0, 5, 6), bank, true));
+        tokens.insert(Token::new(ZeroRange::from_u32(0, 0, 5, 6),
+                                 DMLSymbolKind::Parameter, false));
+        tokens.insert(Token::new(ZeroRange::from_u32(0, 0, 10, 13),
+                                 DMLSymbolKind::Template, false));
+        let tokens: Vec<Token> = tokens.tokens.into_values().collect();
+        assert_eq!(tokens, vec![
+            Token::new(ZeroRange::from_u32(0, 0, 5, 6), bank, true),
+        ]);
+    }
+
+    #[test]
+    fn test_encode() {
+        let token = |row, col_start, col_end, kind, declaration| Token::new(
+            ZeroRange::from_u32(row, row, col_start, col_end),
+            kind, declaration);
+        let tokens = [
+            token(1, 5, 9, DMLSymbolKind::CompObject(CompObjectKind::Bank),
+                  true),
+            token(1, 13, 16, DMLSymbolKind::Template, false),
+            token(4, 2, 8, DMLSymbolKind::Parameter, true),
+        ];
+        assert_eq!(encode(&tokens), vec![
+            SemanticToken {
+                delta_line: 1, delta_start: 5, length: 4,
+                token_type: token_type_index("bank"),
+                token_modifiers_bitset: DECLARATION,
+            },
+            SemanticToken {
+                delta_line: 0, delta_start: 8, length: 3,
+                token_type: token_type_index("template"),
+                token_modifiers_bitset: 0,
+            },
+            SemanticToken {
+                delta_line: 3, delta_start: 2, length: 6,
+                token_type: token_type_index("parameter"),
+                token_modifiers_bitset: DECLARATION | READONLY,
+            },
+        ]);
+    }
+}
@@ -0,0 +1,266 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Persistence of the workspace symbol index across server restarts, so that
+//! reopening a repository in the same state does not require re-analyzing
+//! its files to answer workspace symbol requests
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use log::{debug, info};
+use lsp_types::WorkspaceSymbol;
+use serde::{Deserialize, Serialize};
+
+use crate::cache;
+use crate::stats::{self, Cache};
+pub use crate::analysis::content_hash;
+
+/// The cache file of the index of a workspace, see `cache::cache_path`
+pub const SYMBOL_INDEX_FILE: &str = "symbol-index.json";
+
+// Bump when the stored format, or the symbols extracted from a file, change
+const SYMBOL_INDEX_FORMAT: u32 = 3;
+
+/// The git revision of a workspace, and the files that differ from it
+#[derive(Debug)]
+struct GitState {
+    revision: String,
+    // Modified, untracked and ignored files and directories
+    dirty: HashSet<PathBuf>,
+}
+
+fn git(root: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git").arg("-C").arg(root).args(args)
+        .output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout).ok()
+}
+
+impl GitState {
+    fn of(root: &Path) -> Option<GitState> {
+        let revision = git(root, &["rev-parse", "HEAD"])?.trim().to_string();
+        let toplevel = PathBuf::from(
+            git(root, &["rev-parse", "--show-toplevel"])?.trim());
+        let toplevel = toplevel.canonicalize().unwrap_or(toplevel);
+        let status = git(root, &["status", "--porcelain", "-z", "--ignored",
+                                 "--untracked-files=all"])?;
+        let mut dirty = HashSet::new();
+        let mut entries = status.split('\0');
+        while let Some(entry) = entries.next() {
+            let Some(path) = entry.get(3..).filter(|path|!path.is_empty())
+            else { continue; };
+            // Renames and copies are followed by the original path
+            if entry.starts_with(['R', 'C']) {
+                if let Some(original) = entries.next() {
+                    dirty.insert(toplevel.join(original));
+                }
+            }
+            dirty.insert(toplevel.join(path.trim_end_matches('/')));
+        }
+        Some(GitState { revision, dirty })
+    }
+
+    fn is_dirty(&self, file: &Path) -> bool {
+        file.ancestors().any(|path|self.dirty.contains(path))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IndexedFile {
+    hash: u64,
+    // Whether the file was unmodified at the revision of the index
+    clean: bool,
+    symbols: Vec<WorkspaceSymbol>,
+}
+
+/// The symbols of the analyzed files of a workspace, keyed by the git
+/// revision of the workspace and by the contents of each file
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SymbolIndex {
+    revision: Option<String>,
+    files: BTreeMap<PathBuf, IndexedFile>,
+    #[serde(skip)]
+    changed: bool,
+}
+
+impl SymbolIndex {
+    /// Load the index of the workspace at `root`, without the files that
+    /// changed since they were indexed. Files that were unmodified at the
+    /// revision of the index, and still are, are kept without being read.
+    pub fn load(root: &Path) -> SymbolIndex {
+        let Some(mut index) = cache::load::<SymbolIndex>(
+            root, SYMBOL_INDEX_FILE, SYMBOL_INDEX_FORMAT) else {
+            return SymbolIndex::default();
+        };
+        let indexed = index.files.len();
+        index.retain_unchanged(GitState::of(root).as_ref());
+        stats::record_cache(Cache::SymbolIndex, index.files.len() as u64,
+                            (indexed - index.files.len()) as u64);
+        info!("Loaded the symbols of {} out of {} indexed files from {:?}",
+              index.files.len(), indexed,
+              cache::cache_path(root, SYMBOL_INDEX_FILE));
+        index
+    }
+
+    // Drop the files whose contents differ from the indexed ones, and
+    // move the index to the current revision
+    fn retain_unchanged(&mut self, git: Option<&GitState>) {
+        let same_revision = git.is_some_and(
+            |git|self.revision.as_ref() == Some(&git.revision));
+        let indexed = self.files.len();
+        self.files.retain(|file, indexed| {
+            let clean = git.is_some_and(|git|!git.is_dirty(file));
+            let unchanged = (same_revision && indexed.clean && clean)
+                || fs::read_to_string(file).is_ok_and(
+                    |text|content_hash(&text) == indexed.hash);
+            indexed.clean = clean;
+            unchanged
+        });
+        self.changed |= self.files.len() != indexed;
+        self.revision = git.map(|git|git.revision.clone());
+    }
+
+    /// Record the symbols of a file, as analyzed with contents of the
+    /// given hash
+    pub fn update(&mut self, file: PathBuf, hash: u64,
+                  symbols: Vec<WorkspaceSymbol>) {
+        if self.files.get(&file).is_some_and(|indexed|indexed.hash == hash) {
+            return;
+        }
+        // Not trusted until checked against the file on disk
+        self.files.insert(file, IndexedFile { hash, clean: false, symbols });
+        self.changed = true;
+    }
+
+    /// The indexed files and their symbols
+    pub fn symbols(&self)
+                   -> impl Iterator<Item = (&PathBuf, &[WorkspaceSymbol])> {
+        self.files.iter().map(|(file, indexed)|(file, &indexed.symbols[..]))
+    }
+
+    /// Save the index of the workspace at `root`, if it changed and caching
+    /// is on. Files
+    /// that were analyzed with other contents than the ones on disk, e.g.
+    /// with unsaved edits, are left out.
+    pub fn save(&mut self, root: &Path) -> io::Result<()> {
+        if !self.changed || !cache::enabled() {
+            return Ok(());
+        }
+        self.retain_unchanged(GitState::of(root).as_ref());
+        cache::save(root, SYMBOL_INDEX_FILE, SYMBOL_INDEX_FORMAT, self)?;
+        self.changed = false;
+        debug!("Saved the symbols of {} files to {:?}", self.files.len(),
+               cache::cache_path(root, SYMBOL_INDEX_FILE));
+        Ok(())
+    }
+}
+
+/// How well a symbol name matches a workspace symbol query, `None` if the
+/// characters of the query do not all appear in the name in order. Case is
+/// ignored, and matches of consecutive characters and at the starts of
+/// words score higher.
+pub fn fuzzy_match(query: &str, name: &str) -> Option<i64> {
+    let name: Vec<char> = name.chars().collect();
+    let mut score = 0;
+    let mut next = 0;
+    let mut previous: Option<usize> = None;
+    for wanted in query.chars() {
+        let found = next + name[next..].iter().position(
+            |c|c.to_lowercase().eq(wanted.to_lowercase()))?;
+        let at_word_start = found == 0
+            || matches!(name[found - 1], '_' | '.')
+            || (name[found].is_uppercase() && name[found - 1].is_lowercase());
+        score += 1;
+        if previous.is_some_and(|previous|previous + 1 == found) {
+            score += 5;
+        } else if at_word_start {
+            score += 3;
+        }
+        if name[found] == wanted {
+            score += 1;
+        }
+        // Skipped characters count against the match, more so between
+        // matched characters than before the first one
+        let skipped = (found - next) as i64;
+        score -= if previous.is_some() { skipped.min(5) } else {
+            skipped.min(10) / 2
+        };
+        previous = Some(found);
+        next = found + 1;
+    }
+    if next == name.len() && query.chars().count() == name.len() {
+        score += 10;
+    }
+    Some(score)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use lsp_types::{Location, OneOf, SymbolKind, Uri};
+    use std::str::FromStr;
+
+    fn symbol(name: &str) -> WorkspaceSymbol {
+        WorkspaceSymbol {
+            name: name.to_string(),
+            kind: SymbolKind::CLASS,
+            tags: None,
+            container_name: None,
+            location: OneOf::Left(Location {
+                uri: Uri::from_str("file:///a.dml").unwrap(),
+                range: Default::default(),
+            }),
+            data: None,
+        }
+    }
+
+    #[test]
+    fn test_symbol_index_persistence() {
+        let root = std::env::temp_dir().join(
+            format!("dls-symbol-index-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let root = root.canonicalize().unwrap();
+        let (a, b) = (root.join("a.dml"), root.join("b.dml"));
+        fs::write(&a, "device a;").unwrap();
+        fs::write(&b, "device b;").unwrap();
+
+        let mut index = SymbolIndex::load(&root);
+        assert_eq!(index.symbols().count(), 0);
+        index.update(a.clone(), content_hash("device a;"), vec![symbol("a")]);
+        // Analyzed with unsaved edits, so not persisted
+        index.update(b.clone(), content_hash("device c;"), vec![symbol("c")]);
+        index.save(&root).unwrap();
+
+        let names = |index: &SymbolIndex| index.symbols()
+            .flat_map(|(_, symbols)|symbols.iter().map(|s|s.name.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(names(&SymbolIndex::load(&root)), vec!["a"]);
+        // Files changed since they were indexed are dropped
+        fs::write(&a, "device a2;").unwrap();
+        assert!(names(&SymbolIndex::load(&root)).is_empty());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_fuzzy_match() {
+        assert_eq!(fuzzy_match("rst", "reset"), Some(7));
+        assert!(fuzzy_match("rst", "status").is_none());
+        assert!(fuzzy_match("", "status").is_some());
+        let ranked = |query| {
+            let mut names = vec!["ctrl_reg", "control", "CtrlReg", "crl",
+                                 "interrupt_ctrl"];
+            names.retain(|name|fuzzy_match(query, name).is_some());
+            names.sort_by_key(|name|-fuzzy_match(query, name).unwrap());
+            names
+        };
+        assert_eq!(ranked("ctrl"), vec!["ctrl_reg", "CtrlReg",
+                                        "interrupt_ctrl", "control"]);
+        assert_eq!(ranked("cr"), vec!["crl", "ctrl_reg", "CtrlReg",
+                                      "control", "interrupt_ctrl"]);
+        assert_eq!(ranked("creg"), vec!["ctrl_reg", "CtrlReg"]);
+    }
+}
@@ -0,0 +1,297 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Database of deprecated template and parameter names, with their
+//! replacements, and the rewrites that modernize uses of them
+use std::fmt;
+
+use lsp_types::DiagnosticSeverity;
+
+use crate::analysis::DMLError;
+use crate::analysis::parsing::tree::{ZeroRange, ZeroSpan};
+use crate::analysis::stdlib_versions::{toplevel_statements, SimicsApiVersion};
+use crate::analysis::structure::toplevel::{StatementSpecStatement, TopLevel};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeprecatedKind {
+    Template,
+    Parameter,
+}
+
+impl DeprecatedKind {
+    pub fn kind_name(&self) -> &'static str {
+        match self {
+            DeprecatedKind::Template => "template",
+            DeprecatedKind::Parameter => "parameter",
+        }
+    }
+}
+
+/// Where a name stopped being the preferred one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeprecatedIn {
+    // DML 1.2 names that have a new name in DML 1.4
+    Dml14,
+    SimicsApi(u32),
+}
+
+impl fmt::Display for DeprecatedIn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeprecatedIn::Dml14 => write!(f, "DML 1.4"),
+            DeprecatedIn::SimicsApi(version) =>
+                write!(f, "Simics API {}", version),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deprecation {
+    pub kind: DeprecatedKind,
+    pub name: &'static str,
+    pub deprecated_in: DeprecatedIn,
+    pub replacement: Option<&'static str>,
+    // Whether replacing the name is all that is needed to keep the
+    // behavior, so that the rewrite can be applied without review
+    pub safe: bool,
+}
+
+impl Deprecation {
+    /// Whether the name is deprecated in the given API version, unknown
+    /// versions only consider the deprecations of DML 1.4
+    pub fn applies_to(&self, version: Option<SimicsApiVersion>) -> bool {
+        match self.deprecated_in {
+            DeprecatedIn::Dml14 => true,
+            DeprecatedIn::SimicsApi(since) =>
+                version.is_some_and(|SimicsApiVersion(v)|v >= since),
+        }
+    }
+}
+
+pub const DEPRECATIONS: &[Deprecation] = &[
+    Deprecation {
+        kind: DeprecatedKind::Template,
+        name: "unimplemented",
+        deprecated_in: DeprecatedIn::Dml14,
+        replacement: Some("unimpl"),
+        safe: true,
+    },
+    Deprecation {
+        kind: DeprecatedKind::Template,
+        name: "silent_unimplemented",
+        deprecated_in: DeprecatedIn::Dml14,
+        replacement: Some("silent_unimpl"),
+        safe: true,
+    },
+    Deprecation {
+        kind: DeprecatedKind::Template,
+        name: "checkreserved",
+        deprecated_in: DeprecatedIn::Dml14,
+        replacement: Some("reserved"),
+        safe: true,
+    },
+    Deprecation {
+        kind: DeprecatedKind::Parameter,
+        name: "hard_reset_value",
+        deprecated_in: DeprecatedIn::Dml14,
+        replacement: Some("init_val"),
+        safe: true,
+    },
+    // The new parameter only has an effect with the 'soft_reset_val'
+    // template instantiated
+    Deprecation {
+        kind: DeprecatedKind::Parameter,
+        name: "soft_reset_value",
+        deprecated_in: DeprecatedIn::Dml14,
+        replacement: Some("soft_reset_val"),
+        safe: false,
+    },
+    // Banks are accessed through the transaction interface, and can no
+    // longer be bridged to io_memory
+    Deprecation {
+        kind: DeprecatedKind::Parameter,
+        name: "use_io_memory",
+        deprecated_in: DeprecatedIn::SimicsApi(7),
+        replacement: None,
+        safe: false,
+    },
+];
+
+pub fn deprecation(kind: DeprecatedKind, name: &str)
+                   -> Option<&'static Deprecation> {
+    DEPRECATIONS.iter().find(|d|d.kind == kind && d.name == name)
+}
+
+/// A use of a deprecated name in a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeprecatedUse {
+    pub deprecation: &'static Deprecation,
+    // The span of the name
+    pub span: ZeroSpan,
+}
+
+impl DeprecatedUse {
+    /// The text to replace the use with, if the rewrite is safe
+    pub fn safe_rewrite(&self) -> Option<&'static str> {
+        self.deprecation.replacement.filter(|_|self.deprecation.safe)
+    }
+
+    pub fn description(&self) -> String {
+        let Deprecation { kind, name, deprecated_in, replacement, .. } =
+            self.deprecation;
+        let mut description = format!("The {} name '{}' is deprecated in {}",
+                                      kind.kind_name(), name, deprecated_in);
+        if let Some(replacement) = replacement {
+            description.push_str(&format!("; use '{}' instead", replacement));
+        }
+        description
+    }
+
+    pub fn to_error(&self) -> DMLError {
+        DMLError {
+            span: self.span,
+            description: self.description(),
+            severity: Some(DiagnosticSeverity::WARNING),
+            related: vec![],
+        }
+    }
+}
+
+/// Find the uses of deprecated names in a file
+pub fn deprecated_uses(toplevel: &TopLevel,
+                       version: Option<SimicsApiVersion>)
+                       -> Vec<DeprecatedUse> {
+    let mut uses = vec![];
+    for statement in toplevel_statements(toplevel) {
+        let names = match statement {
+            StatementSpecStatement::Instantiation(inst) =>
+                inst.obj.names.iter()
+                .map(|name|(DeprecatedKind::Template, name)).collect(),
+            StatementSpecStatement::Parameter(param) =>
+                vec![(DeprecatedKind::Parameter, &param.obj.object.name)],
+            _ => vec![],
+        };
+        for (kind, name) in names {
+            if let Some(deprecation) = deprecation(kind, &name.val)
+                .filter(|d|d.applies_to(version)) {
+                    uses.push(DeprecatedUse {
+                        deprecation,
+                        span: name.span,
+                    });
+                }
+        }
+    }
+    uses.sort_by_key(|use_|use_.span.range);
+    uses.dedup();
+    uses
+}
+
+pub fn deprecation_errors(toplevel: &TopLevel,
+                          version: Option<SimicsApiVersion>)
+                          -> Vec<DMLError> {
+    deprecated_uses(toplevel, version).iter()
+        .map(DeprecatedUse::to_error).collect()
+}
+
+/// Apply the safe rewrites of a set of deprecated uses to the text of the
+/// file they were found in, returning the new text and the number of
+/// rewrites applied
+// NOTE: Columns of spans count characters, and names never span lines
+pub fn apply_safe_rewrites(text: &str, uses: &[DeprecatedUse])
+                           -> (String, usize) {
+    let mut rewrites: Vec<(ZeroRange, &str)> = uses.iter()
+        .filter_map(|use_|use_.safe_rewrite().map(|r|(use_.span.range, r)))
+        .filter(|(range, _)|range.row_start == range.row_end)
+        .collect();
+    rewrites.sort_by_key(|(range, _)|*range);
+    let mut applied = 0;
+    let mut result = String::with_capacity(text.len());
+    let mut pending = rewrites.into_iter().peekable();
+    for (row, line) in text.split_inclusive('\n').enumerate() {
+        let mut col = 0;
+        let mut chars = line.chars();
+        while let Some((range, replacement)) = pending.next_if(
+            |(range, _)|range.row_start.0 as usize == row) {
+                let start = range.col_start.0 as usize;
+                let end = range.col_end.0 as usize;
+                // Overlapping rewrites cannot happen for names
+                if start < col {
+                    continue;
+                }
+                result.extend(chars.by_ref().take(start - col));
+                chars.by_ref().take(end - start).for_each(drop);
+                result.push_str(replacement);
+                col = end;
+                applied += 1;
+            }
+        result.extend(chars);
+    }
+    (result, applied)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::PathBuf;
+    use std::str::FromStr;
+    use crate::analysis::{parse_file, FileSpec};
+    use crate::vfs::TextFile;
+
+    const SOURCE: &str = "dml 1.4;
+device test;
+bank regs {
+    register r size 4 @ 0x0 is (unimplemented, read_only) {
+        param hard_reset_value = 0x10;
+        param soft_reset_value = 0x20;
+    }
+    param use_io_memory = false;
+}
+template t is silent_unimplemented {}
+";
+
+    fn toplevel(source: &str) -> TopLevel {
+        let path = PathBuf::from("test.dml");
+        let file = TextFile::from_str(source).unwrap();
+        let filespec = FileSpec { path: &path, file: &file };
+        let (ast, _, _) = parse_file(&path, filespec).unwrap();
+        TopLevel::from_ast(&ast, &mut vec![], filespec)
+    }
+
+    #[test]
+    fn test_deprecated_uses() {
+        let top = toplevel(SOURCE);
+        let names = |version| deprecated_uses(&top, version).iter()
+            .map(|u|u.deprecation.name).collect::<Vec<_>>();
+        assert_eq!(names(None), vec!["unimplemented", "hard_reset_value",
+                                     "soft_reset_value",
+                                     "silent_unimplemented"]);
+        assert_eq!(names(Some(SimicsApiVersion(7))),
+                   vec!["unimplemented", "hard_reset_value",
+                        "soft_reset_value", "use_io_memory",
+                        "silent_unimplemented"]);
+        assert_eq!(deprecation_errors(&top, None)[1].description,
+                   "The parameter name 'hard_reset_value' is deprecated in \
+                    DML 1.4; use 'init_val' instead");
+    }
+
+    #[test]
+    fn test_apply_safe_rewrites() {
+        let top = toplevel(SOURCE);
+        let uses = deprecated_uses(&top, Some(SimicsApiVersion(7)));
+        let (text, applied) = apply_safe_rewrites(SOURCE, &uses);
+        assert_eq!(applied, 3);
+        assert_eq!(text, "dml 1.4;
+device test;
+bank regs {
+    register r size 4 @ 0x0 is (unimpl, read_only) {
+        param init_val = 0x10;
+        param soft_reset_value = 0x20;
+    }
+    param use_io_memory = false;
+}
+template t is silent_unimpl {}
+");
+        // The rewritten file has nothing left to rewrite
+        let uses = deprecated_uses(&toplevel(&text), None);
+        assert_eq!(apply_safe_rewrites(&text, &uses), (text, 0));
+    }
+}
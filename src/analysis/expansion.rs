@@ -0,0 +1,230 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! The object tree of a device after templates and 'in each' declarations
+//! have been applied, with the declarations each object ended up with and
+//! where they come from
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::analysis::{DeviceAnalysis, LocationSpan};
+use crate::analysis::layout::{eval_param, instances};
+use crate::analysis::parsing::tree::{ZeroFilePosition, ZeroSpan};
+use crate::analysis::templating::objects::{DMLCompositeObject,
+                                           DMLNamedMember, DMLObject,
+                                           DMLShallowObjectVariant,
+                                           ObjectSpec, StructureContainer};
+
+/// A parameter, method or other member of an object, at the declaration
+/// that is in effect
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExpandedMember<L> {
+    pub name: String,
+    pub kind: String,
+    pub location: L,
+    /// The template the declaration in effect is in, if any
+    pub template: Option<String>,
+    /// The other declarations of the member, which are overridden
+    pub overrides: Vec<L>,
+    /// The value of a parameter, when it is an integer that can be
+    /// evaluated and the object is not in an array
+    pub value: Option<i128>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExpandedObject<L> {
+    pub name: String,
+    pub kind: String,
+    pub location: L,
+    /// The declarations of the object, including those from 'in each'
+    pub declarations: Vec<L>,
+    /// The index variables of the arrays the object declares
+    pub indices: Vec<String>,
+    /// The templates instantiated in the object, directly or through other
+    /// templates and 'in each' declarations
+    pub templates: Vec<String>,
+    /// The 'in each' declarations that apply to the object
+    pub in_eachs: Vec<L>,
+    /// The members of the object, by name
+    pub members: Vec<ExpandedMember<L>>,
+    /// The subobjects of the object, by name
+    pub objects: Vec<ExpandedObject<L>>,
+}
+
+impl <L> ExpandedMember<L> {
+    pub fn map_locations<T>(self, map: &dyn Fn(&L) -> T) -> ExpandedMember<T> {
+        ExpandedMember {
+            name: self.name,
+            kind: self.kind,
+            location: map(&self.location),
+            template: self.template,
+            overrides: self.overrides.iter().map(map).collect(),
+            value: self.value,
+        }
+    }
+}
+
+impl <L> ExpandedObject<L> {
+    /// Convert the locations of the tree, such as to those of a protocol
+    pub fn map_locations<T>(self, map: &dyn Fn(&L) -> T) -> ExpandedObject<T> {
+        ExpandedObject {
+            name: self.name,
+            kind: self.kind,
+            location: map(&self.location),
+            declarations: self.declarations.iter().map(map).collect(),
+            indices: self.indices,
+            templates: self.templates,
+            in_eachs: self.in_eachs.iter().map(map).collect(),
+            members: self.members.into_iter()
+                .map(|member|member.map_locations(map)).collect(),
+            objects: self.objects.into_iter()
+                .map(|object|object.map_locations(map)).collect(),
+        }
+    }
+}
+
+// Whether a declaration with the name at `loc` is directly in a spec
+fn declares(spec: &ObjectSpec, loc: &ZeroSpan) -> bool {
+    let variables = spec.saveds.iter().chain(&spec.sessions)
+        .flat_map(|decl|&decl.obj.vars);
+    spec.params.iter().any(|decl|decl.obj.loc_span() == loc)
+        || spec.methods.iter().any(|decl|decl.obj.loc_span() == loc)
+        || spec.constants.iter().any(|decl|decl.obj.loc_span() == loc)
+        || spec.hooks.iter().any(|decl|decl.obj.loc_span() == loc)
+        || variables.into_iter().any(|var|var.loc_span() == loc)
+}
+
+// The template of an object that a declaration with the name at `loc` is
+// in, if it is in the body of a template
+fn declaring_template(obj: &DMLCompositeObject, loc: &ZeroSpan)
+                      -> Option<String> {
+    let spec = obj.definitions.iter().find(|spec|declares(spec, loc))?;
+    obj.templates.values()
+        .find(|template|Arc::ptr_eq(&template.spec, spec))
+        .map(|template|template.name.clone())
+}
+
+fn sorted(spans: impl IntoIterator<Item = ZeroSpan>) -> Vec<ZeroSpan> {
+    let mut spans: Vec<ZeroSpan> = spans.into_iter().collect();
+    spans.sort();
+    spans.dedup();
+    spans
+}
+
+fn without(spans: impl IntoIterator<Item = ZeroSpan>, loc: &ZeroSpan)
+           -> Vec<ZeroSpan> {
+    sorted(spans.into_iter().filter(|span|span != loc))
+}
+
+fn expand_member(container: &StructureContainer, obj: &DMLCompositeObject,
+                 variant: &DMLShallowObjectVariant)
+                 -> ExpandedMember<ZeroSpan> {
+    let location = *variant.location();
+    let (overrides, value) = match variant {
+        DMLShallowObjectVariant::Parameter(param) => {
+            let value = instances(container, obj)
+                .filter(|instances|instances.len() == 1)
+                .and_then(|instances|eval_param(
+                    container, obj, &param.identity, &instances[0]));
+            (without(param.definitions.iter().chain(&param.declarations)
+                     .map(|(_, def)|*def.loc_span()), &location),
+             value)
+        },
+        DMLShallowObjectVariant::Method(method) =>
+            (without(method.get_default().into_iter()
+                     .flat_map(|default|default.get_all_defs().into_iter()
+                               .chain(default.get_all_decls())),
+                     &location),
+             None),
+        _ => (vec![], None),
+    };
+    ExpandedMember {
+        name: variant.identity().to_string(),
+        kind: variant.kind_name().to_string(),
+        location,
+        template: declaring_template(obj, &location),
+        overrides,
+        value,
+    }
+}
+
+fn expand(container: &StructureContainer, obj: &DMLCompositeObject)
+          -> ExpandedObject<ZeroSpan> {
+    let mut members = vec![];
+    let mut objects = vec![];
+    for component in obj.components.values() {
+        match component {
+            DMLObject::CompObject(key) => if let Some(sub) = container.get(
+                *key) {
+                objects.push(expand(container, sub));
+            },
+            DMLObject::ShallowObject(shallow) =>
+                members.push(expand_member(container, obj, &shallow.variant)),
+        }
+    }
+    members.sort_by(|member, other|member.name.cmp(&other.name));
+    objects.sort_by(|object, other|object.name.cmp(&other.name));
+    let mut templates: Vec<String> = obj.templates.keys().cloned().collect();
+    templates.sort();
+    ExpandedObject {
+        name: obj.identity().to_string(),
+        kind: obj.kind_name().to_string(),
+        location: obj.declloc,
+        declarations: sorted(obj.all_decls.iter().map(|spec|spec.loc)),
+        indices: obj.arraydimvars.iter()
+            .map(|dim|dim.indexvar.val.to_string()).collect(),
+        templates,
+        in_eachs: sorted(obj.used_ineach_locs.iter().cloned()),
+        members,
+        objects,
+    }
+}
+
+/// The expanded object tree of a device
+pub fn expand_device(device: &DeviceAnalysis)
+                     -> Option<ExpandedObject<ZeroSpan>> {
+    let DMLObject::CompObject(key) = &device.device_obj else {
+        return None;
+    };
+    device.objects.get(*key).map(|obj|expand(&device.objects, obj))
+}
+
+/// The expanded object tree of the innermost object of a device with a
+/// declaration containing `position`
+pub fn expand_object_at(device: &DeviceAnalysis, position: &ZeroFilePosition)
+                        -> Option<ExpandedObject<ZeroSpan>> {
+    device.objects.values()
+        .filter(|obj|obj.all_decls.iter()
+                .any(|spec|spec.span.contains_pos(position)))
+        .max_by_key(|obj|ancestors(&device.objects, obj))
+        .map(|obj|expand(&device.objects, obj))
+}
+
+fn ancestors(container: &StructureContainer, obj: &DMLCompositeObject)
+             -> usize {
+    let mut count = 0;
+    let mut parent = obj.parent;
+    while let Some(key) = parent {
+        count += 1;
+        parent = container.get(key).and_then(|obj|obj.parent);
+    }
+    count
+}
+
+/// The expanded object tree of the object of a device with the dotted
+/// name `name`, relative to the device and without indices
+pub fn expand_object_named(device: &DeviceAnalysis, name: &str)
+                           -> Option<ExpandedObject<ZeroSpan>> {
+    let DMLObject::CompObject(mut key) = device.device_obj.clone() else {
+        return None;
+    };
+    for part in name.split('.').filter(|part|!part.is_empty()) {
+        match device.objects.get(key)?.get_object(part)? {
+            DMLObject::CompObject(sub) => key = *sub,
+            DMLObject::ShallowObject(_) => return None,
+        }
+    }
+    device.objects.get(key).map(|obj|expand(&device.objects, obj))
+}
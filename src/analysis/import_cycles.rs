@@ -0,0 +1,155 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Detection of circular import chains between DML files
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt::Display;
+use std::path::Path;
+
+/// Files mapped to the files they directly import
+pub type ImportGraph<N> = BTreeMap<N, BTreeSet<N>>;
+
+struct Tarjan<'g, N> {
+    graph: &'g ImportGraph<N>,
+    index: BTreeMap<&'g N, usize>,
+    lowlink: BTreeMap<&'g N, usize>,
+    stack: Vec<&'g N>,
+    on_stack: BTreeSet<&'g N>,
+    components: Vec<BTreeSet<&'g N>>,
+}
+
+impl<'g, N: Ord> Tarjan<'g, N> {
+    fn visit(&mut self, node: &'g N) {
+        let index = self.index.len();
+        self.index.insert(node, index);
+        self.lowlink.insert(node, index);
+        self.stack.push(node);
+        self.on_stack.insert(node);
+        for next in self.graph.get(node).into_iter().flatten() {
+            if !self.index.contains_key(next) {
+                self.visit(next);
+                let low = self.lowlink[node].min(self.lowlink[next]);
+                self.lowlink.insert(node, low);
+            } else if self.on_stack.contains(next) {
+                let low = self.lowlink[node].min(self.index[next]);
+                self.lowlink.insert(node, low);
+            }
+        }
+        if self.lowlink[node] == self.index[node] {
+            let mut component = BTreeSet::new();
+            while let Some(member) = self.stack.pop() {
+                self.on_stack.remove(member);
+                component.insert(member);
+                if member == node {
+                    break;
+                }
+            }
+            self.components.push(component);
+        }
+    }
+}
+
+/// The shortest path from `start` back to itself within `component`
+fn shortest_cycle<'g, N: Ord>(graph: &'g ImportGraph<N>, start: &'g N,
+                              component: &BTreeSet<&'g N>) -> Vec<&'g N> {
+    let mut previous: BTreeMap<&N, &N> = BTreeMap::new();
+    let mut queue = VecDeque::from([start]);
+    while let Some(node) = queue.pop_front() {
+        for next in graph.get(node).into_iter().flatten() {
+            if next == start {
+                let mut cycle = vec![node];
+                while let Some(prev) = previous.get(cycle.last().unwrap()) {
+                    cycle.push(prev);
+                }
+                cycle.reverse();
+                return cycle;
+            }
+            if component.contains(next) && !previous.contains_key(next)
+                && next != start {
+                previous.insert(next, node);
+                queue.push_back(next);
+            }
+        }
+    }
+    unreachable!("Strongly connected component without a cycle")
+}
+
+/// Find the import cycles of a graph. Each set of files that import each
+/// other, directly or indirectly, is reported as one shortest cycle through
+/// the smallest file of the set, starting at that file. The file that closes
+/// the cycle by importing the first one again is not repeated.
+pub fn find_import_cycles<N: Ord + Clone>(graph: &ImportGraph<N>)
+                                          -> Vec<Vec<N>> {
+    let mut tarjan = Tarjan {
+        graph,
+        index: BTreeMap::new(),
+        lowlink: BTreeMap::new(),
+        stack: vec![],
+        on_stack: BTreeSet::new(),
+        components: vec![],
+    };
+    for node in graph.keys() {
+        if !tarjan.index.contains_key(node) {
+            tarjan.visit(node);
+        }
+    }
+    let mut cycles: Vec<Vec<N>> = tarjan.components.iter()
+        .filter_map(|component| {
+            let start = *component.iter().next()?;
+            let self_import = graph.get(start)
+                .is_some_and(|imports| imports.contains(start));
+            if component.len() == 1 && !self_import {
+                return None;
+            }
+            Some(shortest_cycle(graph, start, component).into_iter()
+                 .cloned().collect())
+        })
+        .collect();
+    cycles.sort();
+    cycles
+}
+
+/// Describe a cycle as 'a.dml -> b.dml -> a.dml'
+pub fn describe_cycle<N: AsRef<Path>>(cycle: &[N]) -> String {
+    fn name(path: &Path) -> impl Display + '_ {
+        path.file_name().unwrap_or(path.as_os_str()).to_string_lossy()
+    }
+    cycle.iter().chain(cycle.first())
+        .map(|file| name(file.as_ref()).to_string())
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn graph(edges: &[(&'static str, &'static str)])
+             -> ImportGraph<&'static str> {
+        let mut graph = ImportGraph::new();
+        for (from, to) in edges {
+            graph.entry(*from).or_default().insert(*to);
+            graph.entry(*to).or_default();
+        }
+        graph
+    }
+
+    #[test]
+    fn test_find_import_cycles() {
+        assert!(find_import_cycles(&graph(&[("a", "b"), ("b", "c"),
+                                            ("a", "c")])).is_empty());
+        assert_eq!(find_import_cycles(&graph(&[("d", "c"), ("c", "b"),
+                                               ("b", "d"), ("b", "a"),
+                                               ("e", "e")])),
+                   vec![vec!["b", "d", "c"], vec!["e"]]);
+        // Only the shortest cycle through the smallest file is reported
+        assert_eq!(find_import_cycles(&graph(&[("a", "b"), ("b", "c"),
+                                               ("c", "a"), ("b", "a")])),
+                   vec![vec!["a", "b"]]);
+    }
+
+    #[test]
+    fn test_describe_cycle() {
+        assert_eq!(describe_cycle(&["lib/a.dml", "b.dml"]),
+                   "a.dml -> b.dml -> a.dml");
+    }
+}
@@ -0,0 +1,281 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Interned names
+//!
+//! The analysis of a large project holds the same identifiers many times
+//! over, in declarations, references and symbols. Each distinct name is
+//! stored once, for as long as some name refers to it, so that names are
+//! cheap to copy and compare. The names are spread over shards with locks
+//! of their own, so that files parsed in parallel seldom wait for each
+//! other, and the names no longer in use are dropped from a shard as it
+//! grows, so that those typed and then edited away do not accumulate over
+//! a long session.
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// The number of shards the interned names are spread over
+const SHARDS: usize = 16;
+
+/// The fewest names in a shard before those no longer in use are dropped
+const MIN_SWEEP: usize = 1024;
+
+/// Some of the interned names
+struct Shard {
+    names: HashSet<Arc<str>>,
+    /// The number of names at which to drop those no longer in use
+    sweep_at: usize,
+}
+
+impl Default for Shard {
+    fn default() -> Shard {
+        Shard { names: HashSet::default(), sweep_at: MIN_SWEEP }
+    }
+}
+
+impl Shard {
+    fn intern(&mut self, name: &str) -> Arc<str> {
+        if let Some(interned) = self.names.get(name) {
+            return Arc::clone(interned);
+        }
+        if self.names.len() >= self.sweep_at {
+            self.sweep();
+        }
+        let interned: Arc<str> = name.into();
+        self.names.insert(Arc::clone(&interned));
+        interned
+    }
+
+    /// Drop the names only the shard refers to. No name can be interned
+    /// again meanwhile, as that takes the lock of the shard.
+    fn sweep(&mut self) {
+        self.names.retain(|name|Arc::strong_count(name) > 1);
+        self.sweep_at = (self.names.len() * 2).max(MIN_SWEEP);
+    }
+}
+
+lazy_static! {
+    static ref NAMES: [Mutex<Shard>; SHARDS] = Default::default();
+}
+
+/// The shard that `name` is interned in
+fn shard(name: &str) -> &'static Mutex<Shard> {
+    let mut hasher = DefaultHasher::new();
+    name.hash(&mut hasher);
+    &NAMES[hasher.finish() as usize % SHARDS]
+}
+
+/// An interned string. Equal names are the same string, so comparing them
+/// for equality is a pointer comparison.
+#[derive(Clone)]
+pub struct Name(Arc<str>);
+
+impl Name {
+    pub fn new(name: &str) -> Name {
+        Name(shard(name).lock().unwrap().intern(name))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// The number of distinct names in use, and the bytes they take up
+pub fn interned() -> (usize, usize) {
+    let (mut count, mut bytes) = (0, 0);
+    for shard in NAMES.iter() {
+        for name in shard.lock().unwrap().names.iter()
+            .filter(|name|Arc::strong_count(name) > 1) {
+                count += 1;
+                bytes += name.len();
+            }
+    }
+    (count, bytes)
+}
+
+impl Deref for Name {
+    type Target = str;
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for Name {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for Name {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq for Name {
+    fn eq(&self, other: &Name) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Name {}
+
+// Hashed and ordered like the string, so that names can be looked up by
+// strings in maps and sort the same way
+impl Hash for Name {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state)
+    }
+}
+
+impl PartialOrd for Name {
+    fn partial_cmp(&self, other: &Name) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Name {
+    fn cmp(&self, other: &Name) -> Ordering {
+        if self == other {
+            Ordering::Equal
+        } else {
+            self.0.cmp(&other.0)
+        }
+    }
+}
+
+impl PartialEq<str> for Name {
+    fn eq(&self, other: &str) -> bool {
+        &*self.0 == other
+    }
+}
+
+impl PartialEq<&str> for Name {
+    fn eq(&self, other: &&str) -> bool {
+        &*self.0 == *other
+    }
+}
+
+impl PartialEq<String> for Name {
+    fn eq(&self, other: &String) -> bool {
+        *self.0 == **other
+    }
+}
+
+impl PartialEq<Name> for str {
+    fn eq(&self, other: &Name) -> bool {
+        self == &*other.0
+    }
+}
+
+impl PartialEq<Name> for &str {
+    fn eq(&self, other: &Name) -> bool {
+        *self == &*other.0
+    }
+}
+
+impl PartialEq<Name> for String {
+    fn eq(&self, other: &Name) -> bool {
+        **self == *other.0
+    }
+}
+
+impl From<&str> for Name {
+    fn from(name: &str) -> Name {
+        Name::new(name)
+    }
+}
+
+impl From<String> for Name {
+    fn from(name: String) -> Name {
+        Name::new(&name)
+    }
+}
+
+impl From<&String> for Name {
+    fn from(name: &String) -> Name {
+        Name::new(name)
+    }
+}
+
+impl From<&Name> for Name {
+    fn from(name: &Name) -> Name {
+        name.clone()
+    }
+}
+
+impl From<Name> for String {
+    fn from(name: Name) -> String {
+        name.0.to_string()
+    }
+}
+
+impl fmt::Debug for Name {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl fmt::Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Serialize for Name {
+    fn serialize<S: Serializer>(&self, serializer: S)
+                                -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Name {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D)
+                                         -> Result<Name, D::Error> {
+        String::deserialize(deserializer).map(Name::from)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_names() {
+        let name = Name::new("intern_test_name");
+        assert_eq!(name, Name::from("intern_test_name".to_string()));
+        assert_eq!(name.as_str().as_ptr(),
+                   Name::new("intern_test_name").as_str().as_ptr());
+        let other = Name::new("intern_test_other");
+        assert_ne!(name, other);
+        assert!(name < other);
+        assert_eq!(name, "intern_test_name");
+        assert_eq!(format!("{} {:?}", name, name),
+                   "intern_test_name \"intern_test_name\"");
+        let map = HashMap::from([(name, 1)]);
+        assert_eq!(map.get("intern_test_name"), Some(&1));
+        let (count, bytes) = interned();
+        assert!(count >= 2 && bytes >= 33);
+    }
+
+    #[test]
+    fn test_unused_names_dropped() {
+        let mut shard = Shard::default();
+        let kept = Name(shard.intern("intern_test_kept"));
+        shard.intern("intern_test_dropped");
+        assert_eq!(shard.names.len(), 2);
+        shard.sweep();
+        assert_eq!(shard.names.len(), 1);
+        assert_eq!(kept, Name(shard.intern("intern_test_kept")));
+        assert_eq!(shard.sweep_at, MIN_SWEEP);
+    }
+}
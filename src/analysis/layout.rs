@@ -0,0 +1,367 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Evaluation of the register layout of a device: the offsets of registers
+//! and the bit ranges of fields, as set by constant expressions
+//!
+//! Only integer arithmetic on literals, array indices and parameters set to
+//! such expressions is evaluated. Anything else, such as method calls or
+//! parameters that depend on the state of the device, has no known value.
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+
+use lsp_types::DiagnosticSeverity;
+
+use crate::analysis::DMLError;
+
+use crate::analysis::structure::expressions::{BinOp, CompOp, ExpressionKind,
+                                              IntegerLiteral, LogicOp, MathOp,
+                                              UnaryOp};
+use crate::analysis::structure::objects::{CompObjectKind, ParamValue};
+use crate::analysis::templating::objects::{DMLCompositeObject,
+                                           StructureContainer, StructureKey};
+
+// How deep parameters referring to other parameters are followed, which
+// also bounds cyclic definitions
+const MAX_PARAM_DEPTH: usize = 32;
+/// The number of elements of an array beyond which its layout is not
+/// evaluated
+pub const MAX_INSTANCES: usize = 4096;
+
+/// The offset of registers that are not mapped in their bank
+pub const UNMAPPED_OFFSET: u64 = u64::MAX;
+
+/// The values of the array indices of an object and its parents, by the
+/// objects declaring them
+pub type Indices = HashMap<StructureKey, Vec<(String, i128)>>;
+
+/// Evaluate `expr`, looking up identifiers with `lookup`
+pub fn eval_expression(expr: &ExpressionKind,
+                       lookup: &dyn Fn(&str) -> Option<i128>)
+                       -> Option<i128> {
+    let eval = |expr: &ExpressionKind|eval_expression(expr, lookup);
+    match expr {
+        ExpressionKind::IntegerLiteral(IntegerLiteral::Signed(val)) =>
+            Some(val.val.into()),
+        ExpressionKind::IntegerLiteral(IntegerLiteral::Unsigned(val)) =>
+            Some(val.val.into()),
+        ExpressionKind::Identifier(ident) => lookup(&ident.name.val),
+        ExpressionKind::UnaryExpression(unary) => {
+            let operand = eval(&unary.operand)?;
+            match unary.operator {
+                UnaryOp::Minus => operand.checked_neg(),
+                UnaryOp::BinNot => Some(!operand),
+                UnaryOp::Not => Some((operand == 0).into()),
+                _ => None,
+            }
+        },
+        ExpressionKind::BinaryExpression(binary) => {
+            let left = eval(&binary.left)?;
+            // Short-circuit, as DML does
+            match binary.operator {
+                BinOp::Logic(LogicOp::And) if left == 0 => return Some(0),
+                BinOp::Logic(LogicOp::Or) if left != 0 => return Some(1),
+                _ => (),
+            }
+            let right = eval(&binary.right)?;
+            match &binary.operator {
+                BinOp::Math(op) => match op {
+                    MathOp::Plus => left.checked_add(right),
+                    MathOp::Minus => left.checked_sub(right),
+                    MathOp::Multiply => left.checked_mul(right),
+                    MathOp::Divide => left.checked_div(right),
+                    MathOp::Mod => left.checked_rem(right),
+                    MathOp::BinOr => Some(left | right),
+                    MathOp::BinAnd => Some(left & right),
+                    MathOp::BinXor => Some(left ^ right),
+                    MathOp::LShift => u32::try_from(right).ok()
+                        .filter(|shift|*shift < 64)
+                        .and_then(|shift|left.checked_shl(shift)),
+                    MathOp::RShift => u32::try_from(right).ok()
+                        .filter(|shift|*shift < 64)
+                        .and_then(|shift|left.checked_shr(shift)),
+                },
+                BinOp::Comp(op) => Some(match op {
+                    CompOp::Equals => left == right,
+                    CompOp::NotEquals => left != right,
+                    CompOp::GreaterThan => left > right,
+                    CompOp::LessThan => left < right,
+                    CompOp::GreaterEquals => left >= right,
+                    CompOp::LessEquals => left <= right,
+                }.into()),
+                BinOp::Logic(_) => Some((right != 0).into()),
+            }
+        },
+        ExpressionKind::TertiaryExpression(tertiary) =>
+            if eval(&tertiary.left)? != 0 {
+                eval(&tertiary.middle)
+            } else {
+                eval(&tertiary.right)
+            },
+        _ => None,
+    }
+}
+
+// The value of an identifier in the scope of an object, either an array
+// index of it or of a parent, or a parameter set in it or a parent
+fn eval_identifier(container: &StructureContainer, key: StructureKey,
+                   name: &str, indices: &Indices, depth: usize)
+                   -> Option<i128> {
+    let mut current = Some(key);
+    while let Some(key) = current {
+        let obj = container.get(key)?;
+        if let Some((_, value)) = indices.get(&key).into_iter().flatten()
+            .find(|(var, _)|var == name) {
+                return Some(*value);
+            }
+        if let Some(param) = obj.get_param(name) {
+            return match &param.get_likely_definition().value {
+                Some(ParamValue::Set(expr)) if depth < MAX_PARAM_DEPTH =>
+                    eval_in_object(container, key, expr, indices, depth + 1),
+                _ => None,
+            };
+        }
+        current = obj.parent;
+    }
+    None
+}
+
+fn eval_in_object(container: &StructureContainer, key: StructureKey,
+                  expr: &ExpressionKind, indices: &Indices, depth: usize)
+                  -> Option<i128> {
+    eval_expression(expr, &|name|eval_identifier(
+        container, key, name, indices, depth))
+}
+
+/// Evaluate the parameter `name` of an object, with array indices `indices`
+pub fn eval_param(container: &StructureContainer, obj: &DMLCompositeObject,
+                  name: &str, indices: &Indices) -> Option<i128> {
+    eval_identifier(container, obj.key, name, indices, 0)
+}
+
+/// The array indices of every element of an object, in order, or None if
+/// the size of an array it is in could not be evaluated or is larger than
+/// `MAX_INSTANCES`
+pub fn instances(container: &StructureContainer, obj: &DMLCompositeObject)
+                 -> Option<Vec<Indices>> {
+    let mut chain = vec![obj];
+    while let Some(parent) = chain.last().unwrap().parent {
+        chain.push(container.get(parent)?);
+    }
+    let mut instances = vec![Indices::new()];
+    for obj in chain.into_iter().rev() {
+        for dim in &obj.arraydimvars {
+            let mut next = vec![];
+            for indices in &instances {
+                // Array sizes are evaluated in the scope of the parent
+                let size = match (&dim.size, obj.parent) {
+                    (Some(size), Some(parent)) =>
+                        eval_in_object(container, parent, size, indices, 0)?,
+                    _ => return None,
+                };
+                let size = usize::try_from(size).ok()?;
+                if next.len() + size > MAX_INSTANCES {
+                    return None;
+                }
+                for index in 0..size {
+                    let mut indices = indices.clone();
+                    indices.entry(obj.key).or_default()
+                        .push((dim.indexvar.val.to_string(), index as i128));
+                    next.push(indices);
+                }
+            }
+            instances = next;
+        }
+    }
+    Some(instances)
+}
+
+/// The name of an element of an object, relative to the object `outer` or
+/// to the device if None, with the indices of it and the objects between
+pub fn element_name(container: &StructureContainer, obj: &DMLCompositeObject,
+                    indices: &Indices, outer: Option<StructureKey>) -> String {
+    let mut parts = vec![];
+    let mut current = Some(obj);
+    while let Some(obj) = current.filter(
+        |obj|obj.kind != CompObjectKind::Device && Some(obj.key) != outer) {
+        let mut part = obj.identity.val.to_string();
+        for (_, index) in indices.get(&obj.key).into_iter().flatten() {
+            part.push_str(&format!("[{}]", index));
+        }
+        parts.push(part);
+        current = obj.parent.and_then(|parent|container.get(parent));
+    }
+    parts.reverse();
+    parts.join(".")
+}
+
+/// Whether an element of an object is in the element of a parent with the
+/// indices `outer`
+pub fn is_within(indices: &Indices, outer: &Indices) -> bool {
+    outer.iter().all(|(key, vars)|indices.get(key) == Some(vars))
+}
+
+/// The bank an object is in
+pub fn containing_bank<'c>(container: &'c StructureContainer,
+                           obj: &DMLCompositeObject)
+                           -> Option<&'c DMLCompositeObject> {
+    let mut parent = container.get(obj.parent?)?;
+    while parent.kind != CompObjectKind::Bank {
+        parent = container.get(parent.parent?)?;
+    }
+    Some(parent)
+}
+
+/// The offset of each element of a register, relative to its bank
+pub fn register_offsets(container: &StructureContainer,
+                        register: &DMLCompositeObject)
+                        -> Option<Vec<u64>> {
+    instances(container, register)?.iter()
+        .map(|indices|eval_param(container, register, "offset", indices)
+             .and_then(|offset|u64::try_from(offset).ok()))
+        .collect()
+}
+
+/// The most and least significant bits of each element of a field
+pub fn field_bits(container: &StructureContainer, field: &DMLCompositeObject)
+                  -> Option<Vec<(u64, u64)>> {
+    instances(container, field)?.iter()
+        .map(|indices| {
+            let bit = |name|eval_param(container, field, name, indices)
+                .and_then(|bit|u64::try_from(bit).ok());
+            Some((bit("msb")?, bit("lsb")?))
+        })
+        .collect()
+}
+
+// The bytes an element of a register is mapped at, from its first to its
+// last byte
+fn register_bytes(container: &StructureContainer,
+                  register: &DMLCompositeObject, indices: &Indices)
+                  -> Option<(u64, u64)> {
+    let offset = eval_param(container, register, "offset", indices)
+        .and_then(|offset|u64::try_from(offset).ok())
+        .filter(|offset|*offset != UNMAPPED_OFFSET)?;
+    let size = eval_param(container, register, "size", indices)
+        .and_then(|size|u64::try_from(size).ok())
+        .filter(|size|*size > 0)?;
+    Some((offset, offset.checked_add(size - 1)?))
+}
+
+/// Warnings about registers mapped at overlapping bytes of the same bank
+/// element, one for each pair of register declarations. Registers whose
+/// offsets or sizes cannot be evaluated are not checked.
+pub fn overlapping_registers(container: &StructureContainer)
+                             -> Vec<DMLError> {
+    let mut errors = vec![];
+    let mut reported = HashSet::new();
+    for bank in container.values()
+        .filter(|obj|obj.kind == CompObjectKind::Bank) {
+            let registers: Vec<&DMLCompositeObject> = container.values()
+                .filter(|obj|obj.kind == CompObjectKind::Register
+                        && containing_bank(container, obj)
+                        .is_some_and(|parent|parent.key == bank.key))
+                .collect();
+            for bank_indices in instances(container, bank).into_iter()
+                .flatten() {
+                    let mut mapped = vec![];
+                    for register in &registers {
+                        for indices in instances(container, register)
+                            .into_iter().flatten()
+                            .filter(|indices|is_within(indices,
+                                                       &bank_indices)) {
+                                if let Some(bytes) = register_bytes(
+                                    container, register, &indices) {
+                                    mapped.push((bytes, *register, indices));
+                                }
+                            }
+                    }
+                    // Registers at the same bytes are reported as
+                    // overlapping the one declared first
+                    mapped.sort_by_key(|((first, last), register, _)|
+                                       (*first, *last, register.declloc));
+                    // The register reaching furthest so far, which any
+                    // register starting before its end overlaps
+                    let mut furthest: Option<usize> = None;
+                    for (index, ((first, last), register, indices))
+                        in mapped.iter().enumerate() {
+                            if let Some(furthest) = furthest
+                                .map(|furthest|&mapped[furthest])
+                                .filter(|((_, end), _, _)|end >= first) {
+                                    let ((_, end), other, other_indices) =
+                                        furthest;
+                                    if reported.insert((other.key,
+                                                        register.key)) {
+                                        errors.push(overlap_error(
+                                            container, bank,
+                                            (register, indices),
+                                            (other, other_indices),
+                                            (*first, (*last).min(*end))));
+                                    }
+                                }
+                            if furthest.is_none_or(
+                                |furthest|mapped[furthest].0.1 < *last) {
+                                furthest = Some(index);
+                            }
+                        }
+                }
+        }
+    errors
+}
+
+fn overlap_error(container: &StructureContainer, bank: &DMLCompositeObject,
+                 (register, indices): (&DMLCompositeObject, &Indices),
+                 (other, other_indices): (&DMLCompositeObject, &Indices),
+                 (first, last): (u64, u64)) -> DMLError {
+    DMLError {
+        span: register.declloc,
+        description: format!(
+            "Register '{}' overlaps register '{}' in bank '{}' at {:#x}–{:#x}",
+            element_name(container, register, indices, Some(bank.key)),
+            element_name(container, other, other_indices, Some(bank.key)),
+            element_name(container, bank, indices, None), first, last),
+        related: vec![(other.declloc,
+                       "Overlapping register declared here".to_string())],
+        severity: Some(DiagnosticSeverity::WARNING),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::PathBuf;
+    use std::str::FromStr;
+    use crate::analysis::{parse_file, FileSpec};
+    use crate::analysis::structure::toplevel::TopLevel;
+    use crate::vfs::TextFile;
+
+    fn eval_params(source: &str) -> Vec<Option<i128>> {
+        let path = PathBuf::from("test.dml");
+        let file = TextFile::from_str(source).unwrap();
+        let filespec = FileSpec { path: &path, file: &file };
+        let (ast, _, _) = parse_file(&path, filespec).unwrap();
+        let top = TopLevel::from_ast(&ast, &mut vec![], filespec);
+        let lookup = |name: &str| match name {
+            "i" => Some(3),
+            "base" => Some(0x100),
+            _ => None,
+        };
+        top.spec.params.iter().map(|param| match &param.obj.value {
+            Some(ParamValue::Set(expr)) => eval_expression(expr, &lookup),
+            _ => None,
+        }).collect()
+    }
+
+    #[test]
+    fn test_eval_expression() {
+        assert_eq!(eval_params("dml 1.4;
+device test;
+param a = base + i * 4;
+param b = (1 << 4) - 1;
+param c = i > 2 ? 0x10 : 0x20;
+param d = -(i % 2) | 8;
+param e = unknown + 1;
+param f = 1 / 0;
+param g = i == 3 && base != 0;
+"), vec![Some(0x10c), Some(15), Some(0x10), Some(-1), None, None, Some(1)]);
+    }
+}
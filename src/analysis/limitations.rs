@@ -0,0 +1,47 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Known limitations of the analysis, reported to the user when they
+//! affect a result
+
+use std::fmt;
+
+// The issue number is used as the _unique identifier_ for this
+// limitation, and should be the number of an open GITHUB
+// issue.
+// Example:
+// DLSLimitation {
+//         issue_num: 42,
+//         description: "Example of a DLS limitation".to_string(),
+//     };
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash,)]
+pub struct DLSLimitation {
+    pub issue_num: u64,
+    pub description: String,
+}
+
+impl fmt::Display for DLSLimitation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (issue#{})", self.description, self.issue_num)
+    }
+}
+
+pub fn type_semantic_limitation() -> DLSLimitation {
+    DLSLimitation {
+        issue_num: 65,
+        description: "The DLS does not currently support semantic analysis of \
+                      types, including reference finding".to_string(),
+    }
+}
+
+pub fn isolated_template_limitation(template_name: &str) -> DLSLimitation {
+    DLSLimitation {
+        issue_num: 31,
+        description:
+        format!("References from, and definitions inside, a template \
+                 cannot be evaluated \
+                 without an instantiating object. Open a device file \
+                 that uses the template '{}' to obtain such information.",
+                 template_name),
+    }
+}
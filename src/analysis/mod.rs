@@ -8,8 +8,19 @@ pub mod symbols;
 pub mod provisionals;
 pub mod scope;
 pub mod reference;
+pub mod refactorings;
+pub mod deprecations;
+pub mod expansion;
+pub mod quick_fixes;
+pub mod signatures;
+pub mod import_cycles;
+pub mod intern;
+pub mod layout;
+pub mod limitations;
+pub mod stdlib_versions;
 pub mod structure;
 pub mod templating;
+pub mod unused;
 
 use std::collections::{HashMap, HashSet};
 use std::fmt;
@@ -18,23 +29,24 @@ use std::hash::{Hash, Hasher};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::{Instant, SystemTime};
 
 use lsp_types::{DiagnosticSeverity};
-use logos::Logos;
 use log::{debug, error, info, trace};
 use rayon::prelude::*;
 
-use crate::actions::SourcedDMLError;
-use crate::actions::analysis_storage::TimestampedStorage;
-use crate::actions::semantic_lookup::{DLSLimitation, isolated_template_limitation};
+use crate::analysis::intern::Name;
+use crate::analysis::limitations::{DLSLimitation, isolated_template_limitation};
 use crate::analysis::symbols::{DMLSymbolKind, SimpleSymbol, StructureSymbol, SymbolContainer, SymbolMaker, SymbolSource};
 pub use crate::analysis::symbols::SymbolRef;
 use crate::analysis::reference::{GlobalReference, NodeRef, Reference, ReferenceKind, ReferenceVariant, VariableReference};
 use crate::analysis::scope::{Scope, SymbolContext,
                              ContextKey, ContextedSymbol};
 use crate::analysis::parsing::parser::{FileInfo, FileParser};
-use crate::analysis::parsing::lexer::TokenKind;
+use crate::analysis::parsing::incremental::{parse_toplevel_incrementally,
+                                            PreviousParse, SkippedToken};
 use crate::analysis::provisionals::ProvisionalsManager;
+use crate::analysis::unused::{unused_declarations, UnusedDeclaration};
 
 pub use crate::analysis::parsing::tree::
 {ZeroRange, ZeroSpan, ZeroPosition, ZeroFilePosition};
@@ -64,6 +76,7 @@ use crate::analysis::templating::types::DMLResolvedType;
 
 use crate::concurrency::AliveStatus;
 use crate::file_management::{PathResolver, CanonPath};
+use crate::stats::{self, Phase};
 
 use crate::vfs::{TextFile, Error};
 
@@ -95,7 +108,7 @@ pub trait Named {
 
 impl <T: DMLNamed> Named for T {
     fn get_name(&self) -> String {
-        self.name().val.clone()
+        self.name().val.to_string()
     }
 }
 
@@ -193,6 +206,25 @@ impl DMLError {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourcedDMLError {
+    pub error: DMLError,
+    pub source: &'static str,
+}
+
+#[derive(Debug, Clone)]
+pub struct TimestampedStorage<T> {
+    pub timestamp: SystemTime,
+    pub stored: T,
+}
+
+/// A hash of file contents that is stable between server builds (64-bit
+/// FNV-1a), so that it can be compared with hashes stored on disk
+pub fn content_hash(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte|
+                      (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
 #[derive(Debug, Clone)]
 pub struct LocalDMLError {
     pub range: ZeroRange,
@@ -200,7 +232,7 @@ pub struct LocalDMLError {
 }
 
 impl LocalDMLError {
-    fn with_file<F: Into<PathBuf>>(self, file: F) -> DMLError {
+    pub fn with_file<F: Into<PathBuf>>(self, file: F) -> DMLError {
         DMLError {
             span: ZeroSpan::from_range(self.range, file),
             description: self.description,
@@ -243,11 +275,44 @@ pub fn make_error_from_missing_content(
     }
 }
 
+/// What analyses keep and compute beyond what is needed for diagnostics,
+/// trading speed for memory when limited
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnalysisOptions {
+    // Keep the syntax trees of files, which are otherwise re-parsed for
+    // linting
+    pub keep_ast: bool,
+    // Resolve references in method bodies during device analysis
+    pub method_references: bool,
+    // Bound on the number of reference lookups cached per device
+    pub reference_cache_limit: Option<usize>,
+}
+
+impl Default for AnalysisOptions {
+    fn default() -> Self {
+        AnalysisOptions {
+            keep_ast: true,
+            method_references: true,
+            reference_cache_limit: None,
+        }
+    }
+}
+
+impl AnalysisOptions {
+    pub fn low_memory() -> Self {
+        AnalysisOptions {
+            keep_ast: false,
+            method_references: false,
+            reference_cache_limit: Some(4096),
+        }
+    }
+}
+
 // Analysis from the perspective of a particular DML file
 #[derive(Debug, Clone)]
 pub struct IsolatedAnalysis {
-    // Toplevel ast of file
-    pub ast: parsing::structure::TopAst,
+    // Toplevel ast of file, not kept in low-memory mode
+    pub ast: Option<parsing::structure::TopAst>,
 
     // Toplevel structure of file
     pub toplevel: TopLevel,
@@ -266,13 +331,20 @@ pub struct IsolatedAnalysis {
 
     // Errors are used as input for various responses/requests to the client
     pub errors: Vec<DMLError>,
+
+    // Hash of the analyzed contents, see 'content_hash'
+    pub content_hash: u64,
+
+    // The text and skipped tokens of the parse, kept along with the ast
+    // so that the file can be re-parsed incrementally after an edit
+    pub reparse: Option<(String, Vec<SkippedToken>)>,
 }
 
 // Invariant: range covers all ranges in sub_ranges
 #[derive(Debug, Clone)]
 pub struct RangeEntry {
     range: ZeroRange,
-    symbols: HashMap<String, SymbolRef>,
+    symbols: HashMap<Name, SymbolRef>,
     // TODO: Consider replacing with a spatial-search data-structure
     // e.g. segment or interval tree
     sub_ranges: Vec<RangeEntry>,
@@ -331,7 +403,7 @@ pub struct SymbolStorage {
     pub template_symbols: HashMap<ZeroSpan, SymbolRef>,
     // Because some implicit parameters are defined in the same
     // place, we need to disambiguate this by name
-    pub param_symbols: HashMap<(ZeroSpan, String),
+    pub param_symbols: HashMap<(ZeroSpan, Name),
                                HashMap<StructureKey, SymbolRef>>,
     pub object_symbols: HashMap<StructureKey, SymbolRef>,
     // This is doubly-indexed, by decl location and then
@@ -366,11 +438,15 @@ pub struct DeviceAnalysis {
     pub templates: TemplateTraitInfo,
     pub symbol_info: SymbolStorage,
     pub reference_info: ReferenceStorage,
+    reference_cache_limit: Option<usize>,
     pub template_object_implementation_map: HashMap<ZeroSpan,
                                                     Vec<StructureKey>>,
     pub path: CanonPath,
     pub dependant_files: Vec<CanonPath>,
     pub clientpath: PathBuf,
+    // Declarations nothing in the device uses, unless references in method
+    // bodies were not resolved
+    pub unused: Vec<UnusedDeclaration>,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -476,7 +552,7 @@ impl ReferenceMatches {
 pub type TypeHint = DMLResolvedType;
 
 // Agnostic reference
-type AgnRef = Vec<String>;
+type AgnRef = Vec<Name>;
 
 type ReferenceCacheKey = (String, AgnRef, Option<ZeroRange>);
 #[derive(Default)]
@@ -501,7 +577,7 @@ fn object_to_path_name_aux(object: Option<&DMLObject>, container: &StructureCont
 }
 
 impl ReferenceCache {
-    fn flatten_ref(refr: &NodeRef, agn: &mut Vec<String>) {
+    fn flatten_ref(refr: &NodeRef, agn: &mut AgnRef) {
         match refr {
             NodeRef::Simple(dmlstring) => agn.push(dmlstring.val.clone()),
             NodeRef::Sub(sub, dmlstring, _) => {
@@ -842,7 +918,7 @@ impl DeviceAnalysis {
                         self.symbol_info.variable_symbols.get(h.loc_span()),
                     DMLShallowObjectVariant::Parameter(p) =>
                         self.symbol_info.param_symbols.get(
-                            &(*p.loc_span(), p.name().val.to_string()))
+                            &(*p.loc_span(), p.name().val.clone()))
                         .and_then(|m|m.get(&parent.key))
                 },
         }
@@ -1532,14 +1608,30 @@ pub fn parse_file(path: &Path, file: FileSpec<'_>)
                   -> Result<(parsing::structure::TopAst,
                              ProvisionalsManager,
                              Vec<DMLError>), Error>
+{
+    parse_file_incrementally(path, file, None)
+        .map(|(ast, provisionals, errors, _)|(ast, provisionals, errors))
+}
+
+// The skipped tokens are only given if the parse can be re-used
+type IncrementalParse = (parsing::structure::TopAst,
+                         ProvisionalsManager,
+                         Vec<DMLError>,
+                         Option<Vec<SkippedToken>>);
+
+/// Parse a file, reusing what an edit did not affect of a previous parse
+/// of it. Also gives the skipped tokens, if the positions of the parse are
+/// exact enough for it to be re-used in turn.
+pub fn parse_file_incrementally(path: &Path, file: FileSpec<'_>,
+                                previous: Option<&PreviousParse>)
+                                -> Result<IncrementalParse, Error>
 {
     let content = &file.file.text;
-    let lexer = TokenKind::lexer(content);
-    let mut parser = FileParser::new(lexer);
     let mut parse_state = FileInfo::default();
-    let ast = parsing::structure::parse_toplevel(
-        &mut parser, &mut parse_state, file);
-    let mut skipped_errors = parser.report_skips();
+    let (ast, skipped, exact) = stats::timed(Phase::Parse, ||
+        parse_toplevel_incrementally(content, previous, &mut parse_state,
+                                     file));
+    let mut skipped_errors = FileParser::report_skipped(&skipped);
     let mut missing_errors = ast.report_missing();
     missing_errors.append(&mut skipped_errors);
     parsing::structure::post_parse_toplevel(
@@ -1548,7 +1640,7 @@ pub fn parse_file(path: &Path, file: FileSpec<'_>)
     // NOTE: I dont know how to do rust iterators
     let errors = missing_errors.into_iter()
         .map(|e|e.with_file(path)).collect();
-    Ok((ast, parse_state.provisionals, errors))
+    Ok((ast, parse_state.provisionals, errors, exact.then_some(skipped)))
 }
 
 fn collect_toplevel(path: &Path, tree: &parsing::structure::TopAst,
@@ -1592,12 +1684,25 @@ impl IsolatedAnalysis {
                file: TextFile,
                status: AliveStatus)
                -> Result<IsolatedAnalysis, Error> {
+        Self::new_incrementally(path, clientpath, file, None, status)
+    }
+
+    /// Analyze a file, re-using what an edit did not affect of the
+    /// previous parse of it
+    pub fn new_incrementally(path: &CanonPath,
+                             clientpath: &PathBuf,
+                             file: TextFile,
+                             previous: Option<&PreviousParse>,
+                             status: AliveStatus)
+                             -> Result<IsolatedAnalysis, Error> {
         trace!("local analysis: {} at {}", path.as_str(), path.as_str());
         status.assert_alive();
         let filespec = FileSpec {
             path, file: &file
         };
-        let (mut ast, provisionals, mut errors) = parse_file(path, filespec)?;
+        let content_hash = content_hash(&file.text);
+        let (mut ast, provisionals, mut errors, mut skipped) =
+            parse_file_incrementally(path, filespec, previous)?;
         status.assert_alive();
         // Add invalid provisionals to errors
         for duped_provisional in &provisionals.duped_provisionals {
@@ -1648,6 +1753,7 @@ impl IsolatedAnalysis {
                 bitorder: None,
                 declarations: vec![],
             };
+            skipped = None;
             info!("Bailed on further analysis of {} due to it not being a \
                    DML 1.4 file", clientpath.display());
         }
@@ -1667,12 +1773,14 @@ impl IsolatedAnalysis {
         }
         let top_context = toplevel.to_context();
         let res = IsolatedAnalysis {
-            ast,
+            ast: Some(ast),
             toplevel,
             top_context,
             path: path.clone(),
             clientpath: clientpath.clone(),
             errors,
+            content_hash,
+            reparse: skipped.map(|skipped|(file.text.clone(), skipped)),
         };
         status.assert_alive();
         info!("Produced an isolated analysis of {:?}", res.path);
@@ -1680,6 +1788,13 @@ impl IsolatedAnalysis {
         Ok(res)
     }
 
+    /// What the file can be re-parsed from after an edit, if the syntax
+    /// tree was kept
+    pub fn into_previous_parse(self) -> Option<PreviousParse> {
+        let (text, skipped) = self.reparse?;
+        Some(PreviousParse { text, ast: self.ast?, skipped })
+    }
+
     pub fn get_imports(&self) -> &Vec<ObjectDecl<Import>> {
         &self.toplevel.spec.imports
     }
@@ -1707,7 +1822,7 @@ impl IsolatedAnalysis {
                          ObjectDecl::always(&Import {
                              span: ZeroSpan::invalid(self.path.clone()),
                              name: DMLString {
-                                 val: format!("\"{}\"", import),
+                                 val: Name::from(format!("\"{}\"", import)),
                                  span: ZeroSpan::invalid(self.path.clone()),
                              }
                          }))));
@@ -1942,7 +2057,7 @@ fn add_new_symbol_from_shallow(maker: &SymbolMaker,
         DMLShallowObjectVariant::Parameter(_) => {
             log_non_same_insert(storage.param_symbols.entry(
                 (*shallow.location(),
-                 shallow.identity().to_string()))
+                 Name::from(shallow.identity())))
                                 .or_default(),
                                 shallow.parent,
                                 new_sym);
@@ -2235,11 +2350,17 @@ impl DeviceAnalysis {
     fn match_references(&mut self,
                         bases: &Vec<IsolatedAnalysis>,
                         method_structure: &HashMap<ZeroSpan, RangeEntry>,
+                        method_references: bool,
                         errors: &mut Vec<DMLError>,
                         status: &AliveStatus) {
         info!("Match references");
         let reference_cache: Mutex<ReferenceCache> = Mutex::default();
         for scope_chain in all_scopes(bases) {
+            if !method_references && scope_chain.last().is_some_and(
+                |scope|matches!(scope.create_context(),
+                                ContextKey::Method(_))) {
+                    continue;
+                }
             debug!("Got scope at {:?}", scope_chain.last()
                    .map(|s|s.span().start_position()));
             self.match_references_in_scope(scope_chain,
@@ -2281,10 +2402,12 @@ impl DeviceAnalysis {
     pub fn new(root: IsolatedAnalysis,
                timed_bases: Vec<TimestampedStorage<IsolatedAnalysis>>,
                imp_map: HashMap<Import, String>,
+               options: AnalysisOptions,
                status: AliveStatus)
                -> Result<DeviceAnalysis, Error> {
         info!("device analysis: {:?}", root.path);
         status.assert_alive();
+        let start = Instant::now();
 
         if root.toplevel.device.is_none() {
             return Err(Error::InternalError(
@@ -2303,7 +2426,7 @@ impl DeviceAnalysis {
                     base.toplevel.spec.imports.push(ObjectDecl::always(&Import {
                         span: ZeroSpan::invalid(root.path.clone()),
                         name: DMLString {
-                            val: format!("\"{}\"", import),
+                            val: Name::from(format!("\"{}\"", import)),
                             span: ZeroSpan::invalid(root.path.clone()),
                         }
                     }));
@@ -2371,8 +2494,9 @@ impl DeviceAnalysis {
         let mut container = StructureContainer::default();
         info!("Make device");
         let device_key = make_device(root.path.as_str(), &root.toplevel,
-                                     &tt_info, imp_map, &mut container,
-                                     &mut rank_maker, &mut errors).key;
+                                     &tt_info, imp_map.clone(),
+                                     &mut container, &mut rank_maker,
+                                     &mut errors).key;
         status.assert_alive();
         // maps template declaration loc to objects
         let template_object_implementation_map =
@@ -2400,21 +2524,24 @@ impl DeviceAnalysis {
         extend_with_templates(&maker, &mut symbol_info, &tt_info);
         //extend_with_types(&mut symbols, ??)
         let mut device = DeviceAnalysis {
-            name: root.toplevel.device.unwrap().name.val,
+            name: root.toplevel.device.unwrap().name.val.to_string(),
             errors: HashMap::default(),
             objects: container,
             device_obj: DMLObject::CompObject(device_key),
             templates: tt_info,
             symbol_info,
             reference_info: ReferenceStorage::default(),
+            reference_cache_limit: options.reference_cache_limit,
             template_object_implementation_map,
             path: root.path.clone(),
             clientpath: root.path.clone().into(),
             dependant_files: bases.iter().map(|b|&b.path).cloned().collect(),
+            unused: vec![],
         };
         status.assert_alive();
         device.match_references(&bases,
                                 &method_structure,
+                                options.method_references,
                                 &mut errors,
                                 &status);
 
@@ -2426,6 +2553,10 @@ impl DeviceAnalysis {
         for obj in device.objects.values() {
             device.param_invariants(obj, &mut errors);
         }
+        errors.extend(layout::overlapping_registers(&device.objects));
+        if options.method_references {
+            device.unused = unused_declarations(&device, &bases, &imp_map);
+        }
 
         for error in errors {
             device.errors.entry(error.span.path())
@@ -2434,6 +2565,7 @@ impl DeviceAnalysis {
         }
         trace!("Errors are {:?}", device.errors);
         status.assert_alive();
+        stats::record_phase(Phase::Resolve, start.elapsed());
         info!("Done with device");
         Ok(device)
     }
@@ -2470,6 +2602,98 @@ impl DeviceAnalysis {
         }
     }
 
+    /// The non-abstract methods implementing a method symbol, directly or
+    /// through other implementations, including the method itself if it is
+    /// not abstract. Other symbols have no implementations of this kind.
+    pub fn symbol_implementations(&self, symbol: &SymbolRef)
+                                  -> Vec<SymbolRef> {
+        let symbol_lock = symbol.lock().unwrap();
+
+        // Special case for methods, recursively follow the implementations to find
+        // all methods
+        if symbol_lock.kind == DMLSymbolKind::Method {
+            // Re-dropping the lock here is sensible, as we want to iterate over non-locked symbols
+            drop(symbol_lock);
+            #[allow(clippy::mutable_key_type)]
+            let mut syms: HashSet::<&SymbolRef> = HashSet::default();
+            let mut next_iteration = vec![symbol];
+            while let Some(next) = next_iteration.pop() {
+                let next_lock = next.lock().unwrap();
+                if !syms.contains(next) {
+                    let parent = if let Some(meth_source) = next_lock.source.as_method() {
+                        if !meth_source.1.is_abstract() {
+                            syms.insert(next);
+                        }
+                        meth_source.0
+                    } else {
+                        internal_error!("Expected method symbol source of symbol iterated over by\
+                                         implementations_of_symbol, symbol is {:?}", next_lock);
+                        continue;
+                    };
+
+                    for impl_ref in &next_lock.implementations {
+                        // Note: We only need to find the implementations of the variant of this method
+                        // under parent. Other variants are handled by finding multiple symbols at
+                        // the ref location
+                        if let Some(impl_sym) = self.symbol_info.method_symbols.get(impl_ref)
+                            .and_then(|m|m.get(parent)) {
+                            next_iteration.push(impl_sym);
+                        } else {
+                            internal_error!("Expected method implementation symbol to exist in \
+                                             analysis, symbol is {:?}, implementation ref is {:?}",
+                            next_lock, impl_ref);
+                        }
+                    }
+                }
+            }
+            syms.into_iter().map(Arc::clone).collect()
+        } else {
+            vec![]
+        }
+    }
+
+    // The methods overriding, or overridden by, a method symbol, including itself
+    fn method_family(&self, symbol: &SymbolRef) -> Vec<SymbolRef> {
+        let bases = symbol.lock().unwrap().bases.clone();
+        let mut family = vec![Arc::clone(symbol)];
+        for base in bases {
+            for base_symbol in self.symbol_info.method_symbols.get(&base)
+                .into_iter().flat_map(|by_parent|by_parent.values()) {
+                    family.push(Arc::clone(base_symbol));
+                    family.extend(self.symbol_implementations(base_symbol));
+                }
+        }
+        family
+    }
+
+    /// The locations of all names of the renameable symbols among `symbols`,
+    /// in their declarations, definitions and references, along with those
+    /// of any methods overriding or overridden by them
+    pub fn rename_locations(&self, symbols: &[SymbolRef]) -> HashSet<ZeroSpan> {
+        let mut renamed = vec![];
+        for symbol in symbols {
+            let kind = symbol.lock().unwrap().kind;
+            if !kind.is_renameable() {
+                continue;
+            }
+            if kind == DMLSymbolKind::Method {
+                renamed.extend(self.method_family(symbol));
+            } else {
+                renamed.push(Arc::clone(symbol));
+            }
+        }
+        let mut locations: HashSet<ZeroSpan> = HashSet::new();
+        for symbol in renamed {
+            let symbol_lock = symbol.lock().unwrap();
+            locations.insert(symbol_lock.loc);
+            locations.extend(symbol_lock.bases.iter().cloned());
+            locations.extend(symbol_lock.definitions.iter().cloned());
+            locations.extend(symbol_lock.declarations.iter().cloned());
+            locations.extend(symbol_lock.references.iter().cloned());
+        }
+        locations
+    }
+
     pub fn symbols_of_ref(&self, loc: ZeroSpan) -> Vec<SymbolRef> {
         // Would like to use .entry here, but it does not play nice with the
         // mutable borrow of .reference_info and .symbol_info
@@ -2477,6 +2701,10 @@ impl DeviceAnalysis {
         if let Some(syms) = locked_info.get(&loc) {
             syms.clone()
         } else {
+            if self.reference_cache_limit
+                .is_some_and(|limit|locked_info.len() >= limit) {
+                    locked_info.clear();
+                }
             let mut syms = vec![];
             for sym in self.symbol_info.all_symbols() {
                 if sym.lock().unwrap().references.contains(&loc) {
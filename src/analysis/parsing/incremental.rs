@@ -0,0 +1,300 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Incremental re-parsing of edited files
+//!
+//! An edit typically changes a few lines of a file. The top-level
+//! declarations before the changed lines are kept from the previous syntax
+//! tree of the file, as are those after them if the edit did not add or
+//! remove lines, and only the declarations in between are parsed again.
+//! Since the parser looks one token ahead, a declaration is only kept when
+//! the token following it is unchanged too.
+use log::debug;
+use logos::Logos;
+
+use crate::analysis::FileSpec;
+use crate::analysis::parsing::lexer::TokenKind;
+use crate::analysis::parsing::parser::{FileInfo, FileParser, Parse, Token};
+use crate::analysis::parsing::structure::{parse_toplevel_header,
+                                          toplevel_context,
+                                          DMLObject, TopAst};
+use crate::analysis::parsing::tree::{TreeElement, TreeElementMember,
+                                     ZeroPosition};
+use crate::stats::{self, Cache};
+
+/// A token skipped by the parser, with a description of what was expected
+pub type SkippedToken = (Token, &'static str);
+
+/// The parse of a file, kept to parse it again after it is edited
+#[derive(Debug, Clone)]
+pub struct PreviousParse {
+    pub text: String,
+    pub ast: TopAst,
+    pub skipped: Vec<SkippedToken>,
+}
+
+// The rows of the lines changed between two texts
+#[derive(Debug, PartialEq)]
+struct Damage {
+    // The first row that differs
+    first_row: u32,
+    // The first rows of the lines that are unchanged up to the end, in the
+    // old and the new text
+    old_suffix_row: u32,
+    new_suffix_row: u32,
+}
+
+fn damage(old: &str, new: &str) -> Damage {
+    // Lines are compared with their line breaks, so that a last line
+    // differs from the same line followed by another
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+    let prefix = old_lines.iter().zip(&new_lines)
+        .take_while(|(old, new)|old == new).count();
+    let suffix = old_lines.iter().rev().zip(new_lines.iter().rev())
+        .take(old_lines.len().min(new_lines.len()) - prefix)
+        .take_while(|(old, new)|old == new).count();
+    Damage {
+        first_row: prefix as u32,
+        old_suffix_row: (old_lines.len() - suffix) as u32,
+        new_suffix_row: (new_lines.len() - suffix) as u32,
+    }
+}
+
+// The tokens of an element are not necessarily gathered in the order they
+// appear in
+fn first_token(element: &dyn TreeElementMember) -> Option<Token> {
+    element.tokens().into_iter().min_by_key(|token|token.range.start())
+}
+
+fn last_token(element: &dyn TreeElementMember) -> Option<Token> {
+    element.tokens().into_iter().max_by_key(|token|token.range.end())
+}
+
+// The byte offset of a position in a text, as long as the line is ASCII up
+// to it, since the parser counts the columns of whitespace in bytes and
+// those of tokens in UTF-16 code units
+fn byte_offset(text: &str, position: ZeroPosition) -> Option<usize> {
+    let line_start = if position.row.0 == 0 {
+        0
+    } else {
+        text.match_indices('\n').nth(position.row.0 as usize - 1)?.0 + 1
+    };
+    let offset = line_start + position.col.0 as usize;
+    text.as_bytes().get(line_start..offset)?.is_ascii().then_some(offset)
+}
+
+/// Parse the top level of a file, reusing the declarations of a previous
+/// parse of it that an edit cannot have affected. Gives the same tree and
+/// skipped tokens as parsing the whole file, and whether the positions of
+/// tokens are exact, which they are not after lexer errors spanning lines.
+/// Only parses with exact positions can be reused.
+pub fn parse_toplevel_incrementally(text: &str,
+                                    previous: Option<&PreviousParse>,
+                                    file_info: &mut FileInfo,
+                                    file: FileSpec<'_>)
+                                    -> (TopAst, Vec<SkippedToken>, bool) {
+    let mut stream = FileParser::new(TokenKind::lexer(text));
+    let mut context = toplevel_context();
+    let mut toplevel = parse_toplevel_header(&mut context, &mut stream,
+                                             file_info, file);
+    let mut skipped = vec![];
+    // The offset in the text of what the parser lexes
+    let mut stream_offset = 0;
+    let mut reusable: &[DMLObject] = &[];
+    let mut damage_rows = None;
+    let mut reused = 0;
+    if let Some(previous) = previous {
+        let damage = damage(&previous.text, text);
+        let old = &previous.ast.declarations;
+        let unchanged = |decl: &DMLObject|first_token(decl).is_some_and(
+            |token|token.range.row_end.0 < damage.first_row);
+        // Declarations followed by a declaration starting before the edit
+        let mut kept = old.iter().skip(1).take_while(|decl|unchanged(decl))
+            .count();
+        while kept > 0 {
+            // The parser resumes after the last kept declaration and the
+            // tokens skipped after it, which the following declaration,
+            // being unchanged, cannot have affected. The header is
+            // unchanged too, so the tokens skipped in it are as before.
+            let before = old[kept].range().start();
+            let kept_skipped: Vec<&SkippedToken> = previous.skipped.iter()
+                .filter(|(token, _)|token.range.start() < before)
+                .collect();
+            let resume = last_token(&old[kept - 1])
+                .into_iter().chain(kept_skipped.iter().map(|(token, _)|*token))
+                .map(|token|token.range.end()).max()
+                .and_then(|end|Some((end, byte_offset(text, end)?)));
+            if let Some((position, offset)) = resume {
+                debug!("Reusing {} of {} declarations before row {}",
+                       kept, old.len(), damage.first_row);
+                skipped.extend(kept_skipped.into_iter().cloned());
+                toplevel.declarations.extend(old[..kept].iter().cloned());
+                reused = kept;
+                stream = FileParser::starting_at(
+                    TokenKind::lexer(&text[offset..]), position);
+                stream_offset = offset;
+                break;
+            }
+            kept -= 1;
+        }
+        // Declarations after the edit are only kept if they have not moved
+        if damage.old_suffix_row == damage.new_suffix_row {
+            reusable = &old[kept..];
+            damage_rows = Some(damage);
+        }
+    }
+    while let Some(next) = context.peek(&mut stream) {
+        if damage_rows.as_ref().is_some_and(
+            |damage|next.range.row_start.0 >= damage.new_suffix_row)
+            && byte_offset(text, next.range.start())
+            == Some(stream_offset + stream.lexer.span().start) {
+                let index = reusable.partition_point(
+                    |decl|decl.range().start() < next.range.start());
+                if reusable.get(index).and_then(|decl|first_token(decl))
+                    == Some(next) {
+                        debug!("Reusing {} declarations from row {}",
+                               reusable.len() - index,
+                               next.range.row_start.0);
+                        skipped.append(&mut stream.skipped_tokens);
+                        skipped.extend(
+                            previous.into_iter()
+                                .flat_map(|previous|&previous.skipped)
+                                .filter(|(token, _)|token.range.start()
+                                        >= next.range.start())
+                                .cloned());
+                        let parsed = toplevel.declarations.len() - reused;
+                        reused += reusable.len() - index;
+                        toplevel.declarations.extend(
+                            reusable[index..].iter().cloned());
+                        stats::record_cache(Cache::IncrementalParse,
+                                            reused as u64, parsed as u64);
+                        return (toplevel, skipped, true);
+                    }
+            }
+        toplevel.declarations.push(
+            DMLObject::parse(&context, &mut stream, file_info));
+    }
+    skipped.append(&mut stream.skipped_tokens);
+    if previous.is_some() {
+        stats::record_cache(Cache::IncrementalParse, reused as u64,
+                            (toplevel.declarations.len() - reused) as u64);
+    }
+    let exact = stream.get_position().row.0 as usize
+        == text.matches('\n').count();
+    (toplevel, skipped, exact)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::PathBuf;
+    use std::str::FromStr;
+    use crate::file_management::CanonPath;
+    use crate::vfs::TextFile;
+
+    fn parse(text: &str, previous: Option<&PreviousParse>)
+             -> (PreviousParse, bool) {
+        let path = CanonPath::from_path_buf(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR"))).unwrap();
+        let file = TextFile::from_str(text).unwrap();
+        let (ast, skipped, exact) = parse_toplevel_incrementally(
+            text, previous, &mut FileInfo::default(),
+            FileSpec { path: &path, file: &file });
+        (PreviousParse { text: text.to_string(), ast, skipped }, exact)
+    }
+
+    #[test]
+    fn test_damage() {
+        assert_eq!(damage("a\nb\nc\n", "a\nx\ny\nc\n"),
+                   Damage { first_row: 1, old_suffix_row: 2,
+                            new_suffix_row: 3 });
+        assert_eq!(damage("a\nb", "a\nb\n"),
+                   Damage { first_row: 1, old_suffix_row: 2,
+                            new_suffix_row: 2 });
+        assert_eq!(damage("a\na\n", "a\na\na\n"),
+                   Damage { first_row: 2, old_suffix_row: 2,
+                            new_suffix_row: 3 });
+    }
+
+    #[test]
+    fn test_incremental_parse_matches_full_parse() {
+        let original = "dml 1.4;\ndevice d;\n\
+                        // A comment\n\
+                        param p = 1;\n\
+                        bank b {\n    register r size 4 @ 0;\n}\n\
+                        /* multi\n   line */ method m() {\n    return;\n}\n\
+                        template t { param q = \"ü\"; }\n\
+                        #if (true) { param r = 2; } #else { param r = 3; }\n";
+        let edits = [
+            ("param p = 1;", "param p = 2;"),
+            ("param p = 1;", "param p = 1"),
+            ("    register r size 4 @ 0;\n",
+             "    register r size 4 @ 0;\n    register s size 4 @ 4;\n"),
+            ("}\n/* multi", "} /* multi"),
+            ("/* multi", "multi"),
+            ("line */", "line"),
+            ("    return;\n}\n", "    return;\n"),
+            ("\"ü\"", "\"u\""),
+            (" #else { param r = 3; }", ""),
+            ("device d;\n", "device d;\nbitorder be;\n"),
+            ("dml 1.4;", "dml 1.4"),
+            ("param r = 2; }", "param r = 2; } param s;"),
+        ];
+        let (previous, exact) = parse(original, None);
+        assert!(exact);
+        for (from, to) in edits {
+            let edited = original.replacen(from, to, 1);
+            let (full, full_exact) = parse(&edited, None);
+            let (incremental, exact) = parse(&edited, Some(&previous));
+            assert_eq!(incremental.ast, full.ast, "after {:?}", to);
+            assert_eq!(incremental.skipped, full.skipped, "after {:?}", to);
+            assert_eq!(exact, full_exact, "after {:?}", to);
+            // And back again
+            if exact {
+                let (restored, _) = parse(original, Some(&incremental));
+                assert_eq!(restored.ast, previous.ast, "after {:?}", to);
+                assert_eq!(restored.skipped, previous.skipped,
+                           "after {:?}", to);
+            }
+        }
+    }
+
+    #[test]
+    fn test_incremental_parse_of_random_edits() {
+        let original = include_str!("../../test/golden/pcie_endpoint.dml");
+        let (mut previous, _) = parse(original, None);
+        // A fixed sequence of edits: insertions of characters and line
+        // breaks and removals of ranges, each applied to the previous text
+        let mut seed: u64 = 17;
+        let mut random = |bound: usize|{
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+            (seed >> 33) as usize % bound
+        };
+        for _ in 0..200 {
+            let mut text = previous.text.clone();
+            let at = random(text.len() + 1);
+            if !text.is_char_boundary(at) {
+                continue;
+            }
+            match random(4) {
+                0 => text.insert(at, ['{', '}', ';', 'x', ' ', '"'][random(6)]),
+                1 => text.insert(at, '\n'),
+                _ => {
+                    let end = (at + random(8)).min(text.len());
+                    if !text.is_char_boundary(end) {
+                        continue;
+                    }
+                    text.replace_range(at..end, "");
+                },
+            }
+            let (full, full_exact) = parse(&text, None);
+            let (incremental, exact) = parse(&text, Some(&previous));
+            assert_eq!(incremental.ast, full.ast);
+            assert_eq!(incremental.skipped, full.skipped);
+            assert_eq!(exact, full_exact);
+            if exact {
+                previous = incremental;
+            }
+        }
+    }
+}
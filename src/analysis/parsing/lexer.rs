@@ -591,6 +591,13 @@ impl TokenKind {
     }
 }
 
+/// Whether a name can be declared in DML
+pub fn is_identifier(name: &str) -> bool {
+    let mut lexer = TokenKind::lexer(name);
+    matches!(lexer.next(), Some(Ok(TokenKind::Identifier)))
+        && lexer.slice() == name
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -6,6 +6,7 @@ pub mod parser;
 #[macro_use]
 pub mod tree;
 pub mod expression;
+pub mod incremental;
 pub mod misc;
 pub mod statement;
 pub mod structure;
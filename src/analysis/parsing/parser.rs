@@ -336,12 +336,19 @@ pub struct FileParser<'a> {
 
 impl <'a> FileParser<'a> {
     pub fn new(lexer: Lexer::<'a, TokenKind>) -> Self {
+        Self::starting_at(lexer, Position::<ZeroIndexed>::from_u32(0, 0))
+    }
+
+    /// A parser of text that is at `position` of a file, right after a
+    /// token of it
+    pub fn starting_at(lexer: Lexer::<'a, TokenKind>,
+                       position: Position<ZeroIndexed>) -> Self {
         let mut to_return = FileParser {
             lexer,
-            current_column: 0,
-            current_line: 0,
-            previous_column: 0,
-            previous_line: 0,
+            current_column: position.col.0,
+            current_line: position.row.0,
+            previous_column: position.col.0,
+            previous_line: position.row.0,
             next_token: None,
             skipped_tokens: vec![],
         };
@@ -473,7 +480,12 @@ impl <'a> FileParser<'a> {
     }
 
     pub fn report_skips(&self) -> Vec<LocalDMLError> {
-        self.skipped_tokens.iter().map(
+        Self::report_skipped(&self.skipped_tokens)
+    }
+
+    pub fn report_skipped(skipped: &[(Token, &'static str)])
+                          -> Vec<LocalDMLError> {
+        skipped.iter().map(
             |(tok, desc)|LocalDMLError {
                 range: tok.range,
                 description: format!("Unexpected token {}, expected {}",
@@ -2162,15 +2162,20 @@ impl TreeElement for TopAst {
     }
 }
 
-pub fn parse_toplevel(stream: &mut FileParser<'_>,
-                      file_info: &mut FileInfo,
-                      file: FileSpec<'_>)
-                      -> TopAst {
-    let mut top_context = ParseContext::new_context(
-        dmlobject_first_token_matcher);
-    let version = dmlversion_parse(&top_context, stream, file_info);
+pub fn toplevel_context() -> ParseContext {
+    ParseContext::new_context(dmlobject_first_token_matcher)
+}
+
+/// Parse what precedes the declarations of a file, leaving the declarations
+/// empty
+pub fn parse_toplevel_header(top_context: &mut ParseContext,
+                             stream: &mut FileParser<'_>,
+                             file_info: &mut FileInfo,
+                             file: FileSpec<'_>)
+                             -> TopAst {
+    let version = dmlversion_parse(top_context, stream, file_info);
     let provisionals = if top_context.peek_kind(stream) == Some(TokenKind::Provisional) {
-        Some(provisionals_parse(&top_context, stream, file_info))
+        Some(provisionals_parse(top_context, stream, file_info))
     } else {
         None
     };
@@ -2181,23 +2186,32 @@ pub fn parse_toplevel(stream: &mut FileParser<'_>,
     }
 
     let device = if top_context.peek_kind(stream) == Some(TokenKind::Device) {
-        Some(device_parse(&top_context, stream, file_info))
+        Some(device_parse(top_context, stream, file_info))
     } else {
         None
     };
     let bitorder = if top_context.peek_kind(stream) == Some(TokenKind::Bitorder) {
-        Some(bitorder_parse(&top_context, stream, file_info))
+        Some(bitorder_parse(top_context, stream, file_info))
     } else {
         None
     };
+    TopAst {
+        version, provisionals, device, bitorder, declarations: vec![]
+    }
+}
 
-    let mut declarations = vec![];
+pub fn parse_toplevel(stream: &mut FileParser<'_>,
+                      file_info: &mut FileInfo,
+                      file: FileSpec<'_>)
+                      -> TopAst {
+    let mut top_context = toplevel_context();
+    let mut toplevel = parse_toplevel_header(&mut top_context, stream,
+                                             file_info, file);
     while top_context.peek(stream).is_some() {
-        declarations.push(DMLObject::parse(&top_context, stream, file_info));
-    }
-    TopAst {
-        version, provisionals, device, bitorder, declarations
+        toplevel.declarations.push(
+            DMLObject::parse(&top_context, stream, file_info));
     }
+    toplevel
 }
 
 #[allow(clippy::ptr_arg)]
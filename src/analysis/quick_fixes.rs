@@ -0,0 +1,606 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Quick fixes for common problems in DML files, as edits of their text
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use logos::Logos;
+use serde::{Deserialize, Serialize};
+
+use crate::analysis::parsing::lexer::TokenKind;
+use crate::analysis::parsing::tree::ZeroRange;
+use crate::analysis::structure::objects::CompositeObject;
+use crate::analysis::structure::toplevel::{ObjectDecl, StatementSpec,
+                                            TopLevel};
+
+/// A fix of a problem in a file, as replacements of ranges of its text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuickFix {
+    pub title: String,
+    pub edits: Vec<(ZeroRange, String)>,
+}
+
+// A token of DML code, with its position in the file
+#[derive(Debug, Clone)]
+pub(crate) struct Token<'t> {
+    pub(crate) kind: TokenKind,
+    pub(crate) text: &'t str,
+    pub(crate) range: ZeroRange,
+    // The byte range of the token in the file
+    pub(crate) bytes: std::ops::Range<usize>,
+}
+
+// The tokens of a file, except whitespace
+// NOTE: Columns count characters, as in the spans of the analysis
+pub(crate) fn tokens_with_comments(text: &str) -> Vec<Token<'_>> {
+    let mut tokens = vec![];
+    let mut lexer = TokenKind::lexer(text);
+    let (mut row, mut col) = (0, 0);
+    while let Some(kind) = lexer.next() {
+        let slice = lexer.slice();
+        let (start_row, start_col) = (row, col);
+        for c in slice.chars() {
+            if c == '\n' {
+                row += 1;
+                col = 0;
+            } else {
+                col += 1;
+            }
+        }
+        match kind {
+            Ok(TokenKind::Whitespace | TokenKind::Newline) | Err(_) => (),
+            Ok(kind) => tokens.push(Token {
+                kind,
+                text: slice,
+                range: ZeroRange::from_u32(start_row, row, start_col, col),
+                bytes: lexer.span(),
+            }),
+        }
+    }
+    tokens
+}
+
+pub(crate) fn is_comment(kind: TokenKind) -> bool {
+    matches!(kind, TokenKind::Comment | TokenKind::MultilineComment)
+}
+
+// The tokens of a file, except whitespace and comments
+pub(crate) fn code_tokens(text: &str) -> Vec<Token<'_>> {
+    let mut tokens = tokens_with_comments(text);
+    tokens.retain(|token|!is_comment(token.kind));
+    tokens
+}
+
+// The range of whole lines, from the start of the first to the start of the
+// line after the last
+fn whole_lines(first: u32, last: u32) -> ZeroRange {
+    ZeroRange::from_u32(first, last + 1, 0, 0)
+}
+
+/// The name of the missing template in a "No template named" error
+pub fn missing_template(description: &str) -> Option<&str> {
+    description.strip_prefix("No template named '")?.strip_suffix('\'')
+}
+
+/// The path to import `target` as from `file`: relative to the directory of
+/// `file` if it is under it, or else to the first of `search_dirs` it is
+/// under
+pub fn import_path(file: &Path, target: &Path, search_dirs: &[PathBuf])
+                   -> Option<String> {
+    let relative = file.parent().into_iter()
+        .chain(search_dirs.iter().map(PathBuf::as_path))
+        .find_map(|dir|target.strip_prefix(dir).ok())?;
+    let parts: Vec<String> = relative.iter()
+        .map(|part|part.to_string_lossy().into_owned()).collect();
+    (!parts.is_empty()).then(||parts.join("/"))
+}
+
+/// Import a file, after the last import of the file or else after its
+/// device declaration
+pub fn add_import(toplevel: &TopLevel, import: &str) -> QuickFix {
+    let row = toplevel.spec.imports.iter()
+        .map(|import|import.obj.span.range.row_end.0)
+        .max()
+        .or(toplevel.device.as_ref().map(|device|device.span.range.row_end.0))
+        .or(toplevel.version.as_ref()
+            .map(|version|version.span.range.row_end.0))
+        .map_or(0, |row|row + 1);
+    QuickFix {
+        title: format!("Import \"{}\"", import),
+        edits: vec![(ZeroRange::from_u32(row, row, 0, 0),
+                     format!("import \"{}\";\n", import))],
+    }
+}
+
+/// How organized imports are grouped
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+         ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ImportGrouping {
+    /// Imports of the Simics base library, then those of the project,
+    /// separated by a blank line
+    #[default]
+    BaseFirst,
+    /// All imports sorted together
+    Single,
+}
+
+// Files of the DML standard library outside of its 'simics' directory
+const BASE_LIBRARY_FILES: [&str; 4] = [
+    "dml-builtins.dml", "dml12-compatibility.dml", "internal.dml",
+    "utility.dml",
+];
+
+/// Whether an imported path is a file of the Simics base library, rather
+/// than of the project
+pub fn is_base_import(import: &str) -> bool {
+    import.starts_with("simics/") || BASE_LIBRARY_FILES.contains(&import)
+}
+
+// A top-level import alone on its lines
+struct ImportLines<'t> {
+    // The imported path, without quotes
+    path: &'t str,
+    rows: RangeInclusive<u32>,
+    // The lines of the import, with any comment after it
+    text: String,
+    unused: bool,
+}
+
+// The imports outside of '#if' in a file, None if one of them shares a line
+// with other code
+fn import_lines<'t>(text: &'t str, unused: &[ZeroRange])
+                    -> Option<Vec<ImportLines<'t>>> {
+    let lines: Vec<&str> = text.split('\n')
+        .map(|line|line.trim_end_matches('\r')).collect();
+    let tokens = tokens_with_comments(text);
+    let mut imports = vec![];
+    let mut depth = 0;
+    for (index, token) in tokens.iter().enumerate() {
+        match token.kind {
+            TokenKind::LBrace => depth += 1,
+            TokenKind::RBrace => depth -= 1,
+            TokenKind::Import if depth == 0 => {
+                let file = tokens.get(index + 1)
+                    .filter(|file|file.kind == TokenKind::StringConstant)?;
+                let semi = tokens.get(index + 2)
+                    .filter(|semi|semi.kind == TokenKind::SemiColon)?;
+                let (first, last) = (token.range.row_start.0,
+                                     semi.range.row_end.0);
+                let before: String = lines[first as usize].chars()
+                    .take(token.range.col_start.0 as usize).collect();
+                let after: String = lines[last as usize].chars()
+                    .skip(semi.range.col_end.0 as usize).collect();
+                let after = after.trim();
+                if !before.trim().is_empty() || !(after.is_empty()
+                                                  || after.starts_with("//")
+                                                  || after.starts_with("/*")) {
+                    return None;
+                }
+                imports.push(ImportLines {
+                    path: &file.text[1..file.text.len() - 1],
+                    rows: first..=last,
+                    text: lines[first as usize..=last as usize].join("\n")
+                        .trim_start().to_string(),
+                    unused: unused.contains(&file.range),
+                });
+            },
+            _ => (),
+        }
+    }
+    Some(imports)
+}
+
+/// Organize the imports of a file: remove those with the imported file
+/// names at `unused`, and duplicates, and sort the others by their paths,
+/// in the groups of `grouping`, in place of the first import. Imports under
+/// `#if` are left as they are, and nothing is organized if an import
+/// shares a line with other code.
+pub fn organize_imports(text: &str, unused: &[ZeroRange],
+                        grouping: ImportGrouping) -> Option<QuickFix> {
+    let imports = import_lines(text, unused)?;
+    let first = imports.first()?;
+    let lines: Vec<&str> = text.split('\n')
+        .map(|line|line.trim_end_matches('\r')).collect();
+    let newline = if text.contains("\r\n") { "\r\n" } else { "\n" };
+
+    let mut kept: Vec<&ImportLines<'_>> = vec![];
+    for import in imports.iter().filter(|import|!import.unused) {
+        if !kept.iter().any(|other|other.path == import.path) {
+            kept.push(import);
+        }
+    }
+    kept.sort_by_key(|import|(grouping == ImportGrouping::BaseFirst
+                              && !is_base_import(import.path),
+                              import.path));
+    let mut organized = String::new();
+    for (index, import) in kept.iter().enumerate() {
+        if index > 0 && grouping == ImportGrouping::BaseFirst
+            && is_base_import(kept[index - 1].path)
+            && !is_base_import(import.path) {
+            organized.push_str(newline);
+        }
+        organized.push_str(&import.text.replace('\n', newline));
+        organized.push_str(newline);
+    }
+
+    // The organized imports replace the first ones, up to the first line
+    // that is neither an import nor blank, and the others are removed
+    let mut last = *first.rows.end();
+    let mut next = 1;
+    while let Some(import) = imports.get(next) {
+        let between = last + 1..*import.rows.start();
+        if !between.clone().all(|row|lines[row as usize].trim().is_empty()) {
+            break;
+        }
+        last = *import.rows.end();
+        next += 1;
+    }
+    let replaced: String = lines[*first.rows.start() as usize..=last as usize]
+        .iter().map(|line|format!("{}{}", line, newline)).collect();
+    if replaced == organized && next == imports.len() {
+        return None;
+    }
+    let mut edits = vec![(whole_lines(*first.rows.start(), last), organized)];
+    edits.extend(imports[next..].iter().map(
+        |import|(whole_lines(*import.rows.start(), *import.rows.end()),
+                 String::new())));
+    Some(QuickFix { title: "Organize imports".to_string(), edits })
+}
+
+/// The text of a file with its imports organized, as by `organize_imports`
+/// without removing unused ones
+pub fn organized_imports(text: &str, grouping: ImportGrouping) -> String {
+    let Some(fix) = organize_imports(text, &[], grouping) else {
+        return text.to_string();
+    };
+    // The edits replace whole lines
+    let mut lines: Vec<String> = text.split_inclusive('\n')
+        .map(str::to_string).collect();
+    let mut edits = fix.edits;
+    edits.sort_by_key(|(range, _)|std::cmp::Reverse(*range));
+    for (range, replacement) in edits {
+        let end = (range.row_end.0 as usize).min(lines.len());
+        lines.splice(range.row_start.0 as usize..end, [replacement]);
+    }
+    lines.concat()
+}
+
+/// Remove the instantiation of the template named at `name`, from an `is`
+/// statement or the `is` clause of an object declaration
+pub fn remove_instantiation(text: &str, name: ZeroRange) -> Option<QuickFix> {
+    let tokens = code_tokens(text);
+    let index = tokens.iter().position(
+        |token|token.range == name && token.kind == TokenKind::Identifier)?;
+    let title = format!("Remove instantiation of '{}'", tokens[index].text);
+    let before = index.checked_sub(1).map(|i|tokens[i].kind);
+
+    // The tokens of the whole instantiation, from 'is' to its last name
+    let (is, last) = match before? {
+        TokenKind::Is => (index - 1, index),
+        TokenKind::LParen | TokenKind::Comma => {
+            let open = tokens[..index].iter()
+                .rposition(|token|token.kind == TokenKind::LParen)?;
+            let close = index + tokens[index..].iter()
+                .position(|token|token.kind == TokenKind::RParen)?;
+            if close - open > 2 {
+                // Only remove the name from the list, with one comma
+                let range = if tokens[index + 1].kind == TokenKind::Comma {
+                    ZeroRange::from_positions(
+                        tokens[index].range.start(),
+                        tokens[index + 2].range.start())
+                } else {
+                    ZeroRange::from_positions(
+                        tokens[index - 2].range.end(),
+                        tokens[index].range.end())
+                };
+                return Some(QuickFix {
+                    title,
+                    edits: vec![(range, String::new())],
+                });
+            }
+            if open == 0 || tokens[open - 1].kind != TokenKind::Is {
+                return None;
+            }
+            (open - 1, close)
+        },
+        _ => return None,
+    };
+    let statement = is.checked_sub(1).is_none_or(
+        |i|matches!(tokens[i].kind, TokenKind::SemiColon | TokenKind::LBrace
+                    | TokenKind::RBrace));
+    let range = match tokens.get(last + 1) {
+        // An 'is' statement, which is removed with its line if it is the
+        // only thing on it
+        Some(semi) if statement && semi.kind == TokenKind::SemiColon => {
+            let (first_row, last_row) = (tokens[is].range.row_start.0,
+                                         semi.range.row_end.0);
+            let lines: Vec<&str> = text.split('\n').collect();
+            let alone_before = lines[first_row as usize].chars()
+                .take(tokens[is].range.col_start.0 as usize)
+                .all(char::is_whitespace);
+            let alone_after = lines[last_row as usize].chars()
+                .skip(semi.range.col_end.0 as usize)
+                .all(char::is_whitespace);
+            if alone_before && alone_after {
+                whole_lines(first_row, last_row)
+            } else {
+                // Along with the space up to the next statement
+                let end = tokens.get(last + 2)
+                    .filter(|next|next.range.row_start == semi.range.row_end)
+                    .map_or(semi.range.end(), |next|next.range.start());
+                ZeroRange::from_positions(tokens[is].range.start(), end)
+            }
+        },
+        // An 'is' clause, removed with the space before it
+        _ => ZeroRange::from_positions(
+            tokens.get(is.checked_sub(1)?)?.range.end(),
+            tokens[last].range.end()),
+    };
+    Some(QuickFix { title, edits: vec![(range, String::new())] })
+}
+
+/// Remove the declaration at `range`, with the lines it is on if nothing
+/// else is on them
+pub fn remove_declaration(text: &str, title: String, range: ZeroRange)
+                          -> QuickFix {
+    let lines: Vec<&str> = text.split('\n').collect();
+    let line = |row: u32|lines.get(row as usize).copied().unwrap_or("");
+    let alone_before = line(range.row_start.0).chars()
+        .take(range.col_start.0 as usize)
+        .all(char::is_whitespace);
+    let alone_after = line(range.row_end.0).chars()
+        .skip(range.col_end.0 as usize)
+        .all(char::is_whitespace);
+    let range = if alone_before && alone_after {
+        whole_lines(range.row_start.0, range.row_end.0)
+    } else {
+        range
+    };
+    QuickFix { title, edits: vec![(range, String::new())] }
+}
+
+/// The DML 1.2 'parameter' keywords of a file, which are 'param' in DML 1.4
+pub fn parameter_keywords(text: &str) -> Vec<ZeroRange> {
+    let tokens = code_tokens(text);
+    tokens.iter().enumerate()
+        .filter(|(index, token)| token.kind == TokenKind::Identifier
+                && token.text == "parameter"
+                && tokens.get(index + 1).is_some_and(
+                    |next|next.kind == TokenKind::Identifier)
+                && index.checked_sub(1).is_none_or(
+                    |i|matches!(tokens[i].kind, TokenKind::SemiColon
+                                | TokenKind::LBrace | TokenKind::RBrace)))
+        .map(|(_, token)|token.range)
+        .collect()
+}
+
+// The composite objects declared in a statement spec, at any depth
+fn composite_objects<'t>(spec: &'t StatementSpec,
+                         objects: &mut Vec<&'t ObjectDecl<CompositeObject>>) {
+    for object in &spec.objects {
+        objects.push(object);
+        composite_objects(&object.spec, objects);
+    }
+}
+
+/// Insert a placeholder documentation string in the declarations of
+/// undocumented objects that have their names on the given rows
+pub fn documentation_stubs(toplevel: &TopLevel, text: &str,
+                           rows: std::ops::RangeInclusive<u32>)
+                           -> Vec<QuickFix> {
+    let mut objects = vec![];
+    composite_objects(&toplevel.spec, &mut objects);
+    for template in &toplevel.templates {
+        composite_objects(&template.spec, &mut objects);
+    }
+    let tokens = code_tokens(text);
+    let chars: Vec<Vec<char>> = text.split('\n')
+        .map(|line|line.chars().collect()).collect();
+    let mut fixes = vec![];
+    for decl in objects {
+        let object = &decl.obj;
+        let name = &object.object.name;
+        if object.doc.is_some()
+            || !rows.contains(&name.span.range.row_start.0)
+            || decl.spec.params.iter().any(
+                |param|param.obj.object.name.val == "desc") {
+            continue;
+        }
+        // The documentation goes right before the statements of the
+        // object, the first '{' or ';' outside of its dimensions and
+        // is clause
+        let Some(index) = tokens.iter()
+            .position(|token|token.range == name.span.range) else {
+                continue;
+            };
+        let mut depth = 0;
+        let Some(body) = tokens[index + 1..].iter().find(|token| {
+            match token.kind {
+                TokenKind::LParen | TokenKind::LBracket => depth += 1,
+                TokenKind::RParen | TokenKind::RBracket => depth -= 1,
+                TokenKind::LBrace | TokenKind::SemiColon => return depth == 0,
+                _ => (),
+            }
+            false
+        }) else {
+            continue;
+        };
+        let position = body.range.start();
+        let spaced = position.col.0.checked_sub(1).is_some_and(
+            |col|chars[position.row.0 as usize][col as usize]
+                .is_whitespace());
+        fixes.push(QuickFix {
+            title: format!("Add documentation to {} '{}'",
+                           object.kind.kind_name(), name.val),
+            edits: vec![(ZeroRange::from_positions(position, position),
+                         if spaced { "\"TODO\" " } else { " \"TODO\"" }
+                         .to_string())],
+        });
+    }
+    fixes
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use std::str::FromStr;
+    use crate::analysis::{parse_file, FileSpec};
+    use crate::vfs::TextFile;
+
+    pub(crate) fn toplevel(source: &str) -> TopLevel {
+        let path = PathBuf::from("test.dml");
+        let file = TextFile::from_str(source).unwrap();
+        let filespec = FileSpec { path: &path, file: &file };
+        let (ast, _, _) = parse_file(&path, filespec).unwrap();
+        TopLevel::from_ast(&ast, &mut vec![], filespec)
+    }
+
+    // Apply the edits of a fix, which are assumed to not overlap
+    pub(crate) fn apply(text: &str, fix: &QuickFix) -> String {
+        let mut edits = fix.edits.clone();
+        edits.sort_by_key(|(range, _)|std::cmp::Reverse(*range));
+        let mut lines: Vec<Vec<char>> = text.split('\n')
+            .map(|line|line.chars().collect()).collect();
+        for (range, replacement) in edits {
+            let (start, end) = (range.start(), range.end());
+            let mut joined: Vec<char> =
+                lines[start.row.0 as usize][..start.col.0 as usize].to_vec();
+            joined.extend(replacement.chars());
+            joined.extend(&lines[end.row.0 as usize][end.col.0 as usize..]);
+            let new_lines: Vec<Vec<char>> = joined.split(|c|*c == '\n')
+                .map(<[char]>::to_vec).collect();
+            lines.splice(start.row.0 as usize..=end.row.0 as usize,
+                         new_lines);
+        }
+        lines.iter().map(|line|line.iter().collect::<String>())
+            .collect::<Vec<_>>().join("\n")
+    }
+
+    fn name_range(text: &str, row: u32, name: &str) -> ZeroRange {
+        let line = text.split('\n').nth(row as usize).unwrap();
+        let col = line.find(name).unwrap() as u32;
+        ZeroRange::from_u32(row, row, col, col + name.len() as u32)
+    }
+
+    #[test]
+    fn test_add_import() {
+        let source = "dml 1.4;\ndevice d;\nimport \"a.dml\";\nbank b;\n";
+        assert_eq!(apply(source, &add_import(&toplevel(source), "t.dml")),
+                   "dml 1.4;\ndevice d;\nimport \"a.dml\";\n\
+                    import \"t.dml\";\nbank b;\n");
+        let source = "dml 1.4;\ndevice d;\nbank b;\n";
+        assert_eq!(apply(source, &add_import(&toplevel(source), "t.dml")),
+                   "dml 1.4;\ndevice d;\nimport \"t.dml\";\nbank b;\n");
+
+        let search = [PathBuf::from("/lib")];
+        assert_eq!(import_path(Path::new("/dev/d.dml"),
+                               Path::new("/dev/sub/t.dml"), &search),
+                   Some("sub/t.dml".to_string()));
+        assert_eq!(import_path(Path::new("/dev/d.dml"),
+                               Path::new("/lib/t.dml"), &search),
+                   Some("t.dml".to_string()));
+        assert_eq!(import_path(Path::new("/dev/d.dml"),
+                               Path::new("/other/t.dml"), &search), None);
+        assert_eq!(missing_template("No template named 'foo'"), Some("foo"));
+        assert_eq!(missing_template("No template; 'foo'"), None);
+    }
+
+    #[test]
+    fn test_organize_imports() {
+        let source = "dml 1.4;\ndevice d;\nimport \"regs.dml\"; // Banks\n\
+                      import \"utility.dml\";\n\nimport \"a.dml\";\n\
+                      import \"regs.dml\";\nbank b;\nimport \"simics/base.dml\";\n\
+                      #if (true) {\n    import \"z.dml\";\n}\n";
+        let organize = |unused: &[ZeroRange], grouping| apply(
+            source, &organize_imports(source, unused, grouping).unwrap());
+        assert_eq!(organize(&[], ImportGrouping::BaseFirst),
+                   "dml 1.4;\ndevice d;\nimport \"simics/base.dml\";\n\
+                    import \"utility.dml\";\n\nimport \"a.dml\";\n\
+                    import \"regs.dml\"; // Banks\nbank b;\n\
+                    #if (true) {\n    import \"z.dml\";\n}\n");
+        assert_eq!(organize(&[name_range(source, 5, "\"a.dml\"")],
+                            ImportGrouping::Single),
+                   "dml 1.4;\ndevice d;\nimport \"regs.dml\"; // Banks\n\
+                    import \"simics/base.dml\";\nimport \"utility.dml\";\n\
+                    bank b;\n#if (true) {\n    import \"z.dml\";\n}\n");
+
+        let organized = "dml 1.4;\nimport \"utility.dml\";\n\n\
+                         import \"a.dml\";\n";
+        assert_eq!(organize_imports(organized, &[], ImportGrouping::BaseFirst),
+                   None);
+        assert_eq!(organized_imports(organized, ImportGrouping::Single),
+                   "dml 1.4;\nimport \"a.dml\";\nimport \"utility.dml\";\n");
+        // Imports sharing lines with other code are left as they are
+        assert_eq!(organize_imports("import \"b.dml\"; import \"a.dml\";\n",
+                                    &[], ImportGrouping::Single), None);
+    }
+
+    #[test]
+    fn test_remove_instantiation() {
+        let source = "dml 1.4;\ndevice d;\nis foo;\n\
+                      bank b is (bar, foo) {\n    is (foo); param p = 1;\n}\n\
+                      bank c is foo;\nbank e is (foo, bar);\n";
+        let remove = |row, name| apply(
+            source, &remove_instantiation(source, name_range(source, row,
+                                                             name)).unwrap());
+        assert_eq!(remove(2, "foo"),
+                   source.replacen("is foo;\n", "", 1));
+        assert_eq!(remove(3, "foo"),
+                   source.replace("(bar, foo)", "(bar)"));
+        assert_eq!(remove(3, "bar"),
+                   source.replace("(bar, foo)", "(foo)"));
+        assert_eq!(remove(4, "foo"),
+                   source.replace("is (foo); param", "param"));
+        assert_eq!(remove(7, "foo"),
+                   source.replace("(foo, bar)", "(bar)"));
+        assert_eq!(remove(6, "foo"),
+                   source.replace("bank c is foo;", "bank c;"));
+        assert!(remove_instantiation(source, name_range(source, 3, "b"))
+                .is_none());
+    }
+
+    #[test]
+    fn test_remove_declaration() {
+        let source = "dml 1.4;\ndevice d;\nparam p = 1;\n\
+                      method m() {\n}\nbank b { param q = 2; }\n";
+        let remove = |range| apply(source, &remove_declaration(
+            source, "Remove".to_string(), range));
+        assert_eq!(remove(ZeroRange::from_u32(2, 2, 0, 12)),
+                   source.replace("param p = 1;\n", ""));
+        assert_eq!(remove(ZeroRange::from_u32(3, 4, 0, 1)),
+                   source.replace("method m() {\n}\n", ""));
+        assert_eq!(remove(ZeroRange::from_u32(5, 5, 9, 21)),
+                   source.replace("param q = 2;", ""));
+    }
+
+    #[test]
+    fn test_parameter_keywords() {
+        let source = "dml 1.2;\ndevice d;\nparameter a = 1;\n\
+                      bank b { parameter c default 2; }\n\
+                      method m() { local int parameter; }\n";
+        assert_eq!(parameter_keywords(source),
+                   vec![name_range(source, 2, "parameter"),
+                        name_range(source, 3, "parameter")]);
+    }
+
+    #[test]
+    fn test_documentation_stubs() {
+        let source = "dml 1.4;\ndevice d;\nbank b {\n\
+                      register r[i < 2] size 4 @ 4 * i is (read) {}\n\
+                      register s size 4 @ 8 \"Documented\";\n\
+                      register t size 4 @ 12 { param desc = \"T\"; }\n\
+                      register u size 4 @ 16;\n}\n";
+        let top = toplevel(source);
+        let stubs = |row| documentation_stubs(&top, source, row..=row);
+        assert_eq!(stubs(2).iter().map(|fix|fix.title.as_str())
+                   .collect::<Vec<_>>(), vec!["Add documentation to bank 'b'"]);
+        assert_eq!(apply(source, &stubs(3)[0]),
+                   source.replace("(read) {}", "(read) \"TODO\" {}"));
+        assert!(stubs(4).is_empty());
+        assert!(stubs(5).is_empty());
+        assert_eq!(apply(source, &stubs(6)[0]),
+                   source.replace("@ 16;", "@ 16 \"TODO\";"));
+    }
+}
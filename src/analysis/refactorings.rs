@@ -0,0 +1,1180 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Refactorings of DML code, as edits of its text
+use std::collections::HashSet;
+
+use crate::analysis::{DeclarationSpan, DMLNamed};
+use crate::analysis::parsing::lexer::TokenKind;
+use crate::analysis::parsing::tree::{ZeroPosition, ZeroRange};
+use crate::analysis::quick_fixes::{code_tokens, remove_declaration,
+                                   QuickFix, Token};
+use crate::analysis::structure::objects::{CompositeObject, Method,
+                                          MethodArgument, MethodModifier,
+                                          Variable};
+use crate::analysis::structure::statements::{ForPre, Statement,
+                                             StatementKind, SwitchCase};
+use crate::analysis::structure::toplevel::{ExistCondition, ObjectDecl,
+                                           StatementSpec, TopLevel};
+
+// The byte offset of a position in a file
+fn offset(text: &str, position: ZeroPosition) -> Option<usize> {
+    let mut start = 0;
+    for line in text.split_inclusive('\n').take(position.row.0 as usize) {
+        start += line.len();
+    }
+    let line = text.get(start..)?;
+    let col = position.col.0 as usize;
+    line.char_indices().map(|(i, _)|i).chain(std::iter::once(line.len()))
+        .nth(col).map(|i|start + i)
+}
+
+// The whitespace at the start of a line of a file
+fn indentation(text: &str, row: u32) -> &str {
+    let line = text.lines().nth(row as usize).unwrap_or("");
+    &line[..line.len() - line.trim_start().len()]
+}
+
+fn spec_methods<'t>(spec: &'t StatementSpec, methods: &mut Vec<&'t Method>) {
+    methods.extend(spec.methods.iter().map(|method|&method.obj));
+    for object in &spec.objects {
+        spec_methods(&object.spec, methods);
+    }
+    for ineach in &spec.ineachs {
+        spec_methods(&ineach.spec, methods);
+    }
+}
+
+// The statements directly under a statement
+fn substatements(statement: &Statement) -> Vec<&Statement> {
+    match statement.as_ref() {
+        StatementKind::ForEach(foreach) => vec![&foreach.body],
+        StatementKind::HashIf(hashif) =>
+            std::iter::once(&hashif.ifbody).chain(&hashif.elsebody)
+            .collect(),
+        StatementKind::If(ifstmnt) =>
+            std::iter::once(&ifstmnt.ifbody).chain(&ifstmnt.elsebody)
+            .collect(),
+        StatementKind::Switch(switch) => switch.cases.iter()
+            .filter_map(|case|match case {
+                SwitchCase::Statement(statement) => Some(statement),
+                _ => None,
+            })
+            .collect(),
+        StatementKind::While(whilestmnt) => vec![&whilestmnt.body],
+        StatementKind::For(forstmnt) => vec![&forstmnt.body],
+        StatementKind::DoWhile(dowhile) => vec![&dowhile.body],
+        StatementKind::HashSelect(select) =>
+            vec![&select.selectbranch, &select.elsebranch],
+        StatementKind::TryCatch(trycatch) =>
+            vec![&trycatch.tryblock, &trycatch.catchblock],
+        StatementKind::Compound(compound) =>
+            compound.statements.iter().collect(),
+        _ => vec![],
+    }
+}
+
+// The variables declared by a statement, as their names and declarations
+fn declared(variable: &Variable, text: &str) -> Vec<Local> {
+    variable.vars.iter().map(|var|{
+        let range = var.object.span.range;
+        Local {
+            name: var.object.name.val.to_string(),
+            declaration: offset(text, range.start())
+                .zip(offset(text, range.end()))
+                .and_then(|(start, end)|text.get(start..end))
+                .map(str::to_string),
+        }
+    }).collect()
+}
+
+// A local variable in scope at the extracted statements
+#[derive(Debug, Clone)]
+struct Local {
+    name: String,
+    // The declaration of the local, e.g. "uint32 *p", or None if it cannot
+    // be passed to a method, as the identifiers of 'foreach' and 'select',
+    // and untyped arguments of inline methods
+    declaration: Option<String>,
+}
+
+impl Local {
+    // The type of the local, as its declaration without its name
+    fn type_text(&self) -> Option<String> {
+        let declaration = self.declaration.as_ref()?;
+        let at = declaration.rfind(self.name.as_str())?;
+        let typed = format!("{}{}", &declaration[..at],
+                            &declaration[at + self.name.len()..]);
+        Some(typed.trim().to_string())
+    }
+}
+
+// The contiguous statements of `statements` that are within `range`, and
+// the locals in scope at them
+fn select<'t>(statements: &'t [Statement], range: ZeroRange, text: &str,
+              locals: &mut Vec<Local>) -> Option<&'t [Statement]> {
+    for (index, statement) in statements.iter().enumerate() {
+        let span = statement.span().range;
+        if span.end() <= range.start() {
+            if let StatementKind::VariableDecl(variable) = statement.as_ref() {
+                locals.extend(declared(variable, text));
+            }
+            continue;
+        }
+        if span.start() >= range.end() {
+            return None;
+        }
+        if span.start() < range.start() {
+            return select_within(statement, range, text, locals);
+        }
+        let count = statements[index..].iter()
+            .take_while(|statement|
+                        statement.span().range.start() < range.end())
+            .count();
+        let selected = &statements[index..index + count];
+        return (selected.last()?.span().range.end() <= range.end())
+            .then_some(selected);
+    }
+    None
+}
+
+// The statements within `range` under a statement that starts before it
+fn select_within<'t>(statement: &'t Statement, range: ZeroRange, text: &str,
+                     locals: &mut Vec<Local>) -> Option<&'t [Statement]> {
+    if let StatementKind::Compound(compound) = statement.as_ref() {
+        return select(&compound.statements, range, text, locals);
+    }
+    if let StatementKind::Switch(_) = statement.as_ref() {
+        // The cases of a switch are not statements of their own
+        return None;
+    }
+    match statement.as_ref() {
+        StatementKind::For(forstmnt) => if let Some(ForPre::Declaration(
+            variable)) = &forstmnt.pre {
+            locals.extend(declared(variable, text));
+        },
+        StatementKind::ForEach(foreach) => locals.push(Local {
+            name: foreach.identifier.name().val.to_string(),
+            declaration: None,
+        }),
+        StatementKind::HashSelect(select) => locals.push(Local {
+            name: select.ident.name().val.to_string(),
+            declaration: None,
+        }),
+        _ => (),
+    }
+    let body = substatements(statement).into_iter().find(
+        |body|body.span().range.start() <= range.start()
+            && range.end() <= body.span().range.end())?;
+    select(std::slice::from_ref(body), range, text, locals)
+}
+
+// Whether statements leave the statements around them, by returning or by
+// breaking out of or continuing a loop outside of them
+fn escapes(statement: &Statement, in_loop: bool, in_switch: bool) -> bool {
+    let (in_loop, in_switch) = match statement.as_ref() {
+        StatementKind::Return(_) => return true,
+        StatementKind::Break(_) => return !in_loop && !in_switch,
+        StatementKind::Continue(_) => return !in_loop,
+        StatementKind::ForEach(_) | StatementKind::While(_)
+            | StatementKind::For(_) | StatementKind::DoWhile(_) =>
+            (true, false),
+        StatementKind::Switch(_) => (in_loop, true),
+        _ => (in_loop, in_switch),
+    };
+    substatements(statement).into_iter()
+        .any(|statement|escapes(statement, in_loop, in_switch))
+}
+
+// The names of the variables declared under a statement
+fn declarations_under(statement: &Statement, text: &str,
+                      names: &mut HashSet<String>) {
+    match statement.as_ref() {
+        StatementKind::VariableDecl(variable) => names.extend(
+            declared(variable, text).into_iter().map(|local|local.name)),
+        StatementKind::For(forstmnt) => if let Some(ForPre::Declaration(
+            variable)) = &forstmnt.pre {
+            names.extend(
+                declared(variable, text).into_iter().map(|local|local.name));
+        },
+        StatementKind::ForEach(foreach) => {
+            names.insert(foreach.identifier.name().val.to_string());
+        },
+        StatementKind::HashSelect(select) => {
+            names.insert(select.ident.name().val.to_string());
+        },
+        _ => (),
+    }
+    for statement in substatements(statement) {
+        declarations_under(statement, text, names);
+    }
+}
+
+// Whether a token is a variable, rather than a member of something
+fn is_variable(tokens: &[Token<'_>], index: usize) -> bool {
+    tokens[index].kind == TokenKind::Identifier
+        && (index == 0 || !matches!(tokens[index - 1].kind,
+                                    TokenKind::Dot | TokenKind::Arrow))
+}
+
+// Whether the variable at a token may be changed by the code around it:
+// assigned, incremented, decremented or having its address taken
+fn is_changed(tokens: &[Token<'_>], index: usize) -> bool {
+    let operand_before = index >= 2 && matches!(
+        tokens[index - 2].kind,
+        TokenKind::Identifier | TokenKind::RParen | TokenKind::RBracket
+            | TokenKind::IntConstant | TokenKind::HexConstant
+            | TokenKind::BinaryConstant | TokenKind::FloatConstant
+            | TokenKind::CharConstant);
+    match index.checked_sub(1).map(|before|tokens[before].kind) {
+        Some(TokenKind::PlusPlus | TokenKind::MinusMinus) => return true,
+        // Taking the address, rather than a bitwise and
+        Some(TokenKind::BinAnd) if !operand_before => return true,
+        _ => (),
+    }
+    // Skip over members and indices of the variable itself
+    let mut next = index + 1;
+    while let Some(token) = tokens.get(next) {
+        match token.kind {
+            TokenKind::Dot => next += 2,
+            TokenKind::LBracket => {
+                let mut depth = 0;
+                while let Some(token) = tokens.get(next) {
+                    match token.kind {
+                        TokenKind::LBracket => depth += 1,
+                        TokenKind::RBracket => depth -= 1,
+                        _ => (),
+                    }
+                    next += 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+            },
+            _ => break,
+        }
+    }
+    tokens.get(next).is_some_and(|token|matches!(
+        token.kind,
+        TokenKind::Assign | TokenKind::TimesAssign | TokenKind::DivideAssign
+            | TokenKind::ModAssign | TokenKind::PlusAssign
+            | TokenKind::MinusAssign | TokenKind::LShiftAssign
+            | TokenKind::RShiftAssign | TokenKind::BAndAssign
+            | TokenKind::BXorAssign | TokenKind::BOrAssign
+            | TokenKind::PlusPlus | TokenKind::MinusMinus))
+}
+
+// The arguments of a method, as locals of its body
+fn arguments(method: &Method, tokens: &[Token<'_>], text: &str)
+             -> Vec<Local> {
+    method.arguments.iter().map(|argument| match argument {
+        MethodArgument::Inline(name) => Local {
+            name: name.val.to_string(),
+            declaration: None,
+        },
+        // The declaration is the tokens between the commas or parentheses
+        // around the name
+        MethodArgument::Typed(name, _) => Local {
+            name: name.val.to_string(),
+            declaration: tokens.iter()
+                .position(|token|token.range == name.span.range)
+                .and_then(|at|{
+                    let first = tokens[..at].iter().rposition(
+                        |token|matches!(token.kind, TokenKind::LParen
+                                        | TokenKind::Comma))? + 1;
+                    let last = at + tokens[at..].iter().position(
+                        |token|matches!(token.kind, TokenKind::RParen
+                                        | TokenKind::Comma))? - 1;
+                    text.get(tokens[first].bytes.start..tokens[last].bytes.end)
+                })
+                .map(str::to_string),
+        },
+    }).collect()
+}
+
+// The first name of the form `base`, `base_2`, `base_3`, ... that is not
+// an identifier of a file
+fn unused_name(tokens: &[Token<'_>], base: &str) -> Option<String> {
+    let identifiers: HashSet<&str> = tokens.iter()
+        .filter(|token|token.kind == TokenKind::Identifier)
+        .map(|token|token.text)
+        .collect();
+    std::iter::once(base.to_string())
+        .chain((2..).map(|n|format!("{}_{}", base, n)))
+        .find(|name|!identifiers.contains(name.as_str()))
+}
+
+/// Extract the statements within `range` of a method body into a new method
+/// of the same object, placed after the method and called in their place.
+/// The locals that the statements use become arguments of the new method,
+/// and those that they change and that are used after them become its
+/// return values. Statements that return, or break out of or continue a
+/// loop around them, cannot be extracted.
+pub fn extract_method(toplevel: &TopLevel, text: &str, range: ZeroRange)
+                      -> Option<QuickFix> {
+    if range.start() == range.end() {
+        return None;
+    }
+    let mut methods = vec![];
+    spec_methods(&toplevel.spec, &mut methods);
+    for template in &toplevel.templates {
+        spec_methods(&template.spec, &mut methods);
+    }
+    let method = methods.into_iter().find(|method|{
+        let body = method.body.span().range;
+        body.start() < range.start() && range.end() < body.end()
+    })?;
+    let tokens = code_tokens(text);
+    let mut locals = arguments(method, &tokens, text);
+    let selected = select_within(&method.body, range, text, &mut locals)?;
+    if selected.iter().any(|statement|escapes(statement, false, false)) {
+        return None;
+    }
+    let first = selected.first()?.span().range;
+    let last = selected.last()?.span().range;
+    let start = offset(text, first.start())?;
+    let end = offset(text, last.end())?;
+    let method_end = offset(text, method.object.span.range.end())?;
+    let within: Vec<usize> = (0..tokens.len())
+        .filter(|i|start <= tokens[*i].bytes.start
+                && tokens[*i].bytes.end <= end)
+        .collect();
+    if within.iter().any(|i|matches!(tokens[*i].kind, TokenKind::Return
+                                      | TokenKind::Default)) {
+        return None;
+    }
+
+    // The names declared by the statements must be new, and not be used
+    // after them
+    let mut inner = HashSet::default();
+    for statement in selected {
+        declarations_under(statement, text, &mut inner);
+    }
+    let after: Vec<usize> = (0..tokens.len())
+        .filter(|i|end <= tokens[*i].bytes.start
+                && tokens[*i].bytes.end <= method_end
+                && is_variable(&tokens, *i))
+        .collect();
+    if inner.iter().any(|name|locals.iter().any(|local|&local.name == name)
+                        || after.iter().any(|i|tokens[*i].text == name)) {
+        return None;
+    }
+
+    // Locals are in scope until they are shadowed
+    let local = |name: &str|locals.iter().rev()
+        .find(|local|local.name == name);
+    let mut used: Vec<&Local> = vec![];
+    let mut changed: HashSet<&str> = HashSet::default();
+    for i in within.iter().copied().filter(|i|is_variable(&tokens, *i)) {
+        let Some(found) = local(tokens[i].text) else {
+            continue;
+        };
+        found.declaration.as_ref()?;
+        if !used.iter().any(|other|other.name == found.name) {
+            used.push(found);
+        }
+        if is_changed(&tokens, i) {
+            changed.insert(tokens[i].text);
+        }
+    }
+    let outputs: Vec<&Local> = used.iter().copied()
+        .filter(|local|changed.contains(local.name.as_str())
+                && after.iter().any(|i|tokens[*i].text == local.name))
+        .collect();
+    let returns = outputs.iter().map(|local|local.type_text())
+        .collect::<Option<Vec<String>>>()?;
+
+    let name = unused_name(&tokens, "extracted")?;
+    let arguments: Vec<&str> = used.iter()
+        .map(|local|local.name.as_str()).collect();
+    let call = format!("{}({})", name, arguments.join(", "));
+    let call = match outputs.as_slice() {
+        [] => format!("{};", call),
+        [output] => format!("{} = {};", output.name, call),
+        _ => format!("({}) = {};", outputs.iter()
+                     .map(|local|local.name.as_str())
+                     .collect::<Vec<_>>().join(", "), call),
+    };
+
+    // The body is indented one step further than the method, as the
+    // statements are in their method
+    let indent = indentation(text, method.object.span.range.row_start.0);
+    let statement_indent = indentation(text, first.row_start.0);
+    let step = statement_indent.strip_prefix(indent)
+        .filter(|step|!step.is_empty())
+        .unwrap_or("    ");
+    let mut body = String::new();
+    for (i, line) in text[start..end].split('\n').enumerate() {
+        let line = if i == 0 {
+            line
+        } else {
+            line.strip_prefix(statement_indent)
+                .unwrap_or_else(||line.trim_start())
+        };
+        if !line.is_empty() {
+            body.push_str(indent);
+            body.push_str(step);
+            body.push_str(line);
+        }
+        body.push('\n');
+    }
+    match outputs.as_slice() {
+        [] => (),
+        [output] => body.push_str(&format!("{}{}return {};\n", indent, step,
+                                           output.name)),
+        _ => body.push_str(&format!(
+            "{}{}return ({});\n", indent, step, outputs.iter()
+                .map(|local|local.name.as_str())
+                .collect::<Vec<_>>().join(", "))),
+    }
+    let mut header = String::new();
+    if method.modifier == MethodModifier::Shared {
+        header.push_str("shared ");
+    }
+    if method.independent {
+        header.push_str("independent ");
+    }
+    let parameters: Vec<&str> = used.iter()
+        .filter_map(|local|local.declaration.as_deref()).collect();
+    header.push_str(&format!("method {}({})", name, parameters.join(", ")));
+    if !returns.is_empty() {
+        header.push_str(&format!(" -> ({})", returns.join(", ")));
+    }
+    if method.throws {
+        header.push_str(" throws");
+    }
+    let end_of_method = method.object.span.range.end();
+    Some(QuickFix {
+        title: format!("Extract into method '{}'", name),
+        edits: vec![
+            (ZeroRange::from_positions(first.start(), last.end()), call),
+            (ZeroRange::from_positions(end_of_method, end_of_method),
+             format!("\n\n{}{} {{\n{}{}}}", indent, header, body, indent)),
+        ],
+    })
+}
+
+// The text of a range of a file, with the indentation of its first line
+// removed from the lines after it
+fn dedented(text: &str, range: ZeroRange) -> Option<String> {
+    let source = text.get(offset(text, range.start())?
+                          ..offset(text, range.end())?)?;
+    let indent = indentation(text, range.row_start.0);
+    Some(source.split('\n').enumerate()
+         .map(|(i, line)|if i == 0 {
+             line
+         } else {
+             line.strip_prefix(indent).unwrap_or_else(||line.trim_start())
+         })
+         .collect::<Vec<_>>()
+         .join("\n"))
+}
+
+// The objects to extract a template from: the sibling objects within a
+// range, or else the innermost object around it
+fn objects_within<'t>(spec: &'t StatementSpec, range: ZeroRange,
+                      around: &mut Option<&'t ObjectDecl<CompositeObject>>)
+                      -> Vec<&'t ObjectDecl<CompositeObject>> {
+    let within: Vec<_> = spec.objects.iter()
+        .filter(|object|{
+            let span = object.obj.span().range;
+            range.start() <= span.start() && span.end() <= range.end()
+        })
+        .collect();
+    if !within.is_empty() {
+        return within;
+    }
+    for object in &spec.objects {
+        let span = object.obj.span().range;
+        if span.start() <= range.start() && range.end() <= span.end() {
+            *around = Some(object);
+        }
+        let within = objects_within(&object.spec, range, around);
+        if !within.is_empty() {
+            return within;
+        }
+    }
+    for ineach in &spec.ineachs {
+        let within = objects_within(&ineach.spec, range, around);
+        if !within.is_empty() {
+            return within;
+        }
+    }
+    vec![]
+}
+
+// The parameters and methods declared unconditionally in the body of an
+// object, as their ranges and their code, which ignores whitespace and
+// comments. Parameters given in the declaration of the object itself, as
+// the size and offset of a register, are not members of its body.
+fn members(object: &ObjectDecl<CompositeObject>, tokens: &[Token<'_>])
+           -> Vec<(ZeroRange, String)> {
+    let name = object.obj.name().span.range;
+    let span = object.obj.span().range;
+    let Some(body) = tokens.iter()
+        .find(|token|token.kind == TokenKind::LBrace
+              && name.end() <= token.range.start()
+              && token.range.end() <= span.end())
+        .map(|token|token.range.end()) else {
+            return vec![];
+        };
+    let mut members: Vec<ZeroRange> = object.spec.params.iter()
+        .filter(|param|param.cond == ExistCondition::Always)
+        .map(|param|param.obj.span().range)
+        .chain(object.spec.methods.iter()
+               .filter(|method|method.cond == ExistCondition::Always)
+               .map(|method|method.obj.span().range))
+        .filter(|range|body <= range.start())
+        .collect();
+    members.sort();
+    members.into_iter().map(|range|(range, tokens.iter()
+        .filter(|token|range.start() <= token.range.start()
+                && token.range.end() <= range.end())
+        .map(|token|token.text)
+        .collect::<Vec<_>>()
+        .join(" ")))
+        .collect()
+}
+
+/// Extract the parameters and methods that the objects within `range`, or
+/// the object around it, have in common into a new template, declared
+/// before the top-level declaration holding the objects and instantiated by
+/// them in place of the declarations
+pub fn extract_template(toplevel: &TopLevel, text: &str, range: ZeroRange)
+                        -> Option<QuickFix> {
+    if range.start() == range.end() {
+        return None;
+    }
+    let mut around = None;
+    let mut objects = objects_within(&toplevel.spec, range, &mut around);
+    for template in &toplevel.templates {
+        if objects.is_empty() {
+            objects = objects_within(&template.spec, range, &mut around);
+        }
+    }
+    if objects.is_empty() {
+        objects = vec![around?];
+    }
+    let tokens = code_tokens(text);
+    let declared: Vec<Vec<(ZeroRange, String)>> = objects.iter()
+        .map(|object|members(object, &tokens)).collect();
+    let common: Vec<&(ZeroRange, String)> = declared[0].iter()
+        .filter(|(_, code)|declared[1..].iter().all(
+            |members|members.iter().any(|(_, other)|other == code)))
+        .collect();
+    if common.is_empty() {
+        return None;
+    }
+    let name = unused_name(&tokens, "extracted_template")?;
+
+    // Parameters are kept together, and methods separated by blank lines
+    let mut body = String::new();
+    for (range, _) in &common {
+        let is_method = objects[0].spec.methods.iter()
+            .any(|method|method.obj.span().range == *range);
+        if !body.is_empty() && (is_method || body.ends_with("}\n")) {
+            body.push('\n');
+        }
+        for line in dedented(text, *range)?.split('\n') {
+            if !line.is_empty() {
+                body.push_str("    ");
+                body.push_str(line);
+            }
+            body.push('\n');
+        }
+    }
+    let kind = objects[0].obj.kind.kind;
+    let instantiates = if objects.iter()
+        .all(|object|object.obj.kind.kind == kind) {
+        format!(" is {}", kind.kind_name())
+    } else {
+        String::new()
+    };
+
+    // The template goes before the top-level declaration holding the
+    // objects
+    let first = objects[0].obj.span().range;
+    let row = toplevel.spec.objects.iter().map(|object|object.obj.span().range)
+        .chain(toplevel.templates.iter()
+               .map(|template|template.obj.span().range))
+        .find(|range|range.start() <= first.start()
+              && first.end() <= range.end())
+        .map_or(first.row_start.0, |range|range.row_start.0);
+    let mut edits = vec![(ZeroRange::from_u32(row, row, 0, 0),
+                          format!("template {}{} {{\n{}}}\n\n", name,
+                                  instantiates, body))];
+    for members in &declared {
+        let mut removed = members.iter()
+            .filter(|(_, code)|common.iter().any(|(_, other)|other == code))
+            .map(|(range, _)|remove_declaration(text, String::new(), *range)
+                 .edits.remove(0).0);
+        let first = removed.next()?;
+        let instantiation = if first.col_start.0 == 0
+            && first.row_end.0 > first.row_start.0 {
+            // Whole lines are removed, so the instantiation takes their
+            // place and indentation
+            format!("{}is {};\n", indentation(text, first.row_start.0), name)
+        } else {
+            format!("is {};", name)
+        };
+        edits.push((first, instantiation));
+        edits.extend(removed.map(|range|(range, String::new())));
+    }
+    Some(QuickFix {
+        title: format!("Extract template '{}'", name),
+        edits,
+    })
+}
+
+// The specs of a file with their methods, at any depth
+fn method_specs<'t>(spec: &'t StatementSpec,
+                    specs: &mut Vec<&'t StatementSpec>) {
+    specs.push(spec);
+    for object in &spec.objects {
+        method_specs(&object.spec, specs);
+    }
+    for ineach in &spec.ineachs {
+        method_specs(&ineach.spec, specs);
+    }
+}
+
+// The token ranges of the arguments of the call whose name is the token at
+// `at`, and the index of its closing parenthesis
+fn call_arguments(tokens: &[Token<'_>], at: usize)
+                  -> Option<(Vec<std::ops::Range<usize>>, usize)> {
+    if tokens.get(at + 1)?.kind != TokenKind::LParen {
+        return None;
+    }
+    let mut arguments = vec![];
+    let mut depth = 0;
+    let mut start = at + 2;
+    for (index, token) in tokens.iter().enumerate().skip(at + 1) {
+        match token.kind {
+            TokenKind::LParen | TokenKind::LBracket | TokenKind::LBrace =>
+                depth += 1,
+            TokenKind::RParen | TokenKind::RBracket | TokenKind::RBrace => {
+                depth -= 1;
+                if depth == 0 {
+                    if index > at + 2 {
+                        arguments.push(start..index);
+                    }
+                    return arguments.iter().all(|argument|!argument.is_empty())
+                        .then_some((arguments, index));
+                }
+            },
+            TokenKind::Comma if depth == 1 => {
+                arguments.push(start..index);
+                start = index + 1;
+            },
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Inline the method declared with its name at `declaration` into its only
+/// call, whose name is at `call`, and remove its declaration. The call must
+/// be in a method of the same object. A method returning a value must have
+/// a body of only a return statement, and is inlined as its expression;
+/// other methods must not return, and their bodies replace the call
+/// statement. Typed arguments become locals, while untyped arguments of
+/// inline methods are replaced by the expressions passed for them, as the
+/// compiler does.
+pub fn inline_method(toplevel: &TopLevel, text: &str,
+                     declaration: ZeroRange, call: ZeroRange)
+                     -> Option<QuickFix> {
+    let mut specs = vec![];
+    method_specs(&toplevel.spec, &mut specs);
+    for template in &toplevel.templates {
+        method_specs(&template.spec, &mut specs);
+    }
+    let spec = specs.into_iter().find(|spec|spec.methods.iter().any(
+        |method|method.obj.object.name.span.range == declaration))?;
+    let method = &spec.methods.iter()
+        .find(|method|method.obj.object.name.span.range == declaration)?.obj;
+    let span = method.object.span.range;
+    if method.default || method.memoized || span.contains_pos(call.start())
+        || !spec.methods.iter().any(
+            |caller|caller.obj.body.span().range.contains_pos(call.start())) {
+        return None;
+    }
+    let StatementKind::Compound(body) = method.body.as_ref() else {
+        return None;
+    };
+
+    let tokens = code_tokens(text);
+    let at = tokens.iter().position(|token|token.range == call)?;
+    if !is_variable(&tokens, at) {
+        return None;
+    }
+    let (argument_tokens, close) = call_arguments(&tokens, at)?;
+    if argument_tokens.len() != method.arguments.len() {
+        return None;
+    }
+    let argument_text = |range: &std::ops::Range<usize>|
+        &text[tokens[range.start].bytes.start..tokens[range.end - 1].bytes.end];
+    let parameters = arguments(method, &tokens, text);
+
+    // The code inlined, as byte offsets in the file
+    let body_start = offset(text, body.span.range.start())?;
+    let body_end = offset(text, body.span.range.end())?;
+    let within: Vec<usize> = (0..tokens.len())
+        .filter(|i|body_start < tokens[*i].bytes.start
+                && tokens[*i].bytes.end < body_end)
+        .collect();
+    if within.iter().any(|i|tokens[*i].kind == TokenKind::Default) {
+        return None;
+    }
+    let returns_expression = !method.returns.is_empty();
+    let (start, end) = if returns_expression {
+        let [statement] = body.statements.as_slice() else {
+            return None;
+        };
+        let StatementKind::Return(ret) = statement.as_ref() else {
+            return None;
+        };
+        if method.returns.len() != 1 || ret.ret.is_none() {
+            return None;
+        }
+        let first = within.first()?;
+        let last = within.last()?;
+        (tokens[*first + 1].bytes.start, tokens[*last - 1].bytes.end)
+    } else {
+        if within.iter().any(|i|tokens[*i].kind == TokenKind::Return) {
+            return None;
+        }
+        // The spans of variable declarations start after 'local'
+        match (within.first(), within.last()) {
+            (Some(first), Some(last)) =>
+                (tokens[*first].bytes.start, tokens[*last].bytes.end),
+            _ => (body_start + 1, body_start + 1),
+        }
+    };
+    let used: Vec<usize> = within.iter().copied()
+        .filter(|i|start <= tokens[*i].bytes.start
+                && tokens[*i].bytes.end <= end
+                && is_variable(&tokens, *i))
+        .collect();
+
+    // Arguments are replaced by what is passed for them where possible,
+    // and otherwise declared as locals
+    let mut declared_names = HashSet::default();
+    for statement in &body.statements {
+        declarations_under(statement, text, &mut declared_names);
+    }
+    let mut replaced: Vec<(&str, String)> = vec![];
+    let mut locals: Vec<(&str, String)> = vec![];
+    let mut mentioned_by_locals: Vec<&str> = vec![];
+    for (parameter, range) in parameters.iter().zip(&argument_tokens) {
+        let passed = argument_text(range);
+        let uses = used.iter()
+            .filter(|i|tokens[**i].text == parameter.name).count();
+        let changed = used.iter().any(
+            |i|tokens[*i].text == parameter.name && is_changed(&tokens, *i));
+        let mentioned: Vec<&str> = range.clone()
+            .filter(|i|is_variable(&tokens, *i))
+            .map(|i|tokens[i].text).collect();
+        if mentioned.iter().any(|name|declared_names.contains(*name)) {
+            return None;
+        }
+        let simple = range.len() == 1;
+        if passed == parameter.name && !changed {
+            continue;
+        }
+        let substituted = parameter.declaration.is_none() || returns_expression;
+        if substituted {
+            if changed || (uses > 1 && !simple) {
+                return None;
+            }
+            replaced.push((parameter.name.as_str(), if simple {
+                passed.to_string()
+            } else {
+                format!("({})", passed)
+            }));
+        } else {
+            mentioned_by_locals.extend(mentioned);
+            locals.push((parameter.name.as_str(),
+                         format!("local {} = {};",
+                                 parameter.declaration.as_ref()?, passed)));
+        }
+    }
+    // The locals must not be used by what is passed for them
+    if locals.iter().any(|(name, _)|mentioned_by_locals.contains(name)) {
+        return None;
+    }
+    let mut code = String::new();
+    let mut position = start;
+    for i in &used {
+        if let Some((_, replacement)) = replaced.iter()
+            .find(|(name, _)|*name == tokens[*i].text) {
+                code.push_str(&text[position..tokens[*i].bytes.start]);
+                code.push_str(replacement);
+                position = tokens[*i].bytes.end;
+            }
+    }
+    code.push_str(&text[position..end]);
+
+    let name = &method.object.name.val;
+    let mut edits = vec![];
+    if returns_expression {
+        let simple = !code.contains(|c: char|!c.is_alphanumeric() && c != '_');
+        edits.push((ZeroRange::from_positions(call.start(),
+                                              tokens[close].range.end()),
+                    if simple { code } else { format!("({})", code) }));
+    } else {
+        // The body replaces the call statement, in a block of its own if
+        // it declares locals, or if it is several statements where one
+        // statement is expected
+        let semi = tokens.get(close + 1)
+            .filter(|token|token.kind == TokenKind::SemiColon)?;
+        let statement = ZeroRange::from_positions(call.start(),
+                                                  semi.range.end());
+        let before = at.checked_sub(1).map(|i|tokens[i].kind);
+        if !before.is_none_or(|kind|matches!(
+            kind, TokenKind::SemiColon | TokenKind::LBrace | TokenKind::RBrace
+                | TokenKind::RParen | TokenKind::Else | TokenKind::Colon)) {
+            return None;
+        }
+        let in_block = before.is_none_or(|kind|matches!(
+            kind, TokenKind::SemiColon | TokenKind::LBrace
+                | TokenKind::RBrace));
+        let declares = body.statements.iter().any(|statement|matches!(
+            statement.as_ref(), StatementKind::VariableDecl(_)));
+        let indent = indentation(text, call.row_start.0);
+        let method_indent = indentation(text, span.row_start.0);
+        let statement_indent = within.first().map_or(
+            "", |first|indentation(text, tokens[*first].range.row_start.0));
+        let step = statement_indent.strip_prefix(method_indent)
+            .filter(|step|!step.is_empty())
+            .unwrap_or("    ");
+        let lines: Vec<&str> = code.split('\n').enumerate()
+            .map(|(i, line)|if i == 0 {
+                line
+            } else {
+                line.strip_prefix(statement_indent)
+                    .unwrap_or_else(||line.trim_start())
+            })
+            .collect();
+        if body.statements.is_empty() && in_block {
+            edits.push(remove_declaration(text, String::new(), statement)
+                       .edits.remove(0));
+        } else if !locals.is_empty() || declares
+            || (body.statements.len() != 1 && !in_block) {
+            let mut block = "{\n".to_string();
+            for line in locals.iter().map(|(_, local)|local.as_str()).chain(
+                lines.into_iter().filter(|_|!body.statements.is_empty())) {
+                if !line.is_empty() {
+                    block.push_str(indent);
+                    block.push_str(step);
+                    block.push_str(line);
+                }
+                block.push('\n');
+            }
+            block.push_str(indent);
+            block.push('}');
+            edits.push((statement, block));
+        } else {
+            edits.push((statement, lines.join(&format!("\n{}", indent))));
+        }
+    }
+    edits.push(remove_declaration(text, String::new(), span).edits.remove(0));
+    Some(QuickFix {
+        title: format!("Inline method '{}'", name),
+        edits,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::analysis::quick_fixes::test::{apply, toplevel};
+
+    // The range from the first occurrence of `from` to the end of the first
+    // occurrence of `to` after it
+    fn selection(source: &str, from: &str, to: &str) -> ZeroRange {
+        let position = |at: usize|{
+            let before = &source[..at];
+            let row = before.matches('\n').count() as u32;
+            let col = before.rsplit('\n').next().unwrap().chars().count();
+            ZeroPosition::from_u32(row, col as u32)
+        };
+        let start = source.find(from).unwrap();
+        let end = start + source[start..].find(to).unwrap() + to.len();
+        ZeroRange::from_positions(position(start), position(end))
+    }
+
+    fn extract(source: &str, from: &str, to: &str) -> Option<String> {
+        let fix = extract_method(&toplevel(source), source,
+                                 selection(source, from, to))?;
+        Some(apply(source, &fix))
+    }
+
+    // Inline the method named `name` into its call on row `row`
+    fn inline(source: &str, name: &str, row: u32) -> Option<String> {
+        let range = |row: usize|{
+            let line = source.split('\n').nth(row).unwrap();
+            let col = line.find(&format!("{}(", name)).unwrap() as u32;
+            ZeroRange::from_u32(row as u32, row as u32, col,
+                                col + name.len() as u32)
+        };
+        let declaration = source.split('\n')
+            .position(|line|line.contains(&format!("method {}(", name)))
+            .unwrap();
+        let fix = inline_method(&toplevel(source), source,
+                                range(declaration), range(row as usize))?;
+        Some(apply(source, &fix))
+    }
+
+    fn extract_into_template(source: &str, from: &str, to: &str)
+                             -> Option<String> {
+        let fix = extract_template(&toplevel(source), source,
+                                   selection(source, from, to))?;
+        Some(apply(source, &fix))
+    }
+
+    #[test]
+    fn test_extract_method() {
+        let source = "\
+dml 1.4;
+device test;
+bank regs {
+    method write(uint64 value) {
+        local int count = 0;
+        local uint64 *p = &count;
+        log info: \"writing\";
+        count += value;
+        count++;
+        log info: \"%d\", count;
+    }
+}
+";
+        assert_eq!(extract(source, "log info: \"writing", "count++;")
+                   .unwrap(), "\
+dml 1.4;
+device test;
+bank regs {
+    method write(uint64 value) {
+        local int count = 0;
+        local uint64 *p = &count;
+        count = extracted(count, value);
+        log info: \"%d\", count;
+    }
+
+    method extracted(int count, uint64 value) -> (int) {
+        log info: \"writing\";
+        count += value;
+        count++;
+        return count;
+    }
+}
+");
+        // Only the statements within the selection are extracted
+        assert_eq!(extract(source, "\"writing\"", "count++;"), None);
+        assert_eq!(extract(source, "log info: \"w", "count +="), None);
+    }
+
+    #[test]
+    fn test_extract_method_nested() {
+        let source = "\
+dml 1.4;
+device test;
+template t {
+    shared method f(int n) -> (int) throws {
+        local int sum = 0;
+        for (local int i = 0; i < n; i++) {
+            local int x = i * 2;
+            if (x > 3)
+                break;
+            sum += x;
+        }
+        return sum;
+    }
+}
+";
+        assert_eq!(extract(source, "for (", "        }").unwrap(), "\
+dml 1.4;
+device test;
+template t {
+    shared method f(int n) -> (int) throws {
+        local int sum = 0;
+        sum = extracted(n, sum);
+        return sum;
+    }
+
+    shared method extracted(int n, int sum) -> (int) throws {
+        for (local int i = 0; i < n; i++) {
+            local int x = i * 2;
+            if (x > 3)
+                break;
+            sum += x;
+        }
+        return sum;
+    }
+}
+");
+        assert_eq!(extract(source, "x > 3", "break;"), None);
+        // The break would leave the loop around the statements
+        assert_eq!(extract(source, "local int x", "sum += x;"), None);
+        assert_eq!(extract(source, "local int sum", "return sum;"), None);
+    }
+
+    #[test]
+    fn test_extract_template() {
+        let source = "\
+dml 1.4;
+device test;
+bank regs {
+    register a size 4 @ 0x0 {
+        param init_val = 0;
+        method read() -> (uint64) {
+            log info: \"read\";
+            return this.val;
+        }
+    }
+    register b size 4 @ 0x4 {
+        param init_val = 0;
+        method read() -> (uint64) {
+            log info: \"read\";
+            return this.val;
+        }
+        param desc = \"b\";
+    }
+}
+";
+        assert_eq!(extract_into_template(source, "register a", "\"b\";\n    }")
+                   .unwrap(), "\
+dml 1.4;
+device test;
+template extracted_template is register {
+    param init_val = 0;
+
+    method read() -> (uint64) {
+        log info: \"read\";
+        return this.val;
+    }
+}
+
+bank regs {
+    register a size 4 @ 0x0 {
+        is extracted_template;
+    }
+    register b size 4 @ 0x4 {
+        is extracted_template;
+        param desc = \"b\";
+    }
+}
+");
+        // Outside of any object, there is nothing to extract
+        assert_eq!(extract_into_template(source, "device", "test;"), None);
+    }
+
+    #[test]
+    fn test_inline_method() {
+        let source = "\
+dml 1.4;
+device test;
+bank regs {
+    method write(uint64 value) {
+        if (value > 3)
+            check(value + 1, value);
+        log info: \"%d\", twice(value);
+    }
+    method check(int n, uint64 value) {
+        local int limit = 8;
+        if (n > limit)
+            log error: \"%d\", value;
+    }
+    inline method twice(inline x) -> (uint64) {
+        return x * 2;
+    }
+}
+";
+        assert_eq!(inline(source, "check", 5).unwrap(), "\
+dml 1.4;
+device test;
+bank regs {
+    method write(uint64 value) {
+        if (value > 3)
+            {
+                local int n = value + 1;
+                local int limit = 8;
+                if (n > limit)
+                    log error: \"%d\", value;
+            }
+        log info: \"%d\", twice(value);
+    }
+    inline method twice(inline x) -> (uint64) {
+        return x * 2;
+    }
+}
+");
+        assert_eq!(inline(source, "twice", 6).unwrap(), "\
+dml 1.4;
+device test;
+bank regs {
+    method write(uint64 value) {
+        if (value > 3)
+            check(value + 1, value);
+        log info: \"%d\", (value * 2);
+    }
+    method check(int n, uint64 value) {
+        local int limit = 8;
+        if (n > limit)
+            log error: \"%d\", value;
+    }
+}
+");
+    }
+
+    #[test]
+    fn test_inline_method_refused() {
+        let source = "\
+dml 1.4;
+device test;
+bank regs {
+    method write(uint64 value) {
+        log_value(value);
+        count(value);
+    }
+    method log_value(uint64 value) {
+        log info: \"%d\", value;
+    }
+    method count(uint64 value) {
+        if (value == 0)
+            return;
+        value++;
+    }
+}
+register r size 4 @ 0x0 {
+    method read() -> (uint64) {
+        log_value(0);
+        return 0;
+    }
+}
+";
+        assert_eq!(inline(source, "log_value", 4).unwrap(), "\
+dml 1.4;
+device test;
+bank regs {
+    method write(uint64 value) {
+        log info: \"%d\", value;
+        count(value);
+    }
+    method count(uint64 value) {
+        if (value == 0)
+            return;
+        value++;
+    }
+}
+register r size 4 @ 0x0 {
+    method read() -> (uint64) {
+        log_value(0);
+        return 0;
+    }
+}
+");
+        // The call is in another object
+        assert_eq!(inline(source, "log_value", 18), None);
+        // The method returns early
+        assert_eq!(inline(source, "count", 5), None);
+    }
+}
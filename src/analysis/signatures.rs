@@ -0,0 +1,339 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Signatures of methods and templates, as shown while writing calls of
+//! methods and lists of instantiated templates, read from the source text of
+//! their declarations
+use std::collections::HashMap;
+
+use crate::analysis::parsing::lexer::TokenKind;
+use crate::analysis::parsing::tree::{ZeroPosition, ZeroRange};
+use crate::analysis::quick_fixes::{code_tokens, is_comment,
+                                   tokens_with_comments, Token};
+
+/// A signature, with the byte ranges of its parameters in its label
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    pub label: String,
+    pub documentation: Option<String>,
+    pub parameters: Vec<SignatureParameter>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureParameter {
+    pub label: std::ops::Range<usize>,
+    pub documentation: Option<String>,
+}
+
+/// What is being written at a position
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CallSite {
+    /// The arguments of a call of the method named at `name`
+    Method { name: ZeroRange, argument: u32 },
+    /// A list of instantiated templates, naming `template` at the position
+    Templates { template: Option<String> },
+}
+
+/// The call or template list that a position is in the parentheses of
+pub fn call_site(text: &str, position: ZeroPosition) -> Option<CallSite> {
+    let tokens: Vec<Token<'_>> = code_tokens(text).into_iter()
+        .take_while(|token|(token.range.row_start, token.range.col_start)
+                    < (position.row, position.col))
+        .collect();
+    let mut depth = 0;
+    let mut argument = 0;
+    // The name in the list that the position is at
+    let mut current: Option<Option<String>> = None;
+    for (index, token) in tokens.iter().enumerate().rev() {
+        match token.kind {
+            TokenKind::RParen | TokenKind::RBracket => depth += 1,
+            TokenKind::LBracket if depth > 0 => depth -= 1,
+            TokenKind::LParen if depth > 0 => depth -= 1,
+            TokenKind::LParen => {
+                let before = index.checked_sub(1).map(|i|&tokens[i])?;
+                return match before.kind {
+                    TokenKind::Identifier => Some(CallSite::Method {
+                        name: before.range, argument,
+                    }),
+                    TokenKind::Is => Some(CallSite::Templates {
+                        template: current.flatten(),
+                    }),
+                    _ => None,
+                };
+            },
+            TokenKind::Comma if depth == 0 => {
+                argument += 1;
+                current = current.or(Some(None));
+            },
+            TokenKind::Identifier if depth == 0 && current.is_none() =>
+                current = Some(Some(token.text.to_string())),
+            TokenKind::LBrace | TokenKind::RBrace | TokenKind::SemiColon
+                | TokenKind::LBracket => return None,
+            _ => (),
+        }
+    }
+    None
+}
+
+// Whether a token may come before the name in a declaration
+fn is_declaration_keyword(kind: TokenKind) -> bool {
+    matches!(kind, TokenKind::Method | TokenKind::Shared | TokenKind::Inline
+             | TokenKind::Independent | TokenKind::Startup
+             | TokenKind::Memoized | TokenKind::Param | TokenKind::Template)
+}
+
+// The text of a comment, without its delimiters
+fn comment_lines(comment: &str) -> Vec<String> {
+    let comment = comment.trim_start_matches('/');
+    let comment = comment.strip_prefix('*').map_or(
+        comment, |comment|comment.trim_end_matches('/')
+            .trim_end_matches('*'));
+    comment.lines()
+        .map(|line|line.trim().trim_start_matches('*').trim().to_string())
+        .collect()
+}
+
+// The documentation of a declaration, from the comments right before it.
+// Lines starting with `@param <name>` document the parameter named so.
+fn doc_comments(tokens: &[Token<'_>], name: usize)
+                 -> (Option<String>, HashMap<String, String>) {
+    let mut first = name;
+    while first > 0 && is_declaration_keyword(tokens[first - 1].kind) {
+        first -= 1;
+    }
+    let mut comments = vec![];
+    let mut row = tokens[first].range.row_start.0;
+    for token in tokens[..first].iter().rev() {
+        if !is_comment(token.kind) || token.range.row_end.0 + 1 < row {
+            break;
+        }
+        row = token.range.row_start.0;
+        comments.push(token.text);
+    }
+    let mut lines = vec![];
+    let mut parameters = HashMap::new();
+    for line in comments.into_iter().rev().flat_map(comment_lines) {
+        let parameter = line.strip_prefix("@param").filter(
+            |rest|rest.starts_with(char::is_whitespace));
+        if let Some(parameter) = parameter {
+            let mut words = parameter.trim().splitn(2, char::is_whitespace);
+            if let Some(name) = words.next() {
+                parameters.insert(name.to_string(),
+                                  words.next().unwrap_or("").trim()
+                                  .to_string());
+            }
+        } else {
+            lines.push(line);
+        }
+    }
+    let documentation = lines.join("\n").trim().to_string();
+    ((!documentation.is_empty()).then_some(documentation), parameters)
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// The index of the parenthesis closing the one at `open`
+fn closing_paren(tokens: &[Token<'_>], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (index, token) in tokens.iter().enumerate().skip(open) {
+        match token.kind {
+            TokenKind::LParen => depth += 1,
+            TokenKind::RParen => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(index);
+                }
+            },
+            _ => (),
+        }
+    }
+    None
+}
+
+/// The signature of the method whose declaration names it at `name` in
+/// `text`
+pub fn method_signature(text: &str, name: ZeroRange) -> Option<Signature> {
+    let tokens = tokens_with_comments(text);
+    let code: Vec<usize> = (0..tokens.len())
+        .filter(|index|!is_comment(tokens[*index].kind)).collect();
+    let code_tokens: Vec<Token<'_>> = code.iter()
+        .map(|index|tokens[*index].clone()).collect();
+    let name_index = code_tokens.iter()
+        .position(|token|token.range == name)?;
+    let (documentation, parameter_docs) = doc_comments(
+        &tokens, code[name_index]);
+    let open = name_index + 1;
+    if code_tokens.get(open)?.kind != TokenKind::LParen {
+        return None;
+    }
+    let close = closing_paren(&code_tokens, open)?;
+
+    let mut label = format!("method {}(", code_tokens[name_index].text);
+    let mut parameters = vec![];
+    let mut start = open + 1;
+    while start < close {
+        let mut end = start;
+        let mut depth = 0;
+        while end < close && !(depth == 0
+                               && code_tokens[end].kind == TokenKind::Comma) {
+            match code_tokens[end].kind {
+                TokenKind::LParen | TokenKind::LBracket => depth += 1,
+                TokenKind::RParen | TokenKind::RBracket => depth -= 1,
+                _ => (),
+            }
+            end += 1;
+        }
+        if !parameters.is_empty() {
+            label.push_str(", ");
+        }
+        let parameter_start = label.len();
+        label.push_str(&collapse_whitespace(
+            &text[code_tokens[start].bytes.start
+                  ..code_tokens[end - 1].bytes.end]));
+        // The name of an argument is its last token
+        let argument = code_tokens[end - 1].text;
+        parameters.push(SignatureParameter {
+            label: parameter_start..label.len(),
+            documentation: parameter_docs.get(argument).cloned()
+                .filter(|doc|!doc.is_empty()),
+        });
+        start = end + 1;
+    }
+    label.push(')');
+
+    let mut next = close + 1;
+    if code_tokens.get(next).map(|token|token.kind) == Some(TokenKind::Arrow)
+        && code_tokens.get(next + 1).map(|token|token.kind)
+        == Some(TokenKind::LParen) {
+            let returns_close = closing_paren(&code_tokens, next + 1)?;
+            label.push_str(" -> ");
+            label.push_str(&collapse_whitespace(
+                &text[code_tokens[next + 1].bytes.start
+                      ..code_tokens[returns_close].bytes.end]));
+            next = returns_close + 1;
+        }
+    if code_tokens.get(next).map(|token|token.kind) == Some(TokenKind::Throws) {
+        label.push_str(" throws");
+    }
+    Some(Signature { label, documentation, parameters })
+}
+
+/// The signature of the template declared with the name at `name` in
+/// `text`, listing the parameters it declares, named at `parameters`
+pub fn template_signature(text: &str, name: ZeroRange,
+                          parameters: &[ZeroRange]) -> Option<Signature> {
+    let tokens = tokens_with_comments(text);
+    let name_index = tokens.iter().position(|token|token.range == name)?;
+    let (documentation, _) = doc_comments(&tokens, name_index);
+    let mut label = format!("template {}", tokens[name_index].text);
+    let mut signature_parameters = vec![];
+    let mut parameters: Vec<usize> = parameters.iter()
+        .filter_map(|parameter|tokens.iter()
+                    .position(|token|token.range == *parameter))
+        .collect();
+    parameters.sort();
+    for (number, parameter) in parameters.into_iter().enumerate() {
+        label.push_str(if number == 0 { " { " } else { "; " });
+        label.push_str("param ");
+        let start = label.len();
+        label.push_str(tokens[parameter].text);
+        signature_parameters.push(SignatureParameter {
+            label: start..label.len(),
+            documentation: doc_comments(&tokens, parameter).0,
+        });
+    }
+    if !signature_parameters.is_empty() {
+        label.push_str("; }");
+    }
+    Some(Signature {
+        label,
+        documentation,
+        parameters: signature_parameters,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SOURCE: &str = "dml 1.4;
+device test;
+// Write a value
+// @param value the value to write
+// @param mask which bits to write
+method write(uint64 value, uint64 mask) -> (bool, int) throws {
+    return (true, 0);
+}
+/// Registers with a width
+template wide {
+    /* The width, in bits */
+    param width;
+    param other = 2;
+}
+";
+
+    fn range_of(text: &str, needle: &str, nth: usize) -> ZeroRange {
+        let (row, line) = text.lines().enumerate()
+            .filter(|(_, line)|line.contains(needle))
+            .nth(nth).unwrap();
+        let col = line[..line.find(needle).unwrap()].chars().count() as u32;
+        ZeroRange::from_u32(row as u32, row as u32, col,
+                            col + needle.chars().count() as u32)
+    }
+
+    #[test]
+    fn test_call_site() {
+        let text = "method m() {\n    write(a, f(b, c), \n}\nbank b is (x, y";
+        let at = |row, col|call_site(text, ZeroPosition::from_u32(row, col));
+        assert_eq!(at(1, 10), Some(CallSite::Method {
+            name: ZeroRange::from_u32(1, 1, 4, 9), argument: 0,
+        }));
+        assert_eq!(at(1, 22), Some(CallSite::Method {
+            name: ZeroRange::from_u32(1, 1, 4, 9), argument: 2,
+        }));
+        assert_eq!(at(1, 17), Some(CallSite::Method {
+            name: ZeroRange::from_u32(1, 1, 13, 14), argument: 1,
+        }));
+        assert_eq!(at(0, 11), None);
+        assert_eq!(at(3, 12), Some(CallSite::Templates {
+            template: Some("x".to_string()),
+        }));
+        assert_eq!(at(3, 14), Some(CallSite::Templates { template: None }));
+        assert_eq!(at(3, 15), Some(CallSite::Templates {
+            template: Some("y".to_string()),
+        }));
+    }
+
+    #[test]
+    fn test_method_signature() {
+        let signature = method_signature(
+            SOURCE, range_of(SOURCE, "write", 2)).unwrap();
+        assert_eq!(signature.label, "method write(uint64 value, uint64 mask) \
+                                     -> (bool, int) throws");
+        assert_eq!(signature.documentation.as_deref(), Some("Write a value"));
+        let parameters: Vec<(&str, Option<&str>)> = signature.parameters
+            .iter().map(|parameter|(&signature.label[parameter.label.clone()],
+                                    parameter.documentation.as_deref()))
+            .collect();
+        assert_eq!(parameters, vec![
+            ("uint64 value", Some("the value to write")),
+            ("uint64 mask", Some("which bits to write")),
+        ]);
+    }
+
+    #[test]
+    fn test_template_signature() {
+        let signature = template_signature(
+            SOURCE, range_of(SOURCE, "wide", 0),
+            &[range_of(SOURCE, "other", 0), range_of(SOURCE, "width", 2)])
+            .unwrap();
+        assert_eq!(signature.label,
+                   "template wide { param width; param other; }");
+        assert_eq!(signature.documentation.as_deref(),
+                   Some("Registers with a width"));
+        assert_eq!(signature.parameters[0].documentation.as_deref(),
+                   Some("The width, in bits"));
+        assert_eq!(signature.parameters[1].documentation, None);
+    }
+}
@@ -0,0 +1,339 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Knowledge of how the DML standard library differs between Simics API
+//! versions, and diagnostics for uses of templates and interfaces that are
+//! not available in (or deprecated by) the version in use
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use lazy_static::lazy_static;
+use lsp_types::DiagnosticSeverity;
+use regex::Regex;
+
+use crate::analysis::DMLError;
+use crate::analysis::structure::expressions::DMLString;
+use crate::analysis::structure::objects::CompObjectKind;
+use crate::analysis::structure::toplevel::{StatementSpecStatement,
+                                           TopLevel};
+
+/// A Simics API version, as given to dmlc with '--simics-api'
+/// NOTE: Only the major version matters to the standard library
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SimicsApiVersion(pub u32);
+
+impl fmt::Display for SimicsApiVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for SimicsApiVersion {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.trim().split('.').next()
+            .and_then(|major|major.parse().ok())
+            .map(SimicsApiVersion)
+            .ok_or_else(||format!("Invalid Simics API version '{}'", s))
+    }
+}
+
+impl SimicsApiVersion {
+    /// Obtain the API version from a list of dmlc flags, if specified
+    pub fn from_dmlc_flags<S: AsRef<str>>(flags: &[S])
+                                          -> Option<SimicsApiVersion> {
+        let mut flags = flags.iter().map(AsRef::as_ref);
+        while let Some(flag) = flags.next() {
+            if let Some(version) = flag.strip_prefix("--simics-api=") {
+                return version.parse().ok();
+            }
+            if flag == "--simics-api" {
+                return flags.next().and_then(|v|v.parse().ok());
+            }
+        }
+        None
+    }
+
+    /// Guess the API version from the location of the DML standard library,
+    /// which is normally inside a Simics base package directory named
+    /// after its version (e.g. 'simics-6.0.185')
+    pub fn from_stdlib_path(path: &Path) -> Option<SimicsApiVersion> {
+        lazy_static! {
+            static ref PACKAGE_DIR: Regex =
+                Regex::new(r"^simics(?:-base)?-(\d+)\.\d+").unwrap();
+        }
+        path.ancestors()
+            .filter_map(|p|p.file_name()?.to_str())
+            .find_map(|name|PACKAGE_DIR.captures(name))
+            .and_then(|caps|caps[1].parse().ok())
+            .map(SimicsApiVersion)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StdlibItemKind {
+    Template,
+    Interface,
+}
+
+impl StdlibItemKind {
+    pub fn kind_name(&self) -> &'static str {
+        match self {
+            StdlibItemKind::Template => "template",
+            StdlibItemKind::Interface => "interface",
+        }
+    }
+}
+
+/// The availability of a standard library template or interface across
+/// Simics API versions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StdlibItem {
+    pub name: &'static str,
+    pub kind: StdlibItemKind,
+    /// First API version providing the item, if not always available
+    pub since: Option<u32>,
+    /// First API version in which use of the item is discouraged
+    pub deprecated_in: Option<u32>,
+    /// First API version no longer providing the item
+    pub removed_in: Option<u32>,
+    /// What to use instead, when deprecated or removed
+    pub replacement: Option<&'static str>,
+}
+
+/// How a standard library item relates to a particular API version
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Availability {
+    Available,
+    NotYetAvailable(u32),
+    Deprecated(u32),
+    Removed(u32),
+}
+
+impl StdlibItem {
+    pub fn availability(&self, version: SimicsApiVersion) -> Availability {
+        let SimicsApiVersion(version) = version;
+        if let Some(since) = self.since.filter(|since|version < *since) {
+            return Availability::NotYetAvailable(since);
+        }
+        if let Some(removed) = self.removed_in.filter(|r|version >= *r) {
+            return Availability::Removed(removed);
+        }
+        if let Some(deprecated) = self.deprecated_in.filter(|d|version >= *d) {
+            return Availability::Deprecated(deprecated);
+        }
+        Availability::Available
+    }
+}
+
+const fn template(name: &'static str) -> StdlibItem {
+    StdlibItem {
+        name,
+        kind: StdlibItemKind::Template,
+        since: None,
+        deprecated_in: None,
+        removed_in: None,
+        replacement: None,
+    }
+}
+
+const fn interface(name: &'static str) -> StdlibItem {
+    StdlibItem {
+        kind: StdlibItemKind::Interface,
+        ..template(name)
+    }
+}
+
+/// Standard library items whose availability depends on the API version,
+/// items not listed here are assumed to be available in every version
+pub const STDLIB_ITEMS: &[StdlibItem] = &[
+    StdlibItem {
+        since: Some(6),
+        ..template("bank_transaction")
+    },
+    StdlibItem {
+        since: Some(6),
+        ..template("init_as_subobj")
+    },
+    StdlibItem {
+        removed_in: Some(7),
+        replacement: Some("bank_transaction"),
+        ..template("bank_io_memory")
+    },
+    StdlibItem {
+        removed_in: Some(7),
+        replacement: Some("map_target"),
+        ..template("function_io_memory")
+    },
+    StdlibItem {
+        removed_in: Some(7),
+        replacement: Some("map_target"),
+        ..template("function_mapped_bank")
+    },
+    StdlibItem {
+        deprecated_in: Some(7),
+        replacement: Some("transaction"),
+        ..interface("io_memory")
+    },
+];
+
+pub fn stdlib_item(kind: StdlibItemKind, name: &str)
+                   -> Option<&'static StdlibItem> {
+    STDLIB_ITEMS.iter().find(|item|item.kind == kind && item.name == name)
+}
+
+fn version_error(item: &StdlibItem,
+                 name: &DMLString,
+                 version: SimicsApiVersion) -> Option<DMLError> {
+    let (description, severity) = match item.availability(version) {
+        Availability::Available => return None,
+        Availability::NotYetAvailable(since) => (
+            format!("The {} '{}' is not available before Simics API {} \
+                     (API {} is in use)",
+                    item.kind.kind_name(), item.name, since, version),
+            DiagnosticSeverity::ERROR),
+        Availability::Removed(removed) => (
+            format!("The {} '{}' was removed in Simics API {} \
+                     (API {} is in use)",
+                    item.kind.kind_name(), item.name, removed, version),
+            DiagnosticSeverity::ERROR),
+        Availability::Deprecated(deprecated) => (
+            format!("The {} '{}' is deprecated since Simics API {}",
+                    item.kind.kind_name(), item.name, deprecated),
+            DiagnosticSeverity::WARNING),
+    };
+    let description = match item.replacement {
+        Some(replacement) => format!("{}; use '{}' instead",
+                                     description, replacement),
+        None => description,
+    };
+    Some(DMLError {
+        span: name.span,
+        description,
+        severity: Some(severity),
+        related: vec![],
+    })
+}
+
+/// All statements of a file, including those inside templates
+pub fn toplevel_statements(toplevel: &TopLevel)
+                           -> Vec<StatementSpecStatement<'_>> {
+    let mut statements = toplevel.spec.all_statements();
+    for template in &toplevel.templates {
+        statements.extend(template.spec.all_statements());
+    }
+    statements
+}
+
+/// Report uses of standard library templates and interfaces in a file that
+/// are unavailable or deprecated in the given API version
+pub fn stdlib_version_errors(toplevel: &TopLevel,
+                             version: SimicsApiVersion) -> Vec<DMLError> {
+    let mut errors = vec![];
+    for statement in toplevel_statements(toplevel) {
+        let uses: Vec<(StdlibItemKind, &DMLString)> = match statement {
+            StatementSpecStatement::Instantiation(inst) =>
+                inst.obj.names.iter()
+                .map(|name|(StdlibItemKind::Template, name)).collect(),
+            StatementSpecStatement::Object(obj) if matches!(
+                obj.obj.kind.kind,
+                CompObjectKind::Implement | CompObjectKind::Interface) =>
+                vec![(StdlibItemKind::Interface, &obj.obj.object.name)],
+            _ => vec![],
+        };
+        for (kind, name) in uses {
+            if let Some(error) = stdlib_item(kind, &name.val).and_then(
+                |item|version_error(item, name, version)) {
+                errors.push(error);
+            }
+        }
+    }
+    errors
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::PathBuf;
+    use crate::analysis::{parse_file, FileSpec};
+    use crate::vfs::TextFile;
+
+    fn toplevel(source: &str) -> TopLevel {
+        let path = PathBuf::from("test.dml");
+        let file = TextFile::from_str(source).unwrap();
+        let filespec = FileSpec { path: &path, file: &file };
+        let (ast, _, _) = parse_file(&path, filespec).unwrap();
+        TopLevel::from_ast(&ast, &mut vec![], filespec)
+    }
+
+    #[test]
+    fn test_parse_api_version() {
+        assert_eq!("7".parse(), Ok(SimicsApiVersion(7)));
+        assert_eq!("4.8".parse(), Ok(SimicsApiVersion(4)));
+        assert!("latest".parse::<SimicsApiVersion>().is_err());
+        assert_eq!(SimicsApiVersion::from_dmlc_flags(
+            &["-I", "lib", "--simics-api=6"]), Some(SimicsApiVersion(6)));
+        assert_eq!(SimicsApiVersion::from_dmlc_flags(
+            &["--simics-api", "7"]), Some(SimicsApiVersion(7)));
+        assert_eq!(SimicsApiVersion::from_dmlc_flags(&["-g"]), None);
+    }
+
+    #[test]
+    fn test_detect_from_stdlib_path() {
+        assert_eq!(SimicsApiVersion::from_stdlib_path(Path::new(
+            "/opt/simics/simics-7.12.0/linux64/bin/dml/1.4/dml-builtins.dml")),
+                   Some(SimicsApiVersion(7)));
+        assert_eq!(SimicsApiVersion::from_stdlib_path(Path::new(
+            "/opt/simics-base-6.0.185/linux64/bin/dml/1.4")),
+                   Some(SimicsApiVersion(6)));
+        assert_eq!(SimicsApiVersion::from_stdlib_path(Path::new(
+            "/home/user/dml/lib/1.4/dml-builtins.dml")), None);
+    }
+
+    #[test]
+    fn test_availability() {
+        let item = stdlib_item(StdlibItemKind::Template,
+                               "function_mapped_bank").unwrap();
+        assert_eq!(item.availability(SimicsApiVersion(6)),
+                   Availability::Available);
+        assert_eq!(item.availability(SimicsApiVersion(7)),
+                   Availability::Removed(7));
+        let item = stdlib_item(StdlibItemKind::Template,
+                               "bank_transaction").unwrap();
+        assert_eq!(item.availability(SimicsApiVersion(5)),
+                   Availability::NotYetAvailable(6));
+        assert!(stdlib_item(StdlibItemKind::Interface,
+                            "function_mapped_bank").is_none());
+    }
+
+    #[test]
+    fn test_version_errors() {
+        let top = toplevel("dml 1.4;
+device test;
+bank regs is function_mapped_bank {
+    register r size 4 @ 0x0;
+}
+implement io_memory {}
+template t is (bank_io_memory, sticky) {}
+");
+        for version in [5, 6] {
+            assert!(stdlib_version_errors(&top, SimicsApiVersion(version))
+                    .is_empty());
+        }
+        let mut errors: Vec<(String, DiagnosticSeverity)> =
+            stdlib_version_errors(&top, SimicsApiVersion(7)).into_iter()
+            .map(|e|(e.description, e.severity.unwrap())).collect();
+        errors.sort_by(|(d1, _), (d2, _)|d1.cmp(d2));
+        assert_eq!(errors, vec![
+            ("The interface 'io_memory' is deprecated since Simics API 7; \
+              use 'transaction' instead".to_string(),
+             DiagnosticSeverity::WARNING),
+            ("The template 'bank_io_memory' was removed in Simics API 7 \
+              (API 7 is in use); use 'bank_transaction' instead".to_string(),
+             DiagnosticSeverity::ERROR),
+            ("The template 'function_mapped_bank' was removed in Simics API \
+              7 (API 7 is in use); use 'map_target' instead".to_string(),
+             DiagnosticSeverity::ERROR),
+        ]);
+    }
+}
@@ -11,6 +11,7 @@ use crate::analysis::parsing::lexer::TokenKind;
 use crate::analysis::parsing::tree::{LeafToken, ZeroRange,
                                      ZeroSpan, TreeElement};
 use crate::analysis::FileSpec;
+use crate::analysis::intern::Name;
 use crate::analysis::structure::types;
 use crate::analysis::structure::types::DMLType;
 
@@ -568,7 +569,7 @@ pub enum ExpressionKind {
     // TODO: f64 is annoying, as it does not implement Eq or Ord
     // likely we have to use another, safer, float-like struct
     // perhaps a custom-made one
-    FloatLiteral(DMLString),
+    FloatLiteral(Value<String>),
     FunctionCall(FunctionCall),
     CastExpression(CastExpression),
     NewExpression(NewExpression),
@@ -798,16 +799,24 @@ impl ExpressionKind {
     }
 }
 
-pub type DMLString = Value<String>;
+pub type DMLString = Value<Name>;
 
 impl DMLString {
     pub fn from_token(token: &LeafToken,
                       file: FileSpec<'_>) -> Option<DMLString> {
         Some(DMLString {
-            val: token.read_leaf(file.file)?,
+            val: Name::from(token.read_leaf(file.file)?),
             span: ZeroSpan::from_range(token.range(), file.path),
         })
     }
+
+    /// The name as a string value, e.g. to make a string literal of it
+    pub fn to_string_value(&self) -> Value<String> {
+        Value {
+            val: self.val.to_string(),
+            span: self.span,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -4,6 +4,7 @@ use crate::analysis::parsing::lexer::TokenKind;
 use crate::analysis::parsing::{misc, structure};
 use crate::analysis::parsing::tree::{LeafToken, ZeroRange,
                                      ZeroSpan, TreeElement};
+use crate::analysis::intern::Name;
 use crate::analysis::structure::expressions::{Expression, Value, DMLString,
                                               ExpressionKind};
 use crate::analysis::structure::statements::{Statement, StatementKind};
@@ -1827,8 +1828,8 @@ fn to_register<'a>(content: &structure::RegisterContent,
                 obj.statements.statements.push(
                     DMLStatement::Object(DMLObject::Parameter(Parameter {
                         object: DMLObjectCommon {
-                            name: Value::<String> {
-                                val: "offset".to_string(),
+                            name: DMLString {
+                                val: Name::from("offset"),
                                 span: ZeroSpan::from_range(
                                     addrtok.range(), file.path),
                             },
@@ -1865,8 +1866,8 @@ fn to_field<'a>(content: &structure::FieldContent,
                 obj.statements.statements.push(
                     DMLStatement::Object(DMLObject::Parameter(Parameter {
                         object: DMLObjectCommon {
-                            name: Value::<String> {
-                                val: "msb".to_string(),
+                            name: DMLString {
+                                val: Name::from("msb"),
                                 span: ZeroSpan::from_range(at.range(), file.path),
                             },
                             span: ZeroSpan::from_range(
@@ -1881,8 +1882,8 @@ fn to_field<'a>(content: &structure::FieldContent,
                 obj.statements.statements.push(
                     DMLStatement::Object(DMLObject::Parameter(Parameter {
                         object: DMLObjectCommon {
-                            name: Value::<String> {
-                                val: "lsb".to_string(),
+                            name: DMLString {
+                                val: Name::from("lsb"),
                                 span: ZeroSpan::from_range(
                                     at.range(), file.path),
                             },
@@ -33,6 +33,39 @@ pub enum DMLSymbolKind {
     Typedef,
 }
 
+impl DMLSymbolKind {
+    /// The kind of a symbol as written in DML
+    pub fn kind_name(&self) -> &'static str {
+        match self {
+            DMLSymbolKind::CompObject(kind) => kind.kind_name(),
+            DMLSymbolKind::Parameter => "param",
+            DMLSymbolKind::Constant => "constant",
+            DMLSymbolKind::Extern => "extern",
+            DMLSymbolKind::Hook => "hook",
+            DMLSymbolKind::Local => "local",
+            DMLSymbolKind::Loggroup => "loggroup",
+            DMLSymbolKind::Method => "method",
+            DMLSymbolKind::MethodArg => "argument",
+            DMLSymbolKind::Saved => "saved",
+            DMLSymbolKind::Session => "session",
+            DMLSymbolKind::Template => "template",
+            DMLSymbolKind::Typedef => "typedef",
+        }
+    }
+
+    /// Whether symbols of this kind can be renamed
+    pub fn is_renameable(&self) -> bool {
+        matches!(self,
+                 DMLSymbolKind::CompObject(CompObjectKind::Device
+                                           | CompObjectKind::Bank
+                                           | CompObjectKind::Register
+                                           | CompObjectKind::Field)
+                 | DMLSymbolKind::Method
+                 | DMLSymbolKind::Template
+                 | DMLSymbolKind::Parameter)
+    }
+}
+
 pub trait StructureSymbol : Named + LocationSpan {
     fn kind(&self) -> DMLSymbolKind;
 }
@@ -232,6 +265,18 @@ impl Symbol {
             && self.declarations == other.declarations
             && self.source.equivalent(&other.source)
     }
+
+    /// The locations referring to this symbol, along with those declaring
+    /// and defining it if `include_declaration` is set
+    pub fn reference_sites(&self, include_declaration: bool) -> Vec<ZeroSpan> {
+        let mut sites: Vec<ZeroSpan> = self.references.iter().cloned()
+            .collect();
+        if include_declaration {
+            sites.extend(self.declarations.iter()
+                         .chain(self.definitions.iter()).cloned());
+        }
+        sites
+    }
 }
 
 pub type SymbolID = u64;
@@ -10,6 +10,7 @@ use slotmap::{DefaultKey, Key, SlotMap};
 
 use crate::utility::{partial_sort_by_key, partial_sort_by_key_in_place};
 use crate::analysis::{DMLNamed, DMLError};
+use crate::analysis::intern::Name;
 use crate::analysis::parsing::tree::ZeroSpan;
 use crate::analysis::structure::objects::{ArrayDim, CompObjectKind,
                                           CompObjectKindDecl,
@@ -21,7 +22,7 @@ use crate::analysis::structure::objects::{ArrayDim, CompObjectKind,
                                           Parameter, ParamValue,
                                           Variable,
                                           VariableDecl};
-use crate::analysis::structure::expressions::{DMLString, Value, ExpressionKind,
+use crate::analysis::structure::expressions::{DMLString, ExpressionKind,
                                               Identifier, ConstantList};
 use crate::analysis::structure::toplevel::{ExistCondition, ObjectDecl,
                                            StatementSpec, TopLevel};
@@ -36,7 +37,7 @@ use crate::analysis::templating::traits::{DMLTemplate, DMLTrait,
                                           get_impls, TraitMemberKind, TemplateTraitInfo};
 use crate::analysis::{LocationSpan, DeclarationSpan, combine_vec_of_decls};
 
-type InEachSpec = HashMap<String, Vec<(Vec<String>, (ZeroSpan, Arc<ObjectSpec>))>>;
+type InEachSpec = HashMap<Name, Vec<(Vec<Name>, (ZeroSpan, Arc<ObjectSpec>))>>;
 pub type StructureKey = DefaultKey;
 pub type StructureContainer = SlotMap<StructureKey, DMLCompositeObject>;
 
@@ -131,9 +132,9 @@ fn create_spec<'t>(loc: ZeroSpan,
             instantiations.insert(
                 ObjectDecl::always(&Instantiation {
                     span: loc,
-                    names: vec![Value::<String> {
+                    names: vec![DMLString {
                         span: loc,
-                        val: templ_name.to_string(),
+                        val: Name::from(templ_name),
                     }],
                 }),
                 vec![Arc::clone(templ)]);
@@ -283,7 +284,7 @@ pub fn make_device<'t>(path: &str,
         name: DMLString {
             // We need to quote the path here, so as to adhere to all other
             // imports that are quoted
-            val: format!("\"{}\"", path),
+            val: Name::from(format!("\"{}\"", path)),
             span: ZeroSpan::invalid(path),
         },
     };
@@ -821,7 +822,7 @@ pub struct DMLCompositeObject {
     pub parent: Option<StructureKey>,
     // None: 'none' for size in arraydim here means an _unknown_ size
     pub arraydimvars: Vec<ArrayDim>,
-    pub components: HashMap<String, DMLObject>,
+    pub components: HashMap<Name, DMLObject>,
     // Used for goto-implementation on composite objects
     pub used_ineach_locs: Vec<ZeroSpan>,
 }
@@ -908,7 +909,7 @@ impl DMLCompositeObject {
                            "some composite object (cannot resolve)".to_string(),
                    });
         } else {
-            self.components.insert(child.identity().to_string(),
+            self.components.insert(Name::from(child.identity()),
                                    DMLObject::ShallowObject(DMLShallowObject {
                                        parent: self.key,
                                        variant: child,
@@ -965,7 +966,7 @@ fn add_template_specs(obj_specs: &mut Vec<Arc<ObjectSpec>>,
         used_templates.insert(tpl.name.to_string());
         let mut modifications = vec![];
         {
-            if let Some(templ_specs) = each_stmts.get(&tpl.name) {
+            if let Some(templ_specs) = each_stmts.get(tpl.name.as_str()) {
                 for (needed_templates, (loc, spec)) in templ_specs {
                     let mut can_add = true;
                     for templ in needed_templates {
@@ -1099,8 +1100,8 @@ fn make_objectdecl_auto_param(name: &str,
                               -> ObjectDecl<Parameter> {
     ObjectDecl::always(&Parameter {
         object: DMLObjectCommon {
-            name: Value::<String> {
-                val: name.to_string(),
+            name: DMLString {
+                val: Name::from(name),
                 span: *span,
             },
             span: *span,
@@ -1389,27 +1390,27 @@ fn handle_variable(obj: &mut DMLCompositeObject,
 
 // Maps name to (used, definitions), where definitions is a vector of
 //    (Rank, Method) tuples
-type MethodMapping = HashMap<String, (bool, Vec<(Rank, MethodDecl)>)>;
+type MethodMapping = HashMap<Name, (bool, Vec<(Rank, MethodDecl)>)>;
 // Maps name to (used, definitions), where definitions is a vector of
 //    (Rank, Decl, Spec) tuples
-type ObjectMapping = HashMap<String, (bool, Vec<(Rank,
+type ObjectMapping = HashMap<Name, (bool, Vec<(Rank,
                                                  ObjectDecl<CompositeObject>,
                                                  Arc<ObjectSpec>)>)>;
 // Maps name to (used, definitions), similar to methodmapping
-type SavedMapping = HashMap<String,
+type SavedMapping = HashMap<Name,
                             (bool,
                              Vec<(Rank,
                                   (VariableDecl, Option<Initializer>))>)>;
-type SessionMapping = HashMap<String,
+type SessionMapping = HashMap<Name,
                               (bool,
                                Vec<(Rank,
                                     (VariableDecl, Option<Initializer>))>)>;
-type HookMapping = HashMap<String, (bool, Vec<(Rank, ObjectDecl<Hook>)>)>;
+type HookMapping = HashMap<Name, (bool, Vec<(Rank, ObjectDecl<Hook>)>)>;
 
 // Figure out symbol mappings for these specs
 // reports unguarded error
 // the hashmap is the symbol mapping
-type CollectedSymbols = (HashMap<String, (ZeroSpan, Vec<ZeroSpan>)>,
+type CollectedSymbols = (HashMap<Name, (ZeroSpan, Vec<ZeroSpan>)>,
                          Vec<Constant>,
                          SavedMapping, SessionMapping,
                          MethodMapping, HookMapping, ObjectMapping);
@@ -1502,7 +1503,7 @@ fn collect_symbols(parameters: &[DMLParameter],
             if let Some((_, e)) = hooks.get_mut(name) {
                 e.push(to_insert);
             } else {
-                hooks.insert(name.to_string(), (false, vec![to_insert]));
+                hooks.insert(Name::from(name), (false, vec![to_insert]));
             }
         }
 
@@ -1541,7 +1542,7 @@ fn collect_symbols(parameters: &[DMLParameter],
     // constant > parameter > subobj > method > saved > session
     // Grab the most-relevant decl from each ambiguousdecl, if they have
     // parameter-to-parameter collisions then that has already been reported
-    let mut symbols: HashMap<String, (ZeroSpan, Vec<ZeroSpan>)>
+    let mut symbols: HashMap<Name, (ZeroSpan, Vec<ZeroSpan>)>
         = HashMap::new();
 
     // NOTE: constants are top-level only, so ordering doesn't really matter
@@ -1572,7 +1573,7 @@ fn collect_symbols(parameters: &[DMLParameter],
             rest.push(maybe_auth_decl_span);
         } else {
             *used = true;
-            symbols.insert(name.to_string(), (maybe_auth_decl_span, vec![]));
+            symbols.insert(Name::from(name), (maybe_auth_decl_span, vec![]));
         }
     }
 
@@ -1583,7 +1584,7 @@ fn collect_symbols(parameters: &[DMLParameter],
             rest.push(maybe_auth_decl_span);
         } else {
             *used = true;
-            symbols.insert(name.to_string(), (maybe_auth_decl_span, vec![]));
+            symbols.insert(Name::from(name), (maybe_auth_decl_span, vec![]));
         }
     }
 
@@ -1594,7 +1595,7 @@ fn collect_symbols(parameters: &[DMLParameter],
                 rest.push(maybe_auth_decl_span);
             } else {
                 *used = true;
-                symbols.insert(name.to_string(),
+                symbols.insert(Name::from(name),
                                (maybe_auth_decl_span, vec![]));
             }
         }
@@ -1606,7 +1607,7 @@ fn collect_symbols(parameters: &[DMLParameter],
                 rest.push(maybe_auth_decl_span);
             } else {
                 *used = true;
-                symbols.insert(name.to_string(),
+                symbols.insert(Name::from(name),
                                (maybe_auth_decl_span, vec![]));
             }
         }
@@ -1619,7 +1620,7 @@ fn collect_symbols(parameters: &[DMLParameter],
                 rest.push(maybe_auth_decl_span);
             } else {
                 *used = true;
-                symbols.insert(name.to_string(),
+                symbols.insert(Name::from(name),
                                (maybe_auth_decl_span, vec![]));
             }
         }
@@ -1641,7 +1642,7 @@ fn collect_symbols(parameters: &[DMLParameter],
     (symbols, constants, saveds, sessions, methods, hooks, subobjs)
 }
 
-fn merge_composite_subobj<'c>(name: String,
+fn merge_composite_subobj<'c>(name: Name,
                               parent_each_stmts: &InEachSpec,
                               specs: Vec<(ObjectDecl<CompositeObject>,
                                           Arc<ObjectSpec>)>,
@@ -1993,7 +1994,7 @@ fn check_trait_overrides(obj: &DMLCompositeObject,
                          report: &mut Vec<DMLError>) {
     debug!("Checking traits overrides on {:?}", obj.identity);
     trace!("all symbols are: {:?}",
-           obj.components.keys().collect::<Vec<&String>>());
+           obj.components.keys().collect::<Vec<&Name>>());
     fn report_collision(name: &str,
                         srcloc: ZeroSpan,
                         coll_loc: ZeroSpan,
@@ -2144,7 +2145,8 @@ fn make_auto_params(identity: &DMLString,
                 "_ident",
                 &identity.span,
                 Some(ParamValue::Set(Box::new(
-                    ExpressionKind::StringLiteral(identity.clone()))))));
+                    ExpressionKind::StringLiteral(
+                        identity.to_string_value()))))));
 
         let mut indices = vec![];
         for arraydim in index_info {
@@ -2207,7 +2209,7 @@ fn make_auto_params(identity: &DMLString,
                 Some(ParamValue::Set(
                     // TODO: Calculate real qname
                     Box::new(ExpressionKind::StringLiteral(
-                        identity.clone()))))));
+                        identity.to_string_value()))))));
 
         auto_parameters.insert(
             "parent".to_string(),
@@ -12,6 +12,7 @@ use crate::analysis::structure::expressions::DMLString;
 use crate::analysis::structure::toplevel::{ObjectDecl, ExistCondition};
 
 use crate::analysis::DMLError;
+use crate::analysis::intern::Name;
 use crate::analysis::templating::methods::{DMLConcreteMethod, DMLMethodRef, DefaultCallReference, MethodDecl, MethodDeclaration};
 use crate::analysis::templating::objects::{CoarseObjectKind,
                                            DMLNamedMember,
@@ -46,21 +47,21 @@ pub struct DMLTrait {
     // _not_ objectdecls as conditional declarations are not
     // part of template type (this is IMO weird but what can you do)
 
-    pub sessions: HashMap<String, Declaration>,
-    pub saveds: HashMap<String, Declaration>,
-    pub params: HashMap<String, Declaration>,
+    pub sessions: HashMap<Name, Declaration>,
+    pub saveds: HashMap<Name, Declaration>,
+    pub params: HashMap<Name, Declaration>,
 
-    pub abstract_methods: HashMap<String, MethodDecl>,
+    pub abstract_methods: HashMap<Name, MethodDecl>,
     // These are now concrete method declarations, since we need to be able
     // to handle them as such for purposes of TQMIC resolutions or default calls
-    pub methods: HashMap<String, DMLConcreteMethod>,
+    pub methods: HashMap<Name, DMLConcreteMethod>,
 
     // Maps a symbol name to the ancestor implementing it
     // does NOT include symbols defined in this trait
-    pub ancestor_map: HashMap<String, Arc<DMLTrait>>,
+    pub ancestor_map: HashMap<Name, Arc<DMLTrait>>,
 
     // These symbols are reserved but not defined within this trait
-    pub reserved_symbols: HashMap<String, ZeroSpan>,
+    pub reserved_symbols: HashMap<Name, ZeroSpan>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -145,14 +146,14 @@ where T: Borrow<str> {
     }
 }
 
-pub fn get_impls(me: &Arc<DMLTrait>) -> HashMap<String, Arc<DMLTrait>> {
+pub fn get_impls(me: &Arc<DMLTrait>) -> HashMap<Name, Arc<DMLTrait>> {
     trace!("Getting all impls of {:?}", me.name);
     me.sessions.keys()
         .chain(me.saveds.keys())
         .chain(me.params.keys())
         .chain(me.methods.keys())
         .chain(me.abstract_methods.keys())
-        .map(|name|(name.to_string(), Arc::clone(me)))
+        .map(|name|(name.clone(), Arc::clone(me)))
         .chain(me.ancestor_map.iter().map(|(n, rc)|(n.clone(), Arc::clone(rc))))
         .collect()
 }
@@ -313,8 +314,8 @@ impl DMLTrait {
 
         // Check that our implementations do not collide
         let mut used_names = HashMap::default();
-        fn maybe_report_collision(used: &mut HashMap<String, ZeroSpan>,
-                                  name: String,
+        fn maybe_report_collision(used: &mut HashMap<Name, ZeroSpan>,
+                                  name: Name,
                                   span: ZeroSpan,
                                   report: &mut Vec<DMLError>) -> bool {
             if used.contains_key(name.as_str()) {
@@ -345,7 +346,7 @@ impl DMLTrait {
 
         // Flatten sessions and saveds into non-colliding data declarations
         // with resolved types
-        let sessions: HashMap<String, Declaration> =
+        let sessions: HashMap<Name, Declaration> =
             spec.sessions.iter().filter_map(discard_conditional)
             .flat_map(|sess|sess.vars)
             .map(|decl|Declaration {
@@ -364,7 +365,7 @@ impl DMLTrait {
                 report)).map(|d|(d.name.val.clone(), d)).collect();
         trace!("sessions are: {:?}", sessions);
 
-        let saveds: HashMap<String, Declaration> =
+        let saveds: HashMap<Name, Declaration> =
             spec.saveds.iter().filter_map(discard_conditional)
             .flat_map(|sav|sav.vars)
             .map(|decl|Declaration {
@@ -384,7 +385,7 @@ impl DMLTrait {
         trace!("saveds are: {:?}", saveds);
 
         // Map typed parameters into non-colliding declarations
-        let params: HashMap<String, Declaration> = spec.params.iter()
+        let params: HashMap<Name, Declaration> = spec.params.iter()
             .filter_map(discard_conditional)
             .filter(|param|param.typed.is_some())
             .map(|param|Declaration {
@@ -404,14 +405,14 @@ impl DMLTrait {
         trace!("params are: {:?}", params);
 
         // Map methods into non-colliding method declarations
-        let abstract_methods: HashMap<String, MethodDecl> = spec.methods.iter()
+        let abstract_methods: HashMap<Name, MethodDecl> = spec.methods.iter()
             .filter_map(discard_conditional)
             .filter(|method|method.is_abstract())
             .filter_map(|m| {
                 let new_m = MethodDecl::from_content(&m, report);
                 if maybe_report_collision(
                     &mut used_names,
-                    new_m.identity().to_string(),
+                    new_m.name.val.clone(),
                     *new_m.location(),
                     report) {
                     Some(new_m)
@@ -420,7 +421,7 @@ impl DMLTrait {
                 }}).map(|m|(m.name.val.clone(), m)).collect();
         trace!("abstract methods are: {:?}", abstract_methods);
         
-        let mut defined_methods: HashMap<String, MethodDecl> = HashMap::default();
+        let mut defined_methods: HashMap<Name, MethodDecl> = HashMap::default();
         for method in spec.methods.iter()
             .filter_map(discard_conditional)
             .filter(|method|!method.is_abstract()) {
@@ -433,7 +434,7 @@ impl DMLTrait {
             if abstract_methods.contains_key(new_m.name.val.as_str())
             || (maybe_report_collision(
                 &mut used_names,
-                new_m.identity().to_string(),
+                new_m.name.val.clone(),
                 *new_m.location(),
                 report)) {
                     // Need one more check here, if we have multiple
@@ -452,14 +453,14 @@ impl DMLTrait {
                         });
                     } else {
                         defined_methods.insert(
-                            new_m.identity().to_string(), new_m.clone());
+                            new_m.name.val.clone(), new_m);
                         }
                     }
         }
         
         trace!("defined methods are: {:?}", defined_methods);
 
-        let mut methods: HashMap<String, DMLConcreteMethod> = HashMap::default();
+        let mut methods: HashMap<Name, DMLConcreteMethod> = HashMap::default();
 
         // Check that our overrides are sound and construct our concrete methods
         // NOTE: Override order is already checked in merge_impl_maps, so we can use
@@ -597,7 +598,7 @@ pub fn merge_impl_maps<'t, I>(name: &str,
                               loc: &ZeroSpan,
                               parents_iter: I,
                               report: &mut Vec<DMLError>)
-                              -> HashMap<String, Arc<DMLTrait>>
+                              -> HashMap<Name, Arc<DMLTrait>>
 where
     I: IntoIterator<Item = &'t Arc<DMLTrait>>
 {
@@ -608,12 +609,12 @@ where
         |mut s, t|{write!(s, "{}, ", t.name).expect("write string error");
                    s}));
 
-    let mut map: HashMap<String, Arc<DMLTrait>> = HashMap::default();
+    let mut map: HashMap<Name, Arc<DMLTrait>> = HashMap::default();
     // Maps name to traits from which we have ambiguous
     // implementations of a declaration
-    let mut conflicting_defs: HashMap::<String, Vec<Arc<DMLTrait>>>
+    let mut conflicting_defs: HashMap::<Name, Vec<Arc<DMLTrait>>>
         = HashMap::default();
-    let mut ambiguous_defs: HashMap::<String, Vec<Arc<DMLTrait>>>
+    let mut ambiguous_defs: HashMap::<Name, Vec<Arc<DMLTrait>>>
         = HashMap::default();
     for parent in parents {
         trace!("Handling parent {}", parent.name);
@@ -0,0 +1,269 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Declarations that nothing in a device uses: imports of files that
+//! contribute nothing to it, templates never instantiated, methods never
+//! called and parameters never read
+use std::collections::{HashMap, HashSet};
+
+use lsp_types::DiagnosticSeverity;
+
+use crate::analysis::{DMLError, DeclarationSpan, DeviceAnalysis,
+                      IsolatedAnalysis, LocationSpan};
+use crate::analysis::parsing::tree::{ZeroRange, ZeroSpan};
+use crate::analysis::structure::expressions::DMLString;
+use crate::analysis::structure::objects::Import;
+use crate::analysis::structure::toplevel::{StatementSpec, TopLevel};
+use crate::analysis::symbols::SymbolSource;
+use crate::analysis::templating::methods::MethodDeclaration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UnusedKind {
+    Import,
+    Template,
+    Method,
+    Parameter,
+}
+
+impl UnusedKind {
+    pub fn kind_name(&self) -> &'static str {
+        match self {
+            UnusedKind::Import => "import",
+            UnusedKind::Template => "template",
+            UnusedKind::Method => "method",
+            UnusedKind::Parameter => "parameter",
+        }
+    }
+}
+
+/// A declaration that nothing in a device uses
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct UnusedDeclaration {
+    pub kind: UnusedKind,
+    pub name: String,
+    // The span of the name, or of the imported file name
+    pub span: ZeroSpan,
+}
+
+const DESCRIPTIONS: [(UnusedKind, &str, &str); 4] = [
+    (UnusedKind::Import, "Nothing imported from '", "' is used"),
+    (UnusedKind::Template, "Template '", "' is never instantiated"),
+    (UnusedKind::Method, "Method '", "' is never called"),
+    (UnusedKind::Parameter, "Parameter '", "' is never read"),
+];
+
+impl UnusedDeclaration {
+    pub fn description(&self) -> String {
+        let (_, prefix, suffix) = DESCRIPTIONS.iter()
+            .find(|(kind, _, _)|*kind == self.kind).unwrap();
+        format!("{}{}{}", prefix, self.name, suffix)
+    }
+
+    pub fn to_error(&self, severity: DiagnosticSeverity) -> DMLError {
+        DMLError {
+            span: self.span,
+            description: self.description(),
+            severity: Some(severity),
+            related: vec![],
+        }
+    }
+}
+
+/// The kind and name of the unused declaration in the description of an
+/// unused declaration error
+pub fn unused_declaration(description: &str) -> Option<(UnusedKind, &str)> {
+    DESCRIPTIONS.iter().find_map(
+        |(kind, prefix, suffix)|description.strip_prefix(prefix)?
+            .strip_suffix(suffix).map(|name|(*kind, name)))
+}
+
+// The templates, methods and parameters declared in a spec and the
+// specs nested in it, by kind
+fn spec_declarations<'t>(spec: &'t StatementSpec,
+                         declarations: &mut Vec<(UnusedKind, &'t DMLString,
+                                                 &'t ZeroSpan)>) {
+    for method in &spec.methods {
+        declarations.push((UnusedKind::Method, &method.obj.object.name,
+                           method.obj.span()));
+    }
+    for param in &spec.params {
+        declarations.push((UnusedKind::Parameter, &param.obj.object.name,
+                           param.obj.span()));
+    }
+    for object in &spec.objects {
+        spec_declarations(&object.spec, declarations);
+    }
+    for ineach in &spec.ineachs {
+        spec_declarations(&ineach.spec, declarations);
+    }
+}
+
+fn declarations(toplevel: &TopLevel)
+                -> Vec<(UnusedKind, &DMLString, &ZeroSpan)> {
+    let mut declarations = vec![];
+    for import in &toplevel.spec.imports {
+        declarations.push((UnusedKind::Import, &import.obj.name,
+                           &import.obj.span));
+    }
+    for template in &toplevel.templates {
+        declarations.push((UnusedKind::Template, &template.obj.object.name,
+                           template.obj.span()));
+        spec_declarations(&template.spec, &mut declarations);
+    }
+    spec_declarations(&toplevel.spec, &mut declarations);
+    declarations
+}
+
+/// The range of the whole declaration of the kind whose name is at `name`,
+/// in a file
+pub fn declaration_range(toplevel: &TopLevel, kind: UnusedKind,
+                         name: ZeroRange) -> Option<ZeroRange> {
+    declarations(toplevel).into_iter()
+        .find(|(decl_kind, decl_name, _)|*decl_kind == kind
+              && decl_name.span.range == name)
+        .map(|(_, _, span)|span.range)
+}
+
+fn template_used(device: &DeviceAnalysis, loc: &ZeroSpan) -> bool {
+    // Duplicate templates have no symbols, and are reported as such
+    device.symbol_info.template_symbols.get(loc).is_none_or(|symbol|{
+        let symbol = symbol.lock().unwrap();
+        !symbol.references.is_empty() || !symbol.implementations.is_empty()
+    }) || device.template_object_implementation_map.get(loc)
+        .is_some_and(|objects|!objects.is_empty())
+}
+
+fn method_used(device: &DeviceAnalysis, loc: &ZeroSpan) -> bool {
+    // Methods that are in no object are in templates, which are unused
+    // themselves
+    let Some(symbols) = device.symbol_info.method_symbols.get(loc) else {
+        return true;
+    };
+    symbols.values().any(|symbol|{
+        let symbol = symbol.lock().unwrap();
+        // Methods overriding others may be called through what they
+        // override, and independent methods may be startup methods
+        // called by the simulator
+        let called_implicitly = match &symbol.source {
+            SymbolSource::Method(_, method) =>
+                method.get_default().is_some() || method.is_shared()
+                || method.get_decl().independent,
+            _ => true,
+        };
+        called_implicitly || !symbol.references.is_empty()
+            || !symbol.implementations.is_empty()
+    })
+}
+
+fn parameter_used(device: &DeviceAnalysis, name: &DMLString) -> bool {
+    let Some(symbols) = device.symbol_info.param_symbols.get(
+        &(name.span, name.val.clone())) else {
+        return true;
+    };
+    // Parameters declared elsewhere too are part of the interface of a
+    // template, and may be read by code we do not analyze
+    symbols.values().any(|symbol|{
+        let symbol = symbol.lock().unwrap();
+        !symbol.references.is_empty()
+            || symbol.definitions.iter().chain(&symbol.declarations)
+            .any(|decl|*decl != name.span)
+    })
+}
+
+// Whether a file declares anything used in the device, which are
+// instantiated templates and anything outside of templates other than
+// imports
+fn contributes(device: &DeviceAnalysis, toplevel: &TopLevel) -> bool {
+    let spec = &toplevel.spec;
+    toplevel.templates.iter().any(
+        |template|template_used(device, template.loc_span()))
+        || toplevel.bitorder.is_some()
+        || !toplevel.loggroups.is_empty()
+        || !toplevel.cblocks.is_empty()
+        || !toplevel.externs.is_empty()
+        || !toplevel.typedefs.is_empty()
+        || !spec.objects.is_empty() || !spec.sessions.is_empty()
+        || !spec.saveds.is_empty() || !spec.params.is_empty()
+        || !spec.methods.is_empty() || !spec.ineachs.is_empty()
+        || !spec.exports.is_empty() || !spec.hooks.is_empty()
+        || !spec.instantiations.is_empty() || !spec.errors.is_empty()
+        || !spec.constants.is_empty()
+}
+
+// The files imported from the root, directly or not, without following
+// the import `skipped`
+fn reachable<'a>(imports: &HashMap<&'a str, Vec<(&'a Import, &'a str)>>,
+                 root: &'a str, skipped: Option<&Import>)
+                 -> HashSet<&'a str> {
+    let mut reached = HashSet::from([root]);
+    let mut queue = vec![root];
+    while let Some(file) = queue.pop() {
+        for (import, imported) in imports.get(file).into_iter().flatten() {
+            if Some(*import) != skipped && reached.insert(imported) {
+                queue.push(imported);
+            }
+        }
+    }
+    reached
+}
+
+fn unused_imports(device: &DeviceAnalysis, bases: &[IsolatedAnalysis],
+                  imp_map: &HashMap<Import, String>)
+                  -> Vec<UnusedDeclaration> {
+    let toplevels: HashMap<&str, &TopLevel> = bases.iter()
+        .map(|base|(base.path.as_str(), &base.toplevel)).collect();
+    let imports: HashMap<&str, Vec<(&Import, &str)>> = bases.iter()
+        .map(|base|(base.path.as_str(), base.toplevel.spec.imports.iter()
+                    .filter_map(|import|imp_map.get(&import.obj)
+                                .map(|path|(&import.obj, path.as_str())))
+                    .collect()))
+        .collect();
+    let all = reachable(&imports, device.path.as_str(), None);
+    let mut unused = vec![];
+    for (import, _) in imports.values().flatten() {
+        // Implicit imports have no location
+        if import.span.range == ZeroRange::invalid() {
+            continue;
+        }
+        let kept = reachable(&imports, device.path.as_str(), Some(import));
+        // Imports of files that are imported elsewhere too only affect
+        // the order of declarations
+        let mut dropped = all.difference(&kept).peekable();
+        if dropped.peek().is_some() && !dropped.any(
+            |file|toplevels.get(file).is_none_or(
+                |toplevel|contributes(device, toplevel))) {
+            unused.push(UnusedDeclaration {
+                kind: UnusedKind::Import,
+                name: import.imported_name().to_string(),
+                span: import.name.span,
+            });
+        }
+    }
+    unused
+}
+
+/// The declarations in the files of a device that nothing in the device
+/// uses. Relies on the references of method bodies being resolved.
+pub fn unused_declarations(device: &DeviceAnalysis,
+                           bases: &[IsolatedAnalysis],
+                           imp_map: &HashMap<Import, String>)
+                           -> Vec<UnusedDeclaration> {
+    let mut unused = unused_imports(device, bases, imp_map);
+    for base in bases {
+        for (kind, name, _) in declarations(&base.toplevel) {
+            let used = match kind {
+                UnusedKind::Import => continue,
+                UnusedKind::Template => template_used(device, &name.span),
+                UnusedKind::Method => method_used(device, &name.span),
+                UnusedKind::Parameter => parameter_used(device, name),
+            };
+            if !used {
+                unused.push(UnusedDeclaration {
+                    kind,
+                    name: name.val.to_string(),
+                    span: name.span,
+                });
+            }
+        }
+    }
+    unused
+}
@@ -0,0 +1,82 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Authentication of clients connecting over the network
+//!
+//! When the language server or the MCP server listens for connections, the
+//! clients can be required to present a shared secret token, read from a
+//! file or from the `DLS_AUTH_TOKEN` environment variable. MCP clients present
+//! it as a bearer token in the WebSocket handshake, and language server
+//! clients in the initialization options of their `initialize` request. Any
+//! other traffic of a connection that has not presented the token is
+//! rejected, and the connection closed.
+
+use anyhow::{anyhow, bail, Result};
+use std::fmt;
+use std::path::Path;
+
+/// The environment variable holding the token, when no file is given
+pub const AUTH_TOKEN_ENV: &str = "DLS_AUTH_TOKEN";
+
+/// A token that clients must present to be served
+#[derive(Clone, PartialEq, Eq)]
+pub struct AuthToken(String);
+
+// The token is a secret, and must not end up in logs
+impl fmt::Debug for AuthToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "AuthToken(..)")
+    }
+}
+
+impl AuthToken {
+    pub fn new(token: &str) -> Result<AuthToken> {
+        let token = token.trim();
+        if token.is_empty() {
+            bail!("The authentication token is empty");
+        }
+        if token.chars().any(char::is_whitespace) {
+            bail!("The authentication token contains whitespace");
+        }
+        Ok(AuthToken(token.to_string()))
+    }
+
+    /// The token in the file at `path`, surrounding whitespace removed
+    pub fn from_file(path: &Path) -> Result<AuthToken> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Could not read {}: {}", path.display(), e))?;
+        AuthToken::new(&text)
+            .map_err(|e| anyhow!("{}: {}", path.display(), e))
+    }
+
+    /// The token in the file at `file` if given, otherwise that in
+    /// `AUTH_TOKEN_ENV` if it is set, or `None` if clients need no token
+    pub fn configured(file: Option<&Path>) -> Result<Option<AuthToken>> {
+        if let Some(path) = file {
+            return AuthToken::from_file(path).map(Some);
+        }
+        match std::env::var(AUTH_TOKEN_ENV) {
+            Ok(token) => AuthToken::new(&token)
+                .map(Some)
+                .map_err(|e| anyhow!("{}: {}", AUTH_TOKEN_ENV, e)),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Whether `presented` is the token. Compares in time independent of
+    /// where the two differ, so that the token cannot be guessed from how
+    /// long rejecting a wrong one takes.
+    pub fn matches(&self, presented: &str) -> bool {
+        let (expected, presented) = (self.0.as_bytes(), presented.as_bytes());
+        expected.len() == presented.len()
+            && expected.iter().zip(presented)
+            .fold(0, |difference, (a, b)| difference | (a ^ b)) == 0
+    }
+
+    /// Whether the value of an `Authorization` header presents the token as
+    /// a bearer token
+    pub fn matches_bearer(&self, authorization: &str) -> bool {
+        authorization.split_once(' ')
+            .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+            .is_some_and(|(_, token)| self.matches(token.trim()))
+    }
+}
@@ -0,0 +1,315 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Benchmarking of the analysis, for tracking its throughput
+//!
+//! `dls bench` analyzes a corpus of DML files in-process the way the server
+//! does: each file is parsed and analyzed in isolation, every device is
+//! analyzed with the files it imports, and each file is linted. The first
+//! pass runs with cold caches. The passes after it analyze the same files
+//! again from their previous parses, as the server does when files are
+//! opened again or edited, and with the files in the cache of the operating
+//! system.
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::{anyhow, bail};
+use rayon::prelude::*;
+use serde::Serialize;
+
+use crate::analysis::parsing::incremental::PreviousParse;
+use crate::analysis::structure::objects::Import;
+use crate::analysis::{AnalysisOptions, DeviceAnalysis, IsolatedAnalysis,
+                      TimestampedStorage};
+use crate::check::EXIT_FAILURE;
+use crate::concurrency::{install_analysis, set_analysis_threads,
+                         JobStatusKeeper};
+use crate::file_management::{dml_files, CanonPath, PathResolver};
+use crate::lint::{parse_lint_cfg, LintCfg, LinterAnalysis};
+use crate::stats::{self, Phase, Stats};
+use crate::vfs::TextFile;
+
+#[derive(Debug, Clone)]
+pub struct BenchOptions {
+    /// DML files, and directories to benchmark all DML files under
+    pub paths: Vec<PathBuf>,
+    /// Where imports are looked up, after the directory of the importing
+    /// file
+    pub include_paths: Vec<PathBuf>,
+    /// The number of passes after the cold one
+    pub warm_passes: usize,
+    /// See `concurrency::analysis_threads`
+    pub analysis_threads: Option<usize>,
+    pub linting_enabled: bool,
+    pub lint_cfg_path: Option<PathBuf>,
+    /// Print the results as JSON
+    pub json: bool,
+}
+
+/// The time taken by a pass over the corpus, and by each of its phases
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct PassResult {
+    pub name: String,
+    pub seconds: f64,
+    pub files_per_second: f64,
+    pub megabytes_per_second: f64,
+    pub parse_seconds: f64,
+    pub resolve_seconds: f64,
+    pub lint_seconds: f64,
+    pub devices: usize,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct BenchResult {
+    pub files: usize,
+    pub bytes: usize,
+    pub threads: usize,
+    pub passes: Vec<PassResult>,
+    /// The peak resident memory of the process, on systems with procfs
+    pub peak_resident_bytes: Option<u64>,
+}
+
+/// Benchmark the analysis as described by `options`, printing the results,
+/// and return the exit code of `dls bench`
+pub fn run(options: BenchOptions) -> i32 {
+    match bench(&options) {
+        Ok(result) => {
+            if options.json {
+                println!("{}", serde_json::to_string_pretty(&result).unwrap());
+            } else {
+                println!("{}", render(&result));
+            }
+            0
+        },
+        Err(e) => {
+            eprintln!("dls bench: {}", e);
+            EXIT_FAILURE
+        },
+    }
+}
+
+// The files of the corpus and their contents
+type Corpus = Vec<(CanonPath, String)>;
+
+fn bench(options: &BenchOptions) -> anyhow::Result<BenchResult> {
+    let mut files = vec![];
+    for path in &options.paths {
+        if path.is_dir() {
+            files.extend(dml_files(path, usize::MAX));
+        } else if path.is_file() {
+            files.push(path.clone());
+        } else {
+            bail!("No such file or directory: {}", path.display());
+        }
+    }
+    let mut corpus: Corpus = files.into_iter()
+        .map(|file| {
+            let path = CanonPath::from_path_buf(file.clone())
+                .ok_or_else(||anyhow!("Could not find {}", file.display()))?;
+            let text = std::fs::read_to_string(&file).map_err(
+                |e|anyhow!("Could not read {}: {}", file.display(), e))?;
+            Ok((path, text))
+        })
+        .collect::<anyhow::Result<_>>()?;
+    corpus.sort_by(|(a, _), (b, _)|a.cmp(b));
+    corpus.dedup_by(|(a, _), (b, _)|a == b);
+    if corpus.is_empty() {
+        bail!("No DML files to benchmark");
+    }
+    let lint_cfg = match &options.lint_cfg_path {
+        Some(path) => parse_lint_cfg(path.clone())
+            .map_err(|e|anyhow!("Could not read {}: {}", path.display(), e))?
+            .0,
+        None => LintCfg::default(),
+    };
+    let mut resolver = PathResolver::from(None);
+    resolver.add_paths(options.include_paths.iter().cloned());
+    let bytes = corpus.iter().map(|(_, text)|text.len()).sum();
+    set_analysis_threads(options.analysis_threads);
+
+    let mut passes = vec![];
+    let mut previous = HashMap::default();
+    for pass in 0..=options.warm_passes {
+        let name = if pass == 0 {
+            "cold".to_string()
+        } else {
+            format!("warm {}", pass)
+        };
+        let before = stats::snapshot();
+        let start = Instant::now();
+        let (devices, parses) = analyze(&corpus, &previous, &resolver,
+                                        options.linting_enabled
+                                        .then_some(&lint_cfg))?;
+        passes.push(pass_result(name, start.elapsed(), corpus.len(), bytes,
+                                devices, &before, &stats::snapshot()));
+        previous = parses;
+    }
+    Ok(BenchResult {
+        files: corpus.len(),
+        bytes,
+        threads: install_analysis(rayon::current_num_threads),
+        passes,
+        peak_resident_bytes: stats::peak_resident_bytes(),
+    })
+}
+
+// Analyze the corpus, re-parsing from the previous parses of its files,
+// and give the number of devices analyzed and the parses of the files
+fn analyze(corpus: &Corpus,
+           previous: &HashMap<CanonPath, PreviousParse>,
+           resolver: &PathResolver,
+           lint_cfg: Option<&LintCfg>)
+           -> anyhow::Result<(usize, HashMap<CanonPath, PreviousParse>)> {
+    let (_keeper, status) = JobStatusKeeper::new();
+    let isolated = |path: &CanonPath, text: &str,
+                    previous: Option<&PreviousParse>| {
+        let file = TextFile::from_str(text).map_err(
+            |_|anyhow!("Could not read {}", path.as_str()))?;
+        IsolatedAnalysis::new_incrementally(
+            path, &path.to_path_buf(), file, previous, status.clone())
+            .map_err(|e|anyhow!("Failed to analyze {}: {}", path.as_str(), e))
+    };
+    let mut analyses: HashMap<CanonPath, IsolatedAnalysis> =
+        install_analysis(||corpus.par_iter()
+                         .map(|(path, text)|Ok((path.clone(), isolated(
+                             path, text, previous.get(path))?)))
+                         .collect::<anyhow::Result<_>>())?;
+
+    // Imports are resolved in the context of each device, analyzing the
+    // imported files outside of the corpus as they are found
+    let mut jobs = vec![];
+    for (device, _) in corpus {
+        if !analyses[device].is_device_file() {
+            continue;
+        }
+        let mut import_sources: HashMap<Import, String> = HashMap::default();
+        let mut bases: Vec<CanonPath> = vec![];
+        let mut queue = vec![device.clone()];
+        while let Some(next) = queue.pop() {
+            if bases.contains(&next) {
+                continue;
+            }
+            if !analyses.contains_key(&next) {
+                let text = std::fs::read_to_string(next.as_path()).map_err(
+                    |e|anyhow!("Could not read {}: {}", next.as_str(), e))?;
+                analyses.insert(next.clone(), isolated(&next, &text, None)?);
+            }
+            let (found, _) = analyses[&next].resolve_imports(
+                resolver, Some(device));
+            for (dependency, import) in found {
+                import_sources.insert(import, dependency.as_str().to_string());
+                queue.push(dependency);
+            }
+            bases.push(next);
+        }
+        jobs.push((device, bases, import_sources));
+    }
+    let timestamp = SystemTime::now();
+    let devices = install_analysis(||jobs.into_par_iter()
+        .map(|(device, bases, import_sources)| {
+            let bases = bases.iter()
+                .map(|base|TimestampedStorage {
+                    timestamp,
+                    stored: analyses[base].clone(),
+                })
+                .collect();
+            DeviceAnalysis::new(analyses[device].clone(), bases,
+                                import_sources, AnalysisOptions::default(),
+                                status.clone())
+                .map_err(|e|anyhow!("Failed to analyze the device of {}: {}",
+                                    device.as_str(), e))
+        })
+        .collect::<anyhow::Result<Vec<_>>>())?;
+
+    if let Some(cfg) = lint_cfg {
+        install_analysis(||corpus.par_iter().try_for_each(|(path, text)| {
+            let file = TextFile::from_str(text).map_err(
+                |_|anyhow!("Could not read {}", path.as_str()))?;
+            LinterAnalysis::new(path.as_path(), file, cfg.clone(),
+                                analyses[path].clone(), status.clone())
+                .map(|_|())
+                .map_err(|e|anyhow!("Failed to lint {}: {}", path.as_str(), e))
+        }))?;
+    }
+    let parses = corpus.iter()
+        .filter_map(|(path, _)|Some((path.clone(), analyses.remove(path)?
+                                     .into_previous_parse()?)))
+        .collect();
+    Ok((devices.len(), parses))
+}
+
+fn pass_result(name: String, elapsed: Duration, files: usize, bytes: usize,
+               devices: usize, before: &Stats, after: &Stats) -> PassResult {
+    let seconds = elapsed.as_secs_f64();
+    let phase_seconds = |phase: Phase| {
+        let total = |stats: &Stats|stats.phases.get(&phase)
+            .map_or(0, |phase|phase.total_ms);
+        (total(after) - total(before)) as f64 / 1000.0
+    };
+    PassResult {
+        name,
+        seconds,
+        files_per_second: files as f64 / seconds,
+        megabytes_per_second: bytes as f64 / 1_000_000.0 / seconds,
+        // Phases run in parallel, so their times add up to more than that
+        // of the pass
+        parse_seconds: phase_seconds(Phase::Parse),
+        resolve_seconds: phase_seconds(Phase::Resolve),
+        lint_seconds: phase_seconds(Phase::Lint),
+        devices,
+    }
+}
+
+/// The output of `dls bench` when not printing JSON
+pub fn render(result: &BenchResult) -> String {
+    let mut output = format!(
+        "Benchmarked {} files ({:.2} MB) on {} threads\n",
+        result.files, result.bytes as f64 / 1_000_000.0, result.threads);
+    writeln!(output, "{:<10} {:>9} {:>9} {:>8} {:>9} {:>9} {:>9}",
+             "pass", "time (s)", "files/s", "MB/s", "parse", "resolve",
+             "lint").unwrap();
+    for pass in &result.passes {
+        writeln!(output,
+                 "{:<10} {:>9.3} {:>9.1} {:>8.2} {:>9.3} {:>9.3} {:>9.3}",
+                 pass.name, pass.seconds, pass.files_per_second,
+                 pass.megabytes_per_second, pass.parse_seconds,
+                 pass.resolve_seconds, pass.lint_seconds).unwrap();
+    }
+    match result.peak_resident_bytes {
+        Some(peak) => write!(output, "Peak RSS: {:.1} MB",
+                             peak as f64 / 1_000_000.0).unwrap(),
+        None => output.push_str("Peak RSS: unknown"),
+    }
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn test_bench_corpus() {
+        let options = BenchOptions {
+            paths: vec![Path::new(env!("CARGO_MANIFEST_DIR"))
+                        .join("src/test/golden/pcie_endpoint.dml")],
+            include_paths: vec![],
+            warm_passes: 1,
+            analysis_threads: None,
+            linting_enabled: true,
+            lint_cfg_path: None,
+            json: true,
+        };
+        let result = bench(&options).unwrap();
+        assert_eq!(result.files, 1);
+        assert_eq!(result.passes.iter().map(|pass|pass.name.as_str())
+                   .collect::<Vec<_>>(), ["cold", "warm 1"]);
+        assert!(result.passes.iter().all(
+            |pass|pass.devices == 1 && pass.files_per_second > 0.0));
+        let output = render(&result);
+        assert!(output.starts_with("Benchmarked 1 files"));
+        assert!(output.contains("\nwarm 1 "));
+    }
+}
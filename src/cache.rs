@@ -0,0 +1,129 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! The on-disk cache of a workspace
+//!
+//! What the language server and the MCP server extract from the files of a
+//! workspace is kept in the `.dls-cache` directory at its root, keyed by the
+//! contents of each file, so that later sessions need not analyze unchanged
+//! files again. Each cache file records the version of its format and the
+//! version of the DLS that wrote it, and is ignored by any other version.
+//! Caching can be turned off for the whole process, e.g. with `--no-cache`.
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use log::{debug, warn};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// The directory of the cache, relative to the root of a workspace
+pub const CACHE_DIR: &str = ".dls-cache";
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Turn reading and writing the cache on or off
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// The path of the cache file `name` of the workspace at `root`
+pub fn cache_path(root: &Path, name: &str) -> PathBuf {
+    root.join(CACHE_DIR).join(name)
+}
+
+#[derive(Deserialize)]
+struct Stamp {
+    format: u32,
+    dls_version: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheFile<T> {
+    format: u32,
+    dls_version: String,
+    data: T,
+}
+
+/// Read the cache file `name` of the workspace at `root`, if caching is
+/// on and the file was written in version `format` of its format by this
+/// version of the DLS
+pub fn load<T: DeserializeOwned>(root: &Path, name: &str, format: u32)
+                                 -> Option<T> {
+    if !enabled() {
+        return None;
+    }
+    let path = cache_path(root, name);
+    let text = fs::read_to_string(&path).ok()?;
+    match serde_json::from_str::<CacheFile<T>>(&text) {
+        Ok(file) if file.format == format
+            && file.dls_version == crate::version() => Some(file.data),
+        result => {
+            match serde_json::from_str::<Stamp>(&text) {
+                Ok(stamp) if stamp.format != format
+                    || stamp.dls_version != crate::version() =>
+                    debug!("Ignoring cache {:?} of format {} written by DLS \
+                            {}", path, stamp.format, stamp.dls_version),
+                _ => warn!("Ignoring unreadable cache {:?}: {}", path,
+                           result.err().map(|e|e.to_string())
+                           .unwrap_or_default()),
+            }
+            None
+        },
+    }
+}
+
+/// Write the cache file `name` of the workspace at `root`, in version
+/// `format` of its format, unless caching is off. The file is replaced
+/// at once, so that concurrent sessions never read a partial one.
+pub fn save<T: Serialize>(root: &Path, name: &str, format: u32, data: &T)
+                          -> io::Result<()> {
+    if !enabled() {
+        return Ok(());
+    }
+    let dir = root.join(CACHE_DIR);
+    fs::create_dir_all(&dir)?;
+    // Keep the cache out of version control without having projects ignore
+    // it themselves
+    let ignore = dir.join(".gitignore");
+    if !ignore.exists() {
+        fs::write(ignore, "*\n")?;
+    }
+    let path = cache_path(root, name);
+    let partial = dir.join(format!("{}.{}.tmp", name, std::process::id()));
+    fs::write(&partial, serde_json::to_string(&CacheFile {
+        format,
+        dls_version: crate::version(),
+        data,
+    })?)?;
+    fs::rename(&partial, &path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cache_versions() {
+        let root = std::env::temp_dir().join(
+            format!("dls-cache-test-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        assert_eq!(load::<Vec<u32>>(&root, "test.json", 1), None);
+        save(&root, "test.json", 1, &vec![1, 2]).unwrap();
+        assert_eq!(load::<Vec<u32>>(&root, "test.json", 1), Some(vec![1, 2]));
+        assert!(root.join(CACHE_DIR).join(".gitignore").is_file());
+        // Caches of other formats, or written by other versions, are ignored
+        assert_eq!(load::<Vec<u32>>(&root, "test.json", 2), None);
+        fs::write(cache_path(&root, "test.json"),
+                  r#"{"format": 1, "dls_version": "0.0.0", "data": [1]}"#)
+            .unwrap();
+        assert_eq!(load::<Vec<u32>>(&root, "test.json", 1), None);
+        fs::write(cache_path(&root, "test.json"), "[").unwrap();
+        assert_eq!(load::<Vec<u32>>(&root, "test.json", 1), None);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
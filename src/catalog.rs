@@ -0,0 +1,323 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Catalog of known Simics interfaces and DML library templates
+//!
+//! A catalog is bundled with the language and MCP servers, and can be
+//! extended or overridden by JSON or TOML data files listed in the
+//! `DML_MCP_CATALOG` environment variable (separated like `PATH`; directories
+//! contribute all their `.json` and `.toml` files). Entries from data files
+//! replace bundled entries with the same name. The data files are re-read
+//! whenever one of them changes.
+//!
+//! Entries that only exist in some Simics API versions are filtered using
+//! the version table of the language server analysis.
+
+use anyhow::{anyhow, Result};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use crate::analysis::stdlib_versions::{stdlib_item, Availability,
+                                       SimicsApiVersion, StdlibItemKind};
+
+/// Environment variable listing catalog data files and directories
+pub const CATALOG_PATH_VAR: &str = "DML_MCP_CATALOG";
+
+const BUILTIN_CATALOG: &str = include_str!("builtin_catalog.json");
+
+/// A Simics interface that devices can implement or connect to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InterfaceInfo {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// DML file declaring the interface
+    #[serde(default)]
+    pub import: Option<String>,
+    /// C signatures of the interface methods
+    #[serde(default)]
+    pub methods: Vec<String>,
+}
+
+/// A template from the DML library, or provided by the site
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TemplateInfo {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// DML file declaring the template, if it is not in the standard library
+    #[serde(default)]
+    pub import: Option<String>,
+    /// Kinds of objects the template can be instantiated in
+    #[serde(default)]
+    pub applies_to: Vec<String>,
+}
+
+/// The contents of a single catalog data file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CatalogFile {
+    #[serde(default)]
+    pub interfaces: Vec<InterfaceInfo>,
+    #[serde(default)]
+    pub templates: Vec<TemplateInfo>,
+}
+
+impl CatalogFile {
+    /// Parse catalog data, as TOML if `path` has a `.toml` extension and as
+    /// JSON otherwise
+    pub fn parse(path: &Path, text: &str) -> Result<Self> {
+        if path.extension().is_some_and(|ext| ext == "toml") {
+            toml::from_str(text)
+                .map_err(|e| anyhow!("Invalid catalog {}: {}", path.display(), e))
+        } else {
+            serde_json::from_str(text)
+                .map_err(|e| anyhow!("Invalid catalog {}: {}", path.display(), e))
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Could not read {}: {}", path.display(), e))?;
+        Self::parse(path, &text)
+    }
+}
+
+/// Interfaces and templates known to the server, by name
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Catalog {
+    pub interfaces: BTreeMap<String, InterfaceInfo>,
+    pub templates: BTreeMap<String, TemplateInfo>,
+}
+
+impl Catalog {
+    /// The catalog bundled with the server
+    pub fn builtin() -> Self {
+        let mut catalog = Self::default();
+        catalog.extend(CatalogFile::parse(Path::new("builtin_catalog.json"),
+                                          BUILTIN_CATALOG)
+                       .expect("bundled catalog is valid"));
+        catalog
+    }
+
+    /// Add the entries of a data file, replacing entries with the same name
+    pub fn extend(&mut self, file: CatalogFile) {
+        for interface in file.interfaces {
+            self.interfaces.insert(interface.name.clone(), interface);
+        }
+        for template in file.templates {
+            self.templates.insert(template.name.clone(), template);
+        }
+    }
+
+    pub fn interface(&self, name: &str) -> Option<&InterfaceInfo> {
+        self.interfaces.get(name)
+    }
+
+    pub fn template(&self, name: &str) -> Option<&TemplateInfo> {
+        self.templates.get(name)
+    }
+
+    /// A warning about `name` if it is not a known interface, suggesting
+    /// the known interface with the closest name if there is a close one
+    pub fn unknown_interface_warning(&self, name: &str) -> Option<String> {
+        if self.interfaces.contains_key(name) {
+            return None;
+        }
+        let suggestion = self.interfaces.keys()
+            .map(|known| (edit_distance(name, known), known))
+            .filter(|(distance, _)| *distance <= name.chars().count() / 3 + 1)
+            .min();
+        Some(match suggestion {
+            Some((_, known)) => format!(
+                "unknown interface '{}', did you mean '{}'?", name, known),
+            None => format!("unknown interface '{}'", name),
+        })
+    }
+
+    /// The entries usable with a Simics API version: entries that the
+    /// version does not provide are left out, and the descriptions of
+    /// deprecated entries say so
+    pub fn for_simics_api(&self, version: SimicsApiVersion) -> Catalog {
+        let mut catalog = Catalog::default();
+        for info in self.interfaces.values() {
+            if let Some(description) = versioned_description(
+                StdlibItemKind::Interface, &info.name, &info.description,
+                version) {
+                catalog.interfaces.insert(info.name.clone(), InterfaceInfo {
+                    description,
+                    ..info.clone()
+                });
+            }
+        }
+        for info in self.templates.values() {
+            if let Some(description) = versioned_description(
+                StdlibItemKind::Template, &info.name, &info.description,
+                version) {
+                catalog.templates.insert(info.name.clone(), TemplateInfo {
+                    description,
+                    ..info.clone()
+                });
+            }
+        }
+        catalog
+    }
+}
+
+/// The description of an entry under a Simics API version, or `None` if
+/// the version does not provide it
+fn versioned_description(kind: StdlibItemKind, name: &str,
+                         description: &Option<String>,
+                         version: SimicsApiVersion) -> Option<Option<String>> {
+    let Some(item) = stdlib_item(kind, name) else {
+        return Some(description.clone());
+    };
+    match item.availability(version) {
+        Availability::Available => Some(description.clone()),
+        Availability::Deprecated(since) => {
+            let mut note = format!("Deprecated since Simics API {}", since);
+            if let Some(replacement) = item.replacement {
+                write!(note, ", use '{}' instead", replacement).unwrap();
+            }
+            Some(Some(match description {
+                Some(description) => format!("{} ({})", description, note),
+                None => note,
+            }))
+        },
+        Availability::NotYetAvailable(_) | Availability::Removed(_) => None,
+    }
+}
+
+/// The number of single character insertions, deletions, substitutions
+/// and transpositions of adjacent characters needed to turn `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    // Rows i - 2, i - 1 and i of the distances between prefixes
+    let mut rows = [vec![0; b.len() + 1], vec![0; b.len() + 1],
+                    (0..=b.len()).collect()];
+    for i in 1..=a.len() {
+        rows.rotate_left(1);
+        rows[2][0] = i;
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (rows[1][j] + 1).min(rows[2][j - 1] + 1)
+                .min(rows[1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(rows[0][j - 2] + 1);
+            }
+            rows[2][j] = distance;
+        }
+    }
+    rows[2][b.len()]
+}
+
+/// Modification times and sizes of the files a catalog was loaded from
+type Fingerprint = Vec<(PathBuf, Option<(SystemTime, u64)>)>;
+
+/// The catalog in use, reloaded when its data files change
+#[derive(Debug)]
+pub struct CatalogStore {
+    sources: Vec<PathBuf>,
+    state: Mutex<(Fingerprint, Arc<Catalog>)>,
+}
+
+impl CatalogStore {
+    /// A store with the built-in catalog extended by `sources`
+    pub fn new(sources: Vec<PathBuf>) -> Self {
+        let fingerprint = fingerprint(&sources);
+        let catalog = Arc::new(load_catalog(&fingerprint));
+        Self {
+            sources,
+            state: Mutex::new((fingerprint, catalog)),
+        }
+    }
+
+    /// A store with the data files listed in `DML_MCP_CATALOG`
+    pub fn from_env() -> Self {
+        let sources: Vec<PathBuf> = std::env::var_os(CATALOG_PATH_VAR)
+            .map(|paths| std::env::split_paths(&paths).collect())
+            .unwrap_or_default();
+        if !sources.is_empty() {
+            info!("Loading catalog data from {:?}", sources);
+        }
+        Self::new(sources)
+    }
+
+    /// The data files and directories the catalog is extended by
+    pub fn sources(&self) -> &[PathBuf] {
+        &self.sources
+    }
+
+    /// The current catalog, re-reading the data files if any of them
+    /// has been added, removed or modified since they were last read
+    pub fn current(&self) -> Arc<Catalog> {
+        let mut state = self.state.lock().unwrap();
+        if !self.sources.is_empty() {
+            let fingerprint = fingerprint(&self.sources);
+            if fingerprint != state.0 {
+                info!("Catalog data changed, reloading");
+                state.1 = Arc::new(load_catalog(&fingerprint));
+                state.0 = fingerprint;
+            }
+        }
+        Arc::clone(&state.1)
+    }
+}
+
+impl Default for CatalogStore {
+    fn default() -> Self {
+        Self::new(vec![])
+    }
+}
+
+fn is_catalog_file(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "json" || ext == "toml")
+}
+
+fn fingerprint(sources: &[PathBuf]) -> Fingerprint {
+    let mut files = vec![];
+    for source in sources {
+        if source.is_dir() {
+            let mut entries: Vec<PathBuf> = std::fs::read_dir(source)
+                .map(|dir| dir.filter_map(|entry| entry.ok())
+                     .map(|entry| entry.path())
+                     .filter(|path| is_catalog_file(path))
+                     .collect())
+                .unwrap_or_default();
+            entries.sort();
+            files.extend(entries);
+        } else {
+            files.push(source.clone());
+        }
+    }
+    files.into_iter()
+        .map(|path| {
+            let stamp = std::fs::metadata(&path)
+                .and_then(|meta| Ok((meta.modified()?, meta.len()))).ok();
+            (path, stamp)
+        })
+        .collect()
+}
+
+fn load_catalog(files: &Fingerprint) -> Catalog {
+    let mut catalog = Catalog::builtin();
+    for (path, stamp) in files {
+        if stamp.is_none() {
+            warn!("Catalog data file {} does not exist", path.display());
+            continue;
+        }
+        match CatalogFile::load(path) {
+            Ok(file) => {
+                debug!("Loaded {} interfaces and {} templates from {}",
+                       file.interfaces.len(), file.templates.len(),
+                       path.display());
+                catalog.extend(file);
+            },
+            Err(e) => warn!("{}", e),
+        }
+    }
+    catalog
+}
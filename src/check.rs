@@ -0,0 +1,310 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Headless checking of DML files, for gating changes in CI
+//!
+//! `dls check` runs the DLS as a server in a subprocess, opens the checked
+//! files in it the way an editor would, waits for their analysis and prints
+//! the diagnostics reported for them and the files they import.
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail};
+use clap::ValueEnum;
+use lsp_types::DiagnosticSeverity;
+use serde_json::{json, Value};
+
+use crate::config::Config;
+use crate::dfa::client::Diagnostic;
+use crate::dfa::ClientInterface;
+use crate::file_management::dml_files;
+
+use log::debug;
+
+/// Exit code of `dls check` when errors were reported
+pub const EXIT_ERRORS: i32 = 1;
+/// Exit code of `dls check` when the files could not be checked
+pub const EXIT_FAILURE: i32 = 2;
+
+/// How `dls check` prints diagnostics
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// One `file:line:column: severity: message` line per diagnostic
+    #[default]
+    Human,
+    /// A JSON object listing the diagnostics of each file
+    Json,
+    /// A SARIF 2.1.0 log, as read by code scanning services
+    Sarif,
+}
+
+#[derive(Debug, Clone)]
+pub struct CheckOptions {
+    /// DML files, and directories to check all DML files under
+    pub paths: Vec<PathBuf>,
+    pub format: OutputFormat,
+    pub compile_info_path: Option<PathBuf>,
+    pub linting_enabled: bool,
+    pub lint_cfg_path: Option<PathBuf>,
+}
+
+/// The diagnostics reported for a file
+#[derive(Debug)]
+pub struct FileReport<'a> {
+    pub path: &'a Path,
+    pub diagnostics: Vec<&'a Diagnostic>,
+}
+
+/// Check files as described by `options`, printing their diagnostics, and
+/// return the exit code of `dls check`
+pub fn run(options: CheckOptions) -> i32 {
+    match check(&options) {
+        Ok(true) => EXIT_ERRORS,
+        Ok(false) => 0,
+        Err(e) => {
+            eprintln!("dls check: {}", e);
+            EXIT_FAILURE
+        },
+    }
+}
+
+// Check files, returning whether any errors were reported
+fn check(options: &CheckOptions) -> anyhow::Result<bool> {
+    let mut files = vec![];
+    for path in &options.paths {
+        if path.is_dir() {
+            files.extend(dml_files(path, usize::MAX));
+        } else if path.is_file() {
+            files.push(path.clone());
+        } else {
+            bail!("No such file or directory: {}", path.display());
+        }
+    }
+    files.sort();
+    files.dedup();
+    if files.is_empty() {
+        bail!("No DML files to check");
+    }
+    // The first directory given is the workspace, or else the directory of
+    // the first file
+    let root = options.paths.iter().find(|path|path.is_dir())
+        .map(PathBuf::as_path)
+        .or_else(|| files[0].parent())
+        .filter(|root|!root.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+        .canonicalize()?;
+    debug!("Checking {:?} in workspace {:?}", files, root);
+
+    let server = std::env::current_exe()?;
+    let mut client = ClientInterface::start(&server, &root,
+                                            options.linting_enabled)?;
+    client.set_config(Config {
+        compile_info_path: options.compile_info_path.clone(),
+        linting_enabled: options.linting_enabled,
+        lint_cfg_path: options.lint_cfg_path.clone(),
+        // Checking must not leave a symbol index in the checked workspace
+        persist_symbol_index: false,
+        .. Default::default()
+    })?;
+    for file in &files {
+        client.open_file(file)?;
+    }
+    client.wait_for_analysis().map_err(
+        |status|anyhow!("The server exited during analysis ({:?})", status))?;
+
+    let mut reports: Vec<FileReport<'_>> = client.diagnostics().iter()
+        .filter(|(_, diagnostics)|!diagnostics.is_empty())
+        .map(|(path, diagnostics)| {
+            let mut diagnostics: Vec<&Diagnostic> =
+                diagnostics.iter().collect();
+            diagnostics.sort_by_key(|diag|(diag.line, diag.column));
+            FileReport { path: path.as_path(), diagnostics }
+        })
+        .collect();
+    reports.sort_by_key(|report|report.path);
+    let has_errors = reports.iter().flat_map(|report|&report.diagnostics)
+        .any(|diag|is_error(diag));
+    println!("{}", render(options.format, &reports, files.len()));
+
+    // Everything has been reported, so a failed shutdown does not matter
+    client.shutdown().ok();
+    Ok(has_errors)
+}
+
+// Diagnostics without a severity are errors, as for dmlc
+fn is_error(diag: &Diagnostic) -> bool {
+    diag.severity.is_none_or(|severity|severity == DiagnosticSeverity::ERROR)
+}
+
+fn severity_name(diag: &Diagnostic) -> &'static str {
+    match diag.severity {
+        Some(DiagnosticSeverity::WARNING) => "warning",
+        Some(DiagnosticSeverity::INFORMATION) => "info",
+        Some(DiagnosticSeverity::HINT) => "hint",
+        _ => "error",
+    }
+}
+
+fn sarif_level(diag: &Diagnostic) -> &'static str {
+    match diag.severity {
+        Some(DiagnosticSeverity::WARNING) => "warning",
+        Some(DiagnosticSeverity::INFORMATION) |
+        Some(DiagnosticSeverity::HINT) => "note",
+        _ => "error",
+    }
+}
+
+// The SARIF location of a file, relative to the source root `base` when it
+// is inside it, so that the results can be matched to files in a checkout
+fn sarif_artifact_location(path: &Path, base: Option<&Path>) -> Value {
+    let encode = |path: &Path| path.iter()
+        .filter(|part|*part != "/" && *part != "\\")
+        .map(|part|urlencoding::encode(&part.to_string_lossy()).into_owned())
+        .collect::<Vec<_>>().join("/");
+    match base.and_then(|base|path.strip_prefix(base).ok()) {
+        Some(relative) => json!({
+            "uri": encode(relative),
+            "uriBaseId": "%SRCROOT%",
+        }),
+        None => json!({"uri": format!("file:///{}", encode(path))}),
+    }
+}
+
+/// The output of `dls check` in `format`, for the diagnostics of some of
+/// `checked` files. Lines and columns are one-indexed in all formats.
+pub fn render(format: OutputFormat, reports: &[FileReport<'_>],
+              checked: usize) -> String {
+    let diagnostics = || reports.iter().flat_map(
+        |report|report.diagnostics.iter().map(
+            move |diag|(report.path, *diag)));
+    let errors = diagnostics().filter(|(_, diag)|is_error(diag)).count();
+    let warnings = diagnostics()
+        .filter(|(_, diag)|diag.severity == Some(DiagnosticSeverity::WARNING))
+        .count();
+    match format {
+        OutputFormat::Human => {
+            let mut output = String::new();
+            for (path, diag) in diagnostics() {
+                output.push_str(&format!("{}:{}:{}: {}: {}\n",
+                                         path.display(), diag.line + 1,
+                                         diag.column + 1, severity_name(diag),
+                                         diag.desc));
+            }
+            output.push_str(&format!(
+                "Checked {} file{}: {} error{}, {} warning{}",
+                checked, if checked == 1 { "" } else { "s" },
+                errors, if errors == 1 { "" } else { "s" },
+                warnings, if warnings == 1 { "" } else { "s" }));
+            output
+        },
+        OutputFormat::Json => {
+            let files: Vec<Value> = reports.iter().map(|report| json!({
+                "path": report.path,
+                "diagnostics": report.diagnostics.iter().map(|diag| json!({
+                    "line": diag.line + 1,
+                    "column": diag.column + 1,
+                    "end_line": diag.end_line + 1,
+                    "end_column": diag.end_column + 1,
+                    "severity": severity_name(diag),
+                    "message": diag.desc,
+                })).collect::<Vec<_>>(),
+            })).collect();
+            serde_json::to_string_pretty(&json!({
+                "checked": checked,
+                "errors": errors,
+                "warnings": warnings,
+                "files": files,
+            })).unwrap()
+        },
+        OutputFormat::Sarif => {
+            let base = std::env::current_dir().ok();
+            let results: Vec<Value> = diagnostics().map(|(path, diag)| {
+                json!({
+                    "level": sarif_level(diag),
+                    "message": {"text": diag.desc},
+                    "locations": [{
+                        "physicalLocation": {
+                            "artifactLocation": sarif_artifact_location(
+                                path, base.as_deref()),
+                            "region": {
+                                "startLine": diag.line + 1,
+                                "startColumn": diag.column + 1,
+                                "endLine": diag.end_line + 1,
+                                "endColumn": diag.end_column + 1,
+                            }
+                        }
+                    }]
+                })
+            }).collect();
+            serde_json::to_string_pretty(&json!({
+                "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+                "version": "2.1.0",
+                "runs": [{
+                    "tool": {
+                        "driver": {
+                            "name": "dls",
+                            "version": env!("CARGO_PKG_VERSION"),
+                            "informationUri":
+                            "https://github.com/intel/dml-language-server",
+                        }
+                    },
+                    "results": results,
+                }]
+            })).unwrap()
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn diagnostic(line: u32, severity: DiagnosticSeverity, desc: &str)
+                  -> Diagnostic {
+        Diagnostic {
+            line,
+            column: 4,
+            end_line: line,
+            end_column: 9,
+            severity: Some(severity),
+            desc: desc.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_render_check_output() {
+        let (error, warning) = (
+            diagnostic(2, DiagnosticSeverity::ERROR, "Unknown template"),
+            diagnostic(7, DiagnosticSeverity::WARNING, "Line too long"));
+        let reports = [FileReport {
+            path: Path::new("/work/dev.dml"),
+            diagnostics: vec![&error, &warning],
+        }];
+
+        assert_eq!(render(OutputFormat::Human, &reports, 2),
+                   "/work/dev.dml:3:5: error: Unknown template\n\
+                    /work/dev.dml:8:5: warning: Line too long\n\
+                    Checked 2 files: 1 error, 1 warning");
+        assert_eq!(render(OutputFormat::Human, &[], 1),
+                   "Checked 1 file: 0 errors, 0 warnings");
+
+        let json: Value = serde_json::from_str(
+            &render(OutputFormat::Json, &reports, 2)).unwrap();
+        assert_eq!(json["errors"], 1);
+        assert_eq!(json["files"][0]["diagnostics"][1], json!({
+            "line": 8, "column": 5, "end_line": 8, "end_column": 10,
+            "severity": "warning", "message": "Line too long",
+        }));
+
+        let sarif: Value = serde_json::from_str(
+            &render(OutputFormat::Sarif, &reports, 2)).unwrap();
+        assert_eq!(sarif["version"], "2.1.0");
+        let results = &sarif["runs"][0]["results"];
+        assert_eq!(results[0]["level"], "error");
+        assert_eq!(results[1]["level"], "warning");
+        let location = &results[0]["locations"][0]["physicalLocation"];
+        assert_eq!(location["artifactLocation"]["uri"], "file:///work/dev.dml");
+        assert_eq!(location["region"]["startLine"], 3);
+        assert_eq!(sarif_artifact_location(Path::new("/work/my dev/a.dml"),
+                                           Some(Path::new("/work"))),
+                   json!({"uri": "my%20dev/a.dml", "uriBaseId": "%SRCROOT%"}));
+    }
+}
@@ -2,9 +2,11 @@
 //  SPDX-License-Identifier: Apache-2.0 and MIT
 use std::thread;
 use std::collections::HashMap;
-use std::sync::{Arc, Weak};
+use std::sync::{Arc, RwLock, Weak};
 
 use crossbeam::channel::{bounded, select, Receiver, Select, Sender};
+use lazy_static::lazy_static;
+use log::{debug, error, info};
 
 /// `ConcurrentJob` is a handle for some long-running computation
 /// off the main thread. It can be used, indirectly, to wait for
@@ -41,6 +43,9 @@ impl JobStatusKeeper {
     }
 }
 
+/// Panic message with which killed jobs stop
+const KILLED_JOB_PANIC: &str = "Sub-job killed";
+
 #[derive(Clone, Debug)]
 pub struct AliveStatus(Weak<()>);
 
@@ -50,7 +55,7 @@ impl AliveStatus {
     }
     pub fn assert_alive(&self) {
         if !self.is_alive() {
-            panic!("Sub-job killed");
+            panic!("{}", KILLED_JOB_PANIC);
         }
     }
 }
@@ -174,3 +179,74 @@ fn is_closed(chan: &Receiver<Never>) -> bool {
         default => false,
     }
 }
+
+/// Upper bound of the autodetected number of analysis threads. Analyses of
+/// separate files and devices rarely keep more threads busy, and on large
+/// build servers the remaining cores are better left to other jobs.
+const MAX_DEFAULT_ANALYSIS_THREADS: usize = 16;
+
+/// The number of threads that can run in parallel, respecting the CPU
+/// affinity and quota of the process
+pub fn available_threads() -> usize {
+    thread::available_parallelism().map_or(1, |threads|threads.get())
+}
+
+/// The number of analysis threads to use, `configured` or else one less
+/// than the available threads, so that the client is not starved
+pub fn analysis_threads(configured: Option<usize>) -> usize {
+    configured.filter(|threads|*threads > 0).unwrap_or_else(
+        ||(available_threads() - 1).clamp(1, MAX_DEFAULT_ANALYSIS_THREADS))
+}
+
+/// Log the panic of an analysis job, which is expected when the job was
+/// killed and a bug otherwise
+fn log_job_panic(payload: Box<dyn std::any::Any + Send>) {
+    let message = payload.downcast_ref::<&str>().copied()
+        .or_else(||payload.downcast_ref::<String>().map(String::as_str));
+    match message {
+        Some(KILLED_JOB_PANIC) => debug!("Killed analysis job stopped"),
+        Some(message) => error!("Analysis job panicked: {}", message),
+        None => error!("Analysis job panicked"),
+    }
+}
+
+fn build_analysis_pool(threads: usize) -> rayon::ThreadPool {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .thread_name(|num| format!("analysis-worker-{}", num))
+        // Killed jobs panic to stop, which must not abort the server
+        .panic_handler(log_job_panic)
+        .build()
+        .unwrap()
+}
+
+lazy_static! {
+    /// Thread pool running analysis jobs, and the parallel work within them
+    static ref ANALYSIS_POOL: RwLock<Arc<rayon::ThreadPool>> =
+        RwLock::new(Arc::new(build_analysis_pool(analysis_threads(None))));
+}
+
+/// Resize the analysis thread pool, see `analysis_threads`. Jobs already
+/// running finish on the threads of the previous pool.
+pub fn set_analysis_threads(configured: Option<usize>) {
+    let threads = analysis_threads(configured);
+    let mut pool = ANALYSIS_POOL.write().unwrap();
+    if pool.current_num_threads() != threads {
+        info!("Using {} analysis threads", threads);
+        *pool = Arc::new(build_analysis_pool(threads));
+    }
+}
+
+/// Run analysis work on the analysis thread pool
+pub fn spawn_analysis<F>(work: F) where F: FnOnce() + Send + 'static {
+    let pool = Arc::clone(&ANALYSIS_POOL.read().unwrap());
+    pool.spawn(work);
+}
+
+/// Run parallel work on the analysis thread pool and wait for it, so that
+/// it uses no more threads than analysis does
+pub fn install_analysis<F, R>(work: F) -> R
+where F: FnOnce() -> R + Send, R: Send {
+    let pool = Arc::clone(&ANALYSIS_POOL.read().unwrap());
+    pool.install(work)
+}
@@ -11,7 +11,11 @@ use serde::de::Deserializer;
 use serde::{Deserialize, Serialize};
 
 use log::{error, trace};
+use lsp_types::DiagnosticSeverity;
 
+use crate::analysis::AnalysisOptions;
+use crate::analysis::quick_fixes::ImportGrouping;
+use crate::analysis::unused::UnusedKind;
 use crate::lsp_data::SerializeError;
 
 /// Some values in the config can be inferred without an explicit value set by
@@ -137,6 +141,95 @@ pub struct Config {
     pub compile_info_path: Option<PathBuf>,
     pub analysis_retain_duration: Option<f64>,
     pub new_device_context_mode: DeviceContextMode,
+    /// Simics API version whose DML standard library is in use, e.g. "7".
+    /// Detected from the compile info or the library location when unset
+    pub simics_api_version: Option<String>,
+    /// `true` to keep the workspace symbol index in `.dls-cache` under each
+    /// workspace root, so that it survives server restarts
+    /// Default: `true`.
+    pub persist_symbol_index: bool,
+    /// `true` to use less memory at the cost of speed, e.g. for analyzing
+    /// large code bases in constrained CI containers: syntax trees are not
+    /// kept after analysis, references in method bodies are not resolved, and
+    /// reference lookup caches are bounded
+    /// Default: `false`.
+    pub low_memory: bool,
+    /// Number of threads analyzing files and devices in parallel. Set lower
+    /// to keep cores free for other work, e.g. on shared build servers
+    /// Default: one less than the available cores, at most 16.
+    pub analysis_threads: Option<usize>,
+    /// Directories searched for imported files after the include paths and
+    /// the workspace, such as the `dml/` base library of a Simics
+    /// installation, e.g. `["/opt/simics/simics-7/linux64/bin/dml"]`
+    pub library_paths: Vec<PathBuf>,
+    /// How imports, templates, methods and parameters that nothing in the
+    /// devices using them uses are reported, e.g.
+    /// `{"imports": "warning", "parameters": "off"}`
+    pub unused_declarations: UnusedDeclarations,
+    /// How the "Organize imports" source action groups imports: `"base-first"`
+    /// to put imports of the Simics base library before those of the project,
+    /// or `"single"` to sort all imports together
+    /// Default: `"base-first"`.
+    pub import_grouping: ImportGrouping,
+    /// `true` to also organize the imports of documents when formatting them,
+    /// without removing unused ones
+    /// Default: `false`.
+    pub organize_imports_on_format: bool,
+}
+
+/// The severity of a kind of diagnostic, or "off" to not report it
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DiagnosticLevel {
+    Off,
+    Hint,
+    Information,
+    Warning,
+    Error,
+}
+
+impl DiagnosticLevel {
+    pub fn severity(self) -> Option<DiagnosticSeverity> {
+        match self {
+            DiagnosticLevel::Off => None,
+            DiagnosticLevel::Hint => Some(DiagnosticSeverity::HINT),
+            DiagnosticLevel::Information =>
+                Some(DiagnosticSeverity::INFORMATION),
+            DiagnosticLevel::Warning => Some(DiagnosticSeverity::WARNING),
+            DiagnosticLevel::Error => Some(DiagnosticSeverity::ERROR),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(default)]
+pub struct UnusedDeclarations {
+    pub imports: DiagnosticLevel,
+    pub templates: DiagnosticLevel,
+    pub methods: DiagnosticLevel,
+    pub parameters: DiagnosticLevel,
+}
+
+impl Default for UnusedDeclarations {
+    fn default() -> Self {
+        UnusedDeclarations {
+            imports: DiagnosticLevel::Warning,
+            templates: DiagnosticLevel::Hint,
+            methods: DiagnosticLevel::Hint,
+            parameters: DiagnosticLevel::Hint,
+        }
+    }
+}
+
+impl UnusedDeclarations {
+    pub fn severity(&self, kind: UnusedKind) -> Option<DiagnosticSeverity> {
+        match kind {
+            UnusedKind::Import => self.imports,
+            UnusedKind::Template => self.templates,
+            UnusedKind::Method => self.methods,
+            UnusedKind::Parameter => self.parameters,
+        }.severity()
+    }
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -222,6 +315,14 @@ impl Default for Config {
             compile_info_path: None,
             analysis_retain_duration: None,
             new_device_context_mode: DeviceContextMode::Always,
+            simics_api_version: None,
+            persist_symbol_index: true,
+            low_memory: false,
+            analysis_threads: None,
+            library_paths: vec![],
+            unused_declarations: UnusedDeclarations::default(),
+            import_grouping: ImportGrouping::default(),
+            organize_imports_on_format: false,
         }
     }
 }
@@ -232,6 +333,14 @@ lazy_static::lazy_static! {
 }
 
 impl Config {
+    pub fn analysis_options(&self) -> AnalysisOptions {
+        if self.low_memory {
+            AnalysisOptions::low_memory()
+        } else {
+            AnalysisOptions::default()
+        }
+    }
+
     #[allow(clippy::ptr_arg)]
     pub fn try_deserialize_vec(
         vals: &Vec<(String, serde_json::value::Value)>,
@@ -30,16 +30,25 @@ use crate::lsp_data::{parse_file_path, parse_uri};
 
 use log::{debug, trace};
 
+/// A diagnostic reported by the server, with zero-indexed positions
 #[derive(Debug, PartialEq)]
 pub struct Diagnostic {
-    line: u32,
-    desc: String,
+    pub line: u32,
+    pub column: u32,
+    pub end_line: u32,
+    pub end_column: u32,
+    pub severity: Option<DiagnosticSeverity>,
+    pub desc: String,
 }
 
 impl From<lsp_types::Diagnostic> for Diagnostic {
     fn from(diag: lsp_types::Diagnostic) -> Diagnostic {
         Diagnostic {
             line: diag.range.start.line,
+            column: diag.range.start.character,
+            end_line: diag.range.end.line,
+            end_column: diag.range.end.character,
+            severity: diag.severity,
             desc: diag.message,
         }
     }
@@ -398,6 +407,11 @@ impl ClientInterface {
         }
     }
 
+    /// The diagnostics last reported for each file
+    pub fn diagnostics(&self) -> &HashMap<CanonPath, Vec<Diagnostic>> {
+        &self.diagnostics
+    }
+
     pub fn no_errors(&self) -> bool {
         self.diagnostics.is_empty()
     }
@@ -44,6 +44,15 @@ impl CanonPath {
             fs::canonicalize(from).map(CanonPath)
         }
 
+    /// The canonical path of a file that may since have been removed,
+    /// which is that of its directory joined with its name
+    pub fn from_removed_path_buf(from: PathBuf) -> Option<CanonPath> {
+        Self::from_path_buf(from.clone()).or_else(||{
+            let dir = Self::from_path_buf(from.parent()?.to_path_buf())?;
+            Some(CanonPath(dir.0.join(from.file_name()?)))
+        })
+    }
+
     pub fn as_str(&self) -> &str {
         self.0.to_str().unwrap()
     }
@@ -52,6 +61,18 @@ impl CanonPath {
     }
 }
 
+/// The DML files under `dir`, leaving out hidden directories such as .git
+pub fn dml_files(dir: &Path, max_depth: usize)
+                 -> impl Iterator<Item = PathBuf> {
+    walkdir::WalkDir::new(dir).max_depth(max_depth).into_iter()
+        .filter_entry(|entry|entry.depth() == 0
+                      || !entry.file_name().to_string_lossy().starts_with('.'))
+        .filter_map(Result::ok)
+        .filter(|entry|entry.file_type().is_file()
+                && entry.path().extension().is_some_and(|ext|ext == "dml"))
+        .map(walkdir::DirEntry::into_path)
+}
+
 /// This is how we resolve relative paths to in-workspace full paths
 #[derive(Debug, Clone)]
 pub struct PathResolver {
@@ -59,6 +80,8 @@ pub struct PathResolver {
     // import resolution
     roots: Vec<PathBuf>,
     include_paths: HashMap<CanonPath, Vec<PathBuf>>,
+    // Searched after everything else
+    library_paths: Vec<PathBuf>,
     #[allow(clippy::type_complexity)]
     cache: RefCell<HashMap<(PathBuf, Option<CanonPath>), Option<CanonPath>>>,
 }
@@ -72,6 +95,7 @@ impl From<Option<PathBuf>> for PathResolver {
         PathResolver {
             roots,
             include_paths: HashMap::default(),
+            library_paths: vec![],
             cache: RefCell::default(),
         }
     }
@@ -90,6 +114,23 @@ impl PathResolver {
         self.include_paths = include_paths.clone();
     }
 
+    pub fn set_library_paths(&mut self, library_paths: &[PathBuf]) {
+        self.library_paths = library_paths.to_vec();
+    }
+
+    /// The include and library paths imports are searched in, other than
+    /// the roots
+    pub fn search_dirs(&self) -> Vec<PathBuf> {
+        let mut dirs: Vec<PathBuf> = vec![];
+        for dir in self.include_paths.values().flatten()
+            .chain(&self.library_paths) {
+                if !dirs.contains(dir) {
+                    dirs.push(dir.clone());
+                }
+            }
+        dirs
+    }
+
     pub fn resolve_under_any_context(&self, path: &Path)
                                      -> Option<CanonPath> {
         for context in self.include_paths.keys() {
@@ -98,7 +139,7 @@ impl PathResolver {
                 return Some(cp);
             }
         }
-        None
+        self.resolve_with_maybe_context(path, None, None)
     }
 
     pub fn resolve_with_maybe_context(&self,
@@ -190,6 +231,7 @@ impl PathResolver {
         if let Some(extra) = extra_path {
             roots.push(extra.clone().into());
         }
+        roots.extend(self.library_paths.iter().cloned());
         // directory order is largely undefined, I think
         for dir in roots {
             for to_try in [dir.join(path), dir.join("1.4").join(path)] {
@@ -205,3 +247,27 @@ impl PathResolver {
         None
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_removed_path_and_search_dirs() {
+        let dir = CanonPath::from_path_buf(std::env::temp_dir()).unwrap();
+        assert_eq!(CanonPath::from_removed_path_buf(
+            dir.join("dls-no-such-file.dml")).unwrap().as_path(),
+                   dir.join("dls-no-such-file.dml"));
+        assert!(CanonPath::from_removed_path_buf(
+            dir.join("dls-no-such-dir").join("file.dml")).is_none());
+
+        let mut resolver = PathResolver::from(None);
+        resolver.set_include_paths(&HashMap::from([
+            (dir.clone(), vec![PathBuf::from("/a"), PathBuf::from("/b")])]));
+        resolver.set_library_paths(&[PathBuf::from("/b"),
+                                     PathBuf::from("/c")]);
+        assert_eq!(resolver.search_dirs(),
+                   vec![PathBuf::from("/a"), PathBuf::from("/b"),
+                        PathBuf::from("/c")]);
+    }
+}
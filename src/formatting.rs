@@ -0,0 +1,857 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Formatting of DML source
+//!
+//! The formatter re-indents and re-spaces code in the style checked by the
+//! linter, without changing its tokens. Lines are broken where they are in
+//! the source, at most one blank line in a row is kept, and the contents of
+//! comments and C blocks are kept as written. Only code without syntax
+//! errors is formatted, and formatted code formats to itself.
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use logos::Logos;
+use lsp_types::DiagnosticSeverity;
+use serde::{Deserialize, Serialize};
+
+use crate::analysis::parsing::lexer::TokenKind;
+use crate::analysis::quick_fixes::{organized_imports, ImportGrouping};
+use crate::analysis::{parse_file, FileSpec};
+use crate::file_management::dml_files;
+use crate::vfs::TextFile;
+
+/// How code is indented
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IndentStyle {
+    Spaces(usize),
+    Tabs,
+}
+
+impl IndentStyle {
+    /// One level of indentation
+    pub fn unit(&self) -> String {
+        match self {
+            IndentStyle::Spaces(n) => " ".repeat(*n),
+            IndentStyle::Tabs => "\t".to_string(),
+        }
+    }
+}
+
+/// The layout of DML code, shared by the formatter and the code generator
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FormatConfig {
+    pub indent_style: IndentStyle,
+    /// Generated code is wrapped to lines of at most this many columns, and
+    /// formatted lines continued inside parentheses are only aligned with
+    /// the parenthesis when they then fit in this many columns
+    pub max_line_length: usize,
+    /// Formatting whole files also sorts and deduplicates their imports in
+    /// these groups, when set
+    #[serde(default)]
+    pub organize_imports: Option<ImportGrouping>,
+}
+
+impl Default for FormatConfig {
+    fn default() -> Self {
+        FormatConfig {
+            indent_style: IndentStyle::Spaces(4),
+            max_line_length: 100,
+            organize_imports: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FormatError {
+    /// The code does not parse, with the first error on a zero-indexed line
+    Syntax { line: u32, message: String },
+    /// Formatting would have changed more than whitespace
+    Internal,
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormatError::Syntax { line, message } =>
+                write!(f, "Cannot format code with syntax errors \
+                           (line {}: {})", line + 1, message),
+            FormatError::Internal =>
+                write!(f, "Formatting would change more than whitespace, \
+                           so the code was left as is"),
+        }
+    }
+}
+
+impl std::error::Error for FormatError {}
+
+/// A token, with the whitespace before it
+#[derive(Debug, Clone, Copy)]
+struct Token<'a> {
+    kind: TokenKind,
+    text: &'a str,
+    space: &'a str,
+}
+
+// The tokens on each line of `text`, `None` if it does not lex. Comments
+// and C blocks spanning several lines belong to the line they start on.
+fn lex_lines(text: &str) -> Option<Vec<Vec<Token<'_>>>> {
+    let mut lines = vec![vec![]];
+    let mut lexer = TokenKind::lexer(text);
+    let mut space = "";
+    while let Some(kind) = lexer.next() {
+        let kind = kind.ok().filter(|kind|*kind != TokenKind::LexerError)?;
+        let slice = lexer.slice();
+        match kind {
+            TokenKind::Whitespace => {
+                space = slice;
+                continue;
+            },
+            TokenKind::Newline => lines.push(vec![]),
+            // Line comments include the newline ending them
+            TokenKind::Comment => {
+                lines.last_mut().unwrap().push(Token {
+                    kind, text: slice.trim_end(), space,
+                });
+                if slice.ends_with('\n') {
+                    lines.push(vec![]);
+                }
+            },
+            _ => lines.last_mut().unwrap().push(Token {
+                kind, text: slice, space,
+            }),
+        }
+        space = "";
+    }
+    Some(lines)
+}
+
+fn is_comment(kind: TokenKind) -> bool {
+    matches!(kind, TokenKind::Comment | TokenKind::MultilineComment)
+}
+
+// The tokens of `text`, leaving out whitespace and comments
+fn code_tokens(text: &str) -> Vec<(Option<TokenKind>, &str)> {
+    let mut lexer = TokenKind::lexer(text);
+    let mut tokens = vec![];
+    while let Some(kind) = lexer.next() {
+        let kind = kind.ok();
+        if !kind.is_some_and(|kind|is_comment(kind) || matches!(
+            kind, TokenKind::Whitespace | TokenKind::Newline)) {
+            tokens.push((kind, lexer.slice()));
+        }
+    }
+    tokens
+}
+
+// Keywords, identifiers and constants
+fn is_word(token: &Token<'_>) -> bool {
+    token.text.starts_with(|c: char|c.is_alphanumeric()
+                           || matches!(c, '_' | '"' | '\''))
+        || matches!(token.kind, TokenKind::HashIf | TokenKind::HashElse
+                    | TokenKind::HashForeach | TokenKind::HashSelect)
+}
+
+// Tokens after which `+`, `-`, `*` and `&` are binary operators
+fn ends_operand(kind: TokenKind) -> bool {
+    use TokenKind::*;
+    matches!(kind,
+             Identifier | IntConstant | HexConstant | BinaryConstant
+             | FloatConstant | StringConstant | CharConstant | RParen
+             | RBracket | This | Undefined | Char | Double | Float | Int
+             | Long | Short | Signed | Unsigned | Void)
+}
+
+// Operators that are always binary
+fn is_binary_operator(kind: TokenKind) -> bool {
+    use TokenKind::*;
+    matches!(kind,
+             Divide | Mod | BinOr | BinXor | LShift | RShift | Or | And
+             | Equals | GreaterThan | LessThan | NotEquals | GEquals
+             | LEquals | Assign | TimesAssign | DivideAssign | ModAssign
+             | PlusAssign | MinusAssign | LShiftAssign | RShiftAssign
+             | BAndAssign | BXorAssign | BOrAssign | CondOp | HashCondOp
+             | HashColon)
+}
+
+// Keywords separated from a parenthesis after them
+fn spaced_before_paren(kind: TokenKind) -> bool {
+    use TokenKind::*;
+    matches!(kind,
+             If | While | For | Foreach | Switch | HashIf | HashForeach
+             | HashSelect | Select | Is | Each | In | Return | Throws | After
+             | Assert | Where | Then | Catch | With | Try | Await | Async
+             | Log | Else | Do | Local | Session | Saved)
+}
+
+/// What a token of code is to the tokens around it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Role {
+    /// Ends an operand, including postfix `++` and `--`
+    Operand,
+    /// A prefix operator
+    Prefix,
+    /// Spaced on both sides
+    Spaced,
+    /// A binary `*` or `&`, which may also be part of a pointer type, so it
+    /// keeps its spacing
+    Kept,
+    /// Not spaced on either side
+    Tight,
+    /// The colon of a case label
+    Label,
+    Other,
+}
+
+/// Whether there is a space between two tokens on the same line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Space {
+    Yes,
+    No,
+    /// If there is one in the source
+    Keep,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Paren {
+    bracket: bool,
+    /// The column of lines continued inside the parenthesis
+    column: usize,
+    /// The indentation of the line of the parenthesis
+    line_indent: usize,
+    /// Whether the parenthesis ends its line
+    hanging: bool,
+    /// Whether `column` is still relative to the indentation of the line
+    /// being laid out
+    pending: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Block {
+    switch: bool,
+    /// The braces of an initializer, whose elements are laid out like
+    /// statements
+    initializer: bool,
+}
+
+#[derive(Clone)]
+struct Formatter<'c> {
+    config: &'c FormatConfig,
+    unit: String,
+    blocks: Vec<Block>,
+    parens: Vec<Paren>,
+    /// The last token of code, and its role
+    last: Option<(TokenKind, Role)>,
+    /// The first token of the statement being laid out
+    statement: Option<TokenKind>,
+    /// Conditional expressions whose `:` has not been laid out
+    conditionals: usize,
+}
+
+impl<'c> Formatter<'c> {
+    fn new(config: &'c FormatConfig) -> Self {
+        Formatter {
+            config,
+            unit: config.indent_style.unit(),
+            blocks: vec![],
+            parens: vec![],
+            last: None,
+            statement: None,
+            conditionals: 0,
+        }
+    }
+
+    fn role(&self, kind: TokenKind) -> Role {
+        use TokenKind::*;
+        let after_operand = self.last.is_some_and(
+            |(_, role)|role == Role::Operand);
+        match kind {
+            PlusPlus | MinusMinus | Plus | Minus | Multiply | BinAnd
+                if !after_operand => Role::Prefix,
+            PlusPlus | MinusMinus => Role::Operand,
+            Plus | Minus => Role::Spaced,
+            Multiply | BinAnd => Role::Kept,
+            Not | BinNot => Role::Prefix,
+            Colon if self.conditionals > 0 => Role::Spaced,
+            Colon if self.parens.is_empty()
+                && matches!(self.statement, Some(Case | Default)) =>
+                Role::Label,
+            Colon if self.parens.last().is_some_and(|paren|paren.bracket) =>
+                Role::Tight,
+            Dot => Role::Tight,
+            _ if is_binary_operator(kind) => Role::Spaced,
+            _ if ends_operand(kind) => Role::Operand,
+            _ => Role::Other,
+        }
+    }
+
+    fn space(&self, prev: &Token<'_>, token: &Token<'_>, role: Role,
+             next: Option<&Token<'_>>) -> Space {
+        use TokenKind::*;
+        let prev_role = self.last.map_or(Role::Other, |(_, role)|role);
+        match (prev.kind, token.kind) {
+            (MultilineComment, _) => Space::Keep,
+            (_, Comma | SemiColon) => Space::No,
+            (Comma | SemiColon, _) => Space::Yes,
+            (LParen | LBracket, _) | (_, RParen | RBracket) => Space::No,
+            _ if prev_role == Role::Tight || role == Role::Tight => Space::No,
+            _ if role == Role::Label => Space::No,
+            _ if prev_role == Role::Label => Space::Yes,
+            // Arrows are only spaced before the outputs of methods
+            (_, Arrow) if next.is_some_and(|next|next.kind == LParen) =>
+                Space::Yes,
+            (Arrow, LParen) => Space::Yes,
+            (_, Arrow) | (Arrow, _) => Space::No,
+            (_, LBrace) => Space::Yes,
+            // Initializers keep the spacing inside their braces
+            (LBrace, _) | (_, RBrace) if self.blocks.last().is_some_and(
+                |block|block.initializer) => Space::Keep,
+            (LBrace, RBrace) => Space::Keep,
+            (LBrace, _) | (_, RBrace) | (RBrace, _) => Space::Yes,
+            _ if prev_role == Role::Spaced || role == Role::Spaced =>
+                Space::Yes,
+            _ if prev_role == Role::Prefix => Space::No,
+            _ if prev_role == Role::Kept || role == Role::Kept => Space::Keep,
+            (_, PlusPlus | MinusMinus) if role == Role::Operand => Space::No,
+            (Identifier | RParen | RBracket, LParen) => Space::No,
+            (kind, LParen) if spaced_before_paren(kind) => Space::Yes,
+            (_, LBracket) if prev_role == Role::Operand => Space::No,
+            (RParen | RBracket, _) if is_word(token) || role == Role::Prefix =>
+                Space::Yes,
+            _ if is_word(prev) && (is_word(token) || role == Role::Prefix) =>
+                Space::Yes,
+            _ => Space::Keep,
+        }
+    }
+
+    // Lay out a token of code with some role, ending `column` characters
+    // into its line
+    fn advance(&mut self, token: &Token<'_>, role: Role, column: usize,
+               ends_line: bool) {
+        use TokenKind::*;
+        if self.statement.is_none() {
+            self.statement = Some(token.kind);
+        }
+        match token.kind {
+            LParen | LBracket => self.parens.push(Paren {
+                bracket: token.kind == LBracket,
+                column,
+                line_indent: 0,
+                hanging: ends_line,
+                pending: true,
+            }),
+            RParen | RBracket => {
+                self.parens.pop();
+            },
+            LBrace => {
+                let in_initializer = self.blocks.last().is_some_and(
+                    |block|block.initializer);
+                let initializer = match self.last {
+                    Some((Assign, _)) => true,
+                    Some((Comma | LBrace, _)) => in_initializer,
+                    _ => false,
+                };
+                self.blocks.push(Block {
+                    switch: self.statement == Some(Switch),
+                    initializer,
+                });
+                self.statement = None;
+            },
+            RBrace => {
+                let initializer = self.blocks.pop().is_some_and(
+                    |block|block.initializer);
+                // The statement of an initializer continues after it
+                self.statement = initializer.then_some(RBrace);
+            },
+            SemiColon if self.parens.is_empty() => {
+                self.statement = None;
+                self.conditionals = 0;
+            },
+            Comma if self.parens.is_empty() && self.blocks.last().is_some_and(
+                |block|block.initializer) => self.statement = None,
+            CBlock if self.parens.is_empty() => self.statement = None,
+            CondOp | HashCondOp => self.conditionals += 1,
+            Colon | HashColon if role == Role::Spaced =>
+                self.conditionals = self.conditionals.saturating_sub(1),
+            Colon if role == Role::Label => self.statement = None,
+            _ => (),
+        }
+        self.last = Some((token.kind, role));
+    }
+
+    // The indentation of a line starting with `first`, whose contents are
+    // `width` characters wide
+    fn indentation(&self, first: &Token<'_>, width: usize) -> String {
+        use TokenKind::*;
+        let mut depth = self.blocks.len();
+        if first.kind == RBrace
+            || matches!(first.kind, Case | Default)
+            && self.statement.is_none()
+            && self.blocks.last().is_some_and(|block|block.switch) {
+            depth = depth.saturating_sub(1);
+        }
+        let block = self.unit.repeat(depth);
+        let Some(paren) = self.parens.last() else {
+            let continued = self.statement.is_some()
+                && !matches!(first.kind, LBrace | RBrace);
+            return if continued {
+                format!("{}{}", block, self.unit)
+            } else {
+                block
+            };
+        };
+        if self.config.indent_style == IndentStyle::Tabs {
+            return format!("{}{}", block, self.unit);
+        }
+        let hang = paren.line_indent + self.unit.len();
+        let column = if matches!(first.kind, RParen | RBracket) {
+            if paren.hanging { paren.line_indent } else { paren.column - 1 }
+        } else if paren.hanging
+            || paren.column + width > self.config.max_line_length
+            && hang < paren.column {
+            hang
+        } else {
+            paren.column
+        };
+        " ".repeat(column)
+    }
+
+    // Lay out the tokens of a line
+    fn line(&mut self, tokens: &[Token<'_>]) -> String {
+        let first = &tokens[0];
+        // The indentation depends on the state at the start of the line,
+        // and on the width of its contents
+        let start = self.clone();
+        let mut content = String::new();
+        for (index, token) in tokens.iter().enumerate() {
+            let role = self.role(token.kind);
+            if index > 0 {
+                let space = if is_comment(token.kind) {
+                    token.space
+                } else {
+                    match self.space(&tokens[index - 1], token, role,
+                                     tokens.get(index + 1)) {
+                        Space::Yes => " ",
+                        Space::No => "",
+                        Space::Keep if token.space.is_empty() => "",
+                        Space::Keep => " ",
+                    }
+                };
+                content.push_str(space);
+            }
+            content.push_str(token.text);
+            if is_comment(token.kind) {
+                continue;
+            }
+            // Columns after tokens spanning lines are absolute
+            let line_start = content.rfind('\n').map(|i|i + 1);
+            let column = content[line_start.unwrap_or(0)..].chars().count();
+            let ends_line = tokens[index + 1..].iter()
+                .all(|token|is_comment(token.kind));
+            self.advance(token, role, column, ends_line);
+            if line_start.is_some() {
+                if let Some(paren) = self.parens.last_mut() {
+                    paren.pending = false;
+                }
+            }
+        }
+        let width = content.lines().next().unwrap_or_default()
+            .chars().count();
+        let indent = start.indentation(first, width);
+        let indent_width = indent.chars().count();
+        for paren in self.parens.iter_mut().filter(|paren|paren.pending) {
+            paren.column += indent_width;
+            paren.line_indent = indent_width;
+            if paren.hanging {
+                paren.column = indent_width + self.unit.len();
+            }
+            paren.pending = false;
+        }
+        if first.kind == TokenKind::MultilineComment {
+            content = shift_comment(&content, first.space, &indent);
+        }
+        format!("{}{}", indent, content)
+    }
+}
+
+// Shift the lines after the first of a comment starting a line by as many
+// columns as its first line moved, if both are indented with spaces
+fn shift_comment(comment: &str, from: &str, to: &str) -> String {
+    if from == to || from.contains('\t') || to.contains('\t') {
+        return comment.to_string();
+    }
+    let mut lines = comment.split('\n');
+    let mut shifted = lines.next().unwrap_or_default().to_string();
+    for line in lines {
+        shifted.push('\n');
+        if to.len() > from.len() {
+            if !line.trim().is_empty() {
+                shifted.push_str(&" ".repeat(to.len() - from.len()));
+            }
+            shifted.push_str(line);
+        } else {
+            let leading = line.len() - line.trim_start_matches(' ').len();
+            shifted.push_str(&line[leading.min(from.len() - to.len())..]);
+        }
+    }
+    shifted
+}
+
+/// A line of formatted code, replacing lines of the source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineEdit {
+    /// The zero-indexed source lines replaced, several for comments and C
+    /// blocks spanning lines
+    pub rows: RangeInclusive<u32>,
+    /// The formatted line without its line ending, `None` if the source
+    /// lines are removed
+    pub text: Option<String>,
+}
+
+// The formatted lines of `text`, one for each line of the source except
+// those continuing comments and C blocks, and the formatted text
+fn layout(text: &str, config: &FormatConfig)
+          -> Result<(Vec<LineEdit>, String), FormatError> {
+    let file = TextFile::from_str(text).map_err(|_|FormatError::Internal)?;
+    let path = Path::new("formatted.dml");
+    let (_, _, errors) = parse_file(path, FileSpec { path, file: &file })
+        .map_err(|_|FormatError::Internal)?;
+    if let Some(error) = errors.iter()
+        .filter(|error|error.severity.is_none_or(
+            |severity|severity == DiagnosticSeverity::ERROR))
+        .min_by_key(|error|error.span.range) {
+        return Err(FormatError::Syntax {
+            line: error.span.range.row_start.0,
+            message: error.description.clone(),
+        });
+    }
+    let mut lines = lex_lines(text).ok_or_else(|| FormatError::Syntax {
+        line: 0, message: "Unrecognized token".to_string(),
+    })?;
+    // Nothing follows the last line ending
+    if lines.last().is_some_and(Vec::is_empty) {
+        lines.pop();
+    }
+
+    let mut formatter = Formatter::new(config);
+    let mut formatted: Vec<LineEdit> = vec![];
+    let mut row = 0;
+    for tokens in &lines {
+        let continued = tokens.iter()
+            .map(|token|token.text.matches('\n').count() as u32).sum::<u32>();
+        // At most one blank line in a row is kept, and none first
+        let text = if !tokens.is_empty() {
+            Some(formatter.line(tokens))
+        } else if formatted.last().is_some_and(
+            |line|line.text.as_ref().is_some_and(|text|!text.is_empty())) {
+            Some(String::new())
+        } else {
+            None
+        };
+        formatted.push(LineEdit { rows: row..=row + continued, text });
+        row += continued + 1;
+    }
+    for line in formatted.iter_mut().rev() {
+        if line.text.as_ref().is_some_and(|text|!text.is_empty()) {
+            break;
+        }
+        line.text = None;
+    }
+    let newline = if text.contains("\r\n") { "\r\n" } else { "\n" };
+    let mut output = String::new();
+    for line in formatted.iter().filter_map(|line|line.text.as_ref()) {
+        output.push_str(line);
+        output.push_str(newline);
+    }
+
+    if code_tokens(&output) != code_tokens(text) {
+        return Err(FormatError::Internal);
+    }
+    Ok((formatted, output))
+}
+
+/// Format DML code, see the module documentation, organizing its imports
+/// if the configuration says so
+pub fn format_source(text: &str, config: &FormatConfig)
+                     -> Result<String, FormatError> {
+    let (_, output) = layout(text, config)?;
+    // The imports of formatted code are formatted, so organizing them
+    // keeps the code formatted
+    Ok(match config.organize_imports {
+        Some(grouping) => organized_imports(&output, grouping),
+        None => output,
+    })
+}
+
+/// The edits formatting the zero-indexed lines `rows` of `text`, leaving
+/// out lines that are already formatted. The lines are formatted as part of
+/// the whole text, which must not have syntax errors. Imports are not
+/// organized.
+pub fn format_lines(text: &str, config: &FormatConfig,
+                    rows: RangeInclusive<u32>)
+                    -> Result<Vec<LineEdit>, FormatError> {
+    let source: Vec<&str> = text.split('\n').collect();
+    let (lines, _) = layout(text, config)?;
+    Ok(lines.into_iter()
+       .filter(|line|line.rows.start() <= rows.end()
+               && rows.start() <= line.rows.end())
+       .filter(|line|match &line.text {
+           Some(formatted) => {
+               let rows = *line.rows.start() as usize
+                   ..=*line.rows.end() as usize;
+               source[rows].join("\n").trim_end_matches('\r') != formatted
+           },
+           None => true,
+       })
+       .collect())
+}
+
+/// The edit indenting the zero-indexed line `row` of `text` by the depth of
+/// the braces around it, `None` if it is blank, continues a comment or C
+/// block, or is already indented so. Unlike `format_lines`, this only lexes
+/// the text up to the line, so it works on code being typed, which rarely
+/// parses.
+pub fn reindent_line(text: &str, row: u32, config: &FormatConfig)
+                     -> Option<LineEdit> {
+    let line = text.split('\n').nth(row as usize)?.trim_end_matches('\r');
+    let code = line.trim_start();
+    if code.is_empty() {
+        return None;
+    }
+    let mut depth: usize = 0;
+    let mut current = 0;
+    let mut lexer = TokenKind::lexer(text);
+    while let Some(kind) = lexer.next() {
+        if current == row && kind != Ok(TokenKind::Whitespace) {
+            // A line starting with a closing brace is outside its block
+            if kind == Ok(TokenKind::RBrace) {
+                depth = depth.saturating_sub(1);
+            }
+            break;
+        }
+        match kind {
+            Ok(TokenKind::LBrace) => depth += 1,
+            Ok(TokenKind::RBrace) => depth = depth.saturating_sub(1),
+            _ => (),
+        }
+        // Tokens other than newlines and line comments continuing on the
+        // line mean that it is part of a comment or C block
+        let slice = lexer.slice();
+        current += slice.matches('\n').count() as u32;
+        if current > row || current == row && slice.contains('\n')
+            && !slice.ends_with('\n') {
+            return None;
+        }
+    }
+    let indented = format!("{}{}", config.indent_style.unit().repeat(depth),
+                           code);
+    (indented != line).then_some(LineEdit {
+        rows: row..=row,
+        text: Some(indented),
+    })
+}
+
+/// Exit code of `dls fmt --check` when files are not formatted
+pub const EXIT_UNFORMATTED: i32 = 1;
+/// Exit code of `dls fmt` when files could not be formatted
+pub const EXIT_FAILURE: i32 = 2;
+
+/// Format DML files, and the DML files under directories, in place, or
+/// with `check` only list those that are not formatted. Returns the exit
+/// code of `dls fmt`.
+pub fn run(paths: &[PathBuf], check: bool, config: &FormatConfig) -> i32 {
+    let mut files = vec![];
+    for path in paths {
+        if path.is_dir() {
+            files.extend(dml_files(path, usize::MAX));
+        } else if path.is_file() {
+            files.push(path.clone());
+        } else {
+            eprintln!("dls fmt: No such file or directory: {}",
+                      path.display());
+            return EXIT_FAILURE;
+        }
+    }
+    files.sort();
+    files.dedup();
+
+    let (mut failed, mut unformatted) = (false, false);
+    for file in &files {
+        let result = std::fs::read_to_string(file)
+            .map_err(|e|e.to_string())
+            .and_then(|text|format_source(&text, config)
+                      .map(|formatted|(formatted != text, formatted))
+                      .map_err(|e|e.to_string()));
+        let error = match result {
+            Ok((false, _)) => continue,
+            Ok((true, _)) if check => {
+                println!("{}", file.display());
+                unformatted = true;
+                continue;
+            },
+            Ok((true, formatted)) => match std::fs::write(file, formatted) {
+                Ok(()) => continue,
+                Err(e) => e.to_string(),
+            },
+            Err(e) => e,
+        };
+        eprintln!("dls fmt: {}: {}", file.display(), error);
+        failed = true;
+    }
+    if failed {
+        EXIT_FAILURE
+    } else if unformatted {
+        EXIT_UNFORMATTED
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn format_file(text: &str) -> String {
+        let formatted = format_source(text, &FormatConfig::default())
+            .unwrap();
+        assert_eq!(format_source(&formatted, &FormatConfig::default()),
+                   Ok(formatted.clone()), "Formatting is not idempotent");
+        formatted
+    }
+
+    // Format the statements of a DML 1.4 file
+    fn format(text: &str) -> String {
+        format_file(&format!("dml 1.4;\n{}", text))
+            .strip_prefix("dml 1.4;\n").unwrap().to_string()
+    }
+
+    #[test]
+    fn test_format_indentation() {
+        assert_eq!(format_file("\n\ndml 1.4;\ndevice d;\n\n\n\n\
+                           bank b {\nregister r @ 0x0 {\n\
+                           method read() -> (uint64) {\n\
+                           switch (x) {\n   case 1:\nreturn 1;\n\
+                           default:\n  return 2;\n}\n}\n}\n}\n\n"),
+                   "dml 1.4;\ndevice d;\n\n\
+                    bank b {\n    register r @ 0x0 {\n        \
+                    method read() -> (uint64) {\n            \
+                    switch (x) {\n            case 1:\n                \
+                    return 1;\n            default:\n                \
+                    return 2;\n            }\n        }\n    }\n}\n");
+        // Continuation lines
+        assert_eq!(format("method m() {\nif (a)\nx = 1;\nlocal int y = a\n\
+                           + b;\nfoo(a,\nb);\nbar(\nc\n);\n}\n"),
+                   "method m() {\n    if (a)\n        x = 1;\n    \
+                    local int y = a\n        + b;\n    foo(a,\n        \
+                    b);\n    bar(\n        c\n    );\n}\n");
+        assert_eq!(format("method m() {\nlocal int a[2] = {\n1,\n2\n};\n}\n"),
+                   "method m() {\n    local int a[2] = {\n        1,\n        \
+                    2\n    };\n}\n");
+        assert_eq!(format("bank b {\n  /* a\n     b */\n  // c   \n}\n"),
+                   "bank b {\n    /* a\n       b */\n    // c\n}\n");
+        let tabs = FormatConfig {
+            indent_style: IndentStyle::Tabs,
+            .. FormatConfig::default()
+        };
+        assert_eq!(format_source("dml 1.4;\nbank b {\nparam p = f(1,\n2);\n}\n",
+                                 &tabs),
+                   Ok("dml 1.4;\nbank b {\n\tparam p = f(1,\n\t\t2);\n}\n".to_string()));
+    }
+
+    #[test]
+    fn test_format_spacing() {
+        assert_eq!(format("method  m(int a,int b)->(int){\n\
+                           local int x=-a+b*2 ;\nif(x>0&&!a){x++;}\n\
+                           x = a?b:c;\nreturn x [ 3 : 0 ];\n}\n"),
+                   "method m(int a, int b) -> (int) {\n    \
+                    local int x = -a + b*2;\n    \
+                    if (x > 0 && !a) { x++; }\n    x = a ? b : c;\n    \
+                    return x[3:0];\n}\n");
+        assert_eq!(format("session int *p;\nmethod m() {\n\
+                           x = p->f + sizeof (y);  // c\n}\n"),
+                   "session int *p;\nmethod m() {\n    \
+                    x = p->f + sizeof (y);  // c\n}\n");
+    }
+
+    #[test]
+    fn test_format_line_endings() {
+        assert_eq!(format_file("dml 1.4;\r\n\r\nbank b{\r\n}\r\n"),
+                   "dml 1.4;\r\n\r\nbank b {\r\n}\r\n");
+    }
+
+    #[test]
+    fn test_format_lines() {
+        let text = "dml 1.4;\nbank b {\nparam p=1;\n\n\n/* a\n  b */\n\
+                    param q = 2;\n}\n";
+        assert_eq!(format_lines(text, &FormatConfig::default(), 2..=5),
+                   Ok(vec![
+                       LineEdit {
+                           rows: 2..=2,
+                           text: Some("    param p = 1;".to_string()),
+                       },
+                       LineEdit { rows: 4..=4, text: None },
+                       LineEdit {
+                           rows: 5..=6,
+                           text: Some("    /* a\n      b */".to_string()),
+                       },
+                   ]));
+        assert_eq!(format_lines(text, &FormatConfig::default(), 0..=1),
+                   Ok(vec![]));
+    }
+
+    #[test]
+    fn test_reindent_line() {
+        let config = FormatConfig::default();
+        // Unterminated, so the text does not parse
+        let text = "dml 1.4;\nbank b {\nregister r {\n/* {\n { */ x\n\
+                    \"{\" }\n  }\n";
+        assert_eq!(reindent_line(text, 2, &config), Some(LineEdit {
+            rows: 2..=2,
+            text: Some("    register r {".to_string()),
+        }));
+        assert_eq!(reindent_line(text, 3, &config), Some(LineEdit {
+            rows: 3..=3,
+            text: Some("        /* {".to_string()),
+        }));
+        assert_eq!(reindent_line(text, 4, &config), None);
+        assert_eq!(reindent_line(text, 5, &config), Some(LineEdit {
+            rows: 5..=5,
+            text: Some("        \"{\" }".to_string()),
+        }));
+        assert_eq!(reindent_line(text, 6, &config), Some(LineEdit {
+            rows: 6..=6,
+            text: Some("}".to_string()),
+        }));
+        assert_eq!(reindent_line(text, 7, &config), None);
+        assert_eq!(reindent_line("a {\r\n\tb;\r\n", 1, &FormatConfig {
+            indent_style: IndentStyle::Tabs,
+            .. FormatConfig::default()
+        }), None);
+    }
+
+    #[test]
+    fn test_format_syntax_errors() {
+        assert!(matches!(
+            format_source("dml 1.4;\nbank b {\n", &FormatConfig::default()),
+            Err(FormatError::Syntax { line: 2, .. })));
+    }
+
+    #[test]
+    fn test_format_organize_imports() {
+        let config = FormatConfig {
+            organize_imports: Some(ImportGrouping::BaseFirst),
+            ..FormatConfig::default()
+        };
+        let source = "dml 1.4;\nimport   \"b.dml\";\nimport \"utility.dml\";\n\
+                      import \"a.dml\";\n";
+        let formatted = format_source(source, &config).unwrap();
+        assert_eq!(formatted, "dml 1.4;\nimport \"utility.dml\";\n\n\
+                               import \"a.dml\";\nimport \"b.dml\";\n");
+        assert_eq!(format_source(&formatted, &config), Ok(formatted.clone()));
+        // Imports are only organized when asked to
+        assert_eq!(format_file(source), "dml 1.4;\nimport \"b.dml\";\n\
+                                         import \"utility.dml\";\n\
+                                         import \"a.dml\";\n");
+    }
+}
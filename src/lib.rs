@@ -28,19 +28,41 @@ macro_rules! internal_error {
     }
 }
 
+#[cfg(feature = "lsp")]
 #[macro_use]
 pub mod actions;
 pub mod analysis;
+#[cfg(any(feature = "lsp", feature = "mcp"))]
+pub mod auth;
+#[cfg(feature = "lsp")]
+pub mod bench;
+#[cfg(any(feature = "lsp", feature = "mcp"))]
+pub mod cache;
+#[cfg(any(feature = "lsp", feature = "mcp"))]
+pub mod catalog;
+#[cfg(feature = "lsp")]
+pub mod check;
+#[cfg(feature = "lsp")]
 pub mod cmd;
 pub mod concurrency;
+#[cfg(feature = "lsp")]
 pub mod config;
+#[cfg(feature = "lsp")]
 pub mod dfa;
 pub mod file_management;
+pub mod formatting;
 pub mod lint;
+#[cfg(feature = "lsp")]
 pub mod lsp_data;
+#[cfg(feature = "mcp")]
 pub mod mcp;
+pub mod migration;
+#[cfg(any(feature = "lsp", feature = "mcp"))]
+pub mod project;
+#[cfg(feature = "lsp")]
 pub mod server;
 pub mod span;
+pub mod stats;
 pub mod utility;
 pub mod vfs;
 #[cfg(test)]
@@ -48,6 +70,7 @@ pub mod test;
 pub mod logging;
 pub mod file_tests;
 
+#[cfg(feature = "lsp")]
 type Span = span::Span<span::ZeroIndexed>;
 
 pub fn version() -> String {
@@ -6,7 +6,7 @@ use std::fs;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use lazy_static::lazy_static;
-use log::{debug, error, trace};
+use log::{debug, trace};
 use rules::linelength::{BreakBeforeBinaryOpOptions,
     BreakFuncCallOpenParenOptions,
     BreakMethodOutputOptions,
@@ -34,16 +34,15 @@ use rules::{spacing::{SpReservedOptions,
                 IndentSwitchCaseOptions,
                 IndentEmptyLoopOptions,
                 IndentContinuationLineOptions}};
-use crate::analysis::{DMLError, IsolatedAnalysis, LocalDMLError, ZeroRange};
+use crate::analysis::{parse_file, DMLError, FileSpec, IsolatedAnalysis,
+                      LocalDMLError, ZeroRange};
 use crate::analysis::parsing::tree::TreeElement;
 use crate::file_management::CanonPath;
+use crate::stats::{self, Phase};
 use crate::vfs::{Error, TextFile};
 use crate::analysis::parsing::structure::TopAst;
 use crate::lint::rules::indentation::{MAX_LENGTH_DEFAULT,
-                                      INDENTATION_LEVEL_DEFAULT,
-                                      setup_indentation_size
-                                    };
-use crate::server::{maybe_notify_unknown_lint_fields, Output};
+                                      INDENTATION_LEVEL_DEFAULT};
 use crate::concurrency::AliveStatus;
 
 pub fn parse_lint_cfg(path: PathBuf) -> Result<(LintCfg, Vec<String>), String> {
@@ -60,21 +59,6 @@ pub fn parse_lint_cfg(path: PathBuf) -> Result<(LintCfg, Vec<String>), String> {
     Ok((cfg, unknowns))
 }
 
-pub fn maybe_parse_lint_cfg<O: Output>(path: PathBuf, out: &O) -> Option<LintCfg> {
-    match parse_lint_cfg(path) {
-        Ok((mut cfg, unknowns)) => {
-            // Send visible warning to client
-            maybe_notify_unknown_lint_fields(out, &unknowns);
-            setup_indentation_size(&mut cfg);
-            Some(cfg)
-        },
-        Err(e) => {
-            error!("Failed to parse linting CFG: {}", e);
-            None
-        }
-    }
-}
-
 
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
@@ -218,8 +202,15 @@ impl LinterAnalysis {
             .map_err(|e|Error::Io(Some(path.to_path_buf()),
                                   Some(e.to_string())))?;
         let rules =  instantiate_rules(&cfg);
-        let local_lint_errors = begin_style_check(
-            original_analysis.ast, &file.text, &rules)?;
+        let ast = match original_analysis.ast {
+            Some(ast) => ast,
+            // Not kept in low-memory mode
+            None => parse_file(&canonpath, FileSpec {
+                path: &canonpath, file: &file
+            })?.0,
+        };
+        let local_lint_errors = stats::timed(
+            Phase::Lint, ||begin_style_check(ast, &file.text, &rules))?;
         status.assert_alive();
         let mut lint_errors = vec![];
         for entry in local_lint_errors {
@@ -618,4 +609,29 @@ method my_method() { /* however, this long line will not be allowed even though
                        &set_up()
         );
     }
+
+    #[test]
+    fn test_lint_without_kept_ast() {
+        use super::*;
+        use crate::concurrency::JobStatusKeeper;
+
+        let path = std::env::temp_dir().join(
+            format!("dls-lint-{}.dml", std::process::id()));
+        let source = "dml 1.4;\ndevice test;\nbank b {\n\tparam p = 1;\n}\n";
+        fs::write(&path, source).unwrap();
+        let canon_path = CanonPath::from_path_buf(path.clone()).unwrap();
+        let file = TextFile::from_str(source).unwrap();
+        let (_keeper, status) = JobStatusKeeper::new();
+        let mut analysis = IsolatedAnalysis::new(
+            &canon_path, &path, file.clone(), status.clone()).unwrap();
+        let lint = |analysis| LinterAnalysis::new(
+            &path, file.clone(), LintCfg::default(), analysis,
+            status.clone()).unwrap().errors;
+        let errors = lint(analysis.clone());
+        assert!(!errors.is_empty());
+        // The file is re-parsed when the syntax tree was not kept
+        analysis.ast = None;
+        assert_eq!(lint(analysis), errors);
+        fs::remove_file(&path).unwrap();
+    }
 }
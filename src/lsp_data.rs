@@ -289,6 +289,9 @@ pub struct InitializationOptions {
     /// `true` if analysis should not be triggered immediately after receiving `initialize`.
     pub omit_init_analyse: bool,
     pub cmd_run: bool,
+    /// `true` to warm up the server right after `initialize`, as by a
+    /// `server/warmup` request
+    pub warmup: bool,
     /// `DidChangeConfigurationParams.settings` payload for upfront configuration.
     pub settings: Option<ChangeConfigSettings>,
 }
@@ -353,6 +356,12 @@ impl ClientCapabilities {
         self.capabilities.workspace.as_ref()
             .map_or(false, |w|w.configuration.unwrap_or(false))
     }
+    pub fn snippet_support(&self) -> bool {
+        self.capabilities.text_document.as_ref()
+            .and_then(|t|t.completion.as_ref())
+            .and_then(|c|c.completion_item.as_ref())
+            .is_some_and(|i|i.snippet_support.unwrap_or(false))
+    }
 }
 
 #[cfg(test)]
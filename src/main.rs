@@ -5,10 +5,20 @@
 //! The DLS provides a server that runs in the background, providing IDEs,
 //! editors, and other tools with information about DML devices.
 use log::debug;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
-use clap::{Parser, command, arg};
+use clap::{Parser, Subcommand, command, arg};
+
+use dls::auth::AuthToken;
+use dls::bench::BenchOptions;
+use dls::analysis::quick_fixes::ImportGrouping;
+use dls::check::{CheckOptions, OutputFormat};
+use dls::formatting::{FormatConfig, IndentStyle};
+#[cfg(feature = "exporters")]
+use dls::mcp::exporters::DocFormat;
 
 /// The main entry point to the DLS.
 // Parses CLI arguments and then runs the server.
@@ -27,33 +37,234 @@ pub fn main() {
                         order to provide syntactic and semantic analysis \
                         and feedback to DML files.")]
 pub struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
     #[arg(long = "cli")]
     /// Starts the DLS in command line mode
     cli: bool,
-    /// Optional DML compile-info file (cli only)
-    #[arg(long = "compile-info")]
+    /// Optional DML compile-info file (cli and check only)
+    #[arg(long = "compile-info", global = true)]
     compile_info_path: Option<PathBuf>,
     /// Turn linting on or off (default on)
-    #[arg(short = 'l', long = "linting")]
+    #[arg(short = 'l', long = "linting", global = true)]
     linting_enabled: Option<bool>,
-    /// Optional Lint CFG (cli only)
-    #[arg(long = "lint-cfg")]
+    /// Optional Lint CFG (cli, check and bench only)
+    #[arg(long = "lint-cfg", global = true)]
     lint_cfg_path: Option<PathBuf>,
+    /// Analyze the workspace and standard library in the background right
+    /// after initialization
+    #[arg(long = "warmup")]
+    warmup: bool,
+    /// Neither read nor write the workspace caches under `.dls-cache`
+    #[arg(long = "no-cache")]
+    no_cache: bool,
+    /// Listen for clients on this TCP port instead of using stdin/out
+    #[arg(long = "port", conflicts_with = "socket")]
+    port: Option<u16>,
+    /// The address to listen on with --port
+    #[arg(long = "host", requires = "port",
+          default_value_t = IpAddr::V4(Ipv4Addr::LOCALHOST))]
+    host: IpAddr,
+    /// Listen for clients on this Unix domain socket instead of using
+    /// stdin/out
+    #[cfg(unix)]
+    #[arg(long = "socket")]
+    socket: Option<PathBuf>,
+    /// File holding the token clients connecting with --port or --socket
+    /// must present (default: the DLS_AUTH_TOKEN environment variable, if
+    /// set)
+    #[arg(long = "auth-token-file")]
+    auth_token_file: Option<PathBuf>,
+    /// Seconds a client connecting with --port or --socket may send nothing
+    /// before it is disconnected, or 0 to never disconnect it
+    #[arg(long = "idle-timeout",
+          default_value_t = dls::server::DEFAULT_IDLE_TIMEOUT.as_secs())]
+    idle_timeout: u64,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Analyze DML files without an editor and print their diagnostics,
+    /// exiting with status 1 if there are errors
+    Check {
+        /// DML files, or directories to check all DML files under
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        /// How to print the diagnostics
+        #[arg(long = "format", value_enum, default_value_t)]
+        format: OutputFormat,
+    },
+    /// Format DML files in place
+    Fmt {
+        /// DML files, or directories to format all DML files under
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        /// Only list the files that are not formatted, exiting with status
+        /// 1 if there are any
+        #[arg(long = "check")]
+        check: bool,
+        /// Spaces per indentation level
+        #[arg(long = "indent", default_value_t = 4)]
+        indent: usize,
+        /// Indent with tabs instead of spaces
+        #[arg(long = "tabs", conflicts_with = "indent")]
+        tabs: bool,
+        /// Lines continued inside parentheses are aligned with the
+        /// parenthesis only if they fit in this many columns
+        #[arg(long = "max-line-length", default_value_t = 100)]
+        max_line_length: usize,
+        /// Also sort and deduplicate imports, in these groups
+        #[arg(long = "organize-imports", value_enum, num_args = 0..=1,
+              default_missing_value = "base-first")]
+        organize_imports: Option<ImportGrouping>,
+    },
+    /// Migrate DML 1.2 files to DML 1.4 in place, printing what is left to
+    /// migrate by hand and exiting with status 1 if there is any
+    Migrate {
+        /// DML files, or directories to migrate all DML 1.2 files under
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        /// Only print what is left to migrate by hand, without changing
+        /// the files
+        #[arg(long = "dry-run")]
+        dry_run: bool,
+    },
+    /// Measure the throughput of parsing, analyzing and linting DML files,
+    /// first with cold caches and then with warm ones
+    Bench {
+        /// DML files, or directories to analyze all DML files under
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        /// Directories to look up imports in, such as that of the standard
+        /// library
+        #[arg(short = 'I', long = "include-path")]
+        include_paths: Vec<PathBuf>,
+        /// Passes over the files after the cold one
+        #[arg(long = "warm-passes", default_value_t = 3)]
+        warm_passes: usize,
+        /// Threads to analyze files and devices on in parallel (default:
+        /// one less than the available cores, at most 16)
+        #[arg(long = "analysis-threads")]
+        analysis_threads: Option<usize>,
+        /// Print the results as JSON
+        #[arg(long = "json")]
+        json: bool,
+    },
+    /// Generate the documentation of a device, with its register tables,
+    /// field layouts and methods
+    #[cfg(feature = "exporters")]
+    Docgen {
+        /// DML file declaring the device, or device specification file
+        path: PathBuf,
+        /// Format of the documentation
+        #[arg(long = "format", value_enum, default_value_t)]
+        format: DocFormat,
+        /// File to write the documentation to, instead of printing it
+        #[arg(short = 'o', long = "output")]
+        output: Option<PathBuf>,
+    },
+    /// Call the MCP tools recorded in an audit log of the MCP server again,
+    /// exiting with status 1 if any result differs from the recorded one
+    #[cfg(feature = "mcp")]
+    McpReplay {
+        /// Audit log written by dml-mcp-server --audit-log
+        log: PathBuf,
+        /// Only replay the calls of this tool
+        #[arg(long = "tool")]
+        tool: Option<String>,
+        /// Print the result of each call
+        #[arg(long = "show")]
+        show: bool,
+    },
 }
 
 fn main_inner() -> i32 {
     debug!("server main called");
     let Args {
+        command,
         cli,
         compile_info_path,
         linting_enabled,
         lint_cfg_path,
+        warmup,
+        no_cache,
+        port,
+        host,
+        #[cfg(unix)]
+        socket,
+        auth_token_file,
+        idle_timeout,
     } = Args::parse();
-    if cli {
-        dls::cmd::run(compile_info_path, linting_enabled, lint_cfg_path);
-        0
-    } else {
-        let vfs = Arc::new(dls::vfs::Vfs::new());
-        dls::server::run_server(vfs)
+    let idle_timeout = (idle_timeout > 0)
+        .then(|| Duration::from_secs(idle_timeout));
+    dls::cache::set_enabled(!no_cache);
+    match command {
+        Some(Command::Check { paths, format }) =>
+            dls::check::run(CheckOptions {
+                paths,
+                format,
+                compile_info_path,
+                linting_enabled: linting_enabled.unwrap_or(true),
+                lint_cfg_path,
+            }),
+        Some(Command::Fmt { paths, check, indent, tabs, max_line_length,
+                            organize_imports }) =>
+            dls::formatting::run(&paths, check, &FormatConfig {
+                indent_style: if tabs {
+                    IndentStyle::Tabs
+                } else {
+                    IndentStyle::Spaces(indent)
+                },
+                max_line_length,
+                organize_imports,
+            }),
+        Some(Command::Migrate { paths, dry_run }) =>
+            dls::migration::run(&paths, dry_run),
+        Some(Command::Bench { paths, include_paths, warm_passes,
+                              analysis_threads, json }) =>
+            dls::bench::run(BenchOptions {
+                paths,
+                include_paths,
+                warm_passes,
+                analysis_threads,
+                linting_enabled: linting_enabled.unwrap_or(true),
+                lint_cfg_path,
+                json,
+            }),
+        #[cfg(feature = "exporters")]
+        Some(Command::Docgen { path, format, output }) =>
+            dls::mcp::exporters::docgen::run(&path, format, output.as_deref()),
+        #[cfg(feature = "mcp")]
+        Some(Command::McpReplay { log, tool, show }) =>
+            dls::mcp::audit::run(&log, tool.as_deref(), show),
+        None if cli => {
+            dls::cmd::run(compile_info_path, linting_enabled, lint_cfg_path);
+            0
+        },
+        None if port.is_some() => match auth_token(auth_token_file) {
+            Ok(token) => dls::server::run_tcp_server(
+                SocketAddr::new(host, port.unwrap()), warmup, token,
+                idle_timeout),
+            Err(code) => code,
+        },
+        #[cfg(unix)]
+        None if socket.is_some() => match auth_token(auth_token_file) {
+            Ok(token) => dls::server::run_unix_server(
+                &socket.unwrap(), warmup, token, idle_timeout),
+            Err(code) => code,
+        },
+        None => {
+            let vfs = Arc::new(dls::vfs::Vfs::new());
+            dls::server::run_server(vfs, warmup)
+        },
     }
 }
+
+/// The token clients connecting over the network must present, or the exit
+/// code if it cannot be read
+fn auth_token(file: Option<PathBuf>) -> Result<Option<AuthToken>, i32> {
+    AuthToken::configured(file.as_deref()).map_err(|e| {
+        eprintln!("dls: {}", e);
+        1
+    })
+}
@@ -0,0 +1,546 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Bridge between MCP tools and the DML analysis engine
+//!
+//! MCP tools receive DML as in-memory text as often as they receive paths
+//! into a workspace, so this module runs the parser and the structural pass
+//! directly instead of going through the language server analysis queue.
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use crate::analysis::parsing::tree::ZeroSpan;
+use crate::analysis::structure::expressions::{BinOp, ExpressionKind,
+                                              IntegerLiteral, MathOp,
+                                              UnaryOp};
+use crate::analysis::structure::objects::{CompObjectKind, CompositeObject,
+                                          DMLObject, DMLStatement, Method,
+                                          MethodArgument, ParamValue,
+                                          Parameter, Statements};
+use crate::analysis::structure::toplevel::TopLevel;
+use crate::analysis::{parse_file, AnalysisOptions, DMLError, DeviceAnalysis,
+                      FileSpec, IsolatedAnalysis, TimestampedStorage};
+use crate::concurrency::JobStatusKeeper;
+use crate::file_management::{CanonPath, PathResolver};
+use crate::vfs::TextFile;
+
+use super::generation::{ArrayDimension, BankSpec, ConnectSpec, DeviceSpec,
+                        FieldSpec, InterfaceSpec, MethodSpec, ParameterSpec,
+                        RegisterSpec};
+
+/// A DML file that has been parsed and structured, but not resolved
+#[derive(Debug)]
+pub struct ParsedDML {
+    pub path: PathBuf,
+    pub text: String,
+    pub toplevel: TopLevel,
+    pub errors: Vec<DMLError>,
+}
+
+/// Parse DML source text, reporting syntax and structural errors
+pub fn parse_dml(path: &Path, text: &str) -> Result<ParsedDML> {
+    let file = TextFile::from_str(text)
+        .map_err(|_| anyhow!("Could not read DML source"))?;
+    let filespec = FileSpec { path, file: &file };
+    let (ast, _, mut errors) = parse_file(path, filespec)
+        .map_err(|e| anyhow!("Failed to parse {}: {:?}", path.display(), e))?;
+    let mut report = vec![];
+    let toplevel = TopLevel::from_ast(&ast, &mut report, filespec);
+    errors.extend(report.into_iter().map(|e| e.with_file(path)));
+    Ok(ParsedDML {
+        path: path.to_path_buf(),
+        text: text.to_string(),
+        toplevel,
+        errors,
+    })
+}
+
+/// Read and parse a DML file from disk
+pub fn parse_dml_file(path: &Path) -> Result<ParsedDML> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("Could not read {}: {}", path.display(), e))?;
+    parse_dml(path, &text)
+}
+
+impl ParsedDML {
+    /// The source text covered by a span within this file
+    pub fn source_of(&self, span: &ZeroSpan) -> String {
+        let range = span.range;
+        let (row_start, row_end) = (range.row_start.0 as usize,
+                                    range.row_end.0 as usize);
+        let (col_start, col_end) = (range.col_start.0 as usize,
+                                    range.col_end.0 as usize);
+        let mut result = String::new();
+        for (row, line) in self.text.lines().enumerate()
+            .skip(row_start).take(row_end + 1 - row_start) {
+                let start = if row == row_start { col_start } else { 0 };
+                let end = if row == row_end { col_end } else { usize::MAX };
+                if row != row_start {
+                    result.push('\n');
+                }
+                result.extend(line.chars().skip(start)
+                              .take(end.saturating_sub(start)));
+            }
+        result
+    }
+
+    /// Extract the register map of the parsed file as a device specification
+    ///
+    /// Only the structure that can be determined without resolving templates
+    /// is extracted: banks, registers (also inside groups, with qualified
+    /// names), fields, connects and implemented interfaces. Expressions that are not
+    /// integer constants are kept as their source text.
+    pub fn to_device_spec(&self) -> DeviceSpec {
+        let name = self.toplevel.device.as_ref()
+            .map(|dev| dev.name.val.to_string())
+            .unwrap_or_else(|| self.path.file_stem()
+                            .map(|s| s.to_string_lossy().to_string())
+                            .unwrap_or_default());
+        let mut spec = DeviceSpec {
+            name,
+            base_template: None,
+            templates: self.toplevel.spec.instantiations.iter()
+                .flat_map(|inst| inst.obj.names.iter())
+                .map(|name| name.val.to_string())
+                .collect(),
+            documentation: None,
+            banks: vec![],
+            interfaces: vec![],
+            connects: vec![],
+            ports: vec![],
+            variables: vec![],
+            events: vec![],
+            methods: self.toplevel.spec.methods.iter()
+                .map(|decl| self.method_spec(&decl.obj))
+                .collect(),
+            dependencies: self.toplevel.spec.imports.iter()
+                .map(|imp| imp.obj.imported_name().to_string())
+                .collect(),
+            constants: vec![],
+            typedefs: vec![],
+            structs: vec![],
+            variants: vec![],
+        };
+        for param in &self.toplevel.spec.params {
+            if param.obj.object.name.val == "desc" {
+                spec.documentation = self.param_string(&param.obj);
+            }
+        }
+        for obj in self.toplevel.spec.objects.iter().map(|decl| &decl.obj) {
+            match obj.comp_kind() {
+                CompObjectKind::Bank => spec.banks.push(self.bank_spec(obj)),
+                CompObjectKind::Implement => spec.interfaces.push(
+                    InterfaceSpec { name: obj.object.name.val.to_string(), methods: vec![] }),
+                CompObjectKind::Connect => spec.connects.push(ConnectSpec {
+                    name: obj.object.name.val.to_string(),
+                    documentation: self.documentation(obj),
+                    interfaces: composite_objects(&obj.statements)
+                        .filter(|iface| iface.comp_kind()
+                                == CompObjectKind::Interface)
+                        .map(|iface| iface.object.name.val.to_string())
+                        .collect(),
+                }),
+                _ => (),
+            }
+        }
+        spec
+    }
+
+    // The signature of a method; its body is left out, as it is only
+    // known as statements and not as the source it was written as. The
+    // types are taken from the source of the signature, as the structure
+    // only records where they are declared.
+    fn method_spec(&self, method: &Method) -> MethodSpec {
+        let source = self.source_of(&method.object.span);
+        let signature = source.split('{').next().unwrap_or_default();
+        let (arguments, returns) = split_signature(signature);
+        MethodSpec {
+            name: method.object.name.val.to_string(),
+            parameters: method.arguments.iter().zip(
+                arguments.iter().map(String::as_str)
+                    .chain(std::iter::repeat("")))
+                .map(|(arg, declaration)| match arg {
+                    MethodArgument::Typed(name, _) => ParameterSpec {
+                        name: name.val.to_string(),
+                        param_type: declaration.trim()
+                            .strip_suffix(name.val.as_str())
+                            .unwrap_or(declaration).trim().to_string(),
+                    },
+                    MethodArgument::Inline(name) => ParameterSpec {
+                        name: name.val.to_string(),
+                        param_type: "inline".to_string(),
+                    },
+                })
+                .collect(),
+            return_type: returns,
+            body: None,
+            documentation: None,
+        }
+    }
+
+    fn bank_spec(&self, bank: &CompositeObject) -> BankSpec {
+        let mut registers = vec![];
+        self.collect_registers(&bank.statements, None, &mut registers);
+        BankSpec {
+            name: bank.object.name.val.to_string(),
+            documentation: self.documentation(bank),
+            registers,
+            reserved: vec![],
+        }
+    }
+
+    fn collect_registers(&self, statements: &Statements,
+                         prefix: Option<&str>,
+                         registers: &mut Vec<RegisterSpec>) {
+        for obj in composite_objects(statements) {
+            let name = match prefix {
+                Some(prefix) => format!("{}.{}", prefix,
+                                        obj.object.name.val),
+                None => obj.object.name.val.to_string(),
+            };
+            match obj.comp_kind() {
+                CompObjectKind::Register =>
+                    registers.push(self.register_spec(obj, name)),
+                CompObjectKind::Group =>
+                    self.collect_registers(&obj.statements, Some(&name),
+                                           registers),
+                _ => (),
+            }
+        }
+    }
+
+    fn register_spec(&self, register: &CompositeObject, name: String)
+                     -> RegisterSpec {
+        let size = find_param(&register.statements, "size")
+            .and_then(|p| self.param_integer(p))
+            .map_or(4, |size| size as u64);
+        let offset = find_param(&register.statements, "offset")
+            .and_then(|p| self.param_text(p));
+        let reset = find_param(&register.statements, "init_val")
+            .and_then(|p| self.param_text(p));
+        let fields = composite_objects(&register.statements)
+            .filter(|obj| obj.comp_kind() == CompObjectKind::Field)
+            .map(|field| self.field_spec(field))
+            .collect();
+        let dimensions = register.dims.iter()
+            .map(|dim| ArrayDimension {
+                index: dim.indexvar.val.to_string(),
+                size: dim.size.as_deref().and_then(eval_integer)
+                    .map_or(0, |size| size as u64),
+            })
+            .collect();
+        RegisterSpec {
+            name,
+            dimensions,
+            size,
+            offset,
+            reset,
+            access: None,
+            documentation: self.documentation(register),
+            fields,
+            methods: vec![],
+            templates: instantiated_templates(&register.statements)
+                .map(String::from)
+                .collect(),
+        }
+    }
+
+    fn field_spec(&self, field: &CompositeObject) -> FieldSpec {
+        let msb = find_param(&field.statements, "msb")
+            .and_then(|p| self.param_text(p));
+        let lsb = find_param(&field.statements, "lsb")
+            .and_then(|p| self.param_text(p));
+        let bits = match (msb, lsb) {
+            (Some(msb), Some(lsb)) if msb != lsb => format!("{}:{}", msb, lsb),
+            (Some(msb), _) => msb,
+            (None, Some(lsb)) => lsb,
+            (None, None) => "0".to_string(),
+        };
+        let access = instantiated_templates(&field.statements)
+            .find_map(access_of_template)
+            .map(|access| access.to_string());
+        // The template implementing the access type is implied by it
+        let templates = instantiated_templates(&field.statements)
+            .filter(|template| access_of_template(template).is_none())
+            .map(String::from)
+            .collect();
+        FieldSpec {
+            name: field.object.name.val.to_string(),
+            bits,
+            access,
+            documentation: self.documentation(field),
+            templates,
+        }
+    }
+
+    fn documentation(&self, obj: &CompositeObject) -> Option<String> {
+        if let Some(ExpressionKind::StringLiteral(doc)) = obj.doc.as_deref() {
+            return Some(doc.val.clone());
+        }
+        find_param(&obj.statements, "desc")
+            .and_then(|p| self.param_string(p))
+    }
+
+    fn param_string(&self, param: &Parameter) -> Option<String> {
+        match &param.value {
+            Some(ParamValue::Set(expr)) => match expr.as_ref() {
+                ExpressionKind::StringLiteral(s) => Some(s.val.clone()),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn param_integer(&self, param: &Parameter) -> Option<i128> {
+        match &param.value {
+            Some(ParamValue::Set(expr)) => eval_integer(expr),
+            _ => None,
+        }
+    }
+
+    /// The value of a parameter as DML source text, constant-folded
+    /// (into hexadecimal for addresses and register values)
+    fn param_text(&self, param: &Parameter) -> Option<String> {
+        match &param.value {
+            Some(ParamValue::Set(expr)) => Some(
+                match eval_integer(expr) {
+                    Some(val) if val >= 0 && matches!(
+                        param.object.name.val.as_str(), "offset" | "init_val")
+                        => format!("0x{:x}", val),
+                    Some(val) => val.to_string(),
+                    None => {
+                        use crate::analysis::DeclarationSpan;
+                        self.source_of(expr.span())
+                    },
+                }),
+            _ => None,
+        }
+    }
+}
+
+/// A location in a DML file, with one-based positions
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SourceLocation {
+    pub file: PathBuf,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl From<&ZeroSpan> for SourceLocation {
+    fn from(span: &ZeroSpan) -> Self {
+        SourceLocation {
+            file: span.path(),
+            line: span.range.row_start.0 + 1,
+            column: span.range.col_start.0 + 1,
+        }
+    }
+}
+
+/// A range in a DML file, with one-based positions and an exclusive end
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct SourceRange {
+    pub start_line: u32,
+    pub start_column: u32,
+    pub end_line: u32,
+    pub end_column: u32,
+}
+
+impl From<&ZeroSpan> for SourceRange {
+    fn from(span: &ZeroSpan) -> Self {
+        SourceRange {
+            start_line: span.range.row_start.0 + 1,
+            start_column: span.range.col_start.0 + 1,
+            end_line: span.range.row_end.0 + 1,
+            end_column: span.range.col_end.0 + 1,
+        }
+    }
+}
+
+/// Analyze the device declared in the file at `path` with the files it
+/// imports, resolving templates and 'in each' declarations as the language
+/// server does. Imports are looked up next to the device file and then in
+/// each of `include_paths`; those not found, such as the standard library
+/// when it is not among the include paths, are left out.
+pub fn analyze_device(path: &Path, include_paths: &[PathBuf])
+                      -> Result<DeviceAnalysis> {
+    analyze_device_files(path, include_paths).map(|(device, _)| device)
+}
+
+/// Analyze a device like `analyze_device`, keeping the analysis of each of
+/// its files on its own, which positions in the file are looked up in
+pub fn analyze_device_files(path: &Path, include_paths: &[PathBuf])
+                            -> Result<(DeviceAnalysis,
+                                       HashMap<CanonPath, IsolatedAnalysis>)> {
+    let root = CanonPath::from_path_buf(path.to_path_buf())
+        .ok_or_else(|| anyhow!("Could not find {}", path.display()))?;
+    let mut resolver = PathResolver::from(None);
+    resolver.add_paths(include_paths.iter().cloned());
+    let (_keeper, status) = JobStatusKeeper::new();
+    let mut analyses: HashMap<CanonPath, IsolatedAnalysis> = HashMap::new();
+    let mut import_sources = HashMap::new();
+    let mut queue = vec![root.clone()];
+    while let Some(next) = queue.pop() {
+        if analyses.contains_key(&next) {
+            continue;
+        }
+        let text = std::fs::read_to_string(next.as_path())
+            .map_err(|e| anyhow!("Could not read {}: {}", next.as_str(), e))?;
+        let file = TextFile::from_str(&text)
+            .map_err(|_| anyhow!("Could not read DML source"))?;
+        let analysis = IsolatedAnalysis::new(
+            &next, &next.to_path_buf(), file, status.clone())
+            .map_err(|e| anyhow!("Failed to analyze {}: {}", next.as_str(), e))?;
+        let (found, _) = analysis.resolve_imports(&resolver, Some(&root));
+        for (dependency, import) in found {
+            import_sources.insert(import, dependency.as_str().to_string());
+            queue.push(dependency);
+        }
+        analyses.insert(next, analysis);
+    }
+    let root_analysis = analyses[&root].clone();
+    if !root_analysis.is_device_file() {
+        return Err(anyhow!("{} does not declare a device", path.display()));
+    }
+    let timestamp = SystemTime::now();
+    let bases = analyses.values().cloned()
+        .map(|stored| TimestampedStorage { timestamp, stored })
+        .collect();
+    let device = DeviceAnalysis::new(root_analysis, bases, import_sources,
+                                     AnalysisOptions::default(), status)
+        .map_err(|e| anyhow!("Failed to analyze the device of {}: {}",
+                             path.display(), e))?;
+    Ok((device, analyses))
+}
+
+/// The declarations of the arguments of a method signature, such as
+/// `method m(uint64 value, bool hard) -> (int) throws`, and its return
+/// types, in parentheses if there is more than one
+fn split_signature(signature: &str) -> (Vec<String>, Option<String>) {
+    let Some(open) = signature.find('(') else {
+        return (vec![], None);
+    };
+    let (mut depth, mut start, mut arguments) = (0, open + 1, vec![]);
+    let mut close = signature.len();
+    for (i, c) in signature.char_indices().skip_while(|(i, _)| *i <= open) {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' if depth > 0 => depth -= 1,
+            ',' if depth == 0 => {
+                arguments.push(signature[start..i].to_string());
+                start = i + 1;
+            },
+            ')' => {
+                close = i;
+                break;
+            },
+            _ => (),
+        }
+    }
+    if close == signature.len() {
+        return (vec![], None);
+    }
+    if !signature[start..close].trim().is_empty() {
+        arguments.push(signature[start..close].to_string());
+    }
+    let returns = signature[close + 1..].split_once("->").map(|(_, returns)| {
+        let mut returns = returns.trim();
+        for modifier in ["default", "throws"] {
+            returns = returns.strip_suffix(modifier).unwrap_or(returns)
+                .trim_end();
+        }
+        let types = returns.strip_prefix('(')
+            .and_then(|returns| returns.strip_suffix(')'))
+            .unwrap_or(returns).trim();
+        if types.contains(',') {
+            format!("({})", types)
+        } else {
+            types.to_string()
+        }
+    });
+    (arguments, returns.filter(|returns| !returns.is_empty()))
+}
+
+/// Iterate over the composite objects declared directly in `statements`,
+/// skipping those inside `#if` blocks
+pub fn composite_objects(statements: &Statements)
+                         -> impl Iterator<Item = &CompositeObject> {
+    statements.statements.iter().filter_map(|stmt| match stmt {
+        DMLStatement::Object(DMLObject::CompositeObject(obj)) => Some(obj),
+        _ => None,
+    })
+}
+
+/// The names of the templates instantiated directly in `statements`
+pub fn instantiated_templates(statements: &Statements)
+                              -> impl Iterator<Item = &str> {
+    statements.instantiations.iter()
+        .flat_map(|inst| inst.names.iter())
+        .map(|name| name.val.as_str())
+}
+
+/// Find a parameter declared directly in `statements`
+pub fn find_param<'t>(statements: &'t Statements, name: &str)
+                      -> Option<&'t Parameter> {
+    statements.statements.iter().find_map(|stmt| match stmt {
+        DMLStatement::Object(DMLObject::Parameter(param))
+            if param.object.name.val == name => Some(param),
+        _ => None,
+    })
+}
+
+/// The field access implied by instantiating a standard utility template
+pub fn access_of_template(template: &str) -> Option<&'static str> {
+    match template {
+        "read_only" | "ignore_write" => Some("ro"),
+        "write_only" | "read_zero" => Some("wo"),
+        "write_1_clears" => Some("w1c"),
+        "clear_on_read" => Some("rc"),
+        "write_1_only" => Some("w1s"),
+        "constant" => Some("const"),
+        "reserved" | "unimpl" => Some("reserved"),
+        _ => None,
+    }
+}
+
+/// Fold an integer constant expression, if it only consists of literals
+pub fn eval_integer(expr: &ExpressionKind) -> Option<i128> {
+    match expr {
+        ExpressionKind::IntegerLiteral(IntegerLiteral::Signed(val)) =>
+            Some(val.val as i128),
+        ExpressionKind::IntegerLiteral(IntegerLiteral::Unsigned(val)) =>
+            Some(val.val as i128),
+        ExpressionKind::UnaryExpression(unary) => {
+            let operand = eval_integer(&unary.operand)?;
+            match unary.operator {
+                UnaryOp::Minus => Some(-operand),
+                UnaryOp::BinNot => Some(!operand),
+                _ => None,
+            }
+        },
+        ExpressionKind::BinaryExpression(binary) => {
+            let left = eval_integer(&binary.left)?;
+            let right = eval_integer(&binary.right)?;
+            match &binary.operator {
+                BinOp::Math(MathOp::Plus) => left.checked_add(right),
+                BinOp::Math(MathOp::Minus) => left.checked_sub(right),
+                BinOp::Math(MathOp::Multiply) => left.checked_mul(right),
+                BinOp::Math(MathOp::Divide) => left.checked_div(right),
+                BinOp::Math(MathOp::Mod) => left.checked_rem(right),
+                BinOp::Math(MathOp::BinOr) => Some(left | right),
+                BinOp::Math(MathOp::BinAnd) => Some(left & right),
+                BinOp::Math(MathOp::BinXor) => Some(left ^ right),
+                BinOp::Math(MathOp::LShift) =>
+                    left.checked_shl(u32::try_from(right).ok()?),
+                BinOp::Math(MathOp::RShift) =>
+                    left.checked_shr(u32::try_from(right).ok()?),
+                _ => None,
+            }
+        },
+        _ => None,
+    }
+}
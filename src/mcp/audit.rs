@@ -0,0 +1,285 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Audit log of tool calls, and replaying it
+//!
+//! With an audit log, the server appends a line of JSON for every tool call,
+//! with the arguments of the call, how long it took, the size and a hash of
+//! its result, and its error if it failed. `dls mcp-replay` calls the tools
+//! of a log again with the same arguments against the current build, and
+//! reports the calls whose results differ from the recorded ones.
+//!
+//! Replays never change files: the calls of tools that can write files are
+//! replayed without the arguments asking them to. What a call wrote depends
+//! on the files there were rather than on the tool, so the report of it is
+//! left out of the hash of the result, and the replayed call compares equal
+//! to the recorded one that wrote files.
+
+use anyhow::{anyhow, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::analysis::content_hash;
+use crate::mcp::provenance::utc_timestamp;
+use crate::mcp::tools::ToolRegistry;
+
+/// The arguments asking tools to write files, and the values that ask them
+/// not to
+const WRITE_ARGUMENTS: &[(&str, Value)] = &[
+    ("output_dir", Value::Null),
+    ("force", Value::Bool(false)),
+    ("dry_run", Value::Bool(false)),
+    ("write", Value::Bool(false)),
+    ("apply", Value::Bool(false)),
+];
+
+/// The keys under which tools report what they wrote, unless they hold
+/// counts
+const WRITE_REPORT_KEYS: &[&str] = &["output", "written", "applied"];
+
+/// A tool call, as recorded in the audit log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub timestamp: String,
+    pub tool: String,
+    pub arguments: Value,
+    pub duration_ms: u64,
+    /// The size of the result, serialized as JSON
+    pub result_bytes: usize,
+    /// A hash of the result, to tell whether a replay gives the same one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result_hash: Option<String>,
+    /// The error of a call that failed, or that the tool reported as an
+    /// error
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl AuditRecord {
+    /// The record of a call of a tool with `params`, as passed to
+    /// `ToolRegistry::call_tool`
+    pub fn of_call(params: &Value, duration: Duration, result: &Result<Value>)
+                   -> AuditRecord {
+        let (result_bytes, result_hash, error) = match result {
+            Ok(result) => {
+                let text = result.to_string();
+                // Tools report some failures in the result rather than as
+                // errors
+                let error = (result["isError"] == json!(true)).then(
+                    || result["content"][0]["text"].as_str().unwrap_or("")
+                        .to_string());
+                (text.len(), Some(result_hash(result)), error)
+            },
+            Err(e) => (0, None, Some(e.to_string())),
+        };
+        AuditRecord {
+            timestamp: utc_timestamp(SystemTime::now()),
+            tool: params["name"].as_str().unwrap_or_default().to_string(),
+            arguments: params.get("arguments").cloned().unwrap_or(Value::Null),
+            duration_ms: duration.as_millis() as u64,
+            result_bytes,
+            result_hash,
+            error,
+        }
+    }
+}
+
+/// The hash of `result`, leaving out what the call reports to have written
+/// to disk. Such reports are keys of results that are JSON objects, which
+/// are hashed as reserialized to not depend on their formatting.
+fn result_hash(result: &Value) -> String {
+    let mut comparable = result.clone();
+    if let Some(content) = comparable["content"].as_array_mut() {
+        content.retain_mut(|content| {
+            let Some(Value::Object(mut object)) = content["text"].as_str()
+                .and_then(|text| serde_json::from_str(text).ok()) else {
+                    return true;
+                };
+            let mut reported = false;
+            for key in WRITE_REPORT_KEYS {
+                if object.get(*key).is_some_and(|value| !value.is_number()) {
+                    object.remove(*key);
+                    reported = true;
+                }
+            }
+            let keep = !reported || !object.is_empty();
+            content["text"] = Value::String(Value::Object(object).to_string());
+            keep
+        });
+    }
+    format!("{:016x}", content_hash(&comparable.to_string()))
+}
+
+/// An audit log file, which records are appended to
+pub struct AuditLog {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl AuditLog {
+    /// The audit log at `path`, created if it does not exist
+    pub fn open(path: &Path) -> Result<AuditLog> {
+        let file = OpenOptions::new().create(true).append(true).open(path)
+            .map_err(|e| anyhow!("Could not open the audit log {}: {}",
+                                 path.display(), e))?;
+        Ok(AuditLog { path: path.to_path_buf(), file: Mutex::new(file) })
+    }
+
+    /// Append a record to the log. Failing to write it is logged, but does
+    /// not fail the call it records.
+    pub fn record(&self, record: &AuditRecord) {
+        let line = match serde_json::to_string(record) {
+            Ok(line) => line,
+            Err(e) => {
+                warn!("Could not serialize audit record: {}", e);
+                return;
+            },
+        };
+        let mut file = self.file.lock().unwrap();
+        if let Err(e) = writeln!(file, "{}", line) {
+            warn!("Could not write to the audit log {}: {}",
+                  self.path.display(), e);
+        }
+    }
+}
+
+/// The records of the audit log at `path`, in the order they were written
+pub fn read_log(path: &Path) -> Result<Vec<AuditRecord>> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("Could not read {}: {}", path.display(), e))?;
+    text.lines().enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| serde_json::from_str(line).map_err(
+            |e| anyhow!("{}:{}: invalid audit record: {}", path.display(),
+                        index + 1, e)))
+        .collect()
+}
+
+/// How a replayed call compares to the recorded one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplayStatus {
+    /// The same result, or the same failure, as recorded
+    Same,
+    /// A different result than recorded
+    Changed,
+    /// The call succeeded when recorded, but fails now
+    NowFails,
+    /// The call failed when recorded, but succeeds now
+    NowSucceeds,
+}
+
+/// A recorded call, replayed
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplayedCall {
+    pub record: AuditRecord,
+    /// The record of the replayed call
+    pub replayed: AuditRecord,
+    pub status: ReplayStatus,
+    /// The result of the replayed call
+    #[serde(skip)]
+    pub result: Option<Value>,
+}
+
+/// Call the tool of `record` again with the same arguments, except for
+/// those asking it to write files
+pub async fn replay_call(registry: &ToolRegistry, record: &AuditRecord)
+                         -> ReplayedCall {
+    let mut arguments = record.arguments.clone();
+    if let (true, Some(given)) = (registry.writes_files(&record.tool),
+                                  arguments.as_object_mut()) {
+        // Set rather than removed, so that project defaults do not fill
+        // them in
+        for (name, value) in WRITE_ARGUMENTS {
+            given.insert(name.to_string(), value.clone());
+        }
+    }
+    let params = json!({"name": record.tool, "arguments": arguments});
+    let started = Instant::now();
+    let result = registry.call_tool(&params).await;
+    let replayed = AuditRecord::of_call(&params, started.elapsed(), &result);
+    let status = match (&record.error, &replayed.error) {
+        (None, Some(_)) => ReplayStatus::NowFails,
+        (Some(_), None) => ReplayStatus::NowSucceeds,
+        (Some(_), Some(_)) => ReplayStatus::Same,
+        (None, None) if record.result_hash == replayed.result_hash =>
+            ReplayStatus::Same,
+        (None, None) => ReplayStatus::Changed,
+    };
+    ReplayedCall {
+        record: record.clone(),
+        replayed,
+        status,
+        result: result.ok(),
+    }
+}
+
+/// Replay the calls in the audit log at `log`, or only those of `tool`,
+/// printing how each compares to the recorded call, and its result if
+/// `show` is set. The exit status is 1 if any result differs from the
+/// recorded one.
+pub fn run(log: &Path, tool: Option<&str>, show: bool) -> i32 {
+    let records = match read_log(log) {
+        Ok(records) => records,
+        Err(e) => {
+            eprintln!("dls mcp-replay: {}", e);
+            return 1;
+        },
+    };
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("dls mcp-replay: {}", e);
+            return 1;
+        },
+    };
+    runtime.block_on(async {
+        let registry = match ToolRegistry::new().await {
+            Ok(registry) => registry,
+            Err(e) => {
+                eprintln!("dls mcp-replay: {}", e);
+                return 1;
+            },
+        };
+        let mut differences = 0;
+        for record in records.iter()
+            .filter(|record| tool.is_none_or(|tool| record.tool == tool)) {
+                let call = replay_call(&registry, record).await;
+                let status = match call.status {
+                    ReplayStatus::Same => "same",
+                    ReplayStatus::Changed => "changed",
+                    ReplayStatus::NowFails => "now fails",
+                    ReplayStatus::NowSucceeds => "now succeeds",
+                };
+                println!("{:<26} {:<12} {:>6} ms -> {:>6} ms  {}",
+                         record.tool, status, record.duration_ms,
+                         call.replayed.duration_ms,
+                         call.replayed.error.as_deref()
+                         .and_then(|error| error.lines().next())
+                         .unwrap_or(""));
+                if show {
+                    if let Some(result) = &call.result {
+                        for content in result["content"].as_array()
+                            .into_iter().flatten() {
+                                println!("{}", content["text"].as_str()
+                                         .unwrap_or_default());
+                            }
+                    }
+                }
+                if call.status != ReplayStatus::Same {
+                    differences += 1;
+                }
+            }
+        if differences > 0 {
+            eprintln!("{} call(s) differ from the audit log", differences);
+            1
+        } else {
+            0
+        }
+    })
+}
@@ -0,0 +1,518 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Explanations of diagnostics
+//!
+//! A built-in knowledge base of the rules behind the diagnostics of the
+//! parser, the analysis, the linter and `validate_spec`, each with a minimal
+//! example breaking the rule and the example corrected, so that clients
+//! generating DML can correct what they generated.
+
+use serde::Serialize;
+
+/// Language of the examples of an explanation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExampleLanguage {
+    Dml,
+    /// Device specifications
+    Json,
+}
+
+/// The rule behind a kind of diagnostic
+#[derive(Debug, Clone, Serialize)]
+pub struct Explanation {
+    /// Code of the diagnostic: the name of a lint rule or `validate_spec`
+    /// finding, or a name given here to analysis errors
+    pub code: &'static str,
+    pub title: &'static str,
+    /// Case-insensitive fragments of the messages of the diagnostic
+    #[serde(skip)]
+    pub patterns: &'static [&'static str],
+    pub explanation: &'static str,
+    pub language: ExampleLanguage,
+    pub failing_example: &'static str,
+    pub corrected_example: &'static str,
+}
+
+/// The knowledge base, with more specific patterns before more general ones
+pub static EXPLANATIONS: &[Explanation] = &[
+    Explanation {
+        code: "version-declaration",
+        title: "The language version must be declared first",
+        patterns: &["Version declaration must be first statement",
+                    "First statement must be version declaration"],
+        explanation: "Every DML file starts with the version of the \
+                      language it is written in, `dml 1.4;`, before any \
+                      other declaration.",
+        language: ExampleLanguage::Dml,
+        failing_example: "device sample;\ndml 1.4;\n",
+        corrected_example: "dml 1.4;\ndevice sample;\n",
+    },
+    Explanation {
+        code: "device-declaration",
+        title: "The device must be declared right after the version",
+        patterns: &["Device declaration must be second statement"],
+        explanation: "A file declaring a device does so in the statement \
+                      following the version declaration, before imports \
+                      and other declarations.",
+        language: ExampleLanguage::Dml,
+        failing_example: "dml 1.4;\nimport \"utility.dml\";\ndevice sample;\n",
+        corrected_example: "dml 1.4;\ndevice sample;\nimport \"utility.dml\";\n",
+    },
+    Explanation {
+        code: "bitorder",
+        title: "Invalid bit order",
+        patterns: &["bitorder must be", "Bitorder declaration must follow"],
+        explanation: "The bit order of a device, which decides how bit \
+                      ranges of fields and slices are numbered, is `le` \
+                      (bit 0 is the least significant) or `be`, and is \
+                      declared right after the device.",
+        language: ExampleLanguage::Dml,
+        failing_example: "dml 1.4;\ndevice sample;\nbitorder little;\n",
+        corrected_example: "dml 1.4;\ndevice sample;\nbitorder le;\n",
+    },
+    Explanation {
+        code: "time-unit",
+        title: "Missing or unknown time unit of an after statement",
+        patterns: &["Expected time unit"],
+        explanation: "The delay of an `after` statement is given in seconds \
+                      (`s`), picoseconds (`ps`) or processor cycles \
+                      (`cycles`).",
+        language: ExampleLanguage::Dml,
+        failing_example: "dml 1.4;\ndevice sample;\nmethod tick() {\n}\n\
+                          method start() {\n    after 10 ns: tick();\n}\n",
+        corrected_example: "dml 1.4;\ndevice sample;\nmethod tick() {\n}\n\
+                            method start() {\n    after 10000 ps: tick();\n}\n",
+    },
+    Explanation {
+        code: "inline-method",
+        title: "Untyped arguments and inline methods",
+        patterns: &["inline arguments require method",
+                    "only use inline if there are"],
+        explanation: "Arguments declared `inline`, without a type, take the \
+                      type of what is passed, so the method must be \
+                      declared `inline` and is expanded where it is called. \
+                      Methods with only typed arguments are not declared \
+                      `inline`.",
+        language: ExampleLanguage::Dml,
+        failing_example: "dml 1.4;\ndevice sample;\n\
+                          method log_value(inline value) {\n\
+                          \x20   log info: \"%d\", value;\n}\n",
+        corrected_example: "dml 1.4;\ndevice sample;\n\
+                            inline method log_value(inline value) {\n\
+                            \x20   log info: \"%d\", value;\n}\n",
+    },
+    Explanation {
+        code: "startup-method",
+        title: "Invalid startup or memoized method",
+        patterns: &["declared as startup", "startup method",
+                    "declared as memoized"],
+        explanation: "A `startup` method runs when the device is created, \
+                      so it must be `independent` of the device instance \
+                      and can take no arguments. A `memoized` method must \
+                      also be `independent startup`, and must return \
+                      values or throw, as its result is what is remembered.",
+        language: ExampleLanguage::Dml,
+        failing_example: "dml 1.4;\ndevice sample;\n\
+                          startup method init() {\n}\n",
+        corrected_example: "dml 1.4;\ndevice sample;\n\
+                            independent startup method init() {\n}\n",
+    },
+    Explanation {
+        code: "typed-parameter",
+        title: "Typed parameter outside a template",
+        patterns: &["Typed parameter declaration only permitted"],
+        explanation: "A typed parameter, `param name : type;`, declares \
+                      what the objects instantiating a template must \
+                      provide, and is only permitted directly in a \
+                      template.",
+        language: ExampleLanguage::Dml,
+        failing_example: "dml 1.4;\ndevice sample;\nbank regs {\n\
+                          \x20   param page : uint64;\n}\n",
+        corrected_example: "dml 1.4;\ndevice sample;\n\
+                            template paged {\n\
+                            \x20   param page : uint64;\n}\n\
+                            bank regs is paged {\n\
+                            \x20   param page = 0x1000;\n}\n",
+    },
+    Explanation {
+        code: "shared-method",
+        title: "Shared method outside a template",
+        patterns: &["Shared method declaration only permitted"],
+        explanation: "A `shared` method is compiled once for all objects \
+                      instantiating a template, and is only permitted \
+                      directly in a template.",
+        language: ExampleLanguage::Dml,
+        failing_example: "dml 1.4;\ndevice sample;\nbank regs {\n\
+                          \x20   shared method reset_all() {\n    }\n}\n",
+        corrected_example: "dml 1.4;\ndevice sample;\n\
+                            template resettable {\n\
+                            \x20   shared method reset_all() {\n    }\n}\n\
+                            bank regs is resettable;\n",
+    },
+    Explanation {
+        code: "unexpected-token",
+        title: "Syntax error",
+        patterns: &["Unexpected token", "Expected "],
+        explanation: "The parser found a token where the grammar does not \
+                      allow it, most often because a `;` ends a declaration \
+                      that has no body, or a brace or parenthesis is not \
+                      closed. The diagnostic lists what the parser expected \
+                      instead.",
+        language: ExampleLanguage::Dml,
+        failing_example: "dml 1.4;\ndevice sample;\nbank regs {\n\
+                          \x20   register status size 4 @ 0x0\n}\n",
+        corrected_example: "dml 1.4;\ndevice sample;\nbank regs {\n\
+                            \x20   register status size 4 @ 0x0;\n}\n",
+    },
+    Explanation {
+        code: "unknown-template",
+        title: "Instantiation of an unknown template",
+        patterns: &["No template named"],
+        explanation: "Templates are instantiated by name with `is`, and \
+                      must be declared in the device or in a file it \
+                      imports. Templates of the standard library, such as \
+                      `read_only`, are available in all devices; others \
+                      need an `import`.",
+        language: ExampleLanguage::Dml,
+        failing_example: "dml 1.4;\ndevice sample;\nbank regs {\n\
+                          \x20   register status size 4 @ 0x0 is read_onyl;\n}\n",
+        corrected_example: "dml 1.4;\ndevice sample;\nbank regs {\n\
+                            \x20   register status size 4 @ 0x0 is read_only;\n}\n",
+    },
+    Explanation {
+        code: "duplicate-template",
+        title: "Template declared more than once",
+        patterns: &["Duplicate template name"],
+        explanation: "Template names are global to the device, including \
+                      the files it imports, so each template is declared \
+                      once.",
+        language: ExampleLanguage::Dml,
+        failing_example: "dml 1.4;\ndevice sample;\ntemplate counter {\n}\n\
+                          template counter {\n}\n",
+        corrected_example: "dml 1.4;\ndevice sample;\ntemplate counter {\n}\n\
+                            template timer {\n}\n",
+    },
+    Explanation {
+        code: "name-collision",
+        title: "Two declarations of the same name",
+        patterns: &["Name collision", "is already in use"],
+        explanation: "Declarations in the same object share one namespace, \
+                      whatever their kind, so a register and a method, or \
+                      a method and a parameter, can not have the same \
+                      name.",
+        language: ExampleLanguage::Dml,
+        failing_example: "dml 1.4;\ndevice sample;\nbank regs {\n\
+                          \x20   register status size 4 @ 0x0;\n\
+                          \x20   method status() {\n    }\n}\n",
+        corrected_example: "dml 1.4;\ndevice sample;\nbank regs {\n\
+                            \x20   register status size 4 @ 0x0;\n\
+                            \x20   method update_status() {\n    }\n}\n",
+    },
+    Explanation {
+        code: "conflicting-parameter",
+        title: "Parameter given different values",
+        patterns: &["Conflicting assignments to parameter"],
+        explanation: "Declarations of the same object in several places are \
+                      merged, so an object declared twice by mistake, such \
+                      as two registers with the same name, has two values \
+                      of its parameters. Each parameter is assigned once, \
+                      or declared `default` where it may be overridden.",
+        language: ExampleLanguage::Dml,
+        failing_example: "dml 1.4;\ndevice sample;\nbank regs {\n\
+                          \x20   register status size 4 @ 0x0;\n\
+                          \x20   register status size 4 @ 0x4;\n}\n",
+        corrected_example: "dml 1.4;\ndevice sample;\nbank regs {\n\
+                            \x20   register status size 4 @ 0x0;\n\
+                            \x20   register control size 4 @ 0x4;\n}\n",
+    },
+    Explanation {
+        code: "inconsistent-object-type",
+        title: "An object declared as different kinds of object",
+        patterns: &["Inconsistent object type"],
+        explanation: "An object can be declared in several places, which \
+                      are merged, but all declarations must be of the same \
+                      kind, such as all `register` or all `group`.",
+        language: ExampleLanguage::Dml,
+        failing_example: "dml 1.4;\ndevice sample;\nbank regs {\n\
+                          \x20   register status size 4 @ 0x0;\n\
+                          \x20   group status {\n    }\n}\n",
+        corrected_example: "dml 1.4;\ndevice sample;\nbank regs {\n\
+                            \x20   register status size 4 @ 0x0;\n\
+                            \x20   register status {\n\
+                            \x20       param desc = \"Status\";\n    }\n}\n",
+    },
+    Explanation {
+        code: "parameter-override",
+        title: "Override of a parameter that is not a default",
+        patterns: &["overrides non-default parameter"],
+        explanation: "A parameter can only be given another value where it \
+                      is declared with `default`, which is how templates \
+                      declare values that instantiating objects may \
+                      change.",
+        language: ExampleLanguage::Dml,
+        failing_example: "dml 1.4;\ndevice sample;\ntemplate wide {\n\
+                          \x20   param width = 8;\n}\n\
+                          bank regs is wide {\n    param width = 16;\n}\n",
+        corrected_example: "dml 1.4;\ndevice sample;\ntemplate wide {\n\
+                            \x20   param width default 8;\n}\n\
+                            bank regs is wide {\n    param width = 16;\n}\n",
+    },
+    Explanation {
+        code: "missing-parameter",
+        title: "Parameter without a value",
+        patterns: &["No assignment to parameter"],
+        explanation: "A parameter that is declared without a value, such as \
+                      a typed parameter of a template, must be given one in \
+                      each object that has it.",
+        language: ExampleLanguage::Dml,
+        failing_example: "dml 1.4;\ndevice sample;\ntemplate paged {\n\
+                          \x20   param page : uint64;\n}\n\
+                          bank regs is paged;\n",
+        corrected_example: "dml 1.4;\ndevice sample;\ntemplate paged {\n\
+                            \x20   param page : uint64;\n}\n\
+                            bank regs is paged {\n\
+                            \x20   param page = 0x1000;\n}\n",
+    },
+    Explanation {
+        code: "method-override",
+        title: "Method override with a different signature",
+        patterns: &["in method override"],
+        explanation: "A method overriding a `default` method takes the \
+                      same arguments and returns the same types as the \
+                      method it overrides.",
+        language: ExampleLanguage::Dml,
+        failing_example: "dml 1.4;\ndevice sample;\ntemplate notified {\n\
+                          \x20   method notify(uint64 value) default {\n\
+                          \x20   }\n}\nbank regs is notified {\n\
+                          \x20   method notify() {\n    }\n}\n",
+        corrected_example: "dml 1.4;\ndevice sample;\ntemplate notified {\n\
+                            \x20   method notify(uint64 value) default {\n\
+                            \x20   }\n}\nbank regs is notified {\n\
+                            \x20   method notify(uint64 value) {\n    }\n}\n",
+    },
+    Explanation {
+        code: "unguarded-error",
+        title: "Error statement that is always compiled",
+        patterns: &["unguarded error statement"],
+        explanation: "An object-level `error` statement fails compilation \
+                      when it is compiled, so it belongs behind an `#if` \
+                      with the condition it reports.",
+        language: ExampleLanguage::Dml,
+        failing_example: "dml 1.4;\ndevice sample;\nbank regs {\n\
+                          \x20   error \"regs is not supported\";\n}\n",
+        corrected_example: "dml 1.4;\ndevice sample;\nparam supported = true;\n\
+                            bank regs {\n    #if (!supported) {\n\
+                            \x20       error \"regs is not supported\";\n\
+                            \x20   }\n}\n",
+    },
+    Explanation {
+        code: "long_lines",
+        title: "Line longer than the limit",
+        patterns: &["Line length is above the threshold"],
+        explanation: "Lines are at most 80 columns long, unless the lint \
+                      configuration sets another `max_length`. Long \
+                      declarations are broken after a comma or before a \
+                      binary operator.",
+        language: ExampleLanguage::Dml,
+        failing_example: "dml 1.4;\ndevice sample;\nbank regs {\n\
+                          \x20   register status size 4 @ 0x0 \
+                          \"The status register of the device, with the \
+                          interrupt flags\";\n}\n",
+        corrected_example: "dml 1.4;\ndevice sample;\nbank regs {\n\
+                            \x20   register status size 4 @ 0x0 {\n\
+                            \x20       param desc = \"The status register of \
+                            the device\";\n    }\n}\n",
+    },
+    Explanation {
+        code: "indent_no_tabs",
+        title: "Tab used for indentation",
+        patterns: &["Tab characters (ASCII 9)"],
+        explanation: "Lines are indented with spaces, so that they look the \
+                      same in all editors.",
+        language: ExampleLanguage::Dml,
+        failing_example: "dml 1.4;\ndevice sample;\nbank regs {\n\
+                          \tregister status size 4 @ 0x0;\n}\n",
+        corrected_example: "dml 1.4;\ndevice sample;\nbank regs {\n\
+                            \x20   register status size 4 @ 0x0;\n}\n",
+    },
+    Explanation {
+        code: "sp_punct",
+        title: "Missing space after punctuation",
+        patterns: &["Missing space after punctuation"],
+        explanation: "Commas, semicolons and colons are followed by a space \
+                      unless they end the line.",
+        language: ExampleLanguage::Dml,
+        failing_example: "dml 1.4;\ndevice sample;\n\
+                          method add(int a,int b) -> (int) {\n\
+                          \x20   return a + b;\n}\n",
+        corrected_example: "dml 1.4;\ndevice sample;\n\
+                            method add(int a, int b) -> (int) {\n\
+                            \x20   return a + b;\n}\n",
+    },
+    Explanation {
+        code: "sp_binop",
+        title: "Missing space around a binary operator",
+        patterns: &["Missing space around binary operator"],
+        explanation: "Binary operators are surrounded by spaces.",
+        language: ExampleLanguage::Dml,
+        failing_example: "dml 1.4;\ndevice sample;\n\
+                          method add(int a, int b) -> (int) {\n\
+                          \x20   return a+b;\n}\n",
+        corrected_example: "dml 1.4;\ndevice sample;\n\
+                            method add(int a, int b) -> (int) {\n\
+                            \x20   return a + b;\n}\n",
+    },
+    Explanation {
+        code: "nsp_funpar",
+        title: "Space before the parenthesis of a call",
+        patterns: &["no space between a method/function name"],
+        explanation: "The name of a called or declared method is directly \
+                      followed by its opening parenthesis.",
+        language: ExampleLanguage::Dml,
+        failing_example: "dml 1.4;\ndevice sample;\nmethod reset () {\n}\n",
+        corrected_example: "dml 1.4;\ndevice sample;\nmethod reset() {\n}\n",
+    },
+    Explanation {
+        code: "nsp_trailing",
+        title: "Trailing whitespace",
+        patterns: &["trailing whitespace"],
+        explanation: "Lines do not end with spaces or tabs.",
+        language: ExampleLanguage::Dml,
+        failing_example: "dml 1.4;\ndevice sample;  \n",
+        corrected_example: "dml 1.4;\ndevice sample;\n",
+    },
+    Explanation {
+        code: "schema",
+        title: "Specification not matching the schema",
+        patterns: &["Invalid device_spec", "missing field"],
+        explanation: "A device specification is an object with the `name` \
+                      of the device and its `banks`, each with a `name` \
+                      and `registers`, each with a `name`, a `size` in \
+                      bytes and an `offset`. The schema is available as a \
+                      resource of the server.",
+        language: ExampleLanguage::Json,
+        failing_example: "{\"name\": \"sample\", \"banks\": [\
+                          {\"registers\": [{\"name\": \"status\"}]}]}",
+        corrected_example: "{\"name\": \"sample\", \"banks\": [\
+                            {\"name\": \"regs\", \"registers\": [\
+                            {\"name\": \"status\", \"size\": 4, \
+                            \"offset\": \"0x0\"}]}]}",
+    },
+    Explanation {
+        code: "duplicate-register",
+        title: "Register declared more than once in a bank",
+        patterns: &["is declared more than once in bank"],
+        explanation: "Registers of a bank have distinct names; registers \
+                      that repeat are declared once as an array.",
+        language: ExampleLanguage::Json,
+        failing_example: "{\"name\": \"regs\", \"registers\": [\
+                          {\"name\": \"ch\", \"size\": 4, \"offset\": \"0x0\"}, \
+                          {\"name\": \"ch\", \"size\": 4, \"offset\": \"0x4\"}]}",
+        corrected_example: "{\"name\": \"regs\", \"registers\": [\
+                            {\"name\": \"ch\", \"size\": 4, \"offset\": \"0x0\", \
+                            \"dimensions\": [{\"index\": \"i\", \"size\": 2}]}]}",
+    },
+    Explanation {
+        code: "duplicate-field",
+        title: "Field declared more than once in a register",
+        patterns: &["is declared more than once in register"],
+        explanation: "Fields of a register have distinct names.",
+        language: ExampleLanguage::Json,
+        failing_example: "{\"name\": \"ctrl\", \"size\": 1, \"fields\": [\
+                          {\"name\": \"en\", \"bits\": \"0\"}, \
+                          {\"name\": \"en\", \"bits\": \"1\"}]}",
+        corrected_example: "{\"name\": \"ctrl\", \"size\": 1, \"fields\": [\
+                            {\"name\": \"rx_en\", \"bits\": \"0\"}, \
+                            {\"name\": \"tx_en\", \"bits\": \"1\"}]}",
+    },
+    Explanation {
+        code: "field-too-wide",
+        title: "Field outside its register",
+        patterns: &["does not fit in the"],
+        explanation: "The bits of a field are within the `size` of its \
+                      register, which is in bytes: a register of size 1 \
+                      has bits 7 to 0.",
+        language: ExampleLanguage::Json,
+        failing_example: "{\"name\": \"ctrl\", \"size\": 1, \"fields\": [\
+                          {\"name\": \"mode\", \"bits\": \"8:6\"}]}",
+        corrected_example: "{\"name\": \"ctrl\", \"size\": 2, \"fields\": [\
+                            {\"name\": \"mode\", \"bits\": \"8:6\"}]}",
+    },
+    Explanation {
+        code: "invalid-bits",
+        title: "Malformed bit range of a field",
+        patterns: &["are not a bit or a"],
+        explanation: "The `bits` of a field are a single bit, such as \
+                      \"3\", or a range from the most to the least \
+                      significant bit, such as \"7:4\".",
+        language: ExampleLanguage::Json,
+        failing_example: "{\"name\": \"mode\", \"bits\": \"4-7\"}",
+        corrected_example: "{\"name\": \"mode\", \"bits\": \"7:4\"}",
+    },
+    Explanation {
+        code: "unknown-access",
+        title: "Unknown access type",
+        patterns: &["Unknown access type"],
+        explanation: "The `access` of a register or field is one of the \
+                      access types of the generator, `rw`, `ro`, `wo`, \
+                      `w1c`, `w1s`, `rc`, `const`, `reserved` and `unimpl`, \
+                      or their long names such as `read_only`, which select \
+                      the standard library template implementing it.",
+        language: ExampleLanguage::Json,
+        failing_example: "{\"name\": \"status\", \"size\": 4, \
+                          \"access\": \"r/o\"}",
+        corrected_example: "{\"name\": \"status\", \"size\": 4, \
+                            \"access\": \"ro\"}",
+    },
+    Explanation {
+        code: "empty-bank",
+        title: "Bank without registers",
+        patterns: &["has no registers"],
+        explanation: "A bank maps registers into a memory space; a bank \
+                      without registers is usually a specification that \
+                      lost its registers, or one that is not finished.",
+        language: ExampleLanguage::Json,
+        failing_example: "{\"name\": \"regs\"}",
+        corrected_example: "{\"name\": \"regs\", \"registers\": [\
+                            {\"name\": \"status\", \"size\": 4, \
+                            \"offset\": \"0x0\"}]}",
+    },
+    Explanation {
+        code: "missing-documentation",
+        title: "Object without documentation",
+        patterns: &["has no documentation"],
+        explanation: "Documentation of devices, banks, registers and fields \
+                      becomes their `desc` parameter, which Simics shows \
+                      in its help and register views.",
+        language: ExampleLanguage::Json,
+        failing_example: "{\"name\": \"status\", \"size\": 4, \
+                          \"documentation\": null}",
+        corrected_example: "{\"name\": \"status\", \"size\": 4, \
+                            \"documentation\": \"Interrupt status\"}",
+    },
+];
+
+/// Codes are compared ignoring case and the difference between `-` and `_`
+fn same_code(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.chars().zip(b.chars()).all(|(a, b)| {
+        a.eq_ignore_ascii_case(&b) || (matches!(a, '-' | '_')
+                                       && matches!(b, '-' | '_'))
+    })
+}
+
+/// The explanation of a diagnostic given by its code, or by its message,
+/// which may start with the code as annotated lint warnings do
+pub fn explain_diagnostic(diagnostic: &str) -> Option<&'static Explanation> {
+    let diagnostic = diagnostic.trim();
+    let code = diagnostic.split_once(':')
+        .map_or(diagnostic, |(code, _)| code.trim());
+    if let Some(explanation) = EXPLANATIONS.iter()
+        .find(|explanation| same_code(explanation.code, code)) {
+        return Some(explanation);
+    }
+    let message = diagnostic.to_lowercase();
+    EXPLANATIONS.iter().find(|explanation| explanation.patterns.iter()
+        .any(|pattern| message.contains(&pattern.to_lowercase())))
+}
+
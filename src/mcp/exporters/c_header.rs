@@ -0,0 +1,182 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! C header generation from a device register map
+//!
+//! Produces `#define`s for register offsets and sizes, field shifts, widths
+//! and masks, and optionally a struct overlay per bank, so firmware can be
+//! written against the same register map as the model.
+
+use std::fmt::Write;
+
+use crate::mcp::generation::{BankSpec, DeviceSpec, RegisterSpec};
+
+use super::c_identifier;
+
+/// Options controlling C header generation
+#[derive(Debug, Clone)]
+pub struct CHeaderOptions {
+    /// Prefix of all generated macros, defaults to the upper-cased
+    /// device name
+    pub prefix: Option<String>,
+    /// Emit a `volatile` struct overlay for each bank
+    pub include_structs: bool,
+}
+
+impl Default for CHeaderOptions {
+    fn default() -> Self {
+        Self {
+            prefix: None,
+            include_structs: true,
+        }
+    }
+}
+
+/// Generate a C header describing the register map of a device
+pub fn generate_c_header(spec: &DeviceSpec, options: &CHeaderOptions)
+                         -> String {
+    let prefix = options.prefix.clone()
+        .unwrap_or_else(|| c_identifier(&spec.name).to_uppercase());
+    let guard = format!("{}_REGS_H", prefix);
+    let mut out = String::new();
+
+    writeln!(out, "/*").unwrap();
+    writeln!(out, " * Register definitions for DML device '{}'", spec.name)
+        .unwrap();
+    writeln!(out, " * Generated by dml-mcp-server {}",
+             env!("CARGO_PKG_VERSION")).unwrap();
+    writeln!(out, " */").unwrap();
+    writeln!(out, "#ifndef {}", guard).unwrap();
+    writeln!(out, "#define {}", guard).unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "#include <stdint.h>").unwrap();
+
+    for bank in &spec.banks {
+        writeln!(out).unwrap();
+        write_bank_defines(&mut out, &prefix, bank);
+    }
+
+    if options.include_structs {
+        for bank in &spec.banks {
+            writeln!(out).unwrap();
+            write_bank_struct(&mut out, &spec.name, bank);
+        }
+    }
+
+    writeln!(out).unwrap();
+    writeln!(out, "#endif /* {} */", guard).unwrap();
+    out
+}
+
+fn write_bank_defines(out: &mut String, prefix: &str, bank: &BankSpec) {
+    match &bank.documentation {
+        Some(doc) => writeln!(out, "/* Bank '{}': {} */", bank.name, doc),
+        None => writeln!(out, "/* Bank '{}' */", bank.name),
+    }.unwrap();
+    let bank_prefix = format!("{}_{}", prefix,
+                              c_identifier(&bank.name).to_uppercase());
+    for register in &bank.registers {
+        let base = format!("{}_{}", bank_prefix,
+                           c_identifier(&register.name).to_uppercase());
+        match (register.offset_value(), &register.offset) {
+            (Some(offset), _) =>
+                writeln!(out, "#define {}_OFFSET 0x{:x}u", base, offset),
+            (None, Some(expr)) =>
+                writeln!(out, "/* {}: offset '{}' is not a constant */",
+                         register.name, expr),
+            (None, None) =>
+                writeln!(out, "/* {}: unmapped register */", register.name),
+        }.unwrap();
+        writeln!(out, "#define {}_SIZE {}u", base, register.size).unwrap();
+        for field in &register.fields {
+            let field_base = format!("{}_{}", base,
+                                     c_identifier(&field.name).to_uppercase());
+            let (msb, lsb) = match field.bit_range() {
+                Some(range) if range.0 < 64 => range,
+                _ => {
+                    writeln!(out, "/* {}.{}: bit range '{}' is not a \
+                                   constant */",
+                             register.name, field.name, field.bits).unwrap();
+                    continue;
+                },
+            };
+            let width = msb - lsb + 1;
+            let mask = ((1u128 << width) - 1) << lsb;
+            let suffix = if msb >= 32 { "ull" } else { "u" };
+            writeln!(out, "#define {}_SHIFT {}u", field_base, lsb).unwrap();
+            writeln!(out, "#define {}_WIDTH {}u", field_base, width).unwrap();
+            writeln!(out, "#define {}_MASK 0x{:x}{}", field_base, mask, suffix)
+                .unwrap();
+        }
+    }
+}
+
+fn c_type(size: u64) -> Option<&'static str> {
+    match size {
+        1 => Some("uint8_t"),
+        2 => Some("uint16_t"),
+        4 => Some("uint32_t"),
+        8 => Some("uint64_t"),
+        _ => None,
+    }
+}
+
+fn write_bank_struct(out: &mut String, device: &str, bank: &BankSpec) {
+    let type_name = format!("{}_{}_t", c_identifier(device).to_lowercase(),
+                            c_identifier(&bank.name).to_lowercase());
+    let mut mapped: Vec<(u64, &RegisterSpec)> = bank.registers.iter()
+        .filter(|reg| reg.dimensions.is_empty())
+        .filter_map(|reg| reg.offset_value().map(|offset| (offset, reg)))
+        .collect();
+    mapped.sort_by_key(|(offset, _)| *offset);
+
+    let unmapped = bank.registers.len() - mapped.len();
+    // Register arrays are not part of the overlay, but their elements
+    // may still overlap other registers
+    let mut spans: Vec<(u64, u64)> = bank.registers.iter()
+        .filter_map(|reg| Some(reg.element_offsets()?.into_iter()
+                               .map(move |offset| (offset, reg.size))))
+        .flatten()
+        .collect();
+    spans.sort();
+    // A register ending past the end of the address space overlaps any
+    // register after it
+    let overlapping = spans.windows(2)
+        .any(|pair| pair[0].0.checked_add(pair[0].1)
+             .is_none_or(|end| end > pair[1].0));
+    let misaligned = mapped.iter()
+        .any(|(offset, reg)| c_type(reg.size).is_some()
+             && offset % reg.size != 0);
+    if overlapping || misaligned {
+        writeln!(out, "/* Struct overlay for bank '{}' omitted: registers \
+                       are {} */",
+                 bank.name,
+                 if overlapping { "overlapping" } else { "misaligned" })
+            .unwrap();
+        return;
+    }
+
+    writeln!(out, "/* Struct overlay for bank '{}'{} */", bank.name,
+             if unmapped > 0 {
+                 format!(", excluding {} register(s) without a constant \
+                          offset", unmapped)
+             } else {
+                 String::new()
+             }).unwrap();
+    writeln!(out, "typedef struct {{").unwrap();
+    let mut position = 0;
+    for (offset, register) in mapped {
+        if offset > position {
+            writeln!(out, "    uint8_t reserved_0x{:x}[{}];",
+                     position, offset - position).unwrap();
+        }
+        let name = c_identifier(&register.name).to_lowercase();
+        match c_type(register.size) {
+            Some(ctype) => writeln!(out, "    volatile {} {}; /* 0x{:x} */",
+                                    ctype, name, offset),
+            None => writeln!(out, "    volatile uint8_t {}[{}]; /* 0x{:x} */",
+                             name, register.size, offset),
+        }.unwrap();
+        position = offset.saturating_add(register.size);
+    }
+    writeln!(out, "}} {};", type_name).unwrap();
+}
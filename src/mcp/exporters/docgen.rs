@@ -0,0 +1,332 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Device documentation in Markdown or HTML
+//!
+//! The documentation of a device starts with an overview of what it
+//! instantiates, implements and connects to, followed by a table of the
+//! registers of each bank, the fields of each register with a diagram of
+//! their layout, and the signatures of the methods of the device.
+
+use anyhow::Result;
+use clap::ValueEnum;
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::mcp::analysis::parse_dml_file;
+use crate::mcp::exporters::html::escape_html;
+use crate::mcp::exporters::{render_field_layout, LayoutFormat};
+use crate::mcp::generation::{DeviceSpec, MethodSpec, RegisterSpec};
+
+/// Output format of `generate_device_docs`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum DocFormat {
+    #[default]
+    Markdown,
+    /// A standalone HTML page
+    Html,
+}
+
+impl DocFormat {
+    /// Extension of files in this format
+    pub fn extension(self) -> &'static str {
+        match self {
+            DocFormat::Markdown => "md",
+            DocFormat::Html => "html",
+        }
+    }
+}
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; color: #222; max-width: 60em; }
+table { border-collapse: collapse; margin: 0.5em 0; }
+th, td { border-bottom: 1px solid #ccc; padding: 2px 8px; text-align: left; }
+";
+
+/// Writes the blocks of a document in one of the formats. Inline text is
+/// formatted by `text` and `code` before it is passed to the blocks.
+struct DocWriter {
+    format: DocFormat,
+    out: String,
+}
+
+impl DocWriter {
+    fn text(&self, text: &str) -> String {
+        match self.format {
+            // Line breaks would end table rows and list items
+            DocFormat::Markdown => text.replace('\n', " ").replace('|', "\\|"),
+            DocFormat::Html => escape_html(text),
+        }
+    }
+
+    fn code(&self, text: &str) -> String {
+        match self.format {
+            DocFormat::Markdown => format!("`{}`", text.replace('\n', " ")),
+            DocFormat::Html => format!("<code>{}</code>", escape_html(text)),
+        }
+    }
+
+    fn heading(&mut self, level: usize, text: &str) {
+        match self.format {
+            DocFormat::Markdown =>
+                writeln!(self.out, "{} {}\n", "#".repeat(level), text),
+            DocFormat::Html =>
+                writeln!(self.out, "<h{0}>{1}</h{0}>", level, text),
+        }.unwrap();
+    }
+
+    fn paragraph(&mut self, text: &str) {
+        match self.format {
+            DocFormat::Markdown => writeln!(self.out, "{}\n", text),
+            DocFormat::Html => writeln!(self.out, "<p>{}</p>", text),
+        }.unwrap();
+    }
+
+    fn list(&mut self, items: &[String]) {
+        if items.is_empty() {
+            return;
+        }
+        match self.format {
+            DocFormat::Markdown => {
+                for item in items {
+                    writeln!(self.out, "- {}", item).unwrap();
+                }
+                writeln!(self.out).unwrap();
+            },
+            DocFormat::Html => {
+                writeln!(self.out, "<ul>").unwrap();
+                for item in items {
+                    writeln!(self.out, "<li>{}</li>", item).unwrap();
+                }
+                writeln!(self.out, "</ul>").unwrap();
+            },
+        }
+    }
+
+    fn table(&mut self, headers: &[&str], rows: &[Vec<String>]) {
+        match self.format {
+            DocFormat::Markdown => {
+                writeln!(self.out, "| {} |", headers.join(" | ")).unwrap();
+                writeln!(self.out, "|{}", "---|".repeat(headers.len()))
+                    .unwrap();
+                for row in rows {
+                    writeln!(self.out, "| {} |", row.join(" | ")).unwrap();
+                }
+                writeln!(self.out).unwrap();
+            },
+            DocFormat::Html => {
+                writeln!(self.out, "<table>\n<tr><th>{}</th></tr>",
+                         headers.join("</th><th>")).unwrap();
+                for row in rows {
+                    writeln!(self.out, "<tr><td>{}</td></tr>",
+                             row.join("</td><td>")).unwrap();
+                }
+                writeln!(self.out, "</table>").unwrap();
+            },
+        }
+    }
+
+    /// The field layout diagram of a register, if it can be drawn
+    fn layout(&mut self, register: &RegisterSpec) {
+        match self.format {
+            DocFormat::Markdown => {
+                if let Some(layout) = render_field_layout(
+                    register, LayoutFormat::Ascii) {
+                    writeln!(self.out, "```text\n{}```\n", layout).unwrap();
+                }
+            },
+            DocFormat::Html => {
+                if let Some(layout) = render_field_layout(
+                    register, LayoutFormat::Svg) {
+                    self.out.push_str(&layout);
+                }
+            },
+        }
+    }
+}
+
+/// Generate the documentation of a device
+pub fn generate_device_docs(spec: &DeviceSpec, format: DocFormat) -> String {
+    let mut doc = DocWriter { format, out: String::new() };
+    if format == DocFormat::Html {
+        writeln!(doc.out, "<!DOCTYPE html>\n<html>\n<head>\n\
+                           <meta charset=\"utf-8\">\n<title>{}</title>\n\
+                           <style>{}</style>\n</head>\n<body>",
+                 escape_html(&spec.name), STYLE).unwrap();
+    }
+    let title = format!("Device {}", doc.code(&spec.name));
+    doc.heading(1, &title);
+    if let Some(documentation) = &spec.documentation {
+        let text = doc.text(documentation);
+        doc.paragraph(&text);
+    }
+    write_overview(&mut doc, spec);
+    for bank in &spec.banks {
+        write_bank(&mut doc, &bank.name, bank.documentation.as_deref(),
+                   &bank.registers);
+    }
+    if !spec.methods.is_empty() {
+        doc.heading(2, "Methods");
+        let items: Vec<String> = spec.methods.iter()
+            .map(|method| method_item(&doc, method))
+            .collect();
+        doc.list(&items);
+    }
+    if format == DocFormat::Html {
+        writeln!(doc.out, "</body>\n</html>").unwrap();
+    }
+    doc.out
+}
+
+fn write_overview(doc: &mut DocWriter, spec: &DeviceSpec) {
+    let register_count: usize = spec.banks.iter()
+        .map(|bank| bank.registers.len())
+        .sum();
+    let plural = |count: usize| if count == 1 { "" } else { "s" };
+    let mut items = vec![format!("{} bank{} with {} register{}",
+                                 spec.banks.len(), plural(spec.banks.len()),
+                                 register_count, plural(register_count))];
+    let templates = code_list(doc, spec.base_template.iter()
+                              .chain(&spec.templates));
+    if !templates.is_empty() {
+        items.push(format!("Templates: {}", templates));
+    }
+    let interfaces = code_list(doc, spec.interfaces.iter()
+                               .map(|iface| &iface.name));
+    if !interfaces.is_empty() {
+        items.push(format!("Implements: {}", interfaces));
+    }
+    let ports = code_list(doc, spec.ports.iter().map(|port| &port.name));
+    if !ports.is_empty() {
+        items.push(format!("Ports: {}", ports));
+    }
+    let imports = code_list(doc, &spec.dependencies);
+    if !imports.is_empty() {
+        items.push(format!("Imports: {}", imports));
+    }
+    doc.heading(2, "Overview");
+    doc.list(&items);
+    if !spec.connects.is_empty() {
+        let rows: Vec<Vec<String>> = spec.connects.iter()
+            .map(|connect| vec![
+                doc.code(&connect.name),
+                code_list(doc, &connect.interfaces),
+                doc.text(connect.documentation.as_deref().unwrap_or("")),
+            ])
+            .collect();
+        doc.table(&["Connect", "Interfaces", "Description"], &rows);
+    }
+}
+
+/// Names as a comma-separated list of code
+fn code_list<'a>(doc: &DocWriter, names: impl IntoIterator<Item = &'a String>)
+                 -> String {
+    names.into_iter().map(|name| doc.code(name)).collect::<Vec<_>>().join(", ")
+}
+
+fn write_bank(doc: &mut DocWriter, name: &str, documentation: Option<&str>,
+              registers: &[RegisterSpec]) {
+    let heading = format!("Bank {}", doc.code(name));
+    doc.heading(2, &heading);
+    if let Some(documentation) = documentation {
+        let text = doc.text(documentation);
+        doc.paragraph(&text);
+    }
+    let mut registers: Vec<&RegisterSpec> = registers.iter().collect();
+    registers.sort_by_key(|reg| reg.offset_value().unwrap_or(u64::MAX));
+    let rows: Vec<Vec<String>> = registers.iter()
+        .map(|register| vec![
+            doc.code(&match (register.offset_value(), &register.offset) {
+                (Some(offset), _) => format!("0x{:x}", offset),
+                (None, Some(offset)) => offset.clone(),
+                (None, None) => "unmapped".to_string(),
+            }),
+            doc.code(&register_display_name(register)),
+            register.size.to_string(),
+            doc.code(&match (register.reset_value(), &register.reset) {
+                (Some(reset), _) => format!("0x{:x}", reset),
+                (None, reset) => reset.clone().unwrap_or_default(),
+            }),
+            doc.text(register.access.as_deref().unwrap_or("rw")),
+            doc.text(register.documentation.as_deref().unwrap_or("")),
+        ])
+        .collect();
+    doc.table(&["Offset", "Register", "Size", "Reset", "Access",
+                "Description"], &rows);
+
+    for register in registers.iter().filter(|reg| !reg.fields.is_empty()) {
+        let heading = format!("Register {}",
+                              doc.code(&format!("{}.{}", name, register.name)));
+        doc.heading(3, &heading);
+        if let Some(documentation) = &register.documentation {
+            let text = doc.text(documentation);
+            doc.paragraph(&text);
+        }
+        doc.layout(register);
+        let rows: Vec<Vec<String>> = register.fields.iter()
+            .map(|field| vec![
+                doc.code(&format!("[{}]", field.bits)),
+                doc.code(&field.name),
+                doc.text(field.access.as_deref().unwrap_or("rw")),
+                doc.text(field.documentation.as_deref().unwrap_or("")),
+            ])
+            .collect();
+        doc.table(&["Bits", "Field", "Access", "Description"], &rows);
+    }
+}
+
+/// The name of a register with its array dimensions, e.g. `r[i < 4]`
+fn register_display_name(register: &RegisterSpec) -> String {
+    let mut name = register.name.clone();
+    for dimension in &register.dimensions {
+        write!(name, "[{} < {}]", dimension.index, dimension.size).unwrap();
+    }
+    name
+}
+
+fn method_item(doc: &DocWriter, method: &MethodSpec) -> String {
+    let parameters: Vec<String> = method.parameters.iter()
+        .map(|param| format!("{}: {}", param.name, param.param_type))
+        .collect();
+    let mut signature = format!("{}({})", method.name, parameters.join(", "));
+    if let Some(return_type) = &method.return_type {
+        write!(signature, " -> {}", return_type).unwrap();
+    }
+    let mut item = doc.code(&signature);
+    if let Some(documentation) = &method.documentation {
+        write!(item, ": {}", doc.text(documentation)).unwrap();
+    }
+    item
+}
+
+/// The device of a DML file or of a device specification file
+fn load_device(path: &Path) -> Result<DeviceSpec> {
+    if path.extension().is_some_and(|ext| ext == "dml") {
+        Ok(parse_dml_file(path)?.to_device_spec())
+    } else {
+        DeviceSpec::load(path)
+    }
+}
+
+/// Document the device of a DML or specification file, for `dls docgen`.
+/// The documentation is written to `output`, or printed if there is none.
+/// Returns the exit status.
+pub fn run(path: &Path, format: DocFormat, output: Option<&Path>) -> i32 {
+    let spec = match load_device(path) {
+        Ok(spec) => spec,
+        Err(e) => {
+            eprintln!("dls docgen: {}", e);
+            return 1;
+        },
+    };
+    let docs = generate_device_docs(&spec, format);
+    match output {
+        Some(output) => {
+            if let Err(e) = std::fs::write(output, docs) {
+                eprintln!("dls docgen: {}: {}", output.display(), e);
+                return 1;
+            }
+        },
+        None => print!("{}", docs),
+    }
+    0
+}
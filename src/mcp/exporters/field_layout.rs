@@ -0,0 +1,137 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Field layout diagrams of registers
+//!
+//! A register is drawn as a row of boxes, one per field and per gap between
+//! fields, from the most significant bit down, with the bit numbers that
+//! each box spans above it. Fields that overlap a more significant field
+//! can not be drawn and are left out.
+
+use std::fmt::Write;
+
+use crate::mcp::exporters::html::escape_html;
+use crate::mcp::generation::RegisterSpec;
+
+/// Output format of `render_field_layout`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayoutFormat {
+    /// Plain text, for comments and terminals
+    Ascii,
+    /// A standalone SVG image, for documentation
+    Svg,
+}
+
+/// Width of a bit in SVG diagrams, in pixels
+const SVG_BIT_WIDTH: u64 = 28;
+const SVG_RULER_HEIGHT: u64 = 18;
+const SVG_BOX_HEIGHT: u64 = 32;
+
+/// A box of the diagram: the most and least significant bits it spans,
+/// and the field occupying them, if any
+struct Segment<'a> {
+    msb: u64,
+    lsb: u64,
+    name: Option<&'a str>,
+}
+
+/// The boxes of a register, most significant first, or `None` if the
+/// register is too wide to draw
+fn segments(register: &RegisterSpec) -> Option<Vec<Segment<'_>>> {
+    let width = register.size * 8;
+    if width == 0 || width > 64 {
+        return None;
+    }
+    let mut ranges: Vec<(u64, u64, &str)> = register.fields.iter()
+        .filter_map(|f| f.bit_range().map(|(msb, lsb)| (msb, lsb, f.name.as_str())))
+        .filter(|(msb, _, _)| *msb < width)
+        .collect();
+    ranges.sort_by_key(|(msb, _, _)| std::cmp::Reverse(*msb));
+
+    let mut segments = vec![];
+    let mut bit = width;
+    for (msb, lsb, name) in ranges {
+        if msb >= bit {
+            continue;
+        }
+        if msb + 1 < bit {
+            segments.push(Segment { msb: bit - 1, lsb: msb + 1, name: None });
+        }
+        segments.push(Segment { msb, lsb, name: Some(name) });
+        bit = lsb;
+    }
+    if bit > 0 {
+        segments.push(Segment { msb: bit - 1, lsb: 0, name: None });
+    }
+    Some(segments)
+}
+
+/// Draw the field layout of a register, or `None` if it is wider than 64
+/// bits
+pub fn render_field_layout(register: &RegisterSpec, format: LayoutFormat)
+                           -> Option<String> {
+    let segments = segments(register)?;
+    Some(match format {
+        LayoutFormat::Ascii => render_ascii(&segments),
+        LayoutFormat::Svg => render_svg(register, &segments),
+    })
+}
+
+fn render_ascii(segments: &[Segment<'_>]) -> String {
+    let (mut ruler, mut border, mut names) =
+        (String::new(), String::new(), String::new());
+    for segment in segments {
+        let name = segment.name.unwrap_or("-");
+        let (msb, lsb) = (segment.msb.to_string(), segment.lsb.to_string());
+        let bits_width = if segment.msb == segment.lsb {
+            msb.len()
+        } else {
+            msb.len() + lsb.len() + 1
+        };
+        let width = name.len().max(bits_width) + 2;
+        if segment.msb == segment.lsb {
+            write!(ruler, " {:^width$}", msb).unwrap();
+        } else {
+            write!(ruler, " {}{:>pad$}", msb, lsb, pad = width - msb.len())
+                .unwrap();
+        }
+        write!(border, "+{}", "-".repeat(width)).unwrap();
+        write!(names, "|{:^width$}", name).unwrap();
+    }
+    format!("{}\n{}+\n{}|\n{}+\n", ruler.trim_end(), border, names, border)
+}
+
+fn render_svg(register: &RegisterSpec, segments: &[Segment<'_>]) -> String {
+    let width = register.size * 8 * SVG_BIT_WIDTH;
+    let height = SVG_RULER_HEIGHT + SVG_BOX_HEIGHT;
+    let mut out = String::new();
+    writeln!(out, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" \
+                   height=\"{1}\" viewBox=\"-1 -1 {2} {3}\" \
+                   font-family=\"monospace\" font-size=\"11\">",
+             width, height, width + 2, height + 2).unwrap();
+    writeln!(out, "<title>{}</title>", escape_html(&register.name)).unwrap();
+    let mut x = 0;
+    for segment in segments {
+        let box_width = (segment.msb - segment.lsb + 1) * SVG_BIT_WIDTH;
+        let fill = if segment.name.is_some() { "#dde8f5" } else { "#eeeeee" };
+        writeln!(out, "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" \
+                       fill=\"{}\" stroke=\"#999999\"/>",
+                 x, SVG_RULER_HEIGHT, box_width, SVG_BOX_HEIGHT, fill).unwrap();
+        // Bit numbers at the edges of the box
+        writeln!(out, "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\" \
+                       fill=\"#777777\">{}</text>",
+                 x + SVG_BIT_WIDTH / 2, SVG_RULER_HEIGHT - 5, segment.msb)
+            .unwrap();
+        if segment.lsb != segment.msb {
+            writeln!(out, "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\" \
+                           fill=\"#777777\">{}</text>",
+                     x + box_width - SVG_BIT_WIDTH / 2, SVG_RULER_HEIGHT - 5,
+                     segment.lsb).unwrap();
+        }
+        writeln!(out, "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">{}</text>",
+                 x + box_width / 2, SVG_RULER_HEIGHT + SVG_BOX_HEIGHT / 2 + 4,
+                 escape_html(segment.name.unwrap_or("-"))).unwrap();
+        x += box_width;
+    }
+    writeln!(out, "</svg>").unwrap();
+    out
+}
@@ -0,0 +1,170 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Standalone HTML register map viewer
+//!
+//! The generated page has no external dependencies: banks, registers and
+//! fields are rendered as nested collapsible sections with a bit diagram per
+//! register, and a small inline script filters them by a search string.
+
+use std::fmt::Write;
+
+use crate::mcp::generation::{DeviceSpec, FieldSpec, RegisterSpec};
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; color: #222; }
+h1 { font-size: 1.5em; }
+input#search { width: 24em; padding: 0.3em; margin-bottom: 1em; }
+details { margin: 0.3em 0 0.3em 1em; }
+summary { cursor: pointer; }
+.offset { font-family: monospace; color: #555; margin-right: 0.5em; }
+.doc { color: #666; font-style: italic; margin-left: 0.5em; }
+table.bits { border-collapse: collapse; margin: 0.5em 0; font-size: 0.8em; }
+table.bits td { border: 1px solid #999; padding: 2px 4px; text-align: center; }
+table.bits tr.index td { border: none; color: #777; font-family: monospace; }
+td.field { background: #dde8f5; }
+td.unused { background: #eee; color: #aaa; }
+table.fields { border-collapse: collapse; margin: 0.5em 0; }
+table.fields th, table.fields td { border-bottom: 1px solid #ccc;
+                                   padding: 2px 8px; text-align: left; }
+.hidden { display: none; }
+";
+
+const SCRIPT: &str = "
+document.getElementById('search').addEventListener('input', function (e) {
+  var query = e.target.value.toLowerCase();
+  document.querySelectorAll('details.register').forEach(function (reg) {
+    var match = query === '' ||
+        reg.getAttribute('data-search').indexOf(query) >= 0;
+    reg.classList.toggle('hidden', !match);
+    reg.open = match && query !== '';
+  });
+  document.querySelectorAll('details.bank').forEach(function (bank) {
+    if (query !== '') {
+      bank.open = bank.querySelector('details.register:not(.hidden)') !== null;
+    }
+  });
+});
+";
+
+/// Escape text for inclusion in HTML content or attribute values
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Generate a standalone HTML page showing the register map of a device
+pub fn generate_register_map_html(spec: &DeviceSpec) -> String {
+    let mut out = String::new();
+    let title = format!("Register map of {}", spec.name);
+
+    writeln!(out, "<!DOCTYPE html>").unwrap();
+    writeln!(out, "<html>\n<head>\n<meta charset=\"utf-8\">").unwrap();
+    writeln!(out, "<title>{}</title>", escape_html(&title)).unwrap();
+    writeln!(out, "<style>{}</style>\n</head>\n<body>", STYLE).unwrap();
+    writeln!(out, "<h1>{}</h1>", escape_html(&title)).unwrap();
+    if let Some(doc) = &spec.documentation {
+        writeln!(out, "<p>{}</p>", escape_html(doc)).unwrap();
+    }
+    writeln!(out, "<input id=\"search\" type=\"search\" \
+                   placeholder=\"Filter registers and fields\">").unwrap();
+
+    for bank in &spec.banks {
+        writeln!(out, "<details class=\"bank\" open>").unwrap();
+        write!(out, "<summary><b>bank {}</b> ({} registers)",
+               escape_html(&bank.name), bank.registers.len()).unwrap();
+        if let Some(doc) = &bank.documentation {
+            write!(out, "<span class=\"doc\">{}</span>", escape_html(doc))
+                .unwrap();
+        }
+        writeln!(out, "</summary>").unwrap();
+        let mut registers: Vec<&RegisterSpec> = bank.registers.iter()
+            .collect();
+        registers.sort_by_key(|reg| reg.offset_value().unwrap_or(u64::MAX));
+        for register in registers {
+            write_register(&mut out, register);
+        }
+        writeln!(out, "</details>").unwrap();
+    }
+
+    writeln!(out, "<script>{}</script>\n</body>\n</html>", SCRIPT).unwrap();
+    out
+}
+
+fn write_register(out: &mut String, register: &RegisterSpec) {
+    let search_text = std::iter::once(register.name.as_str())
+        .chain(register.fields.iter().map(|f| f.name.as_str()))
+        .collect::<Vec<_>>().join(" ").to_lowercase();
+    writeln!(out, "<details class=\"register\" data-search=\"{}\">",
+             escape_html(&search_text)).unwrap();
+    write!(out, "<summary><span class=\"offset\">{}</span>{} \
+                 <small>({} bytes)</small>",
+           escape_html(register.offset.as_deref().unwrap_or("unmapped")),
+           escape_html(&register.name), register.size).unwrap();
+    if let Some(doc) = &register.documentation {
+        write!(out, "<span class=\"doc\">{}</span>", escape_html(doc)).unwrap();
+    }
+    writeln!(out, "</summary>").unwrap();
+    write_bit_diagram(out, register);
+    if !register.fields.is_empty() {
+        writeln!(out, "<table class=\"fields\">\n<tr><th>Field</th>\
+                       <th>Bits</th><th>Access</th><th>Description</th></tr>")
+            .unwrap();
+        for field in &register.fields {
+            writeln!(out, "<tr><td>{}</td><td>[{}]</td><td>{}</td>\
+                           <td>{}</td></tr>",
+                     escape_html(&field.name), escape_html(&field.bits),
+                     escape_html(field.access.as_deref().unwrap_or("rw")),
+                     escape_html(field.documentation.as_deref()
+                                 .unwrap_or(""))).unwrap();
+        }
+        writeln!(out, "</table>").unwrap();
+    }
+    writeln!(out, "</details>").unwrap();
+}
+
+fn write_bit_diagram(out: &mut String, register: &RegisterSpec) {
+    let width = register.size * 8;
+    if width == 0 || width > 64 {
+        return;
+    }
+    let mut ranges: Vec<(u64, u64, &FieldSpec)> = register.fields.iter()
+        .filter_map(|f| f.bit_range().map(|(msb, lsb)| (msb, lsb, f)))
+        .filter(|(msb, _, _)| *msb < width)
+        .collect();
+    ranges.sort_by_key(|(msb, _, _)| std::cmp::Reverse(*msb));
+
+    writeln!(out, "<table class=\"bits\">\n<tr class=\"index\">").unwrap();
+    for bit in (0..width).rev() {
+        write!(out, "<td>{}</td>", bit).unwrap();
+    }
+    writeln!(out, "</tr>\n<tr>").unwrap();
+    let mut bit = width;
+    for (msb, lsb, field) in ranges {
+        // Overlapping fields are listed in the field table, but can not
+        // be drawn
+        if msb >= bit {
+            continue;
+        }
+        for _ in msb + 1..bit {
+            write!(out, "<td class=\"unused\">-</td>").unwrap();
+        }
+        write!(out, "<td class=\"field\" colspan=\"{}\" title=\"{}\">{}</td>",
+               msb - lsb + 1, escape_html(&field.bits),
+               escape_html(&field.name)).unwrap();
+        bit = lsb;
+    }
+    for _ in 0..bit {
+        write!(out, "<td class=\"unused\">-</td>").unwrap();
+    }
+    writeln!(out, "\n</tr>\n</table>").unwrap();
+}
@@ -0,0 +1,33 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Exporters producing non-DML artifacts from a device specification
+
+use serde::Serialize;
+
+pub mod c_header;
+pub mod docgen;
+pub mod field_layout;
+pub mod html;
+pub mod simics_test;
+pub mod tools;
+
+pub use c_header::{generate_c_header, CHeaderOptions};
+pub use docgen::{generate_device_docs, DocFormat};
+pub use field_layout::{render_field_layout, LayoutFormat};
+pub use html::generate_register_map_html;
+pub use simics_test::{generate_simics_test, SimicsTestOptions};
+
+/// A file produced by an exporter, with a path relative to the output
+/// directory
+#[derive(Debug, Clone, Serialize)]
+pub struct GeneratedFile {
+    pub path: String,
+    pub content: String,
+}
+
+/// Turn a (possibly qualified) DML name into a C identifier fragment
+pub fn c_identifier(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
@@ -0,0 +1,249 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Simics test skeleton generation
+//!
+//! Produces a test suite directory in the layout used by Simics packages:
+//! an (empty) `SUITEINFO` marker, a common Python module that instantiates
+//! the device and wraps its registers with `dev_util`, and `s-*.py` scripts
+//! checking reset values and the read/write behavior implied by field
+//! access types.
+
+use std::fmt::Write;
+
+use crate::mcp::generation::{DeviceSpec, RegisterSpec};
+
+use super::{c_identifier, GeneratedFile};
+
+/// Options controlling test skeleton generation
+#[derive(Debug, Clone)]
+pub struct SimicsTestOptions {
+    /// Directory of the generated test suite
+    pub suite_dir: String,
+}
+
+impl Default for SimicsTestOptions {
+    fn default() -> Self {
+        Self {
+            suite_dir: "test".to_string(),
+        }
+    }
+}
+
+/// Bit masks of a register grouped by the behavior a test can check
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct AccessMasks {
+    /// Bits that read back what was written
+    read_write: u64,
+    /// Bits that keep their reset value when written
+    read_only: u64,
+    /// Bits that read as zero, also after being written with ones
+    read_zero: u64,
+    /// Bits that are set when written with ones
+    write_one_set: u64,
+    /// Bits that are cleared when read
+    clear_on_read: u64,
+}
+
+fn width_mask(bits: u64) -> u64 {
+    if bits >= 64 { u64::MAX } else { (1 << bits) - 1 }
+}
+
+fn access_masks(register: &RegisterSpec) -> AccessMasks {
+    let mut masks = AccessMasks::default();
+    if register.fields.is_empty() {
+        masks.read_write = width_mask(register.size * 8);
+        return masks;
+    }
+    for field in &register.fields {
+        let mask = match field.bit_range() {
+            Some((msb, lsb)) if msb < 64 =>
+                width_mask(msb - lsb + 1) << lsb,
+            _ => continue,
+        };
+        match field.access.as_deref().unwrap_or("rw") {
+            "rw" => masks.read_write |= mask,
+            "ro" | "const" | "reserved" => masks.read_only |= mask,
+            "wo" | "w1c" => masks.read_zero |= mask,
+            "w1s" => masks.write_one_set |= mask,
+            "rc" => masks.clear_on_read |= mask,
+            // Unknown access types are not checked
+            _ => (),
+        }
+    }
+    masks
+}
+
+/// A register that the tests can access, with its Python variable name
+struct TestedRegister<'s> {
+    variable: String,
+    label: String,
+    offset: u64,
+    register: &'s RegisterSpec,
+}
+
+fn tested_registers(spec: &DeviceSpec) -> (Vec<TestedRegister<'_>>,
+                                           Vec<String>) {
+    let mut tested = vec![];
+    let mut skipped = vec![];
+    for bank in &spec.banks {
+        for register in &bank.registers {
+            let label = format!("{}.{}", bank.name, register.name);
+            match register.offset_value() {
+                Some(offset) if register.size <= 8 => tested.push(
+                    TestedRegister {
+                        variable: c_identifier(&label).to_lowercase(),
+                        label,
+                        offset,
+                        register,
+                    }),
+                _ => skipped.push(label),
+            }
+        }
+    }
+    (tested, skipped)
+}
+
+/// Generate a Simics test suite skeleton for a device
+pub fn generate_simics_test(spec: &DeviceSpec, options: &SimicsTestOptions)
+                            -> Vec<GeneratedFile> {
+    let device = c_identifier(&spec.name);
+    let common_module = format!("{}_common", device);
+    let (registers, skipped) = tested_registers(spec);
+    let path = |name: &str| format!("{}/{}", options.suite_dir, name);
+
+    vec![
+        GeneratedFile {
+            path: path("SUITEINFO"),
+            content: String::new(),
+        },
+        GeneratedFile {
+            path: path(&format!("{}.py", common_module)),
+            content: common_script(spec, &registers, &skipped),
+        },
+        GeneratedFile {
+            path: path(&format!("s-{}-reset.py", device)),
+            content: reset_script(spec, &common_module, &registers),
+        },
+        GeneratedFile {
+            path: path(&format!("s-{}-access.py", device)),
+            content: access_script(spec, &common_module, &registers),
+        },
+    ]
+}
+
+fn common_script(spec: &DeviceSpec, registers: &[TestedRegister<'_>],
+                 skipped: &[String]) -> String {
+    let device = c_identifier(&spec.name);
+    let mut out = String::new();
+    writeln!(out, "# Common setup for the tests of the {} device", spec.name)
+        .unwrap();
+    writeln!(out, "# Generated by dml-mcp-server {}\n",
+             env!("CARGO_PKG_VERSION")).unwrap();
+    writeln!(out, "import dev_util\nimport simics\n").unwrap();
+    writeln!(out, "def create_{}(name=\"{}\"):", device, device).unwrap();
+    writeln!(out, "    '''Instantiate a {} device'''", spec.name).unwrap();
+    writeln!(out, "    dev = simics.pre_conf_object(name, \"{}\")", spec.name)
+        .unwrap();
+    for interface in &spec.interfaces {
+        writeln!(out, "    # TODO: connect objects needed by the '{}' \
+                       interface", interface.name).unwrap();
+    }
+    writeln!(out, "    simics.SIM_add_configuration([dev], None)").unwrap();
+    writeln!(out, "    return simics.SIM_get_object(name)\n").unwrap();
+    writeln!(out, "class Registers:").unwrap();
+    writeln!(out, "    '''dev_util wrappers for the registers of a {} \
+                   device'''", spec.name).unwrap();
+    writeln!(out, "    def __init__(self, dev):").unwrap();
+    if registers.is_empty() && skipped.is_empty() {
+        writeln!(out, "        pass").unwrap();
+    }
+    for reg in registers {
+        let bank = reg.label.split('.').next().unwrap_or_default();
+        writeln!(out, "        self.{} = dev_util.Register_LE(\n            \
+                       dev.bank.{}, 0x{:x}, size={})",
+                 reg.variable, bank, reg.offset, reg.register.size).unwrap();
+    }
+    for label in skipped {
+        writeln!(out, "        # TODO: {} has no constant offset or is \
+                       wider than 8 bytes", label).unwrap();
+    }
+    out
+}
+
+fn script_prelude(out: &mut String, spec: &DeviceSpec, common_module: &str,
+                  summary: &str) {
+    let device = c_identifier(&spec.name);
+    writeln!(out, "# {} of the {} device", summary, spec.name).unwrap();
+    writeln!(out, "# Generated by dml-mcp-server {}\n",
+             env!("CARGO_PKG_VERSION")).unwrap();
+    writeln!(out, "import stest").unwrap();
+    writeln!(out, "from {} import create_{}, Registers\n",
+             common_module, device).unwrap();
+    writeln!(out, "dev = create_{}()", device).unwrap();
+    writeln!(out, "regs = Registers(dev)").unwrap();
+}
+
+fn reset_script(spec: &DeviceSpec, common_module: &str,
+                registers: &[TestedRegister<'_>]) -> String {
+    let mut out = String::new();
+    script_prelude(&mut out, spec, common_module,
+                   "Check the reset values of the registers");
+    writeln!(out).unwrap();
+    for reg in registers {
+        match reg.register.reset_value() {
+            Some(reset) => writeln!(
+                out, "stest.expect_equal(regs.{}.read(), 0x{:x}, \
+                      \"{} after reset\")",
+                reg.variable, reset, reg.label),
+            None => writeln!(
+                out, "# TODO: {} resets to '{}', which is not a constant",
+                reg.label, reg.register.reset.as_deref().unwrap_or_default()),
+        }.unwrap();
+    }
+    out
+}
+
+/// Check the bits in `mask` of the last read `value`
+fn write_expect(out: &mut String, label: &str, mask: u64, expected: u64,
+                what: &str) {
+    if mask != 0 {
+        writeln!(out, "stest.expect_equal(value & 0x{:x}, 0x{:x}, \"{}: {}\")",
+                 mask, expected & mask, label, what).unwrap();
+    }
+}
+
+fn access_script(spec: &DeviceSpec, common_module: &str,
+                 registers: &[TestedRegister<'_>]) -> String {
+    let mut out = String::new();
+    script_prelude(&mut out, spec, common_module,
+                   "Exercise the read/write behavior of the registers");
+    for reg in registers {
+        let masks = access_masks(reg.register);
+        let pattern = width_mask(reg.register.size * 8);
+        let reset = reg.register.reset_value();
+        writeln!(out, "\n# {}", reg.label).unwrap();
+        writeln!(out, "regs.{}.write(0x{:x})", reg.variable, pattern).unwrap();
+        writeln!(out, "value = regs.{}.read()", reg.variable).unwrap();
+        let label = reg.label.as_str();
+        write_expect(&mut out, label, masks.read_write, pattern,
+                     "read/write bits");
+        write_expect(&mut out, label, masks.write_one_set, pattern,
+                     "write-1-to-set bits");
+        write_expect(&mut out, label, masks.read_zero, 0,
+                     "write-only and write-1-to-clear bits");
+        match reset {
+            Some(reset) => write_expect(&mut out, label, masks.read_only,
+                                        reset, "read-only bits"),
+            None if masks.read_only != 0 => writeln!(
+                out, "# TODO: check read-only bits against the reset value")
+                .unwrap(),
+            None => (),
+        }
+        if masks.clear_on_read != 0 {
+            writeln!(out, "stest.expect_equal(regs.{}.read() & 0x{:x}, 0, \
+                           \"{}: clear-on-read bits\")",
+                     reg.variable, masks.clear_on_read, reg.label).unwrap();
+        }
+    }
+    out
+}
@@ -0,0 +1,278 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! MCP tools exposing the exporters
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+use crate::mcp::exporters::{generate_c_header, generate_device_docs,
+                             generate_register_map_html, generate_simics_test,
+                             render_field_layout, CHeaderOptions, DocFormat,
+                             LayoutFormat, SimicsTestOptions};
+use crate::mcp::generation::RegisterSpec;
+use crate::mcp::schema::validate_device_spec;
+use crate::mcp::tools::{device_source_properties, device_spec_from_input,
+                        DMLTool, ToolContent, ToolResult};
+
+/// Generate a Simics test suite skeleton for a device
+#[derive(Default)]
+pub struct GenerateTestTool;
+
+#[async_trait]
+impl DMLTool for GenerateTestTool {
+    fn name(&self) -> &str {
+        "generate_test"
+    }
+    
+    fn description(&self) -> &str {
+        "Generate a Simics Python test suite (SUITEINFO and s-*.py scripts) \
+         that instantiates a device, checks register reset values and \
+         exercises read/write side effects"
+    }
+    
+    fn input_schema(&self) -> Value {
+        let mut properties = device_source_properties();
+        properties["suite_dir"] = json!({
+            "type": "string",
+            "description": "Directory of the generated test suite",
+            "default": "test"
+        });
+        json!({
+            "type": "object",
+            "properties": properties
+        })
+    }
+    
+    async fn execute(&self, input: Value) -> Result<ToolResult> {
+        let spec = device_spec_from_input(&input)?;
+        let mut options = SimicsTestOptions::default();
+        if let Some(suite_dir) = input["suite_dir"].as_str() {
+            options.suite_dir = suite_dir.to_string();
+        }
+        
+        let files = generate_simics_test(&spec, &options);
+        
+        Ok(ToolResult {
+            content: vec![ToolContent {
+                content_type: "text".to_string(),
+                text: serde_json::to_string_pretty(&json!({ "files": files }))?,
+            }],
+            is_error: None,
+        })
+    }
+}
+
+/// Generate a C header with register offsets, field masks and struct overlays
+#[derive(Default)]
+pub struct GenerateCHeaderTool;
+
+#[async_trait]
+impl DMLTool for GenerateCHeaderTool {
+    fn name(&self) -> &str {
+        "generate_c_header"
+    }
+    
+    fn description(&self) -> &str {
+        "Generate a C header with register offset defines, field masks/shifts \
+         and struct overlays for a device's register map"
+    }
+    
+    fn input_schema(&self) -> Value {
+        let mut properties = device_source_properties();
+        properties["prefix"] = json!({
+            "type": "string",
+            "description": "Prefix for generated macros (default: upper-cased device name)"
+        });
+        properties["include_structs"] = json!({
+            "type": "boolean",
+            "description": "Emit a struct overlay per bank (default: true)"
+        });
+        json!({
+            "type": "object",
+            "properties": properties
+        })
+    }
+    
+    async fn execute(&self, input: Value) -> Result<ToolResult> {
+        let spec = device_spec_from_input(&input)?;
+        let options = CHeaderOptions {
+            prefix: input["prefix"].as_str().map(|s| s.to_string()),
+            include_structs: input["include_structs"].as_bool().unwrap_or(true),
+        };
+        
+        Ok(ToolResult {
+            content: vec![ToolContent {
+                content_type: "text".to_string(),
+                text: generate_c_header(&spec, &options),
+            }],
+            is_error: None,
+        })
+    }
+}
+
+/// Export a standalone, searchable HTML register map viewer
+#[derive(Default)]
+pub struct ExportRegisterMapHtmlTool;
+
+#[async_trait]
+impl DMLTool for ExportRegisterMapHtmlTool {
+    fn name(&self) -> &str {
+        "export_register_map_html"
+    }
+    
+    fn description(&self) -> &str {
+        "Export a device's register map as a standalone HTML page with \
+         searchable, collapsible banks, registers and field bit diagrams"
+    }
+    
+    fn input_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": device_source_properties()
+        })
+    }
+    
+    async fn execute(&self, input: Value) -> Result<ToolResult> {
+        let spec = device_spec_from_input(&input)?;
+        
+        Ok(ToolResult {
+            content: vec![ToolContent {
+                content_type: "text".to_string(),
+                text: generate_register_map_html(&spec),
+            }],
+            is_error: None,
+        })
+    }
+}
+
+/// Generate the documentation of a device
+#[derive(Default)]
+pub struct GenerateDocsTool;
+
+#[async_trait]
+impl DMLTool for GenerateDocsTool {
+    fn name(&self) -> &str {
+        "generate_docs"
+    }
+    
+    fn description(&self) -> &str {
+        "Generate Markdown or HTML documentation of a device: an overview, a \
+         register table per bank, the fields of each register with a layout \
+         diagram, and the device methods"
+    }
+    
+    fn input_schema(&self) -> Value {
+        let mut properties = device_source_properties();
+        properties["format"] = json!({
+            "type": "string",
+            "enum": ["markdown", "html"],
+            "default": "markdown"
+        });
+        json!({
+            "type": "object",
+            "properties": properties
+        })
+    }
+    
+    async fn execute(&self, input: Value) -> Result<ToolResult> {
+        let format = match input["format"].as_str() {
+            None | Some("markdown") => DocFormat::Markdown,
+            Some("html") => DocFormat::Html,
+            Some(other) => return Err(anyhow!("Unknown format: {}", other)),
+        };
+        let spec = device_spec_from_input(&input)?;
+        
+        Ok(ToolResult {
+            content: vec![ToolContent {
+                content_type: "text".to_string(),
+                text: generate_device_docs(&spec, format),
+            }],
+            is_error: None,
+        })
+    }
+}
+
+/// Draw the field layout of a register
+#[derive(Default)]
+pub struct RenderFieldLayoutTool;
+
+#[async_trait]
+impl DMLTool for RenderFieldLayoutTool {
+    fn name(&self) -> &str {
+        "render_field_layout"
+    }
+    
+    fn description(&self) -> &str {
+        "Draw the field layout of a register, as ASCII art with a bit ruler \
+         for comments and reviews, or as SVG for documentation"
+    }
+    
+    fn input_schema(&self) -> Value {
+        let mut properties = device_source_properties();
+        properties["register"] = json!({
+            "type": "string",
+            "description": "Name of the register of the device to draw, \
+                            optionally qualified by its bank as bank.register"
+        });
+        properties["register_spec"] = json!({
+            "type": "object",
+            "description": "Register to draw, as in a device specification, \
+                            instead of a register of a device"
+        });
+        properties["format"] = json!({
+            "type": "string",
+            "enum": ["ascii", "svg"],
+            "default": "ascii"
+        });
+        json!({
+            "type": "object",
+            "properties": properties,
+            "anyOf": [
+                {"required": ["register"]},
+                {"required": ["register_spec"]}
+            ]
+        })
+    }
+    
+    async fn execute(&self, input: Value) -> Result<ToolResult> {
+        let format = match input["format"].as_str() {
+            None | Some("ascii") => LayoutFormat::Ascii,
+            Some("svg") => LayoutFormat::Svg,
+            Some(other) => return Err(anyhow!("Unknown format: {}", other)),
+        };
+        let register: RegisterSpec = if let Some(qname) = input["register"].as_str() {
+            let spec = device_spec_from_input(&input)?;
+            let (bank, name) = match qname.split_once('.') {
+                Some((bank, name)) => (Some(bank), name),
+                None => (None, qname),
+            };
+            spec.banks.iter()
+                .filter(|b| bank.is_none_or(|bank| b.name == bank))
+                .flat_map(|b| &b.registers)
+                .find(|register| register.name == name)
+                .cloned()
+                .ok_or_else(|| anyhow!("No register {} in device {}",
+                                       qname, spec.name))?
+        } else {
+            // Checked as the register of a device, to reuse the schema
+            let register = &input["register_spec"];
+            validate_device_spec(&json!({
+                "name": "device",
+                "banks": [{"name": "bank", "registers": [register]}]
+            })).map_err(|e| anyhow!("Invalid register_spec: {}", e))?;
+            serde_json::from_value(register.clone())?
+        };
+        let layout = render_field_layout(&register, format)
+            .ok_or_else(|| anyhow!("Register {} of {} bytes can not be drawn",
+                                   register.name, register.size))?;
+        
+        Ok(ToolResult {
+            content: vec![ToolContent {
+                content_type: "text".to_string(),
+                text: layout,
+            }],
+            is_error: None,
+        })
+    }
+}
@@ -1,9 +1,18 @@
 //! Code generation engine for DML
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use log::{debug, info};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::catalog::{Catalog, InterfaceInfo};
+use crate::mcp::provenance::{BannerConfig, Provenance};
+use crate::mcp::schema::validate_device_spec;
+
+pub use crate::formatting::{FormatConfig, IndentStyle};
 
 /// Code generation context
 #[derive(Debug, Clone)]
@@ -18,33 +27,109 @@ pub struct GenerationContext {
 /// Generation configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GenerationConfig {
-    pub indent_style: IndentStyle,
+    /// Indentation, and the maximum line length that method signatures,
+    /// field declarations and documentation comments are wrapped to, as
+    /// for formatted code
+    #[serde(flatten)]
+    pub format: FormatConfig,
     pub line_ending: LineEnding,
-    pub max_line_length: usize,
     pub generate_docs: bool,
     pub validate_output: bool,
+    /// How reserved regions of banks are represented
+    #[serde(default)]
+    pub reserved_regions: ReservedRegionPolicy,
+    /// How devices are split across files
+    #[serde(default)]
+    pub split: SplitMode,
+    /// Log statements added to the read and write methods of registers
+    #[serde(default)]
+    pub logging: LoggingPolicy,
+    /// Start generated files with a banner recording their provenance
+    #[serde(default)]
+    pub banner: Option<BannerConfig>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum IndentStyle {
-    Spaces(usize),
-    Tabs,
+/// Representation of reserved address regions in generated banks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReservedRegionPolicy {
+    /// Emit placeholder registers instantiating the `reserved` template
+    Registers,
+    /// Leave the region unmapped
+    Gap,
+    /// Leave the region unmapped, but document it with a parameter
+    #[default]
+    Params,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Files a generated device is split across, see `DeviceParts::into_files`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SplitMode {
+    /// A single file
+    #[default]
+    None,
+    /// The banks and the interfaces each in a file of their own
+    Sections,
+    /// Every bank in a file of its own
+    Banks,
+}
+
+/// Logging in the read and write methods of generated registers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+         clap::ValueEnum)]
+pub enum LoggingPolicy {
+    /// Leave the methods as specified
+    #[default]
+    None,
+    /// Log written values with `log info, 2`, and writes to bits outside
+    /// the fields of the register with `log spec_viol`
+    Minimal,
+    /// As `Minimal`, and also log reads with `log info, 3`
+    Verbose,
+}
+
+/// How `DeviceSpec::merge` resolves conflicting definitions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeStrategy {
+    /// Fail on the first conflict
+    #[default]
+    Error,
+    /// Keep the definitions of the specification merged into
+    PreferExisting,
+    /// Replace definitions with those of the merged specification
+    PreferNew,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum LineEnding {
     Unix,
     Windows,
 }
 
+impl LineEnding {
+    /// The characters ending each line
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Unix => "\n",
+            LineEnding::Windows => "\r\n",
+        }
+    }
+}
+
+/// Columns taken up by a tab, when wrapping code indented with tabs
+const TAB_WIDTH: usize = 8;
+
 impl Default for GenerationConfig {
     fn default() -> Self {
         Self {
-            indent_style: IndentStyle::Spaces(4),
+            format: FormatConfig::default(),
             line_ending: LineEnding::Unix,
-            max_line_length: 100,
             generate_docs: true,
             validate_output: true,
+            reserved_regions: ReservedRegionPolicy::default(),
+            split: SplitMode::default(),
+            logging: LoggingPolicy::default(),
+            banner: None,
         }
     }
 }
@@ -53,6 +138,8 @@ impl Default for GenerationConfig {
 pub struct DMLGenerator {
     pub context: GenerationContext,
     templates: TemplateRegistry,
+    /// Interfaces to generate stub methods of, see `generate_interface`
+    catalog: Arc<Catalog>,
 }
 
 impl DMLGenerator {
@@ -60,54 +147,122 @@ impl DMLGenerator {
         Self {
             context,
             templates: TemplateRegistry::new(),
+            catalog: Arc::new(Catalog::builtin()),
         }
     }
     
-    /// Generate a complete device
+    /// Use the interfaces of `catalog` rather than those of the bundled
+    /// catalog
+    pub fn with_catalog(mut self, catalog: Arc<Catalog>) -> Self {
+        self.catalog = catalog;
+        self
+    }
+    
+    /// Warnings about the interfaces of `spec` that the catalog does not
+    /// know, which are likely misspelled
+    pub fn interface_warnings(&self, spec: &DeviceSpec) -> Vec<String> {
+        spec.interface_names().into_iter()
+            .filter_map(|name| self.catalog.unknown_interface_warning(name))
+            .collect()
+    }
+    
+    /// Generate a complete device, in a single file
     pub async fn generate_device(
         &self,
         device_spec: &DeviceSpec,
     ) -> Result<GeneratedCode> {
+        let mut files = self.device_files(device_spec, SplitMode::None).await?;
+        Ok(files.remove(0))
+    }
+    
+    /// Generate a complete device, split across files as configured. The
+    /// file declaring the device comes first, importing the others.
+    pub async fn generate_device_files(
+        &self,
+        device_spec: &DeviceSpec,
+    ) -> Result<Vec<GeneratedCode>> {
+        self.device_files(device_spec, self.context.config.split).await
+    }
+    
+    async fn device_files(&self, device_spec: &DeviceSpec, split: SplitMode)
+                          -> Result<Vec<GeneratedCode>> {
         info!("Generating device: {}", device_spec.name);
         
-        let mut code = String::new();
-        
-        // Generate header
-        code.push_str(&self.generate_header()?);
-        
-        // Generate device declaration
-        code.push_str(&self.generate_device_declaration(device_spec)?);
+        let mut parts = DeviceParts {
+            name: device_spec.name.clone(),
+            imports: self.context.imports.clone(),
+            declarations: self.generate_declarations(device_spec),
+            // Generate device declaration
+            declaration: self.generate_device_declaration(device_spec)?,
+            banks: vec![],
+            interfaces: String::new(),
+            rest: String::new(),
+        };
         
         // Generate banks
         for bank in &device_spec.banks {
-            code.push_str(&self.generate_bank(bank).await?);
+            parts.banks.push((bank.name.clone(),
+                              self.generate_bank(bank).await?));
         }
         
-        // Generate interfaces
-        for interface in &device_spec.interfaces {
-            code.push_str(&self.generate_interface(interface)?);
+        // Generate interfaces, by name so that reordering them in the
+        // specification does not change the output
+        let mut interfaces: Vec<&InterfaceSpec> =
+            device_spec.interfaces.iter().collect();
+        interfaces.sort_by(|a, b| a.name.cmp(&b.name));
+        for interface in interfaces {
+            parts.interfaces.push_str(&self.generate_interface(interface)?);
+        }
+        
+        let code = &mut parts.rest;
+        
+        // Generate connects
+        for connect in &device_spec.connects {
+            code.push_str(&self.generate_connect(connect)?);
+        }
+        
+        // Generate ports
+        for port in &device_spec.ports {
+            code.push_str(&self.generate_port(port)?);
+        }
+        
+        // Generate variables
+        for variable in &device_spec.variables {
+            code.push_str(&self.generate_variable(variable)?);
+        }
+        
+        // Generate events
+        for event in &device_spec.events {
+            code.push_str(&self.generate_event(event)?);
         }
         
         // Generate methods
         for method in &device_spec.methods {
-            code.push_str(&self.generate_method(method)?);
+            code.push_str(&self.generate_method_at(method, "")?);
         }
         
-        // Close device
-        code.push_str("}\n");
-        
-        let generated = GeneratedCode {
-            content: code,
-            file_path: format!("{}.dml", device_spec.name),
-            dependencies: device_spec.dependencies.clone(),
-        };
+        // Generate optional features
+        for variant in &device_spec.variants {
+            code.push_str(&self.generate_variant(variant).await?);
+        }
         
-        // Validate if requested
-        if self.context.config.validate_output {
-            self.validate_generated_code(&generated).await?;
+        let line_ending = self.context.config.line_ending;
+        let mut files = parts.into_files(split, line_ending);
+        let banner = self.context.config.banner.as_ref().map(
+            |config| Provenance::for_spec(device_spec, config).banner()
+                .replace('\n', line_ending.as_str()));
+        for generated in &mut files {
+            if let Some(banner) = &banner {
+                generated.content.insert_str(0, banner);
+            }
+            
+            // Validate if requested
+            if self.context.config.validate_output {
+                self.validate_generated_code(generated).await?;
+            }
         }
         
-        Ok(generated)
+        Ok(files)
     }
     
     /// Generate a register
@@ -115,30 +270,53 @@ impl DMLGenerator {
         &self,
         register_spec: &RegisterSpec,
     ) -> Result<String> {
+        self.register_code(register_spec)
+    }
+    
+    fn register_code(&self, register_spec: &RegisterSpec) -> Result<String> {
         debug!("Generating register: {}", register_spec.name);
         
         let mut code = String::new();
+        let indent = self.get_indent();
+        let eol = self.eol();
         
         // Add documentation
         if self.context.config.generate_docs {
             if let Some(doc) = &register_spec.documentation {
-                code.push_str(&format!("    /// {}\n", doc));
+                code.push_str(&self.doc_comment(&indent, doc));
             }
         }
         
         // Register declaration
-        code.push_str(&format!(
-            "    register {} size {}",
-            register_spec.name,
-            register_spec.size
-        ));
+        code.push_str(&format!("{}register {}", indent, register_spec.name));
+        for dim in &register_spec.dimensions {
+            code.push_str(&format!("[{} < {}]", dim.index, dim.size));
+        }
+        code.push_str(&format!(" size {}", register_spec.size));
         
         // Add offset if specified
         if let Some(offset) = &register_spec.offset {
             code.push_str(&format!(" @ {}", offset));
         }
         
-        code.push_str(" {\n");
+        code.push_str(" {");
+        code.push_str(eol);
+        
+        // Standard templates rather than read and write methods
+        let access = match &register_spec.access {
+            Some(access) => template_of_access(access).map_err(
+                |e| anyhow!("{} of register {}", e, register_spec.name))?,
+            None => None,
+        };
+        for template in access.into_iter()
+            .chain(register_spec.templates.iter().map(String::as_str)) {
+            code.push_str(&format!("{0}{0}is {1};{2}", indent, template, eol));
+        }
+        
+        if let Some(reset) = &register_spec.reset {
+            code.push_str(&format!("{}{}param init_val = {};{}",
+                                   indent, indent, reset, eol));
+        }
         
         // Generate fields
         for field in &register_spec.fields {
@@ -147,77 +325,161 @@ impl DMLGenerator {
         
         // Add methods if any
         for method in &register_spec.methods {
-            code.push_str(&self.generate_method(method)?);
+            code.push_str(&self.generate_method_at(
+                &self.with_logging(method, register_spec), &indent.repeat(2))?);
         }
         
-        code.push_str("    }\n");
+        code.push_str(&format!("{}}}{}", indent, eol));
         
         Ok(code)
     }
     
-    /// Generate a method
+    /// Generate a method, as declared in an object
     pub fn generate_method(&self, method_spec: &MethodSpec) -> Result<String> {
+        self.generate_method_at(method_spec, &self.get_indent())
+    }
+    
+    fn generate_method_at(&self, method_spec: &MethodSpec, indent: &str)
+                          -> Result<String> {
         debug!("Generating method: {}", method_spec.name);
         
         let mut code = String::new();
-        let indent = self.get_indent();
+        let eol = self.eol();
         
         // Add documentation
         if self.context.config.generate_docs {
             if let Some(doc) = &method_spec.documentation {
-                code.push_str(&format!("{}/// {}\n", indent, doc));
+                code.push_str(&self.doc_comment(indent, doc));
             }
         }
         
-        // Method signature
-        code.push_str(&format!("{}method {}", indent, method_spec.name));
-        
-        // Parameters
-        if !method_spec.parameters.is_empty() {
-            code.push('(');
-            for (i, param) in method_spec.parameters.iter().enumerate() {
-                if i > 0 {
-                    code.push_str(", ");
-                }
-                code.push_str(&format!("{}: {}", param.name, param.param_type));
+        // Method signature, one word per parameter so that long parameter
+        // lists are wrapped between parameters
+        let mut words = vec![format!("{}method {}(", indent, method_spec.name)];
+        for (i, param) in method_spec.parameters.iter().enumerate() {
+            let param = format!("{} {}", param.param_type, param.name);
+            if i == 0 {
+                words[0].push_str(&param);
+            } else {
+                words.push(param);
+            }
+            if i + 1 < method_spec.parameters.len() {
+                words.last_mut().unwrap().push(',');
             }
-            code.push(')');
         }
+        words.last_mut().unwrap().push(')');
         
-        // Return type
+        // Return types
         if let Some(return_type) = &method_spec.return_type {
-            code.push_str(&format!(" -> {}", return_type));
+            words.push(format!("-> {}", return_types(return_type)));
         }
-        
-        code.push_str(" {\n");
+        words.last_mut().unwrap().push_str(" {");
+        code.push_str(&self.wrap_words(
+            &words, &format!("{0}{1}{1}", indent, self.get_indent())));
         
         // Method body
+        let inner = format!("{}{}", indent, self.get_indent());
         if let Some(body) = &method_spec.body {
-            code.push_str(&format!("{}    {}\n", indent, body));
+            code.push_str(&indent_body(body, &inner, eol));
         } else {
-            code.push_str(&format!("{}    // TODO: Implement method\n", indent));
+            code.push_str(&format!("{}// TODO: Implement method{}", inner, eol));
         }
         
-        code.push_str(&format!("{}}}\n", indent));
+        code.push_str(&format!("{}}}{}", indent, eol));
         
         Ok(code)
     }
     
-    fn generate_header(&self) -> Result<String> {
-        let mut header = String::new();
+    /// A register method with the log statements of the logging policy
+    /// added to the start of its body, if it is the `read` or `write`
+    /// method of the register
+    fn with_logging(&self, method_spec: &MethodSpec,
+                    register_spec: &RegisterSpec) -> MethodSpec {
+        let policy = self.context.config.logging;
+        let mut statements = vec![];
+        match method_spec.name.as_str() {
+            "read" if policy == LoggingPolicy::Verbose => statements.push(
+                "log info, 3: \"Read from %s\", qname;".to_string()),
+            "write" if policy != LoggingPolicy::None => {
+                if let Some(value) = method_spec.parameters.first() {
+                    let value = &value.name;
+                    statements.push(format!(
+                        "log info, 2: \"Write to %s: 0x%x\", qname, {};",
+                        value));
+                    if let Some(reserved) = register_spec.reserved_bits() {
+                        statements.push(format!(
+                            "if (({0} & 0x{1:x}) != 0) {{\n\
+                             {2}log spec_viol, 1 then 2: \
+                             \"Write to reserved bits of %s: 0x%x\", \
+                             qname, {0} & 0x{1:x};\n\
+                             }}", value, reserved, self.get_indent()));
+                    }
+                }
+            },
+            _ => (),
+        }
+        if statements.is_empty() {
+            return method_spec.clone();
+        }
+        let body = match &method_spec.body {
+            Some(body) => indent_body(body, "", "\n"),
+            None => "// TODO: Implement method\n".to_string(),
+        };
+        MethodSpec {
+            body: Some(format!("{}\n{}", statements.join("\n"),
+                               body.trim_end())),
+            ..method_spec.clone()
+        }
+    }
+    
+    /// The global constants and types of a device, following its
+    /// declaration and imports
+    fn generate_declarations(&self, device_spec: &DeviceSpec) -> String {
+        let mut code = String::new();
+        let docs = self.context.config.generate_docs;
+        let eol = self.eol();
         
-        header.push_str("dml 1.4;\n\n");
+        for constant in &device_spec.constants {
+            if let (true, Some(doc)) = (docs, &constant.documentation) {
+                code.push_str(&self.doc_comment("", doc));
+            }
+            code.push_str(&format!("constant {} = {};{}", constant.name,
+                                   constant.value, eol));
+        }
         
-        // Add imports
-        for import in &self.context.imports {
-            header.push_str(&format!("import \"{}\";\n", import));
+        for typedef in &device_spec.typedefs {
+            if let (true, Some(doc)) = (docs, &typedef.documentation) {
+                code.push_str(&self.doc_comment("", doc));
+            }
+            code.push_str(&format!("typedef {} {};{}", typedef.definition,
+                                   typedef.name, eol));
         }
         
-        if !self.context.imports.is_empty() {
-            header.push('\n');
+        for struct_spec in &device_spec.structs {
+            if let (true, Some(doc)) = (docs, &struct_spec.documentation) {
+                code.push_str(&self.doc_comment("", doc));
+            }
+            match &struct_spec.byte_order {
+                Some(order) => code.push_str(
+                    &format!("typedef layout \"{}\" {{{}", order, eol)),
+                None => code.push_str(&format!("typedef struct {{{}", eol)),
+            }
+            for member in &struct_spec.members {
+                code.push_str(&format!("{}{} {}", self.get_indent(),
+                                       member.member_type, member.name));
+                if let Some(size) = member.array_size {
+                    code.push_str(&format!("[{}]", size));
+                }
+                code.push(';');
+                code.push_str(eol);
+            }
+            code.push_str(&format!("}} {};{}", struct_spec.name, eol));
         }
         
-        Ok(header)
+        if !code.is_empty() {
+            code.push_str(eol);
+        }
+        code
     }
     
     fn generate_device_declaration(&self, device_spec: &DeviceSpec) -> Result<String> {
@@ -226,80 +488,391 @@ impl DMLGenerator {
         // Device documentation
         if self.context.config.generate_docs {
             if let Some(doc) = &device_spec.documentation {
-                code.push_str(&format!("/// {}\n", doc));
+                code.push_str(&self.doc_comment("", doc));
             }
         }
         
-        // Device declaration
-        code.push_str(&format!("device {}", device_spec.name));
+        // Device declaration, the rest of the file being its contents
+        let eol = self.eol();
+        code.push_str(&format!("device {};{}", device_spec.name, eol));
         
         // Inheritance
         if let Some(base) = &device_spec.base_template {
-            code.push_str(&format!(" : {}", base));
+            code.push_str(&format!("is {};{}", base, eol));
         }
         
-        code.push_str(" {\n");
+        for template in &device_spec.templates {
+            code.push_str(&format!("is {};{}", template, eol));
+        }
+        
+        code.push_str(eol);
         
         Ok(code)
     }
     
     async fn generate_bank(&self, bank_spec: &BankSpec) -> Result<String> {
         let mut code = String::new();
-        let indent = self.get_indent();
         
         if self.context.config.generate_docs {
             if let Some(doc) = &bank_spec.documentation {
-                code.push_str(&format!("{}/// {}\n", indent, doc));
+                code.push_str(&self.doc_comment("", doc));
             }
         }
         
-        code.push_str(&format!("{}bank {} {{\n", indent, bank_spec.name));
+        let eol = self.eol();
+        code.push_str(&format!("bank {} {{{}", bank_spec.name, eol));
         
         // Generate registers
         for register in &bank_spec.registers {
-            let register_code = self.generate_register(register).await?;
+            let register_code = self.register_code(register)?;
             code.push_str(&register_code);
         }
         
-        code.push_str(&format!("{}}}\n", indent));
+        for region in &bank_spec.reserved {
+            code.push_str(&self.generate_reserved_region(region)?);
+        }
+        
+        code.push('}');
+        code.push_str(eol);
         
         Ok(code)
     }
     
-    fn generate_interface(&self, interface_spec: &InterfaceSpec) -> Result<String> {
+    /// The parameter selecting a variant, followed by an `#if` on it
+    /// compiling in the objects of the variant
+    async fn generate_variant(&self, variant_spec: &VariantSpec)
+                              -> Result<String> {
+        let mut code = String::new();
+        let eol = self.eol();
+        
+        if self.context.config.generate_docs {
+            if let Some(doc) = &variant_spec.documentation {
+                code.push_str(&self.doc_comment("", doc));
+            }
+        }
+        
+        code.push_str(&format!("param {} default {};{}", variant_spec.param,
+                               variant_spec.default, eol));
+        code.push_str(&format!("#if ({}) {{{}", variant_spec.param, eol));
+        code.push_str(&self.generate_variant_content(&variant_spec.enabled)
+                      .await?);
+        if !variant_spec.disabled.is_empty() {
+            code.push_str(&format!("}} #else {{{}", eol));
+            code.push_str(&self.generate_variant_content(
+                &variant_spec.disabled).await?);
+        }
+        code.push('}');
+        code.push_str(eol);
+        
+        Ok(code)
+    }
+    
+    /// The objects of one branch of a variant, indented one level further
+    /// than the same objects directly in the device
+    async fn generate_variant_content(&self, content: &VariantContentSpec)
+                                      -> Result<String> {
+        let mut code = String::new();
+        for bank in &content.banks {
+            code.push_str(&self.generate_bank(bank).await?);
+        }
+        for interface in &content.interfaces {
+            code.push_str(&self.generate_interface(interface)?);
+        }
+        for connect in &content.connects {
+            code.push_str(&self.generate_connect(connect)?);
+        }
+        for port in &content.ports {
+            code.push_str(&self.generate_port(port)?);
+        }
+        for method in &content.methods {
+            code.push_str(&self.generate_method_at(method, "")?);
+        }
+        let (indent, eol) = (self.get_indent(), self.eol());
+        Ok(code.lines()
+           .map(|line| if line.is_empty() {
+               eol.to_string()
+           } else {
+               format!("{}{}{}", indent, line, eol)
+           })
+           .collect())
+    }
+    
+    /// Generate an `implement` of an interface in the device. Interfaces
+    /// without methods get stubs of the methods the catalog lists for them.
+    pub fn generate_interface(&self, interface_spec: &InterfaceSpec)
+                              -> Result<String> {
+        self.generate_interface_at(interface_spec, "")
+    }
+    
+    fn generate_interface_at(&self, interface_spec: &InterfaceSpec,
+                             indent: &str) -> Result<String> {
+        let eol = self.eol();
+        let stubs;
+        let methods = match (interface_spec.methods.is_empty(),
+                             self.catalog.interface(&interface_spec.name)) {
+            (true, Some(info)) => {
+                stubs = stub_methods(info);
+                &stubs
+            },
+            _ => &interface_spec.methods,
+        };
+        if methods.is_empty() {
+            return Ok(format!("{}implement {};{}", indent, interface_spec.name,
+                              eol));
+        }
+        let mut code = format!("{}implement {} {{{}", indent,
+                               interface_spec.name, eol);
+        let inner = format!("{}{}", indent, self.get_indent());
+        for method in methods {
+            code.push_str(&self.generate_method_at(method, &inner)?);
+        }
+        code.push_str(&format!("{}}}{}", indent, eol));
+        Ok(code)
+    }
+    
+    fn generate_port(&self, port_spec: &PortSpec) -> Result<String> {
+        let mut code = String::new();
+        
+        if self.context.config.generate_docs {
+            if let Some(doc) = &port_spec.documentation {
+                code.push_str(&self.doc_comment("", doc));
+            }
+        }
+        
+        code.push_str(&format!("port {}", port_spec.name));
+        for dim in &port_spec.dimensions {
+            code.push_str(&format!("[{} < {}]", dim.index, dim.size));
+        }
+        let eol = self.eol();
+        code.push_str(" {");
+        code.push_str(eol);
+        let mut interfaces: Vec<&InterfaceSpec> =
+            port_spec.interfaces.iter().collect();
+        interfaces.sort_by(|a, b| a.name.cmp(&b.name));
+        for interface in interfaces {
+            code.push_str(&self.generate_interface_at(interface,
+                                                      &self.get_indent())?);
+        }
+        code.push('}');
+        code.push_str(eol);
+        
+        Ok(code)
+    }
+    
+    fn generate_reserved_region(&self, region: &ReservedRegionSpec)
+                                -> Result<String> {
+        let indent = self.get_indent();
+        let eol = self.eol();
+        if region.size == 0 {
+            return Err(anyhow!("Reserved region at {} is empty",
+                               region.offset));
+        }
+        let name = match region.offset_value() {
+            Some(offset) => format!("reserved_0x{:x}", offset),
+            None => format!("reserved_{}", region.offset
+                            .replace(|c: char| !c.is_ascii_alphanumeric(), "_")),
+        };
+        let description = match &region.documentation {
+            Some(doc) => format!("{} bytes at {}: {}", region.size,
+                                 region.offset, doc),
+            None => format!("{} bytes at {}", region.size, region.offset),
+        };
+        Ok(match self.context.config.reserved_regions {
+            ReservedRegionPolicy::Gap => String::new(),
+            ReservedRegionPolicy::Params => format!(
+                "{}param {} = \"Reserved region, {}\";{}",
+                indent, name, description.replace('"', "\\\""), eol),
+            ReservedRegionPolicy::Registers => {
+                // Registers are at most 8 bytes wide, so larger regions
+                // become register arrays
+                let width = [8, 4, 2, 1].iter().copied()
+                    .find(|width| region.size.is_multiple_of(*width))
+                    .unwrap_or(1);
+                let mut code = String::new();
+                if self.context.config.generate_docs {
+                    code.push_str(&self.doc_comment(
+                        &indent, &format!("Reserved region, {}", description)));
+                }
+                if region.size <= width {
+                    code.push_str(&format!(
+                        "{}register {} size {} @ {} is (reserved);{}",
+                        indent, name, region.size, region.offset, eol));
+                } else {
+                    code.push_str(&format!(
+                        "{}register {}[i < {}] size {} @ {} + i * {} \
+                         is (reserved);{}",
+                        indent, name, region.size / width, width,
+                        region.offset, width, eol));
+                }
+                code
+            },
+        })
+    }
+    
+    fn generate_variable(&self, variable_spec: &VariableSpec) -> Result<String> {
+        let mut code = String::new();
+        
+        if self.context.config.generate_docs {
+            if let Some(doc) = &variable_spec.documentation {
+                code.push_str(&self.doc_comment("", doc));
+            }
+        }
+        
+        code.push_str(&format!(
+            "{} {} {}",
+            if variable_spec.saved { "saved" } else { "session" },
+            variable_spec.var_type,
+            variable_spec.name
+        ));
+        if let Some(size) = variable_spec.array_size {
+            code.push_str(&format!("[{}]", size));
+        }
+        code.push(';');
+        code.push_str(self.eol());
+        
+        Ok(code)
+    }
+    
+    fn generate_event(&self, event_spec: &EventSpec) -> Result<String> {
+        let mut code = String::new();
+        let indent = self.get_indent();
+        
+        if self.context.config.generate_docs {
+            if let Some(doc) = &event_spec.documentation {
+                code.push_str(&self.doc_comment("", doc));
+            }
+        }
+        
+        let eol = self.eol();
+        code.push_str(&format!("event {} is ({}) {{{}",
+                               event_spec.name, event_spec.template, eol));
+        code.push_str(&format!("{}method event() {{{}", indent, eol));
+        match &event_spec.body {
+            Some(body) => code.push_str(
+                &indent_body(body, &indent.repeat(2), eol)),
+            None => code.push_str(&format!("{0}{0}// TODO: Handle event{1}",
+                                           indent, eol)),
+        }
+        code.push_str(&format!("{}}}{}", indent, eol));
+        code.push('}');
+        code.push_str(eol);
+        
+        Ok(code)
+    }
+    
+    fn generate_connect(&self, connect_spec: &ConnectSpec) -> Result<String> {
+        let mut code = String::new();
         let indent = self.get_indent();
-        Ok(format!("{}implement {};\n", indent, interface_spec.name))
+        
+        if self.context.config.generate_docs {
+            if let Some(doc) = &connect_spec.documentation {
+                code.push_str(&self.doc_comment("", doc));
+            }
+        }
+        
+        let eol = self.eol();
+        code.push_str(&format!("connect {} {{{}", connect_spec.name, eol));
+        let interfaces: BTreeSet<&String> =
+            connect_spec.interfaces.iter().collect();
+        for interface in interfaces {
+            code.push_str(&format!("{}interface {};{}", indent, interface,
+                                   eol));
+        }
+        code.push('}');
+        code.push_str(eol);
+        
+        Ok(code)
     }
     
     fn generate_field(&self, field_spec: &FieldSpec) -> Result<String> {
         let mut code = String::new();
-        let indent = "        "; // Double indent for field
+        let indent = self.get_indent().repeat(2);
         
         if self.context.config.generate_docs {
             if let Some(doc) = &field_spec.documentation {
-                code.push_str(&format!("{}/// {}\n", indent, doc));
+                code.push_str(&self.doc_comment(&indent, doc));
             }
         }
         
-        code.push_str(&format!(
+        let mut words = vec![format!(
             "{}field {} @ [{}]",
             indent, field_spec.name, field_spec.bits
-        ));
+        )];
+        
+        // The access type is implemented by a standard template
+        let access = match &field_spec.access {
+            Some(access) => template_of_access(access).map_err(
+                |e| anyhow!("{} of field {}", e, field_spec.name))?,
+            None => None,
+        };
+        if let Some(template) = access {
+            words.push(format!("is ({})", template));
+        }
         
-        if let Some(access) = &field_spec.access {
-            code.push_str(&format!(" access {}", access));
+        if !field_spec.templates.is_empty() {
+            words.push(format!("is ({})", field_spec.templates.join(", ")));
         }
         
-        code.push_str(";\n");
+        words.last_mut().unwrap().push(';');
+        code.push_str(&self.wrap_words(
+            &words, &format!("{}{}", indent, self.get_indent())));
         
         Ok(code)
     }
     
     pub fn get_indent(&self) -> String {
-        match self.context.config.indent_style {
-            IndentStyle::Spaces(n) => " ".repeat(n),
-            IndentStyle::Tabs => "\t".to_string(),
+        self.context.config.format.indent_style.unit()
+    }
+    
+    /// A documentation comment at `indent`, with each line of `doc` filled
+    /// up to the maximum line length
+    fn doc_comment(&self, indent: &str, doc: &str) -> String {
+        let prefix = format!("{}///", indent);
+        let mut code = String::new();
+        for line in doc.lines() {
+            let mut words: Vec<String> =
+                line.split_whitespace().map(String::from).collect();
+            match words.first_mut() {
+                Some(first) => *first = format!("{} {}", prefix, first),
+                None => words.push(prefix.clone()),
+            }
+            code.push_str(&self.wrap_words(&words, &format!("{} ", prefix)));
         }
+        if code.is_empty() {
+            code.push_str(&prefix);
+            code.push_str(self.eol());
+        }
+        code
+    }
+    
+    /// Join `words` with spaces into lines of at most the maximum line
+    /// length, starting each continuation line with `continuation`. Words
+    /// that do not fit on a line of their own are not broken.
+    fn wrap_words(&self, words: &[String], continuation: &str) -> String {
+        let max = self.context.config.format.max_line_length;
+        let mut code = String::new();
+        let mut line = String::new();
+        for word in words {
+            if line.is_empty() {
+                line.push_str(word);
+            } else if line_width(&line) + 1 + line_width(word) > max
+                && line_width(continuation) < line_width(&line) {
+                code.push_str(&line);
+                code.push_str(self.eol());
+                line = format!("{}{}", continuation, word);
+            } else {
+                line.push(' ');
+                line.push_str(word);
+            }
+        }
+        code.push_str(&line);
+        code.push_str(self.eol());
+        code
+    }
+    
+    /// The configured line ending
+    fn eol(&self) -> &'static str {
+        self.context.config.line_ending.as_str()
     }
     
     async fn validate_generated_code(&self, _generated: &GeneratedCode) -> Result<()> {
@@ -352,60 +925,973 @@ pub struct GeneratedCode {
     pub dependencies: Vec<String>,
 }
 
+/// The language version starting a generated file
+fn file_header(eol: &str) -> String {
+    format!("dml 1.4;{0}{0}", eol)
+}
+
+/// Import statements of `imports`, sorted and without duplicates. In the
+/// file declaring the device they follow the device declaration, which
+/// must be the first statement after the language version.
+fn import_statements<'a>(imports: impl IntoIterator<Item = &'a String>,
+                         eol: &str) -> String {
+    let mut code = String::new();
+    
+    let imports: BTreeSet<&String> = imports.into_iter().collect();
+    for import in &imports {
+        code.push_str(&format!("import \"{}\";{}", import, eol));
+    }
+    
+    if !imports.is_empty() {
+        code.push_str(eol);
+    }
+    
+    code
+}
+
+/// The code of a generated device, in the parts it can be split into
+#[derive(Debug, Default)]
+pub struct DeviceParts {
+    pub name: String,
+    /// Files imported by the device
+    pub imports: Vec<String>,
+    /// The global constants and types, following the imports
+    pub declarations: String,
+    /// The device declaration, preceding the imports
+    pub declaration: String,
+    /// The code of each bank, by bank name
+    pub banks: Vec<(String, String)>,
+    pub interfaces: String,
+    /// The code following the interfaces
+    pub rest: String,
+}
+
+impl DeviceParts {
+    /// The files of the device, with `line_ending` line endings, which the
+    /// parts must already have. The first file declares the device and
+    /// imports the others, which are `<device>_registers.dml` and
+    /// `<device>_interfaces.dml` when split into sections, and
+    /// `<device>_<bank>.dml` when split into banks.
+    pub fn into_files(self, split: SplitMode, line_ending: LineEnding)
+                      -> Vec<GeneratedCode> {
+        let DeviceParts { name, imports, declarations, declaration, banks,
+                          interfaces, rest } = self;
+        let eol = line_ending.as_str();
+        let file_name = |part: &str| format!("{}_{}.dml", name, part);
+        let mut main = declarations;
+        let mut parts: Vec<(String, String)> = vec![];
+        match split {
+            SplitMode::None => {
+                for (_, bank) in banks {
+                    main.push_str(&bank);
+                }
+                main.push_str(&interfaces);
+            },
+            SplitMode::Sections => {
+                let registers: String = banks.into_iter()
+                    .map(|(_, bank)| bank).collect();
+                if !registers.is_empty() {
+                    parts.push((file_name("registers"), registers));
+                }
+                if !interfaces.is_empty() {
+                    parts.push((file_name("interfaces"), interfaces));
+                }
+            },
+            SplitMode::Banks => {
+                for (name, bank) in banks {
+                    parts.push((file_name(&name), bank));
+                }
+                main.push_str(&interfaces);
+            },
+        }
+        main.push_str(&rest);
+        
+        let mut dependencies = imports;
+        dependencies.extend(parts.iter().map(|(path, _)| path.clone()));
+        let mut files = vec![GeneratedCode {
+            content: file_header(eol) + &declaration
+                + &import_statements(&dependencies, eol) + &main,
+            file_path: format!("{}.dml", name),
+            dependencies,
+        }];
+        files.extend(parts.into_iter().map(|(file_path, code)| GeneratedCode {
+            content: file_header(eol) + &code,
+            file_path,
+            dependencies: vec![],
+        }));
+        files
+    }
+}
+
 // ========== Specification Types ==========
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A device to generate, or one extracted from existing DML
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct DeviceSpec {
     pub name: String,
     pub base_template: Option<String>,
+    /// Templates instantiated by the device with `is`, in addition to the
+    /// base template
+    #[serde(default)]
+    pub templates: Vec<String>,
     pub documentation: Option<String>,
+    #[serde(default)]
     pub banks: Vec<BankSpec>,
+    #[serde(default)]
     pub interfaces: Vec<InterfaceSpec>,
+    #[serde(default)]
+    pub connects: Vec<ConnectSpec>,
+    #[serde(default)]
+    pub ports: Vec<PortSpec>,
+    #[serde(default)]
+    pub variables: Vec<VariableSpec>,
+    #[serde(default)]
+    pub events: Vec<EventSpec>,
+    #[serde(default)]
     pub methods: Vec<MethodSpec>,
+    #[serde(default)]
     pub dependencies: Vec<String>,
+    /// Global constants, e.g. bit masks shared by several registers
+    #[serde(default)]
+    pub constants: Vec<ConstantSpec>,
+    #[serde(default)]
+    pub typedefs: Vec<TypedefSpec>,
+    /// Struct types, e.g. the layouts of descriptors in memory
+    #[serde(default)]
+    pub structs: Vec<StructSpec>,
+    /// Optional features, selected when the model is built
+    #[serde(default)]
+    pub variants: Vec<VariantSpec>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A register bank of the device
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct BankSpec {
     pub name: String,
     pub documentation: Option<String>,
+    #[serde(default)]
     pub registers: Vec<RegisterSpec>,
+    #[serde(default)]
+    pub reserved: Vec<ReservedRegionSpec>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// An address region of a bank that is reserved, e.g. by a datasheet
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ReservedRegionSpec {
+    pub offset: String,
+    /// Size of the region in bytes
+    pub size: u64,
+    pub documentation: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct RegisterSpec {
     pub name: String,
+    /// Array dimensions, outermost first; empty for a single register
+    #[serde(default)]
+    pub dimensions: Vec<ArrayDimension>,
+    /// Size of the register in bytes
     pub size: u64,
+    /// Offset in the bank, a DML expression such as `0x10`
     pub offset: Option<String>,
+    /// Value of the register after reset, defaults to 0
+    #[serde(default)]
+    pub reset: Option<String>,
+    /// Access type of the whole register, e.g. `ro`, `w1c`, `unimpl` or
+    /// `const`, implemented by instantiating the standard template for it;
+    /// defaults to `rw`
+    #[serde(default)]
+    pub access: Option<String>,
     pub documentation: Option<String>,
+    #[serde(default)]
     pub fields: Vec<FieldSpec>,
+    #[serde(default)]
     pub methods: Vec<MethodSpec>,
+    /// Further templates instantiated by the register
+    #[serde(default)]
+    pub templates: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// One dimension of an object array, `index < size`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ArrayDimension {
+    pub index: String,
+    /// Number of elements, 0 when not known
+    pub size: u64,
+}
+
+/// A bit field of a register
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct FieldSpec {
     pub name: String,
+    /// Bit range, `msb:lsb` or a single bit
     pub bits: String,
+    /// Access type, e.g. `rw`, `ro` or `w1c`; defaults to `rw`
     pub access: Option<String>,
     pub documentation: Option<String>,
+    /// Templates instantiated by the field, in addition to the one
+    /// implementing its access type
+    #[serde(default)]
+    pub templates: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct InterfaceSpec {
     pub name: String,
+    /// Implementations of the interface methods
+    #[serde(default)]
+    pub methods: Vec<MethodSpec>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A `port` object, e.g. one per input pin
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PortSpec {
+    pub name: String,
+    /// Array dimensions, outermost first; empty for a single port
+    #[serde(default)]
+    pub dimensions: Vec<ArrayDimension>,
+    pub documentation: Option<String>,
+    #[serde(default)]
+    pub interfaces: Vec<InterfaceSpec>,
+}
+
+/// A connect to another object, through the listed interfaces
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ConnectSpec {
+    pub name: String,
+    pub documentation: Option<String>,
+    #[serde(default)]
+    pub interfaces: Vec<String>,
+}
+
+/// A device-level `saved` or `session` variable
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct VariableSpec {
+    pub name: String,
+    pub var_type: String,
+    /// Number of elements if the variable is an array
+    #[serde(default)]
+    pub array_size: Option<u64>,
+    /// Whether the variable is checkpointed (`saved`) rather than `session`
+    #[serde(default)]
+    pub saved: bool,
+    pub documentation: Option<String>,
+}
+
+/// An `event` object, posted on the time or cycle queue
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct EventSpec {
+    pub name: String,
+    /// Event template, e.g. `simple_time_event` or `uint64_cycle_event`
+    pub template: String,
+    pub documentation: Option<String>,
+    /// Body of the `event` method, run when the event expires
+    pub body: Option<String>,
+}
+
+/// A method, with its body given as DML statements
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct MethodSpec {
     pub name: String,
+    #[serde(default)]
     pub parameters: Vec<ParameterSpec>,
     pub return_type: Option<String>,
     pub body: Option<String>,
     pub documentation: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ParameterSpec {
     pub name: String,
     pub param_type: String,
+}
+
+/// An optional feature of a device, compiled in depending on the value of
+/// a parameter, e.g. a bank that only some models of the device have
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct VariantSpec {
+    /// Name of the boolean parameter selecting the feature
+    pub param: String,
+    /// Value of the parameter unless a model overrides it
+    #[serde(default)]
+    pub default: bool,
+    pub documentation: Option<String>,
+    /// Objects compiled in when the parameter is true
+    #[serde(default)]
+    pub enabled: VariantContentSpec,
+    /// Objects compiled in when the parameter is false
+    #[serde(default)]
+    pub disabled: VariantContentSpec,
+}
+
+/// The objects of one branch of a `VariantSpec`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct VariantContentSpec {
+    #[serde(default)]
+    pub banks: Vec<BankSpec>,
+    #[serde(default)]
+    pub interfaces: Vec<InterfaceSpec>,
+    #[serde(default)]
+    pub connects: Vec<ConnectSpec>,
+    #[serde(default)]
+    pub ports: Vec<PortSpec>,
+    #[serde(default)]
+    pub methods: Vec<MethodSpec>,
+}
+
+impl VariantContentSpec {
+    pub fn is_empty(&self) -> bool {
+        self.banks.is_empty() && self.interfaces.is_empty()
+            && self.connects.is_empty() && self.ports.is_empty()
+            && self.methods.is_empty()
+    }
+}
+
+/// A global `constant`, with its value given as a DML expression
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ConstantSpec {
+    pub name: String,
+    pub value: String,
+    pub documentation: Option<String>,
+}
+
+/// A `typedef` giving a name to a type, e.g. `uint32` or `bool[4]`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct TypedefSpec {
+    pub name: String,
+    /// The named type
+    pub definition: String,
+    pub documentation: Option<String>,
+}
+
+/// A struct type, or a `layout` with its members in memory in the given
+/// byte order
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct StructSpec {
+    pub name: String,
+    /// `little-endian` or `big-endian` for a layout, none for a struct
+    #[serde(default)]
+    pub byte_order: Option<String>,
+    #[serde(default)]
+    pub members: Vec<StructMemberSpec>,
+    pub documentation: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct StructMemberSpec {
+    pub name: String,
+    pub member_type: String,
+    /// Number of elements if the member is an array
+    #[serde(default)]
+    pub array_size: Option<u64>,
+}
+
+impl DeviceSpec {
+    /// Parse a specification, as YAML or TOML if `path` has a `.yaml`/`.yml`
+    /// or `.toml` extension, as an SVD or IP-XACT register description if it
+    /// has an `.svd` or `.xml` one and as JSON otherwise, and check it against
+    /// the published schema
+    pub fn parse(path: &Path, text: &str) -> Result<Self> {
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        let spec: Result<serde_json::Value> = match extension {
+            #[cfg(feature = "importers")]
+            Some("yaml" | "yml") => serde_yaml::from_str(text)
+                .map_err(Into::into),
+            #[cfg(feature = "importers")]
+            Some("toml") => toml::from_str(text).map_err(Into::into),
+            #[cfg(feature = "importers")]
+            Some("svd" | "xml") =>
+                crate::mcp::importers::import_register_description(text)
+                .and_then(|spec| Ok(serde_json::to_value(spec)?)),
+            #[cfg(not(feature = "importers"))]
+            Some(format @ ("yaml" | "yml" | "toml" | "svd" | "xml")) =>
+                Err(anyhow!(
+                    "{} specifications are not supported by this build, \
+                     which lacks the 'importers' feature",
+                    format.to_uppercase())),
+            _ => serde_json::from_str(text).map_err(Into::into),
+        };
+        spec.and_then(|spec| {
+            validate_device_spec(&spec)?;
+            Ok(serde_json::from_value(spec)?)
+        }).map_err(|e| anyhow!("Invalid device specification {}: {}",
+                               path.display(), e))
+    }
+
+    /// Read a specification file, see `parse`
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Could not read {}: {}", path.display(), e))?;
+        Self::parse(path, &text)
+    }
+
+    /// The specification in the format `parse` reads for `path`
+    pub fn to_text(&self, path: &Path) -> Result<String> {
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        match extension {
+            #[cfg(feature = "importers")]
+            Some("yaml" | "yml") => Ok(serde_yaml::to_string(self)?),
+            #[cfg(feature = "importers")]
+            Some("toml") => Ok(toml::to_string_pretty(self)?),
+            Some(format @ ("svd" | "xml")) => Err(anyhow!(
+                "{} register descriptions can be read but not written",
+                format.to_uppercase())),
+            #[cfg(not(feature = "importers"))]
+            Some(format @ ("yaml" | "yml" | "toml")) => Err(anyhow!(
+                "{} specifications are not supported by this build, which \
+                 lacks the 'importers' feature", format.to_uppercase())),
+            _ => Ok(serde_json::to_string_pretty(self)? + "\n"),
+        }
+    }
+
+    /// Write a specification file, see `to_text`
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, self.to_text(path)?)
+            .map_err(|e| anyhow!("Could not write {}: {}", path.display(), e))
+    }
+
+    /// Bring the specification into canonical form, so that specifications
+    /// describing the same device compare equal
+    ///
+    /// Registers are sorted by offset and fields by bit position, constant
+    /// offsets and bit ranges are rewritten in a single notation, missing
+    /// field access and register reset values are filled in with their
+    /// defaults, and duplicate imports, interfaces, templates, constants and
+    /// types are removed.
+    pub fn normalize(&mut self) {
+        for bank in &mut self.banks {
+            for register in &mut bank.registers {
+                register.normalize();
+            }
+            // Stable, so registers without a constant offset keep their order
+            bank.registers.sort_by_key(
+                |reg| reg.offset_value().unwrap_or(u64::MAX));
+            for region in &mut bank.reserved {
+                if let Some(offset) = region.offset_value() {
+                    region.offset = format!("0x{:x}", offset);
+                }
+            }
+            bank.reserved.sort_by_key(
+                |region| region.offset_value().unwrap_or(u64::MAX));
+        }
+        dedup_by_key(&mut self.dependencies, |dep| dep.clone());
+        dedup_by_key(&mut self.templates, |template| template.clone());
+        dedup_by_key(&mut self.interfaces, |iface| iface.name.clone());
+        dedup_by_key(&mut self.constants, |constant| constant.name.clone());
+        dedup_by_key(&mut self.typedefs, |typedef| typedef.name.clone());
+        dedup_by_key(&mut self.structs, |struct_spec| struct_spec.name.clone());
+        for connect in &mut self.connects {
+            dedup_by_key(&mut connect.interfaces, |iface| iface.clone());
+        }
+    }
+
+    /// The names of the interfaces the device, its ports and its variants
+    /// implement or connect to, sorted and without duplicates
+    pub fn interface_names(&self) -> BTreeSet<&str> {
+        let contents = std::iter::once((&self.interfaces, &self.ports,
+                                        &self.connects))
+            .chain(self.variants.iter().flat_map(|variant| [
+                (&variant.enabled.interfaces, &variant.enabled.ports,
+                 &variant.enabled.connects),
+                (&variant.disabled.interfaces, &variant.disabled.ports,
+                 &variant.disabled.connects)]));
+        let mut names = BTreeSet::new();
+        for (interfaces, ports, connects) in contents {
+            let implemented = interfaces.iter()
+                .chain(ports.iter().flat_map(|port| &port.interfaces));
+            names.extend(implemented.map(|iface| iface.name.as_str()));
+            names.extend(connects.iter()
+                         .flat_map(|connect| &connect.interfaces)
+                         .map(String::as_str));
+        }
+        names
+    }
+
+    /// Add the objects of `other` to the device, e.g. to build a device
+    /// from several patterns or to refine a specification
+    ///
+    /// Banks, interfaces and connects with the same name are combined, and
+    /// the templates and imports of both are kept. Registers that overlap
+    /// different registers of the device, objects that have the same name
+    /// as an object of the device but a different definition, and different
+    /// base templates are conflicts, resolved according to `strategy`. The
+    /// name of the device is kept.
+    pub fn merge(&mut self, other: DeviceSpec, strategy: MergeStrategy)
+                 -> Result<()> {
+        match (&self.base_template, other.base_template) {
+            (Some(existing), Some(new)) if *existing != new => match strategy {
+                MergeStrategy::Error => return Err(anyhow!(
+                    "Conflicting base templates {} and {}", existing, new)),
+                MergeStrategy::PreferExisting => (),
+                MergeStrategy::PreferNew => self.base_template = Some(new),
+            },
+            (None, new) => self.base_template = new,
+            _ => (),
+        }
+        if other.documentation.is_some() && (self.documentation.is_none()
+                                             || strategy == MergeStrategy::PreferNew) {
+            self.documentation = other.documentation;
+        }
+        self.templates.extend(other.templates);
+        dedup_by_key(&mut self.templates, |template| template.clone());
+        self.dependencies.extend(other.dependencies);
+        dedup_by_key(&mut self.dependencies, |dep| dep.clone());
+        for bank in other.banks {
+            match self.banks.iter_mut().find(|b| b.name == bank.name) {
+                Some(existing) => existing.merge(bank, strategy)?,
+                None => self.banks.push(bank),
+            }
+        }
+        for interface in other.interfaces {
+            match self.interfaces.iter_mut()
+                .find(|iface| iface.name == interface.name) {
+                Some(existing) => merge_named(
+                    &mut existing.methods, interface.methods,
+                    |method| &method.name,
+                    &format!("method of interface {}", interface.name),
+                    strategy)?,
+                None => self.interfaces.push(interface),
+            }
+        }
+        for connect in other.connects {
+            match self.connects.iter_mut().find(|c| c.name == connect.name) {
+                Some(existing) => {
+                    existing.interfaces.extend(connect.interfaces);
+                    dedup_by_key(&mut existing.interfaces,
+                                 |iface| iface.clone());
+                },
+                None => self.connects.push(connect),
+            }
+        }
+        merge_named(&mut self.ports, other.ports, |port| &port.name,
+                    "port", strategy)?;
+        merge_named(&mut self.variables, other.variables,
+                    |variable| &variable.name, "variable", strategy)?;
+        merge_named(&mut self.events, other.events, |event| &event.name,
+                    "event", strategy)?;
+        merge_named(&mut self.methods, other.methods,
+                    |method| &method.name, "method", strategy)?;
+        merge_named(&mut self.constants, other.constants,
+                    |constant| &constant.name, "constant", strategy)?;
+        merge_named(&mut self.typedefs, other.typedefs,
+                    |typedef| &typedef.name, "typedef", strategy)?;
+        merge_named(&mut self.structs, other.structs,
+                    |struct_spec| &struct_spec.name, "struct", strategy)?;
+        merge_named(&mut self.variants, other.variants,
+                    |variant| &variant.param, "variant", strategy)?;
+        Ok(())
+    }
+
+    /// Move all registers and reserved regions by `delta` bytes, e.g. to
+    /// make room for the registers of another pattern
+    pub fn offset_registers(&mut self, delta: u64) {
+        if delta == 0 {
+            return;
+        }
+        for bank in &mut self.banks {
+            for register in &mut bank.registers {
+                register.offset = register.offset.take().map(
+                    |offset| offset_by(&offset, delta));
+            }
+            for region in &mut bank.reserved {
+                region.offset = offset_by(&region.offset, delta);
+            }
+        }
+    }
+}
+
+impl BankSpec {
+    /// Add the registers and reserved regions of `other`, see
+    /// `DeviceSpec::merge`
+    fn merge(&mut self, other: BankSpec, strategy: MergeStrategy)
+             -> Result<()> {
+        if other.documentation.is_some() && (self.documentation.is_none()
+                                             || strategy == MergeStrategy::PreferNew) {
+            self.documentation = other.documentation;
+        }
+        for register in other.registers {
+            let value = serde_json::to_value(&register)?;
+            if self.registers.iter().any(|existing| serde_json::to_value(existing)
+                                         .is_ok_and(|existing| existing == value)) {
+                continue;
+            }
+            let conflicts = |existing: &RegisterSpec|
+                existing.name == register.name || overlap(existing, &register);
+            if let Some(existing) = self.registers.iter().find(|r| conflicts(r)) {
+                match strategy {
+                    MergeStrategy::Error => return Err(anyhow!(
+                        "Register {} at {} conflicts with register {} at {} \
+                         in bank {}", register.name,
+                        register.offset.as_deref().unwrap_or("unmapped"),
+                        existing.name,
+                        existing.offset.as_deref().unwrap_or("unmapped"),
+                        self.name)),
+                    MergeStrategy::PreferExisting => continue,
+                    MergeStrategy::PreferNew =>
+                        self.registers.retain(|r| !conflicts(r)),
+                }
+            }
+            self.registers.push(register);
+        }
+        self.reserved.extend(other.reserved);
+        Ok(())
+    }
+}
+
+/// Whether two registers both have constant offsets and share any bytes
+fn overlap(a: &RegisterSpec, b: &RegisterSpec) -> bool {
+    match (a.offset_value(), b.offset_value()) {
+        (Some(a_start), Some(b_start)) =>
+            a_start < b_start + b.size && b_start < a_start + a.size,
+        _ => false,
+    }
+}
+
+/// An offset, constant or not, moved by `delta` bytes
+fn offset_by(offset: &str, delta: u64) -> String {
+    match parse_integer(offset) {
+        Some(value) => format!("0x{:02x}", value + delta),
+        None => format!("0x{:x} + {}", delta, offset),
+    }
+}
+
+/// Add the `items` that `existing` lacks, where items with the same name
+/// but different definitions are conflicts, see `DeviceSpec::merge`
+fn merge_named<T: Serialize>(existing: &mut Vec<T>, items: Vec<T>,
+                             name: impl Fn(&T) -> &String, kind: &str,
+                             strategy: MergeStrategy) -> Result<()> {
+    for item in items {
+        let Some(index) = existing.iter().position(|e| name(e) == name(&item))
+        else {
+            existing.push(item);
+            continue;
+        };
+        if serde_json::to_value(&existing[index])? == serde_json::to_value(&item)? {
+            continue;
+        }
+        match strategy {
+            MergeStrategy::Error => return Err(anyhow!(
+                "Conflicting definitions of {} {}", kind, name(&item))),
+            MergeStrategy::PreferExisting => (),
+            MergeStrategy::PreferNew => existing[index] = item,
+        }
+    }
+    Ok(())
+}
+
+/// Stubs of the methods of a catalog interface, with the object argument
+/// of their C signatures left out. Signatures that cannot be parsed are
+/// skipped.
+pub fn stub_methods(info: &InterfaceInfo) -> Vec<MethodSpec> {
+    info.methods.iter().filter_map(|signature| {
+        let (head, params) = signature.trim().strip_suffix(')')?
+            .split_once('(')?;
+        let (return_type, name) = split_declaration(head)?;
+        let parameters = params.split(',')
+            .map(str::trim)
+            .filter(|param| !param.is_empty() && *param != "void")
+            .skip(1)
+            .map(|param| split_declaration(param).map(
+                |(param_type, name)| ParameterSpec {
+                    name: name.to_string(),
+                    param_type: param_type.to_string(),
+                }))
+            .collect::<Option<Vec<_>>>()?;
+        Some(MethodSpec {
+            name: name.to_string(),
+            parameters,
+            return_type: (return_type != "void")
+                .then(|| return_type.to_string()),
+            body: None,
+            documentation: None,
+        })
+    }).collect()
+}
+
+/// Split a C declaration such as `conf_object_t *obj` into its type and
+/// the declared name
+fn split_declaration(declaration: &str) -> Option<(&str, &str)> {
+    let declaration = declaration.trim();
+    let start = declaration
+        .rfind(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .map_or(0, |i| i + 1);
+    let (declared_type, name) = declaration.split_at(start);
+    let declared_type = declared_type.trim();
+    (!declared_type.is_empty() && !name.is_empty())
+        .then_some((declared_type, name))
+}
+
+/// The number of columns a line takes up, counting tabs as `TAB_WIDTH`
+fn line_width(line: &str) -> usize {
+    line.chars().map(|c| if c == '\t' { TAB_WIDTH } else { 1 }).sum()
+}
+
+/// Remove later duplicates from a vector, keeping the first occurrence
+fn dedup_by_key<T, K: Eq + std::hash::Hash>(items: &mut Vec<T>,
+                                            key: impl Fn(&T) -> K) {
+    let mut seen = std::collections::HashSet::new();
+    items.retain(|item| seen.insert(key(item)));
+}
+
+impl RegisterSpec {
+    fn normalize(&mut self) {
+        if let Some(offset) = self.offset_value() {
+            self.offset = Some(format!("0x{:x}", offset));
+        }
+        self.reset = Some(match self.reset_value() {
+            Some(reset) => format!("0x{:x}", reset),
+            None => self.reset.take().unwrap_or_default(),
+        });
+        if let Some(access) = &self.access {
+            self.access = Some(normalize_access(access));
+        }
+        dedup_by_key(&mut self.templates, |template| template.clone());
+        for field in &mut self.fields {
+            field.normalize();
+        }
+        self.fields.sort_by_key(
+            |field| field.bit_range().map_or(u64::MAX, |(_, lsb)| lsb));
+    }
+
+    /// The numeric value of `offset`, if it is an integer literal
+    pub fn offset_value(&self) -> Option<u64> {
+        self.offset.as_deref().and_then(parse_integer)
+    }
+
+    /// The numeric value of `reset`, if it is an integer literal
+    pub fn reset_value(&self) -> Option<u64> {
+        self.reset.as_deref().map_or(Some(0), parse_integer)
+    }
+
+    /// The offsets of all elements of the register, in index order, if
+    /// `offset` is a constant expression of the array indices
+    pub fn element_offsets(&self) -> Option<Vec<u64>> {
+        const MAX_ELEMENTS: u64 = 1 << 16;
+        let offset = self.offset.as_deref()?;
+        let count = self.dimensions.iter()
+            .try_fold(1u64, |count, dim| count.checked_mul(dim.size))?;
+        if count > MAX_ELEMENTS {
+            return None;
+        }
+        let mut offsets = Vec::with_capacity(count as usize);
+        let mut indices = vec![0; self.dimensions.len()];
+        for _ in 0..count {
+            let bindings: Vec<(&str, u64)> = self.dimensions.iter()
+                .zip(&indices)
+                .map(|(dim, index)| (dim.index.as_str(), *index))
+                .collect();
+            offsets.push(eval_offset(offset, &bindings)?);
+            for (index, dim) in indices.iter_mut().zip(&self.dimensions).rev() {
+                *index += 1;
+                if *index < dim.size {
+                    break;
+                }
+                *index = 0;
+            }
+        }
+        Some(offsets)
+    }
+
+    /// The mask of the bits of the register that are not in any field, if
+    /// it has fields, they all have constant bit ranges and they do not
+    /// cover the whole register
+    pub fn reserved_bits(&self) -> Option<u64> {
+        if self.fields.is_empty() {
+            return None;
+        }
+        let mut used = 0u64;
+        for field in &self.fields {
+            let (msb, lsb) = field.bit_range()?;
+            used |= bit_mask(msb) & !(bit_mask(lsb) >> 1);
+        }
+        let reserved = bit_mask(self.size.checked_mul(8)?.checked_sub(1)?)
+            & !used;
+        (reserved != 0).then_some(reserved)
+    }
+}
+
+impl ReservedRegionSpec {
+    /// The numeric value of `offset`, if it is an integer literal
+    pub fn offset_value(&self) -> Option<u64> {
+        parse_integer(&self.offset)
+    }
+}
+
+impl FieldSpec {
+    fn normalize(&mut self) {
+        if let Some((msb, lsb)) = self.bit_range() {
+            self.bits = if msb == lsb {
+                msb.to_string()
+            } else {
+                format!("{}:{}", msb, lsb)
+            };
+        }
+        self.access = Some(normalize_access(
+            self.access.as_deref().unwrap_or("rw")));
+        dedup_by_key(&mut self.templates, |template| template.clone());
+    }
+
+    /// The `(msb, lsb)` bit positions described by `bits`, e.g. "7:0" or "3"
+    pub fn bit_range(&self) -> Option<(u64, u64)> {
+        match self.bits.split_once(':') {
+            Some((msb, lsb)) => {
+                let (msb, lsb) = (parse_integer(msb)?, parse_integer(lsb)?);
+                Some((msb.max(lsb), msb.min(lsb)))
+            },
+            None => parse_integer(&self.bits).map(|bit| (bit, bit)),
+        }
+    }
+}
+
+/// The return types of a method declaration, which are parenthesized
+fn return_types(return_type: &str) -> String {
+    let return_type = return_type.trim();
+    if return_type.starts_with('(') && return_type.ends_with(')') {
+        return_type.to_string()
+    } else {
+        format!("({})", return_type)
+    }
+}
+
+/// Indent a method body, given with its first line unindented and the
+/// following lines indented relative to any common base, at `indent` and
+/// with `eol` line endings
+fn indent_body(body: &str, indent: &str, eol: &str) -> String {
+    let mut lines = body.lines();
+    let first = lines.next().unwrap_or_default();
+    let rest: Vec<&str> = lines.collect();
+    let base = rest.iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    let mut code = format!("{}{}{}", indent, first, eol);
+    for line in rest {
+        if line.trim().is_empty() {
+            code.push_str(eol);
+        } else {
+            code.push_str(&format!("{}{}{}", indent, &line[base..], eol));
+        }
+    }
+    code
+}
+
+/// Evaluate an offset expression of integer literals and array indices,
+/// combined with `+`, `-`, `*` and parentheses
+fn eval_offset(expr: &str, bindings: &[(&str, u64)]) -> Option<u64> {
+    let mut tokens = vec![];
+    let mut chars = expr.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        let mut end = start + c.len_utf8();
+        if c.is_ascii_alphanumeric() || c == '_' {
+            while let Some((pos, c)) = chars.peek().copied() {
+                if !(c.is_ascii_alphanumeric() || c == '_') {
+                    break;
+                }
+                end = pos + c.len_utf8();
+                chars.next();
+            }
+        }
+        tokens.push(&expr[start..end]);
+    }
+    let mut pos = 0;
+    let value = eval_sum(&tokens, &mut pos, bindings)?;
+    (pos == tokens.len()).then_some(value)
+}
+
+fn eval_sum(tokens: &[&str], pos: &mut usize, bindings: &[(&str, u64)])
+            -> Option<u64> {
+    let mut value = eval_product(tokens, pos, bindings)?;
+    while let Some(&op) = tokens.get(*pos).filter(|op| ["+", "-"].contains(op)) {
+        *pos += 1;
+        let operand = eval_product(tokens, pos, bindings)?;
+        value = if op == "+" {
+            value.checked_add(operand)?
+        } else {
+            value.checked_sub(operand)?
+        };
+    }
+    Some(value)
+}
+
+fn eval_product(tokens: &[&str], pos: &mut usize, bindings: &[(&str, u64)])
+                -> Option<u64> {
+    let mut value = eval_atom(tokens, pos, bindings)?;
+    while tokens.get(*pos) == Some(&"*") {
+        *pos += 1;
+        value = value.checked_mul(eval_atom(tokens, pos, bindings)?)?;
+    }
+    Some(value)
+}
+
+fn eval_atom(tokens: &[&str], pos: &mut usize, bindings: &[(&str, u64)])
+             -> Option<u64> {
+    let token = *tokens.get(*pos)?;
+    *pos += 1;
+    if token == "(" {
+        let value = eval_sum(tokens, pos, bindings)?;
+        (tokens.get(*pos) == Some(&")")).then_some(())?;
+        *pos += 1;
+        Some(value)
+    } else {
+        bindings.iter().find(|(index, _)| *index == token)
+            .map(|(_, value)| *value)
+            .or_else(|| parse_integer(token))
+    }
+}
+
+/// The mask of bits `bit` and below
+fn bit_mask(bit: u64) -> u64 {
+    if bit >= 63 { u64::MAX } else { (1 << (bit + 1)) - 1 }
+}
+
+/// The canonical short name of a field access type, e.g. "ro" for
+/// "read-only" or "read_only"
+pub fn normalize_access(access: &str) -> String {
+    let lower = access.trim().to_lowercase().replace('-', "_");
+    match lower.as_str() {
+        "read_write" | "readwrite" => "rw",
+        "read_only" | "readonly" => "ro",
+        "write_only" | "writeonly" => "wo",
+        "write_1_clears" | "write_one_to_clear" => "w1c",
+        "write_1_only" | "write_one_to_set" => "w1s",
+        "clear_on_read" => "rc",
+        "constant" => "const",
+        "unimplemented" => "unimpl",
+        other => other,
+    }.to_string()
+}
+
+/// The standard template implementing an access type, or `None` for `rw`,
+/// which is the default of registers and fields
+pub fn template_of_access(access: &str) -> Result<Option<&'static str>> {
+    Ok(Some(match normalize_access(access).as_str() {
+        "rw" => return Ok(None),
+        "ro" => "read_only",
+        "wo" => "write_only",
+        "w1c" => "write_1_clears",
+        "w1s" => "write_1_only",
+        "rc" => "clear_on_read",
+        "const" => "constant",
+        "reserved" => "reserved",
+        "unimpl" => "unimpl",
+        other => return Err(anyhow!("Unknown access type '{}'", other)),
+    }))
+}
+
+/// Parse a DML-style integer literal (decimal, `0x` hex or `0b` binary)
+pub fn parse_integer(literal: &str) -> Option<u64> {
+    let literal = literal.trim().replace('_', "");
+    if let Some(hex) = literal.strip_prefix("0x")
+        .or_else(|| literal.strip_prefix("0X")) {
+        u64::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = literal.strip_prefix("0b")
+        .or_else(|| literal.strip_prefix("0B")) {
+        u64::from_str_radix(bin, 2).ok()
+    } else {
+        literal.parse().ok()
+    }
 }
\ No newline at end of file
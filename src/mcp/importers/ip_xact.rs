@@ -0,0 +1,136 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! IP-XACT (IEEE 1685) components
+//!
+//! Each address block of the memory maps becomes a bank. Registers of
+//! register files are flattened into the bank, prefixed with the name of
+//! the register file, and registers with a `dim` become register arrays.
+//! Reset values are read from the register, as in IP-XACT 1.5 and 2009, or
+//! put together from those of the fields, as in 2014 and 2022. Access types
+//! are read from the element itself or, as in 2022, its access policy.
+
+use anyhow::{anyhow, Result};
+
+use crate::mcp::generation::{ArrayDimension, BankSpec, DeviceSpec, FieldSpec,
+                             RegisterSpec};
+use crate::mcp::importers::xml::Element;
+use crate::mcp::importers::{access_type, device_spec, documentation,
+                            field_bits, number};
+
+/// Read the IP-XACT `component` element
+pub fn import_ip_xact(component: &Element) -> Result<DeviceSpec> {
+    let name = component.child_text("name")
+        .ok_or_else(|| anyhow!("The IP-XACT component has no name"))?;
+    let banks = component.child("memoryMaps").into_iter()
+        .flat_map(|maps| maps.children("memoryMap"))
+        .flat_map(|map| map.children("addressBlock"))
+        .map(bank)
+        .collect::<Result<_>>()?;
+    Ok(device_spec(name.to_string(), documentation(component), banks))
+}
+
+fn bank(block: &Element) -> Result<BankSpec> {
+    let name = block.child_text("name")
+        .ok_or_else(|| anyhow!("An IP-XACT address block has no name"))?;
+    let mut registers = vec![];
+    add_registers(&mut registers, block, "", 0, access(block))?;
+    Ok(BankSpec {
+        name: name.to_string(),
+        documentation: documentation(block),
+        registers,
+        reserved: vec![],
+    })
+}
+
+/// The `access` of an element, or that of its first access policy
+fn access(element: &Element) -> Option<&str> {
+    element.child_text("access").or_else(|| {
+        element.child("accessPolicies")?.child("accessPolicy")?
+            .child_text("access")
+    })
+}
+
+/// The first reset value of an element
+fn reset(element: &Element) -> Result<Option<u64>> {
+    match element.child("resets").and_then(|resets| resets.child("reset"))
+        .or_else(|| element.child("reset")) {
+        Some(reset) => number(reset, "value"),
+        None => Ok(None),
+    }
+}
+
+fn add_registers(registers: &mut Vec<RegisterSpec>, container: &Element,
+                 prefix: &str, base: u64, container_access: Option<&str>)
+                 -> Result<()> {
+    for child in &container.children {
+        let name = || child.child_text("name")
+            .ok_or_else(|| anyhow!("An IP-XACT {} has no name", child.name));
+        let offset = || number(child, "addressOffset")?
+            .ok_or_else(|| anyhow!("'{}' has no addressOffset",
+                                   child.child_text("name").unwrap_or("")))
+            .map(|offset| base + offset);
+        let access = access(child).or(container_access);
+        match child.name.as_str() {
+            "register" => registers.push(register(
+                child, &format!("{}{}", prefix, name()?), offset()?,
+                access)?),
+            "registerFile" => add_registers(
+                registers, child, &format!("{}{}_", prefix, name()?),
+                offset()?, access)?,
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+fn register(element: &Element, name: &str, offset: u64,
+            access: Option<&str>) -> Result<RegisterSpec> {
+    let bits = number(element, "size")?
+        .ok_or_else(|| anyhow!("Register '{}' has no size", name))?;
+    let access = access_type(access, None, None);
+    let mut reset_value = reset(element)?;
+    let mut fields = vec![];
+    for field in element.children("field") {
+        let field_name = field.child_text("name")
+            .ok_or_else(|| anyhow!("A field of '{}' has no name", name))?;
+        let offset = number(field, "bitOffset")?.ok_or_else(|| anyhow!(
+            "Field '{}' of '{}' has no bitOffset", field_name, name))?;
+        let width = number(field, "bitWidth")?.unwrap_or(1);
+        if let Some(field_reset) = reset(field)? {
+            let value = reset_value.get_or_insert(0);
+            *value |= field_reset.checked_shl(offset as u32).unwrap_or(0);
+        }
+        // A field without access has that of the register
+        let field_access = access_type(self::access(field),
+                                       field.child_text("modifiedWriteValue"),
+                                       field.child_text("readAction"));
+        fields.push(FieldSpec {
+            name: field_name.to_string(),
+            bits: field_bits(offset, width),
+            access: field_access.filter(|field_access| Some(field_access)
+                                        != access.as_ref()),
+            documentation: documentation(field),
+            templates: vec![],
+        });
+    }
+    let size = bits.div_ceil(8);
+    let (dimensions, offset) = match number(element, "dim")? {
+        Some(dim) => (vec![ArrayDimension { index: "i".to_string(),
+                                            size: dim }],
+                      format!("0x{:x} + i * 0x{:x}", offset, size)),
+        None => (vec![], format!("0x{:x}", offset)),
+    };
+    Ok(RegisterSpec {
+        name: name.to_string(),
+        dimensions,
+        size,
+        offset: Some(offset),
+        reset: reset_value.filter(|reset| *reset != 0)
+            .map(|reset| format!("0x{:x}", reset)),
+        access,
+        documentation: documentation(element),
+        fields,
+        methods: vec![],
+        templates: vec![],
+    })
+}
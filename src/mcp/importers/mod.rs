@@ -0,0 +1,119 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Importers reading device specifications from register descriptions
+//!
+//! CMSIS-SVD files and IP-XACT components describe the register maps of
+//! silicon. They are read into a specification with a bank per SVD
+//! peripheral or IP-XACT address block, which the tools can then generate
+//! from or compare with like any other specification.
+
+use anyhow::{anyhow, Result};
+
+use crate::mcp::generation::{BankSpec, DeviceSpec};
+
+pub mod ip_xact;
+pub mod svd;
+pub mod xml;
+
+use xml::{parse_xml, Element};
+
+/// Read an SVD device or an IP-XACT component, told apart by the root
+/// element
+pub fn import_register_description(text: &str) -> Result<DeviceSpec> {
+    let root = parse_xml(text)?;
+    match root.name.as_str() {
+        "device" => svd::import_svd(&root),
+        "component" => ip_xact::import_ip_xact(&root),
+        other => Err(anyhow!(
+            "Expected an SVD device or an IP-XACT component, not '{}'",
+            other)),
+    }
+}
+
+/// Parse an integer as written in register descriptions: decimal, `0x`
+/// hex, SVD `#` binary (where `x` bits are taken as 0), or a Verilog
+/// literal such as `32'h1f`
+pub fn parse_number(text: &str) -> Option<u64> {
+    let text = text.trim().replace('_', "");
+    let (radix, digits) = if let Some(hex) = text.strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X")) {
+        (16, hex.to_string())
+    } else if let Some(bin) = text.strip_prefix('#') {
+        (2, bin.replace(['x', 'X'], "0"))
+    } else if let Some((_, literal)) = text.split_once('\'') {
+        let mut chars = literal.chars();
+        let radix = match chars.next()?.to_ascii_lowercase() {
+            'h' => 16,
+            'd' => 10,
+            'o' => 8,
+            'b' => 2,
+            _ => return None,
+        };
+        (radix, chars.as_str().to_string())
+    } else {
+        (10, text)
+    };
+    u64::from_str_radix(&digits, radix).ok()
+}
+
+/// The number in the child `name` of `element`, if there is one
+pub(crate) fn number(element: &Element, name: &str) -> Result<Option<u64>> {
+    element.child_text(name)
+        .map(|text| parse_number(text).ok_or_else(|| anyhow!(
+            "Invalid {} '{}' of '{}'", name, text,
+            element.child_text("name").unwrap_or(&element.name))))
+        .transpose()
+}
+
+/// The access type of a specification for the `access`, modified write
+/// value and read action of a description, or `None` for read-write
+pub(crate) fn access_type(access: Option<&str>, modified_write: Option<&str>,
+                          read_action: Option<&str>) -> Option<String> {
+    let access = match (modified_write, read_action, access) {
+        (Some("oneToClear"), ..) => "w1c",
+        (Some("oneToSet"), ..) => "w1s",
+        (_, Some("clear"), _) => "rc",
+        (_, _, Some("read-only")) => "ro",
+        (_, _, Some("write-only" | "writeOnce")) => "wo",
+        _ => return None,
+    };
+    Some(access.to_string())
+}
+
+/// The description of `element` on one line
+pub(crate) fn documentation(element: &Element) -> Option<String> {
+    element.child_text("description")
+        .map(|text| text.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+/// The bits of a field of `width` bits starting at bit `offset`
+pub(crate) fn field_bits(offset: u64, width: u64) -> String {
+    if width <= 1 {
+        offset.to_string()
+    } else {
+        format!("{}:{}", offset + width - 1, offset)
+    }
+}
+
+/// A device with nothing but banks
+pub(crate) fn device_spec(name: String, documentation: Option<String>,
+                          banks: Vec<BankSpec>) -> DeviceSpec {
+    DeviceSpec {
+        name,
+        base_template: None,
+        templates: vec![],
+        documentation,
+        banks,
+        interfaces: vec![],
+        connects: vec![],
+        ports: vec![],
+        variables: vec![],
+        events: vec![],
+        methods: vec![],
+        dependencies: vec![],
+        constants: vec![],
+        typedefs: vec![],
+        structs: vec![],
+        variants: vec![],
+    }
+}
@@ -0,0 +1,228 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! CMSIS-SVD device descriptions
+//!
+//! Each peripheral becomes a bank, and one derived from another peripheral
+//! gets the registers of that one unless it has its own. Registers of
+//! clusters are flattened into the bank, prefixed with the name of the
+//! cluster. A register with `dim` becomes a register array when its name
+//! ends in `[%s]`, and one register per element when `%s` is elsewhere in
+//! it. Size, access and reset value are inherited from the enclosing
+//! elements, as SVD prescribes.
+
+use anyhow::{anyhow, Result};
+
+use crate::mcp::generation::{ArrayDimension, BankSpec, DeviceSpec, FieldSpec,
+                             RegisterSpec};
+use crate::mcp::importers::xml::Element;
+use crate::mcp::importers::{access_type, device_spec, documentation,
+                            field_bits, number, parse_number};
+
+/// The register properties an element passes on to the registers in it
+#[derive(Debug, Clone, Default)]
+struct Properties {
+    /// In bits
+    size: Option<u64>,
+    access: Option<String>,
+    reset: Option<u64>,
+}
+
+impl Properties {
+    fn inherit(&self, element: &Element) -> Result<Properties> {
+        Ok(Properties {
+            size: number(element, "size")?.or(self.size),
+            access: element.child_text("access").map(String::from)
+                .or_else(|| self.access.clone()),
+            reset: number(element, "resetValue")?.or(self.reset),
+        })
+    }
+}
+
+/// Read the SVD `device` element
+pub fn import_svd(device: &Element) -> Result<DeviceSpec> {
+    let name = device.child_text("name")
+        .ok_or_else(|| anyhow!("The SVD device has no name"))?;
+    let properties = Properties::default().inherit(device)?;
+    let peripherals: Vec<&Element> = device.child("peripherals")
+        .map(|peripherals| peripherals.children("peripheral").collect())
+        .unwrap_or_default();
+    let banks = peripherals.iter()
+        .map(|peripheral| bank(peripheral, &peripherals, &properties))
+        .collect::<Result<_>>()?;
+    Ok(device_spec(name.to_string(), documentation(device), banks))
+}
+
+fn bank(peripheral: &Element, peripherals: &[&Element],
+        properties: &Properties) -> Result<BankSpec> {
+    let name = peripheral.child_text("name")
+        .ok_or_else(|| anyhow!("An SVD peripheral has no name"))?;
+    let base = peripheral.attribute("derivedFrom")
+        .map(|base| peripherals.iter()
+             .find(|other| other.child_text("name") == Some(base))
+             .ok_or_else(|| anyhow!(
+                 "Peripheral '{}' is derived from the unknown peripheral '{}'",
+                 name, base)))
+        .transpose()?;
+    let mut properties = properties.clone();
+    if let Some(base) = base {
+        properties = properties.inherit(base)?;
+    }
+    let properties = properties.inherit(peripheral)?;
+    let mut registers = vec![];
+    let container = peripheral.child("registers")
+        .or_else(|| base.and_then(|base| base.child("registers")));
+    if let Some(container) = container {
+        add_registers(&mut registers, container, "", 0, &properties)?;
+    }
+    Ok(BankSpec {
+        name: name.to_string(),
+        documentation: documentation(peripheral)
+            .or_else(|| base.and_then(|base| documentation(base))),
+        registers,
+        reserved: vec![],
+    })
+}
+
+/// The names of the elements of a `dim` list: `dimIndex` as a range such as
+/// `0-3` or a list such as `A,B,C`, and by default the element numbers
+fn dim_indices(element: &Element, dim: u64) -> Vec<String> {
+    let Some(indices) = element.child_text("dimIndex") else {
+        return (0..dim).map(|index| index.to_string()).collect();
+    };
+    if let Some((first, last)) = indices.split_once('-') {
+        if let (Ok(first), Ok(last)) = (first.trim().parse::<u64>(),
+                                        last.trim().parse::<u64>()) {
+            return (first..=last).map(|index| index.to_string()).collect();
+        }
+    }
+    indices.split(',').map(|index| index.trim().to_string()).collect()
+}
+
+/// The name and offset of each element of `element`, a single one unless
+/// it has a `dim`
+fn elements(element: &Element, name: &str, offset: u64)
+            -> Result<Vec<(String, u64)>> {
+    let Some(dim) = number(element, "dim")? else {
+        return Ok(vec![(name.to_string(), offset)]);
+    };
+    let increment = number(element, "dimIncrement")?
+        .ok_or_else(|| anyhow!("'{}' has a dim but no dimIncrement", name))?;
+    let name = name.replace("[%s]", "%s");
+    Ok(dim_indices(element, dim).iter().zip(0..)
+       .map(|(index, i)| (name.replace("%s", index), offset + i * increment))
+       .collect())
+}
+
+fn add_registers(registers: &mut Vec<RegisterSpec>, container: &Element,
+                 prefix: &str, base: u64, properties: &Properties)
+                 -> Result<()> {
+    for child in &container.children {
+        let name = || child.child_text("name")
+            .ok_or_else(|| anyhow!("An SVD {} has no name", child.name));
+        let offset = || number(child, "addressOffset")?
+            .ok_or_else(|| anyhow!("'{}' has no addressOffset",
+                                   child.child_text("name").unwrap_or("")))
+            .map(|offset| base + offset);
+        match child.name.as_str() {
+            "register" => {
+                let name = format!("{}{}", prefix, name()?);
+                let properties = properties.inherit(child)?;
+                if name.ends_with("[%s]") {
+                    registers.push(register(child, &name, offset()?,
+                                            &properties)?);
+                } else {
+                    for (name, offset) in elements(child, &name, offset()?)? {
+                        registers.push(register(child, &name, offset,
+                                                &properties)?);
+                    }
+                }
+            },
+            "cluster" => {
+                let properties = properties.inherit(child)?;
+                for (name, offset) in elements(child, name()?, offset()?)? {
+                    add_registers(registers, child,
+                                  &format!("{}{}_", prefix, name), offset,
+                                  &properties)?;
+                }
+            },
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+fn register(element: &Element, name: &str, offset: u64,
+            properties: &Properties) -> Result<RegisterSpec> {
+    let bits = properties.size.unwrap_or(32);
+    let access = access_type(properties.access.as_deref(),
+                             element.child_text("modifiedWriteValues"),
+                             element.child_text("readAction"));
+    let reset = properties.reset
+        .map(|reset| if bits < 64 { reset & ((1 << bits) - 1) } else { reset })
+        .filter(|reset| *reset != 0)
+        .map(|reset| format!("0x{:x}", reset));
+    let fields = element.child("fields").into_iter()
+        .flat_map(|fields| fields.children("field"))
+        .map(|field| self::field(field, access.as_deref()))
+        .collect::<Result<_>>()?;
+    let mut register = RegisterSpec {
+        name: name.to_string(),
+        dimensions: vec![],
+        size: bits.div_ceil(8),
+        offset: Some(format!("0x{:x}", offset)),
+        reset,
+        access,
+        documentation: documentation(element),
+        fields,
+        methods: vec![],
+        templates: vec![],
+    };
+    if let (Some(dim), Some(array)) = (number(element, "dim")?,
+                                       name.strip_suffix("[%s]")) {
+        let increment = number(element, "dimIncrement")?
+            .ok_or_else(|| anyhow!("'{}' has a dim but no dimIncrement",
+                                   name))?;
+        register.name = array.to_string();
+        register.dimensions.push(ArrayDimension {
+            index: "i".to_string(),
+            size: dim,
+        });
+        register.offset = Some(format!("0x{:x} + i * 0x{:x}",
+                                       offset, increment));
+    }
+    Ok(register)
+}
+
+/// A field, whose access is left out when it is that of the register
+fn field(element: &Element, register_access: Option<&str>)
+         -> Result<FieldSpec> {
+    let name = element.child_text("name")
+        .ok_or_else(|| anyhow!("An SVD field has no name"))?;
+    let range = element.child_text("bitRange")
+        .and_then(|range| range.strip_prefix('[')?.strip_suffix(']')?
+                  .split_once(':'));
+    let bits = if let Some((msb, lsb)) = range {
+        let invalid = || anyhow!("Invalid bitRange of field '{}'", name);
+        let msb = parse_number(msb).ok_or_else(invalid)?;
+        let lsb = parse_number(lsb).ok_or_else(invalid)?;
+        field_bits(lsb, msb.saturating_sub(lsb) + 1)
+    } else if let (Some(lsb), Some(msb)) = (number(element, "lsb")?,
+                                            number(element, "msb")?) {
+        field_bits(lsb, msb.saturating_sub(lsb) + 1)
+    } else {
+        let offset = number(element, "bitOffset")?
+            .ok_or_else(|| anyhow!("Field '{}' has no bit position", name))?;
+        field_bits(offset, number(element, "bitWidth")?.unwrap_or(1))
+    };
+    let access = access_type(element.child_text("access"),
+                             element.child_text("modifiedWriteValues"),
+                             element.child_text("readAction"));
+    Ok(FieldSpec {
+        name: name.to_string(),
+        bits,
+        access: access.filter(|access| Some(access.as_str())
+                              != register_access),
+        documentation: documentation(element),
+        templates: vec![],
+    })
+}
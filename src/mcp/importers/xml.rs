@@ -0,0 +1,244 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! A reader of the XML subset used by register descriptions
+//!
+//! Register descriptions are data-only documents, so this reads elements,
+//! attributes and text, resolves the predefined and numeric character
+//! references, and skips the prolog, comments, processing instructions and
+//! document type declarations. Namespace prefixes are dropped from element
+//! and attribute names, since SVD has none and IP-XACT uses a different one
+//! for each revision of the standard.
+
+use anyhow::{anyhow, Result};
+
+/// An element and what it contains
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Element {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Element>,
+    /// The text directly in the element, without that of its children
+    pub text: String,
+}
+
+impl Element {
+    /// The first child element named `name`
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    /// The child elements named `name`
+    pub fn children<'a>(&'a self, name: &'a str)
+                        -> impl Iterator<Item = &'a Element> + 'a {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+
+    /// The trimmed text of the first child element named `name`, if there is
+    /// one and it is not empty
+    pub fn child_text(&self, name: &str) -> Option<&str> {
+        self.child(name).map(|child| child.text.trim())
+            .filter(|text| !text.is_empty())
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.iter().find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Parse a document into its root element
+pub fn parse_xml(text: &str) -> Result<Element> {
+    let mut reader = Reader { text, pos: 0 };
+    reader.skip_misc()?;
+    let root = reader.element()?;
+    reader.skip_misc()?;
+    if reader.pos < text.len() {
+        return Err(reader.error("content after the root element"));
+    }
+    Ok(root)
+}
+
+struct Reader<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn error(&self, message: &str) -> anyhow::Error {
+        let line = self.text[..self.pos].matches('\n').count() + 1;
+        anyhow!("XML error on line {}: {}", line, message)
+    }
+
+    /// Skip past `end`, or fail with `what` if it never comes
+    fn skip_past(&mut self, end: &str, what: &str) -> Result<&'a str> {
+        let start = self.pos;
+        let len = self.rest().find(end)
+            .ok_or_else(|| self.error(&format!("unterminated {}", what)))?;
+        self.pos += len + end.len();
+        let text = self.text;
+        Ok(&text[start..start + len])
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// Skip whitespace, the XML declaration, comments, processing
+    /// instructions and the document type declaration
+    fn skip_misc(&mut self) -> Result<()> {
+        loop {
+            self.skip_whitespace();
+            if self.rest().starts_with("<?") {
+                self.skip_past("?>", "processing instruction")?;
+            } else if self.rest().starts_with("<!--") {
+                self.skip_past("-->", "comment")?;
+            } else if self.rest().starts_with("<!DOCTYPE") {
+                self.skip_doctype()?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Skip a document type declaration, which may have an internal subset
+    /// in brackets
+    fn skip_doctype(&mut self) -> Result<()> {
+        let mut depth = 0;
+        for (i, c) in self.rest().char_indices() {
+            match c {
+                '[' => depth += 1,
+                ']' => depth -= 1,
+                '>' if depth == 0 => {
+                    self.pos += i + 1;
+                    return Ok(());
+                },
+                _ => (),
+            }
+        }
+        Err(self.error("unterminated document type declaration"))
+    }
+
+    fn name(&mut self) -> Result<String> {
+        let rest = self.rest();
+        let len = rest.find(|c: char| c.is_whitespace()
+                            || matches!(c, '>' | '/' | '='))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return Err(self.error("expected a name"));
+        }
+        self.pos += len;
+        let name = &rest[..len];
+        Ok(name.rsplit(':').next().unwrap_or(name).to_string())
+    }
+
+    /// An element, starting at its `<`
+    fn element(&mut self) -> Result<Element> {
+        if !self.rest().starts_with('<') {
+            return Err(self.error("expected an element"));
+        }
+        self.pos += 1;
+        let mut element = Element { name: self.name()?, ..Element::default() };
+        loop {
+            self.skip_whitespace();
+            if self.rest().starts_with("/>") {
+                self.pos += 2;
+                return Ok(element);
+            }
+            if self.rest().starts_with('>') {
+                self.pos += 1;
+                break;
+            }
+            let key = self.name()?;
+            self.skip_whitespace();
+            if !self.rest().starts_with('=') {
+                return Err(self.error(&format!(
+                    "expected a value of attribute '{}'", key)));
+            }
+            self.pos += 1;
+            self.skip_whitespace();
+            let quote = match self.rest().chars().next() {
+                Some(quote @ ('"' | '\'')) => quote,
+                _ => return Err(self.error("expected a quoted value")),
+            };
+            self.pos += 1;
+            let value = self.skip_past(&quote.to_string(), "attribute value")?;
+            let value = self.unescape(value)?;
+            element.attributes.push((key, value));
+        }
+        self.content(&mut element)?;
+        Ok(element)
+    }
+
+    /// The content of `element` up to and including its end tag
+    fn content(&mut self, element: &mut Element) -> Result<()> {
+        loop {
+            let len = self.rest().find('<')
+                .ok_or_else(|| self.error(&format!(
+                    "element '{}' is not closed", element.name)))?;
+            let text = &self.rest()[..len];
+            element.text.push_str(&self.unescape(text)?);
+            self.pos += len;
+            let rest = self.rest();
+            if rest.starts_with("</") {
+                self.pos += 2;
+                let name = self.name()?;
+                if name != element.name {
+                    return Err(self.error(&format!(
+                        "expected the end of '{}', not of '{}'",
+                        element.name, name)));
+                }
+                self.skip_whitespace();
+                if !self.rest().starts_with('>') {
+                    return Err(self.error("expected '>'"));
+                }
+                self.pos += 1;
+                return Ok(());
+            } else if rest.starts_with("<!--") {
+                self.skip_past("-->", "comment")?;
+            } else if rest.starts_with("<![CDATA[") {
+                self.pos += "<![CDATA[".len();
+                let data = self.skip_past("]]>", "CDATA section")?;
+                element.text.push_str(data);
+            } else if rest.starts_with("<?") {
+                self.skip_past("?>", "processing instruction")?;
+            } else {
+                element.children.push(self.element()?);
+            }
+        }
+    }
+
+    /// Resolve the character references in `text`
+    fn unescape(&self, text: &str) -> Result<String> {
+        let mut result = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find('&') {
+            result.push_str(&rest[..start]);
+            let end = rest[start..].find(';')
+                .ok_or_else(|| self.error("unterminated character reference"))?;
+            let reference = &rest[start + 1..start + end];
+            let c = match reference {
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "amp" => Some('&'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                _ => reference.strip_prefix("#x")
+                    .map(|hex| u32::from_str_radix(hex, 16))
+                    .or_else(|| reference.strip_prefix('#')
+                             .map(|dec| dec.parse()))
+                    .and_then(|code| code.ok())
+                    .and_then(char::from_u32),
+            };
+            result.push(c.ok_or_else(|| self.error(&format!(
+                "unknown character reference '&{};'", reference)))?);
+            rest = &rest[start + end + 1..];
+        }
+        result.push_str(rest);
+        Ok(result)
+    }
+}
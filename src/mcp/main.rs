@@ -1,23 +1,223 @@
 //! DML MCP Server main entry point
-//! 
+//!
 //! This binary provides an MCP server for DML code generation using the
-//! existing DML Language Server analysis capabilities.
+//! existing DML Language Server analysis capabilities. It can also generate
+//! a device directly from a specification file, without starting the server.
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use clap::{Parser, Subcommand};
+use dls::concurrency::{analysis_threads, available_threads};
 use dls::mcp::DMLMCPServer;
+use dls::mcp::audit::AuditLog;
+use dls::mcp::tool_policy::ToolPolicy;
+use dls::mcp::server::DEFAULT_SHUTDOWN_TIMEOUT;
+use dls::mcp::truncation::DEFAULT_MAX_RESULT_BYTES;
+#[cfg(feature = "websocket")]
+use dls::auth::AuthToken;
+#[cfg(feature = "websocket")]
+use dls::mcp::websocket::{self, DEFAULT_LISTEN_ADDRESS};
+use dls::mcp::generation::{DeviceSpec, GenerationConfig, LineEnding,
+                           LoggingPolicy};
+use dls::mcp::provenance::BannerConfig;
+use dls::mcp::tools::generate_device_from_spec;
 use env_logger;
-use log::info;
+use log::{info, warn};
+use std::path::PathBuf;
+#[cfg(feature = "websocket")]
+use std::sync::Arc;
+use std::time::Duration;
 
-#[tokio::main]
-async fn main() -> Result<()> {
+#[derive(Parser, Debug)]
+#[command(name = "dml-mcp-server")]
+#[command(version)]
+#[command(about = "MCP server for DML code generation",
+          long_about = "MCP server for DML code generation, communicates \
+                        over stdin/out, or WebSocket with --transport ws, \
+                        using the Model Context Protocol unless a command is \
+                        given.")]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Threads for CPU-bound work such as validating a workspace
+    /// (default: one less than the available cores, at most 16)
+    #[arg(long, global = true)]
+    analysis_threads: Option<usize>,
+    /// Threads of the async runtime handling requests (default: the
+    /// available cores, at most 4)
+    #[arg(long, global = true)]
+    worker_threads: Option<usize>,
+    /// Maximum number of threads for blocking work such as file system
+    /// access (default: twice the analysis threads)
+    #[arg(long, global = true)]
+    blocking_threads: Option<usize>,
+    /// Neither read nor write the workspace caches under `.dls-cache`
+    #[arg(long)]
+    no_cache: bool,
+    /// Seconds the request being handled may take to finish when the server
+    /// is shut down
+    #[arg(long, default_value_t = DEFAULT_SHUTDOWN_TIMEOUT.as_secs())]
+    shutdown_timeout: u64,
+    /// Append a line of JSON for every tool call to this file, which
+    /// `dls mcp-replay` replays
+    #[arg(long)]
+    audit_log: Option<PathBuf>,
+    /// TOML file restricting the tools offered to clients
+    #[arg(long)]
+    tool_config: Option<PathBuf>,
+    /// Only offer these tools (comma-separated, or repeated)
+    #[arg(long, value_delimiter = ',')]
+    allow_tool: Option<Vec<String>>,
+    /// Do not offer these tools (comma-separated, or repeated)
+    #[arg(long, value_delimiter = ',')]
+    deny_tool: Vec<String>,
+    /// Do not offer tools that can change files
+    #[arg(long)]
+    read_only: bool,
+    /// Truncate tool results with more bytes of text than this, keeping the
+    /// full result as a resource
+    #[arg(long, default_value_t = DEFAULT_MAX_RESULT_BYTES)]
+    max_result_bytes: usize,
+    /// How clients connect to the server
+    #[arg(long, value_enum, default_value_t = Transport::Stdio)]
+    transport: Transport,
+    /// The address to listen on for WebSocket connections
+    #[cfg(feature = "websocket")]
+    #[arg(long, default_value = DEFAULT_LISTEN_ADDRESS)]
+    listen: String,
+    /// File holding the token WebSocket clients must present (default: the
+    /// DLS_AUTH_TOKEN environment variable, if set)
+    #[cfg(feature = "websocket")]
+    #[arg(long)]
+    auth_token_file: Option<PathBuf>,
+}
+
+/// How clients connect to the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Transport {
+    /// A single client, over stdin and stdout
+    Stdio,
+    /// Any number of clients, each over a WebSocket connection of its own
+    #[cfg(feature = "websocket")]
+    Ws,
+}
+
+/// Upper bound of the autodetected number of runtime worker threads; the
+/// server handles one client, whose requests rarely keep more busy
+const MAX_DEFAULT_WORKER_THREADS: usize = 4;
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    dls::cache::set_enabled(!args.no_cache);
+    let analysis_threads = analysis_threads(args.analysis_threads);
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(analysis_threads)
+        .thread_name(|num| format!("analysis-worker-{}", num))
+        .build_global()?;
+    let worker_threads = args.worker_threads.filter(|threads| *threads > 0)
+        .unwrap_or_else(|| available_threads().min(MAX_DEFAULT_WORKER_THREADS));
+    let blocking_threads = args.blocking_threads.filter(|threads| *threads > 0)
+        .unwrap_or(2 * analysis_threads);
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(worker_threads)
+        .max_blocking_threads(blocking_threads)
+        .enable_all()
+        .build()?;
+    let result = runtime.block_on(run(args));
+    // The server has already waited for the request it was handling, so
+    // blocking work left of a request that did not finish in time is not
+    // waited for
+    runtime.shutdown_background();
+    result
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Generate a device from a YAML, TOML or JSON device specification, or
+    /// an SVD or IP-XACT register description
+    GenerateDevice {
+        /// The device specification file
+        spec: PathBuf,
+        /// Write the generated DML to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Use Windows (CRLF) line endings
+        #[arg(long)]
+        crlf: bool,
+        /// Log statements to add to the read and write methods of registers
+        #[arg(long, value_enum, default_value_t = LoggingPolicy::None)]
+        logging: LoggingPolicy,
+        /// Start the output with a banner recording the server version and
+        /// the specification it was generated from
+        #[arg(long)]
+        banner: bool,
+        /// Also record the time of generation in the banner
+        #[arg(long, requires = "banner")]
+        timestamp: bool,
+    },
+}
+
+async fn run(args: Args) -> Result<()> {
     // Initialize logging
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
-    
-    info!("Starting DML MCP Server v{}", env!("CARGO_PKG_VERSION"));
-    
-    // Create and run the MCP server
-    let server = DMLMCPServer::new().await?;
-    server.run().await?;
-    
+
+    match args.command {
+        Some(Command::GenerateDevice { spec: spec_file, output, crlf, logging,
+                                       banner, timestamp }) => {
+            let spec = DeviceSpec::load(&spec_file)?;
+            let banner = banner.then(|| BannerConfig {
+                spec_file: spec_file.file_name()
+                    .map(|name| name.to_string_lossy().to_string()),
+                timestamp,
+            });
+            let config = GenerationConfig {
+                line_ending: if crlf { LineEnding::Windows } else { LineEnding::Unix },
+                logging,
+                banner,
+                ..GenerationConfig::default()
+            };
+            let (code, stats) = generate_device_from_spec(&spec, config).await?;
+            match output {
+                Some(output) => std::fs::write(&output, code).map_err(
+                    |e| anyhow!("Could not write {}: {}", output.display(), e))?,
+                None => print!("{}", code),
+            }
+            for warning in &stats.warnings {
+                warn!("{}", warning);
+            }
+            info!("Generated {} registers and {} fields of device {}",
+                  stats.registers, stats.fields, spec.name);
+        },
+        None => {
+            info!("Starting DML MCP Server v{}", env!("CARGO_PKG_VERSION"));
+
+            // Create and run the MCP server
+            let mut server = DMLMCPServer::new().await?
+                .with_shutdown_timeout(
+                    Duration::from_secs(args.shutdown_timeout))
+                .with_max_result_bytes(args.max_result_bytes);
+            let policy = match &args.tool_config {
+                Some(path) => ToolPolicy::load(path)?,
+                None => ToolPolicy::default(),
+            }.restrict(ToolPolicy {
+                allow: args.allow_tool.clone(),
+                deny: args.deny_tool.clone(),
+                read_only: args.read_only,
+            });
+            server = server.with_tool_policy(&policy)?;
+            if let Some(path) = &args.audit_log {
+                info!("Recording tool calls in {}", path.display());
+                server = server.with_audit_log(AuditLog::open(path)?);
+            }
+            match args.transport {
+                Transport::Stdio => server.run().await?,
+                #[cfg(feature = "websocket")]
+                Transport::Ws => websocket::run(
+                    Arc::new(server), &args.listen,
+                    AuthToken::configured(args.auth_token_file.as_deref())?)
+                    .await?,
+            }
+        },
+    }
+
     Ok(())
-}
\ No newline at end of file
+}
@@ -3,10 +3,32 @@
 //! This module provides an MCP server that leverages the existing DML analysis
 //! capabilities to offer intelligent code generation tools.
 
+pub mod analysis;
+pub mod audit;
+pub use crate::catalog;
+#[cfg(feature = "exporters")]
+pub mod exporters;
+pub mod explain;
+#[cfg(feature = "importers")]
+pub mod importers;
+pub mod output;
+pub mod provenance;
+pub mod query;
+pub mod references;
+pub mod register_diff;
+pub mod resources;
+pub mod schema;
 pub mod server;
+pub mod spec_lint;
+pub mod symbols;
+pub mod tool_policy;
 pub mod tools;
+pub mod truncation;
+#[cfg(feature = "websocket")]
+pub mod websocket;
 pub mod generation;
 pub mod templates;
+pub mod workspace;
 
 pub use server::DMLMCPServer;
 pub use tools::*;
@@ -14,9 +36,22 @@ pub use generation::*;
 pub use templates::*;
 use serde::{Deserialize, Serialize};
 
-/// MCP protocol version supported
+/// MCP protocol version supported, used with clients that do not ask for
+/// another supported version
 pub const MCP_VERSION: &str = "2024-11-05";
 
+/// All MCP protocol versions supported, oldest first
+pub const SUPPORTED_MCP_VERSIONS: &[&str] = &[MCP_VERSION, "2025-03-26"];
+
+/// The protocol version to speak with a client asking for `requested`: the
+/// requested version if it is supported, otherwise the newest supported
+/// version older than it, or the oldest supported version if there is none
+pub fn negotiate_version(requested: &str) -> &'static str {
+    SUPPORTED_MCP_VERSIONS.iter().rev()
+        .find(|version| **version <= requested)
+        .unwrap_or(&SUPPORTED_MCP_VERSIONS[0])
+}
+
 /// Server information
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ServerInfo {
@@ -46,7 +81,7 @@ impl Default for ServerCapabilities {
     fn default() -> Self {
         Self {
             tools: true,
-            resources: false,
+            resources: true,
             prompts: false,
             logging: true,
         }
@@ -0,0 +1,247 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Writing generated files to disk, for tools given an `output_dir`
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::mcp::generation::GeneratedCode;
+
+/// Lines of unchanged context around the changes in a diff
+const DIFF_CONTEXT: usize = 3;
+
+/// Above this many compared line pairs, the changed part of a file is
+/// diffed as a whole instead of line by line
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+/// Where, and whether, a tool writes the files it generates
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputOptions {
+    pub dir: PathBuf,
+    /// Overwrite existing files that differ from the generated ones
+    pub force: bool,
+    /// Only report what would be written, with diffs
+    pub dry_run: bool,
+}
+
+/// What writing a generated file does, or would do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WriteAction {
+    Create,
+    Overwrite,
+    /// The file already has the generated contents
+    Unchanged,
+    /// The file exists with other contents, and `force` is not set
+    Refuse,
+}
+
+/// A generated file, as written to the output directory
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileWrite {
+    pub path: PathBuf,
+    pub action: WriteAction,
+    /// Unified diff from the existing file, in dry runs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diff: Option<String>,
+}
+
+/// JSON schema properties of the arguments of tools that can write the
+/// files they generate
+pub fn output_properties() -> Value {
+    json!({
+        "output_dir": {
+            "type": "string",
+            "description": "Directory to write the generated files to, in addition to returning them"
+        },
+        "force": {
+            "type": "boolean",
+            "description": "Overwrite existing files in output_dir",
+            "default": false
+        },
+        "dry_run": {
+            "type": "boolean",
+            "description": "Only report the files that would be written to output_dir, with diffs against the existing ones",
+            "default": false
+        }
+    })
+}
+
+impl OutputOptions {
+    /// The output options of a tool, `None` if it is not to write files
+    pub fn from_input(input: &Value) -> Result<Option<Self>> {
+        let Some(dir) = input.get("output_dir").filter(|dir| !dir.is_null())
+        else {
+            return Ok(None);
+        };
+        let dir = dir.as_str()
+            .ok_or_else(|| anyhow!("output_dir must be a string"))?;
+        Ok(Some(OutputOptions {
+            dir: PathBuf::from(dir),
+            force: input["force"].as_bool().unwrap_or(false),
+            dry_run: input["dry_run"].as_bool().unwrap_or(false),
+        }))
+    }
+
+    /// Write generated files to the output directory, or in a dry run only
+    /// report what would be written. Nothing is written if an existing file
+    /// would be overwritten without `force`.
+    pub fn write(&self, files: &[GeneratedCode]) -> Result<Vec<FileWrite>> {
+        let mut writes = Vec::with_capacity(files.len());
+        for file in files {
+            let relative = Path::new(&file.file_path);
+            if !relative.components().all(
+                |component| matches!(component, Component::Normal(_))) {
+                return Err(anyhow!("Generated file {} is not a relative \
+                                    path inside the output directory",
+                                   file.file_path));
+            }
+            let path = self.dir.join(relative);
+            let existing = if path.exists() {
+                Some(fs::read_to_string(&path).unwrap_or_default())
+            } else {
+                None
+            };
+            let action = match &existing {
+                None => WriteAction::Create,
+                Some(text) if *text == file.content => WriteAction::Unchanged,
+                Some(_) if self.force => WriteAction::Overwrite,
+                Some(_) => WriteAction::Refuse,
+            };
+            let diff = (self.dry_run && action != WriteAction::Unchanged)
+                .then(|| unified_diff(&file.file_path, existing.as_deref(),
+                                      &file.content));
+            writes.push(FileWrite { path, action, diff });
+        }
+        if self.dry_run {
+            return Ok(writes);
+        }
+
+        let refused: Vec<String> = writes.iter()
+            .filter(|write| write.action == WriteAction::Refuse)
+            .map(|write| write.path.display().to_string())
+            .collect();
+        if !refused.is_empty() {
+            return Err(anyhow!("Refusing to overwrite {}; pass force: true \
+                                to overwrite existing files",
+                               refused.join(", ")));
+        }
+        for (write, file) in writes.iter().zip(files) {
+            if matches!(write.action,
+                        WriteAction::Create | WriteAction::Overwrite) {
+                if let Some(parent) = write.path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::write(&write.path, &file.content).map_err(
+                    |e| anyhow!("Could not write {}: {}",
+                                write.path.display(), e))?;
+            }
+        }
+        Ok(writes)
+    }
+}
+
+/// The lines of `old` and `new`, each marked as kept (' '), removed ('-')
+/// or added ('+')
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<(char, &'a str)> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b).count();
+    let a = &old[prefix..old.len() - suffix];
+    let b = &new[prefix..new.len() - suffix];
+
+    let mut lines: Vec<(char, &str)> =
+        old[..prefix].iter().map(|line| (' ', *line)).collect();
+    let (mut i, mut j) = (0, 0);
+    if a.len() * b.len() <= MAX_DIFF_CELLS {
+        // Lengths of the longest common subsequences of the remainders
+        let mut lcs = vec![vec![0u32; b.len() + 1]; a.len() + 1];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                lcs[i][j] = if a[i] == b[j] {
+                    lcs[i + 1][j + 1] + 1
+                } else {
+                    lcs[i + 1][j].max(lcs[i][j + 1])
+                };
+            }
+        }
+        while i < a.len() && j < b.len() {
+            if a[i] == b[j] {
+                lines.push((' ', a[i]));
+                i += 1;
+                j += 1;
+            } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+                lines.push(('-', a[i]));
+                i += 1;
+            } else {
+                lines.push(('+', b[j]));
+                j += 1;
+            }
+        }
+    }
+    lines.extend(a[i..].iter().map(|line| ('-', *line)));
+    lines.extend(b[j..].iter().map(|line| ('+', *line)));
+    lines.extend(old[old.len() - suffix..].iter().map(|line| (' ', *line)));
+    lines
+}
+
+/// A unified diff from the existing contents of a file, if any, to `new`
+pub fn unified_diff(path: &str, old: Option<&str>, new: &str) -> String {
+    let old_lines: Vec<&str> = old.unwrap_or("").lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let lines = diff_lines(&old_lines, &new_lines);
+
+    let mut diff = match old {
+        Some(_) => format!("--- a/{}\n+++ b/{}\n", path, path),
+        None => format!("--- /dev/null\n+++ b/{}\n", path),
+    };
+    let changes: Vec<usize> = lines.iter().enumerate()
+        .filter(|(_, (mark, _))| *mark != ' ')
+        .map(|(index, _)| index)
+        .collect();
+    // Line numbers in the old and new file, before each diff line
+    let mut numbers = Vec::with_capacity(lines.len() + 1);
+    let (mut old_line, mut new_line) = (0, 0);
+    for (mark, _) in &lines {
+        numbers.push((old_line, new_line));
+        if *mark != '+' {
+            old_line += 1;
+        }
+        if *mark != '-' {
+            new_line += 1;
+        }
+    }
+    numbers.push((old_line, new_line));
+
+    let mut index = 0;
+    while index < changes.len() {
+        // Changes closer than twice the context share a hunk
+        let mut last = index;
+        while last + 1 < changes.len()
+            && changes[last + 1] - changes[last] <= 2 * DIFF_CONTEXT {
+            last += 1;
+        }
+        let start = changes[index].saturating_sub(DIFF_CONTEXT);
+        let end = (changes[last] + DIFF_CONTEXT + 1).min(lines.len());
+        let (old_start, new_start) = numbers[start];
+        let (old_end, new_end) = numbers[end];
+        let range = |start: usize, len: usize| if len == 0 {
+            format!("{},0", start)
+        } else {
+            format!("{},{}", start + 1, len)
+        };
+        diff.push_str(&format!("@@ -{} +{} @@\n",
+                               range(old_start, old_end - old_start),
+                               range(new_start, new_end - new_start)));
+        for (mark, line) in &lines[start..end] {
+            diff.push(*mark);
+            diff.push_str(line);
+            diff.push('\n');
+        }
+        index = last + 1;
+    }
+    diff
+}
@@ -0,0 +1,132 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Provenance banners of generated files
+//!
+//! Generated files can start with a comment recording what generated them
+//! and from what, so that regenerated files can be told apart from
+//! hand-written ones, and files generated from a specification that has
+//! since changed can be found.
+
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::analysis::content_hash;
+use crate::mcp::generation::DeviceSpec;
+
+/// First line of a provenance banner
+pub const BANNER_MARKER: &str = "// @generated by dml-mcp-server";
+
+/// Contents of the provenance banner of generated files
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BannerConfig {
+    /// The specification file the device is generated from, as it should
+    /// be recorded
+    #[serde(default)]
+    pub spec_file: Option<String>,
+    /// Record the time of generation, which makes the output differ
+    /// between otherwise identical runs
+    #[serde(default)]
+    pub timestamp: bool,
+}
+
+/// What a file was generated by and from, as recorded in its banner
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Provenance {
+    pub tool_version: String,
+    pub spec_file: Option<String>,
+    /// Hash of the specification, see `spec_hash`
+    pub spec_hash: Option<String>,
+    /// UTC time of generation, in RFC 3339 format
+    pub generated_at: Option<String>,
+}
+
+impl Provenance {
+    /// The provenance of a device generated now from `spec` by this server
+    pub fn for_spec(spec: &DeviceSpec, config: &BannerConfig) -> Self {
+        Provenance {
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            spec_file: config.spec_file.clone(),
+            spec_hash: Some(spec_hash(spec)),
+            generated_at: config.timestamp
+                .then(|| utc_timestamp(SystemTime::now())),
+        }
+    }
+
+    /// The banner recording this provenance, followed by an empty line
+    pub fn banner(&self) -> String {
+        let mut banner = format!("{}\n// Regenerate instead of editing by \
+                                  hand.\n// tool-version: {}\n",
+                                 BANNER_MARKER, self.tool_version);
+        let entries = [("spec-file", &self.spec_file),
+                       ("spec-hash", &self.spec_hash),
+                       ("generated-at", &self.generated_at)];
+        for (key, value) in entries {
+            if let Some(value) = value {
+                banner.push_str(&format!("// {}: {}\n", key, value));
+            }
+        }
+        banner.push('\n');
+        banner
+    }
+
+    /// The provenance recorded in the banner starting `text`, if it starts
+    /// with one
+    pub fn parse(text: &str) -> Option<Provenance> {
+        let mut lines = text.lines();
+        if lines.next()?.trim_end() != BANNER_MARKER {
+            return None;
+        }
+        let mut provenance = Provenance {
+            tool_version: String::new(),
+            spec_file: None,
+            spec_hash: None,
+            generated_at: None,
+        };
+        for line in lines {
+            let Some(comment) = line.strip_prefix("//") else {
+                break;
+            };
+            let Some((key, value)) = comment.split_once(':') else {
+                continue;
+            };
+            let value = value.trim().to_string();
+            match key.trim() {
+                "tool-version" => provenance.tool_version = value,
+                "spec-file" => provenance.spec_file = Some(value),
+                "spec-hash" => provenance.spec_hash = Some(value),
+                "generated-at" => provenance.generated_at = Some(value),
+                _ => (),
+            }
+        }
+        Some(provenance)
+    }
+}
+
+/// A hash of a specification that is stable between server builds, so that
+/// it can be compared with the one recorded when a file was generated
+pub fn spec_hash(spec: &DeviceSpec) -> String {
+    let json = serde_json::to_string(spec).unwrap_or_default();
+    format!("{:016x}", content_hash(&json))
+}
+
+/// A time as an RFC 3339 UTC timestamp with second precision
+pub fn utc_timestamp(time: SystemTime) -> String {
+    let seconds = time.duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let (days, seconds) = (seconds / 86400, seconds % 86400);
+    // Civil date from days since the epoch, see Howard Hinnant's
+    // "chrono-Compatible Low-Level Date Algorithms"
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524
+                       - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era
+        - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day,
+            seconds / 3600, seconds % 3600 / 60, seconds % 60)
+}
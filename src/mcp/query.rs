@@ -0,0 +1,313 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Small query language over the register maps of a workspace
+//!
+//! Queries have the form
+//!
+//! ```text
+//! select registers where size = 8 and bank = "pcie"
+//! list fields with access = "wo"
+//! ```
+//!
+//! The verb (`select` or `list`) and the filter keyword (`where` or `with`)
+//! are interchangeable. Entities are `devices`, `banks`, `registers` and
+//! `fields`; conditions compare an attribute with `=`, `!=`, `<`, `<=`,
+//! `>`, `>=` or `~` (substring match), and are combined with `and`.
+
+use anyhow::{anyhow, Result};
+use serde_json::{json, Map, Value};
+use std::cmp::Ordering;
+use std::fmt;
+
+use super::generation::parse_integer;
+use super::workspace::WorkspaceIndex;
+
+/// The kind of object a query selects
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryEntity {
+    Device,
+    Bank,
+    Register,
+    Field,
+}
+
+impl QueryEntity {
+    fn from_word(word: &str) -> Option<Self> {
+        match word {
+            "device" | "devices" => Some(Self::Device),
+            "bank" | "banks" => Some(Self::Bank),
+            "register" | "registers" => Some(Self::Register),
+            "field" | "fields" => Some(Self::Field),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for QueryEntity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Device => "devices",
+            Self::Bank => "banks",
+            Self::Register => "registers",
+            Self::Field => "fields",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryValue {
+    Integer(u64),
+    Text(String),
+}
+
+/// One `attribute op value` condition
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    pub attribute: String,
+    pub op: QueryOp,
+    pub value: QueryValue,
+}
+
+/// A parsed query
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    pub entity: QueryEntity,
+    pub conditions: Vec<Condition>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Text(String),
+    Op(QueryOp),
+}
+
+fn tokenize(query: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut chars = query.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' || c == '\'' {
+            chars.next();
+            let mut text = String::new();
+            loop {
+                match chars.next() {
+                    Some(end) if end == c => break,
+                    Some(other) => text.push(other),
+                    None => return Err(anyhow!("Unterminated string in query")),
+                }
+            }
+            tokens.push(Token::Text(text));
+        } else if "=!<>~".contains(c) {
+            chars.next();
+            let followed_by_eq = chars.peek() == Some(&'=');
+            let op = match (c, followed_by_eq) {
+                ('=', _) => QueryOp::Eq,
+                ('!', true) => QueryOp::Ne,
+                ('<', true) => QueryOp::Le,
+                ('<', false) => QueryOp::Lt,
+                ('>', true) => QueryOp::Ge,
+                ('>', false) => QueryOp::Gt,
+                ('~', _) => QueryOp::Contains,
+                _ => return Err(anyhow!("Unexpected '{}' in query", c)),
+            };
+            if followed_by_eq && c != '~' {
+                chars.next();
+            }
+            tokens.push(Token::Op(op));
+        } else {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || "=!<>~\"'".contains(c) {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Word(word));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Parse a query string
+pub fn parse_query(query: &str) -> Result<Query> {
+    let mut tokens = tokenize(query)?.into_iter();
+    match tokens.next() {
+        Some(Token::Word(verb)) if verb.eq_ignore_ascii_case("select")
+            || verb.eq_ignore_ascii_case("list") => (),
+        _ => return Err(anyhow!("Query must start with 'select' or 'list'")),
+    }
+    let entity = match tokens.next() {
+        Some(Token::Word(word)) => QueryEntity::from_word(
+            &word.to_lowercase()).ok_or_else(
+            || anyhow!("Unknown entity '{}', expected devices, banks, \
+                        registers or fields", word))?,
+        _ => return Err(anyhow!("Expected an entity after the query verb")),
+    };
+    let mut conditions = vec![];
+    match tokens.next() {
+        None => return Ok(Query { entity, conditions }),
+        Some(Token::Word(word)) if word.eq_ignore_ascii_case("where")
+            || word.eq_ignore_ascii_case("with") => (),
+        Some(other) => return Err(anyhow!("Expected 'where', got {:?}",
+                                          other)),
+    }
+    loop {
+        let attribute = match tokens.next() {
+            Some(Token::Word(word)) => word.to_lowercase(),
+            _ => return Err(anyhow!("Expected an attribute name")),
+        };
+        let op = match tokens.next() {
+            Some(Token::Op(op)) => op,
+            _ => return Err(anyhow!("Expected an operator after '{}'",
+                                    attribute)),
+        };
+        let value = match tokens.next() {
+            Some(Token::Text(text)) => QueryValue::Text(text),
+            Some(Token::Word(word)) => match parse_integer(&word) {
+                Some(number) => QueryValue::Integer(number),
+                None => QueryValue::Text(word),
+            },
+            _ => return Err(anyhow!("Expected a value after '{}'",
+                                    attribute)),
+        };
+        conditions.push(Condition { attribute, op, value });
+        match tokens.next() {
+            None => break,
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case("and") => (),
+            Some(other) => return Err(anyhow!("Expected 'and', got {:?}",
+                                              other)),
+        }
+    }
+    Ok(Query { entity, conditions })
+}
+
+impl Condition {
+    fn matches(&self, row: &Map<String, Value>) -> bool {
+        let actual = match row.get(&self.attribute) {
+            Some(Value::Null) | None => return self.op == QueryOp::Ne,
+            Some(actual) => actual,
+        };
+        let ordering = match (&self.value, actual) {
+            (QueryValue::Integer(expected), Value::Number(n)) =>
+                n.as_u64().map(|n| n.cmp(expected)),
+            (QueryValue::Integer(expected), Value::String(s)) =>
+                parse_integer(s).map(|n| n.cmp(expected)),
+            (QueryValue::Text(expected), Value::String(s)) => {
+                if self.op == QueryOp::Contains {
+                    return s.to_lowercase().contains(&expected.to_lowercase());
+                }
+                Some(s.as_str().cmp(expected.as_str()))
+            },
+            (QueryValue::Text(expected), Value::Bool(b)) =>
+                Some(b.to_string().as_str().cmp(expected.as_str())),
+            _ => None,
+        };
+        match (ordering, self.op) {
+            (None, op) => op == QueryOp::Ne,
+            (Some(ord), QueryOp::Eq) => ord == Ordering::Equal,
+            (Some(ord), QueryOp::Ne) => ord != Ordering::Equal,
+            (Some(ord), QueryOp::Lt) => ord == Ordering::Less,
+            (Some(ord), QueryOp::Le) => ord != Ordering::Greater,
+            (Some(ord), QueryOp::Gt) => ord == Ordering::Greater,
+            (Some(ord), QueryOp::Ge) => ord != Ordering::Less,
+            (Some(ord), QueryOp::Contains) => ord == Ordering::Equal,
+        }
+    }
+}
+
+fn into_map(value: Value) -> Map<String, Value> {
+    match value {
+        Value::Object(map) => map,
+        _ => Map::new(),
+    }
+}
+
+/// Flatten the workspace index into one row per object of the queried kind
+fn rows(index: &WorkspaceIndex, entity: QueryEntity)
+        -> Vec<Map<String, Value>> {
+    let mut rows = vec![];
+    for file in &index.files {
+        let path = index.relative_path(&file.path).display().to_string();
+        let device = &file.spec.name;
+        if entity == QueryEntity::Device {
+            if file.is_device {
+                rows.push(into_map(json!({
+                    "name": device,
+                    "file": path,
+                    "banks": file.spec.banks.len(),
+                    "interfaces": file.spec.interfaces.iter()
+                        .map(|i| i.name.clone()).collect::<Vec<_>>(),
+                    "documentation": file.spec.documentation,
+                })));
+            }
+            continue;
+        }
+        for bank in &file.spec.banks {
+            if entity == QueryEntity::Bank {
+                rows.push(into_map(json!({
+                    "name": bank.name,
+                    "device": device,
+                    "file": path,
+                    "registers": bank.registers.len(),
+                    "documentation": bank.documentation,
+                })));
+                continue;
+            }
+            for register in &bank.registers {
+                if entity == QueryEntity::Register {
+                    rows.push(into_map(json!({
+                        "name": register.name,
+                        "bank": bank.name,
+                        "device": device,
+                        "file": path,
+                        "size": register.size,
+                        "offset": register.offset,
+                        "fields": register.fields.len(),
+                        "documentation": register.documentation,
+                    })));
+                    continue;
+                }
+                for field in &register.fields {
+                    let range = field.bit_range();
+                    rows.push(into_map(json!({
+                        "name": field.name,
+                        "register": register.name,
+                        "bank": bank.name,
+                        "device": device,
+                        "file": path,
+                        "bits": field.bits,
+                        "msb": range.map(|(msb, _)| msb),
+                        "lsb": range.map(|(_, lsb)| lsb),
+                        "width": range.map(|(msb, lsb)| msb - lsb + 1),
+                        "access": field.access.as_deref().unwrap_or("rw"),
+                        "documentation": field.documentation,
+                    })));
+                }
+            }
+        }
+    }
+    rows
+}
+
+/// Evaluate a query against a workspace index, returning the matching
+/// objects as JSON
+pub fn evaluate_query(query: &Query, index: &WorkspaceIndex) -> Vec<Value> {
+    rows(index, query.entity).into_iter()
+        .filter(|row| query.conditions.iter().all(|cond| cond.matches(row)))
+        .map(Value::Object)
+        .collect()
+}
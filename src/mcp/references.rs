@@ -0,0 +1,310 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! References to symbols, resolved in the analysis of a device
+//!
+//! A symbol is given by a position in one of the files of the device, or by
+//! its dotted name, and is looked up as the language server looks up the
+//! symbol or reference under the cursor. Declarations in templates are
+//! resolved through the objects instantiating them, so references in
+//! templates the device does not instantiate are not found.
+//!
+//! Symbols are renamed where the language server renames them: in their
+//! declarations and definitions, in the references to them, and for methods
+//! in the methods they override or are overridden by.
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::analysis::limitations::DLSLimitation;
+use crate::analysis::parsing::lexer::is_identifier;
+use crate::analysis::parsing::tree::{ZeroFilePosition, ZeroPosition, ZeroSpan};
+use crate::analysis::symbols::SymbolRef;
+use crate::analysis::{DeviceAnalysis, IsolatedAnalysis, LocationSpan};
+use crate::file_management::CanonPath;
+use crate::span::{Column, Row};
+
+use super::analysis::{analyze_device_files, SourceRange};
+use super::symbols::declarations_named;
+
+/// A device with the analysis of each of its files
+pub struct AnalyzedDevice {
+    pub device: DeviceAnalysis,
+    files: HashMap<CanonPath, IsolatedAnalysis>,
+    /// Limitations of the analysis that affected lookups
+    pub limitations: HashSet<DLSLimitation>,
+}
+
+/// A symbol of a device, at the place it is first declared
+#[derive(Debug, Clone, Serialize)]
+pub struct ResolvedSymbol {
+    pub name: String,
+    pub kind: &'static str,
+    pub file: PathBuf,
+    pub range: SourceRange,
+}
+
+/// A place a symbol is referred to, or declared
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct ReferenceSite {
+    pub file: PathBuf,
+    pub range: SourceRange,
+    /// The line of the reference, without surrounding whitespace
+    pub context: String,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub declaration: bool,
+}
+
+/// The edits renaming symbols in one file
+#[derive(Debug, Clone, Serialize)]
+pub struct RenamedFile {
+    pub file: PathBuf,
+    /// The ranges of the old name, before renaming
+    pub ranges: Vec<SourceRange>,
+    #[serde(skip)]
+    pub old_text: String,
+    #[serde(skip)]
+    pub new_text: String,
+}
+
+/// The lines of the files that references are in, read once per file
+#[derive(Default)]
+struct SourceLines {
+    files: HashMap<PathBuf, Vec<String>>,
+}
+
+impl SourceLines {
+    fn line(&mut self, file: &Path, row: u32) -> &str {
+        let lines = self.files.entry(file.to_path_buf())
+            .or_insert_with(|| std::fs::read_to_string(file)
+                            .map(|text| text.lines().map(String::from).collect())
+                            .unwrap_or_default());
+        lines.get(row as usize).map(String::as_str).unwrap_or("")
+    }
+
+    /// The text of a span on a single line
+    fn text(&mut self, span: &ZeroSpan) -> String {
+        let range = span.range;
+        let end = if range.row_end == range.row_start {
+            range.col_end.0 as usize
+        } else {
+            usize::MAX
+        };
+        self.line(&span.path(), range.row_start.0).chars()
+            .skip(range.col_start.0 as usize)
+            .take(end.saturating_sub(range.col_start.0 as usize))
+            .collect()
+    }
+}
+
+impl AnalyzedDevice {
+    /// Analyze the device declared in the file at `path`, see
+    /// `analyze_device`
+    pub fn analyze(path: &Path, include_paths: &[PathBuf]) -> Result<Self> {
+        let (device, files) = analyze_device_files(path, include_paths)?;
+        Ok(AnalyzedDevice { device, files, limitations: HashSet::new() })
+    }
+
+    /// The symbols declared or referred to at a one-based position in one
+    /// of the files of the device
+    pub fn symbols_at(&mut self, file: &Path, line: u32, column: u32)
+                      -> Result<Vec<SymbolRef>> {
+        let canon = CanonPath::from_path_buf(file.to_path_buf())
+            .ok_or_else(|| anyhow!("Could not find {}", file.display()))?;
+        let isolated = self.files.get(&canon).ok_or_else(
+            || anyhow!("{} is not one of the files of device {}",
+                       file.display(), self.device.name))?;
+        let position = ZeroFilePosition::new(
+            ZeroPosition::new(Row::new_zero_indexed(line.saturating_sub(1)),
+                              Column::new_zero_indexed(
+                                  column.saturating_sub(1))),
+            canon.as_path());
+        if let Some(mut symbol) = isolated.lookup_context_symbol(&position) {
+            // The device itself is not part of the context of a symbol
+            symbol.remove_head_context();
+            return Ok(self.device.lookup_symbols(&symbol,
+                                                 &mut self.limitations));
+        }
+        Ok(isolated.lookup_reference(&position)
+           .map(|reference| self.device.symbols_of_ref(*reference.loc_span()))
+           .unwrap_or_default())
+    }
+
+    /// The symbols with the dotted name `name` in the files of the device,
+    /// see `declarations_named`
+    pub fn symbols_named(&mut self, name: &str) -> Result<Vec<SymbolRef>> {
+        let declarations: Vec<(PathBuf, SourceRange)> = self.files.iter()
+            .flat_map(|(path, isolated)| {
+                declarations_named(&isolated.toplevel, name).into_iter()
+                    .map(move |declaration| (path.as_path().to_path_buf(),
+                                             declaration.selection_range))
+            })
+            .collect();
+        let mut symbols: Vec<SymbolRef> = vec![];
+        for (file, range) in declarations {
+            for symbol in self.symbols_at(&file, range.start_line,
+                                          range.start_column)? {
+                if !symbols.iter().any(|known| Arc::ptr_eq(known, &symbol)) {
+                    symbols.push(symbol);
+                }
+            }
+        }
+        Ok(symbols)
+    }
+
+    /// The symbols with the dotted name `symbol` if given, or otherwise
+    /// those at a one-based position in `file`, failing if there are none
+    pub fn lookup(&mut self, symbol: Option<&str>, file: &Path,
+                  position: Option<(u32, u32)>) -> Result<Vec<SymbolRef>> {
+        let symbols = match (symbol, position) {
+            (Some(name), _) => self.symbols_named(name)?,
+            (None, Some((line, column))) =>
+                self.symbols_at(file, line, column)?,
+            (None, None) =>
+                return Err(anyhow!("Pass symbol, or line and column")),
+        };
+        if !symbols.is_empty() {
+            return Ok(symbols);
+        }
+        Err(match (symbol, position) {
+            (Some(name), _) => anyhow!("No symbol '{}' in device {}",
+                                       name, self.device.name),
+            (None, Some((line, column))) =>
+                anyhow!("No symbol or reference at {}:{}:{}",
+                        file.display(), line, column),
+            (None, None) => unreachable!(),
+        })
+    }
+
+    /// The names, kinds and places of symbols
+    pub fn describe(&self, symbols: &[SymbolRef]) -> Vec<ResolvedSymbol> {
+        let mut lines = SourceLines::default();
+        let mut described: Vec<ResolvedSymbol> = symbols.iter()
+            .map(|symbol| {
+                let symbol = symbol.lock().unwrap();
+                ResolvedSymbol {
+                    name: lines.text(&symbol.loc),
+                    kind: symbol.kind.kind_name(),
+                    file: symbol.loc.path(),
+                    range: SourceRange::from(&symbol.loc),
+                }
+            })
+            .collect();
+        described.sort_by(|a, b| (&a.file, a.range).cmp(&(&b.file, b.range)));
+        described
+    }
+
+    /// The places `symbols` are referred to, and declared if
+    /// `include_declarations` is set, in the order they are in
+    pub fn references(&self, symbols: &[SymbolRef],
+                      include_declarations: bool) -> Vec<ReferenceSite> {
+        let mut spans: HashMap<ZeroSpan, bool> = HashMap::new();
+        for symbol in symbols {
+            let symbol = symbol.lock().unwrap();
+            for span in &symbol.references {
+                spans.entry(*span).or_insert(false);
+            }
+            if include_declarations {
+                for span in symbol.declarations.iter()
+                    .chain(&symbol.definitions) {
+                    spans.insert(*span, true);
+                }
+            }
+        }
+        let mut lines = SourceLines::default();
+        let mut sites: Vec<ReferenceSite> = spans.into_iter()
+            .map(|(span, declaration)| ReferenceSite {
+                file: span.path(),
+                range: SourceRange::from(&span),
+                context: lines.line(&span.path(), span.range.row_start.0)
+                    .trim().to_string(),
+                declaration,
+            })
+            .collect();
+        sites.sort();
+        sites
+    }
+
+    /// The directory of the standard library, if it is among the files of
+    /// the device
+    fn stdlib_dir(&self) -> Option<PathBuf> {
+        self.files.keys()
+            .find(|path| path.as_path().ends_with("dml-builtins.dml"))
+            .and_then(|path| path.as_path().parent())
+            .map(Path::to_path_buf)
+    }
+
+    /// The edits renaming `symbols` to `new_name`, with the old name, file
+    /// by file. Symbols of kinds the language server does not rename are
+    /// left out, and symbols declared in the standard library are not
+    /// renamed at all.
+    pub fn rename(&self, symbols: &[SymbolRef], new_name: &str)
+                  -> Result<(String, Vec<RenamedFile>)> {
+        if !is_identifier(new_name) {
+            return Err(anyhow!("'{}' is not a valid identifier", new_name));
+        }
+        let Some(renamed) = symbols.iter()
+            .find(|symbol| symbol.lock().unwrap().kind.is_renameable()) else {
+                return Err(anyhow!("Only devices, banks, registers, fields, \
+                                    methods, templates and parameters can \
+                                    be renamed"));
+            };
+        let mut lines = SourceLines::default();
+        let name = lines.text(&renamed.lock().unwrap().loc);
+        let locations = self.device.rename_locations(symbols);
+        if let Some(dir) = self.stdlib_dir() {
+            if locations.iter().any(|span| span.path().starts_with(&dir)) {
+                return Err(anyhow!("'{}' is declared in the standard \
+                                    library, and cannot be renamed", name));
+            }
+        }
+        // Locations that do not spell out the name, such as that of 'this',
+        // are not renamed
+        let mut by_file: HashMap<PathBuf, Vec<ZeroSpan>> = HashMap::new();
+        for span in locations {
+            if span.range.row_start == span.range.row_end
+                && lines.text(&span) == name {
+                    by_file.entry(span.path()).or_default().push(span);
+                }
+        }
+        let mut files = vec![];
+        for (file, mut spans) in by_file {
+            spans.sort_by_key(|span| (span.range.row_start,
+                                      span.range.col_start));
+            let old_text = std::fs::read_to_string(&file).map_err(
+                |e| anyhow!("Could not read {}: {}", file.display(), e))?;
+            let new_text = replace_spans(&old_text, &spans, new_name);
+            files.push(RenamedFile {
+                ranges: spans.iter().map(SourceRange::from).collect(),
+                file,
+                old_text,
+                new_text,
+            });
+        }
+        files.sort_by(|a, b| a.file.cmp(&b.file));
+        Ok((name, files))
+    }
+}
+
+/// `text` with each of the single-line `spans`, sorted by position, replaced
+/// by `replacement`
+fn replace_spans(text: &str, spans: &[ZeroSpan], replacement: &str)
+                 -> String {
+    let mut result = String::with_capacity(text.len());
+    for (row, line) in text.split_inclusive('\n').enumerate() {
+        let mut chars: Vec<char> = line.chars().collect();
+        // From the end of the line, so that earlier columns stay in place
+        for span in spans.iter().rev()
+            .filter(|span| span.range.row_start.0 as usize == row) {
+                let (start, end) = (span.range.col_start.0 as usize,
+                                    span.range.col_end.0 as usize);
+                if start <= end && end <= chars.len() {
+                    chars.splice(start..end, replacement.chars());
+                }
+            }
+        result.extend(chars);
+    }
+    result
+}
@@ -0,0 +1,243 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Differences between the register maps of two devices
+//!
+//! Banks are matched by name. Registers and fields are matched by name
+//! first, and those left over by where they are, so that a register or field
+//! that is renamed but kept in place is reported as renamed rather than as
+//! removed and added.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::mcp::generation::{normalize_access, DeviceSpec, FieldSpec,
+                             RegisterSpec};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    BankAdded,
+    BankRemoved,
+    RegisterAdded,
+    RegisterRemoved,
+    RegisterRenamed,
+    RegisterMoved,
+    SizeChanged,
+    ResetChanged,
+    AccessChanged,
+    FieldAdded,
+    FieldRemoved,
+    FieldRenamed,
+    FieldMoved,
+}
+
+/// A difference between two register maps. `register` and `field` are the
+/// names in the new map, or in the old one for what was removed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RegisterMapChange {
+    pub kind: ChangeKind,
+    pub bank: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub register: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    /// The old value of what changed, e.g. the offset of a moved register
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new: Option<String>,
+}
+
+/// Collects the changes of one bank
+struct BankDiff<'a> {
+    bank: &'a str,
+    changes: Vec<RegisterMapChange>,
+}
+
+impl BankDiff<'_> {
+    fn push(&mut self, kind: ChangeKind, register: Option<&str>,
+            field: Option<&str>, old: Option<String>, new: Option<String>) {
+        self.changes.push(RegisterMapChange {
+            kind,
+            bank: self.bank.to_string(),
+            register: register.map(String::from),
+            field: field.map(String::from),
+            old,
+            new,
+        });
+    }
+
+    fn registers(&mut self, old: &[RegisterSpec], new: &[RegisterSpec]) {
+        let pairs = match_by_name_then_place(
+            old, new, |reg| &reg.name,
+            |a, b| a.offset_value().is_some()
+                && a.offset_value() == b.offset_value());
+        for pair in pairs {
+            match pair {
+                (Some(old), None) => self.push(
+                    ChangeKind::RegisterRemoved, Some(&old.name), None,
+                    Some(offset_text(old)), None),
+                (None, Some(new)) => self.push(
+                    ChangeKind::RegisterAdded, Some(&new.name), None, None,
+                    Some(offset_text(new))),
+                (Some(old), Some(new)) => self.register(old, new),
+                (None, None) => (),
+            }
+        }
+    }
+
+    fn register(&mut self, old: &RegisterSpec, new: &RegisterSpec) {
+        let name = Some(new.name.as_str());
+        if old.name != new.name {
+            self.push(ChangeKind::RegisterRenamed, name, None,
+                      Some(old.name.clone()), Some(new.name.clone()));
+        }
+        let (old_offset, new_offset) = (offset_text(old), offset_text(new));
+        if old_offset != new_offset {
+            self.push(ChangeKind::RegisterMoved, name, None,
+                      Some(old_offset), Some(new_offset));
+        }
+        if old.size != new.size {
+            self.push(ChangeKind::SizeChanged, name, None,
+                      Some(old.size.to_string()), Some(new.size.to_string()));
+        }
+        let (old_reset, new_reset) = (reset_text(old), reset_text(new));
+        if old_reset != new_reset {
+            self.push(ChangeKind::ResetChanged, name, None,
+                      Some(old_reset), Some(new_reset));
+        }
+        if access_of(&old.access) != access_of(&new.access) {
+            self.push(ChangeKind::AccessChanged, name, None,
+                      Some(access_of(&old.access)),
+                      Some(access_of(&new.access)));
+        }
+
+        let pairs = match_by_name_then_place(
+            &old.fields, &new.fields, |field| &field.name,
+            |a, b| a.bit_range().is_some() && a.bit_range() == b.bit_range());
+        for pair in pairs {
+            match pair {
+                (Some(old), None) => self.push(
+                    ChangeKind::FieldRemoved, name, Some(&old.name),
+                    Some(old.bits.clone()), None),
+                (None, Some(new)) => self.push(
+                    ChangeKind::FieldAdded, name, Some(&new.name), None,
+                    Some(new.bits.clone())),
+                (Some(old), Some(new)) => self.field(name, old, new),
+                (None, None) => (),
+            }
+        }
+    }
+
+    fn field(&mut self, register: Option<&str>, old: &FieldSpec,
+             new: &FieldSpec) {
+        let name = Some(new.name.as_str());
+        if old.name != new.name {
+            self.push(ChangeKind::FieldRenamed, register, name,
+                      Some(old.name.clone()), Some(new.name.clone()));
+        }
+        let moved = match (old.bit_range(), new.bit_range()) {
+            (Some(old), Some(new)) => old != new,
+            _ => old.bits != new.bits,
+        };
+        if moved {
+            self.push(ChangeKind::FieldMoved, register, name,
+                      Some(old.bits.clone()), Some(new.bits.clone()));
+        }
+        if access_of(&old.access) != access_of(&new.access) {
+            self.push(ChangeKind::AccessChanged, register, name,
+                      Some(access_of(&old.access)),
+                      Some(access_of(&new.access)));
+        }
+    }
+}
+
+/// Pair up the elements of `old` and `new` with the same name, and then
+/// those left over that are in the same place. Unpaired elements are paired
+/// with `None`. The pairs are in the order of `old`, followed by what was
+/// added in the order of `new`.
+fn match_by_name_then_place<'a, T>(old: &'a [T], new: &'a [T],
+                                   name: impl Fn(&T) -> &String,
+                                   same_place: impl Fn(&T, &T) -> bool)
+                                   -> Vec<(Option<&'a T>, Option<&'a T>)> {
+    let mut matched = vec![false; new.len()];
+    let mut pairs: Vec<(Option<&T>, Option<&T>)> = old.iter()
+        .map(|o| {
+            let found = new.iter().enumerate()
+                .find(|(i, n)| !matched[*i] && name(n) == name(o))
+                .map(|(i, _)| i);
+            if let Some(i) = found {
+                matched[i] = true;
+            }
+            (Some(o), found.map(|i| &new[i]))
+        })
+        .collect();
+    // What is left of both maps has names that are only in one of them
+    for pair in pairs.iter_mut().filter(|(_, n)| n.is_none()) {
+        let old = pair.0.unwrap();
+        let found = new.iter().enumerate()
+            .find(|(i, n)| !matched[*i] && same_place(old, n))
+            .map(|(i, _)| i);
+        if let Some(i) = found {
+            matched[i] = true;
+            pair.1 = Some(&new[i]);
+        }
+    }
+    pairs.extend(new.iter().enumerate()
+                 .filter(|(i, _)| !matched[*i])
+                 .map(|(_, n)| (None, Some(n))));
+    pairs
+}
+
+fn offset_text(register: &RegisterSpec) -> String {
+    match (register.offset_value(), &register.offset) {
+        (Some(offset), _) => format!("0x{:x}", offset),
+        (None, Some(offset)) => offset.clone(),
+        (None, None) => "unmapped".to_string(),
+    }
+}
+
+fn reset_text(register: &RegisterSpec) -> String {
+    match (register.reset_value(), &register.reset) {
+        (Some(reset), _) => format!("0x{:x}", reset),
+        (None, reset) => reset.clone().unwrap_or_default(),
+    }
+}
+
+fn access_of(access: &Option<String>) -> String {
+    normalize_access(access.as_deref().unwrap_or("rw"))
+}
+
+/// The differences between the register maps of the devices `old` and
+/// `new`, bank by bank in the order of `old`, followed by added banks
+pub fn diff_register_maps(old: &DeviceSpec, new: &DeviceSpec)
+                          -> Vec<RegisterMapChange> {
+    let mut changes = vec![];
+    for old_bank in &old.banks {
+        let mut diff = BankDiff { bank: &old_bank.name, changes: vec![] };
+        match new.banks.iter().find(|bank| bank.name == old_bank.name) {
+            Some(new_bank) =>
+                diff.registers(&old_bank.registers, &new_bank.registers),
+            None => diff.push(ChangeKind::BankRemoved, None, None, None, None),
+        }
+        changes.append(&mut diff.changes);
+    }
+    for new_bank in &new.banks {
+        if !old.banks.iter().any(|bank| bank.name == new_bank.name) {
+            let mut diff = BankDiff { bank: &new_bank.name, changes: vec![] };
+            diff.push(ChangeKind::BankAdded, None, None, None, None);
+            changes.append(&mut diff.changes);
+        }
+    }
+    changes
+}
+
+/// The number of changes of each kind
+pub fn change_summary(changes: &[RegisterMapChange])
+                      -> BTreeMap<ChangeKind, usize> {
+    let mut summary = BTreeMap::new();
+    for change in changes {
+        *summary.entry(change.kind).or_insert(0) += 1;
+    }
+    summary
+}
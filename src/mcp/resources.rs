@@ -0,0 +1,51 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Resources served by the MCP server
+
+use serde::{Deserialize, Serialize};
+
+use crate::mcp::schema::{device_spec_schema, DEVICE_SPEC_SCHEMA_URI};
+
+/// Resource definition for MCP
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResourceDefinition {
+    pub uri: String,
+    pub name: String,
+    pub description: String,
+    #[serde(rename = "mimeType")]
+    pub mime_type: String,
+}
+
+/// The contents of a resource
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResourceContents {
+    pub uri: String,
+    #[serde(rename = "mimeType")]
+    pub mime_type: String,
+    pub text: String,
+}
+
+/// List the resources of the server
+pub fn list_resources() -> Vec<ResourceDefinition> {
+    vec![ResourceDefinition {
+        uri: DEVICE_SPEC_SCHEMA_URI.to_string(),
+        name: "Device specification schema".to_string(),
+        description: "JSON Schema of the device specifications accepted by \
+                      the generation tools, as `device_spec` or in a \
+                      `spec_file`".to_string(),
+        mime_type: "application/schema+json".to_string(),
+    }]
+}
+
+/// Read a resource, `None` if the server has no resource at `uri`
+pub fn read_resource(uri: &str) -> Option<ResourceContents> {
+    match uri {
+        DEVICE_SPEC_SCHEMA_URI => Some(ResourceContents {
+            uri: uri.to_string(),
+            mime_type: "application/schema+json".to_string(),
+            text: serde_json::to_string_pretty(device_spec_schema())
+                .expect("Device specification schema is valid JSON"),
+        }),
+        _ => None,
+    }
+}
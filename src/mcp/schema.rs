@@ -0,0 +1,50 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Published JSON Schema of device specifications
+//!
+//! The schema is derived from `DeviceSpec` and the types it is built from,
+//! served as an MCP resource, and used to check every specification that a
+//! tool accepts, so that other toolchains can produce specifications that
+//! are known to be accepted.
+
+use anyhow::{anyhow, Result};
+use jsonschema::JSONSchema;
+use lazy_static::lazy_static;
+use schemars::schema_for;
+use serde_json::Value;
+
+use crate::mcp::generation::DeviceSpec;
+
+/// Resource URI the device specification schema is served at
+pub const DEVICE_SPEC_SCHEMA_URI: &str = "dml://schema/device-spec.json";
+
+lazy_static! {
+    static ref DEVICE_SPEC_SCHEMA: Value =
+        serde_json::to_value(schema_for!(DeviceSpec))
+        .expect("Device specification schema is valid JSON");
+    static ref DEVICE_SPEC_VALIDATOR: JSONSchema =
+        JSONSchema::compile(&DEVICE_SPEC_SCHEMA)
+        .expect("Device specification schema is a valid JSON Schema");
+}
+
+/// The JSON Schema of `DeviceSpec`
+pub fn device_spec_schema() -> &'static Value {
+    &DEVICE_SPEC_SCHEMA
+}
+
+/// Check a device specification against the schema, reporting every
+/// violation with the JSON pointer of the offending value
+pub fn validate_device_spec(spec: &Value) -> Result<()> {
+    let violations = match DEVICE_SPEC_VALIDATOR.validate(spec) {
+        Ok(()) => return Ok(()),
+        Err(errors) => errors.map(|error| {
+            let path = error.instance_path.to_string();
+            if path.is_empty() {
+                error.to_string()
+            } else {
+                format!("{}: {}", path, error)
+            }
+        }).collect::<Vec<_>>(),
+    };
+    Err(anyhow!("{}", violations.join("; ")))
+}
@@ -4,10 +4,21 @@ use anyhow::{anyhow, Result};
 use log::{debug, error, info, warn};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt,
+                BufReader};
 
-use crate::mcp::{ServerCapabilities, ServerInfo, MCP_VERSION};
+use crate::mcp::audit::{AuditLog, AuditRecord};
+use crate::mcp::{negotiate_version, ServerCapabilities, ServerInfo};
+use crate::mcp::resources::{list_resources, read_resource};
+use crate::mcp::tool_policy::ToolPolicy;
 use crate::mcp::tools::ToolRegistry;
+use crate::mcp::truncation::{limit_result, ResultStore,
+                             DEFAULT_MAX_RESULT_BYTES};
+use crate::project::ProjectStore;
 
 /// MCP JSON-RPC message
 #[derive(Debug, Serialize, Deserialize)]
@@ -33,11 +44,88 @@ pub struct JsonRpcError {
     pub data: Option<Value>,
 }
 
+/// How far a client has come in the initialization handshake
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Initialization {
+    /// No initialize request has been handled
+    Pending,
+    /// The initialize request has been responded to, but the client has
+    /// not sent the initialized notification
+    Responded,
+    /// The client has sent the initialized notification
+    Complete,
+}
+
+/// The state of the session with a client. The server has one for the
+/// client on stdio, and a transport serving several clients keeps one for
+/// each of them, so that what one client sets up is not seen by the others.
+#[derive(Debug)]
+pub struct Session {
+    initialization: Initialization,
+    /// The protocol version agreed on in the initialize request
+    protocol_version: Option<&'static str>,
+    /// Whether the client has asked the server to shut down
+    shutdown_requested: bool,
+    /// Whether the client can list its roots
+    client_roots: bool,
+    /// The id of the last request sent to the client
+    last_request_id: u64,
+    /// The id of the roots/list request the client has yet to respond to
+    roots_request: Option<Value>,
+    /// The root directories of the client
+    roots: Vec<PathBuf>,
+    /// The project of the first root of the client, which gives the
+    /// defaults of its tool calls instead of the project of the server
+    project: Option<Arc<ProjectStore>>,
+    /// The full results of the tool calls of the client that were truncated
+    results: ResultStore,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Session {
+            initialization: Initialization::Pending,
+            protocol_version: None,
+            shutdown_requested: false,
+            client_roots: false,
+            last_request_id: 0,
+            roots_request: None,
+            roots: vec![],
+            project: None,
+            results: ResultStore::default(),
+        }
+    }
+}
+
+impl Session {
+    /// Whether the client has asked the server to shut down
+    pub fn shutdown_requested(&self) -> bool {
+        self.shutdown_requested
+    }
+    
+    /// The root directories the client has listed
+    pub fn roots(&self) -> &[PathBuf] {
+        &self.roots
+    }
+}
+
+/// The number of tools or resources listed per page, by default
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+
+/// How long the request being handled may take to finish when the server is
+/// asked to shut down, by default
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
 /// DML MCP Server
 pub struct DMLMCPServer {
     tool_registry: ToolRegistry,
     server_info: ServerInfo,
     capabilities: ServerCapabilities,
+    session: Mutex<Session>,
+    shutdown_timeout: Duration,
+    audit_log: Option<AuditLog>,
+    page_size: usize,
+    max_result_bytes: usize,
 }
 
 impl DMLMCPServer {
@@ -51,37 +139,130 @@ impl DMLMCPServer {
             tool_registry,
             server_info: ServerInfo::default(),
             capabilities: ServerCapabilities::default(),
+            session: Mutex::new(Session::default()),
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            audit_log: None,
+            page_size: DEFAULT_PAGE_SIZE,
+            max_result_bytes: DEFAULT_MAX_RESULT_BYTES,
         })
     }
     
-    /// Run the MCP server
+    /// List `page_size` tools or resources per page, instead of
+    /// `DEFAULT_PAGE_SIZE`
+    pub fn with_page_size(mut self, page_size: usize) -> Self {
+        self.page_size = page_size.max(1);
+        self
+    }
+    
+    /// Truncate tool results with more than `max_bytes` of text, instead of
+    /// `DEFAULT_MAX_RESULT_BYTES`
+    pub fn with_max_result_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_result_bytes = max_bytes;
+        self
+    }
+    
+    /// Give the request being handled `timeout` to finish on shutdown,
+    /// instead of `DEFAULT_SHUTDOWN_TIMEOUT`
+    pub fn with_shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+    
+    /// How long the request being handled may take to finish on shutdown
+    pub fn shutdown_timeout(&self) -> Duration {
+        self.shutdown_timeout
+    }
+    
+    /// Only offer the tools `policy` allows
+    pub fn with_tool_policy(mut self, policy: &ToolPolicy) -> Result<Self> {
+        let disabled = self.tool_registry.apply_policy(policy)?;
+        if !disabled.is_empty() {
+            info!("Disabled tools: {}", disabled.join(", "));
+        }
+        Ok(self)
+    }
+    
+    /// Record every tool call in `audit_log`
+    pub fn with_audit_log(mut self, audit_log: AuditLog) -> Self {
+        self.audit_log = Some(audit_log);
+        self
+    }
+    
+    /// The protocol version agreed on with the client, once it has been
+    /// initialized
+    pub fn protocol_version(&self) -> Option<&'static str> {
+        self.session.lock().unwrap().protocol_version
+    }
+    
+    /// Run the MCP server on stdio, until stdin is closed, the client asks
+    /// it to shut down, or the process receives SIGINT or SIGTERM
     pub async fn run(&self) -> Result<()> {
         info!("Starting MCP server on stdio");
         
-        let stdin = tokio::io::stdin();
-        let mut stdout = tokio::io::stdout();
-        let mut reader = BufReader::new(stdin);
+        let reader = BufReader::new(tokio::io::stdin());
+        let stdout = tokio::io::stdout();
+        self.serve(reader, stdout, shutdown_signal()).await
+    }
+    
+    /// Serve the messages read from `reader`, writing the responses to
+    /// `writer`, until the input ends, a shutdown request has been responded
+    /// to, or `shutdown` completes. A request being handled when `shutdown`
+    /// completes is given the shutdown timeout to finish and respond.
+    pub async fn serve<R, W>(&self, mut reader: R, mut writer: W,
+                             shutdown: impl Future<Output = &'static str>)
+                             -> Result<()>
+    where R: AsyncBufRead + Unpin, W: AsyncWrite + Unpin {
+        tokio::pin!(shutdown);
         let mut line = String::new();
         
         loop {
             line.clear();
-            match reader.read_line(&mut line).await {
+            let read = tokio::select! {
+                read = reader.read_line(&mut line) => read,
+                signal = &mut shutdown => {
+                    info!("Received {}, shutting down", signal);
+                    break;
+                }
+            };
+            match read {
                 Ok(0) => {
                     debug!("EOF reached, shutting down");
                     break;
                 }
                 Ok(_) => {
-                    if let Err(e) = self.handle_message(&line, &mut stdout).await {
+                    let handled = self.handle_message(&line, &mut writer);
+                    tokio::pin!(handled);
+                    let result = tokio::select! {
+                        result = &mut handled => result,
+                        signal = &mut shutdown => {
+                            info!("Received {}, finishing the current request",
+                                  signal);
+                            match tokio::time::timeout(self.shutdown_timeout,
+                                                       handled).await {
+                                Ok(result) => result,
+                                Err(_) => Err(anyhow!(
+                                    "Request did not finish within {:?}",
+                                    self.shutdown_timeout)),
+                            }
+                        }
+                    };
+                    if let Err(e) = result {
                         error!("Error handling message: {}", e);
                     }
+                    if self.session.lock().unwrap().shutdown_requested {
+                        info!("Shutting down on request");
+                        break;
+                    }
                 }
                 Err(e) => {
-                    error!("Error reading from stdin: {}", e);
+                    error!("Error reading input: {}", e);
                     break;
                 }
             }
         }
         
+        writer.flush().await?;
+        info!("MCP server stopped");
         Ok(())
     }
     
@@ -89,7 +270,7 @@ impl DMLMCPServer {
     async fn handle_message(
         &self,
         line: &str,
-        stdout: &mut tokio::io::Stdout,
+        stdout: &mut (impl AsyncWrite + Unpin),
     ) -> Result<()> {
         let line = line.trim();
         if line.is_empty() {
@@ -98,14 +279,100 @@ impl DMLMCPServer {
         
         debug!("Received message: {}", line);
         
-        let message: JsonRpcMessage = serde_json::from_str(line)
+        let Some(response_json) = self.handle_line(line).await? else {
+            return Ok(());
+        };
+        debug!("Sending response: {}", response_json);
+        
+        stdout.write_all(response_json.as_bytes()).await?;
+        stdout.write_all(b"\n").await?;
+        stdout.flush().await?;
+        
+        Ok(())
+    }
+    
+    /// Handle a line of input from the client on stdio, see
+    /// `handle_session_line`
+    pub async fn handle_line(&self, line: &str) -> Result<Option<String>> {
+        self.handle_session_line(&self.session, line).await
+    }
+    
+    /// Handle a line of input in `session`, holding a single message or a
+    /// batch of them, and return the response to send back, if any. The
+    /// messages of a batch are handled in order, and their responses are sent
+    /// back together in the same order.
+    pub async fn handle_session_line(&self, session: &Mutex<Session>,
+                                     line: &str) -> Result<Option<String>> {
+        let value: Value = serde_json::from_str(line)
             .map_err(|e| anyhow!("Failed to parse JSON-RPC message: {}", e))?;
         
-        let response = match message.method.as_deref() {
-            Some("initialize") => self.handle_initialize(&message).await,
-            Some("tools/list") => self.handle_tools_list(&message).await,
-            Some("tools/call") => self.handle_tools_call(&message).await,
-            Some(method) => {
+        let Value::Array(batch) = value else {
+            let message: JsonRpcMessage = serde_json::from_value(value)
+                .map_err(|e| anyhow!("Failed to parse JSON-RPC message: {}", e))?;
+            return match self.handle_request(session, message).await {
+                Some(response) => Ok(Some(serde_json::to_string(&response)?)),
+                None => Ok(None),
+            };
+        };
+        
+        if batch.is_empty() {
+            let response = self.create_error_response(
+                None,
+                -32600,
+                "Invalid Request",
+                Some(json!({"details": "Empty batch"})),
+            );
+            return Ok(Some(serde_json::to_string(&response)?));
+        }
+        debug!("Handling batch of {} messages", batch.len());
+        let mut responses = vec![];
+        for entry in batch {
+            match serde_json::from_value::<JsonRpcMessage>(entry) {
+                Ok(message) => responses.extend(
+                    self.handle_request(session, message).await),
+                Err(e) => responses.push(self.create_error_response(
+                    None,
+                    -32600,
+                    "Invalid Request",
+                    Some(json!({"details": e.to_string()})),
+                )),
+            }
+        }
+        // A batch of only responses is not responded to
+        if responses.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::to_string(&responses)?))
+    }
+    
+    /// Handle a single message, returning the response to it if it is a
+    /// request
+    async fn handle_request(&self, session: &Mutex<Session>,
+                            message: JsonRpcMessage)
+                            -> Option<JsonRpcMessage> {
+        let Some(method) = message.method.as_deref() else {
+            self.handle_response(session, &message);
+            return None;
+        };
+        if message.id.is_none() {
+            return self.handle_notification(session, method);
+        }
+        if let Some(response) = self.check_initialized(session, &message, method) {
+            return Some(response);
+        }
+        let response = match method {
+            "initialize" => self.handle_initialize(session, &message).await,
+            "ping" => self.create_response(message.id.clone(), json!({})),
+            "shutdown" => {
+                info!("Client asked the server to shut down");
+                session.lock().unwrap().shutdown_requested = true;
+                self.create_response(message.id.clone(), json!({}))
+            }
+            "tools/list" => self.handle_tools_list(&message).await,
+            "tools/call" => self.handle_tools_call(session, &message).await,
+            "resources/list" => self.handle_resources_list(session, &message),
+            "resources/read" => self.handle_resources_read(session, &message),
+            method => {
                 warn!("Unknown method: {}", method);
                 self.create_error_response(
                     message.id,
@@ -114,41 +381,146 @@ impl DMLMCPServer {
                     None,
                 )
             }
-            None => {
-                // This might be a response to a request we sent
-                debug!("Received response/notification: {:?}", message);
-                return Ok(());
+        };
+        Some(response)
+    }
+    
+    /// Handle a notification, which is never responded to, returning the
+    /// request to send to the client in turn, if any
+    fn handle_notification(&self, session: &Mutex<Session>, method: &str)
+                           -> Option<JsonRpcMessage> {
+        let mut session = session.lock().unwrap();
+        match method {
+            "notifications/initialized" => {
+                if session.initialization == Initialization::Responded {
+                    info!("Client initialized");
+                    session.initialization = Initialization::Complete;
+                    return self.request_roots(&mut session);
+                }
+                warn!("Initialized notification in state {:?}",
+                      session.initialization);
             }
+            "notifications/roots/list_changed"
+                if session.initialization == Initialization::Complete =>
+                return self.request_roots(&mut session),
+            method => debug!("Ignoring notification: {}", method),
+        }
+        None
+    }
+    
+    /// A roots/list request to send to the client, if it can list its roots
+    fn request_roots(&self, session: &mut Session) -> Option<JsonRpcMessage> {
+        if !session.client_roots {
+            return None;
+        }
+        session.last_request_id += 1;
+        let id = json!(session.last_request_id);
+        session.roots_request = Some(id.clone());
+        Some(JsonRpcMessage {
+            jsonrpc: "2.0".to_string(),
+            id: Some(id),
+            method: Some("roots/list".to_string()),
+            params: None,
+            result: None,
+            error: None,
+        })
+    }
+    
+    /// Handle a response to a request sent to the client
+    fn handle_response(&self, session: &Mutex<Session>,
+                       message: &JsonRpcMessage) {
+        let mut session = session.lock().unwrap();
+        if message.id.is_none() || message.id != session.roots_request {
+            debug!("Ignoring response: {:?}", message);
+            return;
+        }
+        session.roots_request = None;
+        if let Some(error) = &message.error {
+            warn!("Client could not list its roots: {}", error.message);
+            return;
+        }
+        let roots: Vec<PathBuf> = message.result.as_ref()
+            .and_then(|result| result["roots"].as_array()).into_iter()
+            .flatten()
+            .filter_map(|root| root["uri"].as_str())
+            .filter_map(root_path)
+            .collect();
+        info!("Client roots: {:?}", roots);
+        session.project = roots.first()
+            .map(|root| Arc::new(ProjectStore::discover(root)));
+        session.roots = roots;
+    }
+    
+    /// An error response to a request that is not allowed yet: only
+    /// initialize, ping and shutdown are allowed before the initialize
+    /// request is responded to, and tools can only be called once the client
+    /// has sent the initialized notification
+    fn check_initialized(&self, session: &Mutex<Session>,
+                         message: &JsonRpcMessage, method: &str)
+                         -> Option<JsonRpcMessage> {
+        let initialization = session.lock().unwrap().initialization;
+        let allowed = match method {
+            "initialize" | "ping" | "shutdown" => true,
+            "tools/call" => initialization == Initialization::Complete,
+            _ => initialization != Initialization::Pending,
         };
-        
-        let response_json = serde_json::to_string(&response)?;
-        debug!("Sending response: {}", response_json);
-        
-        stdout.write_all(response_json.as_bytes()).await?;
-        stdout.write_all(b"\n").await?;
-        stdout.flush().await?;
-        
-        Ok(())
+        if allowed {
+            return None;
+        }
+        warn!("Rejecting {} before initialization", method);
+        let details = if initialization == Initialization::Pending {
+            "The initialize request must be sent first"
+        } else {
+            "The initialized notification must be sent first"
+        };
+        Some(self.create_error_response(
+            message.id.clone(),
+            -32600,
+            "Server not initialized",
+            Some(json!({"details": details})),
+        ))
     }
     
     /// Handle initialize request
-    async fn handle_initialize(&self, message: &JsonRpcMessage) -> JsonRpcMessage {
+    async fn handle_initialize(&self, session: &Mutex<Session>,
+                               message: &JsonRpcMessage) -> JsonRpcMessage {
         info!("Handling initialize request");
         
+        let Some(requested) = message.params.as_ref()
+            .and_then(|params| params["protocolVersion"].as_str()) else {
+                return self.create_error_response(
+                    message.id.clone(),
+                    -32602,
+                    "Invalid params",
+                    Some(json!({"details": "Missing protocolVersion for initialize"})),
+                );
+            };
+        let mut session = session.lock().unwrap();
+        if session.initialization != Initialization::Pending {
+            return self.create_error_response(
+                message.id.clone(),
+                -32600,
+                "Invalid Request",
+                Some(json!({"details": "The server is already initialized"})),
+            );
+        }
+        let version = negotiate_version(requested);
+        if version != requested {
+            info!("Client asked for protocol version {}, using {}",
+                  requested, version);
+        }
+        session.initialization = Initialization::Responded;
+        session.protocol_version = Some(version);
+        session.client_roots = message.params.as_ref()
+            .is_some_and(|params| params["capabilities"]["roots"].is_object());
+        
         let result = json!({
-            "protocolVersion": MCP_VERSION,
+            "protocolVersion": version,
             "capabilities": self.capabilities,
             "serverInfo": self.server_info
         });
         
-        JsonRpcMessage {
-            jsonrpc: "2.0".to_string(),
-            id: message.id.clone(),
-            method: None,
-            params: None,
-            result: Some(result),
-            error: None,
-        }
+        self.create_response(message.id.clone(), result)
     }
     
     /// Handle tools/list request
@@ -156,33 +528,69 @@ impl DMLMCPServer {
         debug!("Handling tools/list request");
         
         let tools = self.tool_registry.list_tools();
-        let result = json!({
-            "tools": tools
+        self.paginated_response(message, "tools", tools)
+    }
+    
+    /// A response with the page of `items` starting at the cursor of the
+    /// request, as the list `key`, and the cursor of the next page if there
+    /// is one. The cursor is the offset of the page in the list.
+    fn paginated_response<T: Serialize>(&self, message: &JsonRpcMessage,
+                                        key: &str, items: Vec<T>)
+                                        -> JsonRpcMessage {
+        let cursor = message.params.as_ref()
+            .and_then(|params| params.get("cursor"))
+            .filter(|cursor| !cursor.is_null());
+        let start = match cursor.map(|cursor| cursor.as_str()
+                                     .and_then(|cursor| cursor.parse().ok())) {
+            None => 0,
+            Some(Some(start)) if start <= items.len() => start,
+            Some(_) => return self.create_error_response(
+                message.id.clone(),
+                -32602,
+                "Invalid params",
+                Some(json!({"details": format!("Invalid cursor: {}",
+                                               cursor.unwrap())})),
+            ),
+        };
+        let end = (start + self.page_size).min(items.len());
+        let mut result = json!({
+            key: items[start..end],
         });
-        
-        JsonRpcMessage {
-            jsonrpc: "2.0".to_string(),
-            id: message.id.clone(),
-            method: None,
-            params: None,
-            result: Some(result),
-            error: None,
+        if end < items.len() {
+            result["nextCursor"] = json!(end.to_string());
         }
+        self.create_response(message.id.clone(), result)
     }
     
     /// Handle tools/call request
-    async fn handle_tools_call(&self, message: &JsonRpcMessage) -> JsonRpcMessage {
+    async fn handle_tools_call(&self, session: &Mutex<Session>,
+                               message: &JsonRpcMessage) -> JsonRpcMessage {
         debug!("Handling tools/call request");
         
         match &message.params {
             Some(params) => {
-                match self.tool_registry.call_tool(params).await {
+                let project = session.lock().unwrap().project.clone();
+                let project = project.as_deref()
+                    .unwrap_or(self.tool_registry.project())
+                    .current();
+                let started = Instant::now();
+                let result = self.tool_registry.call_tool_in(params, &project)
+                    .await;
+                if let Some(audit_log) = &self.audit_log {
+                    audit_log.record(&AuditRecord::of_call(
+                        params, started.elapsed(), &result));
+                }
+                match result {
                     Ok(result) => JsonRpcMessage {
                         jsonrpc: "2.0".to_string(),
                         id: message.id.clone(),
                         method: None,
                         params: None,
-                        result: Some(result),
+                        result: Some(limit_result(
+                            result,
+                            params["name"].as_str().unwrap_or_default(),
+                            self.max_result_bytes,
+                            &session.lock().unwrap().results)),
                         error: None,
                     },
                     Err(e) => {
@@ -205,6 +613,63 @@ impl DMLMCPServer {
         }
     }
     
+    /// Handle resources/list request
+    fn handle_resources_list(&self, session: &Mutex<Session>,
+                             message: &JsonRpcMessage) -> JsonRpcMessage {
+        debug!("Handling resources/list request");
+
+        let mut resources = list_resources();
+        resources.extend(session.lock().unwrap().results.list());
+        self.paginated_response(message, "resources", resources)
+    }
+
+    /// Handle resources/read request
+    fn handle_resources_read(&self, session: &Mutex<Session>,
+                             message: &JsonRpcMessage) -> JsonRpcMessage {
+        debug!("Handling resources/read request");
+
+        let Some(uri) = message.params.as_ref()
+            .and_then(|params| params["uri"].as_str()) else {
+                return self.create_error_response(
+                    message.id.clone(),
+                    -32602,
+                    "Invalid params",
+                    Some(json!({"details": "Missing uri for resources/read"})),
+                );
+            };
+        let contents = read_resource(uri)
+            .or_else(|| session.lock().unwrap().results.read(uri));
+        match contents {
+            Some(contents) => JsonRpcMessage {
+                jsonrpc: "2.0".to_string(),
+                id: message.id.clone(),
+                method: None,
+                params: None,
+                result: Some(json!({"contents": [contents]})),
+                error: None,
+            },
+            None => self.create_error_response(
+                message.id.clone(),
+                -32002,
+                "Resource not found",
+                Some(json!({"uri": uri})),
+            ),
+        }
+    }
+
+    /// Create response
+    fn create_response(&self, id: Option<Value>, result: Value)
+                       -> JsonRpcMessage {
+        JsonRpcMessage {
+            jsonrpc: "2.0".to_string(),
+            id,
+            method: None,
+            params: None,
+            result: Some(result),
+            error: None,
+        }
+    }
+
     /// Create error response
     fn create_error_response(
         &self,
@@ -226,4 +691,37 @@ impl DMLMCPServer {
             }),
         }
     }
-}
\ No newline at end of file
+}
+
+/// The directory of a `file://` root URI of a client
+fn root_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    // Local files have an empty authority, or localhost
+    let path = path.strip_prefix("localhost").unwrap_or(path);
+    let path = urlencoding::decode(path).ok()?;
+    // Remove the leading slash of a drive letter on Windows
+    Some(PathBuf::from(if cfg!(windows) {
+        path.strip_prefix('/').unwrap_or(&path)
+    } else {
+        &path
+    }))
+}
+
+/// Wait for SIGINT or, on Unix, SIGTERM, returning the name of the signal
+pub async fn shutdown_signal() -> &'static str {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        if let Ok(mut terminate) = signal(SignalKind::terminate()) {
+            return tokio::select! {
+                Ok(()) = tokio::signal::ctrl_c() => "SIGINT",
+                _ = terminate.recv() => "SIGTERM",
+            };
+        }
+    }
+    if tokio::signal::ctrl_c().await.is_err() {
+        warn!("Could not listen for SIGINT");
+        std::future::pending::<()>().await;
+    }
+    "SIGINT"
+}
@@ -0,0 +1,127 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Semantic checks of device specifications
+//!
+//! The schema only checks the shape of a specification. These checks find
+//! specifications that are well-formed but would generate broken or poorly
+//! documented devices, so that they can be fixed before generating code.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use crate::mcp::generation::{template_of_access, DeviceSpec, GenerationConfig};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize,
+         Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FindingSeverity {
+    Error,
+    Warning,
+    Info,
+}
+
+/// A problem with a specification
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpecFinding {
+    pub severity: FindingSeverity,
+    /// Kind of problem, e.g. "duplicate-register"
+    pub code: String,
+    /// JSON pointer to the offending value, e.g. "/banks/0/registers/2"
+    pub path: String,
+    pub message: String,
+}
+
+impl SpecFinding {
+    fn new(severity: FindingSeverity, code: &str, path: String,
+           message: String) -> Self {
+        SpecFinding { severity, code: code.to_string(), path, message }
+    }
+}
+
+/// The problems of a specification that the generator would not report,
+/// in the order of the specification. Missing documentation is only
+/// reported if `config` generates documentation.
+pub fn lint_device_spec(spec: &DeviceSpec, config: &GenerationConfig)
+                        -> Vec<SpecFinding> {
+    let mut findings = vec![];
+    let undocumented = |path: String, kind: &str, name: &str,
+                        documentation: &Option<String>,
+                        findings: &mut Vec<SpecFinding>| {
+        if config.generate_docs && documentation.is_none() {
+            findings.push(SpecFinding::new(
+                FindingSeverity::Info, "missing-documentation", path,
+                format!("{} {} has no documentation", kind, name)));
+        }
+    };
+    undocumented(String::new(), "Device", &spec.name, &spec.documentation,
+                 &mut findings);
+    for (b, bank) in spec.banks.iter().enumerate() {
+        let bank_path = format!("/banks/{}", b);
+        undocumented(bank_path.clone(), "Bank", &bank.name,
+                     &bank.documentation, &mut findings);
+        if bank.registers.is_empty() {
+            findings.push(SpecFinding::new(
+                FindingSeverity::Warning, "empty-bank", bank_path.clone(),
+                format!("Bank {} has no registers", bank.name)));
+        }
+        let mut register_names = HashSet::new();
+        for (r, register) in bank.registers.iter().enumerate() {
+            let path = format!("{}/registers/{}", bank_path, r);
+            if !register_names.insert(&register.name) {
+                findings.push(SpecFinding::new(
+                    FindingSeverity::Error, "duplicate-register", path.clone(),
+                    format!("Register {} is declared more than once in bank {}",
+                            register.name, bank.name)));
+            }
+            undocumented(path.clone(), "Register", &register.name,
+                         &register.documentation, &mut findings);
+            if let Some(access) = &register.access {
+                if template_of_access(access).is_err() {
+                    findings.push(SpecFinding::new(
+                        FindingSeverity::Error, "unknown-access",
+                        format!("{}/access", path),
+                        format!("Unknown access type '{}' of register {}",
+                                access, register.name)));
+                }
+            }
+            let mut field_names = HashSet::new();
+            for (f, field) in register.fields.iter().enumerate() {
+                let path = format!("{}/fields/{}", path, f);
+                if !field_names.insert(&field.name) {
+                    findings.push(SpecFinding::new(
+                        FindingSeverity::Error, "duplicate-field", path.clone(),
+                        format!("Field {} is declared more than once in \
+                                 register {}", field.name, register.name)));
+                }
+                undocumented(path.clone(), "Field", &field.name,
+                             &field.documentation, &mut findings);
+                match field.bit_range() {
+                    Some((msb, _)) if msb >= register.size * 8 =>
+                        findings.push(SpecFinding::new(
+                            FindingSeverity::Error, "field-too-wide",
+                            format!("{}/bits", path),
+                            format!("Field {} [{}] does not fit in the {} \
+                                     bits of register {}", field.name,
+                                    field.bits, register.size * 8,
+                                    register.name))),
+                    Some(_) => (),
+                    None => findings.push(SpecFinding::new(
+                        FindingSeverity::Error, "invalid-bits",
+                        format!("{}/bits", path),
+                        format!("Bits '{}' of field {} are not a bit or a \
+                                 'msb:lsb' range", field.bits, field.name))),
+                }
+                if let Some(access) = &field.access {
+                    if template_of_access(access).is_err() {
+                        findings.push(SpecFinding::new(
+                            FindingSeverity::Error, "unknown-access",
+                            format!("{}/access", path),
+                            format!("Unknown access type '{}' of field {}",
+                                    access, field.name)));
+                    }
+                }
+            }
+        }
+    }
+    findings
+}
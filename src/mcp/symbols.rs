@@ -0,0 +1,350 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Symbols declared in DML files, for tools that locate code to edit
+//!
+//! Symbols are collected from the structure of each file on its own, without
+//! resolving imports or instantiating templates, so each symbol is found
+//! where it is declared rather than in the objects it ends up in.
+
+use anyhow::{anyhow, Result};
+use log::warn;
+use rayon::prelude::*;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::analysis::parsing::tree::ZeroSpan;
+use crate::analysis::scope::{ContextKey, Scope, SubSymbol, SymbolContext};
+use crate::analysis::structure::toplevel::TopLevel;
+use crate::analysis::symbols::{DMLSymbolKind, StructureSymbol};
+use crate::analysis::{DeclarationSpan, LocationSpan, Named};
+
+use super::analysis::{parse_dml_file, ParsedDML, SourceRange};
+use super::generation::{normalize_access, DeviceSpec};
+use super::workspace::find_dml_files;
+
+/// The kinds of symbols, as written in DML
+pub const SYMBOL_KINDS: &[&str] = &[
+    "attribute", "bank", "connect", "device", "event", "field", "group",
+    "implement", "interface", "port", "register", "subdevice", "param",
+    "constant", "extern", "hook", "local", "loggroup", "method", "argument",
+    "saved", "session", "template", "typedef",
+];
+
+/// Kinds only searched for when asked for, as they are rarely what an edit
+/// is looking for and outnumber the others
+const LOCAL_KINDS: &[&str] = &["local", "argument"];
+
+/// A symbol declared in a DML file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SymbolDeclaration {
+    pub name: String,
+    pub kind: &'static str,
+    /// Dotted path of the objects, methods and templates the symbol is
+    /// declared in, e.g. `regs.control` for a field of register `control`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container: Option<String>,
+    pub file: PathBuf,
+    /// The whole declaration
+    pub range: SourceRange,
+    /// The name in the declaration
+    pub selection_range: SourceRange,
+}
+
+impl SymbolDeclaration {
+    fn new(name: String, kind: DMLSymbolKind, container: Option<&str>,
+           span: &ZeroSpan, loc: &ZeroSpan) -> Self {
+        SymbolDeclaration {
+            name,
+            kind: kind.kind_name(),
+            container: container.map(String::from),
+            file: loc.path(),
+            range: SourceRange::from(span),
+            selection_range: SourceRange::from(loc),
+        }
+    }
+
+    /// The name of the symbol with its container, e.g. `regs.control.enable`
+    pub fn qualified_name(&self) -> String {
+        match &self.container {
+            Some(container) => format!("{}.{}", container, self.name),
+            None => self.name.clone(),
+        }
+    }
+}
+
+/// The name of a context in the containers of the symbols declared in it
+fn context_name(context: &SymbolContext) -> String {
+    match &context.context {
+        ContextKey::AllWithTemplate(_, templates) =>
+            format!("in each ({})", templates.join(", ")),
+        _ => context.get_name(),
+    }
+}
+
+fn collect_symbols(context: &SymbolContext, container: Option<&str>,
+                   symbols: &mut Vec<SymbolDeclaration>) {
+    // Objects and methods are both symbols and contexts of their own, and
+    // are only collected as the latter
+    let contexts: HashSet<ZeroSpan> = context.subsymbols.iter()
+        .filter(|sub| matches!(sub, SubSymbol::Context(_)))
+        .map(|sub| *sub.loc_span())
+        .collect();
+    for sub in &context.subsymbols {
+        match sub {
+            SubSymbol::Context(sub) => {
+                if let Some(kind) = sub.context.kind() {
+                    symbols.push(SymbolDeclaration::new(
+                        sub.get_name(), kind, container, sub.span(),
+                        sub.loc_span()));
+                }
+                let name = context_name(sub);
+                let inner = match container {
+                    Some(container) => format!("{}.{}", container, name),
+                    None => name,
+                };
+                collect_symbols(sub, Some(&inner), symbols);
+            },
+            SubSymbol::Simple(simple) => {
+                if !contexts.contains(simple.loc_span()) {
+                    symbols.push(SymbolDeclaration::new(
+                        simple.get_name(), simple.kind(), container,
+                        simple.loc_span(), simple.loc_span()));
+                }
+            },
+        }
+    }
+}
+
+/// The symbols declared in a file, in the order they are declared in
+pub fn declared_symbols(parsed: &ParsedDML) -> Vec<SymbolDeclaration> {
+    toplevel_symbols(&parsed.toplevel)
+}
+
+fn toplevel_symbols(toplevel: &TopLevel) -> Vec<SymbolDeclaration> {
+    let mut symbols = vec![];
+    collect_symbols(&toplevel.to_context(), None, &mut symbols);
+    symbols.sort_by_key(|symbol| (symbol.selection_range.start_line,
+                                  symbol.selection_range.start_column));
+    symbols
+}
+
+/// The declarations in a file of the symbol with the dotted name `name`,
+/// e.g. `regs.control.enable`, which may start with the name of the device.
+/// Files without a device declaration take any name of a device.
+pub fn declarations_named(toplevel: &TopLevel, name: &str)
+                          -> Vec<SymbolDeclaration> {
+    let without_device = name.split_once('.')
+        .filter(|(device, _)| toplevel.device.as_ref()
+                .is_none_or(|declared| declared.name.val == *device))
+        .map(|(_, name)| name);
+    toplevel_symbols(toplevel).into_iter()
+        .filter(|declaration| {
+            let qualified = declaration.qualified_name();
+            qualified == name || Some(qualified.as_str()) == without_device
+        })
+        .collect()
+}
+
+/// Apply `f` to each DML file under `root` in parallel, and collect what it
+/// returns file by file
+fn collect_workspace<T: Send>(root: &Path,
+                              f: impl Fn(&ParsedDML) -> Vec<T> + Sync)
+                              -> Result<Vec<T>> {
+    if !root.is_dir() {
+        return Err(anyhow!("Workspace root {} is not a directory",
+                           root.display()));
+    }
+    let paths: Vec<PathBuf> = find_dml_files(root);
+    Ok(paths.par_iter()
+       .filter_map(|path| match parse_dml_file(path) {
+           Ok(parsed) => Some(f(&parsed)),
+           Err(e) => {
+               warn!("Skipping {} in symbol search: {}", path.display(), e);
+               None
+           },
+       })
+       .flatten()
+       .collect())
+}
+
+/// The symbols declared in the DML files under `root`, file by file
+pub fn workspace_symbols(root: &Path) -> Result<Vec<SymbolDeclaration>> {
+    collect_workspace(root, declared_symbols)
+}
+
+/// Check that each of `kinds` is one of `SYMBOL_KINDS`
+pub fn check_symbol_kinds(kinds: &[String]) -> Result<()> {
+    match kinds.iter().find(|kind| !SYMBOL_KINDS.contains(&kind.as_str())) {
+        Some(kind) => Err(anyhow!("Unknown symbol kind '{}'; the kinds are {}",
+                                  kind, SYMBOL_KINDS.join(", "))),
+        None => Ok(()),
+    }
+}
+
+/// How well a symbol matches a query, lower being better, or `None` if it
+/// does not. A query with a dot is matched against the end of the
+/// qualified name of the symbol, other queries against its name; exact
+/// matches come first, then those ignoring case, then prefixes and last
+/// names merely containing the query.
+fn match_rank(query: &str, symbol: &SymbolDeclaration) -> Option<u8> {
+    if query.is_empty() {
+        return Some(0);
+    }
+    let name = if query.contains('.') {
+        let qualified = symbol.qualified_name();
+        match qualified.len().checked_sub(query.len())
+            .and_then(|start| qualified.get(start..)) {
+            Some(end) if end.eq_ignore_ascii_case(query) => end.to_string(),
+            _ => return None,
+        }
+    } else {
+        symbol.name.clone()
+    };
+    let (lower, query_lower) = (name.to_lowercase(), query.to_lowercase());
+    if name == query {
+        Some(0)
+    } else if lower == query_lower {
+        Some(1)
+    } else if lower.starts_with(&query_lower) {
+        Some(2)
+    } else if lower.contains(&query_lower) {
+        Some(3)
+    } else {
+        None
+    }
+}
+
+/// The symbols matching `query` whose kind is one of `kinds`, best matches
+/// first. Without kinds, all symbols but locals and method arguments are
+/// searched. An empty query matches every symbol.
+pub fn search_symbols(symbols: Vec<SymbolDeclaration>, query: &str,
+                      kinds: &[String]) -> Vec<SymbolDeclaration> {
+    let wanted = |kind: &str| if kinds.is_empty() {
+        !LOCAL_KINDS.contains(&kind)
+    } else {
+        kinds.iter().any(|wanted| wanted == kind)
+    };
+    let mut found: Vec<(u8, SymbolDeclaration)> = symbols.into_iter()
+        .filter(|symbol| wanted(symbol.kind))
+        .filter_map(|symbol| match_rank(query, &symbol)
+                    .map(|rank| (rank, symbol)))
+        .collect();
+    // Stable, so that symbols of the same rank stay in declaration order
+    found.sort_by_key(|(rank, _)| *rank);
+    found.into_iter().map(|(_, symbol)| symbol).collect()
+}
+
+/// A declaration with its source, and what the register map of its file
+/// has on it
+#[derive(Debug, Clone, Serialize)]
+pub struct SymbolDefinition {
+    #[serde(flatten)]
+    pub declaration: SymbolDeclaration,
+    /// The lines of the declaration
+    pub source: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub documentation: Option<String>,
+    /// Offset, size and access of registers, bits and access of fields,
+    /// and the like for banks and methods
+    #[serde(skip_serializing_if = "Value::is_null")]
+    pub properties: Value,
+}
+
+/// The lines of `text` a range spans
+fn source_lines(text: &str, range: &SourceRange) -> String {
+    text.split_inclusive('\n')
+        .skip(range.start_line as usize - 1)
+        .take((range.end_line - range.start_line) as usize + 1)
+        .collect()
+}
+
+/// The documentation and properties of a declaration in the register map
+/// of its file
+fn register_map_properties(spec: &DeviceSpec,
+                           declaration: &SymbolDeclaration)
+                           -> (Option<String>, Value) {
+    let path: Vec<&str> = declaration.container.as_deref()
+        .map(|container| container.split('.').collect())
+        .unwrap_or_default();
+    let bank = |name: &str| spec.banks.iter().find(|bank| bank.name == name);
+    let register = |bank_name: &str, name: &str| bank(bank_name)
+        .and_then(|bank| bank.registers.iter()
+                  .find(|register| register.name == name));
+    match (declaration.kind, &path[..]) {
+        ("bank", []) => match bank(&declaration.name) {
+            Some(bank) => (bank.documentation.clone(), json!({
+                "registers": bank.registers.len(),
+            })),
+            None => (None, Value::Null),
+        },
+        ("register", [bank_name, ..]) =>
+            match register(bank_name, &declaration.name) {
+                Some(register) => (register.documentation.clone(), json!({
+                    "offset": register.offset_value()
+                        .map(|offset| format!("0x{:x}", offset))
+                        .or_else(|| register.offset.clone()),
+                    "size": register.size,
+                    "access": normalize_access(
+                        register.access.as_deref().unwrap_or("rw")),
+                    "reset": register.reset_value()
+                        .map(|reset| format!("0x{:x}", reset))
+                        .or_else(|| register.reset.clone()),
+                    "dimensions": register.dimensions,
+                    "templates": register.templates,
+                    "fields": register.fields.iter()
+                        .map(|field| &field.name).collect::<Vec<_>>(),
+                })),
+                None => (None, Value::Null),
+            },
+        ("field", [bank_name, .., register_name]) =>
+            match register(bank_name, register_name).and_then(
+                |register| register.fields.iter()
+                    .find(|field| field.name == declaration.name)) {
+                Some(field) => (field.documentation.clone(), json!({
+                    "bits": field.bits,
+                    "msb": field.bit_range().map(|(msb, _)| msb),
+                    "lsb": field.bit_range().map(|(_, lsb)| lsb),
+                    "access": normalize_access(
+                        field.access.as_deref().unwrap_or("rw")),
+                    "templates": field.templates,
+                })),
+                None => (None, Value::Null),
+            },
+        _ => (None, Value::Null),
+    }
+}
+
+/// The declarations in a file of the symbol with the dotted name `name`,
+/// see `declarations_named`, with their sources and properties
+pub fn symbol_definitions(parsed: &ParsedDML, name: &str)
+                          -> Vec<SymbolDefinition> {
+    let declarations = declarations_named(&parsed.toplevel, name);
+    if declarations.is_empty() {
+        return vec![];
+    }
+    let spec = parsed.to_device_spec();
+    declarations.into_iter()
+        .map(|declaration| {
+            let (documentation, mut properties) =
+                register_map_properties(&spec, &declaration);
+            let source = source_lines(&parsed.text, &declaration.range);
+            if declaration.kind == "method" {
+                let signature = source.split('{').next().unwrap_or_default();
+                properties = json!({
+                    "signature": signature.split_whitespace()
+                        .collect::<Vec<_>>().join(" "),
+                });
+            }
+            SymbolDefinition { declaration, source, documentation, properties }
+        })
+        .collect()
+}
+
+/// The declarations of a symbol in the DML files under `root`, see
+/// `symbol_definitions`
+pub fn workspace_definitions(root: &Path, name: &str)
+                             -> Result<Vec<SymbolDefinition>> {
+    collect_workspace(root, |parsed| symbol_definitions(parsed, name))
+}
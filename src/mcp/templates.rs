@@ -2,8 +2,9 @@
 
 use anyhow::Result;
 use std::collections::HashMap;
+use std::convert::TryFrom;
 
-use super::generation::{DeviceSpec, BankSpec, RegisterSpec, FieldSpec, MethodSpec, ParameterSpec, InterfaceSpec};
+use super::generation::{DeviceSpec, BankSpec, RegisterSpec, FieldSpec, MethodSpec, ParameterSpec, InterfaceSpec, ArrayDimension, ConnectSpec, VariableSpec, EventSpec, PortSpec};
 
 /// Built-in DML templates and patterns
 pub struct DMLTemplates;
@@ -21,11 +22,20 @@ impl DMLTemplates {
         DeviceSpec {
             name: name.to_string(),
             base_template,
+            templates: vec![],
             documentation: Some(format!("Generated {} device", device_type)),
             banks: vec![],
             interfaces: vec![],
+            connects: vec![],
+            ports: vec![],
+            variables: vec![],
+            events: vec![],
             methods: vec![],
             dependencies: vec![],
+            constants: vec![],
+            typedefs: vec![],
+            structs: vec![],
+            variants: vec![],
         }
     }
     
@@ -40,8 +50,11 @@ impl DMLTemplates {
             registers: vec![
                 RegisterSpec {
                     name: "control".to_string(),
+                    dimensions: vec![],
                     size: 4,
                     offset: Some("0x00".to_string()),
+                    reset: None,
+                    access: None,
                     documentation: Some("Control register".to_string()),
                     fields: vec![
                         FieldSpec {
@@ -49,20 +62,26 @@ impl DMLTemplates {
                             bits: "0".to_string(),
                             access: Some("rw".to_string()),
                             documentation: Some("Enable bit".to_string()),
+                            templates: vec![],
                         },
                         FieldSpec {
                             name: "reset".to_string(),
                             bits: "1".to_string(),
                             access: Some("rw".to_string()),
                             documentation: Some("Reset bit".to_string()),
+                            templates: vec![],
                         },
                     ],
                     methods: vec![],
+                    templates: vec![],
                 },
                 RegisterSpec {
                     name: "status".to_string(),
+                    dimensions: vec![],
                     size: 4,
                     offset: Some("0x04".to_string()),
+                    reset: None,
+                    access: None,
                     documentation: Some("Status register".to_string()),
                     fields: vec![
                         FieldSpec {
@@ -70,22 +89,27 @@ impl DMLTemplates {
                             bits: "0".to_string(),
                             access: Some("ro".to_string()),
                             documentation: Some("Ready status".to_string()),
+                            templates: vec![],
                         },
                         FieldSpec {
-                            name: "error".to_string(),
+                            name: "err".to_string(),
                             bits: "1".to_string(),
                             access: Some("ro".to_string()),
                             documentation: Some("Error status".to_string()),
+                            templates: vec![],
                         },
                     ],
                     methods: vec![],
+                    templates: vec![],
                 },
             ],
+            reserved: vec![],
         });
         
         // Add standard interfaces
         device.interfaces.push(InterfaceSpec {
             name: "io_memory".to_string(),
+            methods: vec![],
         });
         
         device
@@ -101,8 +125,11 @@ impl DMLTemplates {
             registers: vec![
                 RegisterSpec {
                     name: "irq_enable".to_string(),
+                    dimensions: vec![],
                     size: 4,
                     offset: Some("0x00".to_string()),
+                    reset: None,
+                    access: None,
                     documentation: Some("Interrupt enable register".to_string()),
                     fields: vec![],
                     methods: vec![
@@ -111,7 +138,7 @@ impl DMLTemplates {
                             parameters: vec![
                                 ParameterSpec {
                                     name: "value".to_string(),
-                                    param_type: "uint32".to_string(),
+                                    param_type: "uint64".to_string(),
                                 }
                             ],
                             return_type: None,
@@ -119,28 +146,35 @@ impl DMLTemplates {
                             documentation: Some("Enable/disable interrupts".to_string()),
                         }
                     ],
+                    templates: vec![],
                 },
                 RegisterSpec {
                     name: "irq_pending".to_string(),
+                    dimensions: vec![],
                     size: 4,
                     offset: Some("0x04".to_string()),
+                    reset: None,
+                    access: None,
                     documentation: Some("Pending interrupts register".to_string()),
                     fields: vec![],
                     methods: vec![
                         MethodSpec {
                             name: "read".to_string(),
                             parameters: vec![],
-                            return_type: Some("uint32".to_string()),
+                            return_type: Some("uint64".to_string()),
                             body: Some("return pending_irqs;".to_string()),
                             documentation: Some("Read pending interrupts".to_string()),
                         }
                     ],
+                    templates: vec![],
                 },
             ],
+            reserved: vec![],
         });
         
         device.interfaces.push(InterfaceSpec {
             name: "signal".to_string(),
+            methods: vec![],
         });
         
         device.methods.push(MethodSpec {
@@ -173,8 +207,11 @@ impl DMLTemplates {
             registers: vec![
                 RegisterSpec {
                     name: "pc".to_string(),
+                    dimensions: vec![],
                     size: 8,
                     offset: Some("0x00".to_string()),
+                    reset: None,
+                    access: None,
                     documentation: Some("Program counter".to_string()),
                     fields: vec![],
                     methods: vec![
@@ -198,14 +235,16 @@ impl DMLTemplates {
                             documentation: Some("Write program counter".to_string()),
                         }
                     ],
+                    templates: vec![],
                 },
             ],
+            reserved: vec![],
         });
         
         device.interfaces.extend([
-            InterfaceSpec { name: "processor".to_string() },
-            InterfaceSpec { name: "cycle".to_string() },
-            InterfaceSpec { name: "execute".to_string() },
+            InterfaceSpec { name: "processor".to_string(), methods: vec![] },
+            InterfaceSpec { name: "cycle".to_string(), methods: vec![] },
+            InterfaceSpec { name: "execute".to_string(), methods: vec![] },
         ]);
         
         device.methods.extend([
@@ -236,10 +275,12 @@ impl DMLTemplates {
             name: "memory".to_string(),
             documentation: Some(format!("{}MB memory bank", size_mb)),
             registers: vec![], // Memory devices typically don't have registers
+            reserved: vec![],
         });
         
         device.interfaces.push(InterfaceSpec {
             name: "io_memory".to_string(),
+            methods: vec![],
         });
         
         device.methods.extend([
@@ -294,8 +335,11 @@ impl DMLTemplates {
             registers: vec![
                 RegisterSpec {
                     name: "bus_config".to_string(),
+                    dimensions: vec![],
                     size: 4,
                     offset: Some("0x00".to_string()),
+                    reset: None,
+                    access: None,
                     documentation: Some("Bus configuration register".to_string()),
                     fields: vec![
                         FieldSpec {
@@ -303,27 +347,1018 @@ impl DMLTemplates {
                             bits: "7:0".to_string(),
                             access: Some("rw".to_string()),
                             documentation: Some("Bus width".to_string()),
+                            templates: vec![],
                         },
                         FieldSpec {
                             name: "endian".to_string(),
                             bits: "8".to_string(),
                             access: Some("rw".to_string()),
                             documentation: Some("Endianness (0=little, 1=big)".to_string()),
+                            templates: vec![],
                         },
                     ],
                     methods: vec![],
+                    templates: vec![],
                 },
             ],
+            reserved: vec![],
         });
         
         device.interfaces.extend([
-            InterfaceSpec { name: "io_memory".to_string() },
-            InterfaceSpec { name: "signal".to_string() },
+            InterfaceSpec { name: "io_memory".to_string(), methods: vec![] },
+            InterfaceSpec { name: "signal".to_string(), methods: vec![] },
+        ]);
+        
+        device
+    }
+    
+    /// Get DMA controller template
+    ///
+    /// Each channel has its registers at a stride of 0x40, modeled as
+    /// register arrays indexed by the channel number.
+    pub fn dma_controller(name: &str, num_channels: u32) -> DeviceSpec {
+        let mut device = Self::basic_device(name, "peripheral");
+        device.documentation = Some(format!("DMA controller with {} channels", num_channels));
+        
+        let channel_register = |reg_name: &str, size: u64, offset: u64, doc: &str, fields: Vec<FieldSpec>| RegisterSpec {
+            name: reg_name.to_string(),
+            dimensions: vec![ArrayDimension {
+                index: "i".to_string(),
+                size: num_channels as u64,
+            }],
+            size,
+            offset: Some(format!("0x{:02x} + i * 0x40", offset)),
+            reset: None,
+            access: None,
+            documentation: Some(doc.to_string()),
+            fields,
+            methods: vec![],
+            templates: vec![],
+        };
+        
+        let mut control = channel_register("control", 4, 0x1c, "Channel control register", vec![
+            Self::field("start", "0", "rw", "Start a transfer"),
+            Self::field("irq_enable", "1", "rw", "Raise the completion interrupt when done"),
+            Self::field("chained", "2", "rw", "Fetch transfers from the descriptor chain"),
+        ]);
+        control.methods.push(Self::write_method("default(value);\n        if (start.val == 1) {\n            start_transfer(i);\n        }",
+            "Start a transfer when the start bit is set"));
+        
+        device.banks.push(BankSpec {
+            name: "regs".to_string(),
+            documentation: Some("DMA channel registers".to_string()),
+            registers: vec![
+                channel_register("source", 8, 0x00, "Source address", vec![]),
+                channel_register("destination", 8, 0x08, "Destination address", vec![]),
+                channel_register("descriptor", 8, 0x10, "Address of the next transfer descriptor", vec![]),
+                channel_register("length", 4, 0x18, "Transfer length in bytes", vec![]),
+                control,
+                channel_register("status", 4, 0x20, "Channel status register", vec![
+                    Self::field("done", "0", "w1c", "Transfer completed"),
+                    Self::field("failed", "1", "w1c", "Transfer failed"),
+                    Self::field("busy", "2", "ro", "Transfer in progress"),
+                ]),
+            ],
+            reserved: vec![],
+        });
+        
+        device.connects.extend([
+            ConnectSpec {
+                name: "target_mem".to_string(),
+                documentation: Some("Memory space accessed by transfers".to_string()),
+                interfaces: vec!["memory_space".to_string()],
+            },
+            ConnectSpec {
+                name: "irq".to_string(),
+                documentation: Some("Completion interrupt".to_string()),
+                interfaces: vec!["signal".to_string()],
+            },
+        ]);
+        
+        let channel_param = || vec![
+            ParameterSpec {
+                name: "channel".to_string(),
+                param_type: "int".to_string(),
+            }
+        ];
+        device.methods.extend([
+            MethodSpec {
+                name: "fetch_descriptor".to_string(),
+                parameters: channel_param(),
+                return_type: None,
+                body: Some("local uint64 addr = regs.descriptor[channel].val;\n    // TODO: read the descriptor at addr from target_mem and load\n    // source, destination, length and the next descriptor address".to_string()),
+                documentation: Some("Load the next transfer of a channel from its descriptor chain".to_string()),
+            },
+            MethodSpec {
+                name: "start_transfer".to_string(),
+                parameters: channel_param(),
+                return_type: None,
+                body: Some("if (regs.control[channel].chained.val == 1) {\n        fetch_descriptor(channel);\n    }\n    regs.status[channel].busy.val = 1;\n    // TODO: copy length bytes from source to destination in target_mem\n    complete_transfer(channel);".to_string()),
+                documentation: Some("Perform the transfer programmed in a channel".to_string()),
+            },
+            MethodSpec {
+                name: "complete_transfer".to_string(),
+                parameters: channel_param(),
+                return_type: None,
+                body: Some("regs.status[channel].busy.val = 0;\n    regs.status[channel].done.val = 1;\n    regs.control[channel].start.val = 0;\n    if (regs.control[channel].irq_enable.val == 1 && irq.obj) {\n        irq.signal.signal_raise();\n    }".to_string()),
+                documentation: Some("Finish a transfer and raise the completion interrupt".to_string()),
+            },
+        ]);
+        
+        device
+    }
+    
+    /// Get 16550-style UART template
+    ///
+    /// Received characters are buffered in a FIFO of `fifo_size` entries
+    /// kept in saved variables, so the state survives checkpointing. This
+    /// is a starting point rather than a complete model: the divisor latch,
+    /// modem control and status, and the transmit FIFO are not modelled,
+    /// and the `serial_device` implementation is left to be filled in with
+    /// a `write` method calling `receive_char`.
+    pub fn uart_device(name: &str, fifo_size: u32) -> DeviceSpec {
+        let mut device = Self::basic_device(name, "peripheral");
+        device.documentation = Some("16550 compatible UART".to_string());
+        
+        let register = |reg_name: &str, offset: u64, doc: &str, fields: Vec<FieldSpec>, methods: Vec<MethodSpec>| RegisterSpec {
+            name: reg_name.to_string(),
+            dimensions: vec![],
+            size: 1,
+            offset: Some(format!("0x{:02x}", offset)),
+            reset: None,
+            access: None,
+            documentation: Some(doc.to_string()),
+            fields,
+            methods,
+            templates: vec![],
+        };
+        
+        device.banks.push(BankSpec {
+            name: "regs".to_string(),
+            documentation: Some("UART registers".to_string()),
+            registers: vec![
+                register("rbr_thr", 0x0, "Receiver buffer (read) / transmitter holding (write) register", vec![], vec![
+                    Self::read_method(&format!("local uint8 c = 0;\n        if (rx_count > 0) {{\n            c = rx_fifo[rx_head];\n            rx_head = (rx_head + 1) % {};\n            rx_count--;\n        }}\n        update_interrupt();\n        return c;", fifo_size),
+                         "Pop a received character from the FIFO"),
+                    Self::write_method("if (console.obj) {\n            console.serial_device.write(value);\n        }\n        update_interrupt();",
+                          "Transmit a character to the console"),
+                ]),
+                register("ier", 0x1, "Interrupt enable register", vec![
+                    Self::field("erbfi", "0", "rw", "Received data available interrupt"),
+                    Self::field("etbei", "1", "rw", "Transmitter holding register empty interrupt"),
+                    Self::field("elsi", "2", "rw", "Receiver line status interrupt"),
+                    Self::field("edssi", "3", "rw", "Modem status interrupt"),
+                ], vec![]),
+                register("iir_fcr", 0x2, "Interrupt identification (read) / FIFO control (write) register", vec![], vec![
+                    Self::read_method("local uint8 fifo_bits = fifo_enabled ? 0xc0 : 0;\n        if (regs.ier.erbfi.val == 1 && rx_count > 0) {\n            return fifo_bits | 0x4;\n        }\n        if (regs.ier.etbei.val == 1) {\n            return fifo_bits | 0x2;\n        }\n        return fifo_bits | 0x1;",
+                         "Report the highest priority pending interrupt"),
+                    Self::write_method("fifo_enabled = (value & 0x1) != 0;\n        if ((value & 0x2) != 0) {\n            rx_head = 0;\n            rx_count = 0;\n        }\n        update_interrupt();",
+                          "Enable and clear the FIFOs"),
+                ]),
+                register("lcr", 0x3, "Line control register", vec![
+                    Self::field("wls", "1:0", "rw", "Word length select"),
+                    Self::field("stb", "2", "rw", "Number of stop bits"),
+                    Self::field("pen", "3", "rw", "Parity enable"),
+                    Self::field("eps", "4", "rw", "Even parity select"),
+                    Self::field("sp", "5", "rw", "Stick parity"),
+                    Self::field("bc", "6", "rw", "Break control"),
+                    Self::field("dlab", "7", "rw", "Divisor latch access bit"),
+                ], vec![]),
+                register("lsr", 0x5, "Line status register", vec![
+                    Self::field("dr", "0", "ro", "Data ready"),
+                    Self::field("oe", "1", "ro", "Overrun error"),
+                    Self::field("pe", "2", "ro", "Parity error"),
+                    Self::field("fe", "3", "ro", "Framing error"),
+                    Self::field("bi", "4", "ro", "Break interrupt"),
+                    Self::field("thre", "5", "ro", "Transmitter holding register empty"),
+                    Self::field("temt", "6", "ro", "Transmitter empty"),
+                    Self::field("rx_fifo_error", "7", "ro", "Error in receiver FIFO"),
+                ], vec![
+                    Self::read_method("return (rx_count > 0 ? 0x1 : 0) | 0x60;",
+                         "Data ready when the FIFO is not empty; the transmitter is always empty"),
+                ]),
+            ],
+            reserved: vec![],
+        });
+        
+        device.interfaces.push(InterfaceSpec {
+            name: "serial_device".to_string(),
+            methods: vec![],
+        });
+        
+        device.connects.extend([
+            ConnectSpec {
+                name: "console".to_string(),
+                documentation: Some("Console or serial link receiving transmitted characters".to_string()),
+                interfaces: vec!["serial_device".to_string()],
+            },
+            ConnectSpec {
+                name: "irq".to_string(),
+                documentation: Some("Interrupt output".to_string()),
+                interfaces: vec!["signal".to_string()],
+            },
+        ]);
+        
+        device.variables.extend([
+            VariableSpec {
+                name: "rx_fifo".to_string(),
+                var_type: "uint8".to_string(),
+                array_size: Some(fifo_size as u64),
+                saved: true,
+                documentation: Some("Receive FIFO contents".to_string()),
+            },
+            VariableSpec {
+                name: "rx_head".to_string(),
+                var_type: "int".to_string(),
+                array_size: None,
+                saved: true,
+                documentation: Some("Index of the oldest character in the receive FIFO".to_string()),
+            },
+            VariableSpec {
+                name: "rx_count".to_string(),
+                var_type: "int".to_string(),
+                array_size: None,
+                saved: true,
+                documentation: Some("Number of characters in the receive FIFO".to_string()),
+            },
+            VariableSpec {
+                name: "fifo_enabled".to_string(),
+                var_type: "bool".to_string(),
+                array_size: None,
+                saved: true,
+                documentation: Some("FIFO mode enabled through FCR".to_string()),
+            },
+            VariableSpec {
+                name: "irq_raised".to_string(),
+                var_type: "bool".to_string(),
+                array_size: None,
+                saved: true,
+                documentation: Some("Current level of the interrupt output".to_string()),
+            },
+        ]);
+        
+        device.methods.extend([
+            MethodSpec {
+                name: "receive_char".to_string(),
+                parameters: vec![
+                    ParameterSpec {
+                        name: "value".to_string(),
+                        param_type: "int".to_string(),
+                    }
+                ],
+                return_type: Some("int".to_string()),
+                body: Some(format!("if (rx_count == {0}) {{\n        regs.lsr.oe.val = 1;\n        return 1;\n    }}\n    rx_fifo[(rx_head + rx_count) % {0}] = value;\n    rx_count++;\n    update_interrupt();\n    return 0;", fifo_size)),
+                documentation: Some("Buffer a received character, to be called by the serial_device implementation".to_string()),
+            },
+            MethodSpec {
+                name: "update_interrupt".to_string(),
+                parameters: vec![],
+                return_type: None,
+                body: Some("local bool raise = (regs.ier.erbfi.val == 1 && rx_count > 0)\n        || regs.ier.etbei.val == 1;\n    if (raise != irq_raised && irq.obj) {\n        if (raise) {\n            irq.signal.signal_raise();\n        } else {\n            irq.signal.signal_lower();\n        }\n    }\n    irq_raised = raise;".to_string()),
+                documentation: Some("Drive the interrupt output from the enabled interrupt sources".to_string()),
+            },
         ]);
         
         device
     }
     
+    /// Get programmable timer template
+    ///
+    /// The counter is not stored, but computed from the time remaining
+    /// until the `expiry` event, which runs at `clock_hz` divided by the
+    /// prescaler.
+    pub fn timer_device(name: &str, clock_hz: u64) -> DeviceSpec {
+        let mut device = Self::basic_device(name, "peripheral");
+        device.documentation = Some(format!("Programmable timer clocked at {} Hz", clock_hz));
+        
+        let mut control = Self::register("control", 4, 0x00, "Timer control register", vec![
+            Self::field("enable", "0", "rw", "Run the timer"),
+            Self::field("irq_enable", "1", "rw", "Raise the interrupt on expiry"),
+            Self::field("periodic", "2", "rw", "Reload and restart the timer on expiry"),
+            Self::field("prescaler", "15:8", "rw", "Clock divider minus one"),
+        ]);
+        control.methods.push(Self::write_method("default(value);\n        restart();",
+            "Restart the timer with the new configuration"));
+        let mut counter = Self::register("counter", 4, 0x08, "Current counter value", vec![]);
+        counter.methods.push(Self::read_method("return counter_value();",
+            "Compute the counter from the time until expiry"));
+        
+        device.banks.push(BankSpec {
+            name: "regs".to_string(),
+            documentation: Some("Timer registers".to_string()),
+            registers: vec![
+                control,
+                Self::register("load", 4, 0x04, "Value the counter starts from", vec![]),
+                counter,
+                Self::register("status", 4, 0x0c, "Timer status register", vec![
+                    Self::field("expired", "0", "w1c", "The counter has reached zero"),
+                ]),
+            ],
+            reserved: vec![],
+        });
+        
+        device.connects.push(Self::signal_connect("irq", "Timer interrupt"));
+        
+        device.events.push(EventSpec {
+            name: "expiry".to_string(),
+            template: "simple_time_event".to_string(),
+            documentation: Some("Posted when the counter reaches zero".to_string()),
+            body: Some("regs.status.expired.val = 1;\n            if (regs.control.irq_enable.val == 1 && irq.obj) {\n                irq.signal.signal_raise();\n                irq.signal.signal_lower();\n            }\n            if (regs.control.periodic.val == 1) {\n                restart();\n            }".to_string()),
+        });
+        
+        device.methods.extend([
+            MethodSpec {
+                name: "restart".to_string(),
+                parameters: vec![],
+                return_type: None,
+                body: Some(format!("expiry.remove();\n    if (regs.control.enable.val == 1) {{\n        local double tick = (regs.control.prescaler.val + 1) / {}.0;\n        expiry.post((regs.load.val + 1) * tick);\n    }}", clock_hz)),
+                documentation: Some("Start counting down from the load value".to_string()),
+            },
+            MethodSpec {
+                name: "counter_value".to_string(),
+                parameters: vec![],
+                return_type: Some("uint32".to_string()),
+                body: Some(format!("if (!expiry.posted()) {{\n        return 0;\n    }}\n    local double tick = (regs.control.prescaler.val + 1) / {}.0;\n    return cast(expiry.next() / tick, uint32);", clock_hz)),
+                documentation: Some("The number of ticks left until expiry".to_string()),
+            },
+        ]);
+        
+        device
+    }
+    
+    /// Get watchdog timer template
+    ///
+    /// The first expiry raises the interrupt; if the watchdog has not been
+    /// kicked before it expires a second time, the reset output is raised.
+    pub fn watchdog_device(name: &str, clock_hz: u64) -> DeviceSpec {
+        let mut device = Self::basic_device(name, "peripheral");
+        device.documentation = Some(format!("Watchdog timer clocked at {} Hz", clock_hz));
+        
+        let mut control = Self::register("control", 4, 0x00, "Watchdog control register", vec![
+            Self::field("enable", "0", "rw", "Run the watchdog"),
+            Self::field("reset_enable", "1", "rw", "Raise the reset output on the second expiry"),
+            Self::field("prescaler", "15:8", "rw", "Clock divider minus one"),
+        ]);
+        control.methods.push(Self::write_method("default(value);\n        restart();",
+            "Restart the watchdog with the new configuration"));
+        let mut counter = Self::register("counter", 4, 0x08, "Current counter value", vec![]);
+        counter.methods.push(Self::read_method(format!("if (!expiry.posted()) {{\n            return 0;\n        }}\n        local double tick = (regs.control.prescaler.val + 1) / {}.0;\n        return cast(expiry.next() / tick, uint32);", clock_hz).as_str(),
+            "Compute the counter from the time until expiry"));
+        let mut kick = Self::register("kick", 4, 0x0c, "Write 0x5a5a5a5a to restart the countdown", vec![]);
+        kick.methods.push(Self::write_method("if (value == 0x5a5a5a5a) {\n            regs.status.timeout.val = 0;\n            restart();\n        }",
+            "Restart the countdown on the magic value"));
+        
+        device.banks.push(BankSpec {
+            name: "regs".to_string(),
+            documentation: Some("Watchdog registers".to_string()),
+            registers: vec![
+                control,
+                Self::register("timeout", 4, 0x04, "Number of ticks until expiry", vec![]),
+                counter,
+                kick,
+                Self::register("status", 4, 0x10, "Watchdog status register", vec![
+                    Self::field("timeout", "0", "w1c", "The watchdog has expired once"),
+                ]),
+            ],
+            reserved: vec![],
+        });
+        
+        device.connects.extend([
+            Self::signal_connect("irq", "Interrupt raised on the first expiry"),
+            Self::signal_connect("reset_out", "Reset raised on the second expiry"),
+        ]);
+        
+        device.events.push(EventSpec {
+            name: "expiry".to_string(),
+            template: "simple_time_event".to_string(),
+            documentation: Some("Posted when the countdown reaches zero".to_string()),
+            body: Some("if (regs.status.timeout.val == 1) {\n                if (regs.control.reset_enable.val == 1 && reset_out.obj) {\n                    reset_out.signal.signal_raise();\n                }\n                return;\n            }\n            regs.status.timeout.val = 1;\n            if (irq.obj) {\n                irq.signal.signal_raise();\n            }\n            restart();".to_string()),
+        });
+        
+        device.methods.push(MethodSpec {
+            name: "restart".to_string(),
+            parameters: vec![],
+            return_type: None,
+            body: Some(format!("expiry.remove();\n    if (regs.control.enable.val == 1) {{\n        local double tick = (regs.control.prescaler.val + 1) / {}.0;\n        expiry.post((regs.timeout.val + 1) * tick);\n    }}", clock_hz)),
+            documentation: Some("Start counting down from the timeout value".to_string()),
+        });
+        
+        device
+    }
+    
+    /// Get GPIO controller template
+    ///
+    /// Each register has one bit per pin. Input pins are driven through
+    /// the `signal` interface of the `pin` ports, and can interrupt on
+    /// edges or levels of either polarity.
+    pub fn gpio_controller(name: &str, num_pins: u32) -> DeviceSpec {
+        let mut device = Self::basic_device(name, "peripheral");
+        device.documentation = Some(format!("GPIO controller with {} pins", num_pins));
+        
+        let num_pins = num_pins.clamp(1, 32);
+        let pins = if num_pins == 1 { "0".to_string() } else { format!("{}:0", num_pins - 1) };
+        let pin_register = |reg_name: &str, offset: u64, access: &str, doc: &str, field_doc: &str| {
+            Self::register(reg_name, 4, offset, doc, vec![Self::field("pins", &pins, access, field_doc)])
+        };
+        let mut data_in = pin_register("data_in", 0x08, "ro", "Input data register", "Level of input pins");
+        data_in.methods.push(Self::read_method("return pin_levels & ~regs.direction.val;",
+            "Sample the levels of the input pins"));
+        let mut irq_status = pin_register("irq_status", 0x18, "w1c", "Interrupt status register", "Pending interrupts, write 1 to clear");
+        irq_status.methods.push(Self::write_method("default(value);\n        update_interrupt();",
+            "Clear pending interrupts"));
+        
+        device.banks.push(BankSpec {
+            name: "regs".to_string(),
+            documentation: Some("GPIO registers".to_string()),
+            registers: vec![
+                pin_register("direction", 0x00, "rw", "Direction register", "1 for output pins, 0 for input pins"),
+                pin_register("data_out", 0x04, "rw", "Output data register", "Level driven on output pins"),
+                data_in,
+                pin_register("irq_mask", 0x0c, "rw", "Interrupt mask register", "1 to enable the interrupt of a pin"),
+                pin_register("irq_type", 0x10, "rw", "Interrupt type register", "1 for edge, 0 for level triggered interrupts"),
+                pin_register("irq_polarity", 0x14, "rw", "Interrupt polarity register", "1 for rising edge or high level, 0 for falling edge or low level"),
+                irq_status,
+            ],
+            reserved: vec![],
+        });
+        
+        let pin_method = |method_name: &str, level: &str, doc: &str| MethodSpec {
+            name: method_name.to_string(),
+            parameters: vec![],
+            return_type: None,
+            body: Some(format!("set_pin(i, {});", level)),
+            documentation: Some(doc.to_string()),
+        };
+        device.ports.push(PortSpec {
+            name: "pin".to_string(),
+            dimensions: vec![ArrayDimension {
+                index: "i".to_string(),
+                size: num_pins as u64,
+            }],
+            documentation: Some("Input pins".to_string()),
+            interfaces: vec![InterfaceSpec {
+                name: "signal".to_string(),
+                methods: vec![
+                    pin_method("signal_raise", "true", "Drive the pin high"),
+                    pin_method("signal_lower", "false", "Drive the pin low"),
+                ],
+            }],
+        });
+        
+        device.connects.push(Self::signal_connect("irq", "Interrupt output"));
+        
+        device.variables.extend([
+            VariableSpec {
+                name: "pin_levels".to_string(),
+                var_type: "uint32".to_string(),
+                array_size: None,
+                saved: true,
+                documentation: Some("Levels of the input pins".to_string()),
+            },
+            VariableSpec {
+                name: "irq_raised".to_string(),
+                var_type: "bool".to_string(),
+                array_size: None,
+                saved: true,
+                documentation: Some("Current level of the interrupt output".to_string()),
+            },
+        ]);
+        
+        device.methods.extend([
+            MethodSpec {
+                name: "set_pin".to_string(),
+                parameters: vec![
+                    ParameterSpec {
+                        name: "pin".to_string(),
+                        param_type: "int".to_string(),
+                    },
+                    ParameterSpec {
+                        name: "level".to_string(),
+                        param_type: "bool".to_string(),
+                    },
+                ],
+                return_type: None,
+                body: Some("local uint32 bit = 1 << pin;\n    local bool old_level = (pin_levels & bit) != 0;\n    pin_levels = level ? pin_levels | bit : pin_levels & ~bit;\n    local bool active = level == ((regs.irq_polarity.val & bit) != 0);\n    if ((regs.irq_type.val & bit) != 0) {\n        if (level != old_level && active) {\n            regs.irq_status.val |= bit;\n        }\n    } else if (active) {\n        regs.irq_status.val |= bit;\n    }\n    update_interrupt();".to_string()),
+                documentation: Some("Update the level of an input pin, latching edge and level interrupts".to_string()),
+            },
+            MethodSpec {
+                name: "update_interrupt".to_string(),
+                parameters: vec![],
+                return_type: None,
+                body: Some("local bool raise = (regs.irq_status.val & regs.irq_mask.val) != 0;\n    if (raise != irq_raised && irq.obj) {\n        if (raise) {\n            irq.signal.signal_raise();\n        } else {\n            irq.signal.signal_lower();\n        }\n    }\n    irq_raised = raise;".to_string()),
+                documentation: Some("Drive the interrupt output from the pending, unmasked interrupts".to_string()),
+            },
+        ]);
+        
+        device
+    }
+    
+    /// PCIe endpoint with a type 0 configuration space
+    ///
+    /// The `pci_config` bank holds the standard header, with one 32-bit
+    /// memory BAR per entry of `bar_sizes` (at most 6, sizes rounded up to
+    /// powers of two), followed by a capability chain of PCI Express, MSI
+    /// and, with `msix_vectors` > 0, MSI-X capabilities. The device
+    /// implements `pcie_device` and issues upstream transactions through
+    /// `upstream_target`; MSI and MSI-X delivery are left as stubs.
+    pub fn pcie_endpoint(name: &str, vendor_id: u16, device_id: u16,
+                         bar_sizes: &[u64], msix_vectors: u32) -> DeviceSpec {
+        let mut device = Self::basic_device(name, "peripheral");
+        device.documentation = Some(format!(
+            "PCIe endpoint {:04x}:{:04x}", vendor_id, device_id));
+        
+        let with_reset = |mut register: RegisterSpec, reset: u64| {
+            register.reset = Some(format!("0x{:x}", reset));
+            register
+        };
+        let ro = |reg_name: &str, size: u64, offset: u64, reset: u64, doc: &str| {
+            RegisterSpec {
+                access: Some("ro".to_string()),
+                ..with_reset(Self::register(reg_name, size, offset, doc, vec![]),
+                             reset)
+            }
+        };
+        
+        let mut registers = vec![
+            ro("vendor_id", 2, 0x00, vendor_id as u64, "Vendor ID"),
+            ro("device_id", 2, 0x02, device_id as u64, "Device ID"),
+            Self::register("command", 2, 0x04, "Command register", vec![
+                Self::field("io", "0", "ro", "I/O space enable, no I/O BARs"),
+                Self::field("mem", "1", "rw", "Memory space enable"),
+                Self::field("master", "2", "rw", "Bus master enable"),
+                Self::field("intx_disable", "10", "rw", "INTx emulation disable"),
+            ]),
+            with_reset(Self::register("status", 2, 0x06, "Status register", vec![
+                Self::field("intx_status", "3", "ro", "INTx emulation interrupt pending"),
+                Self::field("cap_list", "4", "ro", "Capability list present"),
+            ]), 0x10),
+            ro("revision_id", 1, 0x08, 0, "Revision ID"),
+            ro("class_code", 3, 0x09, 0xff0000, "Class code, unassigned by default"),
+            Self::register("cache_line_size", 1, 0x0c, "Cache line size", vec![]),
+            ro("header_type", 1, 0x0e, 0, "Header type 0, single function"),
+        ];
+        
+        let num_bars = bar_sizes.len().min(6);
+        for (i, size) in bar_sizes.iter().take(num_bars).enumerate() {
+            let size = size.max(&16).next_power_of_two().min(1 << 31);
+            let mut bar = Self::register(
+                &format!("bar{}", i), 4, 0x10 + 4 * i as u64,
+                &format!("Base address register {}, 0x{:x} bytes of memory", i, size),
+                vec![
+                    Self::field("space", "0", "ro", "Memory space BAR"),
+                    Self::field("type", "2:1", "ro", "32-bit BAR"),
+                    Self::field("prefetchable", "3", "ro", "Not prefetchable"),
+                    Self::field("base", "31:4", "rw", "Base address"),
+                ]);
+            bar.methods.push(Self::write_method(&format!("default(value & 0x{:x});", !(size - 1) as u32),
+                "Only the address bits above the BAR size are writable"));
+            registers.push(bar);
+        }
+        
+        let msi_next = if msix_vectors > 0 { 0x70 } else { 0x00 };
+        registers.extend([
+            ro("subsystem_vendor_id", 2, 0x2c, vendor_id as u64, "Subsystem vendor ID"),
+            ro("subsystem_id", 2, 0x2e, device_id as u64, "Subsystem ID"),
+            ro("capabilities_ptr", 1, 0x34, 0x40, "Offset of the first capability"),
+            Self::register("interrupt_line", 1, 0x3c, "Interrupt line, used by software only", vec![]),
+            ro("interrupt_pin", 1, 0x3d, 1, "Interrupt pin, INTA"),
+            
+            // PCI Express capability
+            ro("exp_cap_id", 1, 0x40, 0x10, "PCI Express capability ID"),
+            ro("exp_next_ptr", 1, 0x41, 0x60, "Next capability, MSI"),
+            ro("exp_cap", 2, 0x42, 0x0002, "PCI Express capabilities, version 2 endpoint"),
+            ro("exp_device_cap", 4, 0x44, 0, "Device capabilities"),
+            Self::register("exp_device_control", 2, 0x48, "Device control", vec![]),
+            Self::register("exp_device_status", 2, 0x4a, "Device status", vec![
+                Self::field("errors", "3:0", "w1c", "Detected errors, write 1 to clear"),
+            ]),
+            ro("exp_link_cap", 4, 0x4c, 0x11, "Link capabilities, x1 at 2.5 GT/s"),
+            Self::register("exp_link_control", 2, 0x50, "Link control", vec![]),
+            ro("exp_link_status", 2, 0x52, 0x11, "Link status, x1 at 2.5 GT/s"),
+            
+            // MSI capability
+            ro("msi_cap_id", 1, 0x60, 0x05, "MSI capability ID"),
+            ro("msi_next_ptr", 1, 0x61, msi_next, "Next capability"),
+            with_reset(Self::register("msi_control", 2, 0x62, "MSI message control", vec![
+                Self::field("enable", "0", "rw", "MSI enable"),
+                Self::field("multiple_message_capable", "3:1", "ro", "One vector supported"),
+                Self::field("multiple_message_enable", "6:4", "rw", "Vectors enabled"),
+                Self::field("addr64", "7", "ro", "64-bit addresses supported"),
+            ]), 0x80),
+            Self::register("msi_address", 4, 0x64, "MSI message address", vec![
+                Self::field("address", "31:2", "rw", "Low bits of the message address"),
+            ]),
+            Self::register("msi_upper_address", 4, 0x68, "MSI message upper address", vec![]),
+            Self::register("msi_data", 2, 0x6c, "MSI message data", vec![]),
+        ]);
+        
+        if msix_vectors > 0 {
+            let msix_vectors = msix_vectors.min(2048);
+            registers.extend([
+                ro("msix_cap_id", 1, 0x70, 0x11, "MSI-X capability ID"),
+                ro("msix_next_ptr", 1, 0x71, 0x00, "End of the capability list"),
+                with_reset(Self::register("msix_control", 2, 0x72, "MSI-X message control", vec![
+                    Self::field("table_size", "10:0", "ro", "Number of table entries minus one"),
+                    Self::field("function_mask", "14", "rw", "Mask all vectors"),
+                    Self::field("enable", "15", "rw", "MSI-X enable"),
+                ]), (msix_vectors - 1) as u64),
+                ro("msix_table", 4, 0x74, 0x2000, "MSI-X table in BAR 0 at offset 0x2000"),
+                ro("msix_pba", 4, 0x78, 0x3000, "MSI-X pending bit array in BAR 0 at offset 0x3000"),
+            ]);
+        }
+        
+        device.banks.push(BankSpec {
+            name: "pci_config".to_string(),
+            documentation: Some("PCI configuration space".to_string()),
+            registers,
+            reserved: vec![],
+        });
+        
+        let port_parameters = || vec![
+            ParameterSpec {
+                name: "port_obj".to_string(),
+                param_type: "conf_object_t *".to_string(),
+            },
+            ParameterSpec {
+                name: "device_id".to_string(),
+                param_type: "uint16".to_string(),
+            },
+        ];
+        device.interfaces = vec![InterfaceSpec {
+            name: "pcie_device".to_string(),
+            methods: vec![
+                MethodSpec {
+                    name: "connected".to_string(),
+                    parameters: port_parameters(),
+                    return_type: None,
+                    body: Some("upstream_port = port_obj;\nrequester_id = device_id;".to_string()),
+                    documentation: Some("The device was connected to a PCIe port".to_string()),
+                },
+                MethodSpec {
+                    name: "disconnected".to_string(),
+                    parameters: port_parameters(),
+                    return_type: None,
+                    body: Some("upstream_port = NULL;".to_string()),
+                    documentation: Some("The device was disconnected from its PCIe port".to_string()),
+                },
+                MethodSpec {
+                    name: "hot_reset".to_string(),
+                    parameters: vec![],
+                    return_type: None,
+                    body: Some("pci_config.hard_reset();".to_string()),
+                    documentation: Some("Reset the configuration space".to_string()),
+                },
+            ],
+        }];
+        
+        device.connects.push(ConnectSpec {
+            name: "upstream_target".to_string(),
+            documentation: Some("Target of upstream memory transactions, such as DMA and message signaled interrupts".to_string()),
+            interfaces: vec!["transaction".to_string()],
+        });
+        
+        device.variables.extend([
+            VariableSpec {
+                name: "upstream_port".to_string(),
+                var_type: "conf_object_t *".to_string(),
+                array_size: None,
+                saved: false,
+                documentation: Some("The PCIe port the device is connected to".to_string()),
+            },
+            VariableSpec {
+                name: "requester_id".to_string(),
+                var_type: "uint16".to_string(),
+                array_size: None,
+                saved: true,
+                documentation: Some("Requester ID assigned when connected".to_string()),
+            },
+        ]);
+        
+        let vector_parameter = || vec![ParameterSpec {
+            name: "vector".to_string(),
+            param_type: "int".to_string(),
+        }];
+        device.methods.push(MethodSpec {
+            name: "raise_msi".to_string(),
+            parameters: vector_parameter(),
+            return_type: None,
+            body: Some("if (pci_config.msi_control.enable.val == 0 || pci_config.command.master.val == 0) {\n        return;\n    }\n    // TODO: write msi_data | vector to msi_address through upstream_target\n    log info, 3: \"MSI vector %d\", vector;".to_string()),
+            documentation: Some("Signal an MSI message".to_string()),
+        });
+        if msix_vectors > 0 {
+            device.methods.push(MethodSpec {
+                name: "raise_msix".to_string(),
+                parameters: vector_parameter(),
+                return_type: None,
+                body: Some("if (pci_config.msix_control.enable.val == 0 || pci_config.msix_control.function_mask.val == 1) {\n        return;\n    }\n    // TODO: look up the table entry of the vector in BAR 0 and write its\n    // message data to its message address through upstream_target\n    log info, 3: \"MSI-X vector %d\", vector;".to_string()),
+                documentation: Some("Signal an MSI-X message".to_string()),
+            });
+        }
+        
+        device
+    }
+    
+    /// Network interface controller with RX/TX descriptor rings
+    ///
+    /// Each of the `queues` queue pairs has a receive and a transmit ring
+    /// of `ring_size` 16-byte descriptors in memory, described by base,
+    /// length, head and tail registers. Frames are exchanged with the
+    /// `link` connect through `ethernet_common`, and receive interrupts are
+    /// coalesced by packet count and time through the `itr` register.
+    /// Descriptor and buffer accesses are left as DMA stubs.
+    pub fn nic_device(name: &str, queues: u32, ring_size: u32) -> DeviceSpec {
+        let mut device = Self::basic_device(name, "peripheral");
+        device.documentation = Some(format!(
+            "Network interface controller with {} queue pairs of {} descriptors",
+            queues, ring_size));
+        
+        let queue_register = |reg_name: &str, size: u64, offset: u64, doc: &str, fields: Vec<FieldSpec>| RegisterSpec {
+            name: reg_name.to_string(),
+            dimensions: vec![ArrayDimension {
+                index: "i".to_string(),
+                size: queues as u64,
+            }],
+            size,
+            offset: Some(format!("0x{:03x} + i * 0x40", offset)),
+            reset: None,
+            access: None,
+            documentation: Some(doc.to_string()),
+            fields,
+            methods: vec![],
+            templates: vec![],
+        };
+        let ring_length = |reg_name: &str, offset: u64, doc: &str| RegisterSpec {
+            reset: Some(format!("{}", ring_size)),
+            ..queue_register(reg_name, 4, offset, doc, vec![])
+        };
+        
+        let interrupt_fields = |access: &str| vec![
+            Self::field("rx_done", "0", access, "Frames were received"),
+            Self::field("tx_done", "1", access, "Frames were transmitted"),
+            Self::field("rx_overrun", "2", access, "A frame was dropped for lack of receive descriptors"),
+            Self::field("link_change", "3", access, "The link status changed"),
+        ];
+        let mut int_cause = Self::register("int_cause", 4, 0x10, "Interrupt cause register", interrupt_fields("w1c"));
+        int_cause.methods.push(Self::write_method("default(value);\nupdate_interrupt();",
+            "Lower the interrupt when all causes are cleared"));
+        let mut int_mask = Self::register("int_mask", 4, 0x14, "Interrupt mask register", interrupt_fields("rw"));
+        int_mask.methods.push(Self::write_method("default(value);\nupdate_interrupt();",
+            "Drive the interrupt from the newly enabled causes"));
+        
+        let tail_write = |ring: &str, kick: &str| Self::write_method(&format!("if (value >= regs.{0}_len[i].val) {{\n    log spec_viol: \"{0} tail %d beyond the end of the ring\", value;\n    return;\n}}\ndefault(value);{1}", ring, kick),
+            "Hand descriptors up to the tail over to the device");
+        let mut rx_tail = queue_register("rx_tail", 4, 0x110, "Receive ring tail, the first descriptor owned by software", vec![]);
+        rx_tail.methods.push(tail_write("rx", ""));
+        let mut tx_tail = queue_register("tx_tail", 4, 0x130, "Transmit ring tail, one past the last descriptor to send", vec![]);
+        tx_tail.methods.push(tail_write("tx", "\nprocess_tx(i);"));
+        
+        device.banks.push(BankSpec {
+            name: "regs".to_string(),
+            documentation: Some("NIC registers".to_string()),
+            registers: vec![
+                Self::register("ctrl", 4, 0x00, "Device control register", vec![
+                    Self::field("promiscuous", "2", "rw", "Receive frames for all MAC addresses"),
+                    Self::field("loopback", "3", "rw", "Loop transmitted frames back to the receive rings"),
+                ]),
+                Self::register("status", 4, 0x04, "Device status register", vec![
+                    Self::field("link_up", "0", "ro", "The link is up"),
+                    Self::field("speed", "2:1", "ro", "Link speed, 0 = 10, 1 = 100, 2 = 1000 Mb/s"),
+                ]),
+                Self::register("mac_low", 4, 0x08, "Low four bytes of the MAC address", vec![]),
+                Self::register("mac_high", 4, 0x0c, "High two bytes of the MAC address", vec![
+                    Self::field("address", "15:0", "rw", "MAC address bytes 4 and 5"),
+                    Self::field("valid", "31", "rw", "Filter frames on the MAC address"),
+                ]),
+                int_cause,
+                int_mask,
+                Self::register("itr", 4, 0x18, "Interrupt coalescing register", vec![
+                    Self::field("interval", "15:0", "rw", "Longest delay of a receive interrupt, in microseconds"),
+                    Self::field("packets", "23:16", "rw", "Frames received before the interrupt is raised without delay"),
+                    Self::field("enable", "31", "rw", "Coalesce receive interrupts"),
+                ]),
+                queue_register("rx_base", 8, 0x100, "Physical address of the receive ring", vec![]),
+                ring_length("rx_len", 0x108, "Number of descriptors in the receive ring"),
+                queue_register("rx_head", 4, 0x10c, "Receive ring head, the next descriptor the device fills", vec![
+                    Self::field("index", "31:0", "ro", "Descriptor index"),
+                ]),
+                rx_tail,
+                queue_register("rx_ctrl", 4, 0x114, "Receive queue control", vec![
+                    Self::field("enable", "0", "rw", "Receive frames into the ring"),
+                    Self::field("buffer_size", "2:1", "rw", "Receive buffer size, 0 = 2048, 1 = 1024, 2 = 512, 3 = 256 bytes"),
+                ]),
+                queue_register("tx_base", 8, 0x120, "Physical address of the transmit ring", vec![]),
+                ring_length("tx_len", 0x128, "Number of descriptors in the transmit ring"),
+                queue_register("tx_head", 4, 0x12c, "Transmit ring head, the next descriptor the device sends", vec![
+                    Self::field("index", "31:0", "ro", "Descriptor index"),
+                ]),
+                tx_tail,
+                queue_register("tx_ctrl", 4, 0x134, "Transmit queue control", vec![
+                    Self::field("enable", "0", "rw", "Send frames from the ring"),
+                ]),
+            ],
+            reserved: vec![],
+        });
+        
+        device.interfaces.push(InterfaceSpec {
+            name: "ethernet_common".to_string(),
+            methods: vec![MethodSpec {
+                name: "frame".to_string(),
+                parameters: vec![
+                    ParameterSpec {
+                        name: "frame".to_string(),
+                        param_type: "const frags_t *".to_string(),
+                    },
+                    ParameterSpec {
+                        name: "crc_status".to_string(),
+                        param_type: "eth_frame_crc_status_t".to_string(),
+                    },
+                ],
+                return_type: None,
+                body: Some("receive_frame(0, frame);".to_string()),
+                documentation: Some("Receive a frame from the link".to_string()),
+            }],
+        });
+        
+        device.connects.extend([
+            ConnectSpec {
+                name: "link".to_string(),
+                documentation: Some("Ethernet link that transmitted frames are sent to".to_string()),
+                interfaces: vec!["ethernet_common".to_string()],
+            },
+            ConnectSpec {
+                name: "dma_mem".to_string(),
+                documentation: Some("Memory holding the descriptor rings and frame buffers".to_string()),
+                interfaces: vec!["memory_space".to_string()],
+            },
+            Self::signal_connect("irq", "Interrupt output"),
+        ]);
+        
+        device.variables.extend([
+            VariableSpec {
+                name: "pending_rx".to_string(),
+                var_type: "uint32".to_string(),
+                array_size: None,
+                saved: true,
+                documentation: Some("Frames received since the last receive interrupt".to_string()),
+            },
+            VariableSpec {
+                name: "irq_raised".to_string(),
+                var_type: "bool".to_string(),
+                array_size: None,
+                saved: true,
+                documentation: Some("Current level of the interrupt output".to_string()),
+            },
+        ]);
+        
+        device.events.push(EventSpec {
+            name: "coalesce_timer".to_string(),
+            template: "simple_time_event".to_string(),
+            documentation: Some("Posted when a frame is received with coalescing enabled".to_string()),
+            body: Some("signal_rx();".to_string()),
+        });
+        
+        let queue_parameter = || ParameterSpec {
+            name: "queue".to_string(),
+            param_type: "int".to_string(),
+        };
+        let dma_parameters = || vec![
+            ParameterSpec {
+                name: "addr".to_string(),
+                param_type: "uint64".to_string(),
+            },
+            ParameterSpec {
+                name: "buf".to_string(),
+                param_type: "uint8 *".to_string(),
+            },
+            ParameterSpec {
+                name: "len".to_string(),
+                param_type: "uint32".to_string(),
+            },
+        ];
+        device.methods.extend([
+            MethodSpec {
+                name: "dma_read".to_string(),
+                parameters: dma_parameters(),
+                return_type: None,
+                body: Some("// TODO: read len bytes at addr from dma_mem into buf\nlog info, 4: \"DMA read of %d bytes at 0x%x\", len, addr;".to_string()),
+                documentation: Some("Read a descriptor or frame buffer from memory".to_string()),
+            },
+            MethodSpec {
+                name: "dma_write".to_string(),
+                parameters: dma_parameters(),
+                return_type: None,
+                body: Some("// TODO: write len bytes from buf to addr in dma_mem\nlog info, 4: \"DMA write of %d bytes at 0x%x\", len, addr;".to_string()),
+                documentation: Some("Write a descriptor or frame buffer to memory".to_string()),
+            },
+            MethodSpec {
+                name: "receive_frame".to_string(),
+                parameters: vec![
+                    queue_parameter(),
+                    ParameterSpec {
+                        name: "frame".to_string(),
+                        param_type: "const frags_t *".to_string(),
+                    },
+                ],
+                return_type: None,
+                body: Some("local uint32 head = regs.rx_head[queue].val;\nif (regs.rx_ctrl[queue].enable.val == 0) {\n    return;\n}\nif (head == regs.rx_tail[queue].val) {\n    regs.int_cause.rx_overrun.val = 1;\n    update_interrupt();\n    return;\n}\nlocal uint64 desc = regs.rx_base[queue].val + head * 16;\n// TODO: dma_read the buffer address from the descriptor at desc,\n// dma_write the frame to the buffer and write back its length and status\nregs.rx_head[queue].val = (head + 1) % regs.rx_len[queue].val;\nrx_complete();".to_string()),
+                documentation: Some("Store a received frame in the next descriptor of a receive ring".to_string()),
+            },
+            MethodSpec {
+                name: "process_tx".to_string(),
+                parameters: vec![queue_parameter()],
+                return_type: None,
+                body: Some("if (regs.tx_ctrl[queue].enable.val == 0) {\n    return;\n}\nwhile (regs.tx_head[queue].val != regs.tx_tail[queue].val) {\n    local uint64 desc = regs.tx_base[queue].val + regs.tx_head[queue].val * 16;\n    // TODO: dma_read the buffer address and length from the descriptor at\n    // desc and the frame from the buffer, and send it with\n    // link.ethernet_common.frame()\n    regs.tx_head[queue].val = (regs.tx_head[queue].val + 1) % regs.tx_len[queue].val;\n}\nregs.int_cause.tx_done.val = 1;\nupdate_interrupt();".to_string()),
+                documentation: Some("Send the frames queued in a transmit ring".to_string()),
+            },
+            MethodSpec {
+                name: "rx_complete".to_string(),
+                parameters: vec![],
+                return_type: None,
+                body: Some("pending_rx++;\nif (regs.itr.enable.val == 0 || pending_rx >= regs.itr.packets.val) {\n    signal_rx();\n} else if (!coalesce_timer.posted()) {\n    coalesce_timer.post(regs.itr.interval.val / 1000000.0);\n}".to_string()),
+                documentation: Some("Raise the receive interrupt now, or when the coalescing limits are reached".to_string()),
+            },
+            MethodSpec {
+                name: "signal_rx".to_string(),
+                parameters: vec![],
+                return_type: None,
+                body: Some("coalesce_timer.remove();\npending_rx = 0;\nregs.int_cause.rx_done.val = 1;\nupdate_interrupt();".to_string()),
+                documentation: Some("Raise the receive interrupt for the frames received so far".to_string()),
+            },
+            MethodSpec {
+                name: "update_interrupt".to_string(),
+                parameters: vec![],
+                return_type: None,
+                body: Some("local bool raise = (regs.int_cause.val & regs.int_mask.val) != 0;\nif (raise != irq_raised && irq.obj) {\n    if (raise) {\n        irq.signal.signal_raise();\n    } else {\n        irq.signal.signal_lower();\n    }\n}\nirq_raised = raise;".to_string()),
+                documentation: Some("Drive the interrupt output from the unmasked interrupt causes".to_string()),
+            },
+        ]);
+        
+        device
+    }
+    
+    /// A register of `size` bytes at a constant offset
+    fn register(name: &str, size: u64, offset: u64, doc: &str, fields: Vec<FieldSpec>) -> RegisterSpec {
+        RegisterSpec {
+            name: name.to_string(),
+            dimensions: vec![],
+            size,
+            offset: Some(format!("0x{:02x}", offset)),
+            reset: None,
+            access: None,
+            documentation: Some(doc.to_string()),
+            fields,
+            methods: vec![],
+            templates: vec![],
+        }
+    }
+    
+    /// A connect to an object implementing `signal`
+    fn signal_connect(name: &str, doc: &str) -> ConnectSpec {
+        ConnectSpec {
+            name: name.to_string(),
+            documentation: Some(doc.to_string()),
+            interfaces: vec!["signal".to_string()],
+        }
+    }
+    
+    /// A field with the given access and documentation
+    fn field(name: &str, bits: &str, access: &str, doc: &str) -> FieldSpec {
+        FieldSpec {
+            name: name.to_string(),
+            bits: bits.to_string(),
+            access: Some(access.to_string()),
+            documentation: Some(doc.to_string()),
+            templates: vec![],
+        }
+    }
+    
+    /// A register `read` method override
+    fn read_method(body: &str, doc: &str) -> MethodSpec {
+        MethodSpec {
+            name: "read".to_string(),
+            parameters: vec![],
+            return_type: Some("uint64".to_string()),
+            body: Some(body.to_string()),
+            documentation: Some(doc.to_string()),
+        }
+    }
+    
+    /// A register `write` method override
+    fn write_method(body: &str, doc: &str) -> MethodSpec {
+        MethodSpec {
+            name: "write".to_string(),
+            parameters: vec![
+                ParameterSpec {
+                    name: "value".to_string(),
+                    param_type: "uint64".to_string(),
+                }
+            ],
+            return_type: None,
+            body: Some(body.to_string()),
+            documentation: Some(doc.to_string()),
+        }
+    }
+    
     /// Get common design patterns
     pub fn get_pattern_templates() -> HashMap<String, Box<dyn Fn(&str, &serde_json::Value) -> Result<DeviceSpec>>> {
         let mut patterns: HashMap<String, Box<dyn Fn(&str, &serde_json::Value) -> Result<DeviceSpec>>> = HashMap::new();
@@ -354,6 +1389,50 @@ impl DMLTemplates {
             Ok(Self::bus_interface_device(name, bus_width))
         }));
         
+        patterns.insert("dma".to_string(), Box::new(|name: &str, config: &serde_json::Value| {
+            let num_channels = config["num_channels"].as_u64().unwrap_or(4) as u32;
+            Ok(Self::dma_controller(name, num_channels))
+        }));
+        
+        patterns.insert("uart".to_string(), Box::new(|name: &str, config: &serde_json::Value| {
+            let fifo_size = config["fifo_size"].as_u64().unwrap_or(16) as u32;
+            Ok(Self::uart_device(name, fifo_size))
+        }));
+        
+        patterns.insert("timer".to_string(), Box::new(|name: &str, config: &serde_json::Value| {
+            let clock_hz = config["clock_hz"].as_u64().unwrap_or(1_000_000);
+            Ok(Self::timer_device(name, clock_hz))
+        }));
+        
+        patterns.insert("watchdog".to_string(), Box::new(|name: &str, config: &serde_json::Value| {
+            let clock_hz = config["clock_hz"].as_u64().unwrap_or(32_768);
+            Ok(Self::watchdog_device(name, clock_hz))
+        }));
+        
+        patterns.insert("gpio".to_string(), Box::new(|name: &str, config: &serde_json::Value| {
+            let num_pins = config["num_pins"].as_u64().unwrap_or(32) as u32;
+            Ok(Self::gpio_controller(name, num_pins))
+        }));
+        
+        patterns.insert("pcie_endpoint".to_string(), Box::new(|name: &str, config: &serde_json::Value| {
+            let id = |key: &str, default: u16| config[key].as_u64()
+                .map_or(Ok(default), |id| u16::try_from(id)
+                        .map_err(|_| anyhow::anyhow!("{} must fit in 16 bits", key)));
+            let bar_sizes: Vec<u64> = match config["bar_sizes"].as_array() {
+                Some(sizes) => sizes.iter().filter_map(|size| size.as_u64()).collect(),
+                None => vec![0x4000],
+            };
+            let msix_vectors = config["msix_vectors"].as_u64().unwrap_or(8) as u32;
+            Ok(Self::pcie_endpoint(name, id("vendor_id", 0x8086)?, id("device_id", 0x0001)?,
+                                   &bar_sizes, msix_vectors))
+        }));
+        
+        patterns.insert("nic".to_string(), Box::new(|name: &str, config: &serde_json::Value| {
+            let queues = config["queues"].as_u64().unwrap_or(1).clamp(1, 16) as u32;
+            let ring_size = config["ring_size"].as_u64().unwrap_or(256).clamp(8, 65536) as u32;
+            Ok(Self::nic_device(name, queues, ring_size))
+        }));
+        
         patterns
     }
 }
@@ -367,7 +1446,7 @@ impl DMLSnippets {
         MethodSpec {
             name: "read".to_string(),
             parameters: vec![],
-            return_type: Some("uint32".to_string()),
+            return_type: Some("uint64".to_string()),
             body: Some("return val;".to_string()),
             documentation: Some("Read register value".to_string()),
         }
@@ -379,7 +1458,7 @@ impl DMLSnippets {
             parameters: vec![
                 ParameterSpec {
                     name: "value".to_string(),
-                    param_type: "uint32".to_string(),
+                    param_type: "uint64".to_string(),
                 }
             ],
             return_type: None,
@@ -405,6 +1484,7 @@ impl DMLSnippets {
             bits: "0".to_string(),
             access: Some("rw".to_string()),
             documentation: Some("Enable bit".to_string()),
+            templates: vec![],
         }
     }
     
@@ -414,6 +1494,7 @@ impl DMLSnippets {
             bits: "1:0".to_string(),
             access: Some("ro".to_string()),
             documentation: Some("Status field".to_string()),
+            templates: vec![],
         }
     }
     
@@ -423,6 +1504,7 @@ impl DMLSnippets {
             bits: "31".to_string(),
             access: Some("rw".to_string()),
             documentation: Some("Interrupt enable".to_string()),
+            templates: vec![],
         }
     }
 }
\ No newline at end of file
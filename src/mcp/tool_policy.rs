@@ -0,0 +1,83 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Which tools the server offers
+//!
+//! A tool policy restricts the tools of the server for deployments where
+//! clients must not be able to call some of them, such as tools changing
+//! files. It is read from a TOML file given to the server:
+//! ```toml
+//! # Only offer these tools
+//! allow = ["find_symbol", "read_symbol", "find_references"]
+//! # Never offer these tools
+//! deny = ["validate_workspace"]
+//! # Do not offer tools that can change files
+//! read_only = true
+//! ```
+//! and restricted further by the command line of the server. Tools left out
+//! are neither listed nor callable.
+
+use anyhow::{anyhow, bail, Result};
+use serde::Deserialize;
+use std::path::Path;
+
+/// Restrictions on the tools the server offers
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ToolPolicy {
+    /// The only tools offered, if given
+    pub allow: Option<Vec<String>>,
+    /// Tools never offered
+    pub deny: Vec<String>,
+    /// Leave out the tools that can change files
+    pub read_only: bool,
+}
+
+impl ToolPolicy {
+    pub fn parse(text: &str) -> Result<Self> {
+        toml::from_str(text).map_err(|e| anyhow!("{}", e.message()))
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Could not read {}: {}", path.display(), e))?;
+        Self::parse(&text)
+            .map_err(|e| anyhow!("Invalid {}: {}", path.display(), e))
+    }
+
+    /// This policy restricted further by `other`: tools must be allowed by
+    /// both, and are denied by either
+    pub fn restrict(mut self, other: ToolPolicy) -> Self {
+        self.allow = match (self.allow, other.allow) {
+            (Some(allow), Some(other)) => Some(
+                allow.into_iter().filter(|name| other.contains(name))
+                    .collect()),
+            (allow, other) => allow.or(other),
+        };
+        self.deny.extend(other.deny);
+        self.read_only |= other.read_only;
+        self
+    }
+
+    /// Whether the tool `name` is offered, where `writes_files` tells if it
+    /// can change files
+    pub fn allows(&self, name: &str, writes_files: bool) -> bool {
+        self.allow.as_ref()
+            .is_none_or(|allow| allow.iter().any(|allowed| allowed == name))
+            && !self.deny.iter().any(|denied| denied == name)
+            && !(self.read_only && writes_files)
+    }
+
+    /// Check that the policy only names tools in `known`, so that a
+    /// misspelled name does not leave a tool offered by mistake
+    pub fn check(&self, known: &[&str]) -> Result<()> {
+        let unknown: Vec<&str> = self.allow.iter().flatten()
+            .chain(&self.deny)
+            .map(String::as_str)
+            .filter(|name| !known.contains(name))
+            .collect();
+        if !unknown.is_empty() {
+            bail!("Unknown tool(s) in tool policy: {}", unknown.join(", "));
+        }
+        Ok(())
+    }
+}
@@ -3,17 +3,61 @@
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
 use log::{debug, info};
+use lsp_types::DiagnosticSeverity;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use crate::config::Config;
+use crate::analysis::deprecations::{apply_safe_rewrites, deprecated_uses};
+use crate::analysis::expansion::{expand_device, expand_object_named};
+use crate::analysis::import_cycles::{describe_cycle, find_import_cycles};
+use crate::analysis::intern;
+use crate::analysis::stdlib_versions::SimicsApiVersion;
+use crate::formatting::{format_source, FormatConfig, IndentStyle};
+use crate::migration::migrate_source;
+use crate::mcp::analysis::{analyze_device, parse_dml, parse_dml_file,
+                          ParsedDML, SourceLocation};
+use crate::mcp::catalog::{Catalog, CatalogStore, CATALOG_PATH_VAR};
+use crate::project::{ProjectConfig, ProjectStore};
+use crate::span;
+use crate::stats::{self, Phase};
+#[cfg(feature = "exporters")]
+pub use crate::mcp::exporters::tools::{ExportRegisterMapHtmlTool,
+                                      GenerateCHeaderTool, GenerateDocsTool,
+                                      GenerateTestTool, RenderFieldLayoutTool};
+use crate::mcp::generation::{parse_integer, template_of_access,
+                             DMLGenerator, DeviceParts, DeviceSpec,
+                             GeneratedCode, GenerationConfig,
+                             GenerationContext, InterfaceSpec, LineEnding,
+                             LoggingPolicy, MergeStrategy, SplitMode};
+use crate::mcp::output::{output_properties, unified_diff, OutputOptions,
+                         WriteAction};
+use crate::mcp::provenance::{spec_hash, BannerConfig, Provenance};
+use crate::mcp::templates::DMLTemplates;
+use crate::mcp::tool_policy::ToolPolicy;
+use crate::mcp::explain::{explain_diagnostic, EXPLANATIONS};
+use crate::mcp::query::{evaluate_query, parse_query};
+use crate::mcp::references::AnalyzedDevice;
+use crate::mcp::register_diff::{change_summary, diff_register_maps};
+use crate::mcp::schema::{validate_device_spec, DEVICE_SPEC_SCHEMA_URI};
+use crate::mcp::symbols::{check_symbol_kinds, declared_symbols,
+                          search_symbols, symbol_definitions,
+                          workspace_definitions, workspace_symbols,
+                          SYMBOL_KINDS};
+use crate::mcp::spec_lint::{lint_device_spec, FindingSeverity, SpecFinding};
+use crate::mcp::{ServerCapabilities, ServerInfo, MCP_VERSION,
+                 SUPPORTED_MCP_VERSIONS};
+use crate::mcp::workspace::{file_imports, find_dml_files, scan_imports,
+                            validate_parsed, validate_workspace,
+                            WorkspaceIndex};
 
 /// Tool execution result
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ToolResult {
     pub content: Vec<ToolContent>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "isError", skip_serializing_if = "Option::is_none")]
     pub is_error: Option<bool>,
 }
 
@@ -40,20 +84,31 @@ pub trait DMLTool: Send + Sync {
     fn description(&self) -> &str;
     fn input_schema(&self) -> Value;
     async fn execute(&self, input: Value) -> Result<ToolResult>;
+    /// Whether the tool can change files on disk when asked to
+    fn writes_files(&self) -> bool {
+        false
+    }
 }
 
-/// Tool registry managing all available tools
+/// Tool registry managing all available tools, listed by name
 pub struct ToolRegistry {
-    tools: HashMap<String, Box<dyn DMLTool>>,
-    config: Config,
+    tools: BTreeMap<String, Box<dyn DMLTool>>,
+    /// Tools left out by the tool policy of the server
+    disabled: BTreeSet<String>,
+    catalog: Arc<CatalogStore>,
+    project: Arc<ProjectStore>,
 }
 
 impl ToolRegistry {
     pub async fn new() -> Result<Self> {
-        let config = Config::default();
+        let project = std::env::current_dir()
+            .map(|dir| ProjectStore::discover(&dir))
+            .unwrap_or_default();
         let mut registry = Self {
-            tools: HashMap::new(),
-            config,
+            tools: BTreeMap::new(),
+            disabled: BTreeSet::new(),
+            catalog: Arc::new(CatalogStore::from_env()),
+            project: Arc::new(project),
         };
         
         // Register built-in tools
@@ -65,17 +120,58 @@ impl ToolRegistry {
     
     async fn register_builtin_tools(&mut self) -> Result<()> {
         // Device generation tools
-        self.register_tool(Box::new(GenerateDeviceTool::new())).await?;
+        self.register_tool(Box::new(GenerateDeviceTool::new(
+            Arc::clone(&self.catalog)))).await?;
         self.register_tool(Box::new(GenerateRegisterTool::new())).await?;
         self.register_tool(Box::new(GenerateMethodTool::new())).await?;
+        self.register_tool(Box::new(PreviewGenerationTool)).await?;
         
         // Analysis tools
-        self.register_tool(Box::new(AnalyzeProjectTool::new())).await?;
+        self.register_tool(Box::new(AnalyzeProjectTool)).await?;
         self.register_tool(Box::new(ValidateCodeTool::new())).await?;
+        self.register_tool(Box::new(ExplainErrorTool)).await?;
+        self.register_tool(Box::new(QueryModelTool)).await?;
+        self.register_tool(Box::new(FindSymbolTool)).await?;
+        self.register_tool(Box::new(ReadSymbolTool)).await?;
+        self.register_tool(Box::new(FindReferencesTool)).await?;
+        self.register_tool(Box::new(RenameSymbolTool)).await?;
+        self.register_tool(Box::new(ValidateWorkspaceTool)).await?;
+        self.register_tool(Box::new(DependencyGraphTool)).await?;
+        self.register_tool(Box::new(ExpandTemplatesTool)).await?;
+        self.register_tool(Box::new(ListCatalogTool::new(
+            Arc::clone(&self.catalog)))).await?;
+        self.register_tool(Box::new(ServerCapabilitiesTool::new(
+            Arc::clone(&self.catalog), Arc::clone(&self.project)))).await?;
+        self.register_tool(Box::new(ServerStatsTool)).await?;
         
         // Template tools
         self.register_tool(Box::new(GenerateTemplateTool::new())).await?;
-        self.register_tool(Box::new(ApplyPatternTool::new())).await?;
+        self.register_tool(Box::new(ApplyPatternTool)).await?;
+        
+        // Specification tools
+        self.register_tool(Box::new(NormalizeSpecTool)).await?;
+        self.register_tool(Box::new(PatchDeviceSpecTool)).await?;
+        self.register_tool(Box::new(ValidateSpecTool)).await?;
+        self.register_tool(Box::new(DiffRegisterMapsTool)).await?;
+        
+        // Migration tools
+        self.register_tool(Box::new(ModernizeFileTool)).await?;
+        self.register_tool(Box::new(MigrateDml12Tool)).await?;
+        
+        // Formatting tools
+        self.register_tool(Box::new(FormatCodeTool)).await?;
+        
+        // Test generation and export tools
+        #[cfg(feature = "exporters")]
+        {
+            self.register_tool(Box::new(GenerateTestTool)).await?;
+            self.register_tool(Box::new(GenerateCHeaderTool)).await?;
+            self.register_tool(Box::new(ExportRegisterMapHtmlTool))
+                .await?;
+            self.register_tool(Box::new(RenderFieldLayoutTool))
+                .await?;
+            self.register_tool(Box::new(GenerateDocsTool)).await?;
+        }
         
         Ok(())
     }
@@ -87,6 +183,23 @@ impl ToolRegistry {
         Ok(())
     }
     
+    /// Leave out the tools `policy` does not allow, returning their names
+    pub fn apply_policy(&mut self, policy: &ToolPolicy)
+                        -> Result<Vec<String>> {
+        let known: Vec<&str> = self.tools.keys().map(String::as_str)
+            .chain(self.disabled.iter().map(String::as_str)).collect();
+        policy.check(&known)?;
+        let disabled: Vec<String> = self.tools.values()
+            .filter(|tool| !policy.allows(tool.name(), tool.writes_files()))
+            .map(|tool| tool.name().to_string())
+            .collect();
+        for name in &disabled {
+            self.tools.remove(name);
+            self.disabled.insert(name.clone());
+        }
+        Ok(disabled)
+    }
+    
     pub fn list_tools(&self) -> Vec<ToolDefinition> {
         self.tools
             .values()
@@ -98,7 +211,26 @@ impl ToolRegistry {
             .collect()
     }
     
+    /// Whether the tool `name` can change files
+    pub fn writes_files(&self, name: &str) -> bool {
+        self.tools.get(name).is_some_and(|tool| tool.writes_files())
+    }
+    
+    /// The project of the working directory of the server
+    pub fn project(&self) -> &ProjectStore {
+        &self.project
+    }
+    
+    /// Call a tool, with the defaults of the project of the server for the
+    /// arguments left out
     pub async fn call_tool(&self, params: &Value) -> Result<Value> {
+        self.call_tool_in(params, &self.project.current()).await
+    }
+    
+    /// Call a tool, with the defaults of `project` for the arguments left
+    /// out
+    pub async fn call_tool_in(&self, params: &Value, project: &ProjectConfig)
+                              -> Result<Value> {
         let tool_name = params
             .get("name")
             .and_then(|v| v.as_str())
@@ -108,26 +240,51 @@ impl ToolRegistry {
             .get("arguments")
             .ok_or_else(|| anyhow!("Missing tool arguments"))?;
         
+        if self.disabled.contains(tool_name) {
+            return Err(anyhow!("Tool '{}' is disabled on this server",
+                               tool_name));
+        }
         let tool = self
             .tools
             .get(tool_name)
             .ok_or_else(|| anyhow!("Unknown tool: {}", tool_name))?;
         
+        let arguments = with_project_defaults(
+            &tool.input_schema(), arguments, project);
         debug!("Executing tool: {} with args: {}", tool_name, arguments);
         
-        let result = tool.execute(arguments.clone()).await?;
+        let result = tool.execute(arguments).await?;
         Ok(serde_json::to_value(result)?)
     }
 }
 
+/// The arguments of a tool call, with the defaults of the project filled in
+/// for the arguments the tool takes
+pub fn with_project_defaults(schema: &Value, arguments: &Value,
+                             project: &ProjectConfig) -> Value {
+    let (Some(properties), Some(given)) =
+        (schema["properties"].as_object(), arguments.as_object()) else {
+            return arguments.clone();
+        };
+    let mut arguments = given.clone();
+    for (name, value) in project.tool_defaults() {
+        if properties.contains_key(&name) && !arguments.contains_key(&name) {
+            arguments.insert(name, value);
+        }
+    }
+    Value::Object(arguments)
+}
+
 // ========== Built-in Tools ==========
 
 /// Generate a complete DML device
-pub struct GenerateDeviceTool;
+pub struct GenerateDeviceTool {
+    catalog: Arc<CatalogStore>,
+}
 
 impl GenerateDeviceTool {
-    pub fn new() -> Self {
-        Self
+    pub fn new(catalog: Arc<CatalogStore>) -> Self {
+        Self { catalog }
     }
 }
 
@@ -137,12 +294,16 @@ impl DMLTool for GenerateDeviceTool {
         "generate_device"
     }
     
+    fn writes_files(&self) -> bool {
+        true
+    }
+    
     fn description(&self) -> &str {
         "Generate a complete DML device model with specified configuration"
     }
     
     fn input_schema(&self) -> Value {
-        json!({
+        let mut schema = json!({
             "type": "object",
             "properties": {
                 "device_name": {
@@ -162,7 +323,18 @@ impl DMLTool for GenerateDeviceTool {
                         "properties": {
                             "name": {"type": "string"},
                             "size": {"type": "integer"},
-                            "offset": {"type": "string"}
+                            "offset": {"type": "string"},
+                            "fields": {
+                                "type": "array",
+                                "items": {
+                                    "type": "object",
+                                    "properties": {
+                                        "name": {"type": "string"},
+                                        "bits": {"type": "string"},
+                                        "access": {"type": "string"}
+                                    }
+                                }
+                            }
                         }
                     }
                 },
@@ -174,29 +346,103 @@ impl DMLTool for GenerateDeviceTool {
                 "template_base": {
                     "type": "string",
                     "description": "Base template to inherit from"
+                },
+                "spec_file": {
+                    "type": "string",
+                    "description": "Path to a YAML, TOML or JSON device specification, or an SVD or IP-XACT register description, to generate the device from, instead of the other arguments"
+                },
+                "line_ending": line_ending_property(),
+                "split": {
+                    "type": "string",
+                    "enum": ["none", "sections", "banks"],
+                    "description": "Split the device across files: 'sections' puts the banks and the interfaces in files of their own, 'banks' puts each bank in a file of its own. The main file imports the others.",
+                    "default": "none"
+                },
+                "logging": {
+                    "type": "string",
+                    "enum": ["none", "minimal", "verbose"],
+                    "description": "Log statements added to the read and write methods of the registers of a spec_file: 'minimal' logs written values and writes to reserved bits, 'verbose' also logs reads",
+                    "default": "none"
+                },
+                "banner": {
+                    "type": "boolean",
+                    "description": "Start the files generated from a spec_file with a banner recording the server version, the specification file and its hash, which analyze_project recognizes",
+                    "default": false
+                },
+                "banner_timestamp": {
+                    "type": "boolean",
+                    "description": "Also record the time of generation in the banner, which makes the output differ between runs",
+                    "default": false
                 }
             },
-            "required": ["device_name", "device_type"]
-        })
+            "anyOf": [
+                {"required": ["device_name", "device_type"]},
+                {"required": ["spec_file"]}
+            ]
+        });
+        with_output_properties(&mut schema);
+        schema
     }
     
     async fn execute(&self, input: Value) -> Result<ToolResult> {
-        let device_name = input["device_name"]
-            .as_str()
-            .ok_or_else(|| anyhow!("Missing device_name"))?;
-        
-        let device_type = input["device_type"]
-            .as_str()
-            .ok_or_else(|| anyhow!("Missing device_type"))?;
+        let line_ending = line_ending_from_input(&input)?;
+        let split = split_from_input(&input)?;
+        let logging = logging_from_input(&input)?;
+        let output = OutputOptions::from_input(&input)?;
+        let (files, stats) = if let Some(spec_file) = input["spec_file"].as_str() {
+            let spec = DeviceSpec::load(Path::new(spec_file))?;
+            let banner = input["banner"].as_bool().unwrap_or(false)
+                .then(|| BannerConfig {
+                    spec_file: Path::new(spec_file).file_name()
+                        .map(|name| name.to_string_lossy().to_string()),
+                    timestamp: input["banner_timestamp"].as_bool()
+                        .unwrap_or(false),
+                });
+            let config = GenerationConfig {
+                line_ending,
+                split,
+                logging,
+                banner,
+                ..GenerationConfig::default()
+            };
+            generate_device_files_with_catalog(&spec, config,
+                                               self.catalog.current()).await?
+        } else {
+            let device_name = input["device_name"]
+                .as_str()
+                .ok_or_else(|| anyhow!("Missing device_name"))?;
+            
+            let device_type = input["device_type"]
+                .as_str()
+                .ok_or_else(|| anyhow!("Missing device_type"))?;
+            
+            // Generate device code based on parameters
+            generate_device_code(device_name, device_type, &input,
+                                 &self.catalog.current(), line_ending, split)?
+        };
         
-        // Generate device code based on parameters
-        let generated_code = generate_device_code(device_name, device_type, &input)?;
+        // The code of each file, followed by the file names and statistics
+        let mut summary = json!({ "stats": stats });
+        if split != SplitMode::None {
+            summary["files"] = files.iter()
+                .map(|file| file.file_path.clone()).collect();
+        }
+        if let Some(output) = output {
+            summary["output"] = written_files(&output, &files)?;
+        }
+        let mut content: Vec<ToolContent> = files.into_iter()
+            .map(|file| ToolContent {
+                content_type: "text".to_string(),
+                text: file.content,
+            })
+            .collect();
+        content.push(ToolContent {
+            content_type: "text".to_string(),
+            text: serde_json::to_string_pretty(&summary)?,
+        });
         
         Ok(ToolResult {
-            content: vec![ToolContent {
-                content_type: "text".to_string(),
-                text: generated_code,
-            }],
+            content,
             is_error: None,
         })
     }
@@ -237,6 +483,10 @@ impl DMLTool for GenerateRegisterTool {
                     "type": "string",
                     "description": "Offset address (e.g., '0x100')"
                 },
+                "access": {
+                    "type": "string",
+                    "description": "Access type of the whole register (e.g., 'ro', 'w1c' or 'unimpl'), implemented by the standard template for it"
+                },
                 "fields": {
                     "type": "array",
                     "description": "Register fields",
@@ -252,7 +502,8 @@ impl DMLTool for GenerateRegisterTool {
                 "documentation": {
                     "type": "string",
                     "description": "Documentation for the register"
-                }
+                },
+                "line_ending": line_ending_property()
             },
             "required": ["name", "size"]
         })
@@ -267,7 +518,8 @@ impl DMLTool for GenerateRegisterTool {
             .as_u64()
             .ok_or_else(|| anyhow!("Missing or invalid register size"))?;
         
-        let generated_code = generate_register_code(register_name, size, &input)?;
+        let generated_code = generate_register_code(
+            register_name, size, &input, line_ending_from_input(&input)?)?;
         
         Ok(ToolResult {
             content: vec![ToolContent {
@@ -279,103 +531,2482 @@ impl DMLTool for GenerateRegisterTool {
     }
 }
 
-// Placeholder implementations for other tools
-macro_rules! impl_placeholder_tool {
-    ($name:ident, $tool_name:expr, $description:expr) => {
-        pub struct $name;
-        
-        impl $name {
-            pub fn new() -> Self {
-                Self
-            }
+/// JSON schema of the `line_ending` argument of tools that generate code
+fn line_ending_property() -> Value {
+    json!({
+        "type": "string",
+        "enum": ["unix", "windows"],
+        "description": "Line endings of the generated code, \"unix\" (LF, the default) or \"windows\" (CRLF)"
+    })
+}
+
+/// The line endings requested by the `line_ending` argument of a tool
+fn line_ending_from_input(input: &Value) -> Result<LineEnding> {
+    match input["line_ending"].as_str() {
+        None | Some("unix") => Ok(LineEnding::Unix),
+        Some("windows") => Ok(LineEnding::Windows),
+        Some(other) => Err(anyhow!("Unknown line ending: {}", other)),
+    }
+}
+
+/// Add the arguments for writing generated files to disk to the schema of
+/// a tool
+fn with_output_properties(schema: &mut Value) {
+    if let (Some(properties), Value::Object(output)) =
+        (schema["properties"].as_object_mut(), output_properties()) {
+        properties.extend(output);
+    }
+}
+
+/// Write generated files as asked by the output arguments of a tool,
+/// describing what was, or in a dry run would be, written
+fn written_files(output: &OutputOptions, files: &[GeneratedCode])
+                 -> Result<Value> {
+    Ok(json!({
+        "dir": output.dir,
+        "dry_run": output.dry_run,
+        "files": output.write(files)?,
+    }))
+}
+
+/// How the `split` argument of a tool asks to split generated code
+fn split_from_input(input: &Value) -> Result<SplitMode> {
+    match input["split"].as_str() {
+        None | Some("none") => Ok(SplitMode::None),
+        Some("sections") => Ok(SplitMode::Sections),
+        Some("banks") => Ok(SplitMode::Banks),
+        Some(other) => Err(anyhow!("Unknown split: {}", other)),
+    }
+}
+
+/// The logging policy requested by the `logging` argument of a tool
+fn logging_from_input(input: &Value) -> Result<LoggingPolicy> {
+    match input["logging"].as_str() {
+        None | Some("none") => Ok(LoggingPolicy::None),
+        Some("minimal") => Ok(LoggingPolicy::Minimal),
+        Some("verbose") => Ok(LoggingPolicy::Verbose),
+        Some(other) => Err(anyhow!("Unknown logging policy: {}", other)),
+    }
+}
+
+/// JSON schema properties shared by tools that operate on a device, which can
+/// be given either as a DeviceSpec, inline or in a file, or as DML source
+pub(crate) fn device_source_properties() -> Value {
+    json!({
+        "device_spec": {
+            "type": "object",
+            "description": "Device specification (as accepted by the generator)"
+        },
+        "spec_file": {
+            "type": "string",
+            "description": "Path to a YAML, TOML or JSON device specification, \
+                            or an SVD (.svd) or IP-XACT (.xml) register \
+                            description"
+        },
+        "file": {
+            "type": "string",
+            "description": "Path to a DML file to extract the device from"
+        },
+        "code": {
+            "type": "string",
+            "description": "DML source to extract the device from"
         }
-        
-        #[async_trait]
-        impl DMLTool for $name {
-            fn name(&self) -> &str {
-                $tool_name
-            }
-            
-            fn description(&self) -> &str {
-                $description
-            }
-            
-            fn input_schema(&self) -> Value {
-                json!({"type": "object", "properties": {}})
-            }
-            
-            async fn execute(&self, _input: Value) -> Result<ToolResult> {
-                Ok(ToolResult {
-                    content: vec![ToolContent {
-                        content_type: "text".to_string(),
-                        text: format!("Tool '{}' is not yet implemented", self.name()),
-                    }],
-                    is_error: Some(false),
-                })
-            }
+    })
+}
+
+/// Obtain the device a tool operates on from its `device_spec`, `spec_file`,
+/// `file` or `code` argument
+pub fn device_spec_from_input(input: &Value) -> Result<DeviceSpec> {
+    if let Some(spec) = input.get("device_spec") {
+        return validate_device_spec(spec)
+            .and_then(|()| Ok(serde_json::from_value(spec.clone())?))
+            .map_err(|e| anyhow!("Invalid device_spec: {}", e));
+    }
+    if let Some(spec_file) = input["spec_file"].as_str() {
+        return DeviceSpec::load(Path::new(spec_file));
+    }
+    if let Some(file) = input["file"].as_str() {
+        return Ok(parse_dml_file(Path::new(file))?.to_device_spec());
+    }
+    if let Some(code) = input["code"].as_str() {
+        return Ok(parse_dml(Path::new("input.dml"), code)?.to_device_spec());
+    }
+    Err(anyhow!("One of device_spec, spec_file, file or code is required"))
+}
+
+/// Obtain the optional `simics_api` argument of a tool
+fn simics_api_from_input(input: &Value) -> Result<Option<SimicsApiVersion>> {
+    match &input["simics_api"] {
+        Value::Null => Ok(None),
+        Value::Number(n) => n.to_string().parse().map(Some)
+            .map_err(|e: String| anyhow!(e)),
+        Value::String(s) => s.parse().map(Some)
+            .map_err(|e: String| anyhow!(e)),
+        other => Err(anyhow!("Invalid Simics API version {}", other)),
+    }
+}
+
+/// JSON schema properties of tools that analyze a workspace, which fall back
+/// to analyzing a single file when no workspace is given
+fn workspace_properties() -> Value {
+    json!({
+        "workspace": {
+            "type": "string",
+            "description": "Root directory of the DML workspace"
+        },
+        "file": {
+            "type": "string",
+            "description": "Path to a DML file to analyze on its own when no workspace is given"
+        },
+        "code": {
+            "type": "string",
+            "description": "DML source to analyze on its own when no workspace is given"
         }
-    };
+    })
 }
 
-impl_placeholder_tool!(GenerateMethodTool, "generate_method", "Generate DML method implementation");
-impl_placeholder_tool!(AnalyzeProjectTool, "analyze_project", "Analyze existing DML project structure");
-impl_placeholder_tool!(ValidateCodeTool, "validate_code", "Validate DML code syntax and semantics");
-impl_placeholder_tool!(GenerateTemplateTool, "generate_template", "Generate reusable DML templates");
-impl_placeholder_tool!(ApplyPatternTool, "apply_pattern", "Apply common DML design patterns");
+/// Obtain the single file a workspace tool analyzes without a workspace,
+/// from its `file` or `code` argument
+fn standalone_file_from_input(input: &Value) -> Result<Option<ParsedDML>> {
+    if let Some(file) = input["file"].as_str() {
+        return parse_dml_file(Path::new(file)).map(Some);
+    }
+    if let Some(code) = input["code"].as_str() {
+        return parse_dml(Path::new("input.dml"), code).map(Some);
+    }
+    Ok(None)
+}
 
-// ========== Code Generation Functions ==========
+/// Explain why the result of a workspace tool is partial, when it was run
+/// without a workspace on the file (if any) given as `file` or `code`
+fn without_workspace(standalone: bool, explanation: &str) -> Value {
+    json!({
+        "mode": if standalone { "single_file" } else { "none" },
+        "reason": "No workspace root was given",
+        "explanation": explanation,
+    })
+}
 
-fn generate_device_code(name: &str, device_type: &str, params: &Value) -> Result<String> {
-    let template_base = params["template_base"]
-        .as_str()
-        .unwrap_or("base_device");
-    
-    let mut code = format!(
-        r#"dml 1.4;
+/// List the interfaces and templates known to the server
+pub struct ListCatalogTool {
+    catalog: Arc<CatalogStore>,
+}
+
+impl ListCatalogTool {
+    pub fn new(catalog: Arc<CatalogStore>) -> Self {
+        Self { catalog }
+    }
+}
 
-device {} : {} {{
-    /// Generated {} device
+#[async_trait]
+impl DMLTool for ListCatalogTool {
+    fn name(&self) -> &str {
+        "list_catalog"
+    }
     
-"#,
-        name, template_base, device_type
-    );
+    fn description(&self) -> &str {
+        "List the Simics interfaces and DML templates known to the server, \
+         including site-specific entries from catalog data files"
+    }
     
-    // Add registers if specified
-    if let Some(registers) = params["registers"].as_array() {
-        code.push_str("    bank registers {\n");
-        for register in registers {
-            if let (Some(reg_name), Some(reg_size)) = 
-                (register["name"].as_str(), register["size"].as_u64()) {
-                let offset = register["offset"].as_str().unwrap_or("undefined");
-                code.push_str(&format!(
-                    "        register {} size {} @ {};\n",
-                    reg_name, reg_size, offset
-                ));
+    fn input_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "kind": {
+                    "type": "string",
+                    "enum": ["interfaces", "templates"],
+                    "description": "Only list entries of this kind"
+                },
+                "filter": {
+                    "type": "string",
+                    "description": "Only list entries whose name contains this string"
+                },
+                "simics_api": {
+                    "type": ["string", "integer"],
+                    "description": "Only list entries available in this Simics API version, e.g. 7"
+                }
             }
-        }
-        code.push_str("    }\n");
+        })
     }
     
-    // Add interfaces if specified
-    if let Some(interfaces) = params["interfaces"].as_array() {
-        for interface in interfaces {
-            if let Some(iface) = interface.as_str() {
-                code.push_str(&format!("    implement {};\n", iface));
-            }
+    async fn execute(&self, input: Value) -> Result<ToolResult> {
+        let mut catalog = self.catalog.current();
+        if let Some(version) = simics_api_from_input(&input)? {
+            catalog = Arc::new(catalog.for_simics_api(version));
+        }
+        let filter = input["filter"].as_str().unwrap_or("");
+        let kind = input["kind"].as_str();
+        
+        let mut result = json!({});
+        if kind.is_none() || kind == Some("interfaces") {
+            result["interfaces"] = json!(catalog.interfaces.values()
+                .filter(|info| info.name.contains(filter))
+                .collect::<Vec<_>>());
+        }
+        if kind.is_none() || kind == Some("templates") {
+            result["templates"] = json!(catalog.templates.values()
+                .filter(|info| info.name.contains(filter))
+                .collect::<Vec<_>>());
         }
+        
+        Ok(ToolResult {
+            content: vec![ToolContent {
+                content_type: "text".to_string(),
+                text: serde_json::to_string_pretty(&result)?,
+            }],
+            is_error: None,
+        })
+    }
+}
+
+/// Describe the optional features of the server, so that clients can adapt
+/// to them instead of probing
+pub struct ServerCapabilitiesTool {
+    catalog: Arc<CatalogStore>,
+    project: Arc<ProjectStore>,
+}
+
+impl ServerCapabilitiesTool {
+    pub fn new(catalog: Arc<CatalogStore>, project: Arc<ProjectStore>)
+               -> Self {
+        Self { catalog, project }
+    }
+}
+
+#[async_trait]
+impl DMLTool for ServerCapabilitiesTool {
+    fn name(&self) -> &str {
+        "server_capabilities"
     }
     
-    code.push_str("}\n");
+    fn description(&self) -> &str {
+        "Report the optional subsystems enabled in this server: transports, \
+         validation backends, specification importers and exporters, and the \
+         catalog data and project files in use"
+    }
     
-    Ok(code)
+    fn input_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {}
+        })
+    }
+    
+    async fn execute(&self, _input: Value) -> Result<ToolResult> {
+        let catalog = self.catalog.current();
+        let sources: Vec<Value> = self.catalog.sources().iter()
+            .map(|path| json!({
+                "path": path,
+                "kind": if path.is_dir() { "directory" }
+                        else if path.is_file() { "file" }
+                        else { "missing" },
+            }))
+            .collect();
+        let spec_formats: &[&str] = if cfg!(feature = "importers") {
+            &["json", "yaml", "toml", "svd", "ip-xact"]
+        } else {
+            &["json"]
+        };
+        let exporters: &[&str] = if cfg!(feature = "exporters") {
+            &["generate_c_header", "export_register_map_html", "generate_test",
+              "render_field_layout", "generate_docs"]
+        } else {
+            &[]
+        };
+        let result = json!({
+            "server": ServerInfo::default(),
+            "protocol_version": MCP_VERSION,
+            "protocol_versions": SUPPORTED_MCP_VERSIONS,
+            "capabilities": ServerCapabilities::default(),
+            "transports": ["stdio"],
+            "validation": {
+                // Generated and existing code is checked by the parser and
+                // analysis of the language server, not by dmlc
+                "builtin_parser": true,
+                "dmlc": false,
+            },
+            // Cargo features the server was built with
+            "features": {
+                "lsp": cfg!(feature = "lsp"),
+                "importers": cfg!(feature = "importers"),
+                "exporters": cfg!(feature = "exporters"),
+            },
+            "importers": {
+                "device_spec_formats": spec_formats,
+                "dml_source": true,
+                "device_spec_schema": DEVICE_SPEC_SCHEMA_URI,
+            },
+            "exporters": exporters,
+            "line_endings": ["unix", "windows"],
+            "catalog": {
+                "env_var": CATALOG_PATH_VAR,
+                "sources": sources,
+                "interfaces": catalog.interfaces.len(),
+                "templates": catalog.templates.len(),
+            },
+            "project": {
+                "path": self.project.path(),
+                "exists": self.project.path().is_some_and(Path::is_file),
+                "defaults": self.project.current().tool_defaults(),
+            },
+            "patterns": DMLTemplates::get_pattern_templates().into_keys()
+                .collect::<BTreeSet<_>>(),
+            "analysis_threads": rayon::current_num_threads(),
+        });
+        
+        Ok(ToolResult {
+            content: vec![ToolContent {
+                content_type: "text".to_string(),
+                text: serde_json::to_string_pretty(&result)?,
+            }],
+            is_error: None,
+        })
+    }
+}
+
+/// Report the work done by the server since it started, for performance
+/// reports
+#[derive(Default)]
+pub struct ServerStatsTool;
+
+#[async_trait]
+impl DMLTool for ServerStatsTool {
+    fn name(&self) -> &str {
+        "server_stats"
+    }
+
+    fn description(&self) -> &str {
+        "Report the time spent parsing, resolving and linting since the \
+         server started, the hit rates of its caches, the number of files \
+         parsed and an estimate of the memory it holds"
+    }
+
+    fn input_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {}
+        })
+    }
+
+    async fn execute(&self, _input: Value) -> Result<ToolResult> {
+        let stats = stats::snapshot();
+        let (interned_names, interned_name_bytes) = intern::interned();
+        let result = json!({
+            "files_parsed": stats.phases.get(&Phase::Parse)
+                .map_or(0, |parse| parse.count),
+            "phases": stats.phases.iter()
+                .map(|(phase, totals)| (phase.name().to_string(), json!({
+                    "count": totals.count,
+                    "total_ms": totals.total_ms,
+                    "max_ms": totals.max_ms,
+                })))
+                .collect::<serde_json::Map<_, _>>(),
+            "caches": stats.caches.iter()
+                .map(|(cache, lookups)| (cache.name().to_string(), json!({
+                    "hits": lookups.hits,
+                    "misses": lookups.misses,
+                    "hit_rate": lookups.hit_rate,
+                })))
+                .collect::<serde_json::Map<_, _>>(),
+            "memory": {
+                "resident_bytes": stats::resident_bytes(),
+                "interned_names": interned_names,
+                "interned_name_bytes": interned_name_bytes,
+                "interned_paths": span::tracked_paths(),
+            },
+        });
+
+        Ok(ToolResult {
+            content: vec![ToolContent {
+                content_type: "text".to_string(),
+                text: serde_json::to_string_pretty(&result)?,
+            }],
+            is_error: None,
+        })
+    }
 }
 
-fn generate_register_code(name: &str, size: u64, params: &Value) -> Result<String> {
-    let mut code = format!("register {} size {} {{\n", name, size);
+/// Replace deprecated names in a DML file
+#[derive(Default)]
+pub struct ModernizeFileTool;
+
+#[async_trait]
+impl DMLTool for ModernizeFileTool {
+    fn name(&self) -> &str {
+        "modernize_file"
+    }
     
-    // Add documentation if provided
-    if let Some(doc) = params["documentation"].as_str() {
-        code.push_str(&format!("    /// {}\n", doc));
+    fn writes_files(&self) -> bool {
+        true
+    }
+    
+    fn description(&self) -> &str {
+        "Apply all safe rewrites of deprecated template and parameter names \
+         in a DML file, and list the deprecated uses that need manual review"
+    }
+    
+    fn input_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "file": {
+                    "type": "string",
+                    "description": "Path to the DML file to modernize"
+                },
+                "code": {
+                    "type": "string",
+                    "description": "DML source to modernize"
+                },
+                "simics_api": {
+                    "type": ["string", "integer"],
+                    "description": "Simics API version to modernize for, e.g. 7; without it only DML 1.4 renames are applied"
+                },
+                "write": {
+                    "type": "boolean",
+                    "description": "Write the result back to 'file'",
+                    "default": false
+                }
+            }
+        })
+    }
+    
+    async fn execute(&self, input: Value) -> Result<ToolResult> {
+        let simics_api = simics_api_from_input(&input)?;
+        let write = input["write"].as_bool().unwrap_or(false);
+        if write && input["file"].as_str().is_none() {
+            return Err(anyhow!("'write' requires 'file'"));
+        }
+        let parsed = match (input["file"].as_str(), input["code"].as_str()) {
+            (Some(file), _) => parse_dml_file(Path::new(file))?,
+            (None, Some(code)) => parse_dml(Path::new("input.dml"), code)?,
+            (None, None) => return Err(anyhow!("One of file or code is required")),
+        };
+        let uses = deprecated_uses(&parsed.toplevel, simics_api);
+        let (source, applied) = apply_safe_rewrites(&parsed.text, &uses);
+        let manual: Vec<Value> = uses.iter()
+            .filter(|use_| use_.safe_rewrite().is_none())
+            .map(|use_| json!({
+                "name": use_.deprecation.name,
+                "line": use_.span.range.row_start.0 + 1,
+                "column": use_.span.range.col_start.0 + 1,
+                "message": use_.description(),
+            }))
+            .collect();
+        let written = write && applied > 0;
+        if written {
+            let file = input["file"].as_str().unwrap_or_default();
+            std::fs::write(file, &source)
+                .map_err(|e| anyhow!("Could not write {}: {}", file, e))?;
+            info!("Applied {} rewrites to {}", applied, file);
+        }
+        
+        let result = json!({
+            "applied": applied,
+            "written": written,
+            "manual_review": manual,
+            "source": source,
+        });
+        Ok(ToolResult {
+            content: vec![ToolContent {
+                content_type: "text".to_string(),
+                text: serde_json::to_string_pretty(&result)?,
+            }],
+            is_error: None,
+        })
+    }
+}
+
+/// Migrate DML 1.2 code to DML 1.4 as `dls migrate` does
+#[derive(Default)]
+pub struct MigrateDml12Tool;
+
+#[async_trait]
+impl DMLTool for MigrateDml12Tool {
+    fn name(&self) -> &str {
+        "migrate_dml12"
+    }
+    
+    fn writes_files(&self) -> bool {
+        true
+    }
+    
+    fn description(&self) -> &str {
+        "Migrate a DML 1.2 file to DML 1.4, rewriting the version, parameter \
+         and data declarations, method signatures, call and inline \
+         statements, arrays and bit ranges, and list the constructs that \
+         need to be migrated by hand"
+    }
+    
+    fn input_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "file": {
+                    "type": "string",
+                    "description": "Path to the DML 1.2 file to migrate"
+                },
+                "code": {
+                    "type": "string",
+                    "description": "DML 1.2 source to migrate"
+                },
+                "write": {
+                    "type": "boolean",
+                    "description": "Write the result back to 'file'",
+                    "default": false
+                }
+            }
+        })
+    }
+    
+    async fn execute(&self, input: Value) -> Result<ToolResult> {
+        let write = input["write"].as_bool().unwrap_or(false);
+        if write && input["file"].as_str().is_none() {
+            return Err(anyhow!("'write' requires 'file'"));
+        }
+        let code = match (input["file"].as_str(), input["code"].as_str()) {
+            (Some(file), _) => std::fs::read_to_string(file)
+                .map_err(|e| anyhow!("Could not read {}: {}", file, e))?,
+            (None, Some(code)) => code.to_string(),
+            (None, None) => return Err(anyhow!("One of file or code is required")),
+        };
+        let migration = migrate_source(&code)?;
+        let manual: Vec<Value> = migration.notes.iter()
+            .map(|note| json!({
+                "line": note.line + 1,
+                "message": note.message,
+            }))
+            .collect();
+        if write {
+            let file = input["file"].as_str().unwrap_or_default();
+            std::fs::write(file, &migration.code)
+                .map_err(|e| anyhow!("Could not write {}: {}", file, e))?;
+            info!("Migrated {} with {} rewrites", file, migration.rewrites);
+        }
+        
+        let result = json!({
+            "rewrites": migration.rewrites,
+            "written": write,
+            "manual_review": manual,
+            "source": migration.code,
+        });
+        Ok(ToolResult {
+            content: vec![ToolContent {
+                content_type: "text".to_string(),
+                text: serde_json::to_string_pretty(&result)?,
+            }],
+            is_error: None,
+        })
+    }
+}
+
+/// Format DML code as `dls fmt` does
+#[derive(Default)]
+pub struct FormatCodeTool;
+
+/// The formatting of a tool from its optional `indent` and
+/// `max_line_length` arguments
+fn format_config_from_input(input: &Value) -> Result<FormatConfig> {
+    let mut config = FormatConfig::default();
+    match &input["indent"] {
+        Value::Null => (),
+        Value::String(tabs) if tabs == "tabs" =>
+            config.indent_style = IndentStyle::Tabs,
+        Value::Number(n) if n.as_u64().is_some() =>
+            config.indent_style = IndentStyle::Spaces(
+                n.as_u64().unwrap() as usize),
+        other => return Err(anyhow!("Invalid indent {}, expected a number \
+                                     of spaces or \"tabs\"", other)),
+    }
+    if let Some(length) = input.get("max_line_length")
+        .filter(|length| !length.is_null()) {
+        config.max_line_length = length.as_u64()
+            .ok_or_else(|| anyhow!("max_line_length must be a number"))?
+            as usize;
+    }
+    Ok(config)
+}
+
+#[async_trait]
+impl DMLTool for FormatCodeTool {
+    fn name(&self) -> &str {
+        "format_code"
+    }
+    
+    fn writes_files(&self) -> bool {
+        true
+    }
+    
+    fn description(&self) -> &str {
+        "Format DML code, re-indenting and re-spacing it without changing \
+         its tokens. Code with syntax errors is not formatted."
+    }
+    
+    fn input_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "file": {
+                    "type": "string",
+                    "description": "Path to the DML file to format"
+                },
+                "code": {
+                    "type": "string",
+                    "description": "DML source to format"
+                },
+                "indent": {
+                    "type": ["integer", "string"],
+                    "description": "Spaces per indentation level, or \"tabs\"",
+                    "default": 4
+                },
+                "max_line_length": {
+                    "type": "integer",
+                    "description": "Lines continued inside parentheses are aligned with the parenthesis only if they fit in this many columns",
+                    "default": 100
+                },
+                "write": {
+                    "type": "boolean",
+                    "description": "Write the result back to 'file'",
+                    "default": false
+                }
+            }
+        })
+    }
+    
+    async fn execute(&self, input: Value) -> Result<ToolResult> {
+        let config = format_config_from_input(&input)?;
+        let write = input["write"].as_bool().unwrap_or(false);
+        let code = match (input["file"].as_str(), input["code"].as_str()) {
+            (Some(file), _) => std::fs::read_to_string(file)
+                .map_err(|e| anyhow!("Could not read {}: {}", file, e))?,
+            (None, Some(code)) => code.to_string(),
+            (None, None) => return Err(anyhow!("One of file or code is required")),
+        };
+        if write && input["file"].as_str().is_none() {
+            return Err(anyhow!("'write' requires 'file'"));
+        }
+        let formatted = format_source(&code, &config)?;
+        let changed = formatted != code;
+        if write && changed {
+            let file = input["file"].as_str().unwrap_or_default();
+            std::fs::write(file, &formatted)
+                .map_err(|e| anyhow!("Could not write {}: {}", file, e))?;
+            info!("Formatted {}", file);
+        }
+        
+        let result = json!({
+            "changed": changed,
+            "written": write && changed,
+            "code": formatted,
+        });
+        Ok(ToolResult {
+            content: vec![ToolContent {
+                content_type: "text".to_string(),
+                text: serde_json::to_string_pretty(&result)?,
+            }],
+            is_error: None,
+        })
+    }
+}
+
+/// Canonicalize a device specification
+#[derive(Default)]
+pub struct NormalizeSpecTool;
+
+#[async_trait]
+impl DMLTool for NormalizeSpecTool {
+    fn name(&self) -> &str {
+        "normalize_spec"
+    }
+    
+    fn description(&self) -> &str {
+        "Canonicalize a device specification (sort registers by offset, \
+         normalize bit ranges, fill in defaults, remove duplicate imports) \
+         so that independently produced specifications can be diffed"
+    }
+    
+    fn input_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": device_source_properties()
+        })
+    }
+    
+    async fn execute(&self, input: Value) -> Result<ToolResult> {
+        let mut spec = device_spec_from_input(&input)?;
+        spec.normalize();
+        
+        Ok(ToolResult {
+            content: vec![ToolContent {
+                content_type: "text".to_string(),
+                text: serde_json::to_string_pretty(&spec)?,
+            }],
+            is_error: None,
+        })
+    }
+}
+
+/// Check a device specification for problems without generating code
+#[derive(Default)]
+pub struct ValidateSpecTool;
+
+#[async_trait]
+impl DMLTool for ValidateSpecTool {
+    fn name(&self) -> &str {
+        "validate_spec"
+    }
+    
+    fn description(&self) -> &str {
+        "Check a device specification for problems before generating code: \
+         schema violations, empty banks, duplicate registers and fields, \
+         fields wider than their registers, unknown access types and \
+         missing documentation"
+    }
+    
+    fn input_schema(&self) -> Value {
+        let source = device_source_properties();
+        json!({
+            "type": "object",
+            "properties": {
+                "device_spec": source["device_spec"],
+                "spec_file": source["spec_file"],
+                "generate_docs": {
+                    "type": "boolean",
+                    "description": "Whether documentation will be generated, \
+                                    so that missing documentation is reported",
+                    "default": true
+                }
+            },
+            "anyOf": [
+                {"required": ["device_spec"]},
+                {"required": ["spec_file"]}
+            ]
+        })
+    }
+    
+    async fn execute(&self, input: Value) -> Result<ToolResult> {
+        let config = GenerationConfig {
+            generate_docs: input["generate_docs"].as_bool().unwrap_or(true),
+            ..GenerationConfig::default()
+        };
+        let spec = match (input.get("device_spec"), input["spec_file"].as_str()) {
+            (Some(_), _) => device_spec_from_input(&input),
+            (None, Some(spec_file)) => {
+                let path = Path::new(spec_file);
+                let text = std::fs::read_to_string(path).map_err(
+                    |e| anyhow!("Could not read {}: {}", path.display(), e))?;
+                DeviceSpec::parse(path, &text)
+            },
+            (None, None) => return Err(anyhow!(
+                "One of device_spec or spec_file is required")),
+        };
+        // Schema violations are findings rather than errors of the tool
+        let findings = match spec {
+            Ok(spec) => lint_device_spec(&spec, &config),
+            Err(e) => vec![SpecFinding {
+                severity: FindingSeverity::Error,
+                code: "schema".to_string(),
+                path: String::new(),
+                message: e.to_string(),
+            }],
+        };
+        let count = |severity| findings.iter()
+            .filter(|finding| finding.severity == severity).count();
+        let result = json!({
+            "valid": count(FindingSeverity::Error) == 0,
+            "errors": count(FindingSeverity::Error),
+            "warnings": count(FindingSeverity::Warning),
+            "findings": findings,
+        });
+        
+        Ok(ToolResult {
+            content: vec![ToolContent {
+                content_type: "text".to_string(),
+                text: serde_json::to_string_pretty(&result)?,
+            }],
+            is_error: None,
+        })
+    }
+}
+
+/// Compare the register maps of two devices
+#[derive(Default)]
+pub struct DiffRegisterMapsTool;
+
+#[async_trait]
+impl DMLTool for DiffRegisterMapsTool {
+    fn name(&self) -> &str {
+        "diff_register_maps"
+    }
+    
+    fn description(&self) -> &str {
+        "Compare the register maps of two devices, such as two steppings of \
+         a model or a model and the SVD or IP-XACT description of the \
+         silicon, and report added, removed, renamed and moved registers, \
+         changed sizes, reset values and access types, and added, removed, \
+         renamed and moved fields"
+    }
+    
+    fn input_schema(&self) -> Value {
+        let side = |description: &str| json!({
+            "type": "object",
+            "description": description,
+            "properties": device_source_properties()
+        });
+        json!({
+            "type": "object",
+            "properties": {
+                "old": side("The device before the change, given as \
+                             device_spec, spec_file, file or code"),
+                "new": side("The device after the change, given as \
+                             device_spec, spec_file, file or code")
+            },
+            "required": ["old", "new"]
+        })
+    }
+    
+    async fn execute(&self, input: Value) -> Result<ToolResult> {
+        let old = device_spec_from_input(&input["old"])
+            .map_err(|e| anyhow!("old: {}", e))?;
+        let new = device_spec_from_input(&input["new"])
+            .map_err(|e| anyhow!("new: {}", e))?;
+        let changes = diff_register_maps(&old, &new);
+        let result = json!({
+            "identical": changes.is_empty(),
+            "summary": change_summary(&changes),
+            "changes": changes,
+        });
+        
+        Ok(ToolResult {
+            content: vec![ToolContent {
+                content_type: "text".to_string(),
+                text: serde_json::to_string_pretty(&result)?,
+            }],
+            is_error: None,
+        })
+    }
+}
+
+/// Refine a device specification with a JSON merge patch or by merging
+/// another specification into it
+#[derive(Default)]
+pub struct PatchDeviceSpecTool;
+
+#[async_trait]
+impl DMLTool for PatchDeviceSpecTool {
+    fn name(&self) -> &str {
+        "patch_device_spec"
+    }
+    
+    fn writes_files(&self) -> bool {
+        true
+    }
+    
+    fn description(&self) -> &str {
+        "Modify a device specification, by merging another specification \
+         into it or by applying a JSON merge patch (RFC 7396), and return \
+         the result or write it back to the specification file"
+    }
+    
+    fn input_schema(&self) -> Value {
+        let source = device_source_properties();
+        json!({
+            "type": "object",
+            "properties": {
+                "device_spec": source["device_spec"],
+                "spec_file": source["spec_file"],
+                "merge": {
+                    "type": "object",
+                    "description": "Device specification to merge in: banks, \
+                                    interfaces and connects of the same \
+                                    name are combined, and other objects \
+                                    are added"
+                },
+                "strategy": {
+                    "type": "string",
+                    "enum": ["error", "prefer_existing", "prefer_new"],
+                    "description": "How to resolve registers that overlap, \
+                                    and objects with the same name but \
+                                    different definitions, when merging",
+                    "default": "error"
+                },
+                "patch": {
+                    "type": "object",
+                    "description": "JSON merge patch applied after merge: \
+                                    members replace those of the \
+                                    specification, null removes them, and \
+                                    arrays are replaced as a whole"
+                },
+                "write": {
+                    "type": "boolean",
+                    "description": "Write the result back to spec_file, in \
+                                    its format",
+                    "default": false
+                }
+            },
+            "anyOf": [
+                {"required": ["device_spec"]},
+                {"required": ["spec_file"]}
+            ]
+        })
+    }
+    
+    async fn execute(&self, input: Value) -> Result<ToolResult> {
+        let mut spec = device_spec_from_input(&input)?;
+        if !input["merge"].is_null() {
+            validate_device_spec(&input["merge"])
+                .map_err(|e| anyhow!("Invalid merge: {}", e))?;
+            let strategy = match &input["strategy"] {
+                Value::Null => MergeStrategy::default(),
+                strategy => serde_json::from_value(strategy.clone())
+                    .map_err(|_| anyhow!("Unknown merge strategy: {}",
+                                         strategy))?,
+            };
+            spec.merge(serde_json::from_value(input["merge"].clone())?,
+                       strategy)?;
+        }
+        if !input["patch"].is_null() {
+            let mut value = serde_json::to_value(&spec)?;
+            merge_patch(&mut value, &input["patch"]);
+            validate_device_spec(&value)
+                .map_err(|e| anyhow!("Invalid patched specification: {}", e))?;
+            spec = serde_json::from_value(value)?;
+        }
+        
+        let mut content = vec![ToolContent {
+            content_type: "text".to_string(),
+            text: serde_json::to_string_pretty(&spec)?,
+        }];
+        if input["write"].as_bool().unwrap_or(false) {
+            let spec_file = input["spec_file"].as_str()
+                .ok_or_else(|| anyhow!("write requires spec_file"))?;
+            spec.save(Path::new(spec_file))?;
+            content.push(ToolContent {
+                content_type: "text".to_string(),
+                text: serde_json::to_string_pretty(
+                    &json!({ "written": spec_file }))?,
+            });
+        }
+        
+        Ok(ToolResult {
+            content,
+            is_error: None,
+        })
+    }
+}
+
+/// Apply a JSON merge patch (RFC 7396) to `target`
+fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = json!({});
+    }
+    let target = target.as_object_mut().unwrap();
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(key);
+        } else {
+            merge_patch(target.entry(key.clone()).or_insert(Value::Null),
+                        value);
+        }
+    }
+}
+
+/// Query the register maps of all DML files in a workspace
+#[derive(Default)]
+pub struct QueryModelTool;
+
+#[async_trait]
+impl DMLTool for QueryModelTool {
+    fn name(&self) -> &str {
+        "query_model"
+    }
+    
+    fn description(&self) -> &str {
+        "Query devices, banks, registers and fields of a DML workspace, e.g. \
+         'select registers where size=8 and bank=\"pcie\"' or \
+         'list fields with access=\"wo\"'"
+    }
+    
+    fn input_schema(&self) -> Value {
+        let mut properties = workspace_properties();
+        properties["query"] = json!({
+            "type": "string",
+            "description": "Query of the form '(select|list) \
+                            (devices|banks|registers|fields) \
+                            [where <attr> <op> <value> [and ...]]', \
+                            with operators =, !=, <, <=, >, >= and \
+                            ~ (substring)"
+        });
+        json!({
+            "type": "object",
+            "properties": properties,
+            "required": ["query"]
+        })
+    }
+    
+    async fn execute(&self, input: Value) -> Result<ToolResult> {
+        let query = input["query"].as_str()
+            .ok_or_else(|| anyhow!("Query is required"))?;
+        
+        let query = parse_query(query)?;
+        let (index, degraded) = match input["workspace"].as_str() {
+            Some(workspace) => (WorkspaceIndex::scan(Path::new(workspace))?,
+                                None),
+            None => match standalone_file_from_input(&input)? {
+                Some(parsed) => (WorkspaceIndex::single(&parsed),
+                                 Some(without_workspace(true,
+                                     "Only the given file was queried"))),
+                None => (WorkspaceIndex::default(),
+                         Some(without_workspace(false,
+                             "Nothing was queried; pass workspace, or file \
+                              or code to query a single file"))),
+            },
+        };
+        let results = evaluate_query(&query, &index);
+        let mut response = json!({
+            "entity": query.entity.to_string(),
+            "count": results.len(),
+            "results": results,
+        });
+        if let Some(degraded) = degraded {
+            response["degraded"] = degraded;
+        }
+        
+        Ok(ToolResult {
+            content: vec![ToolContent {
+                content_type: "text".to_string(),
+                text: serde_json::to_string_pretty(&response)?,
+            }],
+            is_error: None,
+        })
+    }
+}
+
+/// Locate the declarations of symbols in a workspace
+#[derive(Default)]
+pub struct FindSymbolTool;
+
+/// Number of symbols `find_symbol` returns unless told otherwise
+const DEFAULT_SYMBOL_LIMIT: u64 = 50;
+
+#[async_trait]
+impl DMLTool for FindSymbolTool {
+    fn name(&self) -> &str {
+        "find_symbol"
+    }
+    
+    fn description(&self) -> &str {
+        "Find where registers, fields, templates, methods and other symbols \
+         are declared in a DML workspace, with the file, range and \
+         containing objects of each declaration"
+    }
+    
+    fn input_schema(&self) -> Value {
+        let mut properties = workspace_properties();
+        properties["query"] = json!({
+            "type": "string",
+            "description": "Name of the symbol, or a part of it; a dotted \
+                            name such as regs.control.enable matches the \
+                            end of the names of symbols with their \
+                            containers. Every symbol matches when empty"
+        });
+        properties["kinds"] = json!({
+            "type": "array",
+            "description": "Kinds of symbols to find; all but locals and \
+                            method arguments by default",
+            "items": {"type": "string", "enum": SYMBOL_KINDS}
+        });
+        properties["limit"] = json!({
+            "type": "integer",
+            "description": "Maximum number of symbols to return, best \
+                            matches first",
+            "default": DEFAULT_SYMBOL_LIMIT,
+            "minimum": 1
+        });
+        json!({
+            "type": "object",
+            "properties": properties
+        })
+    }
+    
+    async fn execute(&self, input: Value) -> Result<ToolResult> {
+        let query = input["query"].as_str().unwrap_or("").to_string();
+        let kinds: Vec<String> = input["kinds"].as_array()
+            .map(|kinds| kinds.iter().filter_map(|kind| kind.as_str())
+                 .map(String::from).collect())
+            .unwrap_or_default();
+        check_symbol_kinds(&kinds)?;
+        let limit = input["limit"].as_u64().unwrap_or(DEFAULT_SYMBOL_LIMIT)
+            as usize;
+        
+        let (root, symbols, degraded) = match input["workspace"].as_str() {
+            Some(workspace) => {
+                let root = PathBuf::from(workspace);
+                let symbols_root = root.clone();
+                let symbols = tokio::task::spawn_blocking(
+                    move || workspace_symbols(&symbols_root)).await??;
+                (root, symbols, None)
+            },
+            None => match standalone_file_from_input(&input)? {
+                Some(parsed) => (PathBuf::new(), declared_symbols(&parsed),
+                                 Some(without_workspace(true,
+                                     "Only the given file was searched"))),
+                None => (PathBuf::new(), vec![],
+                         Some(without_workspace(false,
+                             "Nothing was searched; pass workspace, or file \
+                              or code to search a single file"))),
+            },
+        };
+        let mut found = search_symbols(symbols, &query, &kinds);
+        let count = found.len();
+        found.truncate(limit);
+        for symbol in &mut found {
+            if let Ok(relative) = symbol.file.strip_prefix(&root) {
+                symbol.file = relative.to_path_buf();
+            }
+        }
+        let mut response = json!({
+            "count": count,
+            "truncated": count > found.len(),
+            "symbols": found,
+        });
+        if let Some(degraded) = degraded {
+            response["degraded"] = degraded;
+        }
+        
+        Ok(ToolResult {
+            content: vec![ToolContent {
+                content_type: "text".to_string(),
+                text: serde_json::to_string_pretty(&response)?,
+            }],
+            is_error: None,
+        })
+    }
+}
+
+/// Read the declaration of a symbol with what is known of it
+#[derive(Default)]
+pub struct ReadSymbolTool;
+
+#[async_trait]
+impl DMLTool for ReadSymbolTool {
+    fn name(&self) -> &str {
+        "read_symbol"
+    }
+    
+    fn description(&self) -> &str {
+        "Read the declaration of a symbol given by its dotted name, e.g. \
+         my_device.regs.control.enable: its source, documentation and \
+         properties such as the offset, size and access of a register, \
+         without reading the whole file"
+    }
+    
+    fn input_schema(&self) -> Value {
+        let mut properties = workspace_properties();
+        properties["symbol"] = json!({
+            "type": "string",
+            "description": "Dotted name of the symbol, with the objects, \
+                            methods and templates it is declared in, \
+                            optionally starting with the device name"
+        });
+        json!({
+            "type": "object",
+            "properties": properties,
+            "required": ["symbol"]
+        })
+    }
+    
+    async fn execute(&self, input: Value) -> Result<ToolResult> {
+        let symbol = input["symbol"].as_str()
+            .ok_or_else(|| anyhow!("Missing symbol"))?.to_string();
+        
+        let (root, mut definitions) = match input["workspace"].as_str() {
+            Some(workspace) => {
+                let root = PathBuf::from(workspace);
+                let (definitions_root, name) = (root.clone(), symbol.clone());
+                let definitions = tokio::task::spawn_blocking(
+                    move || workspace_definitions(&definitions_root, &name))
+                    .await??;
+                (root, definitions)
+            },
+            None => match standalone_file_from_input(&input)? {
+                Some(parsed) => (PathBuf::new(),
+                                 symbol_definitions(&parsed, &symbol)),
+                None => return Err(anyhow!(
+                    "Pass workspace, or file or code to read a symbol of")),
+            },
+        };
+        if definitions.is_empty() {
+            return Err(anyhow!("No symbol '{}' is declared; find_symbol \
+                                searches for symbols by part of their name",
+                               symbol));
+        }
+        for definition in &mut definitions {
+            let file = &mut definition.declaration.file;
+            if let Ok(relative) = file.strip_prefix(&root) {
+                *file = relative.to_path_buf();
+            }
+        }
+        let response = json!({
+            "symbol": symbol,
+            "count": definitions.len(),
+            "definitions": definitions,
+        });
+        
+        Ok(ToolResult {
+            content: vec![ToolContent {
+                content_type: "text".to_string(),
+                text: serde_json::to_string_pretty(&response)?,
+            }],
+            is_error: None,
+        })
+    }
+}
+
+/// Find the references to a symbol of a device
+#[derive(Default)]
+pub struct FindReferencesTool;
+
+#[async_trait]
+impl DMLTool for FindReferencesTool {
+    fn name(&self) -> &str {
+        "find_references"
+    }
+    
+    fn description(&self) -> &str {
+        "Find all references to a symbol of a device, given by its dotted \
+         name or by a position in one of the files of the device, with the \
+         line of each reference, e.g. to see what renaming or removing a \
+         register affects"
+    }
+    
+    fn input_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "device": {
+                    "type": "string",
+                    "description": "Path to the DML file declaring the device whose files are searched"
+                },
+                "symbol": {
+                    "type": "string",
+                    "description": "Dotted name of the symbol, e.g. regs.control.enable"
+                },
+                "file": {
+                    "type": "string",
+                    "description": "File of the device the symbol or a reference to it is in, instead of a symbol name; the device file by default"
+                },
+                "line": {
+                    "type": "integer",
+                    "description": "One-based line of the symbol or reference in file",
+                    "minimum": 1
+                },
+                "column": {
+                    "type": "integer",
+                    "description": "One-based column of the symbol or reference in file",
+                    "minimum": 1
+                },
+                "include_declarations": {
+                    "type": "boolean",
+                    "description": "Also list the declarations of the symbol",
+                    "default": false
+                },
+                "include_paths": {
+                    "type": "array",
+                    "description": "Directories to resolve imports in, as given to dmlc with -I, such as that of the standard library",
+                    "items": {"type": "string"}
+                }
+            },
+            "required": ["device"]
+        })
+    }
+    
+    async fn execute(&self, input: Value) -> Result<ToolResult> {
+        let device_file = PathBuf::from(input["device"].as_str()
+            .ok_or_else(|| anyhow!("Missing device"))?);
+        let symbol = input["symbol"].as_str().map(str::to_string);
+        let position = match (input["line"].as_u64(),
+                              input["column"].as_u64()) {
+            (Some(line), Some(column)) => Some((line as u32, column as u32)),
+            _ => None,
+        };
+        if symbol.is_none() && position.is_none() {
+            return Err(anyhow!("Pass symbol, or line and column"));
+        }
+        let file = input["file"].as_str().map(PathBuf::from)
+            .unwrap_or_else(|| device_file.clone());
+        let include_declarations = input["include_declarations"].as_bool()
+            .unwrap_or(false);
+        let include_paths: Vec<PathBuf> = input["include_paths"].as_array()
+            .map(|paths| paths.iter().filter_map(|path| path.as_str())
+                 .map(PathBuf::from).collect())
+            .unwrap_or_default();
+        
+        let response = tokio::task::spawn_blocking(move || {
+            let mut device = AnalyzedDevice::analyze(&device_file,
+                                                     &include_paths)?;
+            let symbols = device.lookup(symbol.as_deref(), &file,
+                                        position)?;
+            let mut described = device.describe(&symbols);
+            let mut references = device.references(&symbols,
+                                                   include_declarations);
+            // Files are given relative to the directory of the device
+            let root = device_file.canonicalize().ok()
+                .and_then(|file| file.parent().map(Path::to_path_buf))
+                .unwrap_or_default();
+            let files = described.iter_mut().map(|symbol| &mut symbol.file)
+                .chain(references.iter_mut().map(|site| &mut site.file));
+            for file in files {
+                if let Ok(relative) = file.strip_prefix(&root) {
+                    *file = relative.to_path_buf();
+                }
+            }
+            let mut limitations: Vec<String> = device.limitations.iter()
+                .map(ToString::to_string).collect();
+            limitations.sort();
+            Ok::<_, anyhow::Error>(json!({
+                "device": device.device.name,
+                "symbols": described,
+                "count": references.len(),
+                "references": references,
+                "limitations": limitations,
+            }))
+        }).await??;
+        
+        Ok(ToolResult {
+            content: vec![ToolContent {
+                content_type: "text".to_string(),
+                text: serde_json::to_string_pretty(&response)?,
+            }],
+            is_error: None,
+        })
+    }
+}
+
+/// Rename a symbol of a device in all of its files
+#[derive(Default)]
+pub struct RenameSymbolTool;
+
+#[async_trait]
+impl DMLTool for RenameSymbolTool {
+    fn name(&self) -> &str {
+        "rename_symbol"
+    }
+    
+    fn writes_files(&self) -> bool {
+        true
+    }
+    
+    fn description(&self) -> &str {
+        "Rename a bank, register, field, method, template or parameter of a \
+         device everywhere it is declared or referred to, as the language \
+         server renames it, and return the edits as a diff of each file. \
+         Files are only changed when apply is set"
+    }
+    
+    fn input_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "device": {
+                    "type": "string",
+                    "description": "Path to the DML file declaring the device whose files are renamed in"
+                },
+                "symbol": {
+                    "type": "string",
+                    "description": "Dotted name of the symbol, e.g. regs.control.enable"
+                },
+                "file": {
+                    "type": "string",
+                    "description": "File of the device the symbol or a reference to it is in, instead of a symbol name; the device file by default"
+                },
+                "line": {
+                    "type": "integer",
+                    "description": "One-based line of the symbol or reference in file",
+                    "minimum": 1
+                },
+                "column": {
+                    "type": "integer",
+                    "description": "One-based column of the symbol or reference in file",
+                    "minimum": 1
+                },
+                "new_name": {
+                    "type": "string",
+                    "description": "The new name of the symbol"
+                },
+                "apply": {
+                    "type": "boolean",
+                    "description": "Write the renamed files instead of only returning the edits",
+                    "default": false
+                },
+                "include_paths": {
+                    "type": "array",
+                    "description": "Directories to resolve imports in, as given to dmlc with -I, such as that of the standard library",
+                    "items": {"type": "string"}
+                }
+            },
+            "required": ["device", "new_name"]
+        })
+    }
+    
+    async fn execute(&self, input: Value) -> Result<ToolResult> {
+        let device_file = PathBuf::from(input["device"].as_str()
+            .ok_or_else(|| anyhow!("Missing device"))?);
+        let new_name = input["new_name"].as_str()
+            .ok_or_else(|| anyhow!("Missing new_name"))?.to_string();
+        let symbol = input["symbol"].as_str().map(str::to_string);
+        let position = match (input["line"].as_u64(),
+                              input["column"].as_u64()) {
+            (Some(line), Some(column)) => Some((line as u32, column as u32)),
+            _ => None,
+        };
+        let file = input["file"].as_str().map(PathBuf::from)
+            .unwrap_or_else(|| device_file.clone());
+        let apply = input["apply"].as_bool().unwrap_or(false);
+        let include_paths: Vec<PathBuf> = input["include_paths"].as_array()
+            .map(|paths| paths.iter().filter_map(|path| path.as_str())
+                 .map(PathBuf::from).collect())
+            .unwrap_or_default();
+        
+        let response = tokio::task::spawn_blocking(move || {
+            let mut device = AnalyzedDevice::analyze(&device_file,
+                                                     &include_paths)?;
+            let symbols = device.lookup(symbol.as_deref(), &file, position)?;
+            let (old_name, renamed) = device.rename(&symbols, &new_name)?;
+            // Files are given relative to the directory of the device
+            let root = device_file.canonicalize().ok()
+                .and_then(|file| file.parent().map(Path::to_path_buf))
+                .unwrap_or_default();
+            let files: Vec<Value> = renamed.iter().map(|file| {
+                let relative = file.file.strip_prefix(&root)
+                    .unwrap_or(&file.file);
+                json!({
+                    "file": relative,
+                    "ranges": file.ranges,
+                    "diff": unified_diff(&relative.to_string_lossy(),
+                                         Some(&file.old_text),
+                                         &file.new_text),
+                })
+            }).collect();
+            if apply {
+                for file in &renamed {
+                    std::fs::write(&file.file, &file.new_text).map_err(
+                        |e| anyhow!("Could not write {}: {}",
+                                    file.file.display(), e))?;
+                }
+            }
+            let mut limitations: Vec<String> = device.limitations.iter()
+                .map(ToString::to_string).collect();
+            limitations.sort();
+            Ok::<_, anyhow::Error>(json!({
+                "device": device.device.name,
+                "old_name": old_name,
+                "new_name": new_name,
+                "count": renamed.iter().map(|file| file.ranges.len())
+                    .sum::<usize>(),
+                "applied": apply,
+                "files": files,
+                "limitations": limitations,
+            }))
+        }).await??;
+        
+        Ok(ToolResult {
+            content: vec![ToolContent {
+                content_type: "text".to_string(),
+                text: serde_json::to_string_pretty(&response)?,
+            }],
+            is_error: None,
+        })
+    }
+}
+
+/// Explain a diagnostic from the built-in knowledge base
+#[derive(Default)]
+pub struct ExplainErrorTool;
+
+#[async_trait]
+impl DMLTool for ExplainErrorTool {
+    fn name(&self) -> &str {
+        "explain_error"
+    }
+    
+    fn description(&self) -> &str {
+        "Explain a diagnostic, given by its code or message: the DML rule \
+         or specification rule involved, a minimal example breaking it and \
+         the example corrected"
+    }
+    
+    fn input_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "diagnostic": {
+                    "type": "string",
+                    "description": "Code of the diagnostic, such as a lint \
+                                    rule or validate_spec finding, or its \
+                                    message as reported"
+                }
+            },
+            "required": ["diagnostic"]
+        })
+    }
+    
+    async fn execute(&self, input: Value) -> Result<ToolResult> {
+        let diagnostic = input["diagnostic"].as_str()
+            .ok_or_else(|| anyhow!("Missing diagnostic"))?;
+        let explanation = explain_diagnostic(diagnostic).ok_or_else(|| {
+            let codes: Vec<&str> = EXPLANATIONS.iter()
+                .map(|explanation| explanation.code)
+                .collect();
+            anyhow!("No explanation of '{}'; the known codes are {}",
+                    diagnostic, codes.join(", "))
+        })?;
+        
+        Ok(ToolResult {
+            content: vec![ToolContent {
+                content_type: "text".to_string(),
+                text: serde_json::to_string_pretty(explanation)?,
+            }],
+            is_error: None,
+        })
+    }
+}
+
+/// Validate all DML files of a workspace
+#[derive(Default)]
+pub struct ValidateWorkspaceTool;
+
+#[async_trait]
+impl DMLTool for ValidateWorkspaceTool {
+    fn name(&self) -> &str {
+        "validate_workspace"
+    }
+    
+    fn description(&self) -> &str {
+        "Validate all DML files of a workspace in parallel, returning the \
+         diagnostics grouped by file together with summary counts"
+    }
+    
+    fn input_schema(&self) -> Value {
+        let mut properties = workspace_properties();
+        properties["simics_api"] = json!({
+            "type": ["string", "integer"],
+            "description": "Simics API version in use, e.g. 7, to also report standard library items it does not provide"
+        });
+        properties["include_clean"] = json!({
+            "type": "boolean",
+            "description": "Also list the files without diagnostics",
+            "default": false
+        });
+        json!({
+            "type": "object",
+            "properties": properties
+        })
+    }
+    
+    async fn execute(&self, input: Value) -> Result<ToolResult> {
+        let simics_api = simics_api_from_input(&input)?;
+        let include_clean = input["include_clean"].as_bool().unwrap_or(false);
+        
+        let (root, results, degraded) = match input["workspace"].as_str() {
+            Some(workspace) => {
+                let root = Path::new(workspace);
+                if !root.is_dir() {
+                    return Err(anyhow!("Workspace root {} is not a directory",
+                                       root.display()));
+                }
+                let results_root = root.to_path_buf();
+                let results = tokio::task::spawn_blocking(
+                    move || validate_workspace(&results_root, simics_api))
+                    .await?;
+                (root, results, None)
+            },
+            None => match standalone_file_from_input(&input)? {
+                Some(parsed) => (Path::new(""),
+                                 vec![validate_parsed(parsed, simics_api)],
+                                 Some(without_workspace(true,
+                                     "Only the given file was validated, \
+                                      without resolving its imports"))),
+                None => (Path::new(""), vec![],
+                         Some(without_workspace(false,
+                             "Nothing was validated; pass workspace, or \
+                              file or code to validate a single file"))),
+            },
+        };
+        
+        let errors: usize = results.iter().map(|file| file.errors).sum();
+        let warnings: usize = results.iter().map(|file| file.warnings).sum();
+        let files_with_diagnostics = results.iter()
+            .filter(|file| !file.diagnostics.is_empty()).count();
+        let files: Vec<Value> = results.iter()
+            .filter(|file| include_clean || !file.diagnostics.is_empty())
+            .map(|file| json!({
+                "file": file.file.strip_prefix(root).unwrap_or(&file.file),
+                "errors": file.errors,
+                "warnings": file.warnings,
+                "diagnostics": file.diagnostics,
+            }))
+            .collect();
+        let mut response = json!({
+            "summary": {
+                "files": results.len(),
+                "files_with_diagnostics": files_with_diagnostics,
+                "errors": errors,
+                "warnings": warnings,
+            },
+            "files": files,
+        });
+        if let Some(degraded) = degraded {
+            response["degraded"] = degraded;
+        }
+        
+        Ok(ToolResult {
+            content: vec![ToolContent {
+                content_type: "text".to_string(),
+                text: serde_json::to_string_pretty(&response)?,
+            }],
+            is_error: None,
+        })
+    }
+}
+
+/// Tell the generated files of a workspace from the hand-written ones
+#[derive(Default)]
+pub struct AnalyzeProjectTool;
+
+#[async_trait]
+impl DMLTool for AnalyzeProjectTool {
+    fn name(&self) -> &str {
+        "analyze_project"
+    }
+    
+    fn description(&self) -> &str {
+        "Analyze existing DML project structure, telling files generated \
+         by this server apart from hand-written ones by their provenance \
+         banners, and finding generated files whose specification has \
+         changed since"
+    }
+    
+    fn input_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": workspace_properties()
+        })
+    }
+    
+    async fn execute(&self, input: Value) -> Result<ToolResult> {
+        let (root, files, degraded) = match input["workspace"].as_str() {
+            Some(workspace) => {
+                let root = Path::new(workspace);
+                if !root.is_dir() {
+                    return Err(anyhow!("Workspace root {} is not a directory",
+                                       root.display()));
+                }
+                let mut files = vec![];
+                for path in find_dml_files(root) {
+                    let text = std::fs::read_to_string(&path).map_err(
+                        |e| anyhow!("Could not read {}: {}", path.display(), e))?;
+                    files.push((path, text));
+                }
+                (root.to_path_buf(), files, None)
+            },
+            None => match standalone_file_from_input(&input)? {
+                Some(parsed) => (PathBuf::new(),
+                                 vec![(parsed.path, parsed.text)],
+                                 Some(without_workspace(true,
+                                     "Only the given file was analyzed"))),
+                None => (PathBuf::new(), vec![],
+                         Some(without_workspace(false,
+                             "Nothing was analyzed; pass workspace, or file \
+                              or code to analyze a single file"))),
+            },
+        };
+        
+        let mut generated = 0;
+        let mut stale = 0;
+        let files: Vec<Value> = files.iter().map(|(path, text)| {
+            let file = path.strip_prefix(&root).unwrap_or(path);
+            let Some(provenance) = Provenance::parse(text) else {
+                return json!({"file": file, "generated": false});
+            };
+            generated += 1;
+            let spec_changed = spec_changed(path, &provenance);
+            if spec_changed == Some(true) {
+                stale += 1;
+            }
+            json!({
+                "file": file,
+                "generated": true,
+                "provenance": provenance,
+                "spec_changed": spec_changed,
+            })
+        }).collect();
+        let mut response = json!({
+            "summary": {
+                "files": files.len(),
+                "generated": generated,
+                "hand_written": files.len() - generated,
+                "spec_changed": stale,
+            },
+            "files": files,
+        });
+        if let Some(degraded) = degraded {
+            response["degraded"] = degraded;
+        }
+        
+        Ok(ToolResult {
+            content: vec![ToolContent {
+                content_type: "text".to_string(),
+                text: serde_json::to_string_pretty(&response)?,
+            }],
+            is_error: None,
+        })
+    }
+}
+
+/// Whether the specification a file was generated from has changed since,
+/// or `None` if that cannot be told, e.g. as the specification is not found.
+/// The specification file is looked up relative to the generated file.
+fn spec_changed(path: &Path, provenance: &Provenance) -> Option<bool> {
+    let (spec_file, hash) = (provenance.spec_file.as_ref()?,
+                             provenance.spec_hash.as_ref()?);
+    let spec_path = path.parent().unwrap_or(Path::new("")).join(spec_file);
+    let spec = DeviceSpec::load(&spec_path).ok()?;
+    Some(&spec_hash(&spec) != hash)
+}
+
+/// Report the imports between the DML files of a workspace
+#[derive(Default)]
+pub struct DependencyGraphTool;
+
+#[async_trait]
+impl DMLTool for DependencyGraphTool {
+    fn name(&self) -> &str {
+        "dependency_graph"
+    }
+    
+    fn description(&self) -> &str {
+        "Report the import graph of the DML files in a workspace, with \
+         unresolved imports and circular import chains"
+    }
+    
+    fn input_schema(&self) -> Value {
+        let mut properties = workspace_properties();
+        properties["include_paths"] = json!({
+            "type": "array",
+            "description": "Additional directories to resolve imports in, as given to dmlc with -I",
+            "items": {"type": "string"}
+        });
+        json!({
+            "type": "object",
+            "properties": properties
+        })
+    }
+    
+    async fn execute(&self, input: Value) -> Result<ToolResult> {
+        let include_paths: Vec<PathBuf> = input["include_paths"].as_array()
+            .map(|paths| paths.iter().filter_map(|path| path.as_str())
+                 .map(PathBuf::from).collect())
+            .unwrap_or_default();
+        
+        let (root, imports, degraded) = match input["workspace"].as_str() {
+            Some(workspace) => {
+                let root = Path::new(workspace).canonicalize()
+                    .map_err(|e| anyhow!("Invalid workspace {}: {}",
+                                         workspace, e))?;
+                let paths_root = root.clone();
+                let imports = tokio::task::spawn_blocking(
+                    move || scan_imports(&paths_root, &include_paths)).await?;
+                (root, imports, None)
+            },
+            None => match standalone_file_from_input(&input)? {
+                Some(parsed) => (PathBuf::new(),
+                                 file_imports(&parsed, &include_paths),
+                                 Some(without_workspace(true,
+                                     "Only the direct imports of the given \
+                                      file were resolved, so circular \
+                                      imports are not reported"))),
+                None => (PathBuf::new(), Default::default(),
+                         Some(without_workspace(false,
+                             "No imports were resolved; pass workspace, or \
+                              file or code to resolve the imports of a \
+                              single file"))),
+            },
+        };
+        let relative = |path: &PathBuf| path.strip_prefix(&root)
+            .unwrap_or(path).to_path_buf();
+        let files: Vec<Value> = imports.graph.iter()
+            .map(|(file, imported)| json!({
+                "file": relative(file),
+                "imports": imported.iter().map(relative).collect::<Vec<_>>(),
+                "unresolved": imports.unresolved.get(file)
+                    .cloned().unwrap_or_default(),
+            }))
+            .collect();
+        let cycles: Vec<Value> = find_import_cycles(&imports.graph).iter()
+            .map(|cycle| json!({
+                "files": cycle.iter().map(relative).collect::<Vec<_>>(),
+                "description": describe_cycle(cycle),
+            }))
+            .collect();
+        let mut response = json!({
+            "files": files,
+            "cycles": cycles,
+        });
+        if let Some(degraded) = degraded {
+            response["degraded"] = degraded;
+        }
+        
+        Ok(ToolResult {
+            content: vec![ToolContent {
+                content_type: "text".to_string(),
+                text: serde_json::to_string_pretty(&response)?,
+            }],
+            is_error: None,
+        })
+    }
+}
+
+/// Show the object tree of a device after templates are applied
+#[derive(Default)]
+pub struct ExpandTemplatesTool;
+
+#[async_trait]
+impl DMLTool for ExpandTemplatesTool {
+    fn name(&self) -> &str {
+        "expand_templates"
+    }
+    
+    fn description(&self) -> &str {
+        "Show the object tree of a device after templates and 'in each' \
+         declarations are applied, with the templates, parameters and \
+         methods each object ends up with and where they are declared"
+    }
+    
+    fn input_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "file": {
+                    "type": "string",
+                    "description": "Path to the DML file declaring the device"
+                },
+                "object": {
+                    "type": "string",
+                    "description": "Dotted name of the object to expand, e.g. regs.status; the whole device by default"
+                },
+                "include_paths": {
+                    "type": "array",
+                    "description": "Directories to resolve imports in, as given to dmlc with -I, such as that of the standard library",
+                    "items": {"type": "string"}
+                }
+            },
+            "required": ["file"]
+        })
+    }
+    
+    async fn execute(&self, input: Value) -> Result<ToolResult> {
+        let file = input["file"].as_str()
+            .ok_or_else(|| anyhow!("Missing file"))?.to_string();
+        let object = input["object"].as_str().map(str::to_string);
+        let include_paths: Vec<PathBuf> = input["include_paths"].as_array()
+            .map(|paths| paths.iter().filter_map(|path| path.as_str())
+                 .map(PathBuf::from).collect())
+            .unwrap_or_default();
+        
+        let (device, expanded) = tokio::task::spawn_blocking(move || {
+            let device = analyze_device(Path::new(&file), &include_paths)?;
+            let expanded = match &object {
+                Some(name) => expand_object_named(&device, name)
+                    .ok_or_else(|| anyhow!("No object '{}' in device {}",
+                                           name, device.name))?,
+                None => expand_device(&device)
+                    .ok_or_else(|| anyhow!("Could not expand device {}",
+                                           device.name))?,
+            };
+            Ok::<_, anyhow::Error>((device.name.clone(), expanded))
+        }).await??;
+        
+        let result = json!({
+            "device": device,
+            "object": expanded.map_locations(
+                &|span| SourceLocation::from(span)),
+        });
+        Ok(ToolResult {
+            content: vec![ToolContent {
+                content_type: "text".to_string(),
+                text: serde_json::to_string_pretty(&result)?,
+            }],
+            is_error: None,
+        })
+    }
+}
+
+/// Generate a device from one of the built-in design patterns
+#[derive(Default)]
+pub struct ApplyPatternTool;
+
+#[async_trait]
+impl DMLTool for ApplyPatternTool {
+    fn name(&self) -> &str {
+        "apply_pattern"
+    }
+    
+    fn writes_files(&self) -> bool {
+        true
+    }
+    
+    fn description(&self) -> &str {
+        "Apply common DML design patterns"
+    }
+    
+    fn input_schema(&self) -> Value {
+        let mut patterns: Vec<String> = DMLTemplates::get_pattern_templates()
+            .into_keys().collect();
+        patterns.sort();
+        let mut schema = json!({
+            "type": "object",
+            "properties": {
+                "pattern": {
+                    "type": "string",
+                    "enum": patterns,
+                    "description": "Design pattern to apply"
+                },
+                "patterns": {
+                    "type": "array",
+                    "description": "Design patterns to combine into one \
+                                    device, instead of a single pattern. \
+                                    Banks, interfaces and connects of the \
+                                    same name are merged; overlapping \
+                                    registers and differing definitions of \
+                                    the same method are errors.",
+                    "items": {
+                        "oneOf": [
+                            {"type": "string", "enum": patterns},
+                            {
+                                "type": "object",
+                                "properties": {
+                                    "pattern": {"type": "string",
+                                                "enum": patterns},
+                                    "config": {"type": "object"},
+                                    "offset": {
+                                        "type": "string",
+                                        "description": "Move the registers \
+                                                        of the pattern by \
+                                                        this many bytes"
+                                    }
+                                },
+                                "required": ["pattern"]
+                            }
+                        ]
+                    }
+                },
+                "base": {
+                    "type": "object",
+                    "description": "Device specification to apply the \
+                                    patterns to"
+                },
+                "name": {
+                    "type": "string",
+                    "description": "Name of the generated device"
+                },
+                "config": {
+                    "type": "object",
+                    "description": "Pattern-specific configuration, e.g. \
+                                    num_channels for 'dma'"
+                }
+            },
+            "required": ["name"],
+            "anyOf": [
+                {"required": ["pattern"]},
+                {"required": ["patterns"]}
+            ]
+        });
+        with_output_properties(&mut schema);
+        schema
+    }
+    
+    async fn execute(&self, input: Value) -> Result<ToolResult> {
+        let name = input["name"].as_str()
+            .ok_or_else(|| anyhow!("Missing name"))?;
+        let applied = match (&input["pattern"], &input["patterns"]) {
+            (Value::String(_), _) => vec![json!({
+                "pattern": input["pattern"],
+                "config": input["config"],
+            })],
+            (_, Value::Array(patterns)) => patterns.iter()
+                .map(|pattern| match pattern {
+                    Value::String(_) => json!({
+                        "pattern": pattern,
+                        "config": input["config"],
+                    }),
+                    _ => pattern.clone(),
+                })
+                .collect(),
+            _ => return Err(anyhow!("Missing pattern")),
+        };
+        let mut spec = match &input["base"] {
+            Value::Null => None,
+            base => {
+                validate_device_spec(base)
+                    .map_err(|e| anyhow!("Invalid base: {}", e))?;
+                let mut base: DeviceSpec = serde_json::from_value(base.clone())?;
+                base.name = name.to_string();
+                Some(base)
+            },
+        };
+        
+        // The pattern closures are not Send, so they must not be held
+        // across the generation below
+        {
+            let patterns = DMLTemplates::get_pattern_templates();
+            for applied in applied {
+                let pattern = applied["pattern"].as_str()
+                    .ok_or_else(|| anyhow!("Missing pattern"))?;
+                let build = patterns.get(pattern)
+                    .ok_or_else(|| anyhow!("Unknown pattern: {}", pattern))?;
+                let mut pattern_spec = build(name, &applied["config"])?;
+                if let Some(offset) = applied["offset"].as_str() {
+                    pattern_spec.offset_registers(parse_integer(offset)
+                        .ok_or_else(|| anyhow!("Invalid offset: {}", offset))?);
+                }
+                spec = Some(match spec {
+                    Some(mut spec) => {
+                        spec.merge(pattern_spec, MergeStrategy::Error).map_err(|e| anyhow!(
+                            "Could not apply pattern {}: {}", pattern, e))?;
+                        spec
+                    },
+                    None => pattern_spec,
+                });
+            }
+        }
+        let spec = spec.ok_or_else(|| anyhow!("No patterns to apply"))?;
+        
+        let output = OutputOptions::from_input(&input)?;
+        let (files, _) = generate_device_files_from_spec(
+            &spec, GenerationConfig::default()).await?;
+        let written = output.map(|output| written_files(&output, &files))
+            .transpose()?;
+        
+        // The code of each file, followed by what was written to disk
+        let mut content: Vec<ToolContent> = files.into_iter()
+            .map(|file| ToolContent {
+                content_type: "text".to_string(),
+                text: file.content,
+            })
+            .collect();
+        if let Some(written) = written {
+            content.push(ToolContent {
+                content_type: "text".to_string(),
+                text: serde_json::to_string_pretty(
+                    &json!({ "output": written }))?,
+            });
+        }
+        
+        Ok(ToolResult {
+            content,
+            is_error: None,
+        })
+    }
+}
+
+/// Diff the code generated from a specification against existing files
+#[derive(Default)]
+pub struct PreviewGenerationTool;
+
+#[async_trait]
+impl DMLTool for PreviewGenerationTool {
+    fn name(&self) -> &str {
+        "preview_generation"
+    }
+    
+    fn description(&self) -> &str {
+        "Generate a device from a specification and return a unified diff \
+         against the existing files in the workspace, to review what \
+         regenerating it would change"
+    }
+    
+    fn input_schema(&self) -> Value {
+        let source = device_source_properties();
+        json!({
+            "type": "object",
+            "properties": {
+                "device_spec": source["device_spec"],
+                "spec_file": source["spec_file"],
+                "workspace": {
+                    "type": "string",
+                    "description": "Directory of the existing files, by default that of spec_file"
+                },
+                "target": {
+                    "type": "string",
+                    "description": "Existing file to compare the device file against, relative to the workspace, by default <device>.dml"
+                },
+                "line_ending": line_ending_property(),
+                "split": {
+                    "type": "string",
+                    "enum": ["none", "sections", "banks"],
+                    "description": "Split the device across files, as with generate_device",
+                    "default": "none"
+                }
+            },
+            "anyOf": [
+                {"required": ["device_spec", "workspace"]},
+                {"required": ["spec_file"]}
+            ]
+        })
+    }
+    
+    async fn execute(&self, input: Value) -> Result<ToolResult> {
+        let spec = device_spec_from_input(&input)?;
+        let dir = match (input["workspace"].as_str(),
+                         input["spec_file"].as_str()) {
+            (Some(workspace), _) => PathBuf::from(workspace),
+            (None, Some(spec_file)) => Path::new(spec_file).parent()
+                .map(Path::to_path_buf).unwrap_or_default(),
+            (None, None) => return Err(anyhow!(
+                "workspace is required unless spec_file is given")),
+        };
+        let config = GenerationConfig {
+            line_ending: line_ending_from_input(&input)?,
+            split: split_from_input(&input)?,
+            ..GenerationConfig::default()
+        };
+        let (mut files, _) = generate_device_files_from_spec(
+            &spec, config).await?;
+        if let Some(target) = input["target"].as_str() {
+            files[0].file_path = target.to_string();
+        }
+        
+        // A dry run that may overwrite reports every difference
+        let preview = OutputOptions { dir, force: true, dry_run: true };
+        let writes = preview.write(&files)?;
+        let diff: String = writes.iter()
+            .filter_map(|write| write.diff.as_deref()).collect();
+        let summary = json!({
+            "changed": writes.iter()
+                .filter(|write| write.action != WriteAction::Unchanged)
+                .count(),
+            "files": writes.iter().map(|write| json!({
+                "path": write.path,
+                "action": write.action,
+            })).collect::<Vec<_>>(),
+        });
+        
+        Ok(ToolResult {
+            content: vec![
+                ToolContent {
+                    content_type: "text".to_string(),
+                    text: if diff.is_empty() {
+                        "No changes".to_string()
+                    } else {
+                        diff
+                    },
+                },
+                ToolContent {
+                    content_type: "text".to_string(),
+                    text: serde_json::to_string_pretty(&summary)?,
+                },
+            ],
+            is_error: None,
+        })
+    }
+}
+
+// Placeholder implementations for other tools
+macro_rules! impl_placeholder_tool {
+    ($name:ident, $tool_name:expr, $description:expr) => {
+        pub struct $name;
+        
+        impl $name {
+            pub fn new() -> Self {
+                Self
+            }
+        }
+        
+        #[async_trait]
+        impl DMLTool for $name {
+            fn name(&self) -> &str {
+                $tool_name
+            }
+            
+            fn description(&self) -> &str {
+                $description
+            }
+            
+            fn input_schema(&self) -> Value {
+                json!({"type": "object", "properties": {}})
+            }
+            
+            async fn execute(&self, _input: Value) -> Result<ToolResult> {
+                Ok(ToolResult {
+                    content: vec![ToolContent {
+                        content_type: "text".to_string(),
+                        text: format!("Tool '{}' is not yet implemented", self.name()),
+                    }],
+                    is_error: Some(false),
+                })
+            }
+        }
+    };
+}
+
+impl_placeholder_tool!(GenerateMethodTool, "generate_method", "Generate DML method implementation");
+impl_placeholder_tool!(ValidateCodeTool, "validate_code", "Validate DML code syntax and semantics");
+impl_placeholder_tool!(GenerateTemplateTool, "generate_template", "Generate reusable DML templates");
+
+// ========== Code Generation Functions ==========
+
+/// Summary of what `generate_device` emitted, returned alongside the code so
+/// that clients can check that the whole request was used
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct DeviceGenerationStats {
+    pub registers: usize,
+    pub fields: usize,
+    /// Registers left out because they lack a name or size
+    pub skipped_registers: usize,
+    pub address_span: Option<AddressSpan>,
+    pub imports: Vec<String>,
+    pub validation_errors: usize,
+    pub validation_warnings: usize,
+    /// Problems with the request that do not stop generation, such as
+    /// interfaces missing from the catalog
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+/// The byte offsets covered by a set of registers, `end` being exclusive
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AddressSpan {
+    pub start: u64,
+    pub end: u64,
+}
+
+fn generate_device_code(name: &str, device_type: &str, params: &Value,
+                        catalog: &Arc<Catalog>, line_ending: LineEnding,
+                        split: SplitMode)
+                        -> Result<(Vec<GeneratedCode>, DeviceGenerationStats)> {
+    let mut stats = DeviceGenerationStats::default();
+    let eol = line_ending.as_str();
+    let template_base = params["template_base"]
+        .as_str()
+        .unwrap_or("base_device");
+    // Sorted, so that the output does not depend on the order of the request
+    let interfaces: BTreeSet<&str> = params["interfaces"].as_array()
+        .map(|interfaces| interfaces.iter()
+             .filter_map(|interface| interface.as_str()).collect())
+        .unwrap_or_default();
+    
+    stats.warnings = interfaces.iter()
+        .filter_map(|iface| catalog.unknown_interface_warning(iface))
+        .collect();
+    
+    // Import the declarations of the implemented interfaces
+    let imports: BTreeSet<&String> = interfaces.iter()
+        .filter_map(|iface| catalog.interface(iface)?.import.as_ref())
+        .collect();
+    stats.imports = imports.into_iter().cloned().collect();
+    
+    let mut parts = DeviceParts {
+        name: name.to_string(),
+        imports: stats.imports.clone(),
+        declaration: format!(
+            "/// Generated {1} device{0}device {2};{0}is {3};{0}{0}",
+            eol, device_type, name, template_base
+        ),
+        ..Default::default()
+    };
+    
+    // Add registers if specified
+    if let Some(registers) = params["registers"].as_array() {
+        let mut code = format!("bank registers {{{}", eol);
+        for register in registers {
+            if let (Some(reg_name), Some(reg_size)) = 
+                (register["name"].as_str(), register["size"].as_u64()) {
+                // Registers without an offset are unmapped
+                let offset = register["offset"].as_str();
+                let location = offset
+                    .map_or_else(String::new, |offset| format!(" @ {}", offset));
+                stats.registers += 1;
+                if let Some(start) = offset.and_then(parse_integer) {
+                    let end = start.checked_add(reg_size).ok_or_else(
+                        || anyhow!("Register {} at {} ends past the end of \
+                                    the address space", reg_name, start))?;
+                    stats.address_span = Some(match stats.address_span {
+                        Some(span) => AddressSpan {
+                            start: span.start.min(start),
+                            end: span.end.max(end),
+                        },
+                        None => AddressSpan { start, end },
+                    });
+                }
+                let fields = register["fields"].as_array()
+                    .filter(|fields| !fields.is_empty());
+                let Some(fields) = fields else {
+                    code.push_str(&format!(
+                        "    register {} size {}{};{}",
+                        reg_name, reg_size, location, eol
+                    ));
+                    continue;
+                };
+                code.push_str(&format!(
+                    "    register {} size {}{} {{{}",
+                    reg_name, reg_size, location, eol
+                ));
+                for field in fields {
+                    if let Some(field_name) = field["name"].as_str() {
+                        let bits = field["bits"].as_str().unwrap_or("0");
+                        let access = field["access"].as_str().unwrap_or("rw");
+                        let template = template_of_access(access).map_err(
+                            |e| anyhow!("{} of field {}", e, field_name))?;
+                        code.push_str(&format!(
+                            "        field {} @ [{}]{};{}",
+                            field_name, bits,
+                            template.map_or_else(String::new,
+                                                 |t| format!(" is ({})", t)),
+                            eol
+                        ));
+                        stats.fields += 1;
+                    }
+                }
+                code.push_str(&format!("    }}{}", eol));
+            } else {
+                stats.skipped_registers += 1;
+            }
+        }
+        code.push('}');
+        code.push_str(eol);
+        parts.banks.push(("registers".to_string(), code));
+    }
+    
+    // Add interfaces if specified, with stubs of their methods
+    let generator = DMLGenerator::new(GenerationContext {
+        device_name: name.to_string(),
+        namespace: name.to_string(),
+        imports: vec![],
+        templates: vec![],
+        config: GenerationConfig { line_ending, ..GenerationConfig::default() },
+    }).with_catalog(Arc::clone(catalog));
+    for iface in &interfaces {
+        parts.interfaces.push_str(&generator.generate_interface(
+            &InterfaceSpec { name: iface.to_string(), methods: vec![] })?);
+    }
+    
+    let files = parts.into_files(split, line_ending);
+    stats.count_diagnostics(&files)?;
+    
+    Ok((files, stats))
+}
+
+impl DeviceGenerationStats {
+    /// Statistics of code generated from a device specification
+    pub fn for_spec(spec: &DeviceSpec, files: &[GeneratedCode])
+                    -> Result<Self> {
+        let mut stats = DeviceGenerationStats {
+            // As imported by the generated code
+            imports: spec.dependencies.iter().collect::<BTreeSet<_>>()
+                .into_iter().cloned().collect(),
+            ..Default::default()
+        };
+        for register in spec.banks.iter().flat_map(|bank| &bank.registers) {
+            stats.registers += 1;
+            stats.fields += register.fields.len();
+            if let Some(start) = register.offset_value() {
+                let end = start.checked_add(register.size).ok_or_else(
+                    || anyhow!("Register {} at {} ends past the end of the \
+                                address space", register.name, start))?;
+                stats.address_span = Some(match stats.address_span {
+                    Some(span) => AddressSpan {
+                        start: span.start.min(start),
+                        end: span.end.max(end),
+                    },
+                    None => AddressSpan { start, end },
+                });
+            }
+        }
+        stats.count_diagnostics(files)?;
+        Ok(stats)
+    }
+    
+    fn count_diagnostics(&mut self, files: &[GeneratedCode]) -> Result<()> {
+        for file in files {
+            self.count_file_diagnostics(&file.file_path, &file.content)?;
+        }
+        Ok(())
+    }
+    
+    fn count_file_diagnostics(&mut self, path: &str, code: &str)
+                              -> Result<()> {
+        let parsed = parse_dml(Path::new(path), code)?;
+        for error in &parsed.errors {
+            match error.severity {
+                Some(DiagnosticSeverity::WARNING) => self.validation_warnings += 1,
+                Some(DiagnosticSeverity::ERROR) | None =>
+                    self.validation_errors += 1,
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Generate the code of a device specification
+pub async fn generate_device_from_spec(spec: &DeviceSpec,
+                                       config: GenerationConfig)
+                                       -> Result<(String, DeviceGenerationStats)> {
+    let config = GenerationConfig { split: SplitMode::None, ..config };
+    let (mut files, stats) = generate_device_files_from_spec(spec, config)
+        .await?;
+    Ok((files.remove(0).content, stats))
+}
+
+/// Generate the code of a device specification, split across files as
+/// configured, see `DeviceParts::into_files`
+pub async fn generate_device_files_from_spec(spec: &DeviceSpec,
+                                             config: GenerationConfig)
+                                             -> Result<(Vec<GeneratedCode>,
+                                                        DeviceGenerationStats)> {
+    generate_device_files_with_catalog(spec, config,
+                                       Arc::new(Catalog::builtin())).await
+}
+
+/// Generate the code of a device specification like
+/// `generate_device_files_from_spec`, with the interfaces of `catalog`
+pub async fn generate_device_files_with_catalog(
+    spec: &DeviceSpec, config: GenerationConfig, catalog: Arc<Catalog>)
+    -> Result<(Vec<GeneratedCode>, DeviceGenerationStats)> {
+    let generator = DMLGenerator::new(GenerationContext {
+        device_name: spec.name.clone(),
+        namespace: spec.name.clone(),
+        imports: spec.dependencies.clone(),
+        templates: vec![],
+        config,
+    }).with_catalog(catalog);
+    let files = generator.generate_device_files(spec).await?;
+    let mut stats = DeviceGenerationStats::for_spec(spec, &files)?;
+    stats.warnings = generator.interface_warnings(spec);
+    Ok((files, stats))
+}
+
+fn generate_register_code(name: &str, size: u64, params: &Value,
+                          line_ending: LineEnding) -> Result<String> {
+    let eol = line_ending.as_str();
+    let mut code = format!("register {} size {} {{{}", name, size, eol);
+    
+    // Add documentation if provided
+    if let Some(doc) = params["documentation"].as_str() {
+        for line in doc.lines() {
+            code.push_str(&format!("    /// {}{}", line, eol));
+        }
+    }
+    
+    if let Some(access) = params["access"].as_str() {
+        if let Some(template) = template_of_access(access)? {
+            code.push_str(&format!("    is {};{}", template, eol));
+        }
     }
     
     // Add fields if specified
@@ -386,14 +3017,15 @@ fn generate_register_code(name: &str, size: u64, params: &Value) -> Result<Strin
                 let access = field["access"].as_str().unwrap_or("rw");
                 
                 code.push_str(&format!(
-                    "    field {} @ [{}] access {};\n",
-                    field_name, bits, access
+                    "    field {} @ [{}] access {};{}",
+                    field_name, bits, access, eol
                 ));
             }
         }
     }
     
-    code.push_str("}\n");
+    code.push('}');
+    code.push_str(eol);
     
     Ok(code)
 }
\ No newline at end of file
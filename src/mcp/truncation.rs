@@ -0,0 +1,168 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Limits on the size of tool results
+//!
+//! A tool result whose text is larger than the limit of the server is cut
+//! down to a preview, at a line boundary, and the full text is kept as a
+//! resource of the server that the client can read. The result says so in
+//! a notice before the preview and in its `_meta`, so that clients are
+//! never handed cut-off output without knowing it.
+
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use crate::mcp::resources::{ResourceContents, ResourceDefinition};
+
+/// The largest size of the text of a tool result, in bytes, by default
+pub const DEFAULT_MAX_RESULT_BYTES: usize = 512 * 1024;
+
+/// The prefix of the URIs of the full results of truncated tool calls
+pub const RESULT_URI_PREFIX: &str = "dml://results/";
+
+/// The number of full results kept; older ones can no longer be read
+const MAX_STORED_RESULTS: usize = 32;
+
+/// How a tool result was truncated, as recorded in its `_meta`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Truncation {
+    /// The size of the text of the full result, in bytes
+    pub total_bytes: usize,
+    /// The size of the preview in the result, in bytes
+    pub preview_bytes: usize,
+    /// The resource holding the full result
+    pub resource_uri: String,
+}
+
+/// A full result of a truncated tool call
+#[derive(Debug)]
+struct StoredResult {
+    uri: String,
+    tool: String,
+    text: String,
+}
+
+/// The number of result stores made so far
+static STORES: AtomicU64 = AtomicU64::new(0);
+
+/// The full results of truncated tool calls, the most recent last. The
+/// URIs of a store are unique to it, so that a session is never handed
+/// the result of another session under the URI of one of its own.
+#[derive(Debug)]
+pub struct ResultStore {
+    /// The prefix of the URIs of the results, `dml://results/<store>/`
+    prefix: String,
+    results: Mutex<(u64, VecDeque<StoredResult>)>,
+}
+
+impl Default for ResultStore {
+    fn default() -> Self {
+        let store = STORES.fetch_add(1, Ordering::Relaxed) + 1;
+        ResultStore {
+            prefix: format!("{}{}/", RESULT_URI_PREFIX, store),
+            results: Mutex::default(),
+        }
+    }
+}
+
+impl ResultStore {
+    /// Keep the full result `text` of a call of `tool`, returning its URI
+    pub fn store(&self, tool: &str, text: String) -> String {
+        let mut results = self.results.lock().unwrap();
+        results.0 += 1;
+        let uri = format!("{}{}", self.prefix, results.0);
+        if results.1.len() == MAX_STORED_RESULTS {
+            results.1.pop_front();
+        }
+        results.1.push_back(StoredResult {
+            uri: uri.clone(),
+            tool: tool.to_string(),
+            text,
+        });
+        uri
+    }
+
+    pub fn list(&self) -> Vec<ResourceDefinition> {
+        self.results.lock().unwrap().1.iter()
+            .map(|result| ResourceDefinition {
+                uri: result.uri.clone(),
+                name: format!("Full result of {}", result.tool),
+                description: format!("The full result of a call of {} that \
+                                      was too large to return ({} bytes)",
+                                     result.tool, result.text.len()),
+                mime_type: "text/plain".to_string(),
+            })
+            .collect()
+    }
+
+    pub fn read(&self, uri: &str) -> Option<ResourceContents> {
+        self.results.lock().unwrap().1.iter()
+            .find(|result| result.uri == uri)
+            .map(|result| ResourceContents {
+                uri: uri.to_string(),
+                mime_type: "text/plain".to_string(),
+                text: result.text.clone(),
+            })
+    }
+}
+
+/// The longest prefix of `text` of at most `max_bytes` bytes that ends at a
+/// line boundary, or at a character boundary if the first line is longer
+fn preview(text: &str, max_bytes: usize) -> &str {
+    if text.len() <= max_bytes {
+        return text;
+    }
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    match text[..end].rfind('\n') {
+        Some(newline) => &text[..newline + 1],
+        None => &text[..end],
+    }
+}
+
+/// Limit the text of the serialized tool result `result`, of a call of
+/// `tool`, to `max_bytes`. A larger result is kept in `store` and its text
+/// items are replaced by a notice and a preview of their text, joined by
+/// newlines; items of other types are left as they are.
+pub fn limit_result(result: Value, tool: &str, max_bytes: usize,
+                    store: &ResultStore) -> Value {
+    let (texts, others): (Vec<&Value>, Vec<&Value>) = result["content"]
+        .as_array().into_iter().flatten()
+        .partition(|content| content["text"].is_string());
+    let text = texts.iter()
+        .filter_map(|content| content["text"].as_str())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let total_bytes = text.len();
+    if total_bytes <= max_bytes {
+        return result;
+    }
+    let shown = preview(&text, max_bytes).to_string();
+    let uri = store.store(tool, text);
+    let truncation = Truncation {
+        total_bytes,
+        preview_bytes: shown.len(),
+        resource_uri: uri.clone(),
+    };
+    let notice = format!("The result of {} is {} bytes, more than the limit \
+                          of {} bytes, and is truncated to the first {} \
+                          bytes below. Read the resource {} for all of it.",
+                         tool, total_bytes, max_bytes, shown.len(), uri);
+    let mut content = vec![
+        json!({"type": "text", "text": notice}),
+        json!({"type": "text", "text": shown}),
+    ];
+    content.extend(others.into_iter().cloned());
+    let mut limited = json!({
+        "content": content,
+        "_meta": {"truncated": truncation},
+    });
+    if let Some(is_error) = result.get("isError") {
+        limited["isError"] = is_error.clone();
+    }
+    limited
+}
@@ -0,0 +1,185 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! The MCP server over WebSocket
+//!
+//! With `--transport ws` the server listens for WebSocket connections rather
+//! than reading stdin, so that clients such as browser-based agent frontends
+//! can connect to it without a stdio bridge. Each text message holds a
+//! JSON-RPC message or batch, as a line does on stdio, and is responded to
+//! with a text message. Every connection is a session of its own, with its
+//! own initialization handshake; a `shutdown` request closes the connection
+//! it was sent on, while the server keeps running until SIGINT or SIGTERM.
+//!
+//! With an authentication token, clients must present it as a bearer token
+//! in the `Authorization` header of the handshake, or, as browsers cannot
+//! set that header, as the `access_token` query parameter of the URL. Other
+//! handshakes are refused with 401 Unauthorized.
+
+use anyhow::{anyhow, Result};
+use futures_util::{SinkExt, StreamExt};
+use log::{debug, error, info, warn};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use tokio_tungstenite::tungstenite::handshake::server::{
+    ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::StatusCode;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::auth::AuthToken;
+use crate::mcp::server::{shutdown_signal, DMLMCPServer, Session};
+
+/// The address the server listens on by default
+pub const DEFAULT_LISTEN_ADDRESS: &str = "127.0.0.1:8765";
+
+/// Run `server` on WebSocket connections to `address`, until the process
+/// receives SIGINT or SIGTERM. Clients must present `token`, if given.
+pub async fn run(server: Arc<DMLMCPServer>, address: &str,
+                 token: Option<AuthToken>) -> Result<()> {
+    let listener = TcpListener::bind(address).await
+        .map_err(|e| anyhow!("Could not listen on {}: {}", address, e))?;
+    let local = listener.local_addr()?;
+    info!("Starting MCP server on ws://{}", local);
+    if token.is_none() && !local.ip().is_loopback() {
+        warn!("Clients connecting to {} are not authenticated, anyone who \
+               can reach it can call the tools of the server", local);
+    }
+    serve(server, listener, token, shutdown_signal()).await
+}
+
+/// Accept WebSocket connections on `listener`, serving each in a session of
+/// its own, until `shutdown` completes. Requests being handled then are
+/// given the shutdown timeout of the server to finish and respond, after
+/// which all connections are closed. Clients must present `token`, if
+/// given.
+pub async fn serve(server: Arc<DMLMCPServer>, listener: TcpListener,
+                   token: Option<AuthToken>,
+                   shutdown: impl Future<Output = &'static str>)
+                   -> Result<()> {
+    tokio::pin!(shutdown);
+    let (stopping, stop) = watch::channel(false);
+    let mut connections = JoinSet::new();
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, peer)) => {
+                    connections.spawn(serve_connection(
+                        Arc::clone(&server), stream, peer, token.clone(),
+                        stop.clone()));
+                },
+                Err(e) => warn!("Could not accept a connection: {}", e),
+            },
+            Some(_) = connections.join_next(), if !connections.is_empty() => (),
+            signal = &mut shutdown => {
+                info!("Received {}, shutting down", signal);
+                break;
+            }
+        }
+    }
+    stopping.send_replace(true);
+    let finished = async {
+        while connections.join_next().await.is_some() {}
+    };
+    if tokio::time::timeout(server.shutdown_timeout(), finished).await
+        .is_err() {
+            error!("Requests did not finish within {:?}",
+                   server.shutdown_timeout());
+            connections.abort_all();
+        }
+    info!("MCP server stopped");
+    Ok(())
+}
+
+/// Whether the handshake `request` presents `token`, as a bearer token or
+/// as the `access_token` query parameter
+fn presents(request: &Request, token: &AuthToken) -> bool {
+    let bearer = request.headers().get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| token.matches_bearer(value));
+    let parameter = request.uri().query().into_iter()
+        .flat_map(|query| query.split('&'))
+        .filter_map(|parameter| parameter.strip_prefix("access_token="))
+        .any(|presented| token.matches(presented));
+    bearer || parameter
+}
+
+/// Serve the messages of the client connected from `peer`, once it has
+/// presented `token` if given, until it closes the connection, asks the
+/// server to shut down, or `stop` is set
+async fn serve_connection(server: Arc<DMLMCPServer>, stream: TcpStream,
+                          peer: SocketAddr, token: Option<AuthToken>,
+                          mut stop: watch::Receiver<bool>) {
+    // The error response is as large as tungstenite makes it
+    #[allow(clippy::result_large_err)]
+    let authenticate = |request: &Request, response: Response|
+                        -> std::result::Result<Response, ErrorResponse> {
+        if token.as_ref().is_none_or(|token| presents(request, token)) {
+            return Ok(response);
+        }
+        warn!("Refused client {} that did not present the authentication \
+               token", peer);
+        let mut refusal = ErrorResponse::new(Some(
+            "Missing or wrong authentication token".to_string()));
+        *refusal.status_mut() = StatusCode::UNAUTHORIZED;
+        refusal.headers_mut().insert("www-authenticate",
+                                     "Bearer".parse().unwrap());
+        Err(refusal)
+    };
+    let mut socket = match tokio_tungstenite::accept_hdr_async(
+        stream, authenticate).await {
+        Ok(socket) => socket,
+        Err(e) => {
+            warn!("WebSocket handshake with {} failed: {}", peer, e);
+            return;
+        },
+    };
+    info!("Client {} connected", peer);
+    let session = Mutex::new(Session::default());
+    loop {
+        let message = tokio::select! {
+            message = socket.next() => message,
+            _ = stop.changed() => break,
+        };
+        let text = match message {
+            Some(Ok(Message::Text(text))) => text,
+            Some(Ok(Message::Close(_))) | None => break,
+            Some(Ok(Message::Binary(_))) => {
+                warn!("Ignoring binary message from {}", peer);
+                continue;
+            },
+            // Pings are answered by the WebSocket layer
+            Some(Ok(_)) => continue,
+            Some(Err(e)) => {
+                warn!("Error reading from {}: {}", peer, e);
+                break;
+            },
+        };
+        let text = text.trim();
+        if text.is_empty() {
+            continue;
+        }
+        debug!("Received message from {}: {}", peer, text);
+        match server.handle_session_line(&session, text).await {
+            Ok(Some(response)) => {
+                debug!("Sending response to {}: {}", peer, response);
+                if let Err(e) = socket.send(Message::Text(response)).await {
+                    warn!("Error writing to {}: {}", peer, e);
+                    break;
+                }
+            },
+            Ok(None) => (),
+            Err(e) => error!("Error handling message from {}: {}", peer, e),
+        }
+        if session.lock().unwrap().shutdown_requested() {
+            info!("Client {} asked to shut down, closing its connection",
+                  peer);
+            break;
+        }
+    }
+    // The client may already be gone
+    let _ = socket.close(None).await;
+    info!("Client {} disconnected", peer);
+}
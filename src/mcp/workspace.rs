@@ -0,0 +1,446 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Index of the DML files in a workspace, as seen by MCP tools
+
+use anyhow::{anyhow, Result};
+use log::{debug, warn};
+use lsp_types::DiagnosticSeverity;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use crate::analysis::{content_hash, DMLError};
+use crate::analysis::deprecations::deprecation_errors;
+use crate::analysis::import_cycles::ImportGraph;
+use crate::analysis::stdlib_versions::{stdlib_version_errors,
+                                       SimicsApiVersion};
+
+use crate::cache;
+use crate::stats::{self, Cache};
+
+use super::analysis::{parse_dml, parse_dml_file, ParsedDML};
+use super::generation::DeviceSpec;
+
+/// A single indexed DML file
+#[derive(Debug, Clone)]
+pub struct IndexedFile {
+    pub path: PathBuf,
+    /// Register map extracted from the file
+    pub spec: DeviceSpec,
+    /// Whether the file declares a device
+    pub is_device: bool,
+    /// Number of syntax errors reported when parsing the file
+    pub error_count: usize,
+}
+
+impl IndexedFile {
+    fn new(parsed: &ParsedDML) -> Self {
+        IndexedFile {
+            path: parsed.path.clone(),
+            spec: parsed.to_device_spec(),
+            is_device: parsed.toplevel.device.is_some(),
+            error_count: parsed.errors.len(),
+        }
+    }
+}
+
+/// The DML files found under a workspace root
+#[derive(Debug, Clone, Default)]
+pub struct WorkspaceIndex {
+    pub root: PathBuf,
+    pub files: Vec<IndexedFile>,
+}
+
+/// Collect all `.dml` files under `root`, in a stable order
+pub fn find_dml_files(root: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = walkdir::WalkDir::new(root)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file()
+                && entry.path().extension().is_some_and(|ext| ext == "dml"))
+        .map(|entry| entry.into_path())
+        .collect();
+    files.sort();
+    files
+}
+
+impl WorkspaceIndex {
+    /// Parse every DML file under `root` that is not in the workspace cache
+    pub fn scan(root: &Path) -> Result<Self> {
+        if !root.is_dir() {
+            return Err(anyhow!("Workspace root {} is not a directory",
+                               root.display()));
+        }
+        let mut index = Self {
+            root: root.to_path_buf(),
+            files: vec![],
+        };
+        let paths = find_dml_files(root);
+        let mut cache = WorkspaceCache::load(root);
+        let summaries = cache.summaries(&paths, None);
+        cache.save();
+        for (path, summary) in paths.into_iter().zip(summaries) {
+            match summary {
+                Ok(summary) => index.files.push(IndexedFile {
+                    path,
+                    spec: summary.spec,
+                    is_device: summary.is_device,
+                    error_count: summary.error_count,
+                }),
+                Err(e) => warn!("Skipping {} in workspace index: {}",
+                                path.display(), e),
+            }
+        }
+        debug!("Indexed {} DML files under {}", index.files.len(),
+               root.display());
+        Ok(index)
+    }
+
+    /// Index a single file, for use without a workspace
+    pub fn single(parsed: &ParsedDML) -> Self {
+        Self {
+            root: parsed.path.parent().map(Path::to_path_buf)
+                .unwrap_or_default(),
+            files: vec![IndexedFile::new(parsed)],
+        }
+    }
+
+    /// The path of a file relative to the workspace root
+    pub fn relative_path<'a>(&self, path: &'a Path) -> &'a Path {
+        path.strip_prefix(&self.root).unwrap_or(path)
+    }
+}
+
+/// A diagnostic in a validated file, with one-based positions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub line: u32,
+    pub column: u32,
+    pub end_line: u32,
+    pub end_column: u32,
+    pub severity: String,
+    pub message: String,
+}
+
+impl From<&DMLError> for Diagnostic {
+    fn from(error: &DMLError) -> Self {
+        let range = error.span.range;
+        Diagnostic {
+            line: range.row_start.0 + 1,
+            column: range.col_start.0 + 1,
+            end_line: range.row_end.0 + 1,
+            end_column: range.col_end.0 + 1,
+            severity: match error.severity {
+                Some(DiagnosticSeverity::WARNING) => "warning",
+                Some(DiagnosticSeverity::INFORMATION) => "information",
+                Some(DiagnosticSeverity::HINT) => "hint",
+                _ => "error",
+            }.to_string(),
+            message: error.description.clone(),
+        }
+    }
+}
+
+impl Diagnostic {
+    // The diagnostic of a file that could not be validated at all
+    fn failure(error: &anyhow::Error) -> Self {
+        Diagnostic {
+            line: 1,
+            column: 1,
+            end_line: 1,
+            end_column: 1,
+            severity: "error".to_string(),
+            message: error.to_string(),
+        }
+    }
+}
+
+/// The diagnostics of a single file
+#[derive(Debug, Clone, Serialize)]
+pub struct FileDiagnostics {
+    pub file: PathBuf,
+    pub errors: usize,
+    pub warnings: usize,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl FileDiagnostics {
+    fn new(file: PathBuf, diagnostics: Vec<Diagnostic>) -> Self {
+        let count = |severity| diagnostics.iter()
+            .filter(|d| d.severity == severity).count();
+        FileDiagnostics {
+            file,
+            errors: count("error"),
+            warnings: count("warning"),
+            diagnostics,
+        }
+    }
+}
+
+/// Validate DML files in parallel, reporting syntax and structural errors,
+/// uses of deprecated names and, given an API version, uses of standard
+/// library items the version does not provide. The result is in the order
+/// of `paths`.
+pub fn validate_files(paths: &[PathBuf],
+                      simics_api: Option<SimicsApiVersion>)
+                      -> Vec<FileDiagnostics> {
+    paths.par_iter()
+        .map(|path| match parse_dml_file(path) {
+            Ok(parsed) => validate_parsed(parsed, simics_api),
+            Err(e) => FileDiagnostics::new(path.clone(),
+                                           vec![Diagnostic::failure(&e)]),
+        })
+        .collect()
+}
+
+/// Validate the DML files under `root` like `validate_files`, reusing the
+/// diagnostics of the files that are unchanged since they were cached
+pub fn validate_workspace(root: &Path, simics_api: Option<SimicsApiVersion>)
+                          -> Vec<FileDiagnostics> {
+    let paths = find_dml_files(root);
+    let mut cache = WorkspaceCache::load(root);
+    let summaries = cache.summaries(&paths, simics_api);
+    cache.save();
+    paths.into_iter().zip(summaries)
+        .map(|(path, summary)| match summary {
+            Ok(mut summary) => {
+                let diagnostics = summary.diagnostics
+                    .remove(&api_key(simics_api)).unwrap_or_default();
+                FileDiagnostics::new(path, diagnostics)
+            },
+            Err(e) => FileDiagnostics::new(path, vec![Diagnostic::failure(&e)]),
+        })
+        .collect()
+}
+
+/// Validate a single parsed file, see `validate_files`
+pub fn validate_parsed(parsed: ParsedDML,
+                       simics_api: Option<SimicsApiVersion>)
+                       -> FileDiagnostics {
+    let diagnostics = diagnostics(&parsed, simics_api);
+    FileDiagnostics::new(parsed.path, diagnostics)
+}
+
+fn diagnostics(parsed: &ParsedDML, simics_api: Option<SimicsApiVersion>)
+               -> Vec<Diagnostic> {
+    let mut errors = parsed.errors.clone();
+    errors.extend(deprecation_errors(&parsed.toplevel, simics_api));
+    if let Some(version) = simics_api {
+        errors.extend(stdlib_version_errors(&parsed.toplevel, version));
+    }
+    errors.sort_by_key(|e| e.span.range);
+    errors.iter().map(Diagnostic::from).collect()
+}
+
+/// The cache file of the summaries of the files of a workspace
+const WORKSPACE_CACHE_FILE: &str = "mcp-workspace.json";
+
+// Bump when the stored format, or what is extracted from a file, change
+const WORKSPACE_CACHE_FORMAT: u32 = 2;
+
+/// What MCP tools extract from a DML file, kept in the workspace cache
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileSummary {
+    // Hash of the contents the summary is of, see 'content_hash'
+    hash: u64,
+    pub spec: DeviceSpec,
+    pub is_device: bool,
+    /// Number of syntax errors reported when parsing the file
+    pub error_count: usize,
+    /// The names of the imported files, as written
+    pub imports: Vec<String>,
+    /// The diagnostics of validating the file, by the Simics API version
+    /// validated against, or "" for none
+    pub diagnostics: BTreeMap<String, Vec<Diagnostic>>,
+}
+
+fn api_key(simics_api: Option<SimicsApiVersion>) -> String {
+    simics_api.map(|version| version.to_string()).unwrap_or_default()
+}
+
+impl FileSummary {
+    fn new(parsed: &ParsedDML, hash: u64) -> Self {
+        FileSummary {
+            hash,
+            spec: parsed.to_device_spec(),
+            is_device: parsed.toplevel.device.is_some(),
+            error_count: parsed.errors.len(),
+            imports: import_names(parsed),
+            diagnostics: BTreeMap::from([(api_key(None),
+                                          diagnostics(parsed, None))]),
+        }
+    }
+}
+
+/// The summaries of the DML files of a workspace, as cached by earlier
+/// sessions, keyed by the paths of the files relative to the workspace root
+#[derive(Debug)]
+pub struct WorkspaceCache {
+    root: PathBuf,
+    files: BTreeMap<PathBuf, FileSummary>,
+    changed: bool,
+}
+
+impl WorkspaceCache {
+    /// The cache of the workspace at `root`, empty if there is none or
+    /// caching is off
+    pub fn load(root: &Path) -> Self {
+        WorkspaceCache {
+            root: root.to_path_buf(),
+            files: cache::load(root, WORKSPACE_CACHE_FILE,
+                               WORKSPACE_CACHE_FORMAT).unwrap_or_default(),
+            changed: false,
+        }
+    }
+
+    /// The summaries of files of the workspace, in the order of `paths`,
+    /// with the diagnostics of validating them against `simics_api`. The
+    /// files that are not cached with their current contents are parsed,
+    /// in parallel.
+    pub fn summaries(&mut self, paths: &[PathBuf],
+                     simics_api: Option<SimicsApiVersion>)
+                     -> Vec<Result<FileSummary>> {
+        let key = api_key(simics_api);
+        let results: Vec<Result<(FileSummary, bool)>> = paths.par_iter()
+            .map(|path| {
+                let text = std::fs::read_to_string(path).map_err(
+                    |e| anyhow!("Could not read {}: {}", path.display(), e))?;
+                let hash = content_hash(&text);
+                let cached = self.files.get(self.relative_path(path))
+                    .filter(|summary| summary.hash == hash);
+                if let Some(summary) = cached.filter(
+                    |summary| summary.diagnostics.contains_key(&key)) {
+                        return Ok((summary.clone(), false));
+                    }
+                let parsed = parse_dml(path, &text)?;
+                let mut summary = cached.cloned()
+                    .unwrap_or_else(|| FileSummary::new(&parsed, hash));
+                summary.diagnostics.entry(key.clone())
+                    .or_insert_with(|| diagnostics(&parsed, simics_api));
+                Ok((summary, true))
+            })
+            .collect();
+        let parsed = results.iter()
+            .filter(|result| result.as_ref().is_ok_and(|(_, parsed)| *parsed))
+            .count();
+        debug!("Parsed {} of {} files of {} not in its cache", parsed,
+               paths.len(), self.root.display());
+        let read = results.iter().filter(|result| result.is_ok()).count();
+        stats::record_cache(Cache::WorkspaceCache, (read - parsed) as u64,
+                            parsed as u64);
+        paths.iter().zip(results)
+            .map(|(path, result)| result.map(|(summary, parsed)| {
+                if parsed {
+                    self.files.insert(self.relative_path(path).to_path_buf(),
+                                      summary.clone());
+                    self.changed = true;
+                }
+                summary
+            }))
+            .collect()
+    }
+
+    fn relative_path<'a>(&self, path: &'a Path) -> &'a Path {
+        path.strip_prefix(&self.root).unwrap_or(path)
+    }
+
+    /// Save the cache, if it changed, without the files that no longer
+    /// exist
+    pub fn save(&mut self) {
+        if !self.changed {
+            return;
+        }
+        let root = &self.root;
+        self.files.retain(|path, _| root.join(path).is_file());
+        match cache::save(root, WORKSPACE_CACHE_FILE, WORKSPACE_CACHE_FORMAT,
+                          &self.files) {
+            Ok(()) => self.changed = false,
+            Err(e) => warn!("Could not save the cache of {}: {}",
+                            root.display(), e),
+        }
+    }
+}
+
+/// The imports between the DML files of a workspace
+#[derive(Debug, Clone, Default)]
+pub struct WorkspaceImports {
+    /// Canonical paths of files mapped to the files they import
+    pub graph: ImportGraph<PathBuf>,
+    /// Imports that could not be resolved, by importing file
+    pub unresolved: BTreeMap<PathBuf, Vec<String>>,
+}
+
+impl WorkspaceImports {
+    // Record the imports of a file, looking them up relative to the file
+    // and then to each of `dirs`
+    fn add(&mut self, file: PathBuf, names: Vec<String>, dirs: &[&Path]) {
+        let resolve = |name: &str| -> Option<PathBuf> {
+            file.parent().filter(|dir| !dir.as_os_str().is_empty())
+                .into_iter()
+                .chain(dirs.iter().copied())
+                .map(|dir| dir.join(name))
+                .find(|path| path.is_file())
+                .and_then(|path| path.canonicalize().ok())
+        };
+        let mut edges = BTreeSet::new();
+        for name in names {
+            match resolve(&name) {
+                Some(path) => {
+                    edges.insert(path);
+                },
+                None => self.unresolved.entry(file.clone()).or_default()
+                    .push(name),
+            }
+        }
+        self.graph.entry(file).or_default().extend(edges);
+    }
+}
+
+fn import_names(parsed: &ParsedDML) -> Vec<String> {
+    parsed.toplevel.spec.imports.iter()
+        .map(|import| import.obj.imported_name().to_string())
+        .collect()
+}
+
+/// Resolve the imports of every DML file under `root`. Imports are looked up
+/// relative to the importing file, then to `root` and then to each of
+/// `include_paths`, like dmlc does with '-I'.
+pub fn scan_imports(root: &Path, include_paths: &[PathBuf])
+                    -> WorkspaceImports {
+    let paths = find_dml_files(root);
+    let mut cache = WorkspaceCache::load(root);
+    let summaries = cache.summaries(&paths, None);
+    cache.save();
+    let files: Vec<(PathBuf, Vec<String>)> = paths.iter().zip(summaries)
+        .filter_map(|(path, summary)| {
+            let summary = summary
+                .map_err(|e| warn!("Skipping {} in import graph: {}",
+                                   path.display(), e))
+                .ok()?;
+            Some((path.canonicalize().ok()?, summary.imports))
+        })
+        .collect();
+    let dirs: Vec<&Path> = std::iter::once(root)
+        .chain(include_paths.iter().map(PathBuf::as_path))
+        .collect();
+    let mut imports = WorkspaceImports::default();
+    for (file, names) in files {
+        imports.add(file, names, &dirs);
+    }
+    imports
+}
+
+/// Resolve the direct imports of a single file, for use without a
+/// workspace. Imports are looked up relative to the file and then to each
+/// of `include_paths`.
+pub fn file_imports(parsed: &ParsedDML, include_paths: &[PathBuf])
+                    -> WorkspaceImports {
+    let file = parsed.path.canonicalize()
+        .unwrap_or_else(|_| parsed.path.clone());
+    let dirs: Vec<&Path> = include_paths.iter().map(PathBuf::as_path)
+        .collect();
+    let mut imports = WorkspaceImports::default();
+    imports.add(file, import_names(parsed), &dirs);
+    imports
+}
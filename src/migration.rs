@@ -0,0 +1,899 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Migration of DML 1.2 code to DML 1.4
+//!
+//! The migration rewrites the DML 1.2 constructs that have a direct DML 1.4
+//! counterpart, token by token so that the layout and comments of the code
+//! are kept: the language version, `parameter` and `data` declarations,
+//! `$` references, `call` and `inline` statements, method signatures with
+//! named return values or `nothrow`, array and bit range declarations,
+//! object-level `if`, `foreach` and `select`, log types and renamed
+//! templates and parameters. Constructs that need to be migrated by hand
+//! are reported as notes, along with anything the result does not parse as.
+use std::fmt;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use logos::Logos;
+use lsp_types::DiagnosticSeverity;
+use serde::{Deserialize, Serialize};
+
+use crate::analysis::deprecations::{deprecation, DeprecatedIn,
+                                    DeprecatedKind};
+use crate::analysis::parsing::lexer::TokenKind;
+use crate::analysis::{parse_file, FileSpec};
+use crate::file_management::dml_files;
+use crate::vfs::TextFile;
+
+/// A construct that has to be migrated by hand, on a zero-indexed line
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MigrationNote {
+    pub line: u32,
+    pub message: String,
+}
+
+/// DML 1.2 code migrated to DML 1.4
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Migration {
+    pub code: String,
+    /// The number of constructs that were rewritten
+    pub rewrites: usize,
+    /// What is left to migrate, by line
+    pub notes: Vec<MigrationNote>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MigrationError {
+    /// The code does not start with a `dml 1.2;` declaration
+    NotDml12,
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::NotDml12 =>
+                write!(f, "Only code declaring 'dml 1.2;' can be migrated"),
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+// The log types, which are strings in DML 1.2
+const LOG_TYPES: &[&str] = &["info", "error", "critical", "spec_viol",
+                             "unimpl", "warning"];
+
+// Methods of the DML 1.2 bank, register, field and attribute templates, which
+// are replaced by methods with other names or signatures in DML 1.4
+const DML12_API_METHODS: &[&str] = &[
+    "access", "after_read", "after_write", "before_read", "before_write",
+    "read_access", "write_access", "read_access_memop", "write_access_memop",
+    "miss_read_access", "miss_write_access", "after_set", "before_set",
+];
+
+// The objects that can be declared as arrays
+const ARRAY_OBJECTS: &[TokenKind] = &[
+    TokenKind::Attribute, TokenKind::Bank, TokenKind::Connect,
+    TokenKind::Event, TokenKind::Field, TokenKind::Group, TokenKind::Port,
+    TokenKind::Register, TokenKind::Subdevice,
+];
+
+// A token of code, `None` for tokens that do not lex
+#[derive(Debug, Clone)]
+struct Token<'a> {
+    kind: Option<TokenKind>,
+    text: &'a str,
+    bytes: Range<usize>,
+    line: u32,
+}
+
+// The tokens of `text`, except whitespace and comments
+fn code_tokens(text: &str) -> Vec<Token<'_>> {
+    let mut tokens = vec![];
+    let mut lexer = TokenKind::lexer(text);
+    let mut line = 0;
+    while let Some(kind) = lexer.next() {
+        let slice = lexer.slice();
+        let kind = kind.ok();
+        if !matches!(kind, Some(TokenKind::Whitespace | TokenKind::Newline
+                                | TokenKind::Comment
+                                | TokenKind::MultilineComment)) {
+            tokens.push(Token {
+                kind,
+                text: slice,
+                bytes: lexer.span(),
+                line,
+            });
+        }
+        line += slice.matches('\n').count() as u32;
+    }
+    tokens
+}
+
+// Where the tokens of a block are
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Block {
+    // The top level, and the bodies of objects, templates and
+    // object-level conditionals
+    Object,
+    // The body of a method
+    Method,
+    // Blocks in the body of a method
+    Code,
+}
+
+// A method whose body is being migrated
+#[derive(Debug, Clone)]
+struct MethodBody {
+    // The names of the return values, which are locals in DML 1.4
+    outputs: Vec<String>,
+    // The declarations of the locals
+    locals: Vec<String>,
+    nothrow: bool,
+    // Where the signature ends, which is where 'throws' goes
+    signature_end: usize,
+    // The indentation of the declaration
+    indent: String,
+}
+
+impl MethodBody {
+    // What the method returns at a 'return' without values
+    fn returned(&self) -> String {
+        match self.outputs.as_slice() {
+            [output] => output.clone(),
+            outputs => format!("({})", outputs.join(", ")),
+        }
+    }
+}
+
+struct Migrator<'a> {
+    text: &'a str,
+    tokens: Vec<Token<'a>>,
+    edits: Vec<(Range<usize>, String)>,
+    rewrites: usize,
+    notes: Vec<MigrationNote>,
+}
+
+impl<'a> Migrator<'a> {
+    fn new(text: &'a str) -> Self {
+        Migrator {
+            text,
+            tokens: code_tokens(text),
+            edits: vec![],
+            rewrites: 0,
+            notes: vec![],
+        }
+    }
+
+    fn kind(&self, index: usize) -> Option<TokenKind> {
+        self.tokens.get(index).and_then(|token|token.kind)
+    }
+
+    fn is(&self, index: usize, kind: TokenKind) -> bool {
+        self.kind(index) == Some(kind)
+    }
+
+    fn replace(&mut self, bytes: Range<usize>, text: impl Into<String>) {
+        self.edits.push((bytes, text.into()));
+    }
+
+    fn insert(&mut self, at: usize, text: impl Into<String>) {
+        self.replace(at..at, text);
+    }
+
+    // Remove a token along with the space after it
+    fn remove(&mut self, index: usize) {
+        let end = self.tokens.get(index + 1)
+            .map_or(self.tokens[index].bytes.end, |next|next.bytes.start);
+        self.replace(self.tokens[index].bytes.start..end, "");
+    }
+
+    fn note(&mut self, index: usize, message: String) {
+        self.notes.push(MigrationNote {
+            line: self.tokens[index].line,
+            message,
+        });
+    }
+
+    // The text from the start of one token to the end of another
+    fn source(&self, first: usize, last: usize) -> &'a str {
+        &self.text[self.tokens[first].bytes.start..self.tokens[last].bytes.end]
+    }
+
+    // The index of the parenthesis, bracket or brace closing the one at
+    // `open`
+    fn closing(&self, open: usize) -> Option<usize> {
+        let mut depth = 0;
+        for (index, token) in self.tokens.iter().enumerate().skip(open) {
+            match token.kind {
+                Some(TokenKind::LParen | TokenKind::LBracket
+                     | TokenKind::LBrace) => depth += 1,
+                Some(TokenKind::RParen | TokenKind::RBracket
+                     | TokenKind::RBrace) => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(index);
+                    }
+                },
+                _ => (),
+            }
+        }
+        None
+    }
+
+    // The index of the parenthesis opening the one at `close`
+    fn opening(&self, close: usize) -> Option<usize> {
+        let mut depth = 0;
+        for index in (0..=close).rev() {
+            match self.kind(index) {
+                Some(TokenKind::RParen) => depth += 1,
+                Some(TokenKind::LParen) => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(index);
+                    }
+                },
+                _ => (),
+            }
+        }
+        None
+    }
+
+    // The comma-separated parts of the list in the parentheses at `open`
+    // and `close`, as ranges of token indices
+    fn list(&self, open: usize, close: usize) -> Vec<Range<usize>> {
+        let mut parts = vec![];
+        let (mut start, mut depth) = (open + 1, 0);
+        for index in open + 1..close {
+            match self.kind(index) {
+                Some(TokenKind::LParen | TokenKind::LBracket
+                     | TokenKind::LBrace) => depth += 1,
+                Some(TokenKind::RParen | TokenKind::RBracket
+                     | TokenKind::RBrace) => depth -= 1,
+                Some(TokenKind::Comma) if depth == 0 => {
+                    parts.push(start..index);
+                    start = index + 1;
+                },
+                _ => (),
+            }
+        }
+        if start < close {
+            parts.push(start..close);
+        }
+        parts
+    }
+
+    fn starts_statement(&self, index: usize) -> bool {
+        index.checked_sub(1).is_none_or(|previous|matches!(
+            self.kind(previous), Some(TokenKind::SemiColon | TokenKind::LBrace
+                                      | TokenKind::RBrace)))
+    }
+
+    // The whitespace the line of a token starts with, and whether the
+    // token is first on its line
+    fn indentation(&self, index: usize) -> (&'a str, bool) {
+        let start = self.tokens[index].bytes.start;
+        let line_start = self.text[..start].rfind('\n').map_or(0, |i|i + 1);
+        let before = &self.text[line_start..start];
+        let indent_end = before.find(|c: char|!c.is_whitespace())
+            .unwrap_or(before.len());
+        (&before[..indent_end], indent_end == before.len())
+    }
+
+    fn version(&mut self) -> Result<(), MigrationError> {
+        if self.is(0, TokenKind::DML) && self.tokens.get(1)
+            .is_some_and(|token|token.text == "1.2")
+            && self.is(2, TokenKind::SemiColon) {
+                self.replace(self.tokens[1].bytes.clone(), "1.4");
+                self.rewrites += 1;
+                Ok(())
+            } else {
+                Err(MigrationError::NotDml12)
+            }
+    }
+
+    fn template_name(&mut self, index: usize) {
+        let name = self.tokens[index].text;
+        let Some(deprecated) = deprecation(DeprecatedKind::Template, name)
+            .filter(|d|d.deprecated_in == DeprecatedIn::Dml14) else {
+                return;
+            };
+        match deprecated.replacement.filter(|_|deprecated.safe) {
+            Some(replacement) => {
+                self.replace(self.tokens[index].bytes.clone(), replacement);
+                self.rewrites += 1;
+            },
+            None => self.note(index, format!(
+                "Template '{}' has no direct counterpart in DML 1.4", name)),
+        }
+    }
+
+    // The templates instantiated by the 'is' at `index`
+    fn instantiation(&mut self, index: usize) {
+        if self.is(index + 1, TokenKind::LParen) {
+            let Some(close) = self.closing(index + 1) else {
+                return;
+            };
+            for name in index + 2..close {
+                if self.is(name, TokenKind::Identifier) {
+                    self.template_name(name);
+                }
+            }
+        } else if self.is(index + 1, TokenKind::Identifier) {
+            self.template_name(index + 1);
+        }
+    }
+
+    // The 'parameter' or 'param' declaration at `index`
+    fn parameter(&mut self, index: usize) {
+        if self.tokens[index].text == "parameter" {
+            self.replace(self.tokens[index].bytes.clone(), "param");
+            self.rewrites += 1;
+        }
+        let name = self.tokens[index + 1].text;
+        match name {
+            "allocate_type" => self.note(index, format!(
+                "Parameter '{}' is not supported in DML 1.4; instantiate \
+                 a template such as 'uint64_attr' instead", name)),
+            "function" => self.note(index, format!(
+                "Parameter '{}' is not supported in DML 1.4; map the bank \
+                 in a memory space instead", name)),
+            _ => (),
+        }
+        let Some(deprecated) = deprecation(DeprecatedKind::Parameter, name)
+            .filter(|d|d.deprecated_in == DeprecatedIn::Dml14) else {
+                return;
+            };
+        match deprecated.replacement {
+            Some(replacement) if deprecated.safe => {
+                self.replace(self.tokens[index + 1].bytes.clone(), replacement);
+                self.rewrites += 1;
+            },
+            Some(replacement) => self.note(index, format!(
+                "Parameter '{}' is replaced by '{}' in DML 1.4, whose \
+                 behavior differs", name, replacement)),
+            None => self.note(index, format!(
+                "Parameter '{}' has no counterpart in DML 1.4", name)),
+        }
+    }
+
+    // The array dimensions and bit ranges of the object declared at `index`
+    fn object(&mut self, index: usize) {
+        let field = self.is(index, TokenKind::Field);
+        let mut open = index + 2;
+        while self.is(open, TokenKind::LBracket) {
+            let Some(close) = self.closing(open) else {
+                return;
+            };
+            if self.is(open + 1, TokenKind::Identifier)
+                && self.is(open + 2, TokenKind::In) {
+                    self.range_dimension(open, close);
+                } else if field {
+                    self.replace(self.tokens[open - 1].bytes.end
+                                 ..self.tokens[open].bytes.start, " @ ");
+                    self.rewrites += 1;
+                } else {
+                    // DML 1.2 arrays of a size have the index 'i'
+                    self.insert(self.tokens[open].bytes.end, "i < ");
+                    self.rewrites += 1;
+                }
+            open = close + 1;
+        }
+    }
+
+    // An '[i in 0..n]' array dimension, which is '[i < n + 1]' in DML 1.4
+    fn range_dimension(&mut self, open: usize, close: usize) {
+        let Some(dots) = (open + 3..close).find(
+            |&index|self.is(index, TokenKind::Dot)
+                && self.is(index + 1, TokenKind::Dot)) else {
+                return;
+            };
+        if dots != open + 4 || self.tokens[open + 3].text != "0" {
+            self.note(open, "Arrays start at index 0 in DML 1.4".to_string());
+            return;
+        }
+        if dots + 2 >= close {
+            return;
+        }
+        let last = &self.tokens[dots + 2..close];
+        let size = match last {
+            [token] if token.kind == Some(TokenKind::IntConstant) =>
+                token.text.replace('_', "").parse::<u64>().ok()
+                .map(|last|(last + 1).to_string()),
+            _ => None,
+        }.unwrap_or_else(||format!("({}) + 1",
+                                   self.source(dots + 2, close - 1)));
+        self.replace(self.tokens[open + 2].bytes.start
+                     ..self.tokens[close - 1].bytes.end,
+                     format!("< {}", size));
+        self.rewrites += 1;
+    }
+
+    // The signature of the method declared at `index`, and its body if it
+    // has one
+    fn method(&mut self, index: usize) -> Option<MethodBody> {
+        if !self.is(index + 1, TokenKind::Identifier) {
+            return None;
+        }
+        let name = self.tokens[index + 1].text;
+        if DML12_API_METHODS.contains(&name) {
+            self.note(index, format!(
+                "Method '{}' of the DML 1.2 standard library is replaced \
+                 by methods of other names or signatures in DML 1.4", name));
+        }
+        let mut next = index + 2;
+        if self.is(next, TokenKind::LParen) {
+            let close = self.closing(next)?;
+            // Untyped arguments are inline in DML 1.4, and so is the method
+            let untyped: Vec<Range<usize>> = self.list(next, close).into_iter()
+                .filter(|arg|arg.len() == 1).collect();
+            if !untyped.is_empty() {
+                self.insert(self.tokens[index].bytes.start, "inline ");
+                self.rewrites += 1;
+            }
+            for arg in untyped {
+                self.insert(self.tokens[arg.start].bytes.start, "inline ");
+            }
+            next = close + 1;
+        }
+        let (mut outputs, mut locals) = (vec![], vec![]);
+        if self.is(next, TokenKind::Arrow) && self.is(next + 1, TokenKind::LParen) {
+            let close = self.closing(next + 1)?;
+            let returns = self.list(next + 1, close);
+            if returns.iter().all(|output|output.len() > 1
+                                  && self.is(output.end - 1,
+                                             TokenKind::Identifier)) {
+                let types: Vec<&str> = returns.iter()
+                    .map(|output|self.source(output.start, output.end - 2))
+                    .collect();
+                outputs = returns.iter()
+                    .map(|output|self.tokens[output.end - 1].text.to_string())
+                    .collect();
+                locals = returns.iter()
+                    .map(|output|format!(
+                        "local {};", self.source(output.start,
+                                                 output.end - 1)))
+                    .collect();
+                self.replace(self.tokens[next + 1].bytes.end
+                             ..self.tokens[close].bytes.start,
+                             types.join(", "));
+                self.rewrites += 1;
+            } else {
+                self.note(index, format!(
+                    "Method '{}' has return values without types", name));
+            }
+            next = close + 1;
+        }
+        let nothrow = self.tokens.get(next)
+            .is_some_and(|token|token.text == "nothrow");
+        if nothrow {
+            self.replace(self.tokens[next - 1].bytes.end
+                         ..self.tokens[next].bytes.end, "");
+            self.rewrites += 1;
+            next += 1;
+        }
+        self.is(next, TokenKind::LBrace).then(||MethodBody {
+            outputs,
+            locals,
+            nothrow,
+            signature_end: self.tokens[next - 1].bytes.end,
+            indent: self.indentation(index).0.to_string(),
+        })
+    }
+
+    // The locals of the return values of a method, at the start of its body
+    fn method_start(&mut self, body: &MethodBody, open: usize) {
+        let on_same_line = self.tokens.get(open + 1)
+            .is_some_and(|next|next.line == self.tokens[open].line);
+        for local in &body.locals {
+            let text = if on_same_line {
+                format!(" {}", local)
+            } else {
+                format!("\n{}    {}", body.indent, local)
+            };
+            self.insert(self.tokens[open].bytes.end, text);
+        }
+    }
+
+    // The end of the body of a method, from `open` to `close`
+    fn method_end(&mut self, body: MethodBody, open: usize, close: usize) {
+        let throws = (open..close).any(|index|self.is(index, TokenKind::Throw));
+        let calls = (open..close).any(
+            |index|matches!(self.kind(index), Some(TokenKind::Call
+                                                   | TokenKind::Inline)));
+        if !body.nothrow && throws {
+            self.insert(body.signature_end, " throws");
+            self.rewrites += 1;
+        } else if !body.nothrow && calls {
+            self.note(open, "Methods do not throw by default in DML 1.4; \
+                             declare the method 'throws' if it calls \
+                             methods that throw".to_string());
+        }
+        if body.outputs.is_empty() {
+            return;
+        }
+        // Return the values at the end of the body, unless it ends by
+        // returning or throwing
+        let last_statement = (open..close.saturating_sub(1)).rev()
+            .find(|&index|matches!(self.kind(index), Some(
+                TokenKind::SemiColon | TokenKind::LBrace
+                    | TokenKind::RBrace)))
+            .map(|index|index + 1);
+        let returns = self.is(close - 1, TokenKind::SemiColon)
+            && last_statement.is_some_and(
+                |start|matches!(self.kind(start), Some(TokenKind::Return
+                                                       | TokenKind::Throw)));
+        if !returns {
+            let statement = format!("return {};", body.returned());
+            let text = if self.indentation(close).1 {
+                format!("    {}\n{}", statement, body.indent)
+            } else {
+                format!("{} ", statement)
+            };
+            self.insert(self.tokens[close].bytes.start, text);
+        }
+    }
+
+    // The 'call' or 'inline' statement at `index`
+    fn call(&mut self, index: usize) {
+        // 'after (t) call m()' is 'after t s: m()'
+        if let Some(open) = index.checked_sub(1)
+            .filter(|&close|self.is(close, TokenKind::RParen))
+            .and_then(|close|self.opening(close)) {
+                if open > 0 && self.is(open - 1, TokenKind::After) {
+                    self.replace(self.tokens[open].bytes.clone(), "");
+                    self.replace(self.tokens[index - 1].bytes.clone(), " s:");
+                    self.remove(index);
+                    self.rewrites += 1;
+                    return;
+                }
+            }
+        let mut depth = 0;
+        let mut end = index + 1;
+        while let Some(kind) = self.kind(end) {
+            match kind {
+                TokenKind::LParen | TokenKind::LBracket => depth += 1,
+                TokenKind::RParen | TokenKind::RBracket => depth -= 1,
+                TokenKind::Arrow | TokenKind::SemiColon if depth == 0 => break,
+                _ => (),
+            }
+            end += 1;
+        }
+        if self.is(end, TokenKind::Arrow) && self.is(end + 1, TokenKind::LParen) {
+            let Some(close) = self.closing(end + 1) else {
+                return;
+            };
+            let outputs = self.list(end + 1, close);
+            let assigned = match outputs.as_slice() {
+                [output] => self.source(output.start, output.end - 1)
+                    .to_string(),
+                _ => format!("({})", self.source(end + 2, close - 1)),
+            };
+            let start = self.tokens[index].bytes.start;
+            self.replace(start..self.tokens[index + 1].bytes.start,
+                         format!("{} = ", assigned));
+            self.replace(self.tokens[end - 1].bytes.end
+                         ..self.tokens[close].bytes.end, "");
+        } else {
+            self.remove(index);
+        }
+        self.rewrites += 1;
+    }
+
+    fn migrate(&mut self) {
+        let mut blocks: Vec<(Block, Option<MethodBody>, usize)> = vec![];
+        let mut pending: Option<MethodBody> = None;
+        for index in 0..self.tokens.len() {
+            let block = blocks.last().map_or(Block::Object, |(block, _, _)|
+                                             *block);
+            let Some(kind) = self.tokens[index].kind else {
+                continue;
+            };
+            match (kind, block) {
+                (TokenKind::LBrace, _) => {
+                    let body = pending.take();
+                    let inner = match (block, &body) {
+                        (Block::Object, Some(body)) => {
+                            self.method_start(body, index);
+                            Block::Method
+                        },
+                        (Block::Object, None) => Block::Object,
+                        _ => Block::Code,
+                    };
+                    blocks.push((inner, body, index));
+                },
+                (TokenKind::RBrace, _) => {
+                    if let Some((Block::Method, Some(body), open))
+                        = blocks.pop() {
+                            self.method_end(body, open, index);
+                        }
+                },
+                (TokenKind::Is, _) => self.instantiation(index),
+                (TokenKind::Identifier | TokenKind::Param, Block::Object)
+                    if self.starts_statement(index)
+                    && (kind == TokenKind::Param
+                        || self.tokens[index].text == "parameter")
+                    && self.is(index + 1, TokenKind::Identifier) =>
+                    self.parameter(index),
+                (TokenKind::Data, Block::Object)
+                    if self.starts_statement(index) => {
+                        self.replace(self.tokens[index].bytes.clone(),
+                                     "session");
+                        self.rewrites += 1;
+                    },
+                (TokenKind::Method, Block::Object) =>
+                    pending = self.method(index),
+                (kind, Block::Object) if ARRAY_OBJECTS.contains(&kind)
+                    && self.starts_statement(index)
+                    && self.is(index + 1, TokenKind::Identifier) =>
+                    self.object(index),
+                (TokenKind::If, Block::Object) => {
+                    self.replace(self.tokens[index].bytes.clone(), "#if");
+                    self.rewrites += 1;
+                },
+                (TokenKind::Else, Block::Object) => {
+                    self.replace(self.tokens[index].bytes.clone(), "#else");
+                    self.rewrites += 1;
+                },
+                (TokenKind::Foreach | TokenKind::Select, Block::Method
+                 | Block::Code) => {
+                    let text = format!("#{}", self.tokens[index].text);
+                    self.replace(self.tokens[index].bytes.clone(), text);
+                    self.rewrites += 1;
+                },
+                (TokenKind::Call | TokenKind::Inline, Block::Method
+                 | Block::Code) => self.call(index),
+                (TokenKind::Return, Block::Method | Block::Code)
+                    if self.is(index + 1, TokenKind::SemiColon) => {
+                        let returned = blocks.iter().rev()
+                            .find_map(|(_, body, _)|body.as_ref())
+                            .filter(|body|!body.outputs.is_empty())
+                            .map(MethodBody::returned);
+                        if let Some(returned) = returned {
+                            self.insert(self.tokens[index].bytes.end,
+                                        format!(" {}", returned));
+                        }
+                    },
+                (TokenKind::Log, _) => {
+                    let log_type = self.tokens.get(index + 1)
+                        .filter(|token|token.kind
+                                == Some(TokenKind::StringConstant))
+                        .map(|token|token.text.trim_matches('"'))
+                        .filter(|log_type|LOG_TYPES.contains(log_type));
+                    if let Some(log_type) = log_type {
+                        self.replace(self.tokens[index + 1].bytes.clone(),
+                                     log_type);
+                        self.rewrites += 1;
+                    }
+                },
+                _ => (),
+            }
+        }
+    }
+
+    fn apply(&mut self) -> String {
+        self.edits.sort_by_key(|(range, _)|(range.start, range.end));
+        let mut code = String::new();
+        let mut at = 0;
+        for (range, text) in &self.edits {
+            if range.start < at {
+                continue;
+            }
+            code.push_str(&self.text[at..range.start]);
+            code.push_str(text);
+            at = range.end;
+        }
+        code.push_str(&self.text[at..]);
+        code
+    }
+}
+
+// Remove the '$' of references, which do not lex
+fn without_dollars(text: &str) -> (String, usize) {
+    let mut code = String::new();
+    let mut removed = 0;
+    let mut lexer = TokenKind::lexer(text);
+    while let Some(kind) = lexer.next() {
+        if kind.is_err() && lexer.slice() == "$" {
+            removed += 1;
+        } else {
+            code.push_str(lexer.slice());
+        }
+    }
+    (code, removed)
+}
+
+// Notes on the syntax errors of migrated code
+fn syntax_notes(code: &str) -> Vec<MigrationNote> {
+    let Ok(file) = TextFile::from_str(code) else {
+        return vec![];
+    };
+    let path = Path::new("migrated.dml");
+    let Ok((_, _, errors)) = parse_file(path, FileSpec { path, file: &file })
+    else {
+        return vec![];
+    };
+    // Only the first error is reported, as the parser recovering from it
+    // tends to report more
+    errors.into_iter()
+        .find(|error|error.severity.is_none_or(
+            |severity|severity == DiagnosticSeverity::ERROR))
+        .map(|error|MigrationNote {
+            line: error.span.range.row_start.0,
+            message: format!("Does not parse as DML 1.4: {}",
+                             error.description),
+        })
+        .into_iter().collect()
+}
+
+/// Migrate DML 1.2 code to DML 1.4, see the module documentation
+pub fn migrate_source(text: &str) -> Result<Migration, MigrationError> {
+    let (text, dollars) = without_dollars(text);
+    let mut migrator = Migrator::new(&text);
+    migrator.version()?;
+    migrator.migrate();
+    let code = migrator.apply();
+    let mut notes = migrator.notes;
+    notes.extend(syntax_notes(&code));
+    notes.sort_by_key(|note|note.line);
+    notes.dedup();
+    Ok(Migration {
+        code,
+        rewrites: migrator.rewrites + dollars,
+        notes,
+    })
+}
+
+/// Exit code of `dls migrate` when parts of files need to be migrated by
+/// hand
+pub const EXIT_NOTES: i32 = 1;
+/// Exit code of `dls migrate` when files could not be migrated
+pub const EXIT_FAILURE: i32 = 2;
+
+/// Migrate DML 1.2 files, and the DML 1.2 files under directories, in place,
+/// or with `dry_run` only report what is left to migrate by hand. Returns the
+/// exit code of `dls migrate`.
+pub fn run(paths: &[PathBuf], dry_run: bool) -> i32 {
+    let mut files = vec![];
+    for path in paths {
+        if path.is_dir() {
+            // Files already in DML 1.4 are skipped
+            files.extend(dml_files(path, usize::MAX).filter(
+                |file|std::fs::read_to_string(file)
+                    .map(|text|migrate_source(&text).is_ok())
+                    .unwrap_or(true)));
+        } else if path.is_file() {
+            files.push(path.clone());
+        } else {
+            eprintln!("dls migrate: No such file or directory: {}",
+                      path.display());
+            return EXIT_FAILURE;
+        }
+    }
+    files.sort();
+    files.dedup();
+
+    let (mut failed, mut noted) = (false, false);
+    for file in &files {
+        let result = std::fs::read_to_string(file)
+            .map_err(|e|e.to_string())
+            .and_then(|text|migrate_source(&text).map_err(|e|e.to_string()));
+        let migration = match result {
+            Ok(migration) => migration,
+            Err(e) => {
+                eprintln!("dls migrate: {}: {}", file.display(), e);
+                failed = true;
+                continue;
+            },
+        };
+        for note in &migration.notes {
+            println!("{}:{}: {}", file.display(), note.line + 1,
+                     note.message);
+            noted = true;
+        }
+        if !dry_run {
+            if let Err(e) = std::fs::write(file, &migration.code) {
+                eprintln!("dls migrate: {}: {}", file.display(), e);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        EXIT_FAILURE
+    } else if noted {
+        EXIT_NOTES
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Migrate the statements of a DML 1.2 file
+    fn migrate(text: &str) -> Migration {
+        let mut migration = migrate_source(&format!("dml 1.2;\n{}", text))
+            .unwrap();
+        migration.code = migration.code.strip_prefix("dml 1.4;\n").unwrap()
+            .to_string();
+        migration
+    }
+
+    fn code(text: &str) -> String {
+        migrate(text).code
+    }
+
+    fn messages(text: &str) -> Vec<String> {
+        migrate(text).notes.into_iter().map(|note|note.message).collect()
+    }
+
+    #[test]
+    fn test_migrate_declarations() {
+        assert_eq!(migrate_source("dml 1.4;\ndevice d;\n"),
+                   Err(MigrationError::NotDml12));
+        assert_eq!(code("device d;\nparameter desc = \"D\";\n\
+                         data int count;\n"),
+                   "device d;\nparam desc = \"D\";\nsession int count;\n");
+        assert_eq!(code("bank b {\n    register r[4] size 4 @ 0x4 * $i {\n\
+                         \x20       field f[3:0];\n        field g[7];\n\
+                         \x20   }\n    register s[j in 0..$n - 1] size 4 \
+                         @ 0x20 + $j * 4;\n}\n"),
+                   "bank b {\n    register r[i < 4] size 4 @ 0x4 * i {\n\
+                    \x20       field f @ [3:0];\n        field g @ [7];\n\
+                    \x20   }\n    register s[j < (n - 1) + 1] size 4 \
+                    @ 0x20 + j * 4;\n}\n");
+        assert_eq!(code("field f[i in 0..3][i * 2 + 1:i * 2];\n"),
+                   "field f[i < 4] @ [i * 2 + 1:i * 2];\n");
+        assert_eq!(code("if ($a) {\n    bank b;\n} else {\n    bank c;\n}\n\
+                         register r @ 0 is (unimplemented) {\n\
+                         \x20   parameter hard_reset_value = 1;\n}\n"),
+                   "#if (a) {\n    bank b;\n} #else {\n    bank c;\n}\n\
+                    register r @ 0 is (unimpl) {\n\
+                    \x20   param init_val = 1;\n}\n");
+    }
+
+    #[test]
+    fn test_migrate_methods() {
+        assert_eq!(code("method m(int a) -> (int b, bool c) nothrow {\n    \
+                         if (a == 0)\n        return;\n    b = a;\n}\n"),
+                   "method m(int a) -> (int, bool) {\n    local int b;\n    \
+                    local bool c;\n    if (a == 0)\n        return (b, c);\n    \
+                    b = a;\n    return (b, c);\n}\n");
+        assert_eq!(code("method m(x) -> (int y) { y = x; return; }\n"),
+                   "inline method m(inline x) -> (int) { local int y; y = x; \
+                    return y; }\n");
+        assert_eq!(code("method m() {\n    if ($x)\n        throw;\n}\n"),
+                   "method m() throws {\n    if (x)\n        throw;\n}\n");
+        assert_eq!(code("method m() nothrow {\n    local int v;\n    \
+                         call $n(1) -> (v);\n    inline $o();\n    \
+                         call p() -> (v, $w);\n    \
+                         after (0.1) call $q();\n    \
+                         foreach x in ($l) log \"info\", 1: \"x\";\n}\n"),
+                   "method m() {\n    local int v;\n    v = n(1);\n    \
+                    o();\n    (v, w) = p();\n    after 0.1 s: q();\n    \
+                    #foreach x in (l) log info, 1: \"x\";\n}\n");
+    }
+
+    #[test]
+    fn test_migration_notes() {
+        assert_eq!(messages("method m() -> (y) { }\n"),
+                   vec!["Method 'm' has return values without types"]);
+        assert_eq!(messages("method n() { call $m(); }\n"),
+                   vec!["Methods do not throw by default in DML 1.4; declare \
+                         the method 'throws' if it calls methods that throw"]);
+        assert_eq!(messages("bank b {\n    register r[i in 1..4] size 4 \
+                             @ 0;\n    parameter function = 1;\n}\n"),
+                   vec!["Arrays start at index 0 in DML 1.4",
+                        "Does not parse as DML 1.4: Expected '<', got 'in'",
+                        "Parameter 'function' is not supported in DML 1.4; \
+                         map the bank in a memory space instead"]);
+        let notes = migrate("bank b {\n    method read_access() nothrow {}\n\
+                             \x20   parameter soft_reset_value = 1;\n}\n")
+            .notes;
+        assert_eq!(notes.iter().map(|note|note.line).collect::<Vec<_>>(),
+                   vec![2, 3]);
+        assert!(notes[1].message.contains("'soft_reset_val'"));
+    }
+}
@@ -0,0 +1,269 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Project configuration files
+//!
+//! A `dml_project.toml` file at the root of a workspace holds the settings
+//! shared by everyone working on the project, so that they need not be set
+//! up in each editor and MCP client:
+//! ```toml
+//! # Directories searched for imported files, as given to dmlc with -I
+//! include_paths = ["include", "../common/dml"]
+//! # The DML base library of the Simics installation in use
+//! base_library = "/opt/simics/simics-7.0.0/linux64/bin/dml"
+//! dml_version = "1.4"
+//! simics_api_version = "7"
+//!
+//! # Lint rules, as in the lint configuration file
+//! [lint]
+//! long_lines = { max_length = 100 }
+//!
+//! # Default arguments of the MCP generation tools
+//! [generation]
+//! line_ending = "windows"
+//! indent = 2
+//! ```
+//! Relative paths are relative to the directory of the file. Settings given
+//! to the language server, such as `lint_cfg_path`, and arguments given to
+//! MCP tools take precedence over those of the project file.
+
+use anyhow::{anyhow, bail, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use crate::analysis::stdlib_versions::SimicsApiVersion;
+use crate::lint::LintCfg;
+
+/// The name of project configuration files
+pub const PROJECT_FILE: &str = "dml_project.toml";
+
+/// The DML versions projects can declare
+const DML_VERSIONS: &[&str] = &["1.4"];
+
+/// The settings of a project configuration file
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProjectConfig {
+    /// Directories searched for imported files before the workspace
+    pub include_paths: Vec<PathBuf>,
+    /// Directory of the DML base library, searched after everything else
+    pub base_library: Option<PathBuf>,
+    /// DML version of the files of the project
+    pub dml_version: Option<String>,
+    /// Simics API version the project is built for, e.g. "7"
+    pub simics_api_version: Option<String>,
+    /// Lint rules, as in the lint configuration file
+    pub lint: Option<serde_json::Value>,
+    /// Default arguments of MCP tools, by argument name
+    pub generation: serde_json::Map<String, serde_json::Value>,
+}
+
+impl ProjectConfig {
+    /// Parse a project file in the directory `dir`, which relative paths
+    /// are resolved against
+    pub fn parse(dir: &Path, text: &str) -> Result<Self> {
+        let mut project: ProjectConfig = toml::from_str(text)
+            .map_err(|e| anyhow!("{}", e.message()))?;
+        if let Some(version) = &project.dml_version {
+            if !DML_VERSIONS.contains(&version.as_str()) {
+                bail!("Unsupported DML version '{}', expected one of {}",
+                      version, DML_VERSIONS.join(", "));
+            }
+        }
+        if let Some(version) = &project.simics_api_version {
+            version.parse::<SimicsApiVersion>().map_err(|e| anyhow!(e))?;
+        }
+        for path in project.include_paths.iter_mut()
+            .chain(project.base_library.as_mut()) {
+                *path = dir.join(&*path);
+            }
+        Ok(project)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Could not read {}: {}", path.display(), e))?;
+        Self::parse(path.parent().unwrap_or(Path::new("")), &text)
+            .map_err(|e| anyhow!("Invalid {}: {}", path.display(), e))
+    }
+
+    /// The directories searched for imported files after the workspace
+    pub fn library_paths(&self) -> Vec<PathBuf> {
+        self.base_library.iter().cloned().collect()
+    }
+
+    pub fn simics_api(&self) -> Option<SimicsApiVersion> {
+        self.simics_api_version.as_ref()
+            .and_then(|version| version.parse().ok())
+    }
+
+    /// The default arguments of MCP tools: those of the `[generation]`
+    /// table, and the include paths and Simics API version of the project
+    pub fn tool_defaults(&self) -> serde_json::Map<String, serde_json::Value> {
+        let mut defaults = self.generation.clone();
+        let include_paths: Vec<&PathBuf> = self.include_paths.iter()
+            .chain(&self.base_library).collect();
+        if !include_paths.is_empty() {
+            defaults.entry("include_paths")
+                .or_insert(serde_json::json!(include_paths));
+        }
+        if let Some(version) = &self.simics_api_version {
+            defaults.entry("simics_api")
+                .or_insert(serde_json::json!(version));
+        }
+        defaults
+    }
+
+    /// The lint rules of the project, if it sets them, along with the
+    /// fields that are not lint rules
+    pub fn lint_config(&self) -> Option<Result<(LintCfg, Vec<String>)>> {
+        self.lint.as_ref().map(|lint| {
+            let mut unknowns = vec![];
+            LintCfg::try_deserialize(lint, &mut unknowns)
+                .map(|cfg| (cfg, unknowns))
+                .map_err(|e| anyhow!("Invalid lint settings: {}", e))
+        })
+    }
+}
+
+/// Modification time and size of a project file, if it exists
+type Stamp = Option<(SystemTime, u64)>;
+
+fn stamp(path: &Path) -> Stamp {
+    std::fs::metadata(path)
+        .and_then(|meta| Ok((meta.modified()?, meta.len()))).ok()
+}
+
+fn load_project(path: &Path, stamp: &Stamp) -> ProjectConfig {
+    if stamp.is_none() {
+        return ProjectConfig::default();
+    }
+    match ProjectConfig::load(path) {
+        Ok(project) => project,
+        Err(e) => {
+            warn!("{}", e);
+            ProjectConfig::default()
+        },
+    }
+}
+
+/// The project configuration in use, reloaded when its file changes
+#[derive(Debug)]
+pub struct ProjectStore {
+    path: Option<PathBuf>,
+    state: Mutex<(Stamp, Arc<ProjectConfig>)>,
+}
+
+impl ProjectStore {
+    /// A store with the project file at `path`, which need not exist yet
+    pub fn new(path: Option<PathBuf>) -> Self {
+        let stamp = path.as_deref().and_then(stamp);
+        let project = path.as_deref()
+            .map(|path| load_project(path, &stamp)).unwrap_or_default();
+        Self {
+            path,
+            state: Mutex::new((stamp, Arc::new(project))),
+        }
+    }
+
+    /// A store with the project file of `dir` or the closest directory
+    /// above it that has one, or else that of `dir` once it is created
+    pub fn discover(dir: &Path) -> Self {
+        let path = dir.ancestors().map(|dir| dir.join(PROJECT_FILE))
+            .find(|path| path.is_file())
+            .unwrap_or_else(|| dir.join(PROJECT_FILE));
+        if path.is_file() {
+            info!("Loading project configuration from {}", path.display());
+        }
+        Self::new(Some(path))
+    }
+
+    /// The project file the configuration is read from
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// The current configuration, re-reading the project file if it has
+    /// been created, removed or modified since it was last read
+    pub fn current(&self) -> Arc<ProjectConfig> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(path) = &self.path {
+            let stamp = stamp(path);
+            if stamp != state.0 {
+                info!("Project configuration changed, reloading");
+                state.1 = Arc::new(load_project(path, &stamp));
+                state.0 = stamp;
+            }
+        }
+        Arc::clone(&state.1)
+    }
+}
+
+impl Default for ProjectStore {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_project() {
+        let project = ProjectConfig::parse(Path::new("/p"), r#"
+include_paths = ["include", "/abs"]
+base_library = "../simics/dml"
+dml_version = "1.4"
+simics_api_version = "7"
+
+[lint]
+long_lines = { max_length = 100 }
+no_such_rule = {}
+
+[generation]
+line_ending = "windows"
+indent = 2
+"#).unwrap();
+        assert_eq!(project.include_paths, vec![PathBuf::from("/p/include"),
+                                               PathBuf::from("/abs")]);
+        assert_eq!(project.library_paths(),
+                   vec![PathBuf::from("/p/../simics/dml")]);
+        assert_eq!(project.simics_api(), Some(SimicsApiVersion(7)));
+        assert_eq!(project.generation["indent"], 2);
+        let (lint, unknowns) = project.lint_config().unwrap().unwrap();
+        assert_eq!(lint.long_lines.unwrap().max_length, 100);
+        assert_eq!(unknowns, vec!["no_such_rule"]);
+
+        assert_eq!(ProjectConfig::parse(Path::new("/p"), "").unwrap(),
+                   ProjectConfig::default());
+        assert!(ProjectConfig::parse(Path::new("/p"), "dml_version = \"1.2\"")
+                .is_err());
+        assert!(ProjectConfig::parse(Path::new("/p"), "includes = []")
+                .is_err());
+    }
+
+    #[test]
+    fn test_project_store_reloads() {
+        let dir = std::env::temp_dir().join(format!("dls-project-test-{}",
+                                                    std::process::id()));
+        let sub = dir.join("sub");
+        std::fs::create_dir_all(&sub).unwrap();
+        let store = ProjectStore::discover(&sub);
+        assert_eq!(store.path(), Some(sub.join(PROJECT_FILE).as_path()));
+        assert_eq!(*store.current(), ProjectConfig::default());
+
+        std::fs::write(dir.join(PROJECT_FILE), "simics_api_version = \"6\"")
+            .unwrap();
+        let store = ProjectStore::discover(&sub);
+        assert_eq!(store.path(), Some(dir.join(PROJECT_FILE).as_path()));
+        assert_eq!(store.current().simics_api(), Some(SimicsApiVersion(6)));
+        std::fs::write(dir.join(PROJECT_FILE), "simics_api_version = \"7\"\n")
+            .unwrap();
+        assert_eq!(store.current().simics_api(), Some(SimicsApiVersion(7)));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(*store.current(), ProjectConfig::default());
+    }
+}
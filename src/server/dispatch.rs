@@ -102,17 +102,31 @@ define_dispatch_request_enum!(
     DocumentSymbolRequest,
     WorkspaceSymbolRequest,
     HoverRequest,
+    InlayHintRequest,
+    SignatureHelpRequest,
+    CallHierarchyPrepare,
+    CallHierarchyIncomingCalls,
+    CallHierarchyOutgoingCalls,
+    FoldingRangeRequest,
+    SelectionRangeRequest,
     GotoImplementation,
     GotoDeclaration,
     DocumentHighlightRequest,
     Rename,
+    PrepareRenameRequest,
     CodeActionRequest,
     ResolveCompletion,
     Formatting,
     RangeFormatting,
+    OnTypeFormatting,
+    SemanticTokensFullRequest,
+    SemanticTokensRangeRequest,
     ExecuteCommand,
     CodeLensRequest,
     GetKnownContextsRequest,
+    RegisterMapRequest,
+    BankLayoutRequest,
+    ExpandTemplatesRequest,
 );
 
 /// Provides ability to dispatch requests to a worker thread that will
@@ -1,16 +1,20 @@
 //  © 2024 Intel Corporation
 //  SPDX-License-Identifier: Apache-2.0 and MIT
 use jsonrpc::error::{RpcError, StandardError, standard_error};
-use log::{debug, trace};
+use log::{debug, trace, warn};
 use serde_json::Value;
 
 use super::{Notification, Request, RequestId};
 use crate::lsp_data::{LSPNotification, LSPRequest};
 
 use std::fmt;
-use std::io::{self, BufRead, Write};
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use jsonrpc::Response;
 
@@ -39,16 +43,138 @@ impl MessageReader for StdioMsgReader {
     }
 }
 
+/// A connection to a client, over which the language server protocol is
+/// spoken as on stdin and stdout.
+pub trait Connection: Read + Write + Send + Sized + 'static {
+    /// Another handle to the same connection.
+    fn try_clone(&self) -> io::Result<Self>;
+
+    /// Closes the connection in both directions.
+    fn close(&self);
+
+    /// Makes reads fail once nothing has been received for `timeout`, or
+    /// wait forever if `None`.
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl Connection for TcpStream {
+    fn try_clone(&self) -> io::Result<Self> {
+        TcpStream::try_clone(self)
+    }
+
+    fn close(&self) {
+        // The client may already have closed it
+        let _ = self.shutdown(Shutdown::Both);
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+}
+
+#[cfg(unix)]
+impl Connection for UnixStream {
+    fn try_clone(&self) -> io::Result<Self> {
+        UnixStream::try_clone(self)
+    }
+
+    fn close(&self) {
+        let _ = self.shutdown(Shutdown::Both);
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UnixStream::set_read_timeout(self, timeout)
+    }
+}
+
+/// A message reader that gets messages from a connection to a client.
+///
+/// Unlike stdin, the connection may be to anyone who can reach the server,
+/// so the messages read are limited in size, and reading fails, ending the
+/// session, when the client sends nothing for a while.
+pub(super) struct ConnectionMsgReader<C> {
+    input: Mutex<BufReader<C>>,
+    /// A message to read before those of the connection
+    unread: Mutex<Option<String>>,
+    /// The largest message read, in bytes
+    max_message_size: AtomicUsize,
+}
+
+impl<C: Connection> ConnectionMsgReader<C> {
+    pub(crate) fn new(connection: C) -> ConnectionMsgReader<C> {
+        ConnectionMsgReader {
+            input: Mutex::new(BufReader::new(connection)),
+            unread: Mutex::new(None),
+            max_message_size: AtomicUsize::new(usize::MAX),
+        }
+    }
+
+    /// Fails reading messages larger than `max_message_size` bytes, or once
+    /// the client has sent nothing for `timeout`.
+    pub(crate) fn limit(&self, max_message_size: usize,
+                        timeout: Option<Duration>) -> io::Result<()> {
+        self.max_message_size.store(max_message_size, Ordering::SeqCst);
+        self.input.lock().unwrap().get_ref().set_read_timeout(timeout)
+    }
+
+    /// Makes `message` the next message read.
+    pub(crate) fn unread(&self, message: String) {
+        *self.unread.lock().unwrap() = Some(message);
+    }
+}
+
+impl<C: Connection> MessageReader for ConnectionMsgReader<C> {
+    fn read_message(&self) -> Option<String> {
+        if let Some(message) = self.unread.lock().unwrap().take() {
+            return Some(message);
+        }
+        let max_size = self.max_message_size.load(Ordering::SeqCst);
+        match read_limited_message(&mut *self.input.lock().unwrap(), max_size) {
+            Ok(message) => Some(message),
+            Err(err) if matches!(err.kind(), io::ErrorKind::WouldBlock
+                                 | io::ErrorKind::TimedOut) => {
+                warn!("Client sent nothing for too long, disconnecting it");
+                None
+            },
+            Err(err) => {
+                debug!("{}", err);
+                None
+            }
+        }
+    }
+}
+
 // Reads the content of the next message from given input.
 //
 // The input is expected to provide a message as described by "Base Protocol" of Language Server
 // Protocol.
 pub fn read_message<R: BufRead>(input: &mut R) -> Result<String, io::Error> {
+    read_limited_message(input, usize::MAX)
+}
+
+/// The longest header line read by `read_limited_message`, in bytes
+const MAX_HEADER_LENGTH: u64 = 1024;
+
+/// Reads the content of the next message from given input, as
+/// `read_message` does, failing if the content is larger than `max_size`
+/// bytes or a header is unreasonably long.
+pub fn read_limited_message<R: BufRead>(input: &mut R, max_size: usize)
+                                        -> Result<String, io::Error> {
     // Read in the "Content-Length: xx" part.
     let mut size: Option<usize> = None;
     loop {
         let mut buffer = String::new();
-        input.read_line(&mut buffer)?;
+        if max_size == usize::MAX {
+            input.read_line(&mut buffer)?;
+        } else {
+            input.by_ref().take(MAX_HEADER_LENGTH).read_line(&mut buffer)?;
+            if !buffer.is_empty() && !buffer.ends_with('\n') {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Header is too long",
+                ));
+            }
+        }
 
         // End of input.
         if buffer.is_empty() {
@@ -103,6 +229,14 @@ pub fn read_message<R: BufRead>(input: &mut R) -> Result<String, io::Error> {
         }
     };
 
+    if size > max_size {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Message of {} bytes is larger than the limit of {} bytes",
+                    size, max_size),
+        ));
+    }
+
     let mut content = vec![0; size];
     input.read_exact(&mut content)?;
 
@@ -218,6 +352,52 @@ impl Output for StdioOutput {
     }
 }
 
+/// An output that sends notifications and responses on a connection to a
+/// client.
+pub(super) struct ConnectionOutput<C> {
+    output: Arc<Mutex<C>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl<C> Clone for ConnectionOutput<C> {
+    fn clone(&self) -> Self {
+        ConnectionOutput {
+            output: Arc::clone(&self.output),
+            next_id: Arc::clone(&self.next_id),
+        }
+    }
+}
+
+impl<C: Connection> ConnectionOutput<C> {
+    /// Constructs a new output on `connection`.
+    pub(crate) fn new(connection: C) -> ConnectionOutput<C> {
+        ConnectionOutput {
+            output: Arc::new(Mutex::new(connection)),
+            next_id: Arc::new(AtomicU64::new(1)),
+        }
+    }
+}
+
+impl<C: Connection> Output for ConnectionOutput<C> {
+    fn response(&self, output: String) {
+        let o = format!("Content-Length: {}\r\n\r\n{}", output.len(), output);
+
+        trace!("response: {:?}", o);
+
+        let mut connection = self.output.lock().unwrap();
+        // Unlike stdout, the connection goes away when the client does,
+        // which ends the session when the next message is read
+        if let Err(e) = connection.write_all(o.as_bytes())
+            .and_then(|_| connection.flush()) {
+                debug!("Could not send message to client: {}", e);
+            }
+    }
+
+    fn provide_id(&self) -> RequestId {
+        RequestId::Num(self.next_id.fetch_add(1, Ordering::SeqCst))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -315,4 +495,53 @@ mod tests {
 
         read_message(&mut input).expect_err("Reading should fail when input ends after header.");
     }
+
+    #[test]
+    fn connection_output_is_read_by_connection_reader() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+
+        let output = ConnectionOutput::new(server);
+        output.response("{\"id\":1}".to_owned());
+        output.clone().response("{\"id\":2}".to_owned());
+        assert_eq!(output.provide_id(), RequestId::Num(1));
+        assert_eq!(output.clone().provide_id(), RequestId::Num(2));
+
+        let reader = ConnectionMsgReader::new(client.try_clone().unwrap());
+        assert_eq!(reader.read_message().unwrap(), "{\"id\":1}");
+        assert_eq!(reader.read_message().unwrap(), "{\"id\":2}");
+        output.output.lock().unwrap().close();
+        assert!(reader.read_message().is_none());
+    }
+
+    #[test]
+    fn read_limited_message_fails_on_large_messages() {
+        let message = "Content-Length: 12\r\n\r\nSome Message";
+        read_limited_message(&mut io::Cursor::new(message), 12)
+            .expect("Reading a message within the limit should succeed");
+        read_limited_message(&mut io::Cursor::new(message), 11)
+            .expect_err("Reading a message over the limit should fail");
+
+        let header = format!("X-Padding: {}\r\n{}", "x".repeat(2000), message);
+        read_limited_message(&mut io::Cursor::new(header), 12)
+            .expect_err("Reading a message with a long header should fail");
+    }
+
+    #[test]
+    fn connection_reader_stops_at_limits() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+
+        let reader = ConnectionMsgReader::new(server);
+        reader.limit(8, Some(Duration::from_millis(50))).unwrap();
+        assert!(reader.read_message().is_none());
+
+        let output = ConnectionOutput::new(client);
+        output.response("{\"id\":1}".to_owned());
+        output.response("{\"id\":10}".to_owned());
+        assert_eq!(reader.read_message().unwrap(), "{\"id\":1}");
+        assert!(reader.read_message().is_none());
+    }
 }
@@ -4,10 +4,14 @@
 //! interactions (for example, to add support for handling new types of
 //! requests).
 
-use crate::actions::{notifications, requests, ActionContext};
+use crate::actions::{code_lens, notifications, requests, semantic_tokens,
+                     ActionContext};
 use crate::analysis::IMPLICIT_IMPORTS;
+use crate::auth::AuthToken;
 use crate::config::{Config, DeviceContextMode, DEPRECATED_OPTIONS};
 use crate::file_management::CanonPath;
+use crate::lint::{parse_lint_cfg, LintCfg};
+use crate::lint::rules::indentation::setup_indentation_size;
 use crate::lsp_data;
 use crate::lsp_data::{
     InitializationOptions, LSPNotification, LSPRequest, MessageType,
@@ -17,7 +21,8 @@ use crate::server::dispatch::Dispatcher;
 pub use crate::server::dispatch::{RequestAction, SentRequest,
                                   DEFAULT_REQUEST_TIMEOUT};
 pub use crate::server::io::{MessageReader, Output};
-use crate::server::io::{StdioMsgReader, StdioOutput};
+use crate::server::io::{Connection, ConnectionMsgReader, ConnectionOutput,
+                        StdioMsgReader, StdioOutput};
 use crate::server::message::{RawMessage, RawMessageOrResponse, RawResponse};
 pub use crate::server::message::{
     Ack, BlockingNotificationAction, BlockingRequestAction,
@@ -25,21 +30,33 @@ pub use crate::server::message::{
     RequestId, Response, ResponseError, ResponseWithMessage,
 };
 use crate::version;
-use jsonrpc::error::StandardError;
+use jsonrpc::error::{RpcError, StandardError};
 use log::{debug, error, info, trace, warn};
 pub use lsp_types::notification::{Exit as ExitNotification, ShowMessage};
 pub use lsp_types::request::Initialize as InitializeRequest;
 pub use lsp_types::request::Shutdown as ShutdownRequest;
 use lsp_types::{
+    CallHierarchyServerCapability,
+    CodeActionProviderCapability,
+    CodeLensOptions,
+    CompletionOptions,
     DeclarationCapability,
+    DocumentOnTypeFormattingOptions,
+    ExecuteCommandOptions,
+    FoldingRangeProviderCapability,
     HoverProviderCapability,
     ImplementationProviderCapability,
-    InitializeResult, OneOf, ServerCapabilities,
+    InitializeResult, OneOf, RenameOptions, SemanticTokensFullOptions,
+    SemanticTokensOptions, SemanticTokensServerCapabilities,
+    ServerCapabilities,
+    SelectionRangeProviderCapability,
     ServerInfo,
+    SignatureHelpOptions,
     TextDocumentSyncCapability,
     TextDocumentSyncOptions,
     TextDocumentSyncKind,
     TextDocumentSyncSaveOptions,
+    WorkDoneProgressOptions,
     WorkspaceServerCapabilities,
     WorkspaceFoldersServerCapabilities,
 };
@@ -47,13 +64,16 @@ use crossbeam::channel;
 use serde::Serialize;
 use serde_json::Value;
 
+use std::net::{SocketAddr, TcpListener};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 use std::thread;
 
 use crate::vfs::Vfs;
 
-use std::sync::atomic::Ordering;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 
 pub mod dispatch;
@@ -63,21 +83,231 @@ pub mod message;
 /// Implementation-defined server-error, according to chapter 5.1:
 /// https://www.jsonrpc.org/specification
 const NOT_INITIALIZED_CODE: i32 = -32002;
+/// Implementation-defined server-error, for clients that have not presented
+/// the authentication token
+const UNAUTHENTICATED_CODE: i32 = -32001;
+
+/// The largest message a client connecting over the network can send, in
+/// bytes
+const MAX_MESSAGE_SIZE: usize = 64 * 1024 * 1024;
+/// How long a client connecting over the network can send nothing before
+/// it is disconnected, by default
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+/// The largest message a client connecting over the network can send
+/// before it has presented the authentication token, in bytes
+const MAX_UNAUTHENTICATED_MESSAGE_SIZE: usize = 16 * 1024;
+/// How long a client connecting over the network has to present the
+/// authentication token
+const AUTHENTICATION_TIMEOUT: Duration = Duration::from_secs(10);
+/// The most clients connecting over the network served at once
+const MAX_CLIENTS: usize = 16;
 
 /// Runs the DML Language Server.
-pub fn run_server(vfs: Arc<Vfs>) -> i32 {
+pub fn run_server(vfs: Arc<Vfs>, warmup: bool) -> i32 {
+    debug!("Language Server starting up. Version: {}", version());
+    serve(vfs, Box::new(StdioMsgReader), StdioOutput::new(), warmup)
+}
+
+/// Runs the DML Language Server for clients connecting to `address`, until
+/// it can no longer listen there. Clients must present `token`, if given,
+/// see `authenticate`, and are disconnected when they send nothing for
+/// `idle_timeout`.
+pub fn run_tcp_server(address: SocketAddr, warmup: bool,
+                      token: Option<AuthToken>,
+                      idle_timeout: Option<Duration>) -> i32 {
+    debug!("Language Server starting up. Version: {}", version());
+    let listener = match TcpListener::bind(address) {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("Could not listen on {}: {}", address, e);
+            return 1;
+        }
+    };
+    info!("Language server listening on {}", address);
+    if token.is_none() && !address.ip().is_loopback() {
+        warn!("Clients connecting to {} are not authenticated, anyone who \
+               can reach it can use the server", address);
+    }
+    serve_connections(listener.incoming(), warmup, token, idle_timeout)
+}
+
+/// Runs the DML Language Server for clients connecting to the Unix domain
+/// socket at `path`, until it can no longer listen there. A socket left
+/// there by a server that is no longer running is replaced. Clients must
+/// present `token`, if given, see `authenticate`, and are disconnected when
+/// they send nothing for `idle_timeout`.
+#[cfg(unix)]
+pub fn run_unix_server(path: &Path, warmup: bool,
+                       token: Option<AuthToken>,
+                       idle_timeout: Option<Duration>) -> i32 {
     debug!("Language Server starting up. Version: {}", version());
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            error!("{} is in use by another server", path.display());
+            return 1;
+        }
+        if let Err(e) = std::fs::remove_file(path) {
+            error!("Could not remove stale socket {}: {}", path.display(), e);
+            return 1;
+        }
+    }
+    let listener = match UnixListener::bind(path) {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("Could not listen on {}: {}", path.display(), e);
+            return 1;
+        }
+    };
+    info!("Language server listening on {}", path.display());
+    serve_connections(listener.incoming(), warmup, token, idle_timeout)
+}
+
+/// Serves the clients of `connections`, each with a server of its own as if
+/// it had started the server on stdio, in a thread of its own. Clients
+/// connecting while `MAX_CLIENTS` are being served are refused.
+fn serve_connections<C: Connection>(
+    connections: impl Iterator<Item = std::io::Result<C>>,
+    warmup: bool,
+    token: Option<AuthToken>,
+    idle_timeout: Option<Duration>) -> i32 {
+    let clients = Arc::new(AtomicUsize::new(0));
+    for connection in connections {
+        let connection = match connection {
+            Ok(connection) => connection,
+            Err(e) => {
+                warn!("Could not accept connection: {}", e);
+                continue;
+            }
+        };
+        if clients.load(Ordering::SeqCst) >= MAX_CLIENTS {
+            warn!("Refused client, {} clients are already being served",
+                  MAX_CLIENTS);
+            connection.close();
+            continue;
+        }
+        let slot = ClientSlot::take(&clients);
+        let token = token.clone();
+        thread::spawn(move || {
+            serve_connection(connection, warmup, token.as_ref(),
+                             idle_timeout);
+            drop(slot);
+        });
+    }
+    1
+}
+
+/// One of the `MAX_CLIENTS` clients being served, until dropped
+struct ClientSlot(Arc<AtomicUsize>);
+
+impl ClientSlot {
+    fn take(clients: &Arc<AtomicUsize>) -> ClientSlot {
+        clients.fetch_add(1, Ordering::SeqCst);
+        ClientSlot(Arc::clone(clients))
+    }
+}
+
+impl Drop for ClientSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Serves the client of `connection` until it exits, closes the connection
+/// or sends nothing for `idle_timeout`, once it has presented `token` if
+/// given.
+fn serve_connection<C: Connection>(connection: C, warmup: bool,
+                                   token: Option<&AuthToken>,
+                                   idle_timeout: Option<Duration>) {
+    let (input, output) = match (connection.try_clone(),
+                                 connection.try_clone()) {
+        (Ok(input), Ok(output)) => (input, output),
+        (Err(e), _) | (_, Err(e)) => {
+            warn!("Could not set up connection: {}", e);
+            return;
+        }
+    };
+    info!("Client connected");
+    let reader = ConnectionMsgReader::new(input);
+    let output = ConnectionOutput::new(output);
+    if let Some(token) = token {
+        if !authenticate(&reader, &output, token, AUTHENTICATION_TIMEOUT) {
+            warn!("Rejected client that did not present the \
+                   authentication token");
+            connection.close();
+            return;
+        }
+    }
+    if let Err(e) = reader.limit(MAX_MESSAGE_SIZE, idle_timeout) {
+        warn!("Could not set up connection: {}", e);
+        connection.close();
+        return;
+    }
+    let exit_code = serve(Arc::new(Vfs::new()), Box::new(reader), output,
+                          warmup);
+    // Ends the message reader of the session, should the client still be
+    // connected
+    connection.close();
+    info!("Client disconnected, exit code {}", exit_code);
+}
+
+/// Checks that the client on `reader` starts with an `initialize` request
+/// presenting `token` as the `authToken` initialization option, responding
+/// with an error if it does not. The token is removed from the request,
+/// which is then the next message read. Until then, the client is not
+/// trusted with more than `MAX_UNAUTHENTICATED_MESSAGE_SIZE` bytes or
+/// `timeout` to send its request, after which it is rejected; the caller
+/// sets the limits of the session once it is authenticated.
+fn authenticate<C: Connection>(reader: &ConnectionMsgReader<C>,
+                               output: &impl Output,
+                               token: &AuthToken,
+                               timeout: Duration) -> bool {
+    if let Err(e) = reader.limit(MAX_UNAUTHENTICATED_MESSAGE_SIZE,
+                                 Some(timeout)) {
+        warn!("Could not set up connection: {}", e);
+        return false;
+    }
+    let Some(message) = reader.read_message() else {
+        return false;
+    };
+    let mut request: Value = serde_json::from_str(&message)
+        .unwrap_or(Value::Null);
+    let presented = request.pointer_mut("/params/initializationOptions")
+        .and_then(Value::as_object_mut)
+        .and_then(|options| options.remove("authToken"));
+    if request["method"] != <InitializeRequest as LSPRequest>::METHOD
+        || !presented.as_ref().and_then(Value::as_str)
+        .is_some_and(|presented| token.matches(presented)) {
+            output.failure(request["id"].clone(), RpcError {
+                code: UNAUTHENTICATED_CODE,
+                message: "The initialize request must present the \
+                          authentication token as the authToken \
+                          initialization option".to_owned(),
+                data: None,
+            });
+            return false;
+        }
+    reader.unread(request.to_string());
+    true
+}
+
+/// Runs a language server reading messages from `reader` and responding on
+/// `output`, until the client exits, returning the exit code.
+fn serve<O: Output>(vfs: Arc<Vfs>,
+                    reader: Box<dyn MessageReader + Send + Sync>,
+                    output: O,
+                    warmup: bool) -> i32 {
     let config = Arc::new(Mutex::new(Config::default()));
     debug!("made config");
-    let msgreader = Box::new(StdioMsgReader);
-    debug!("made msgreader");
-    let service = LsService::new(
+    let mut service = LsService::new(
         vfs,
         config,
-        msgreader,
-        StdioOutput::new(),
+        reader,
+        output,
     );
     debug!("made service");
+    if warmup {
+        service.ctx.warmup_on_init();
+    }
     let exit_code = LsService::run(service);
     debug!("Server shutting down");
     exit_code
@@ -96,6 +326,7 @@ impl BlockingRequestAction for ShutdownRequest {
             ctx.shut_down.store(true, Ordering::SeqCst);
             ctx.stop_all_jobs();
             ctx.wait_for_concurrent_jobs();
+            ctx.save_symbol_indexes();
             Ok(Ack)
         } else {
             Err(ResponseError::Message(
@@ -106,6 +337,66 @@ impl BlockingRequestAction for ShutdownRequest {
     }
 }
 
+impl BlockingRequestAction for requests::WarmupRequest {
+    type Response = requests::WarmupResult;
+
+    fn handle<O: Output>(
+        _id: RequestId,
+        _params: Self::Params,
+        ctx: &mut ActionContext<O>,
+        out: O,
+    ) -> Result<Self::Response, ResponseError> {
+        if let Ok(ctx) = ctx.inited() {
+            Ok(requests::WarmupResult { files: ctx.warmup(&out) })
+        } else {
+            Err(ResponseError::Message(
+                Value::from(NOT_INITIALIZED_CODE),
+                "not yet received `initialize` request".to_owned(),
+            ))
+        }
+    }
+}
+
+impl BlockingRequestAction for requests::MemoryStatsRequest {
+    type Response = requests::MemoryStats;
+
+    fn handle<O: Output>(
+        _id: RequestId,
+        _params: Self::Params,
+        ctx: &mut ActionContext<O>,
+        _out: O,
+    ) -> Result<Self::Response, ResponseError> {
+        if let Ok(ctx) = ctx.inited() {
+            Ok(ctx.memory_stats())
+        } else {
+            Err(ResponseError::Message(
+                Value::from(NOT_INITIALIZED_CODE),
+                "not yet received `initialize` request".to_owned(),
+            ))
+        }
+    }
+}
+
+impl BlockingRequestAction for requests::ServerStatusRequest {
+    type Response = requests::ServerStatus;
+
+    fn handle<O: Output>(
+        _id: RequestId,
+        _params: Self::Params,
+        ctx: &mut ActionContext<O>,
+        _out: O,
+    ) -> Result<Self::Response, ResponseError> {
+        if let Ok(ctx) = ctx.inited() {
+            Ok(ctx.server_status())
+        } else {
+            Err(ResponseError::Message(
+                Value::from(NOT_INITIALIZED_CODE),
+                "not yet received `initialize` request".to_owned(),
+            ))
+        }
+    }
+}
+
 pub(crate) fn maybe_notify_unknown_configs<O: Output>(_out: &O, unknowns: &[String]) {
     use std::fmt::Write;
     if unknowns.is_empty() {
@@ -180,6 +471,40 @@ pub(crate) fn maybe_notify_unknown_lint_fields<O: Output>(out: &O, unknowns: &[S
     }
 }
 
+pub fn maybe_parse_lint_cfg<O: Output>(path: PathBuf, out: &O) -> Option<LintCfg> {
+    match parse_lint_cfg(path) {
+        Ok((mut cfg, unknowns)) => {
+            // Send visible warning to client
+            maybe_notify_unknown_lint_fields(out, &unknowns);
+            setup_indentation_size(&mut cfg);
+            Some(cfg)
+        },
+        Err(e) => {
+            error!("Failed to parse linting CFG: {}", e);
+            None
+        }
+    }
+}
+
+/// The lint rules of a project file, see `ProjectConfig::lint_config`
+pub fn maybe_project_lint_cfg<O: Output>(
+    cfg: anyhow::Result<(LintCfg, Vec<String>)>,
+    out: &O,
+) -> Option<LintCfg> {
+    match cfg {
+        Ok((mut cfg, unknowns)) => {
+            maybe_notify_unknown_lint_fields(out, &unknowns);
+            setup_indentation_size(&mut cfg);
+            Some(cfg)
+        },
+        Err(e) => {
+            error!("Failed to parse project lint settings: {}", e);
+            error_message(out, e.to_string());
+            None
+        }
+    }
+}
+
 pub(crate) fn maybe_notify_duplicated_configs<O: Output>(
     out: &O,
     dups: &std::collections::HashMap<String, Vec<String>>,
@@ -257,6 +582,7 @@ impl BlockingRequestAction for InitializeRequest {
         result.send(id, &out);
 
         let capabilities = lsp_data::ClientCapabilities::new(&params);
+        let warmup = init_options.warmup || ctx.wants_warmup();
         ctx.init(init_options, capabilities, out.clone()).unwrap();
         let mut workspaces = vec![];
         // TODO/NOTE: Do we want to disallow root definition?
@@ -280,6 +606,9 @@ impl BlockingRequestAction for InitializeRequest {
                         initctx.isolated_analyze(pathb.as_path(), None, None, &out);
                     }
             }
+            if warmup {
+                initctx.warmup(&out);
+            }
         } else {
             unreachable!("Context failed to init");
         }
@@ -296,6 +625,10 @@ pub struct LsService<O: Output> {
     server_receive: channel::Receiver<ServerToHandle>,
     ctx: ActionContext<O>,
     dispatcher: Dispatcher<O>,
+    // Whether analyses have finished since errors were last reported,
+    // which is done once the queued messages are handled so that many
+    // analyses finishing at once, as when indexing, are reported together
+    errors_pending: bool,
 }
 
 impl<O: Output> LsService<O> {
@@ -316,6 +649,7 @@ impl<O: Output> LsService<O> {
             server_send, server_receive,
             ctx,
             dispatcher,
+            errors_pending: false,
         }
     }
 
@@ -340,7 +674,8 @@ impl<O: Output> LsService<O> {
                             StandardError::ParseError,
                             Some("Cannot read message"));
                         send.send(ServerToHandle::ExitCode(101)).ok();
-                        continue;
+                        // The input has ended, or is closed
+                        break;
                     }
                 };
                 trace!("Read a message `{}`", msg_string);
@@ -370,6 +705,10 @@ impl<O: Output> LsService<O> {
         loop {
             if self.server_receive.is_empty() {
                 if let ActionContext::Init(ctx) = &mut self.ctx {
+                    if self.errors_pending {
+                        ctx.report_errors(&self.output);
+                        self.errors_pending = false;
+                    }
                     ctx.maybe_end_progress(&self.output);
                     if let Some(max_retain) = ctx.config.lock()
                         .unwrap().analysis_retain_duration {
@@ -396,6 +735,8 @@ impl<O: Output> LsService<O> {
                                                      requests) => {
                     trace!("Received isolated analysis of {:?}", path);
                     if let ActionContext::Init(ctx) = &mut self.ctx {
+                        ctx.index_symbols(&path);
+                        ctx.indexed(&path);
                         // hack where we try to activate a device context
                         // as early as we possibly can, unless device context
                         // mode _requires_ that we wait
@@ -415,7 +756,7 @@ impl<O: Output> LsService<O> {
                                 }
                         }
                         let config = ctx.config.lock().unwrap().to_owned();
-                        ctx.report_errors(&self.output);
+                        self.errors_pending = true;
                         for file in requests {
                             // A little bit of redundancy here, we need to
                             // pre-resolve this import into an absolute path
@@ -447,14 +788,14 @@ impl<O: Output> LsService<O> {
                 ServerToHandle::DeviceAnalysisDone(path) => {
                     trace!("Received device analysis of {:?}", path);
                     if let ActionContext::Init(ctx) = &mut self.ctx {
-                        ctx.report_errors(&self.output);
+                        self.errors_pending = true;
                         ctx.check_state_waits();
                     }
                 },
                 ServerToHandle::LinterDone(path) => {
                     trace!("Received linter analysis of {:?}", path);
-                    if let ActionContext::Init(ctx) = &mut self.ctx {
-                        ctx.report_errors(&self.output);
+                    if let ActionContext::Init(_) = &self.ctx {
+                        self.errors_pending = true;
                     }
                 },
                 ServerToHandle::AnalysisRequest(importpath, context, source) => {
@@ -576,25 +917,42 @@ impl<O: Output> LsService<O> {
                 notifications::ChangeActiveContexts;
             blocking_requests:
                 ShutdownRequest,
-                InitializeRequest;
+                InitializeRequest,
+                requests::WarmupRequest,
+                requests::MemoryStatsRequest,
+                requests::ServerStatusRequest;
             requests:
                 requests::ExecuteCommand,
                 requests::Formatting,
                 requests::RangeFormatting,
+                requests::OnTypeFormatting,
+                requests::SemanticTokensFullRequest,
+                requests::SemanticTokensRangeRequest,
                 requests::ResolveCompletion,
                 requests::Rename,
+                requests::PrepareRenameRequest,
                 requests::CodeActionRequest,
                 requests::DocumentHighlightRequest,
                 requests::GotoImplementation,
                 requests::WorkspaceSymbolRequest,
                 requests::DocumentSymbolRequest,
                 requests::HoverRequest,
+                requests::InlayHintRequest,
+                requests::SignatureHelpRequest,
+                requests::CallHierarchyPrepare,
+                requests::CallHierarchyIncomingCalls,
+                requests::CallHierarchyOutgoingCalls,
+                requests::FoldingRangeRequest,
+                requests::SelectionRangeRequest,
                 requests::GotoDefinition,
                 requests::GotoDeclaration,
                 requests::References,
                 requests::Completion,
                 requests::CodeLensRequest,
-                requests::GetKnownContextsRequest;
+                requests::GetKnownContextsRequest,
+                requests::RegisterMapRequest,
+                requests::BankLayoutRequest,
+                requests::ExpandTemplatesRequest;
         );
         Ok(())
     }
@@ -677,18 +1035,27 @@ fn experimental_caps() -> Value {
 
 fn server_caps<O: Output>(_ctx: &ActionContext<O>) -> ServerCapabilities {
     ServerCapabilities {
-        call_hierarchy_provider: None,
+        call_hierarchy_provider: Some(
+            CallHierarchyServerCapability::Simple(true)),
         declaration_provider: Some(DeclarationCapability::Simple(true)),
         diagnostic_provider: None,
         document_link_provider: None,
         experimental: Some(experimental_caps()),
-        inlay_hint_provider: None,
+        inlay_hint_provider: Some(OneOf::Left(true)),
         inline_value_provider: None,
         linked_editing_range_provider: None,
         moniker_provider: None,
         // NOTE: This means we default to utf-16
         position_encoding: None,
-        semantic_tokens_provider: None,
+        semantic_tokens_provider: Some(
+            SemanticTokensServerCapabilities::SemanticTokensOptions(
+                SemanticTokensOptions {
+                    work_done_progress_options:
+                    WorkDoneProgressOptions::default(),
+                    legend: semantic_tokens::legend(),
+                    range: Some(true),
+                    full: Some(SemanticTokensFullOptions::Bool(true)),
+                })),
         text_document_sync: Some(TextDocumentSyncCapability::Options(
             TextDocumentSyncOptions {
                 open_close: Some(true),
@@ -699,25 +1066,50 @@ fn server_caps<O: Output>(_ctx: &ActionContext<O>) -> ServerCapabilities {
             }
         )),
         hover_provider: Some(HoverProviderCapability::Simple(true)),
-        completion_provider: None,
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec!["\"".to_string(), "/".to_string()]),
+            all_commit_characters: None,
+            resolve_provider: Some(false),
+            work_done_progress_options: WorkDoneProgressOptions::default(),
+            completion_item: None,
+        }),
         definition_provider: Some(OneOf::Left(true)),
         type_definition_provider: None,
         implementation_provider: Some(
             ImplementationProviderCapability::Simple(true)),
-        references_provider: None,
+        references_provider: Some(OneOf::Left(true)),
         document_highlight_provider: None,
         document_symbol_provider: Some(OneOf::Left(true)),
         workspace_symbol_provider: Some(OneOf::Left(true)),
-        code_action_provider: None,
-        document_formatting_provider: None,
-        execute_command_provider: None,
-        rename_provider: None,
+        code_action_provider: Some(
+            CodeActionProviderCapability::Simple(true)),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        execute_command_provider: Some(ExecuteCommandOptions {
+            commands: code_lens::device_commands(),
+            work_done_progress_options: WorkDoneProgressOptions::default(),
+        }),
+        rename_provider: Some(OneOf::Right(RenameOptions {
+            prepare_provider: Some(true),
+            work_done_progress_options: WorkDoneProgressOptions::default(),
+        })),
         color_provider: None,
-        document_range_formatting_provider: None,
-        code_lens_provider: None,
-        document_on_type_formatting_provider: None,
-        signature_help_provider: None,
-        folding_range_provider: None,
+        document_range_formatting_provider: Some(OneOf::Left(true)),
+        code_lens_provider: Some(CodeLensOptions {
+            resolve_provider: Some(false),
+        }),
+        document_on_type_formatting_provider: Some(
+            DocumentOnTypeFormattingOptions {
+                first_trigger_character: "}".to_string(),
+                more_trigger_character: Some(vec![";".to_string(),
+                                                  "\n".to_string()]),
+            }),
+        signature_help_provider: Some(SignatureHelpOptions {
+            trigger_characters: Some(vec!["(".to_string(), ",".to_string()]),
+            retrigger_characters: None,
+            work_done_progress_options: WorkDoneProgressOptions::default(),
+        }),
+        folding_range_provider: Some(
+            FoldingRangeProviderCapability::Simple(true)),
         workspace: Some(WorkspaceServerCapabilities {
             workspace_folders: Some(WorkspaceFoldersServerCapabilities {
                 supported: Some(true),
@@ -725,7 +1117,8 @@ fn server_caps<O: Output>(_ctx: &ActionContext<O>) -> ServerCapabilities {
             }),
             file_operations: None,
         }),
-        selection_range_provider: None,
+        selection_range_provider: Some(
+            SelectionRangeProviderCapability::Simple(true)),
         notebook_document_sync: None,
     }
 }
@@ -834,4 +1227,99 @@ mod test {
         let _request: Request<ShutdownRequest> =
             parsed.parse_as_request().expect("Boring validation is happening");
     }
+
+    #[test]
+    fn parse_warmup_request() {
+        for params in ["", r#", "params": {}"#] {
+            let raw = RawMessageOrResponse::try_parse(&format!(
+                r#"{{"jsonrpc": "2.0", "id": 3, "method": "server/warmup"{}}}"#,
+                params)).unwrap();
+            let parsed = raw.as_message().unwrap();
+            let _request: Request<requests::WarmupRequest> =
+                parsed.parse_as_request().unwrap();
+        }
+    }
+
+    #[test]
+    fn parse_memory_stats_request() {
+        let raw = RawMessageOrResponse::try_parse(
+            r#"{"jsonrpc": "2.0", "id": 4, "method": "server/memoryStats"}"#)
+            .unwrap();
+        let parsed = raw.as_message().unwrap();
+        let _request: Request<requests::MemoryStatsRequest> =
+            parsed.parse_as_request().unwrap();
+        let stats = serde_json::to_value(requests::MemoryStats {
+            interned_names: 2,
+            ..Default::default()
+        }).unwrap();
+        assert_eq!(stats["internedNames"], 2);
+        assert!(stats["residentBytes"].is_null());
+    }
+
+    #[test]
+    fn parse_server_status_request() {
+        let raw = RawMessageOrResponse::try_parse(
+            r#"{"jsonrpc": "2.0", "id": 5, "method": "dml/serverStatus"}"#)
+            .unwrap();
+        let parsed = raw.as_message().unwrap();
+        let _request: Request<requests::ServerStatusRequest> =
+            parsed.parse_as_request().unwrap();
+        let status = serde_json::to_value(requests::ServerStatus::default())
+            .unwrap();
+        // The statistics are at the top level, beside the file counts
+        assert!(status["phases"].is_object());
+        assert!(status["caches"].is_object());
+        assert_eq!(status["indexedFiles"]["symbolIndex"], 0);
+        assert_eq!(status["memory"]["internedNames"], 0);
+    }
+
+    #[test]
+    fn authenticate_checks_initialize_token() {
+        use std::io::Write;
+        use std::net::TcpStream;
+
+        let token = AuthToken::new("s3cret").unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let sends = |input: &str| {
+            let mut client = TcpStream::connect(listener.local_addr().unwrap())
+                .unwrap();
+            let (server, _) = listener.accept().unwrap();
+            client.write_all(input.as_bytes()).unwrap();
+            let reader = ConnectionMsgReader::new(server.try_clone().unwrap());
+            let authenticated = authenticate(
+                &reader, &ConnectionOutput::new(server), &token,
+                Duration::from_millis(100));
+            (authenticated, authenticated.then(|| reader.read_message())
+             .flatten())
+        };
+        let authenticates = |message: &str| sends(
+            &format!("Content-Length: {}\r\n\r\n{}", message.len(), message));
+
+        let (authenticated, request) = authenticates(
+            r#"{"jsonrpc": "2.0", "id": 1, "method": "initialize",
+                "params": {"capabilities": {},
+                           "initializationOptions": {"authToken": "s3cret",
+                                                     "warmup": true}}}"#);
+        assert!(authenticated);
+        // The token is not passed on
+        let request: Value = serde_json::from_str(&request.unwrap()).unwrap();
+        assert_eq!(request["params"]["initializationOptions"],
+                   serde_json::json!({"warmup": true}));
+
+        for message in [
+            r#"{"jsonrpc": "2.0", "id": 1, "method": "initialize",
+                "params": {"initializationOptions": {"authToken": "wrong"}}}"#,
+            r#"{"jsonrpc": "2.0", "id": 1, "method": "initialize",
+                "params": {}}"#,
+            r#"{"jsonrpc": "2.0", "id": 1, "method": "shutdown",
+                "params": {"initializationOptions": {"authToken": "s3cret"}}}"#,
+            "not json",
+        ] {
+            assert!(!authenticates(message).0, "{}", message);
+        }
+
+        // Nor is a client that claims a huge request, or sends nothing
+        assert!(!sends("Content-Length: 1000000000000\r\n\r\n").0);
+        assert!(!sends("").0);
+    }
 }
@@ -233,6 +233,11 @@ fn track_path(path: &PathBuf) -> PathBufKey {
     PathBufKey(index)
 }
 
+/// The number of distinct paths stored
+pub fn tracked_paths() -> usize {
+    PATHBUF_STORAGE.lock().unwrap().len()
+}
+
 fn get_path(index: PathBufKey) -> PathBuf {
     // Guaranteed, because we never clear out the map
     PATHBUF_STORAGE.lock().unwrap().get(index.0).unwrap().clone()
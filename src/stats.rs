@@ -0,0 +1,187 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Statistics of the work done by the process
+//!
+//! The time spent in each phase of the analysis, and how often its caches
+//! spare work, are counted from the start of the process, so that users can
+//! include them in reports of slow analysis.
+use std::collections::BTreeMap;
+use std::fs;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+
+/// A phase of the analysis
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord,
+         Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Phase {
+    /// Parsing a file
+    Parse,
+    /// Resolving the objects, templates and references of a device
+    Resolve,
+    /// Linting a file
+    Lint,
+}
+
+impl Phase {
+    /// The name of the phase in snake case
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::Parse => "parse",
+            Phase::Resolve => "resolve",
+            Phase::Lint => "lint",
+        }
+    }
+}
+
+/// A cache that spares the analysis work
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord,
+         Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Cache {
+    /// Declarations kept from the previous parse of an edited file
+    IncrementalParse,
+    /// Files whose symbols were loaded from the persisted symbol index
+    SymbolIndex,
+    /// Files whose summaries were loaded from the MCP workspace cache
+    WorkspaceCache,
+}
+
+impl Cache {
+    /// The name of the cache in snake case
+    pub fn name(self) -> &'static str {
+        match self {
+            Cache::IncrementalParse => "incremental_parse",
+            Cache::SymbolIndex => "symbol_index",
+            Cache::WorkspaceCache => "workspace_cache",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PhaseStats {
+    /// The number of times the phase ran
+    pub count: u64,
+    pub total_ms: u64,
+    pub max_ms: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// The share of hits in all lookups, if there were any
+    pub hit_rate: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Stats {
+    pub phases: BTreeMap<Phase, PhaseStats>,
+    pub caches: BTreeMap<Cache, CacheStats>,
+}
+
+#[derive(Default)]
+struct Counters {
+    phases: BTreeMap<Phase, (u64, Duration, Duration)>,
+    caches: BTreeMap<Cache, (u64, u64)>,
+}
+
+lazy_static! {
+    static ref COUNTERS: Mutex<Counters> = Mutex::default();
+}
+
+/// Count a run of a phase that took `duration`
+pub fn record_phase(phase: Phase, duration: Duration) {
+    let mut counters = COUNTERS.lock().unwrap();
+    let (count, total, max) = counters.phases.entry(phase).or_default();
+    *count += 1;
+    *total += duration;
+    *max = (*max).max(duration);
+}
+
+/// Run `work` and count it as a run of a phase
+pub fn timed<T>(phase: Phase, work: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = work();
+    record_phase(phase, start.elapsed());
+    result
+}
+
+/// Count lookups in a cache
+pub fn record_cache(cache: Cache, hits: u64, misses: u64) {
+    let mut counters = COUNTERS.lock().unwrap();
+    let counts = counters.caches.entry(cache).or_default();
+    counts.0 += hits;
+    counts.1 += misses;
+}
+
+/// What has been counted so far
+pub fn snapshot() -> Stats {
+    let counters = COUNTERS.lock().unwrap();
+    Stats {
+        phases: counters.phases.iter()
+            .map(|(phase, (count, total, max))|(*phase, PhaseStats {
+                count: *count,
+                total_ms: total.as_millis() as u64,
+                max_ms: max.as_millis() as u64,
+            }))
+            .collect(),
+        caches: counters.caches.iter()
+            .map(|(cache, (hits, misses))|(*cache, CacheStats {
+                hits: *hits,
+                misses: *misses,
+                hit_rate: (hits + misses > 0).then(
+                    ||*hits as f64 / (hits + misses) as f64),
+            }))
+            .collect(),
+    }
+}
+
+// A size in the status of the process, on systems with procfs
+fn status_bytes(field: &str) -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let kilobytes = status.lines().find_map(
+        |line|line.strip_prefix(field)?.strip_prefix(':'))?
+        .trim().strip_suffix("kB")?.trim().parse::<u64>().ok()?;
+    Some(kilobytes * 1024)
+}
+
+/// The resident memory of the process, on systems with procfs
+pub fn resident_bytes() -> Option<u64> {
+    status_bytes("VmRSS")
+}
+
+/// The most resident memory the process has had, on systems with procfs
+pub fn peak_resident_bytes() -> Option<u64> {
+    status_bytes("VmHWM")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_stats() {
+        // The counters are shared with the other tests, so only increases
+        // are checked
+        let before = snapshot();
+        timed(Phase::Lint, ||std::thread::sleep(Duration::from_millis(2)));
+        record_cache(Cache::SymbolIndex, 3, 1);
+        let after = snapshot();
+        let lints = |stats: &Stats|stats.phases.get(&Phase::Lint)
+            .cloned().unwrap_or_default();
+        assert!(lints(&after).count > lints(&before).count);
+        assert!(lints(&after).max_ms >= 2);
+        let index = &after.caches[&Cache::SymbolIndex];
+        assert!(index.hits >= 3 && index.misses >= 1);
+        assert!(index.hit_rate.is_some());
+        assert_eq!(serde_json::to_value(&after).unwrap()["caches"]
+                   ["symbolIndex"]["hits"], index.hits);
+    }
+}
@@ -30,6 +30,12 @@ def send_json_rpc(proc, method, params=None, id_val=1):
         print(f"Error parsing response: {e}")
         return None
 
+def send_notification(proc, method):
+    """Send a JSON-RPC notification, which has no response"""
+    message_str = json.dumps({"jsonrpc": "2.0", "method": method}) + "\n"
+    proc.stdin.write(message_str.encode())
+    proc.stdin.flush()
+
 def test_advanced_generation():
     """Test advanced DML device generation"""
     print("🚀 Advanced DML MCP Server Test")
@@ -50,6 +56,7 @@ def test_advanced_generation():
             "capabilities": {},
             "clientInfo": {"name": "advanced-test", "version": "1.0.0"}
         })
+        send_notification(proc, "notifications/initialized")
         
         # Test: Generate complex peripheral device
         print("🎛️  Generating Complex Peripheral Device")
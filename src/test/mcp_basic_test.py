@@ -34,6 +34,13 @@ def send_json_rpc(proc, method, params=None, id_val=1):
         print(f"Error parsing response: {e}")
         return None
 
+def send_notification(proc, method):
+    """Send a JSON-RPC notification, which has no response"""
+    message_str = json.dumps({"jsonrpc": "2.0", "method": method}) + "\n"
+    print(f"→ Sending: {message_str.strip()}")
+    proc.stdin.write(message_str.encode())
+    proc.stdin.flush()
+
 def test_mcp_server():
     """Test the DML MCP server"""
     print("🚀 Testing DML MCP Server")
@@ -69,6 +76,7 @@ def test_mcp_server():
         else:
             print("❌ Initialize failed")
             return False
+        send_notification(proc, "notifications/initialized")
         
         # Test 2: List tools
         print("\n🔧 Test 2: List Tools")
@@ -0,0 +1,119 @@
+//  © 2024 Intel Corporation
+//  SPDX-License-Identifier: Apache-2.0 and MIT
+//! Conformance of the MCP tools to the protocol and to their own schemas
+//!
+//! Every tool is called with the arguments in
+//! `examples/sample_project/tool_calls.json`, which are checked against the
+//! input schema of the tool, and the results are checked against the
+//! `CallToolResult` schema of the protocol.
+
+#[cfg(test)]
+mod mcp_conformance {
+    use jsonschema::JSONSchema;
+    use serde_json::{json, Value};
+    use std::path::Path;
+
+    use crate::mcp::tools::ToolRegistry;
+
+    const TOOL_CALLS: &str =
+        include_str!("../../examples/sample_project/tool_calls.json");
+
+    fn compile(schema: &Value) -> JSONSchema {
+        JSONSchema::compile(schema)
+            .unwrap_or_else(|e| panic!("Invalid schema {}: {}", schema, e))
+    }
+
+    fn violations(schema: &JSONSchema, value: &Value) -> Vec<String> {
+        match schema.validate(value) {
+            Ok(()) => vec![],
+            Err(errors) => errors.map(|e| format!("{}: {}",
+                                                  e.instance_path, e))
+                .collect(),
+        }
+    }
+
+    /// Replace `$PROJECT` in the string values of `arguments`
+    fn with_project(arguments: &Value, project: &str) -> Value {
+        match arguments {
+            Value::String(s) => Value::String(s.replace("$PROJECT", project)),
+            Value::Array(values) => values.iter()
+                .map(|value| with_project(value, project)).collect(),
+            Value::Object(map) => map.iter()
+                .map(|(key, value)| (key.clone(),
+                                     with_project(value, project)))
+                .collect(),
+            other => other.clone(),
+        }
+    }
+
+    // The result of tools/call, as in the 2024-11-05 protocol schema
+    fn call_tool_result_schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "content": {
+                    "type": "array",
+                    "minItems": 1,
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "type": {"const": "text"},
+                            "text": {"type": "string"}
+                        },
+                        "required": ["type", "text"],
+                        "additionalProperties": false
+                    }
+                },
+                "isError": {"type": "boolean"}
+            },
+            "required": ["content"],
+            "additionalProperties": false
+        })
+    }
+
+    #[tokio::test]
+    async fn test_tool_definitions() {
+        let registry = ToolRegistry::new().await.unwrap();
+        for tool in registry.list_tools() {
+            assert!(!tool.name.is_empty() && tool.name.chars().all(
+                |c| c.is_ascii_alphanumeric() || c == '_'),
+                    "Invalid tool name {:?}", tool.name);
+            assert!(!tool.description.is_empty(),
+                    "{} has no description", tool.name);
+            assert_eq!(tool.input_schema["type"], "object",
+                       "Input schema of {} is not an object", tool.name);
+            compile(&tool.input_schema);
+            let definition = serde_json::to_value(&tool).unwrap();
+            assert!(definition.get("inputSchema").is_some());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tool_calls() {
+        let project = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("examples/sample_project");
+        let calls: Value = serde_json::from_str(TOOL_CALLS).unwrap();
+        let result_schema = compile(&call_tool_result_schema());
+        let registry = ToolRegistry::new().await.unwrap();
+        for tool in registry.list_tools() {
+            let arguments = calls.get(&tool.name).unwrap_or_else(
+                || panic!("No call of {} in tool_calls.json", tool.name));
+            let arguments = with_project(arguments,
+                                         &project.to_string_lossy());
+            let errors = violations(&compile(&tool.input_schema), &arguments);
+            assert!(errors.is_empty(), "Arguments of {} do not match its \
+                                        schema: {:?}", tool.name, errors);
+            let result = registry.call_tool(&json!({
+                "name": tool.name,
+                "arguments": arguments,
+            })).await.unwrap_or_else(
+                |e| panic!("Calling {} failed: {}", tool.name, e));
+            let errors = violations(&result_schema, &result);
+            assert!(errors.is_empty(), "Result of {} is not a \
+                                        CallToolResult: {:?}",
+                    tool.name, errors);
+            assert_ne!(result["isError"], true, "{} reported an error: {}",
+                       tool.name, result["content"][0]["text"]);
+        }
+    }
+}
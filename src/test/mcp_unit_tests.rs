@@ -6,7 +6,8 @@ mod mcp_tests {
     use crate::mcp::generation::{
         GenerationContext, GenerationConfig, DMLGenerator, DeviceSpec, 
         RegisterSpec, FieldSpec, MethodSpec, ParameterSpec,
-        IndentStyle, LineEnding
+        FormatConfig, IndentStyle, LineEnding, LoggingPolicy, ReservedRegionPolicy,
+        SplitMode
     };
     use crate::mcp::templates::DMLTemplates;
     use serde_json::json;
@@ -22,7 +23,7 @@ mod mcp_tests {
     fn test_server_capabilities_default() {
         let caps = ServerCapabilities::default();
         assert!(caps.tools);
-        assert!(!caps.resources);
+        assert!(caps.resources);
         assert!(!caps.prompts);
         assert!(caps.logging);
     }
@@ -32,11 +33,627 @@ mod mcp_tests {
         assert_eq!(MCP_VERSION, "2024-11-05");
     }
 
+    #[tokio::test]
+    async fn test_batch_requests() {
+        use crate::mcp::DMLMCPServer;
+
+        let server = DMLMCPServer::new().await.unwrap();
+        let response = server.handle_line(r#"[
+            {"jsonrpc": "2.0", "id": 0, "method": "initialize",
+             "params": {"protocolVersion": "2024-11-05"}},
+            {"jsonrpc": "2.0", "method": "notifications/initialized"}
+        ]"#).await.unwrap().unwrap();
+        assert!(response.contains("serverInfo"));
+        let response = server.handle_line(r#"[
+            {"jsonrpc": "2.0", "id": 1, "method": "tools/list"},
+            {"jsonrpc": "2.0", "id": "b", "result": {}},
+            {"jsonrpc": "2.0", "id": "c", "method": "no/such/method"},
+            {"id": 4},
+            {"jsonrpc": "2.0", "id": 5, "method": "tools/call",
+             "params": {"name": "server_capabilities", "arguments": {}}}
+        ]"#).await.unwrap().unwrap();
+        let responses: serde_json::Value =
+            serde_json::from_str(&response).unwrap();
+        let responses = responses.as_array().unwrap();
+        // The response to a response is left out, and the others keep
+        // their order and ids
+        assert_eq!(responses.len(), 4);
+        assert_eq!(responses[0]["id"], 1);
+        assert!(responses[0]["result"]["tools"].is_array());
+        assert_eq!(responses[1]["id"], "c");
+        assert_eq!(responses[1]["error"]["code"], -32601);
+        assert_eq!(responses[2]["id"], serde_json::Value::Null);
+        assert_eq!(responses[2]["error"]["code"], -32600);
+        assert_eq!(responses[3]["id"], 5);
+        assert!(responses[3]["result"]["content"].is_array());
+
+        let response = server.handle_line("[]").await.unwrap().unwrap();
+        assert!(response.contains("-32600"));
+        assert!(server.handle_line(r#"[{"jsonrpc": "2.0", "id": 1,
+                                        "result": {}}]"#)
+                .await.unwrap().is_none());
+        // A single request is responded to without a batch
+        let response = server.handle_line(
+            r#"{"jsonrpc": "2.0", "id": 7, "method": "tools/list"}"#)
+            .await.unwrap().unwrap();
+        assert!(response.starts_with('{'));
+    }
+
+    #[test]
+    fn test_negotiate_version() {
+        use crate::mcp::negotiate_version;
+
+        assert_eq!(negotiate_version("2024-11-05"), "2024-11-05");
+        assert_eq!(negotiate_version("2025-03-26"), "2025-03-26");
+        // Newer versions are negotiated down, and older ones get the oldest
+        assert_eq!(negotiate_version("2025-06-18"), "2025-03-26");
+        assert_eq!(negotiate_version("2024-12-01"), "2024-11-05");
+        assert_eq!(negotiate_version("2024-01-01"), "2024-11-05");
+    }
+
+    #[tokio::test]
+    async fn test_initialization_handshake() {
+        use crate::mcp::DMLMCPServer;
+
+        let server = DMLMCPServer::new().await.unwrap();
+        let send = |line: &'static str| {
+            let server = &server;
+            async move {
+                server.handle_line(line).await.unwrap().map(|response|
+                    serde_json::from_str::<serde_json::Value>(&response)
+                        .unwrap())
+            }
+        };
+        let call = r#"{"jsonrpc": "2.0", "id": 3, "method": "tools/call",
+                       "params": {"name": "server_stats", "arguments": {}}}"#;
+        let list = r#"{"jsonrpc": "2.0", "id": 2, "method": "tools/list"}"#;
+
+        // Only pings are answered before initialization
+        let pong = send(r#"{"jsonrpc": "2.0", "id": 1, "method": "ping"}"#)
+            .await.unwrap();
+        assert_eq!(pong["result"], json!({}));
+        assert_eq!(send(list).await.unwrap()["error"]["code"], -32600);
+        assert!(send(r#"{"jsonrpc": "2.0", "id": 1, "method": "initialize",
+                         "params": {}}"#)
+                .await.unwrap()["error"].is_object());
+
+        let response = send(r#"{"jsonrpc": "2.0", "id": 1,
+                                "method": "initialize",
+                                "params": {"protocolVersion": "2099-01-01"}}"#)
+            .await.unwrap();
+        assert_eq!(response["result"]["protocolVersion"], "2025-03-26");
+        assert_eq!(server.protocol_version(), Some("2025-03-26"));
+        // Tools can be listed, but not called, until the client is
+        // initialized
+        assert!(send(list).await.unwrap()["result"]["tools"].is_array());
+        let response = send(call).await.unwrap();
+        assert_eq!(response["error"]["message"], "Server not initialized");
+        assert_eq!(response["id"], 3);
+
+        // Notifications are not responded to
+        assert!(send(r#"{"jsonrpc": "2.0",
+                         "method": "notifications/initialized"}"#)
+                .await.is_none());
+        assert!(send(call).await.unwrap()["result"]["content"].is_array());
+        let response = send(r#"{"jsonrpc": "2.0", "id": 4,
+                                "method": "initialize",
+                                "params": {"protocolVersion": "2024-11-05"}}"#)
+            .await.unwrap();
+        assert_eq!(response["error"]["code"], -32600);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown() {
+        use crate::mcp::DMLMCPServer;
+        use tokio::io::AsyncWriteExt;
+
+        // A shutdown request is responded to, and ends the session
+        let server = DMLMCPServer::new().await.unwrap();
+        let input = r#"{"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {"protocolVersion": "2024-11-05"}}
+{"jsonrpc": "2.0", "id": 2, "method": "shutdown"}
+{"jsonrpc": "2.0", "id": 3, "method": "ping"}
+"#;
+        let mut output = vec![];
+        server.serve(input.as_bytes(), &mut output,
+                     std::future::pending()).await.unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let responses: Vec<serde_json::Value> = output.lines()
+            .map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[1]["id"], 2);
+        assert_eq!(responses[1]["result"], json!({}));
+
+        // A signal ends the session while waiting for input, after the
+        // requests that were read have been responded to
+        let server = DMLMCPServer::new().await.unwrap();
+        let (mut client, input) = tokio::io::duplex(1024);
+        client.write_all(b"{\"jsonrpc\": \"2.0\", \"id\": 1, \
+                           \"method\": \"ping\"}\n").await.unwrap();
+        let mut output = vec![];
+        let signal = async {
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            "SIGTERM"
+        };
+        server.serve(tokio::io::BufReader::new(input), &mut output, signal)
+            .await.unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(),
+                   "{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":{}}\n");
+    }
+
+    #[cfg(feature = "websocket")]
+    #[tokio::test]
+    async fn test_websocket_sessions() {
+        use crate::mcp::websocket::serve;
+        use crate::mcp::DMLMCPServer;
+        use futures_util::{SinkExt, StreamExt};
+        use std::sync::Arc;
+        use tokio_tungstenite::tungstenite::Message;
+
+        let server = Arc::new(DMLMCPServer::new().await.unwrap());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await
+            .unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let serving = tokio::spawn(serve(server, listener, None, async {
+            let _ = stopped.await;
+            "SIGTERM"
+        }));
+
+        let (mut first, _) = tokio_tungstenite::connect_async(&url).await
+            .unwrap();
+        let (mut second, _) = tokio_tungstenite::connect_async(&url).await
+            .unwrap();
+        let request = |id: u32, method: &str| Message::Text(json!({
+            "jsonrpc": "2.0", "id": id, "method": method,
+            "params": {"protocolVersion": "2024-11-05"}
+        }).to_string());
+        async fn response<S>(socket: &mut S) -> serde_json::Value
+        where S: futures_util::Stream<Item = tokio_tungstenite::tungstenite
+                                      ::Result<Message>> + Unpin {
+            let message = socket.next().await.unwrap().unwrap();
+            serde_json::from_str(message.to_text().unwrap()).unwrap()
+        }
+
+        // Initializing one connection does not initialize the other
+        first.send(request(1, "initialize")).await.unwrap();
+        assert_eq!(response(&mut first).await["result"]["protocolVersion"],
+                   "2024-11-05");
+        first.send(request(2, "tools/list")).await.unwrap();
+        assert!(response(&mut first).await["result"]["tools"].is_array());
+        second.send(request(1, "tools/list")).await.unwrap();
+        assert_eq!(response(&mut second).await["error"]["code"], -32600);
+
+        // A shutdown request closes its own connection only
+        first.send(request(3, "shutdown")).await.unwrap();
+        assert_eq!(response(&mut first).await["result"], json!({}));
+        assert!(matches!(first.next().await,
+                         Some(Ok(Message::Close(_))) | None));
+        second.send(request(2, "ping")).await.unwrap();
+        assert_eq!(response(&mut second).await["id"], 2);
+
+        stop.send(()).unwrap();
+        serving.await.unwrap().unwrap();
+        assert!(matches!(second.next().await,
+                         Some(Ok(Message::Close(_))) | None));
+    }
+
+    #[cfg(feature = "websocket")]
+    #[tokio::test]
+    async fn test_websocket_authentication() {
+        use crate::auth::AuthToken;
+        use crate::mcp::websocket::serve;
+        use crate::mcp::DMLMCPServer;
+        use std::sync::Arc;
+        use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+        use tokio_tungstenite::tungstenite::Error;
+
+        let server = Arc::new(DMLMCPServer::new().await.unwrap());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await
+            .unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let token = AuthToken::new("s3cret").unwrap();
+        tokio::spawn(serve(server, listener, Some(token),
+                           std::future::pending()));
+
+        let refused = |result: Result<_, Error>| matches!(
+            result, Err(Error::Http(response)) if response.status() == 401);
+        assert!(refused(tokio_tungstenite::connect_async(&url).await));
+        assert!(refused(tokio_tungstenite::connect_async(
+            format!("{}/?access_token=wrong", url)).await));
+        let mut request = url.as_str().into_client_request().unwrap();
+        request.headers_mut().insert("authorization",
+                                     "Bearer wrong".parse().unwrap());
+        assert!(refused(tokio_tungstenite::connect_async(request).await));
+
+        assert!(tokio_tungstenite::connect_async(
+            format!("{}/?access_token=s3cret", url)).await.is_ok());
+        let mut request = url.as_str().into_client_request().unwrap();
+        request.headers_mut().insert("authorization",
+                                     "Bearer s3cret".parse().unwrap());
+        assert!(tokio_tungstenite::connect_async(request).await.is_ok());
+    }
+
+    #[test]
+    fn test_auth_token() {
+        use crate::auth::AuthToken;
+
+        assert!(AuthToken::new("  ").is_err());
+        assert!(AuthToken::new("two words").is_err());
+        let token = AuthToken::new("s3cret\n").unwrap();
+        assert!(token.matches("s3cret"));
+        assert!(!token.matches("s3cre"));
+        assert!(!token.matches("s3cret2"));
+        assert!(token.matches_bearer("Bearer s3cret"));
+        assert!(token.matches_bearer("bearer s3cret"));
+        assert!(!token.matches_bearer("Basic s3cret"));
+        assert!(!token.matches_bearer("s3cret"));
+        assert!(!format!("{:?}", token).contains("s3cret"));
+    }
+
+    #[tokio::test]
+    async fn test_session_isolation() {
+        use crate::mcp::server::Session;
+        use crate::mcp::DMLMCPServer;
+        use std::sync::Mutex;
+
+        let root = std::env::temp_dir().join(
+            format!("dls-session-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("dml_project.toml"),
+                       "[generation]\nline_ending = \"windows\"\n").unwrap();
+
+        let server = DMLMCPServer::new().await.unwrap()
+            .with_max_result_bytes(20);
+        async fn handle(server: &DMLMCPServer, session: &Mutex<Session>,
+                        message: serde_json::Value)
+                        -> Option<serde_json::Value> {
+            server.handle_session_line(session, &message.to_string()).await
+                .unwrap()
+                .map(|response| serde_json::from_str(&response).unwrap())
+        }
+        let (with_roots, without_roots) = (Mutex::new(Session::default()),
+                                           Mutex::new(Session::default()));
+
+        // Only the client that can list its roots is asked for them
+        handle(&server, &with_roots, json!({
+            "jsonrpc": "2.0", "id": 1, "method": "initialize",
+            "params": {"protocolVersion": "2024-11-05",
+                       "capabilities": {"roots": {"listChanged": true}}}
+        })).await.unwrap();
+        let request = handle(&server, &with_roots, json!({
+            "jsonrpc": "2.0", "method": "notifications/initialized"
+        })).await.unwrap();
+        assert_eq!(request["method"], "roots/list");
+        assert!(handle(&server, &with_roots, json!({
+            "jsonrpc": "2.0", "id": request["id"],
+            "result": {"roots": [{"uri": format!("file://{}", root.display()),
+                                  "name": "project"}]}
+        })).await.is_none());
+        assert_eq!(with_roots.lock().unwrap().roots(),
+                   std::slice::from_ref(&root));
+
+        handle(&server, &without_roots, json!({
+            "jsonrpc": "2.0", "id": 1, "method": "initialize",
+            "params": {"protocolVersion": "2024-11-05", "capabilities": {}}
+        })).await.unwrap();
+        assert!(handle(&server, &without_roots, json!({
+            "jsonrpc": "2.0", "method": "notifications/initialized"
+        })).await.is_none());
+        assert!(without_roots.lock().unwrap().roots().is_empty());
+
+        // Each session uses the defaults of its own project, and keeps its
+        // own truncated results
+        let mut texts = vec![];
+        for session in [&with_roots, &without_roots] {
+            let response = handle(&server, session, json!({
+                "jsonrpc": "2.0", "id": 2, "method": "tools/call",
+                "params": {"name": "generate_register",
+                           "arguments": {"name": "ctrl", "size": 4}}
+            })).await.unwrap();
+            let uri = response["result"]["_meta"]["truncated"]["resource_uri"]
+                .clone();
+            let response = handle(&server, session, json!({
+                "jsonrpc": "2.0", "id": 3, "method": "resources/read",
+                "params": {"uri": uri}
+            })).await.unwrap();
+            texts.push(response["result"]["contents"][0]["text"]
+                       .as_str().unwrap().to_string());
+            let response = handle(&server, session, json!({
+                "jsonrpc": "2.0", "id": 4, "method": "resources/list"
+            })).await.unwrap();
+            assert_eq!(response["result"]["resources"].as_array().unwrap()
+                       .iter().filter(|resource| resource["uri"] == uri)
+                       .count(), 1);
+        }
+        assert!(texts[0].contains("\r\n"));
+        assert!(!texts[1].contains('\r'));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_audit_log_replay() {
+        use crate::mcp::audit::{read_log, replay_call, AuditLog,
+                                ReplayStatus};
+        use crate::mcp::tools::ToolRegistry;
+        use crate::mcp::DMLMCPServer;
+
+        let path = std::env::temp_dir().join(
+            format!("dls-audit-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let server = DMLMCPServer::new().await.unwrap()
+            .with_audit_log(AuditLog::open(&path).unwrap());
+        server.handle_line(r#"[
+            {"jsonrpc": "2.0", "id": 1, "method": "initialize",
+             "params": {"protocolVersion": "2024-11-05"}},
+            {"jsonrpc": "2.0", "method": "notifications/initialized"},
+            {"jsonrpc": "2.0", "id": 2, "method": "tools/call",
+             "params": {"name": "generate_register",
+                        "arguments": {"name": "ctrl", "size": 4,
+                                      "offset": "0x10"}}},
+            {"jsonrpc": "2.0", "id": 3, "method": "tools/call",
+             "params": {"name": "no_such_tool", "arguments": {}}}
+        ]"#).await.unwrap();
+
+        let records = read_log(&path).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].tool, "generate_register");
+        assert_eq!(records[0].arguments["name"], "ctrl");
+        assert!(records[0].result_bytes > 0);
+        assert!(records[0].result_hash.is_some());
+        assert_eq!(records[0].error, None);
+        assert_eq!(records[1].error.as_deref(),
+                   Some("Unknown tool: no_such_tool"));
+
+        let registry = ToolRegistry::new().await.unwrap();
+        for record in &records {
+            let call = replay_call(&registry, record).await;
+            assert_eq!(call.status, ReplayStatus::Same, "{:?}", call);
+        }
+        let mut changed = records[0].clone();
+        changed.arguments["size"] = json!(2);
+        assert_eq!(replay_call(&registry, &changed).await.status,
+                   ReplayStatus::Changed);
+        changed.result_hash = None;
+        changed.error = Some("Failed".to_string());
+        assert_eq!(replay_call(&registry, &changed).await.status,
+                   ReplayStatus::NowSucceeds);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_replay_does_not_write() {
+        use crate::mcp::audit::{read_log, replay_call, AuditLog,
+                                ReplayStatus};
+        use crate::mcp::tools::ToolRegistry;
+        use crate::mcp::DMLMCPServer;
+
+        let dir = std::env::temp_dir().join(
+            format!("dls-replay-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("audit.jsonl");
+        let output = dir.join("out");
+        let file = dir.join("dev.dml");
+        let dml12 = "dml 1.2;\ndevice dev;\n";
+        std::fs::write(&file, dml12).unwrap();
+        let server = DMLMCPServer::new().await.unwrap()
+            .with_audit_log(AuditLog::open(&log).unwrap());
+        let calls = json!([
+            {"jsonrpc": "2.0", "id": 1, "method": "initialize",
+             "params": {"protocolVersion": "2024-11-05"}},
+            {"jsonrpc": "2.0", "method": "notifications/initialized"},
+            {"jsonrpc": "2.0", "id": 2, "method": "tools/call",
+             "params": {"name": "generate_device",
+                        "arguments": {"device_name": "uart",
+                                      "device_type": "peripheral",
+                                      "registers": [{"name": "data",
+                                                     "size": 1,
+                                                     "offset": "0x00"}],
+                                      "output_dir": output,
+                                      "force": true}}},
+            {"jsonrpc": "2.0", "id": 3, "method": "tools/call",
+             "params": {"name": "migrate_dml12",
+                        "arguments": {"file": file, "write": true}}}
+        ]);
+        server.handle_line(&calls.to_string()).await.unwrap();
+        assert!(output.join("uart.dml").exists());
+        assert_ne!(std::fs::read_to_string(&file).unwrap(), dml12);
+
+        // Replays neither write the files again nor undo changes to them,
+        // and compare equal to the calls that wrote them
+        std::fs::remove_dir_all(&output).unwrap();
+        std::fs::write(&file, dml12).unwrap();
+        let registry = ToolRegistry::new().await.unwrap();
+        let records = read_log(&log).unwrap();
+        assert_eq!(records.len(), 2);
+        for record in &records {
+            let call = replay_call(&registry, record).await;
+            assert_eq!(call.status, ReplayStatus::Same, "{:?}", call);
+        }
+        assert!(!output.exists());
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), dml12);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_tool_policy() {
+        use crate::mcp::tool_policy::ToolPolicy;
+        use crate::mcp::tools::ToolRegistry;
+
+        let policy = ToolPolicy::parse(r#"
+allow = ["find_symbol", "read_symbol", "rename_symbol", "format_code"]
+deny = ["read_symbol"]
+"#).unwrap();
+        assert!(ToolPolicy::parse("allow_tools = []").is_err());
+        let policy = policy.restrict(ToolPolicy {
+            read_only: true,
+            ..ToolPolicy::default()
+        });
+        let mut registry = ToolRegistry::new().await.unwrap();
+        let disabled = registry.apply_policy(&policy).unwrap();
+        assert!(disabled.contains(&"generate_device".to_string()));
+        let offered: Vec<String> = registry.list_tools().into_iter()
+            .map(|tool| tool.name).collect();
+        // Tools writing files are left out in read-only mode
+        assert_eq!(offered, ["find_symbol"]);
+        let error = registry.call_tool(&json!({
+            "name": "format_code", "arguments": {"code": "dml 1.4;"}
+        })).await.unwrap_err();
+        assert_eq!(error.to_string(),
+                   "Tool 'format_code' is disabled on this server");
+
+        // Restricting the allowlist further keeps what both allow
+        let policy = ToolPolicy {
+            allow: Some(vec!["find_symbol".to_string(),
+                             "read_symbol".to_string()]),
+            ..ToolPolicy::default()
+        }.restrict(ToolPolicy {
+            allow: Some(vec!["read_symbol".to_string()]),
+            ..ToolPolicy::default()
+        });
+        assert!(policy.allows("read_symbol", false));
+        assert!(!policy.allows("find_symbol", false));
+
+        // Misspelled tools are not silently ignored
+        let mut registry = ToolRegistry::new().await.unwrap();
+        let policy = ToolPolicy {
+            deny: vec!["rename_symbols".to_string()],
+            ..ToolPolicy::default()
+        };
+        assert!(registry.apply_policy(&policy).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_list_pagination() {
+        use crate::mcp::DMLMCPServer;
+
+        let server = DMLMCPServer::new().await.unwrap().with_page_size(10);
+        server.handle_line(r#"{"jsonrpc": "2.0", "id": 0,
+                               "method": "initialize",
+                               "params": {"protocolVersion": "2024-11-05"}}"#)
+            .await.unwrap();
+        let list = |request: String| {
+            let server = &server;
+            async move {
+                serde_json::from_str::<serde_json::Value>(
+                    &server.handle_line(&request).await.unwrap().unwrap())
+                    .unwrap()
+            }
+        };
+        let mut names = vec![];
+        let mut cursor = serde_json::Value::Null;
+        let mut pages = 0;
+        loop {
+            let response = list(json!({
+                "jsonrpc": "2.0", "id": 1, "method": "tools/list",
+                "params": {"cursor": cursor}
+            }).to_string()).await;
+            let tools = response["result"]["tools"].as_array().unwrap();
+            assert!(tools.len() <= 10);
+            names.extend(tools.iter()
+                         .map(|tool| tool["name"].as_str().unwrap()
+                              .to_string()));
+            pages += 1;
+            cursor = response["result"]["nextCursor"].clone();
+            if cursor.is_null() {
+                break;
+            }
+        }
+        let all = DMLMCPServer::new().await.unwrap();
+        all.handle_line(r#"{"jsonrpc": "2.0", "id": 0, "method": "initialize",
+                            "params": {"protocolVersion": "2024-11-05"}}"#)
+            .await.unwrap();
+        let response: serde_json::Value = serde_json::from_str(
+            &all.handle_line(r#"{"jsonrpc": "2.0", "id": 1,
+                                 "method": "tools/list"}"#)
+                .await.unwrap().unwrap()).unwrap();
+        let expected: Vec<String> = response["result"]["tools"].as_array()
+            .unwrap().iter()
+            .map(|tool| tool["name"].as_str().unwrap().to_string())
+            .collect();
+        assert!(response["result"].get("nextCursor").is_none());
+        assert_eq!(names, expected);
+        assert_eq!(pages, expected.len().div_ceil(10));
+
+        let response = list(json!({
+            "jsonrpc": "2.0", "id": 2, "method": "resources/list",
+            "params": {"cursor": "999"}
+        }).to_string()).await;
+        assert_eq!(response["error"]["code"], -32602);
+        let response = list(json!({
+            "jsonrpc": "2.0", "id": 2, "method": "resources/list"
+        }).to_string()).await;
+        assert!(response["result"]["resources"].is_array());
+    }
+
+    #[test]
+    fn test_limit_result() {
+        use crate::mcp::truncation::{limit_result, ResultStore};
+
+        let store = ResultStore::default();
+        let small = json!({"content": [{"type": "text", "text": "short"}]});
+        assert_eq!(limit_result(small.clone(), "read_symbol", 100, &store),
+                   small);
+        assert!(store.list().is_empty());
+
+        let text: String = (0..20).map(|i| format!("line {}\n", i)).collect();
+        let limited = limit_result(
+            json!({"content": [{"type": "text", "text": text}],
+                   "isError": false}),
+            "generate_device", 40, &store);
+        let truncated = &limited["_meta"]["truncated"];
+        assert_eq!(truncated["total_bytes"], text.len());
+        let preview = limited["content"][1]["text"].as_str().unwrap();
+        assert!(preview.len() <= 40);
+        assert!(preview.ends_with('\n'));
+        assert!(text.starts_with(preview));
+        assert_eq!(truncated["preview_bytes"], preview.len());
+        assert_eq!(limited["isError"], false);
+        let uri = truncated["resource_uri"].as_str().unwrap();
+        assert!(limited["content"][0]["text"].as_str().unwrap().contains(uri));
+        assert_eq!(store.list().len(), 1);
+        assert_eq!(store.read(uri).unwrap().text, text);
+        assert!(store.read("dml://results/0").is_none());
+
+        // Another store, as of another session, does not reuse the URIs
+        let other = ResultStore::default();
+        let other_uri = limit_result(
+            json!({"content": [{"type": "text", "text": text}]}),
+            "generate_device", 40, &other)["_meta"]["truncated"]
+            ["resource_uri"].as_str().unwrap().to_string();
+        assert_ne!(other_uri, uri);
+        assert!(other.read(uri).is_none());
+        assert!(store.read(&other_uri).is_none());
+
+        // Only the most recent results are kept
+        for _ in 0..40 {
+            limit_result(json!({"content": [{"type": "text", "text": text}]}),
+                         "generate_device", 40, &store);
+        }
+        assert!(store.read(uri).is_none());
+        assert_eq!(store.list().len(), 32);
+
+        // Text items are counted and kept joined by newlines, and items of
+        // other types are passed through
+        let image = json!({"type": "image", "data": "", "mimeType": "image/png"});
+        let limited = limit_result(
+            json!({"content": [{"type": "text", "text": text},
+                               image,
+                               {"type": "text", "text": text}]}),
+            "generate_docs", 40, &store);
+        let truncated = &limited["_meta"]["truncated"];
+        assert_eq!(truncated["total_bytes"], 2 * text.len() + 1);
+        let uri = truncated["resource_uri"].as_str().unwrap();
+        assert_eq!(store.read(uri).unwrap().text, format!("{}\n{}", text, text));
+        assert_eq!(limited["content"].as_array().unwrap().len(), 3);
+        assert_eq!(limited["content"][2], image);
+    }
+
     #[test]
     fn test_generation_config_default() {
         let config = GenerationConfig::default();
         
-        match config.indent_style {
+        match config.format.indent_style {
             IndentStyle::Spaces(n) => assert_eq!(n, 4),
             IndentStyle::Tabs => panic!("Expected spaces, got tabs"),
         }
@@ -46,7 +663,7 @@ mod mcp_tests {
             LineEnding::Windows => panic!("Expected Unix line endings"),
         }
         
-        assert_eq!(config.max_line_length, 100);
+        assert_eq!(config.format.max_line_length, 100);
         assert!(config.generate_docs);
         assert!(config.validate_output);
     }
@@ -72,11 +689,20 @@ mod mcp_tests {
         let device = DeviceSpec {
             name: "test_device".to_string(),
             base_template: Some("base_device".to_string()),
+            templates: vec![],
             documentation: Some("Test device".to_string()),
             banks: vec![],
             interfaces: vec![],
+            connects: vec![],
+            ports: vec![],
+            variables: vec![],
+            events: vec![],
             methods: vec![],
             dependencies: vec![],
+            constants: vec![],
+            typedefs: vec![],
+            structs: vec![],
+            variants: vec![],
         };
         
         assert_eq!(device.name, "test_device");
@@ -91,15 +717,20 @@ mod mcp_tests {
             bits: "0".to_string(),
             access: Some("rw".to_string()),
             documentation: Some("Enable bit".to_string()),
+            templates: vec![],
         };
         
         let register = RegisterSpec {
             name: "control".to_string(),
+            dimensions: vec![],
             size: 4,
             offset: Some("0x00".to_string()),
+            reset: None,
+            access: None,
             documentation: Some("Control register".to_string()),
             fields: vec![field],
             methods: vec![],
+            templates: vec![],
         };
         
         assert_eq!(register.name, "control");
@@ -160,11 +791,15 @@ mod mcp_tests {
         
         let register_spec = RegisterSpec {
             name: "status".to_string(),
+            dimensions: vec![],
             size: 4,
             offset: Some("0x04".to_string()),
+            reset: None,
+            access: None,
             documentation: Some("Status register".to_string()),
             fields: vec![],
             methods: vec![],
+            templates: vec![],
         };
         
         let result = generator.generate_register(&register_spec).await;
@@ -194,15 +829,20 @@ mod mcp_tests {
             bits: "0".to_string(),
             access: Some("ro".to_string()),
             documentation: Some("Ready bit".to_string()),
+            templates: vec![],
         };
         
         let register_spec = RegisterSpec {
             name: "status".to_string(),
+            dimensions: vec![],
             size: 4,
             offset: Some("0x04".to_string()),
+            reset: None,
+            access: None,
             documentation: Some("Status register".to_string()),
             fields: vec![field],
             methods: vec![],
+            templates: vec![],
         };
         
         let result = generator.generate_register(&register_spec).await;
@@ -211,7 +851,7 @@ mod mcp_tests {
         let code = result.unwrap();
         assert!(code.contains("field ready"));
         assert!(code.contains("@ [0]"));
-        assert!(code.contains("access ro"));
+        assert!(code.contains("is (read_only)"));
     }
 
     #[test]
@@ -244,11 +884,96 @@ mod mcp_tests {
         
         let code = result.unwrap();
         assert!(code.contains("method write"));
-        assert!(code.contains("data: uint32"));
+        assert!(code.contains("method write(uint32 data) {"));
         assert!(code.contains("register_value = data;"));
         assert!(code.contains("/// Write to register"));
     }
 
+    #[test]
+    fn test_generate_method_wrapping() {
+        let config = GenerationConfig {
+            format: FormatConfig {
+                indent_style: IndentStyle::Tabs,
+                max_line_length: 44,
+                ..FormatConfig::default()
+            },
+            line_ending: LineEnding::Windows,
+            ..GenerationConfig::default()
+        };
+        let generator = DMLGenerator::new(GenerationContext {
+            device_name: "test".to_string(),
+            namespace: "test".to_string(),
+            imports: vec![],
+            templates: vec![],
+            config,
+        });
+        let param = |name: &str| ParameterSpec {
+            name: name.to_string(),
+            param_type: "uint64".to_string(),
+        };
+        let method_spec = MethodSpec {
+            name: "transfer".to_string(),
+            parameters: vec![param("source"), param("destination"),
+                             param("length")],
+            return_type: Some("(bool)".to_string()),
+            body: Some("return true;".to_string()),
+            documentation: Some("Copy a block of memory between two \
+                                 addresses of the target".to_string()),
+        };
+
+        let code = generator.generate_method(&method_spec).unwrap();
+        assert_eq!(code, "\t/// Copy a block of memory between\r\n\
+                          \t/// two addresses of the target\r\n\
+                          \tmethod transfer(uint64 source,\r\n\
+                          \t\t\tuint64 destination,\r\n\
+                          \t\t\tuint64 length)\r\n\
+                          \t\t\t-> (bool) {\r\n\
+                          \t\treturn true;\r\n\
+                          \t}\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_generate_field_wrapping() {
+        let config = GenerationConfig {
+            format: FormatConfig {
+                max_line_length: 40,
+                ..FormatConfig::default()
+            },
+            generate_docs: false,
+            ..GenerationConfig::default()
+        };
+        let generator = DMLGenerator::new(GenerationContext {
+            device_name: "test".to_string(),
+            namespace: "test".to_string(),
+            imports: vec![],
+            templates: vec![],
+            config,
+        });
+        let register_spec = RegisterSpec {
+            name: "status".to_string(),
+            dimensions: vec![],
+            size: 4,
+            offset: Some("0x04".to_string()),
+            reset: None,
+            access: None,
+            documentation: None,
+            fields: vec![FieldSpec {
+                name: "tx_fifo_overflow".to_string(),
+                bits: "15:8".to_string(),
+                access: Some("w1c".to_string()),
+                documentation: None,
+                templates: vec![],
+            }],
+            methods: vec![],
+            templates: vec![],
+        };
+
+        let code = generator.generate_register(&register_spec).await.unwrap();
+        assert!(code.contains("        field tx_fifo_overflow @ [15:8]\n\
+                               \x20           is (write_1_clears);\n"));
+        assert!(code.lines().all(|line| line.len() <= 40));
+    }
+
     #[test]
     fn test_basic_device_template() {
         let device = DMLTemplates::basic_device("test_cpu", "cpu");
@@ -336,6 +1061,222 @@ mod mcp_tests {
         assert_eq!(config_reg.fields.len(), 2); // width and endian fields
     }
 
+    #[test]
+    fn test_dma_controller_template() {
+        let device = DMLTemplates::dma_controller("test_dma", 8);
+        
+        assert_eq!(device.name, "test_dma");
+        let registers = &device.banks[0].registers;
+        assert_eq!(registers.len(), 6);
+        assert!(registers.iter().all(|r| r.dimensions.len() == 1
+                                     && r.dimensions[0].size == 8));
+        let control = registers.iter().find(|r| r.name == "control").unwrap();
+        assert_eq!(control.offset.as_deref(), Some("0x1c + i * 0x40"));
+        assert_eq!(control.methods.len(), 1);
+        let irq = device.connects.iter().find(|c| c.name == "irq").unwrap();
+        assert_eq!(irq.interfaces, ["signal"]);
+        assert!(device.methods.iter().any(|m| m.name == "fetch_descriptor"));
+    }
+
+    #[tokio::test]
+    async fn test_generate_dma_controller() {
+        let context = GenerationContext {
+            device_name: "test_dma".to_string(),
+            namespace: "test_dma".to_string(),
+            imports: vec![],
+            templates: vec![],
+            config: GenerationConfig::default(),
+        };
+        
+        let generator = DMLGenerator::new(context);
+        let device = DMLTemplates::dma_controller("test_dma", 2);
+        let code = generator.generate_device(&device).await.unwrap().content;
+        
+        assert!(code.contains("register source[i < 2] size 8 @ 0x00 + i * 0x40 {"));
+        assert!(code.contains("\nconnect irq {\n    interface signal;\n}"));
+        assert!(code.contains("\nmethod fetch_descriptor(int channel) {"));
+    }
+
+    #[tokio::test]
+    async fn test_generated_patterns_parse() {
+        use crate::mcp::analysis::parse_dml;
+        use std::path::Path;
+
+        let generator = DMLGenerator::new(GenerationContext {
+            device_name: "pattern".to_string(),
+            namespace: "pattern".to_string(),
+            imports: vec![],
+            templates: vec![],
+            config: GenerationConfig::default(),
+        });
+        for (name, pattern) in DMLTemplates::get_pattern_templates() {
+            let device = pattern("pattern", &json!({})).unwrap();
+            let code = generator.generate_device(&device).await.unwrap().content;
+            let parsed = parse_dml(Path::new("pattern.dml"), &code).unwrap();
+            assert!(parsed.errors.is_empty(), "{}: {:?}\n{}",
+                    name, parsed.errors, code);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_uart_device_template() {
+        let device = DMLTemplates::uart_device("test_uart", 16);
+        
+        let names: Vec<&str> = device.banks[0].registers.iter()
+            .map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["rbr_thr", "ier", "iir_fcr", "lcr", "lsr"]);
+        assert!(device.banks[0].registers.iter().all(|r| r.size == 1));
+        assert!(device.connects.iter().any(|c| c.name == "console"
+                                           && c.interfaces == ["serial_device"]));
+        assert!(device.variables.iter().all(|v| v.saved));
+        
+        let context = GenerationContext {
+            device_name: "test_uart".to_string(),
+            namespace: "test_uart".to_string(),
+            imports: vec![],
+            templates: vec![],
+            config: GenerationConfig::default(),
+        };
+        let code = DMLGenerator::new(context).generate_device(&device).await
+            .unwrap().content;
+        assert!(code.contains("\nsaved uint8 rx_fifo[16];\n"));
+        assert!(code.contains("\nsaved int rx_count;\n"));
+        assert!(code.contains("method read() -> (uint64) {"));
+        assert!(code.contains("rx_head = (rx_head + 1) % 16;"));
+    }
+
+    #[tokio::test]
+    async fn test_timer_device_template() {
+        let device = DMLTemplates::timer_device("test_timer", 1_000_000);
+        
+        let control = &device.banks[0].registers[0];
+        assert!(control.fields.iter().any(|f| f.name == "prescaler" && f.bits == "15:8"));
+        assert_eq!(device.events.len(), 1);
+        assert_eq!(device.events[0].template, "simple_time_event");
+        assert!(device.connects.iter().any(|c| c.name == "irq"));
+        
+        let context = GenerationContext {
+            device_name: "test_timer".to_string(),
+            namespace: "test_timer".to_string(),
+            imports: vec![],
+            templates: vec![],
+            config: GenerationConfig::default(),
+        };
+        let code = DMLGenerator::new(context).generate_device(&device).await
+            .unwrap().content;
+        assert!(code.contains("\nevent expiry is (simple_time_event) {\n    method event() {\n"));
+        assert!(code.contains("expiry.post((regs.load.val + 1) * tick);"));
+    }
+
+    #[test]
+    fn test_watchdog_device_template() {
+        let device = DMLTemplates::watchdog_device("test_wdt", 32_768);
+        
+        let registers = &device.banks[0].registers;
+        let kick = registers.iter().find(|r| r.name == "kick").unwrap();
+        assert_eq!(kick.methods[0].name, "write");
+        let connects: Vec<&str> = device.connects.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(connects, ["irq", "reset_out"]);
+        assert!(device.events[0].body.as_deref().unwrap().contains("reset_out.signal.signal_raise()"));
+    }
+
+    #[tokio::test]
+    async fn test_gpio_controller_template() {
+        let device = DMLTemplates::gpio_controller("test_gpio", 8);
+        
+        let registers = &device.banks[0].registers;
+        assert!(registers.iter().all(|r| r.fields[0].bits == "7:0"));
+        assert!(registers.iter().any(|r| r.name == "irq_type"));
+        assert_eq!(device.ports.len(), 1);
+        assert_eq!(device.ports[0].dimensions[0].size, 8);
+        assert_eq!(device.ports[0].interfaces[0].methods.len(), 2);
+        
+        let context = GenerationContext {
+            device_name: "test_gpio".to_string(),
+            namespace: "test_gpio".to_string(),
+            imports: vec![],
+            templates: vec![],
+            config: GenerationConfig::default(),
+        };
+        let code = DMLGenerator::new(context).generate_device(&device).await
+            .unwrap().content;
+        assert!(code.contains("\nport pin[i < 8] {\n    implement signal {\n        /// Drive the pin high\n        method signal_raise() {\n            set_pin(i, true);\n        }\n"));
+    }
+
+    #[tokio::test]
+    async fn test_pcie_endpoint_template() {
+        let device = DMLTemplates::pcie_endpoint("test_pcie", 0x8086, 0x1234,
+                                                 &[0x4000, 100], 16);
+        
+        let config = &device.banks[0];
+        assert_eq!(config.name, "pci_config");
+        let register = |name: &str| config.registers.iter()
+            .find(|r| r.name == name).unwrap();
+        assert_eq!(register("device_id").reset.as_deref(), Some("0x1234"));
+        assert_eq!(register("bar1").offset.as_deref(), Some("0x14"));
+        assert_eq!(register("bar1").methods[0].body.as_deref(),
+                   Some("default(value & 0xffffff80);"));
+        assert!(config.registers.iter().all(|r| r.name != "bar2"));
+        assert_eq!(register("msix_control").reset.as_deref(), Some("0xf"));
+        // The capability chain is linked through the next pointers
+        assert_eq!(register("capabilities_ptr").reset.as_deref(), Some("0x40"));
+        assert_eq!(register("exp_next_ptr").reset.as_deref(), Some("0x60"));
+        assert_eq!(register("msi_next_ptr").reset.as_deref(), Some("0x70"));
+        assert_eq!(device.interfaces[0].name, "pcie_device");
+        assert!(device.connects.iter().any(|c| c.name == "upstream_target"
+                                           && c.interfaces == ["transaction"]));
+        
+        let without_msix = DMLTemplates::pcie_endpoint("test_pcie", 0x8086, 0x1234,
+                                                       &[0x1000], 0);
+        assert!(without_msix.banks[0].registers.iter().all(|r| !r.name.starts_with("msix")));
+        assert!(without_msix.methods.iter().all(|m| m.name != "raise_msix"));
+        
+        let context = GenerationContext {
+            device_name: "test_pcie".to_string(),
+            namespace: "test_pcie".to_string(),
+            imports: vec![],
+            templates: vec![],
+            config: GenerationConfig::default(),
+        };
+        let code = DMLGenerator::new(context).generate_device(&device).await
+            .unwrap().content;
+        assert!(code.contains("\nbank pci_config {\n"));
+        assert!(code.contains("register vendor_id size 2 @ 0x00 {\n        is read_only;\n"));
+        assert!(code.contains("\nimplement pcie_device {\n"));
+        assert!(code.contains("\nconnect upstream_target {\n    interface transaction;\n}"));
+    }
+
+    #[tokio::test]
+    async fn test_nic_template() {
+        let device = DMLTemplates::nic_device("test_nic", 2, 512);
+        
+        let regs = &device.banks[0];
+        let register = |name: &str| regs.registers.iter()
+            .find(|r| r.name == name).unwrap();
+        assert_eq!(register("rx_len").dimensions[0].size, 2);
+        assert_eq!(register("rx_len").reset.as_deref(), Some("512"));
+        assert_eq!(register("tx_tail").offset.as_deref(), Some("0x130 + i * 0x40"));
+        assert!(register("itr").fields.iter().any(|f| f.name == "packets"));
+        assert_eq!(device.interfaces[0].name, "ethernet_common");
+        assert!(device.connects.iter().any(|c| c.name == "link"
+                                           && c.interfaces == ["ethernet_common"]));
+        assert_eq!(device.events[0].name, "coalesce_timer");
+        
+        let context = GenerationContext {
+            device_name: "test_nic".to_string(),
+            namespace: "test_nic".to_string(),
+            imports: vec![],
+            templates: vec![],
+            config: GenerationConfig::default(),
+        };
+        let code = DMLGenerator::new(context).generate_device(&device).await
+            .unwrap().content;
+        assert!(code.contains("register rx_base[i < 2] size 8 @ 0x100 + i * 0x40"));
+        assert!(code.contains("\nconnect link {\n    interface ethernet_common;\n}"));
+        // Nested lines of method bodies keep their relative indentation
+        assert!(code.contains("\n    if (raise != irq_raised && irq.obj) {\n        if (raise) {\n            irq.signal.signal_raise();\n"));
+    }
+
     #[test]
     fn test_pattern_templates_exist() {
         let patterns = DMLTemplates::get_pattern_templates();
@@ -345,6 +1286,13 @@ mod mcp_tests {
         assert!(patterns.contains_key("cpu"));
         assert!(patterns.contains_key("memory"));
         assert!(patterns.contains_key("bus_interface"));
+        assert!(patterns.contains_key("dma"));
+        assert!(patterns.contains_key("uart"));
+        assert!(patterns.contains_key("timer"));
+        assert!(patterns.contains_key("watchdog"));
+        assert!(patterns.contains_key("gpio"));
+        assert!(patterns.contains_key("pcie_endpoint"));
+        assert!(patterns.contains_key("nic"));
     }
 
     #[test]
@@ -364,43 +1312,2589 @@ mod mcp_tests {
         assert_eq!(device.name, "test_device");
     }
 
-    #[test]
-    fn test_indent_style_spaces() {
-        let config = GenerationConfig {
-            indent_style: IndentStyle::Spaces(2),
-            ..Default::default()
-        };
-        
-        let context = GenerationContext {
-            device_name: "test".to_string(),
-            namespace: "test".to_string(),
-            imports: vec![],
-            templates: vec![],
-            config,
-        };
-        
-        let generator = DMLGenerator::new(context);
-        let indent = generator.get_indent();
-        assert_eq!(indent, "  "); // 2 spaces
+    #[tokio::test]
+    async fn test_apply_pattern_composition() {
+        use crate::mcp::tools::{ApplyPatternTool, DMLTool};
+
+        let tool = ApplyPatternTool;
+        // Both patterns map registers at 0x0 of bank 'registers'
+        let error = tool.execute(json!({
+            "name": "periph",
+            "patterns": ["memory_mapped", "interrupt_controller"]
+        })).await.unwrap_err();
+        assert!(error.to_string().contains(
+            "Could not apply pattern interrupt_controller: Register irq_enable \
+             at 0x00 conflicts with register control at 0x00 in bank \
+             registers"), "{}", error);
+
+        let result = tool.execute(json!({
+            "name": "periph",
+            "base": {
+                "name": "base",
+                "base_template": null,
+                "documentation": "Peripheral with interrupts and DMA",
+                "interfaces": [{"name": "signal"}]
+            },
+            "patterns": [
+                "memory_mapped",
+                {"pattern": "interrupt_controller", "offset": "0x100"},
+                {"pattern": "dma", "config": {"num_channels": 2}}
+            ]
+        })).await.unwrap();
+        let code = &result.content[0].text;
+        assert!(code.contains("device periph"), "{}", code);
+        assert!(code.contains("/// Peripheral with interrupts and DMA"), "{}", code);
+        assert!(code.contains("register control size 4 @ 0x00"), "{}", code);
+        assert!(code.contains("register irq_enable size 4 @ 0x100"), "{}", code);
+        assert!(code.contains("bank regs"), "{}", code);
+        assert_eq!(code.matches("implement signal").count(), 1, "{}", code);
+        assert!(code.contains("implement io_memory"), "{}", code);
+        assert!(code.contains("method start_transfer"), "{}", code);
     }
 
     #[test]
-    fn test_indent_style_tabs() {
-        let config = GenerationConfig {
-            indent_style: IndentStyle::Tabs,
-            ..Default::default()
-        };
-        
-        let context = GenerationContext {
-            device_name: "test".to_string(),
-            namespace: "test".to_string(),
-            imports: vec![],
-            templates: vec![],
-            config,
+    fn test_merge_device_specs() {
+        use crate::mcp::generation::MergeStrategy;
+
+        let spec = |doc: &str, register: &str, method_body: &str| -> DeviceSpec {
+            serde_json::from_value(json!({
+                "name": "dev",
+                "base_template": null,
+                "documentation": doc,
+                "banks": [{
+                    "name": "regs",
+                    "documentation": null,
+                    "registers": [{"name": register, "size": 4, "offset": "0x0",
+                                   "documentation": null}]
+                }],
+                "methods": [{"name": "reset", "return_type": null,
+                             "documentation": null, "body": method_body}]
+            })).unwrap()
         };
-        
-        let generator = DMLGenerator::new(context);
-        let indent = generator.get_indent();
-        assert_eq!(indent, "\t"); // tab character
+        let existing = spec("Existing", "ctrl", "return;");
+        let new = spec("New", "status", "ctrl.val = 0;");
+
+        let error = existing.clone().merge(new.clone(), MergeStrategy::Error)
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Register status at 0x0 conflicts with \
+                                       register ctrl at 0x0 in bank regs");
+
+        let mut merged = existing.clone();
+        merged.merge(new.clone(), MergeStrategy::PreferExisting).unwrap();
+        assert_eq!(merged.documentation.as_deref(), Some("Existing"));
+        assert_eq!(merged.banks[0].registers.len(), 1);
+        assert_eq!(merged.banks[0].registers[0].name, "ctrl");
+        assert_eq!(merged.methods[0].body.as_deref(), Some("return;"));
+
+        let mut merged = existing.clone();
+        merged.merge(new.clone(), MergeStrategy::PreferNew).unwrap();
+        assert_eq!(merged.documentation.as_deref(), Some("New"));
+        assert_eq!(merged.banks[0].registers.len(), 1);
+        assert_eq!(merged.banks[0].registers[0].name, "status");
+        assert_eq!(merged.methods.len(), 1);
+        assert_eq!(merged.methods[0].body.as_deref(), Some("ctrl.val = 0;"));
+
+        // Identical definitions are not conflicts
+        let mut merged = existing.clone();
+        merged.merge(existing.clone(), MergeStrategy::Error).unwrap();
+        assert_eq!(merged.banks[0].registers.len(), 1);
+        assert_eq!(merged.methods.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_patch_device_spec() {
+        use crate::mcp::tools::{DMLTool, PatchDeviceSpecTool};
+
+        let dir = std::env::temp_dir().join(
+            format!("dls-patch-spec-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let spec_file = dir.join("dev.json");
+        std::fs::write(&spec_file, json!({
+            "name": "dev",
+            "base_template": "peripheral_device",
+            "documentation": "Device",
+            "banks": [{
+                "name": "regs",
+                "documentation": null,
+                "registers": [{"name": "ctrl", "size": 4, "offset": "0x0",
+                               "documentation": null}]
+            }]
+        }).to_string()).unwrap();
+
+        let tool = PatchDeviceSpecTool;
+        let result = tool.execute(json!({
+            "spec_file": spec_file.to_str().unwrap(),
+            "merge": {
+                "name": "more",
+                "base_template": null,
+                "documentation": null,
+                "banks": [{
+                    "name": "regs",
+                    "documentation": "Control registers",
+                    "registers": [{"name": "status", "size": 4, "offset": "0x4",
+                                   "documentation": null}]
+                }]
+            },
+            "patch": {"documentation": null, "base_template": "base_device"},
+            "write": true
+        })).await.unwrap();
+        assert_eq!(result.content.len(), 2);
+        let spec = DeviceSpec::load(&spec_file).unwrap();
+        assert_eq!(spec.name, "dev");
+        assert_eq!(spec.documentation, None);
+        assert_eq!(spec.base_template.as_deref(), Some("base_device"));
+        assert_eq!(spec.banks[0].documentation.as_deref(),
+                   Some("Control registers"));
+        assert_eq!(spec.banks[0].registers.iter().map(|r| r.name.as_str())
+                   .collect::<Vec<_>>(), vec!["ctrl", "status"]);
+
+        // Patches must leave a valid specification
+        let error = tool.execute(json!({
+            "spec_file": spec_file.to_str().unwrap(),
+            "patch": {"banks": [{"name": "regs", "registers": [{"size": "4"}]}]}
+        })).await.unwrap_err();
+        assert!(error.to_string().starts_with("Invalid patched specification"),
+                "{}", error);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_validate_spec() {
+        use crate::mcp::tools::{DMLTool, ValidateSpecTool};
+
+        let tool = ValidateSpecTool;
+        let result = tool.execute(json!({
+            "device_spec": {
+                "name": "dev",
+                "base_template": null,
+                "documentation": "Device",
+                "banks": [
+                    {"name": "empty", "documentation": "Nothing here"},
+                    {
+                        "name": "regs",
+                        "documentation": "Registers",
+                        "registers": [
+                            {"name": "ctrl", "size": 1, "offset": "0x0",
+                             "documentation": "Control", "access": "rwx",
+                             "fields": [
+                                 {"name": "mode", "bits": "8:6",
+                                  "documentation": "Mode"},
+                                 {"name": "mode", "bits": "0", "access": "ro",
+                                  "documentation": null}
+                             ]},
+                            {"name": "ctrl", "size": 4, "offset": "0x4",
+                             "documentation": "Control again"}
+                        ]
+                    }
+                ]
+            }
+        })).await.unwrap();
+        let result: serde_json::Value =
+            serde_json::from_str(&result.content[0].text).unwrap();
+        assert_eq!(result["valid"], false);
+        let findings: Vec<(String, String)> = result["findings"].as_array()
+            .unwrap().iter()
+            .map(|finding| (finding["code"].as_str().unwrap().to_string(),
+                            finding["path"].as_str().unwrap().to_string()))
+            .collect();
+        let expected = [
+            ("empty-bank", "/banks/0"),
+            ("unknown-access", "/banks/1/registers/0/access"),
+            ("field-too-wide", "/banks/1/registers/0/fields/0/bits"),
+            ("duplicate-field", "/banks/1/registers/0/fields/1"),
+            ("missing-documentation", "/banks/1/registers/0/fields/1"),
+            ("duplicate-register", "/banks/1/registers/1"),
+        ];
+        assert_eq!(findings, expected.map(|(code, path)|
+                                          (code.to_string(), path.to_string())));
+        assert_eq!(result["errors"], 4);
+        assert_eq!(result["warnings"], 1);
+
+        // Schema violations are reported as findings
+        let result = tool.execute(json!({
+            "device_spec": {"name": "dev", "banks": [{"name": 3}]},
+            "generate_docs": false
+        })).await.unwrap();
+        let result: serde_json::Value =
+            serde_json::from_str(&result.content[0].text).unwrap();
+        assert_eq!(result["valid"], false);
+        assert_eq!(result["findings"][0]["code"], "schema");
+    }
+
+    #[tokio::test]
+    async fn test_diff_register_maps() {
+        use crate::mcp::tools::{DMLTool, DiffRegisterMapsTool};
+
+        // The next stepping of the sample device
+        let stepping = "dml 1.4;
+
+device sample;
+
+bank regs {
+    register control size 4 @ 0x00 {
+        field enable @ [0];
+        field op_mode @ [3:1] is read_only;
+        field irq @ [4];
+    }
+    register status size 4 @ 0x04 \"Status register\";
+    register data size 4 @ 0x0c is unimpl {
+        param init_val = 32;
+    }
+    register version size 4 @ 0x10;
+}
+bank pci;
+";
+        let tool = DiffRegisterMapsTool;
+        let result = tool.execute(json!({
+            "old": {"code": SAMPLE_DEVICE},
+            "new": {"code": stepping}
+        })).await.unwrap();
+        let result: serde_json::Value =
+            serde_json::from_str(&result.content[0].text).unwrap();
+        assert_eq!(result["identical"], false);
+        let changes: Vec<String> = result["changes"].as_array().unwrap().iter()
+            .map(|change| format!(
+                "{} {}.{}.{} {} -> {}", change["kind"].as_str().unwrap(),
+                change["bank"].as_str().unwrap(),
+                change["register"].as_str().unwrap_or("-"),
+                change["field"].as_str().unwrap_or("-"),
+                change["old"].as_str().unwrap_or("-"),
+                change["new"].as_str().unwrap_or("-")))
+            .collect();
+        assert_eq!(changes, [
+            "field_renamed regs.control.op_mode mode -> op_mode",
+            "field_added regs.control.irq - -> 4",
+            "size_changed regs.status.- 2 -> 4",
+            "register_moved regs.data.- 0x8 -> 0xc",
+            "reset_changed regs.data.- 0x10 -> 0x20",
+            "register_added regs.version.- - -> 0x10",
+            "bank_added pci.-.- - -> -",
+        ]);
+        assert_eq!(result["summary"]["field_renamed"], 1);
+
+        let result = tool.execute(json!({
+            "old": {"code": SAMPLE_DEVICE},
+            "new": {"code": SAMPLE_DEVICE}
+        })).await.unwrap();
+        assert!(result.content[0].text.contains("\"identical\": true"));
+        let error = tool.execute(json!({
+            "old": {"code": SAMPLE_DEVICE},
+            "new": {}
+        })).await.unwrap_err();
+        assert!(error.to_string().starts_with("new: "), "{}", error);
+
+        // Compared with an SVD description of the same register map
+        #[cfg(feature = "importers")]
+        {
+            let dir = std::env::temp_dir().join(
+                format!("dls-diff-svd-{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let svd = dir.join("sample.svd");
+            std::fs::write(&svd, "\
+<device>
+  <name>sample</name>
+  <peripherals>
+    <peripheral>
+      <name>regs</name>
+      <registers>
+        <register>
+          <name>control</name><addressOffset>0x0</addressOffset>
+          <fields>
+            <field><name>enable</name><bitRange>[0:0]</bitRange></field>
+            <field><name>mode</name><bitRange>[3:1]</bitRange>
+                   <access>read-only</access></field>
+          </fields>
+        </register>
+        <register>
+          <name>status</name><addressOffset>0x4</addressOffset>
+          <size>16</size>
+        </register>
+        <register>
+          <name>data</name><addressOffset>0x8</addressOffset>
+          <resetValue>0x10</resetValue>
+        </register>
+      </registers>
+    </peripheral>
+  </peripherals>
+</device>
+").unwrap();
+            let result = tool.execute(json!({
+                "old": {"spec_file": svd},
+                "new": {"code": SAMPLE_DEVICE}
+            })).await.unwrap();
+            std::fs::remove_dir_all(&dir).ok();
+            let result: serde_json::Value =
+                serde_json::from_str(&result.content[0].text).unwrap();
+            assert_eq!(result["identical"], true, "{}", result);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_explain_error() {
+        use crate::mcp::analysis::parse_dml;
+        use crate::mcp::explain::{explain_diagnostic, ExampleLanguage,
+                                  EXPLANATIONS};
+        use crate::mcp::tools::{DMLTool, ExplainErrorTool};
+        use std::path::Path;
+
+        fn code_of(diagnostic: &str) -> Option<&'static str> {
+            explain_diagnostic(diagnostic).map(|explanation| explanation.code)
+        }
+        assert_eq!(code_of("sp_punct"), Some("sp_punct"));
+        assert_eq!(code_of("SP-PUNCT"), Some("sp_punct"));
+        assert_eq!(code_of("field_too_wide"), Some("field-too-wide"));
+        assert_eq!(code_of("long_lines: Line length is above the threshold."),
+                   Some("long_lines"));
+        assert_eq!(code_of("No template named 'read_onyl'"),
+                   Some("unknown-template"));
+        assert_eq!(code_of("Unexpected token '}', expected ';'"),
+                   Some("unexpected-token"));
+        assert_eq!(code_of("Expected time unit ('s', 'cycles', 'ps')"),
+                   Some("time-unit"));
+        assert_eq!(code_of("something else entirely"), None);
+
+        // The examples of the rules the parser checks are what they claim
+        let parser_rules = ["version-declaration", "device-declaration",
+                            "bitorder", "time-unit", "inline-method",
+                            "startup-method", "typed-parameter",
+                            "shared-method", "unexpected-token"];
+        for explanation in EXPLANATIONS {
+            if explanation.language == ExampleLanguage::Json {
+                for example in [explanation.failing_example,
+                                explanation.corrected_example] {
+                    serde_json::from_str::<serde_json::Value>(example)
+                        .unwrap();
+                }
+                continue;
+            }
+            let corrected = parse_dml(Path::new("ok.dml"),
+                                      explanation.corrected_example).unwrap();
+            assert!(corrected.errors.is_empty(), "{}: {:?}",
+                    explanation.code, corrected.errors);
+            if parser_rules.contains(&explanation.code) {
+                let failing = parse_dml(Path::new("fail.dml"),
+                                        explanation.failing_example).unwrap();
+                assert!(failing.errors.iter().any(
+                    |error| code_of(&error.description)
+                        == Some(explanation.code)),
+                        "{}: {:?}", explanation.code, failing.errors);
+            }
+        }
+
+        let tool = ExplainErrorTool;
+        let result = tool.execute(json!({
+            "diagnostic": "Definition overrides non-default parameter"
+        })).await.unwrap();
+        let result: serde_json::Value =
+            serde_json::from_str(&result.content[0].text).unwrap();
+        assert_eq!(result["code"], "parameter-override");
+        assert_eq!(result["language"], "dml");
+        assert!(result["corrected_example"].as_str().unwrap()
+                .contains("param width default 8;"));
+        assert!(result.get("patterns").is_none());
+        let error = tool.execute(json!({"diagnostic": "E1234"})).await
+            .unwrap_err();
+        assert!(error.to_string().contains("the known codes are"));
+    }
+
+    #[tokio::test]
+    async fn test_find_symbol() {
+        use crate::mcp::tools::{DMLTool, FindSymbolTool};
+
+        let dir = std::env::temp_dir().join(
+            format!("dls-find-symbol-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        std::fs::write(dir.join("sample.dml"), SAMPLE_DEVICE).unwrap();
+        std::fs::write(dir.join("lib/common.dml"), "dml 1.4;
+
+template control_reg {
+    method clear_mode() {
+        field mode = 0;
+    }
+}
+").unwrap();
+
+        let tool = FindSymbolTool;
+        let find = |input: serde_json::Value| {
+            let tool = &tool;
+            async move {
+                let result = tool.execute(input).await.unwrap();
+                serde_json::from_str::<serde_json::Value>(
+                    &result.content[0].text).unwrap()
+            }
+        };
+        let workspace = dir.to_str().unwrap();
+        let result = find(json!({"workspace": workspace,
+                                 "query": "mode"})).await;
+        let found: Vec<(&str, &str, &str)> = result["symbols"].as_array()
+            .unwrap().iter()
+            .map(|symbol| (symbol["kind"].as_str().unwrap(),
+                           symbol["container"].as_str().unwrap_or("-"),
+                           symbol["file"].as_str().unwrap()))
+            .collect();
+        // Exact matches first, and no locals unless asked for
+        assert_eq!(found, [("field", "regs.control", "sample.dml"),
+                           ("method", "control_reg",
+                            "lib/common.dml")]);
+        let mode = &result["symbols"][0];
+        assert_eq!(mode["selection_range"], json!({
+            "start_line": 8, "start_column": 15,
+            "end_line": 8, "end_column": 19}));
+        assert_eq!(mode["range"], json!({
+            "start_line": 8, "start_column": 9,
+            "end_line": 8, "end_column": 41}));
+
+        let result = find(json!({"workspace": workspace,
+                                 "query": "regs.control",
+                                 "kinds": ["register"]})).await;
+        assert_eq!(result["count"], 1);
+        assert_eq!(result["symbols"][0]["container"], "regs");
+
+        let result = find(json!({"code": SAMPLE_DEVICE, "kinds": ["field"],
+                                 "limit": 1})).await;
+        assert_eq!(result["count"], 2);
+        assert_eq!(result["truncated"], true);
+        assert_eq!(result["symbols"][0]["name"], "enable");
+        assert_eq!(result["degraded"]["mode"], "single_file");
+
+        assert!(tool.execute(json!({"code": SAMPLE_DEVICE,
+                                    "kinds": ["registers"]})).await
+                .is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_read_symbol() {
+        use crate::mcp::tools::{DMLTool, ReadSymbolTool};
+
+        let tool = ReadSymbolTool;
+        let read = |symbol: &str| tool.execute(json!({
+            "code": SAMPLE_DEVICE, "symbol": symbol}));
+        let result = read("sample.regs.control.mode").await.unwrap();
+        let result: serde_json::Value =
+            serde_json::from_str(&result.content[0].text).unwrap();
+        assert_eq!(result["count"], 1);
+        let mode = &result["definitions"][0];
+        assert_eq!(mode["kind"], "field");
+        assert_eq!(mode["container"], "regs.control");
+        assert_eq!(mode["source"], "        field mode @ [3:1] is read_only;\n");
+        assert_eq!(mode["properties"]["msb"], 3);
+        assert_eq!(mode["properties"]["access"], "ro");
+
+        let result = read("regs.status").await.unwrap();
+        let result: serde_json::Value =
+            serde_json::from_str(&result.content[0].text).unwrap();
+        let status = &result["definitions"][0];
+        assert_eq!(status["documentation"], "Status register");
+        assert_eq!(status["properties"]["offset"], "0x4");
+        assert_eq!(status["properties"]["size"], 2);
+
+        let result = read("regs.data").await.unwrap();
+        let result: serde_json::Value =
+            serde_json::from_str(&result.content[0].text).unwrap();
+        let data = &result["definitions"][0];
+        assert_eq!(data["source"].as_str().unwrap().lines().count(), 3);
+        assert_eq!(data["properties"]["reset"], "0x10");
+        assert_eq!(data["properties"]["templates"], json!(["unimpl"]));
+
+        let error = read("regs.control.enabled").await.unwrap_err();
+        assert!(error.to_string().contains("find_symbol"));
+        // Only the name of the device the file declares may lead the name
+        assert!(read("other.regs.status").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_find_references() {
+        use crate::mcp::tools::{DMLTool, FindReferencesTool};
+
+        let dir = std::env::temp_dir().join(
+            format!("dls-find-references-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("helpers.dml"), "dml 1.4;
+template counting {
+    param limit = 4;
+    method tick() -> (int) {
+        return limit;
+    }
+}
+").unwrap();
+        let file = dir.join("dev.dml");
+        std::fs::write(&file, "dml 1.4;
+device dev;
+import \"helpers.dml\";
+param limit_reached = false;
+bank regs is counting {
+    param limit = 8;
+    method reset() {
+        local int n = tick();
+        n = limit;
+    }
+}
+method poll() -> (int) {
+    return regs.tick() + regs.limit;
+}
+").unwrap();
+
+        let tool = FindReferencesTool;
+        let find = |input: serde_json::Value| {
+            let tool = &tool;
+            async move {
+                let result = tool.execute(input).await.unwrap();
+                serde_json::from_str::<serde_json::Value>(
+                    &result.content[0].text).unwrap()
+            }
+        };
+        let sites = |result: &serde_json::Value| -> Vec<String> {
+            result["references"].as_array().unwrap().iter()
+                .map(|site| format!(
+                    "{}:{}:{} {}{}", site["file"].as_str().unwrap(),
+                    site["range"]["start_line"], site["range"]["start_column"],
+                    site["context"].as_str().unwrap(),
+                    if site["declaration"] == true { " (declaration)" }
+                    else { "" }))
+                .collect()
+        };
+        let device = file.to_str().unwrap();
+        // The parameter set in the bank is also the one its template reads
+        let result = find(json!({"device": device, "symbol": "dev.regs.limit",
+                                 "include_declarations": true})).await;
+        assert_eq!(result["symbols"][0]["kind"], "param");
+        assert_eq!(sites(&result), [
+            "dev.dml:6:11 param limit = 8; (declaration)",
+            "dev.dml:9:13 n = limit;",
+            "dev.dml:13:31 return regs.tick() + regs.limit;",
+            "helpers.dml:5:16 return limit;",
+        ]);
+
+        // A method declared in a template, by its position there
+        let result = find(json!({
+            "device": device,
+            "file": dir.join("helpers.dml").to_str().unwrap(),
+            "line": 4, "column": 12
+        })).await;
+        assert_eq!(result["symbols"][0]["name"], "tick");
+        assert_eq!(result["symbols"][0]["file"], "helpers.dml");
+        assert_eq!(sites(&result), [
+            "dev.dml:8:23 local int n = tick();",
+            "dev.dml:13:17 return regs.tick() + regs.limit;",
+        ]);
+
+        // The same method, from a reference to it
+        let from_reference = find(json!({"device": device, "line": 13,
+                                         "column": 19})).await;
+        assert_eq!(from_reference["references"], result["references"]);
+
+        assert!(tool.execute(json!({"device": device,
+                                    "symbol": "regs.limits"})).await
+                .is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rename_symbol() {
+        use crate::mcp::tools::{DMLTool, RenameSymbolTool};
+
+        let dir = std::env::temp_dir().join(
+            format!("dls-rename-symbol-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("helpers.dml"), "dml 1.4;
+template counting {
+    param limit default 4;
+    method tick() -> (int) default {
+        return limit;
+    }
+}
+").unwrap();
+        let file = dir.join("dev.dml");
+        std::fs::write(&file, "dml 1.4;
+device dev;
+import \"helpers.dml\";
+param limit_reached = false;
+bank regs is counting {
+    param limit = 8;
+    method tick() -> (int) {
+        return default() + limit;
+    }
+}
+method poll() -> (int) {
+    return regs.tick() + regs.limit;
+}
+").unwrap();
+
+        let tool = RenameSymbolTool;
+        let rename = |input: serde_json::Value| {
+            let tool = &tool;
+            async move {
+                let result = tool.execute(input).await.unwrap();
+                serde_json::from_str::<serde_json::Value>(
+                    &result.content[0].text).unwrap()
+            }
+        };
+        let ranges = |result: &serde_json::Value| -> Vec<String> {
+            result["files"].as_array().unwrap().iter()
+                .flat_map(|file| file["ranges"].as_array().unwrap().iter()
+                          .map(move |range| format!(
+                              "{}:{}:{}", file["file"].as_str().unwrap(),
+                              range["start_line"], range["start_column"])))
+                .collect()
+        };
+        let device = file.to_str().unwrap();
+        let result = rename(json!({"device": device, "symbol": "regs.limit",
+                                   "new_name": "threshold"})).await;
+        assert_eq!(result["old_name"], "limit");
+        assert_eq!(result["applied"], false);
+        assert_eq!(ranges(&result), [
+            "dev.dml:6:11", "dev.dml:8:28", "dev.dml:12:31",
+            "helpers.dml:3:11", "helpers.dml:5:16",
+        ]);
+        assert_eq!(result["count"], 5);
+        let diff = result["files"][1]["diff"].as_str().unwrap();
+        assert!(diff.contains("-        return limit;\n\
+                               +        return threshold;\n"), "{}", diff);
+        // Nothing is written without apply
+        assert!(std::fs::read_to_string(&file).unwrap()
+                .contains("param limit = 8;"));
+
+        // A method is renamed along with those it overrides
+        let result = rename(json!({"device": device, "line": 12,
+                                   "column": 17, "new_name": "step",
+                                   "apply": true})).await;
+        assert_eq!(result["applied"], true);
+        assert_eq!(ranges(&result), [
+            "dev.dml:7:12", "dev.dml:12:17", "helpers.dml:4:12",
+        ]);
+        let text = std::fs::read_to_string(&file).unwrap();
+        assert!(text.contains("    method step() -> (int) {\n"));
+        assert!(text.contains("return regs.step() + regs.limit;"));
+        assert!(std::fs::read_to_string(dir.join("helpers.dml")).unwrap()
+                .contains("method step() -> (int) default {"));
+
+        assert!(tool.execute(json!({"device": device, "symbol": "regs.limit",
+                                    "new_name": "not a name"})).await
+                .is_err());
+        assert!(tool.execute(json!({"device": device, "line": 8,
+                                    "column": 15, "new_name": "n"})).await
+                .is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reserved_region_policies() {
+        let device: DeviceSpec = serde_json::from_value(json!({
+            "name": "dev",
+            "base_template": null,
+            "documentation": null,
+            "banks": [{
+                "name": "regs",
+                "documentation": null,
+                "reserved": [
+                    {"offset": "0x40", "size": 64, "documentation": "Vendor specific"},
+                    {"offset": "0x90", "size": 2, "documentation": null}
+                ]
+            }]
+        })).unwrap();
+        
+        let generate = |policy| {
+            let context = GenerationContext {
+                device_name: "dev".to_string(),
+                namespace: "dev".to_string(),
+                imports: vec![],
+                templates: vec![],
+                config: GenerationConfig {
+                    reserved_regions: policy,
+                    ..Default::default()
+                },
+            };
+            let device = device.clone();
+            async move {
+                DMLGenerator::new(context).generate_device(&device).await
+                    .unwrap().content
+            }
+        };
+        
+        let code = generate(ReservedRegionPolicy::Registers).await;
+        assert!(code.contains("register reserved_0x40[i < 8] size 8 @ 0x40 + i * 8 is (reserved);"));
+        assert!(code.contains("register reserved_0x90 size 2 @ 0x90 is (reserved);"));
+        let code = generate(ReservedRegionPolicy::Params).await;
+        assert!(code.contains("param reserved_0x40 = \"Reserved region, 64 bytes at 0x40: Vendor specific\";"));
+        let code = generate(ReservedRegionPolicy::Gap).await;
+        assert!(!code.contains("reserved"));
+    }
+
+    #[tokio::test]
+    async fn test_generate_template_instantiations() {
+        use crate::mcp::analysis::parse_dml;
+        use std::path::Path;
+
+        let device: DeviceSpec = serde_json::from_value(json!({
+            "name": "dev",
+            "base_template": null,
+            "templates": ["hreset", "sreset"],
+            "documentation": null,
+            "banks": [{
+                "name": "regs",
+                "documentation": null,
+                "registers": [{
+                    "name": "id",
+                    "size": 4,
+                    "offset": "0x0",
+                    "documentation": null,
+                    "templates": ["read_only"],
+                    "fields": [{
+                        "name": "rev",
+                        "bits": "7:0",
+                        "access": null,
+                        "documentation": null,
+                        "templates": ["constant", "no_reset"]
+                    }]
+                }]
+            }]
+        })).unwrap();
+        let generator = DMLGenerator::new(GenerationContext {
+            device_name: "dev".to_string(),
+            namespace: "dev".to_string(),
+            imports: vec![],
+            templates: vec![],
+            config: GenerationConfig::default(),
+        });
+        let code = generator.generate_device(&device).await.unwrap().content;
+        assert!(code.contains("device dev;\nis hreset;\nis sreset;\n"));
+        assert!(code.contains("register id size 4 @ 0x0 {\n        is read_only;\n"));
+        assert!(code.contains("field rev @ [7:0] is (constant, no_reset);"));
+        let parsed = parse_dml(Path::new("dev.dml"), &code).unwrap();
+        assert!(parsed.errors.is_empty(), "{:?}\n{}", parsed.errors, code);
+    }
+
+    #[tokio::test]
+    async fn test_generate_register_access() {
+        let generator = DMLGenerator::new(GenerationContext {
+            device_name: "test".to_string(),
+            namespace: "test".to_string(),
+            imports: vec![],
+            templates: vec![],
+            config: GenerationConfig::default(),
+        });
+        let register = |access: &str| -> RegisterSpec {
+            serde_json::from_value(json!({
+                "name": "irq_status",
+                "size": 4,
+                "offset": "0x10",
+                "access": access,
+                "documentation": null,
+                "templates": ["no_reset"]
+            })).unwrap()
+        };
+        let code = generator.generate_register(&register("write-1-clears"))
+            .await.unwrap();
+        assert_eq!(code, "    register irq_status size 4 @ 0x10 {\n\
+                          \x20       is write_1_clears;\n\
+                          \x20       is no_reset;\n\
+                          \x20   }\n");
+        let code = generator.generate_register(&register("rw")).await.unwrap();
+        assert!(!code.contains("write_1_clears"));
+        for (access, template) in [("unimpl", "unimpl"), ("const", "constant"),
+                                   ("read_only", "read_only")] {
+            let code = generator.generate_register(&register(access))
+                .await.unwrap();
+            assert!(code.contains(&format!("is {};", template)), "{}", code);
+        }
+        assert!(generator.generate_register(&register("sometimes"))
+                .await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_generate_register_logging() {
+        let register: RegisterSpec = serde_json::from_value(json!({
+            "name": "ctrl",
+            "size": 4,
+            "offset": "0x0",
+            "documentation": null,
+            "fields": [
+                {"name": "mode", "bits": "3:0", "access": null, "documentation": null},
+                {"name": "count", "bits": "15:8", "access": null, "documentation": null}
+            ],
+            "methods": [
+                {"name": "read", "return_type": "uint64", "documentation": null,
+                 "body": "return this.val;"},
+                {"name": "write", "parameters": [{"name": "value", "param_type": "uint64"}],
+                 "return_type": null, "body": null, "documentation": null}
+            ]
+        })).unwrap();
+        let generate = |logging| {
+            let generator = DMLGenerator::new(GenerationContext {
+                device_name: "test".to_string(),
+                namespace: "test".to_string(),
+                imports: vec![],
+                templates: vec![],
+                config: GenerationConfig { logging, ..Default::default() },
+            });
+            let register = register.clone();
+            async move { generator.generate_register(&register).await.unwrap() }
+        };
+        
+        let code = generate(LoggingPolicy::None).await;
+        assert!(!code.contains("log "));
+        let code = generate(LoggingPolicy::Minimal).await;
+        assert!(!code.contains("Read from"));
+        assert!(code.contains("        method write(uint64 value) {\n\
+                               \x20           log info, 2: \"Write to %s: 0x%x\", qname, value;\n\
+                               \x20           if ((value & 0xffff00f0) != 0) {\n\
+                               \x20               log spec_viol, 1 then 2: \"Write to reserved bits of %s: 0x%x\", qname, value & 0xffff00f0;\n\
+                               \x20           }\n\
+                               \x20           // TODO: Implement method\n\
+                               \x20       }\n"), "{}", code);
+        let code = generate(LoggingPolicy::Verbose).await;
+        assert!(code.contains("            log info, 3: \"Read from %s\", qname;\n\
+                               \x20           return this.val;\n"), "{}", code);
+    }
+
+    #[tokio::test]
+    async fn test_generate_global_declarations() {
+        use crate::mcp::analysis::parse_dml;
+        use std::path::Path;
+
+        let device: DeviceSpec = serde_json::from_value(json!({
+            "name": "dma",
+            "base_template": null,
+            "documentation": null,
+            "dependencies": ["utility.dml"],
+            "constants": [
+                {"name": "DESC_VALID", "value": "0x1", "documentation": "Descriptor is owned by the device"}
+            ],
+            "typedefs": [
+                {"name": "channel_mask_t", "definition": "uint8", "documentation": null}
+            ],
+            "structs": [
+                {"name": "descriptor_t", "byte_order": "little-endian", "documentation": null,
+                 "members": [
+                     {"name": "addr", "member_type": "uint64"},
+                     {"name": "len", "member_type": "uint32"},
+                     {"name": "reserved", "member_type": "uint8", "array_size": 4}
+                 ]},
+                {"name": "channel_t", "documentation": null,
+                 "members": [{"name": "busy", "member_type": "bool"}]}
+            ]
+        })).unwrap();
+        let generator = DMLGenerator::new(GenerationContext {
+            device_name: "dma".to_string(),
+            namespace: "dma".to_string(),
+            imports: vec!["utility.dml".to_string()],
+            templates: vec![],
+            config: GenerationConfig::default(),
+        });
+        let code = generator.generate_device(&device).await.unwrap().content;
+        assert!(code.starts_with("dml 1.4;\n\n\
+                                  device dma;\n\n\
+                                  import \"utility.dml\";\n\n\
+                                  /// Descriptor is owned by the device\n\
+                                  constant DESC_VALID = 0x1;\n\
+                                  typedef uint8 channel_mask_t;\n\
+                                  typedef layout \"little-endian\" {\n\
+                                  \x20   uint64 addr;\n\
+                                  \x20   uint32 len;\n\
+                                  \x20   uint8 reserved[4];\n\
+                                  } descriptor_t;\n\
+                                  typedef struct {\n\
+                                  \x20   bool busy;\n\
+                                  } channel_t;\n\n"), "{}", code);
+        let parsed = parse_dml(Path::new("dma.dml"), &code).unwrap();
+        assert!(parsed.errors.is_empty(), "{:?}\n{}", parsed.errors, code);
+    }
+
+    #[tokio::test]
+    async fn test_generate_variants() {
+        use crate::mcp::analysis::parse_dml;
+        use std::path::Path;
+
+        let device: DeviceSpec = serde_json::from_value(json!({
+            "name": "nic",
+            "base_template": null,
+            "documentation": null,
+            "variants": [{
+                "param": "has_msix",
+                "default": true,
+                "documentation": "Whether the model supports MSI-X",
+                "enabled": {
+                    "banks": [{
+                        "name": "msix",
+                        "documentation": null,
+                        "registers": [{"name": "table", "size": 8, "offset": "0x0",
+                                       "documentation": null}]
+                    }]
+                },
+                "disabled": {
+                    "methods": [{"name": "raise_msix", "return_type": null,
+                                 "documentation": null, "body": "return;"}]
+                }
+            }]
+        })).unwrap();
+        let generator = DMLGenerator::new(GenerationContext {
+            device_name: "nic".to_string(),
+            namespace: "nic".to_string(),
+            imports: vec![],
+            templates: vec![],
+            config: GenerationConfig::default(),
+        });
+        let code = generator.generate_device(&device).await.unwrap().content;
+        assert!(code.ends_with("/// Whether the model supports MSI-X\n\
+                                param has_msix default true;\n\
+                                #if (has_msix) {\n\
+                                \x20   bank msix {\n\
+                                \x20       register table size 8 @ 0x0 {\n\
+                                \x20       }\n\
+                                \x20   }\n\
+                                } #else {\n\
+                                \x20   method raise_msix() {\n\
+                                \x20       return;\n\
+                                \x20   }\n\
+                                }\n"), "{}", code);
+        let parsed = parse_dml(Path::new("nic.dml"), &code).unwrap();
+        assert!(parsed.errors.is_empty(), "{:?}\n{}", parsed.errors, code);
+    }
+
+    #[tokio::test]
+    async fn test_generate_interface_stubs() {
+        use crate::catalog::{Catalog, CatalogFile, InterfaceInfo};
+        use crate::mcp::generation::{stub_methods, InterfaceSpec};
+        use std::sync::Arc;
+
+        let info = InterfaceInfo {
+            name: "ethernet_common".to_string(),
+            description: None,
+            import: None,
+            methods: vec!["void frame(conf_object_t *obj, const frags_t *frame, \
+                           eth_frame_crc_status_t crc_status)".to_string(),
+                          "int busy(conf_object_t *obj)".to_string()],
+        };
+        let stubs = stub_methods(&info);
+        assert_eq!(stubs.len(), 2);
+        assert_eq!(stubs[0].name, "frame");
+        assert_eq!(stubs[0].return_type, None);
+        assert_eq!(stubs[0].parameters.iter()
+                   .map(|p| (p.name.as_str(), p.param_type.as_str()))
+                   .collect::<Vec<_>>(),
+                   vec![("frame", "const frags_t *"),
+                        ("crc_status", "eth_frame_crc_status_t")]);
+        assert_eq!(stubs[1].return_type.as_deref(), Some("int"));
+        assert!(stubs[1].parameters.is_empty());
+
+        let mut catalog = Catalog::builtin();
+        catalog.extend(CatalogFile { interfaces: vec![info], templates: vec![] });
+        let generator = DMLGenerator::new(GenerationContext {
+            device_name: "nic".to_string(),
+            namespace: "nic".to_string(),
+            imports: vec![],
+            templates: vec![],
+            config: GenerationConfig::default(),
+        }).with_catalog(Arc::new(catalog));
+        let code = generator.generate_interface(&InterfaceSpec {
+            name: "ethernet_common".to_string(),
+            methods: vec![],
+        }).unwrap();
+        assert!(code.starts_with("implement ethernet_common {\n"), "{}", code);
+        assert!(code.contains("    method busy() -> (int) {\n"), "{}", code);
+        let code = generator.generate_interface(&InterfaceSpec {
+            name: "signal".to_string(),
+            methods: vec![],
+        }).unwrap();
+        assert_eq!(code, "implement signal {\n\
+                          \x20   method signal_raise() {\n\
+                          \x20       // TODO: Implement method\n\
+                          \x20   }\n\
+                          \x20   method signal_lower() {\n\
+                          \x20       // TODO: Implement method\n\
+                          \x20   }\n\
+                          }\n");
+        // Interfaces unknown to the catalog are left to the user
+        let code = generator.generate_interface(&InterfaceSpec {
+            name: "site_bus".to_string(),
+            methods: vec![],
+        }).unwrap();
+        assert_eq!(code, "implement site_bus;\n");
+    }
+
+    #[tokio::test]
+    async fn test_generate_device_stats() {
+        use crate::mcp::catalog::CatalogStore;
+        use crate::mcp::tools::{AddressSpan, DMLTool, DeviceGenerationStats,
+                                GenerateDeviceTool};
+        use std::sync::Arc;
+        
+        let tool = GenerateDeviceTool::new(Arc::new(CatalogStore::default()));
+        let result = tool.execute(json!({
+            "device_name": "dev",
+            "device_type": "peripheral",
+            "registers": [
+                {"name": "ctrl", "size": 4, "offset": "0x10",
+                 "fields": [{"name": "enable", "bits": "0"},
+                            {"name": "mode", "bits": "2:1"}]},
+                {"name": "status", "size": 2, "offset": "0x4"},
+                {"name": "incomplete"}
+            ],
+            "interfaces": ["signal", "custom"]
+        })).await.unwrap();
+        
+        assert_eq!(result.content.len(), 2);
+        assert!(result.content[0].text.contains("import \"simics/devs/signal.dml\";"));
+        let stats: DeviceGenerationStats = serde_json::from_str::<serde_json::Value>(
+            &result.content[1].text).map(|v| serde_json::from_value(
+                v["stats"].clone()).unwrap()).unwrap();
+        assert_eq!(stats.registers, 2);
+        assert_eq!(stats.fields, 2);
+        assert_eq!(stats.skipped_registers, 1);
+        assert_eq!(stats.address_span, Some(AddressSpan { start: 0x4, end: 0x14 }));
+        assert_eq!(stats.imports, vec!["simics/devs/signal.dml"]);
+        assert_eq!(stats.validation_errors, 0, "{}", result.content[0].text);
+        assert_eq!(stats.warnings, vec!["unknown interface 'custom'"]);
+        
+        let result = tool.execute(json!({
+            "device_name": "dev",
+            "device_type": "peripheral",
+            "registers": [
+                {"name": "top", "size": 8, "offset": "0xfffffffffffffffc"}
+            ]
+        })).await;
+        assert!(result.unwrap_err().to_string().contains("past the end"));
+    }
+
+    #[tokio::test]
+    async fn test_unknown_interface_warnings() {
+        use crate::mcp::catalog::Catalog;
+        use crate::mcp::tools::generate_device_files_from_spec;
+
+        let catalog = Catalog::builtin();
+        assert_eq!(catalog.unknown_interface_warning("io_memory"), None);
+        assert_eq!(catalog.unknown_interface_warning("io_memroy").as_deref(),
+                   Some("unknown interface 'io_memroy', did you mean \
+                         'io_memory'?"));
+        assert_eq!(catalog.unknown_interface_warning("sgnal").as_deref(),
+                   Some("unknown interface 'sgnal', did you mean 'signal'?"));
+        assert_eq!(catalog.unknown_interface_warning("frobnicator").as_deref(),
+                   Some("unknown interface 'frobnicator'"));
+
+        let device: DeviceSpec = serde_json::from_value(json!({
+            "name": "dev",
+            "base_template": null,
+            "documentation": null,
+            "interfaces": [{"name": "io_memroy"}],
+            "connects": [{"name": "irq", "documentation": null,
+                          "interfaces": ["signal", "serial_devcie"]}],
+            "variants": [{"param": "has_eth", "documentation": null,
+                          "enabled": {"interfaces": [{"name": "ethernet_common"}]}}]
+        })).unwrap();
+        let (files, stats) = generate_device_files_from_spec(
+            &device, GenerationConfig::default()).await.unwrap();
+        assert!(files[0].content.contains("implement io_memroy;"));
+        assert_eq!(stats.warnings, vec![
+            "unknown interface 'io_memroy', did you mean 'io_memory'?",
+            "unknown interface 'serial_devcie', did you mean 'serial_device'?",
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_server_capabilities_tool() {
+        use crate::mcp::catalog::CatalogStore;
+        use crate::mcp::tools::{DMLTool, ServerCapabilitiesTool};
+        use crate::project::ProjectStore;
+        use std::sync::Arc;
+
+        let missing = std::env::temp_dir().join("dls-no-such-catalog.toml");
+        let tool = ServerCapabilitiesTool::new(
+            Arc::new(CatalogStore::new(vec![missing.clone()])),
+            Arc::new(ProjectStore::default()));
+        let result = tool.execute(json!({})).await.unwrap();
+        let caps: serde_json::Value =
+            serde_json::from_str(&result.content[0].text).unwrap();
+        assert_eq!(caps["protocol_version"], MCP_VERSION);
+        assert_eq!(caps["transports"], json!(["stdio"]));
+        assert_eq!(caps["validation"]["dmlc"], false);
+        assert_eq!(caps["features"]["exporters"], cfg!(feature = "exporters"));
+        assert_eq!(caps["catalog"]["sources"],
+                   json!([{"path": missing, "kind": "missing"}]));
+        assert!(caps["catalog"]["interfaces"].as_u64().unwrap() > 0);
+        assert!(caps["patterns"].as_array().unwrap()
+                .contains(&json!("memory_mapped")));
+    }
+
+    #[tokio::test]
+    async fn test_server_stats_tool() {
+        use crate::mcp::analysis::parse_dml;
+        use crate::mcp::tools::{DMLTool, ServerStatsTool};
+        use std::path::Path;
+
+        // Holds the names counted as interned
+        let _parsed = parse_dml(Path::new("stats.dml"),
+                                "dml 1.4;\ndevice d;\n").unwrap();
+        let result = ServerStatsTool.execute(json!({})).await.unwrap();
+        let stats: serde_json::Value =
+            serde_json::from_str(&result.content[0].text).unwrap();
+        assert!(stats["files_parsed"].as_u64().unwrap() >= 1);
+        assert!(stats["phases"]["parse"]["total_ms"].is_u64());
+        assert!(stats["caches"].is_object());
+        assert!(stats["memory"]["interned_names"].as_u64().unwrap() > 0);
+    }
+
+    #[test]
+    fn test_project_tool_defaults() {
+        use crate::mcp::tools::with_project_defaults;
+        use crate::project::ProjectConfig;
+        use std::path::Path;
+
+        let project = ProjectConfig::parse(Path::new("/p"), r#"
+include_paths = ["include"]
+simics_api_version = "6"
+[generation]
+line_ending = "windows"
+indent = 2
+"#).unwrap();
+        let schema = json!({"properties": {
+            "line_ending": {}, "include_paths": {}, "file": {}}});
+        assert_eq!(with_project_defaults(&schema, &json!({"file": "a.dml"}),
+                                         &project),
+                   json!({"file": "a.dml", "line_ending": "windows",
+                          "include_paths": ["/p/include"]}));
+        assert_eq!(with_project_defaults(&schema,
+                                         &json!({"line_ending": "unix"}),
+                                         &project),
+                   json!({"line_ending": "unix",
+                          "include_paths": ["/p/include"]}));
+    }
+
+    #[test]
+    fn test_indent_style_spaces() {
+        let config = GenerationConfig {
+            format: FormatConfig {
+                indent_style: IndentStyle::Spaces(2),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        
+        let context = GenerationContext {
+            device_name: "test".to_string(),
+            namespace: "test".to_string(),
+            imports: vec![],
+            templates: vec![],
+            config,
+        };
+        
+        let generator = DMLGenerator::new(context);
+        let indent = generator.get_indent();
+        assert_eq!(indent, "  "); // 2 spaces
+    }
+
+    #[test]
+    fn test_indent_style_tabs() {
+        let config = GenerationConfig {
+            format: FormatConfig {
+                indent_style: IndentStyle::Tabs,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        
+        let context = GenerationContext {
+            device_name: "test".to_string(),
+            namespace: "test".to_string(),
+            imports: vec![],
+            templates: vec![],
+            config,
+        };
+        
+        let generator = DMLGenerator::new(context);
+        let indent = generator.get_indent();
+        assert_eq!(indent, "\t"); // tab character
+    }
+
+    const SAMPLE_DEVICE: &str = "dml 1.4;
+
+device sample;
+
+bank regs {
+    register control size 4 @ 0x00 {
+        field enable @ [0];
+        field mode @ [3:1] is read_only;
+    }
+    register status size 2 @ 0x04 \"Status register\";
+    register data size 4 @ 0x08 is unimpl {
+        param init_val = 16;
+    }
+}
+";
+
+    #[test]
+    fn test_device_spec_from_dml() {
+        use crate::mcp::analysis::parse_dml;
+        use std::path::Path;
+
+        let parsed = parse_dml(Path::new("sample.dml"), SAMPLE_DEVICE).unwrap();
+        assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+        let spec = parsed.to_device_spec();
+        assert_eq!(spec.name, "sample");
+        assert_eq!(spec.banks.len(), 1);
+        let regs = &spec.banks[0].registers;
+        assert_eq!(regs.len(), 3);
+        assert_eq!(regs[0].offset_value(), Some(0));
+        assert_eq!(regs[1].size, 2);
+        assert_eq!(regs[1].documentation.as_deref(), Some("Status register"));
+        assert_eq!(regs[0].fields[1].bit_range(), Some((3, 1)));
+        assert_eq!(regs[0].fields[1].access.as_deref(), Some("ro"));
+        assert_eq!(regs[2].reset.as_deref(), Some("0x10"));
+        assert_eq!(regs[0].reset_value(), Some(0));
+        assert!(regs[0].fields[1].templates.is_empty());
+        assert_eq!(regs[2].templates, vec!["unimpl".to_string()]);
+    }
+
+    #[test]
+    #[cfg(feature = "exporters")]
+    fn test_generate_c_header() {
+        use crate::mcp::analysis::parse_dml;
+        use crate::mcp::exporters::{generate_c_header, CHeaderOptions};
+        use std::path::Path;
+
+        let spec = parse_dml(Path::new("sample.dml"), SAMPLE_DEVICE).unwrap()
+            .to_device_spec();
+        let header = generate_c_header(&spec, &CHeaderOptions::default());
+        assert!(header.contains("#ifndef SAMPLE_REGS_H"));
+        assert!(header.contains("#define SAMPLE_REGS_CONTROL_OFFSET 0x0u"));
+        assert!(header.contains("#define SAMPLE_REGS_CONTROL_MODE_SHIFT 1u"));
+        assert!(header.contains("#define SAMPLE_REGS_CONTROL_MODE_MASK 0xeu"));
+        assert!(header.contains("volatile uint16_t status; /* 0x4 */"));
+        assert!(header.contains("uint8_t reserved_0x6[2];"));
+        assert!(header.contains("} sample_regs_t;"));
+    }
+
+    #[test]
+    #[cfg(feature = "exporters")]
+    fn test_c_header_overlapping_registers() {
+        use crate::mcp::analysis::parse_dml;
+        use crate::mcp::exporters::{generate_c_header, CHeaderOptions};
+        use std::path::Path;
+
+        // The first register ends past the end of the address space
+        let spec = parse_dml(Path::new("edge.dml"), "dml 1.4;
+device edge;
+bank regs {
+    register low size 4 @ 0x0;
+    register top size 8 @ 0xfffffffffffffffc;
+    register last size 1 @ 0xfffffffffffffffe;
+}
+").unwrap().to_device_spec();
+        let header = generate_c_header(&spec, &CHeaderOptions::default());
+        assert!(header.contains("#define EDGE_REGS_TOP_OFFSET \
+                                 0xfffffffffffffffcu"));
+        assert!(header.contains("omitted: registers are overlapping"));
+    }
+
+    #[test]
+    #[cfg(feature = "exporters")]
+    fn test_c_header_overlapping_register_array() {
+        use crate::mcp::analysis::parse_dml;
+        use crate::mcp::exporters::{generate_c_header, CHeaderOptions};
+        use std::path::Path;
+
+        let header_for = |ctrl_offset: &str| {
+            let source = format!("dml 1.4;
+device arr;
+bank regs {{
+    register chan[i < 4] size 4 @ 0x0 + i * 8;
+    register ctrl size 4 @ {};
+}}
+", ctrl_offset);
+            let spec = parse_dml(Path::new("arr.dml"), &source).unwrap()
+                .to_device_spec();
+            generate_c_header(&spec, &CHeaderOptions::default())
+        };
+        // The third element of the array is at 0x10
+        assert!(header_for("0x10")
+                .contains("omitted: registers are overlapping"));
+        let header = header_for("0x14");
+        assert!(!header.contains("omitted"));
+        assert!(header.contains("volatile uint32_t ctrl; /* 0x14 */"));
+    }
+
+    #[test]
+    #[cfg(feature = "exporters")]
+    fn test_generate_register_map_html() {
+        use crate::mcp::analysis::parse_dml;
+        use crate::mcp::exporters::generate_register_map_html;
+        use std::path::Path;
+
+        let spec = parse_dml(Path::new("sample.dml"), SAMPLE_DEVICE).unwrap()
+            .to_device_spec();
+        let html = generate_register_map_html(&spec);
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>Register map of sample</title>"));
+        assert!(html.contains("data-search=\"control enable mode\""));
+        assert!(html.contains("<td class=\"field\" colspan=\"3\" title=\"3:1\">mode</td>"));
+        assert!(html.contains("id=\"search\""));
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "exporters")]
+    async fn test_render_field_layout() {
+        use crate::mcp::exporters::{render_field_layout, LayoutFormat};
+        use crate::mcp::tools::{DMLTool, RenderFieldLayoutTool};
+
+        let register: RegisterSpec = serde_json::from_value(json!({
+            "name": "ctrl",
+            "size": 1,
+            "offset": "0x0",
+            "documentation": null,
+            "fields": [
+                {"name": "enable", "bits": "0", "documentation": null},
+                {"name": "mode", "bits": "3:1", "documentation": null},
+                {"name": "irq", "bits": "7", "documentation": null}
+            ]
+        })).unwrap();
+        assert_eq!(render_field_layout(&register, LayoutFormat::Ascii).unwrap(),
+                   "   7   6   4 3    1    0\n\
+                    +-----+-----+------+--------+\n\
+                    | irq |  -  | mode | enable |\n\
+                    +-----+-----+------+--------+\n");
+        let svg = render_field_layout(&register, LayoutFormat::Svg).unwrap();
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" \
+                                 width=\"224\""), "{}", svg);
+        assert_eq!(svg.matches("<rect").count(), 4);
+        assert!(svg.contains(">mode</text>"));
+
+        let tool = RenderFieldLayoutTool;
+        let result = tool.execute(json!({
+            "code": SAMPLE_DEVICE,
+            "register": "regs.control"
+        })).await.unwrap();
+        assert!(result.content[0].text.contains("| mode |"),
+                "{}", result.content[0].text);
+        let error = tool.execute(json!({
+            "code": SAMPLE_DEVICE,
+            "register": "other.control"
+        })).await.unwrap_err();
+        assert_eq!(error.to_string(), "No register other.control in device sample");
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "exporters")]
+    async fn test_generate_device_docs() {
+        use crate::mcp::analysis::parse_dml;
+        use crate::mcp::exporters::{generate_device_docs, DocFormat};
+        use crate::mcp::tools::{DMLTool, GenerateDocsTool};
+        use std::path::Path;
+
+        let code = format!("{}\nmethod reset(uint64 value, bool hard) \
+                            -> (bool, int) throws {{\n    \
+                            return (true, 0);\n}}\n",
+                           SAMPLE_DEVICE);
+        let spec = parse_dml(Path::new("sample.dml"), &code).unwrap()
+            .to_device_spec();
+        assert_eq!(spec.methods.len(), 1);
+        assert_eq!(spec.methods[0].parameters[1].param_type, "bool");
+        assert_eq!(spec.methods[0].return_type.as_deref(), Some("(bool, int)"));
+
+        let markdown = generate_device_docs(&spec, DocFormat::Markdown);
+        assert!(markdown.starts_with("# Device `sample`\n"), "{}", markdown);
+        assert!(markdown.contains("- 1 bank with 3 registers\n"));
+        assert!(markdown.contains(
+            "| `0x4` | `status` | 2 | `0x0` | rw | Status register |\n"),
+                "{}", markdown);
+        assert!(markdown.contains("### Register `regs.control`\n"));
+        assert!(markdown.contains("| mode | enable |"));
+        assert!(markdown.contains("| `[3:1]` | `mode` | ro |  |\n"));
+        assert!(markdown.contains(
+            "- `reset(value: uint64, hard: bool) -> (bool, int)`\n"));
+
+        let html = generate_device_docs(&spec, DocFormat::Html);
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<h2>Bank <code>regs</code></h2>"));
+        assert!(html.contains("<svg xmlns"));
+        assert!(html.trim_end().ends_with("</html>"));
+
+        let result = GenerateDocsTool.execute(json!({
+            "code": SAMPLE_DEVICE,
+            "format": "html"
+        })).await.unwrap();
+        assert!(result.content[0].text.contains("<title>sample</title>"));
+    }
+
+    #[test]
+    fn test_parse_query() {
+        use crate::mcp::query::{parse_query, QueryEntity, QueryOp, QueryValue};
+
+        let query = parse_query("select registers where size=8 and bank=\"pcie\"")
+            .unwrap();
+        assert_eq!(query.entity, QueryEntity::Register);
+        assert_eq!(query.conditions.len(), 2);
+        assert_eq!(query.conditions[0].attribute, "size");
+        assert_eq!(query.conditions[0].value, QueryValue::Integer(8));
+        assert_eq!(query.conditions[1].value, QueryValue::Text("pcie".to_string()));
+
+        let query = parse_query("list fields with access != 'wo'").unwrap();
+        assert_eq!(query.entity, QueryEntity::Field);
+        assert_eq!(query.conditions[0].op, QueryOp::Ne);
+
+        assert!(parse_query("delete registers").is_err());
+        assert!(parse_query("select widgets").is_err());
+        assert!(parse_query("select fields where access").is_err());
+    }
+
+    #[test]
+    fn test_evaluate_query() {
+        use crate::mcp::analysis::parse_dml;
+        use crate::mcp::query::{evaluate_query, parse_query};
+        use crate::mcp::workspace::{IndexedFile, WorkspaceIndex};
+        use std::path::{Path, PathBuf};
+
+        let parsed = parse_dml(Path::new("/ws/sample.dml"), SAMPLE_DEVICE).unwrap();
+        let index = WorkspaceIndex {
+            root: PathBuf::from("/ws"),
+            files: vec![IndexedFile {
+                path: parsed.path.clone(),
+                spec: parsed.to_device_spec(),
+                is_device: true,
+                error_count: 0,
+            }],
+        };
+
+        let run = |query: &str| evaluate_query(&parse_query(query).unwrap(), &index);
+        let registers = run("select registers where size = 2");
+        assert_eq!(registers.len(), 1);
+        assert_eq!(registers[0]["name"], "status");
+        assert_eq!(registers[0]["file"], "sample.dml");
+        assert_eq!(run("select registers where offset >= 0x4").len(), 2);
+        assert_eq!(run("select registers where bank = \"regs\"").len(), 3);
+        let fields = run("list fields with access = \"ro\"");
+        assert_eq!(fields.len(), 1);
+        assert_eq!(fields[0]["name"], "mode");
+        assert_eq!(fields[0]["width"], 3);
+        assert_eq!(run("list fields with name ~ \"EN\"").len(), 1);
+        assert_eq!(run("select devices").len(), 1);
+        assert!(run("select banks where registers > 3").is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "exporters")]
+    fn test_generate_simics_test() {
+        use crate::mcp::analysis::parse_dml;
+        use crate::mcp::exporters::{generate_simics_test, SimicsTestOptions};
+        use std::path::Path;
+
+        let spec = parse_dml(Path::new("sample.dml"), SAMPLE_DEVICE).unwrap()
+            .to_device_spec();
+        let files = generate_simics_test(&spec, &SimicsTestOptions::default());
+        let paths: Vec<&str> = files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, ["test/SUITEINFO", "test/sample_common.py",
+                           "test/s-sample-reset.py", "test/s-sample-access.py"]);
+
+        let common = &files[1].content;
+        assert!(common.contains("simics.pre_conf_object(name, \"sample\")"));
+        assert!(common.contains("dev.bank.regs, 0x4, size=2)"));
+        let reset = &files[2].content;
+        assert!(reset.contains("stest.expect_equal(regs.regs_data.read(), 0x10, \"regs.data after reset\")"));
+        let access = &files[3].content;
+        assert!(access.contains("from sample_common import create_sample, Registers"));
+        assert!(access.contains("regs.regs_control.write(0xffffffff)"));
+        assert!(access.contains("stest.expect_equal(value & 0x1, 0x1, \"regs.control: read/write bits\")"));
+        assert!(access.contains("stest.expect_equal(value & 0xe, 0x0, \"regs.control: read-only bits\")"));
+    }
+
+    #[test]
+    #[cfg(feature = "importers")]
+    fn test_parse_device_spec_files() {
+        use std::path::Path;
+
+        let yaml = DeviceSpec::parse(Path::new("dev.yaml"), "\
+name: dev
+banks:
+  - name: regs
+    registers:
+      - name: ctrl
+        size: 4
+        offset: \"0x10\"
+        fields:
+          - {name: enable, bits: \"0\", access: rw}
+").unwrap();
+        let toml = DeviceSpec::parse(Path::new("dev.toml"), "\
+name = \"dev\"
+[[banks]]
+name = \"regs\"
+[[banks.registers]]
+name = \"ctrl\"
+size = 4
+offset = \"0x10\"
+fields = [{name = \"enable\", bits = \"0\", access = \"rw\"}]
+").unwrap();
+        for spec in [yaml, toml] {
+            assert_eq!(spec.name, "dev");
+            let register = &spec.banks[0].registers[0];
+            assert_eq!(register.offset_value(), Some(0x10));
+            assert_eq!(register.fields[0].access.as_deref(), Some("rw"));
+        }
+        let error = DeviceSpec::parse(Path::new("dev.yml"), "banks: []\n")
+            .unwrap_err();
+        assert!(error.to_string().starts_with("Invalid device specification dev.yml"));
+    }
+
+    #[test]
+    #[cfg(feature = "importers")]
+    fn test_parse_xml() {
+        use crate::mcp::importers::xml::parse_xml;
+
+        let root = parse_xml("<?xml version=\"1.0\"?>
+<!-- A comment -->
+<ipxact:component xmlns:ipxact=\"http://www.accellera.org/XMLSchema/IPXACT/1685-2014\">
+  <ipxact:name>a &amp; b</ipxact:name>
+  <ipxact:description><![CDATA[x < y]]> &#x41;&#66;</ipxact:description>
+  <ipxact:empty id='1'/>
+</ipxact:component>
+").unwrap();
+        assert_eq!(root.name, "component");
+        assert_eq!(root.child_text("name"), Some("a & b"));
+        assert_eq!(root.child_text("description"), Some("x < y AB"));
+        assert_eq!(root.child("empty").unwrap().attribute("id"), Some("1"));
+        assert_eq!(parse_xml("<a>\n<b></a>").unwrap_err().to_string(),
+                   "XML error on line 2: expected the end of 'b', not of 'a'");
+        assert!(parse_xml("<a>&nbsp;</a>").is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "importers")]
+    fn test_import_svd() {
+        use crate::mcp::importers::parse_number;
+        use std::path::Path;
+
+        assert_eq!(parse_number("0x1F"), Some(0x1f));
+        assert_eq!(parse_number("#1x0"), Some(0b100));
+        assert_eq!(parse_number("32'h0000_00ff"), Some(0xff));
+        assert_eq!(parse_number("'b101"), Some(5));
+        assert_eq!(parse_number("12"), Some(12));
+        assert_eq!(parse_number("twelve"), None);
+
+        let spec = DeviceSpec::parse(Path::new("soc.svd"), "\
+<?xml version=\"1.0\" encoding=\"utf-8\"?>
+<device schemaVersion=\"1.3\">
+  <name>soc</name>
+  <description>Sample
+    SoC</description>
+  <size>32</size>
+  <resetValue>0</resetValue>
+  <peripherals>
+    <peripheral>
+      <name>UART0</name>
+      <description>UART</description>
+      <baseAddress>0x40000000</baseAddress>
+      <registers>
+        <register>
+          <name>DR</name>
+          <addressOffset>0x0</addressOffset>
+          <size>8</size>
+          <fields>
+            <field><name>DATA</name><bitRange>[7:0]</bitRange></field>
+          </fields>
+        </register>
+        <register>
+          <name>SR</name>
+          <addressOffset>0x4</addressOffset>
+          <access>read-only</access>
+          <resetValue>0x00000001</resetValue>
+          <fields>
+            <field><name>READY</name><bitOffset>0</bitOffset><bitWidth>1</bitWidth></field>
+            <field>
+              <name>ERR</name><lsb>1</lsb><msb>2</msb>
+              <access>read-write</access>
+              <modifiedWriteValues>oneToClear</modifiedWriteValues>
+            </field>
+          </fields>
+        </register>
+        <register>
+          <dim>4</dim>
+          <dimIncrement>4</dimIncrement>
+          <name>FIFO[%s]</name>
+          <addressOffset>0x10</addressOffset>
+        </register>
+        <register>
+          <dim>2</dim>
+          <dimIncrement>4</dimIncrement>
+          <dimIndex>A,B</dimIndex>
+          <name>CH%s_CTRL</name>
+          <addressOffset>0x20</addressOffset>
+        </register>
+        <cluster>
+          <name>DMA</name>
+          <addressOffset>0x40</addressOffset>
+          <register>
+            <name>ADDR</name>
+            <addressOffset>0x8</addressOffset>
+            <access>write-only</access>
+          </register>
+        </cluster>
+      </registers>
+    </peripheral>
+    <peripheral derivedFrom=\"UART0\">
+      <name>UART1</name>
+      <baseAddress>0x40001000</baseAddress>
+    </peripheral>
+  </peripherals>
+</device>
+").unwrap();
+        assert_eq!(spec.name, "soc");
+        assert_eq!(spec.documentation.as_deref(), Some("Sample SoC"));
+        assert_eq!(spec.banks.iter().map(|bank| bank.name.as_str())
+                   .collect::<Vec<_>>(), ["UART0", "UART1"]);
+        assert_eq!(spec.banks[1].documentation.as_deref(), Some("UART"));
+        assert_eq!(spec.banks[1].registers.len(),
+                   spec.banks[0].registers.len());
+        let registers: Vec<String> = spec.banks[0].registers.iter()
+            .map(|register| format!(
+                "{} {} @ {} reset {} {}", register.name, register.size,
+                register.offset.as_deref().unwrap(),
+                register.reset.as_deref().unwrap_or("-"),
+                register.access.as_deref().unwrap_or("rw")))
+            .collect();
+        assert_eq!(registers, [
+            "DR 1 @ 0x0 reset - rw",
+            "SR 4 @ 0x4 reset 0x1 ro",
+            "FIFO 4 @ 0x10 + i * 0x4 reset - rw",
+            "CHA_CTRL 4 @ 0x20 reset - rw",
+            "CHB_CTRL 4 @ 0x24 reset - rw",
+            "DMA_ADDR 4 @ 0x48 reset - wo",
+        ]);
+        let fifo = &spec.banks[0].registers[2];
+        assert_eq!(fifo.dimensions[0].size, 4);
+        assert_eq!(fifo.element_offsets(), Some(vec![0x10, 0x14, 0x18, 0x1c]));
+        let fields: Vec<String> = spec.banks[0].registers.iter()
+            .flat_map(|register| &register.fields)
+            .map(|field| format!("{} {} {}", field.name, field.bits,
+                                 field.access.as_deref().unwrap_or("-")))
+            .collect();
+        assert_eq!(fields, ["DATA 7:0 -", "READY 0 -", "ERR 2:1 w1c"]);
+
+        let error = DeviceSpec::parse(Path::new("soc.xml"), "<memory/>")
+            .unwrap_err();
+        assert!(error.to_string().contains(
+            "Expected an SVD device or an IP-XACT component, not 'memory'"),
+                "{}", error);
+        assert!(spec.to_text(Path::new("soc.svd")).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "importers")]
+    fn test_import_ip_xact() {
+        use std::path::Path;
+
+        let spec = DeviceSpec::parse(Path::new("timer.xml"), "\
+<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<ipxact:component xmlns:ipxact=\"http://www.accellera.org/XMLSchema/IPXACT/1685-2014\">
+  <ipxact:vendor>example.com</ipxact:vendor>
+  <ipxact:library>peripherals</ipxact:library>
+  <ipxact:name>timer</ipxact:name>
+  <ipxact:version>1.0</ipxact:version>
+  <ipxact:memoryMaps>
+    <ipxact:memoryMap>
+      <ipxact:name>map</ipxact:name>
+      <ipxact:addressBlock>
+        <ipxact:name>regs</ipxact:name>
+        <ipxact:baseAddress>'h0</ipxact:baseAddress>
+        <ipxact:range>256</ipxact:range>
+        <ipxact:width>32</ipxact:width>
+        <ipxact:register>
+          <ipxact:name>ctrl</ipxact:name>
+          <ipxact:description>Control register</ipxact:description>
+          <ipxact:addressOffset>'h0</ipxact:addressOffset>
+          <ipxact:size>32</ipxact:size>
+          <ipxact:field>
+            <ipxact:name>enable</ipxact:name>
+            <ipxact:bitOffset>0</ipxact:bitOffset>
+            <ipxact:resets><ipxact:reset><ipxact:value>1</ipxact:value></ipxact:reset></ipxact:resets>
+            <ipxact:bitWidth>1</ipxact:bitWidth>
+          </ipxact:field>
+          <ipxact:field>
+            <ipxact:name>prescale</ipxact:name>
+            <ipxact:bitOffset>8</ipxact:bitOffset>
+            <ipxact:resets><ipxact:reset><ipxact:value>'h4</ipxact:value></ipxact:reset></ipxact:resets>
+            <ipxact:bitWidth>4</ipxact:bitWidth>
+            <ipxact:access>read-only</ipxact:access>
+          </ipxact:field>
+        </ipxact:register>
+        <ipxact:register>
+          <ipxact:name>compare</ipxact:name>
+          <ipxact:dim>2</ipxact:dim>
+          <ipxact:addressOffset>'h10</ipxact:addressOffset>
+          <ipxact:size>32</ipxact:size>
+        </ipxact:register>
+        <ipxact:registerFile>
+          <ipxact:name>irq</ipxact:name>
+          <ipxact:addressOffset>'h20</ipxact:addressOffset>
+          <ipxact:range>8</ipxact:range>
+          <ipxact:register>
+            <ipxact:name>status</ipxact:name>
+            <ipxact:addressOffset>'h4</ipxact:addressOffset>
+            <ipxact:size>16</ipxact:size>
+            <ipxact:access>read-only</ipxact:access>
+            <ipxact:field>
+              <ipxact:name>expired</ipxact:name>
+              <ipxact:bitOffset>0</ipxact:bitOffset>
+              <ipxact:bitWidth>1</ipxact:bitWidth>
+              <ipxact:modifiedWriteValue>oneToClear</ipxact:modifiedWriteValue>
+            </ipxact:field>
+          </ipxact:register>
+        </ipxact:registerFile>
+      </ipxact:addressBlock>
+    </ipxact:memoryMap>
+  </ipxact:memoryMaps>
+</ipxact:component>
+").unwrap();
+        assert_eq!(spec.name, "timer");
+        let bank = &spec.banks[0];
+        assert_eq!(bank.name, "regs");
+        let registers: Vec<String> = bank.registers.iter()
+            .map(|register| format!(
+                "{} {} @ {} reset {} {}", register.name, register.size,
+                register.offset.as_deref().unwrap(),
+                register.reset.as_deref().unwrap_or("-"),
+                register.access.as_deref().unwrap_or("rw")))
+            .collect();
+        assert_eq!(registers, [
+            "ctrl 4 @ 0x0 reset 0x401 rw",
+            "compare 4 @ 0x10 + i * 0x4 reset - rw",
+            "irq_status 2 @ 0x24 reset - ro",
+        ]);
+        assert_eq!(bank.registers[0].documentation.as_deref(),
+                   Some("Control register"));
+        assert_eq!(bank.registers[0].fields[1].bits, "11:8");
+        assert_eq!(bank.registers[0].fields[1].access.as_deref(), Some("ro"));
+        assert_eq!(bank.registers[2].fields[0].access.as_deref(), Some("w1c"));
+
+        // IP-XACT 1.5 and 2009 put the reset value on the register
+        let spec = DeviceSpec::parse(Path::new("timer.xml"), "\
+<spirit:component xmlns:spirit=\"http://www.spiritconsortium.org/XMLSchema/SPIRIT/1.5\">
+  <spirit:name>timer</spirit:name>
+  <spirit:memoryMaps><spirit:memoryMap><spirit:name>map</spirit:name>
+    <spirit:addressBlock>
+      <spirit:name>regs</spirit:name>
+      <spirit:register>
+        <spirit:name>ctrl</spirit:name>
+        <spirit:addressOffset>0x8</spirit:addressOffset>
+        <spirit:size>32</spirit:size>
+        <spirit:access>read-write</spirit:access>
+        <spirit:reset><spirit:value>0xff</spirit:value></spirit:reset>
+      </spirit:register>
+    </spirit:addressBlock>
+  </spirit:memoryMap></spirit:memoryMaps>
+</spirit:component>
+").unwrap();
+        let register = &spec.banks[0].registers[0];
+        assert_eq!(register.offset_value(), Some(8));
+        assert_eq!(register.reset.as_deref(), Some("0xff"));
+        assert_eq!(register.access, None);
+    }
+
+    #[tokio::test]
+    async fn test_generation_golden_files() {
+        use crate::mcp::analysis::parse_dml;
+        use crate::mcp::tools::generate_device_from_spec;
+        use std::path::Path;
+
+        // Run with DLS_UPDATE_GOLDEN set to regenerate the files after
+        // intended changes to the generated code
+        let golden = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/test/golden");
+        let update = std::env::var_os("DLS_UPDATE_GOLDEN").is_some();
+        let mut patterns: Vec<_> = DMLTemplates::get_pattern_templates()
+            .into_iter().collect();
+        patterns.sort_by(|a, b| a.0.cmp(&b.0));
+        for (pattern, build) in patterns {
+            let spec = build(&pattern, &json!({})).unwrap();
+            let (code, _) = generate_device_from_spec(&spec, GenerationConfig::default()).await.unwrap();
+            let path = golden.join(format!("{}.dml", pattern));
+            let parsed = parse_dml(&path, &code).unwrap();
+            assert!(parsed.errors.is_empty(), "{}: {:?}", path.display(),
+                    parsed.errors);
+            if update {
+                std::fs::write(&path, &code).unwrap();
+                continue;
+            }
+            let expected = std::fs::read_to_string(&path)
+                .unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+            assert!(code == expected, "Generated code differs from {}",
+                    path.display());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generation_is_order_independent() {
+        use crate::mcp::tools::generate_device_from_spec;
+
+        let mut spec: DeviceSpec = serde_json::from_value(json!({
+            "name": "dev",
+            "interfaces": [{"name": "signal"}, {"name": "io_memory"}],
+            "connects": [{"name": "irq", "interfaces": ["signal", "pulse"]}],
+            "dependencies": ["utility.dml", "simics/devs/signal.dml", "utility.dml"]
+        })).unwrap();
+        let (code, stats) = generate_device_from_spec(&spec, GenerationConfig::default()).await.unwrap();
+        assert!(code.contains("\ndevice dev;\n\nimport \"simics/devs/signal.dml\";\n\
+                               import \"utility.dml\";\n\n"));
+        assert_eq!(stats.imports, ["simics/devs/signal.dml", "utility.dml"]);
+        spec.interfaces.reverse();
+        spec.connects[0].interfaces.reverse();
+        spec.dependencies.reverse();
+        assert_eq!(generate_device_from_spec(&spec, GenerationConfig::default()).await.unwrap().0, code);
+    }
+
+    #[test]
+    fn test_line_ending_crlf_input() {
+        let method_spec = MethodSpec {
+            name: "m".to_string(),
+            parameters: vec![],
+            return_type: None,
+            body: Some("if (true) {\r\n    return;\n}\r\n".to_string()),
+            documentation: Some("Line one\r\nline two".to_string()),
+        };
+        let generate = |line_ending| {
+            let generator = DMLGenerator::new(GenerationContext {
+                device_name: "test".to_string(),
+                namespace: "test".to_string(),
+                imports: vec![],
+                templates: vec![],
+                config: GenerationConfig {
+                    line_ending,
+                    ..GenerationConfig::default()
+                },
+            });
+            generator.generate_method(&method_spec).unwrap()
+        };
+        assert_eq!(generate(LineEnding::Windows),
+                   "    /// Line one\r\n    /// line two\r\n    method m() {\r\n\
+                    \x20       if (true) {\r\n            return;\r\n\
+                    \x20       }\r\n    }\r\n");
+        assert_eq!(generate(LineEnding::Unix),
+                   "    /// Line one\n    /// line two\n    method m() {\n\
+                    \x20       if (true) {\n            return;\n\
+                    \x20       }\n    }\n");
+    }
+
+    #[tokio::test]
+    async fn test_generate_split_device() {
+        use crate::mcp::analysis::parse_dml;
+        use crate::mcp::catalog::CatalogStore;
+        use crate::mcp::tools::{generate_device_files_from_spec,
+                                generate_device_from_spec, DMLTool,
+                                GenerateDeviceTool};
+        use std::path::Path;
+        use std::sync::Arc;
+
+        let spec: DeviceSpec = serde_json::from_value(json!({
+            "name": "dev",
+            "banks": [
+                {"name": "regs", "registers": [{"name": "r", "size": 4, "offset": "0x0"}]},
+                {"name": "pci", "registers": [{"name": "id", "size": 2, "offset": "0x0"}]}
+            ],
+            "interfaces": [{"name": "signal"}],
+            "dependencies": ["utility.dml"]
+        })).unwrap();
+        let split = |split| GenerationConfig { split, ..Default::default() };
+
+        let (files, stats) = generate_device_files_from_spec(
+            &spec, split(SplitMode::Sections)).await.unwrap();
+        let paths: Vec<&str> = files.iter()
+            .map(|file| file.file_path.as_str()).collect();
+        assert_eq!(paths, ["dev.dml", "dev_registers.dml", "dev_interfaces.dml"]);
+        assert!(files[0].content.starts_with(
+            "dml 1.4;\n\ndevice dev;\n\nimport \"dev_interfaces.dml\";\n\
+             import \"dev_registers.dml\";\nimport \"utility.dml\";\n\n"));
+        assert!(!files[0].content.contains("bank "));
+        for file in &files {
+            let parsed = parse_dml(Path::new(&file.file_path), &file.content)
+                .unwrap();
+            assert!(parsed.errors.is_empty(), "{}: {:?}", file.file_path,
+                    parsed.errors);
+        }
+        assert!(files[1].content.starts_with("dml 1.4;\n\n"));
+        assert!(files[1].content.contains("bank regs {")
+                && files[1].content.contains("bank pci {"));
+        assert!(files[2].content.contains("implement signal"));
+        assert_eq!(stats.registers, 2);
+
+        let (files, _) = generate_device_files_from_spec(
+            &spec, split(SplitMode::Banks)).await.unwrap();
+        let paths: Vec<&str> = files.iter()
+            .map(|file| file.file_path.as_str()).collect();
+        assert_eq!(paths, ["dev.dml", "dev_regs.dml", "dev_pci.dml"]);
+        assert!(files[0].content.contains("implement signal"));
+
+        // Without splitting, the code is that of a single file
+        let (files, _) = generate_device_files_from_spec(
+            &spec, GenerationConfig::default()).await.unwrap();
+        assert_eq!(files.len(), 1);
+        let (code, _) = generate_device_from_spec(
+            &spec, split(SplitMode::Banks)).await.unwrap();
+        assert_eq!(files[0].content, code);
+
+        let tool = GenerateDeviceTool::new(Arc::new(CatalogStore::new(vec![])));
+        let result = tool.execute(json!({
+            "device_name": "uart",
+            "device_type": "peripheral",
+            "registers": [{"name": "data", "size": 1, "offset": "0x00"}],
+            "interfaces": ["signal"],
+            "split": "sections"
+        })).await.unwrap();
+        assert_eq!(result.content.len(), 4);
+        assert!(result.content[1].text.contains("bank registers {"));
+        let summary: serde_json::Value =
+            serde_json::from_str(&result.content[3].text).unwrap();
+        assert_eq!(summary["files"],
+                   json!(["uart.dml", "uart_registers.dml", "uart_interfaces.dml"]));
+        assert_eq!(summary["stats"]["registers"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_generate_to_output_dir() {
+        use crate::mcp::catalog::CatalogStore;
+        use crate::mcp::output::unified_diff;
+        use crate::mcp::tools::{DMLTool, GenerateDeviceTool, ToolResult};
+        use std::sync::Arc;
+
+        let dir = std::env::temp_dir().join(format!("dls-output-test-{}",
+                                                    std::process::id()));
+        let tool = GenerateDeviceTool::new(Arc::new(CatalogStore::new(vec![])));
+        let generate = |registers: serde_json::Value, extra: serde_json::Value| {
+            let mut input = json!({
+                "device_name": "uart",
+                "device_type": "peripheral",
+                "registers": registers,
+                "split": "sections",
+                "output_dir": dir.to_string_lossy(),
+            });
+            input.as_object_mut().unwrap()
+                .extend(extra.as_object().unwrap().clone());
+            tool.execute(input)
+        };
+        let output = |result: &ToolResult| -> serde_json::Value {
+            serde_json::from_str::<serde_json::Value>(
+                &result.content.last().unwrap().text).unwrap()["output"].clone()
+        };
+        let actions = |output: &serde_json::Value| -> Vec<String> {
+            output["files"].as_array().unwrap().iter()
+                .map(|file| file["action"].as_str().unwrap().to_string())
+                .collect()
+        };
+        let one = json!([{"name": "data", "size": 1, "offset": "0x00"}]);
+        let two = json!([{"name": "data", "size": 1, "offset": "0x00"},
+                         {"name": "status", "size": 1, "offset": "0x04"}]);
+
+        // Dry runs write nothing, and diff against the missing files
+        let result = generate(one.clone(), json!({"dry_run": true})).await.unwrap();
+        let dry = output(&result);
+        assert_eq!(actions(&dry), ["create", "create"]);
+        assert!(dry["files"][0]["diff"].as_str().unwrap()
+                .starts_with("--- /dev/null\n+++ b/uart.dml\n@@ -0,0 +1,"));
+        assert!(!dir.exists());
+
+        let result = generate(one.clone(), json!({})).await.unwrap();
+        let written = output(&result);
+        assert_eq!(actions(&written), ["create", "create"]);
+        assert!(written["files"][0].get("diff").is_none());
+        assert_eq!(std::fs::read_to_string(dir.join("uart_registers.dml")).unwrap(),
+                   result.content[1].text);
+        let result = generate(one.clone(), json!({})).await.unwrap();
+        assert_eq!(actions(&output(&result)), ["unchanged", "unchanged"]);
+
+        // Changed files are only overwritten with force
+        let error = generate(two.clone(), json!({})).await.unwrap_err();
+        assert!(error.to_string().contains("uart_registers.dml"));
+        assert!(!std::fs::read_to_string(dir.join("uart_registers.dml")).unwrap()
+                .contains("status"));
+        let result = generate(two.clone(), json!({"dry_run": true})).await.unwrap();
+        let dry = output(&result);
+        assert_eq!(actions(&dry), ["unchanged", "refuse"]);
+        let diff = dry["files"][1]["diff"].as_str().unwrap();
+        assert!(diff.starts_with("--- a/uart_registers.dml\n+++ b/uart_registers.dml\n@@ "));
+        assert!(diff.lines().any(|line| line.starts_with('+')
+                                 && line.contains("register status")));
+        let result = generate(two, json!({"force": true})).await.unwrap();
+        assert_eq!(actions(&output(&result)), ["unchanged", "overwrite"]);
+        assert!(std::fs::read_to_string(dir.join("uart_registers.dml")).unwrap()
+                .contains("register status"));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(unified_diff("f", Some("a\nb\nc\nd\ne\nf\ng\nh\ni\n"),
+                                "a\nb\nc\nd\nE\nf\ng\nh\ni\n"),
+                   "--- a/f\n+++ b/f\n@@ -2,7 +2,7 @@\n b\n c\n d\n-e\n+E\n f\n g\n h\n");
+    }
+
+    #[tokio::test]
+    async fn test_preview_generation() {
+        use crate::mcp::tools::{generate_device_from_spec, DMLTool,
+                                PreviewGenerationTool};
+
+        let dir = std::env::temp_dir().join(format!("dls-preview-test-{}",
+                                                    std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let spec = json!({
+            "name": "dev",
+            "banks": [{"name": "regs", "registers": [
+                {"name": "ctrl", "size": 4, "offset": "0x0"},
+                {"name": "status", "size": 4, "offset": "0x4"}
+            ]}]
+        });
+        let spec_file = dir.join("dev.json");
+        std::fs::write(&spec_file, spec.to_string()).unwrap();
+        let preview = |input: serde_json::Value| async move {
+            let result = PreviewGenerationTool.execute(input).await
+                .unwrap();
+            let summary: serde_json::Value =
+                serde_json::from_str(&result.content[1].text).unwrap();
+            (result.content[0].text.clone(), summary)
+        };
+
+        let (diff, summary) = preview(json!({
+            "spec_file": spec_file.to_string_lossy()})).await;
+        assert!(diff.starts_with("--- /dev/null\n+++ b/dev.dml\n"));
+        assert_eq!(summary["files"][0]["action"], "create");
+        assert_eq!(summary["changed"], 1);
+
+        let (code, _) = generate_device_from_spec(
+            &serde_json::from_value(spec.clone()).unwrap(),
+            GenerationConfig::default()).await.unwrap();
+        std::fs::write(dir.join("existing.dml"),
+                       code.replace("register status", "register old_status"))
+            .unwrap();
+        let (diff, summary) = preview(json!({
+            "device_spec": spec,
+            "workspace": dir.to_string_lossy(),
+            "target": "existing.dml"})).await;
+        assert!(diff.starts_with("--- a/existing.dml\n+++ b/existing.dml\n@@ "));
+        assert!(diff.contains("\n-    register old_status")
+                && diff.contains("\n+    register status"));
+        assert_eq!(summary["files"][0]["action"], "overwrite");
+        // Previews never write
+        assert!(!dir.join("dev.dml").exists());
+
+        std::fs::write(dir.join("dev.dml"), code).unwrap();
+        let (diff, summary) = preview(json!({
+            "spec_file": spec_file.to_string_lossy()})).await;
+        assert_eq!(diff, "No changes");
+        assert_eq!(summary["changed"], 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_format_code() {
+        use crate::mcp::tools::{DMLTool, FormatCodeTool};
+
+        let format = |input: serde_json::Value| async move {
+            let result = FormatCodeTool.execute(input).await?;
+            Ok::<serde_json::Value, anyhow::Error>(
+                serde_json::from_str(&result.content[0].text).unwrap())
+        };
+        let result = format(json!({
+            "code": "dml 1.4;\nbank b{\nparam p=1;\n}\n",
+            "indent": 2})).await.unwrap();
+        assert_eq!(result["code"], "dml 1.4;\nbank b {\n  param p = 1;\n}\n");
+        assert_eq!(result["changed"], true);
+        let result = format(json!({
+            "code": "dml 1.4;\nbank b {\n\tparam p = 1;\n}\n",
+            "indent": "tabs"})).await.unwrap();
+        assert_eq!(result["changed"], false);
+
+        let error = format(json!({"code": "dml 1.4;\nbank b {\n"})).await
+            .unwrap_err();
+        assert!(error.to_string().contains("syntax errors"));
+        assert!(format(json!({"code": "dml 1.4;", "write": true})).await
+                .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_migrate_dml12() {
+        use crate::mcp::tools::{DMLTool, MigrateDml12Tool};
+
+        let migrate = |input: serde_json::Value| async move {
+            let result = MigrateDml12Tool.execute(input).await?;
+            Ok::<serde_json::Value, anyhow::Error>(
+                serde_json::from_str(&result.content[0].text).unwrap())
+        };
+        let dir = std::env::temp_dir().join(format!("dls-migrate-test-{}",
+                                                    std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("dev.dml");
+        std::fs::write(&file, "dml 1.2;\ndevice dev;\nbank b {\n    \
+                               method read_access() {}\n}\n").unwrap();
+        let result = migrate(json!({
+            "file": file.to_string_lossy(), "write": true})).await.unwrap();
+        assert_eq!(result["written"], true);
+        assert_eq!(result["rewrites"], 1);
+        assert_eq!(result["manual_review"][0]["line"], 4);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(),
+                   "dml 1.4;\ndevice dev;\nbank b {\n    \
+                    method read_access() {}\n}\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let error = migrate(json!({"code": "dml 1.4;\ndevice dev;\n"})).await
+            .unwrap_err();
+        assert!(error.to_string().contains("dml 1.2"));
+        assert!(migrate(json!({"code": "dml 1.2;", "write": true})).await
+                .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_generation_windows_line_endings() {
+        use crate::mcp::catalog::CatalogStore;
+        use crate::mcp::tools::{generate_device_from_spec, DMLTool,
+                                GenerateDeviceTool};
+        use std::sync::Arc;
+
+        let only_crlf = |code: &str| code.matches('\n').count()
+            == code.matches("\r\n").count()
+            && code.matches('\r').count() == code.matches("\r\n").count();
+        let windows = || GenerationConfig {
+            line_ending: LineEnding::Windows,
+            ..GenerationConfig::default()
+        };
+        for (pattern, build) in DMLTemplates::get_pattern_templates() {
+            let spec = build(&pattern, &json!({})).unwrap();
+            let (unix, _) = generate_device_from_spec(
+                &spec, GenerationConfig::default()).await.unwrap();
+            let (code, _) = generate_device_from_spec(&spec, windows())
+                .await.unwrap();
+            assert!(only_crlf(&code), "{} has LF line endings", pattern);
+            assert_eq!(code.replace("\r\n", "\n"), unix);
+        }
+
+        let tool = GenerateDeviceTool::new(Arc::new(CatalogStore::default()));
+        let result = tool.execute(json!({
+            "device_name": "dev",
+            "device_type": "peripheral",
+            "registers": [{"name": "ctrl", "size": 4, "offset": "0x0",
+                           "fields": [{"name": "enable", "bits": "0"}]}],
+            "interfaces": ["signal"],
+            "line_ending": "windows"
+        })).await.unwrap();
+        assert!(only_crlf(&result.content[0].text));
+        assert!(tool.execute(json!({
+            "device_name": "dev",
+            "device_type": "peripheral",
+            "line_ending": "mac"
+        })).await.is_err());
+    }
+
+    #[test]
+    fn test_device_spec_schema() {
+        use crate::mcp::resources::{list_resources, read_resource};
+        use crate::mcp::schema::{validate_device_spec, DEVICE_SPEC_SCHEMA_URI};
+
+        let schema = read_resource(DEVICE_SPEC_SCHEMA_URI).unwrap();
+        let schema: serde_json::Value = serde_json::from_str(&schema.text).unwrap();
+        assert_eq!(schema["title"], "DeviceSpec");
+        assert!(schema["definitions"]["RegisterSpec"]["properties"]["fields"].is_object());
+        assert_eq!(list_resources()[0].uri, DEVICE_SPEC_SCHEMA_URI);
+        assert!(read_resource("dml://schema/unknown.json").is_none());
+
+        assert!(validate_device_spec(&json!({
+            "name": "dev",
+            "banks": [{"name": "regs", "registers": [{"name": "r", "size": 4}]}]
+        })).is_ok());
+        let error = validate_device_spec(&json!({
+            "name": "dev",
+            "banks": [{"name": "regs", "registers": [{"name": "r", "size": "4"}]}]
+        })).unwrap_err();
+        assert!(error.to_string().starts_with("/banks/0/registers/0/size: "),
+                "{}", error);
+    }
+
+    #[test]
+    fn test_normalize_device_spec() {
+        let mut spec: DeviceSpec = serde_json::from_value(json!({
+            "name": "dev",
+            "base_template": null,
+            "documentation": null,
+            "banks": [{
+                "name": "regs",
+                "documentation": null,
+                "registers": [
+                    {"name": "b", "size": 4, "offset": "0x08", "documentation": null,
+                     "fields": [
+                         {"name": "hi", "bits": "4 : 7", "access": "read-only",
+                          "documentation": null},
+                         {"name": "lo", "bits": "0x0", "access": null,
+                          "documentation": null}
+                     ]},
+                    {"name": "c", "size": 4, "offset": "base + 4", "documentation": null},
+                    {"name": "a", "size": 4, "offset": "0", "reset": "16",
+                     "documentation": null}
+                ]
+            }],
+            "interfaces": [{"name": "io_memory"}, {"name": "io_memory"}],
+            "dependencies": ["utility.dml", "simics/devs/signal.dml", "utility.dml"]
+        })).unwrap();
+        spec.normalize();
+        
+        let registers = &spec.banks[0].registers;
+        let names: Vec<&str> = registers.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["a", "b", "c"]);
+        assert_eq!(registers[0].offset.as_deref(), Some("0x0"));
+        assert_eq!(registers[0].reset.as_deref(), Some("0x10"));
+        assert_eq!(registers[1].reset.as_deref(), Some("0x0"));
+        assert_eq!(registers[2].offset.as_deref(), Some("base + 4"));
+        let fields = &registers[1].fields;
+        assert_eq!(fields[0].name, "lo");
+        assert_eq!(fields[0].bits, "0");
+        assert_eq!(fields[0].access.as_deref(), Some("rw"));
+        assert_eq!(fields[1].bits, "7:4");
+        assert_eq!(fields[1].access.as_deref(), Some("ro"));
+        assert_eq!(spec.interfaces.len(), 1);
+        assert_eq!(spec.dependencies, ["utility.dml", "simics/devs/signal.dml"]);
+
+        // Normalizing is idempotent
+        let once = serde_json::to_value(&spec).unwrap();
+        spec.normalize();
+        assert_eq!(serde_json::to_value(&spec).unwrap(), once);
+    }
+
+    #[test]
+    fn test_builtin_catalog() {
+        use crate::mcp::catalog::Catalog;
+
+        let catalog = Catalog::builtin();
+        let signal = catalog.interface("signal").unwrap();
+        assert_eq!(signal.import.as_deref(), Some("simics/devs/signal.dml"));
+        assert_eq!(signal.methods.len(), 2);
+        assert!(catalog.template("write_1_clears").is_some());
+        assert!(catalog.interface("no_such_interface").is_none());
+    }
+
+    #[test]
+    fn test_catalog_for_simics_api() {
+        use crate::analysis::stdlib_versions::SimicsApiVersion;
+        use crate::mcp::catalog::Catalog;
+
+        let catalog = Catalog::builtin();
+        let api6 = catalog.for_simics_api(SimicsApiVersion(6));
+        assert!(api6.template("function_mapped_bank").is_some());
+        assert_eq!(api6.interface("io_memory"), catalog.interface("io_memory"));
+
+        let api7 = catalog.for_simics_api(SimicsApiVersion(7));
+        assert!(api7.template("function_mapped_bank").is_none());
+        assert!(api7.template("write_1_clears").is_some());
+        let io_memory = api7.interface("io_memory").unwrap();
+        assert!(io_memory.description.as_deref().unwrap()
+                .ends_with("(Deprecated since Simics API 7, use 'transaction' \
+                            instead)"));
+    }
+
+    #[test]
+    fn test_catalog_store_reload() {
+        use crate::mcp::catalog::CatalogStore;
+
+        let dir = std::env::temp_dir().join(format!("dls-catalog-test-{}",
+                                                    std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let site = dir.join("site.toml");
+        std::fs::write(&site, "[[interfaces]]\nname = \"site_bus\"\n\
+                               methods = [\"void ping(conf_object_t *obj)\"]\n")
+            .unwrap();
+
+        let store = CatalogStore::new(vec![dir.clone()]);
+        assert!(store.current().interface("site_bus").is_some());
+        assert!(store.current().interface("signal").is_some());
+
+        // Entries in data files override bundled entries
+        std::fs::write(dir.join("override.json"),
+                       r#"{"interfaces": [{"name": "signal", "description": "Site signal"}]}"#)
+            .unwrap();
+        let catalog = store.current();
+        assert_eq!(catalog.interface("signal").unwrap().description.as_deref(),
+                   Some("Site signal"));
+
+        std::fs::remove_file(&site).unwrap();
+        assert!(store.current().interface("site_bus").is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_validate_files() {
+        use crate::analysis::stdlib_versions::SimicsApiVersion;
+        use crate::mcp::workspace::{find_dml_files, validate_files};
+
+        let dir = std::env::temp_dir().join(format!("dls-validate-test-{}",
+                                                    std::process::id()));
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("clean.dml"), "dml 1.4;\ntemplate t {}\n")
+            .unwrap();
+        std::fs::write(dir.join("dev.dml"),
+                       "dml 1.4;\ndevice dev;\nbank b is function_mapped_bank {\n    \
+                        register r size 4 @ 0x0 is unimplemented;\n}\n")
+            .unwrap();
+        std::fs::write(dir.join("sub/broken.dml"), "dml 1.4;\nbank {\n")
+            .unwrap();
+
+        let paths = find_dml_files(&dir);
+        let results = validate_files(&paths, None);
+        assert_eq!(results.len(), 3);
+        let counts: Vec<(usize, usize)> = results.iter()
+            .map(|file| (file.errors, file.warnings)).collect();
+        assert_eq!(counts, vec![(0, 0), (0, 1), (2, 0)]);
+        assert_eq!(results[1].diagnostics[0].line, 4);
+
+        // Standard library items are only checked against a known version
+        let results = validate_files(&paths, Some(SimicsApiVersion(7)));
+        assert_eq!((results[1].errors, results[1].warnings), (1, 1));
+        assert_eq!(results[1].diagnostics[0].severity, "error");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_workspace_cache() {
+        use crate::analysis::stdlib_versions::SimicsApiVersion;
+        use crate::cache::cache_path;
+        use crate::mcp::workspace::{validate_workspace, WorkspaceIndex};
+
+        let dir = std::env::temp_dir().join(format!("dls-mcp-cache-test-{}",
+                                                    std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.dml"), "dml 1.4;\ndevice a;\n").unwrap();
+        std::fs::write(dir.join("b.dml"), "dml 1.4;\nbank {\n").unwrap();
+        let index = WorkspaceIndex::scan(&dir).unwrap();
+        assert_eq!(index.files.iter().map(|file| file.error_count)
+                   .collect::<Vec<_>>(), vec![0, 2]);
+
+        // Cached results are used as long as the files are unchanged, which
+        // shows in a tampered cache
+        let cache_file = cache_path(&dir, "mcp-workspace.json");
+        let mut cache: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(&cache_file).unwrap()).unwrap();
+        cache["data"]["a.dml"]["diagnostics"][""] = json!([{
+            "line": 1, "column": 1, "end_line": 1, "end_column": 2,
+            "severity": "warning", "message": "cached"
+        }]);
+        std::fs::write(&cache_file, cache.to_string()).unwrap();
+        let results = validate_workspace(&dir, None);
+        assert_eq!(results[0].diagnostics[0].message, "cached");
+        assert_eq!(results[1].errors, 2);
+        // Other API versions are validated against once, then cached too
+        let results = validate_workspace(&dir, Some(SimicsApiVersion(7)));
+        assert!(results[0].diagnostics.is_empty());
+        assert_eq!(validate_workspace(&dir, None)[0].diagnostics[0].message,
+                   "cached");
+
+        std::fs::write(dir.join("a.dml"), "dml 1.4;\ndevice a2;\n").unwrap();
+        assert!(validate_workspace(&dir, None)[0].diagnostics.is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_workspace_tools_without_workspace() {
+        use crate::mcp::tools::{DMLTool, DependencyGraphTool, QueryModelTool,
+                                ToolResult, ValidateWorkspaceTool};
+
+        let run = |result: ToolResult| -> serde_json::Value {
+            assert!(result.is_error.is_none());
+            serde_json::from_str(&result.content[0].text).unwrap()
+        };
+        let code = "dml 1.4;\ndevice dev;\nimport \"missing.dml\";\n\
+                    bank regs {\n    register r size 4 @ 0x0 is unimplemented;\n}\n";
+
+        let validated = run(ValidateWorkspaceTool
+                            .execute(json!({"code": code})).await.unwrap());
+        assert_eq!(validated["degraded"]["mode"], "single_file");
+        assert_eq!(validated["summary"]["files"], 1);
+        assert_eq!(validated["summary"]["warnings"], 1);
+
+        let queried = run(QueryModelTool.execute(json!({
+            "query": "select registers", "code": code})).await.unwrap());
+        assert_eq!(queried["degraded"]["mode"], "single_file");
+        assert_eq!(queried["count"], 1);
+
+        let graph = run(DependencyGraphTool
+                        .execute(json!({"code": code})).await.unwrap());
+        assert_eq!(graph["files"][0]["unresolved"], json!(["missing.dml"]));
+
+        // Without anything to analyze, the result explains what to pass
+        let empty = run(ValidateWorkspaceTool
+                        .execute(json!({})).await.unwrap());
+        assert_eq!(empty["degraded"]["mode"], "none");
+        assert_eq!(empty["summary"]["files"], 0);
+        assert!(empty["degraded"]["explanation"].as_str().unwrap()
+                .contains("workspace"));
+    }
+
+    #[test]
+    fn test_provenance_banner() {
+        use crate::mcp::provenance::{utc_timestamp, Provenance};
+        use std::time::{Duration, UNIX_EPOCH};
+
+        assert_eq!(utc_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(utc_timestamp(UNIX_EPOCH + Duration::from_secs(1_709_251_199)),
+                   "2024-02-29T23:59:59Z");
+        let provenance = Provenance {
+            tool_version: "1.2.3".to_string(),
+            spec_file: Some("uart.yaml".to_string()),
+            spec_hash: Some("0123456789abcdef".to_string()),
+            generated_at: None,
+        };
+        let banner = provenance.banner();
+        assert!(banner.starts_with("// @generated by dml-mcp-server\n"));
+        assert!(banner.contains("// spec-file: uart.yaml\n"));
+        assert!(!banner.contains("generated-at"));
+        assert_eq!(Provenance::parse(&format!("{}dml 1.4;\n", banner)),
+                   Some(provenance));
+        assert_eq!(Provenance::parse("// Hand-written\ndml 1.4;\n"), None);
+    }
+
+    #[tokio::test]
+    async fn test_analyze_project_provenance() {
+        use crate::mcp::catalog::CatalogStore;
+        use crate::mcp::tools::{AnalyzeProjectTool, DMLTool,
+                                GenerateDeviceTool};
+        use std::sync::Arc;
+
+        let dir = std::env::temp_dir().join(format!("dls-provenance-test-{}",
+                                                    std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let spec_file = dir.join("dev.json");
+        let spec = json!({"name": "dev", "base_template": null,
+                          "documentation": null});
+        std::fs::write(&spec_file, spec.to_string()).unwrap();
+        let tool = GenerateDeviceTool::new(Arc::new(CatalogStore::new(vec![])));
+        let result = tool.execute(json!({
+            "spec_file": spec_file, "banner": true})).await.unwrap();
+        let code = &result.content[0].text;
+        assert!(code.starts_with("// @generated by dml-mcp-server\n"));
+        assert!(code.contains("// spec-file: dev.json\n"));
+        assert!(!code.contains("generated-at"));
+        std::fs::write(dir.join("dev.dml"), code).unwrap();
+        std::fs::write(dir.join("utility.dml"), "dml 1.4;\n").unwrap();
+        
+        let analyze = || async {
+            let result = AnalyzeProjectTool.execute(json!({
+                "workspace": dir})).await.unwrap();
+            serde_json::from_str::<serde_json::Value>(&result.content[0].text)
+                .unwrap()
+        };
+        let analysis = analyze().await;
+        assert_eq!(analysis["summary"], json!({
+            "files": 2, "generated": 1, "hand_written": 1, "spec_changed": 0}));
+        assert_eq!(analysis["files"][0]["file"], "dev.dml");
+        assert_eq!(analysis["files"][0]["provenance"]["spec_file"], "dev.json");
+        assert_eq!(analysis["files"][0]["spec_changed"], false);
+        assert_eq!(analysis["files"][1]["generated"], false);
+        
+        let spec = json!({"name": "dev", "base_template": null,
+                          "documentation": "Changed"});
+        std::fs::write(&spec_file, spec.to_string()).unwrap();
+        let analysis = analyze().await;
+        assert_eq!(analysis["files"][0]["spec_changed"], true);
+        assert_eq!(analysis["summary"]["spec_changed"], 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_expand_templates_tool() {
+        use crate::mcp::tools::{DMLTool, ExpandTemplatesTool};
+        use std::path::Path;
+
+        let device = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("examples/sample_project/uart.dml");
+        let result = ExpandTemplatesTool.execute(json!({
+            "file": device, "object": "regs"})).await.unwrap();
+        let expanded: serde_json::Value =
+            serde_json::from_str(&result.content[0].text).unwrap();
+        assert_eq!(expanded["device"], "uart");
+        let bank = &expanded["object"];
+        assert_eq!(bank["kind"], "bank");
+        assert_eq!(bank["members"][0]["name"], "register_size");
+        assert_eq!(bank["members"][0]["value"], 1);
+        let registers: Vec<&str> = bank["objects"].as_array().unwrap().iter()
+            .map(|register| register["name"].as_str().unwrap()).collect();
+        assert_eq!(registers, vec!["data", "scratch", "status"]);
+        assert_eq!(bank["objects"][0]["location"]["line"], 13);
+
+        // Objects that are not in the device are reported
+        assert!(ExpandTemplatesTool.execute(json!({
+            "file": device, "object": "regs.missing"})).await.is_err());
+    }
+
+    #[test]
+    fn test_overlapping_register_warnings() {
+        use crate::mcp::analysis::analyze_device;
+
+        let dir = std::env::temp_dir().join(format!("dls-overlap-test-{}",
+                                                    std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("dev.dml");
+        std::fs::write(&file, "dml 1.4;
+device dev;
+bank regs {
+    register a size 4 @ 0x0;
+    register b size 2 @ 0x2;
+    group g[i < 2] {
+        register c size 4 @ 0x10 + i * 2;
+    }
+    register d size 4 @ 0x20;
+}
+bank other[j < 2] {
+    register e size 4 @ 0x0;
+    register f size 4 @ 0x4 * j;
+}
+").unwrap();
+        let device = analyze_device(&file, &[]).unwrap();
+        let mut warnings: Vec<String> = device.errors.values().flatten()
+            .filter(|error| error.description.contains("overlaps"))
+            .map(|error| error.description.clone())
+            .collect();
+        warnings.sort();
+        assert_eq!(warnings, vec![
+            "Register 'b' overlaps register 'a' in bank 'regs' at 0x2–0x3",
+            "Register 'f' overlaps register 'e' in bank 'other[0]' at 0x0–0x3",
+            "Register 'g[1].c' overlaps register 'g[0].c' in bank 'regs' at \
+             0x12–0x13",
+        ]);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_unused_declarations() {
+        use crate::mcp::analysis::analyze_device;
+
+        let dir = std::env::temp_dir().join(format!("dls-unused-test-{}",
+                                                    std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("lib.dml"), "dml 1.4;
+template from_lib {
+    param z = 1;
+}
+").unwrap();
+        std::fs::write(dir.join("used.dml"), "dml 1.4;
+template used_t {
+    param w = 2;
+    method read_w() -> (int) { return w; }
+}
+").unwrap();
+        let file = dir.join("dev.dml");
+        std::fs::write(&file, "dml 1.4;
+device dev;
+import \"lib.dml\";
+import \"used.dml\";
+template lonely {}
+param read_p = 3;
+param dead_p = 4;
+method helper() -> (int) {
+    return read_p;
+}
+method dead() {
+    local int x = helper();
+}
+bank regs is used_t;
+").unwrap();
+        let device = analyze_device(&file, &[]).unwrap();
+        let mut unused: Vec<String> = device.unused.iter()
+            .map(|unused| unused.description())
+            .collect();
+        unused.sort();
+        assert_eq!(unused, vec![
+            "Method 'dead' is never called",
+            "Method 'read_w' is never called",
+            "Nothing imported from 'lib.dml' is used",
+            "Parameter 'dead_p' is never read",
+            "Template 'from_lib' is never instantiated",
+            "Template 'lonely' is never instantiated",
+        ]);
+        std::fs::remove_dir_all(&dir).ok();
     }
-}
\ No newline at end of file
+}
@@ -1,7 +1,9 @@
 //  © 2024 Intel Corporation
 //  SPDX-License-Identifier: Apache-2.0 and MIT
 
-#[cfg(test)]
+#[cfg(all(test, feature = "mcp"))]
+mod mcp_conformance_tests;
+#[cfg(all(test, feature = "mcp"))]
 mod mcp_unit_tests;
 
 use logos::Logos;